@@ -0,0 +1,60 @@
+//! Playouts per second of the scalar rollout path against the batched one, for a few
+//! positions and batch sizes. Run with `cargo bench --features unstable`.
+
+use std::time::{Duration, Instant};
+
+use ladybug::batch::{scalar_playout, PlayoutBatch};
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const MAX_PLIES: u32 = 80;
+const PLAYOUTS: usize = 2048;
+const LANES: [usize; 4] = [1, 16, 64, 256];
+
+// Playouts per second of running `playouts` playouts with `run`
+fn rate(playouts: usize, run: impl FnOnce()) -> f64 {
+    let start = Instant::now();
+    run();
+    playouts as f64 / start.elapsed().max(Duration::from_nanos(1)).as_secs_f64()
+}
+
+fn main() {
+    let positions = [
+        ("start", Bughouse::default()),
+        (
+            "middlegame",
+            parse_fen("r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1[Pp] w - - 0 8")
+                .unwrap(),
+        ),
+        (
+            "drops",
+            parse_fen("r2q1rk1/ppp2ppp/2n5/3p4/3P4/2N5/PPP2PPP/R2Q1RK1[BNPbnp] w - - 0 12")
+                .unwrap(),
+        ),
+    ];
+    for (name, position) in positions.iter() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let scalar = rate(PLAYOUTS, || {
+            for _ in 0..PLAYOUTS {
+                scalar_playout(position, MAX_PLIES, &mut rng);
+            }
+        });
+        println!("{:<10} scalar        {:>9.0} playouts/s", name, scalar);
+        for &lanes in LANES.iter() {
+            let batched = rate(PLAYOUTS, || {
+                for batch in 0..PLAYOUTS / lanes {
+                    PlayoutBatch::new(position, lanes, MAX_PLIES, batch as u64).run();
+                }
+            });
+            println!(
+                "{:<10} batch of {:<4} {:>9.0} playouts/s, {:.2}x",
+                name,
+                lanes,
+                batched,
+                batched / scalar
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const QUOTA_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Limits for one client of the analysis server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientQuota {
+    /// Sustained request rate
+    pub requests_per_minute: u32,
+    /// Requests that may be made at once after a quiet period
+    pub burst: u32,
+    /// Search time the client may use per hour
+    pub engine_time_per_hour: Duration,
+    /// Requests that may wait for or use the engine at the same time
+    pub max_queued: usize,
+}
+
+impl Default for ClientQuota {
+    fn default() -> Self {
+        ClientQuota {
+            requests_per_minute: 30,
+            burst: 5,
+            engine_time_per_hour: Duration::from_secs(10 * 60),
+            max_queued: 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccessError {
+    Unauthorized,
+    RateLimited { retry_after: Duration },
+    QuotaExhausted { retry_after: Duration },
+    QueueFull,
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Unauthorized => write!(f, "unknown or missing token"),
+            AccessError::RateLimited { retry_after } => {
+                write!(
+                    f,
+                    "rate limited, retry in {:.1}s",
+                    retry_after.as_secs_f32()
+                )
+            }
+            AccessError::QuotaExhausted { retry_after } => write!(
+                f,
+                "engine time quota exhausted, retry in {}s",
+                retry_after.as_secs()
+            ),
+            AccessError::QueueFull => write!(f, "too many requests in progress"),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+#[derive(Debug)]
+struct Client {
+    name: String,
+    quota: ClientQuota,
+    // Token bucket for the request rate
+    tokens: f64,
+    refilled_at: Instant,
+    // Engine time used in the current quota window
+    window_start: Instant,
+    engine_time_used: Duration,
+    in_flight: usize,
+}
+
+impl Client {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        let rate = f64::from(self.quota.requests_per_minute) / 60f64;
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(self.quota.burst));
+        self.refilled_at = now;
+        if now.saturating_duration_since(self.window_start) >= QUOTA_WINDOW {
+            self.window_start = now;
+            self.engine_time_used = Duration::from_secs(0);
+        }
+    }
+
+    fn remaining_engine_time(&self) -> Duration {
+        self.quota
+            .engine_time_per_hour
+            .checked_sub(self.engine_time_used)
+            .unwrap_or_default()
+    }
+}
+
+/// An admitted request. Hand it back to [`AccessControl::finish`] when done.
+#[derive(Debug)]
+pub struct Ticket {
+    token: String,
+    /// The client's name, for logging
+    pub client: String,
+    /// How long the engine may search for this request
+    pub engine_time: Duration,
+}
+
+/// Token authentication, per-client rate limiting and engine time quotas for the
+/// analysis server. Time is passed in explicitly so the policy is easy to test.
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    clients: HashMap<String, Client>,
+}
+
+impl AccessControl {
+    pub fn new() -> AccessControl {
+        AccessControl::default()
+    }
+
+    pub fn add_client(&mut self, token: &str, name: &str, quota: ClientQuota, now: Instant) {
+        self.clients.insert(
+            token.to_string(),
+            Client {
+                name: name.to_string(),
+                quota,
+                tokens: f64::from(quota.burst),
+                refilled_at: now,
+                window_start: now,
+                engine_time_used: Duration::from_secs(0),
+                in_flight: 0,
+            },
+        );
+    }
+
+    /// Clients from lines of `<token> <name> [<engine seconds per hour>]`, with the
+    /// default quota otherwise. Empty lines and `#` comments are skipped.
+    pub fn parse_clients(text: &str, now: Instant) -> Result<AccessControl, String> {
+        let mut access = AccessControl::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("invalid client line: {}", line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (token, name, seconds) = match fields.as_slice() {
+                [token, name] => (token, name, None),
+                [token, name, seconds] => (token, name, Some(seconds)),
+                _ => return Err(invalid()),
+            };
+            let mut quota = ClientQuota::default();
+            if let Some(seconds) = seconds {
+                let seconds = seconds.parse().map_err(|_| invalid())?;
+                quota.engine_time_per_hour = Duration::from_secs(seconds);
+            }
+            access.add_client(token, name, quota, now);
+        }
+        Ok(access)
+    }
+
+    pub fn remove_client(&mut self, token: &str) {
+        self.clients.remove(token);
+    }
+
+    /// Checks whether a request with `token` may run now, and reserves a queue slot
+    /// for it if so.
+    pub fn admit(&mut self, token: &str, now: Instant) -> Result<Ticket, AccessError> {
+        let client = self
+            .clients
+            .get_mut(token)
+            .ok_or(AccessError::Unauthorized)?;
+        client.refill(now);
+
+        if client.in_flight >= client.quota.max_queued {
+            return Err(AccessError::QueueFull);
+        }
+        let engine_time = client.remaining_engine_time();
+        if engine_time == Duration::from_secs(0) {
+            let retry_after = QUOTA_WINDOW - now.saturating_duration_since(client.window_start);
+            return Err(AccessError::QuotaExhausted { retry_after });
+        }
+        if client.tokens < 1f64 {
+            let rate = f64::from(client.quota.requests_per_minute.max(1)) / 60f64;
+            let retry_after = Duration::from_secs_f64((1f64 - client.tokens) / rate);
+            return Err(AccessError::RateLimited { retry_after });
+        }
+
+        client.tokens -= 1f64;
+        client.in_flight += 1;
+        Ok(Ticket {
+            token: token.to_string(),
+            client: client.name.clone(),
+            engine_time,
+        })
+    }
+
+    /// Releases the queue slot of `ticket` and charges the engine time it used.
+    pub fn finish(&mut self, ticket: Ticket, engine_time_used: Duration) {
+        if let Some(client) = self.clients.get_mut(&ticket.token) {
+            client.in_flight = client.in_flight.saturating_sub(1);
+            client.engine_time_used += engine_time_used;
+        }
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use shakmaty::fen::fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::cancel::CancelToken;
+use crate::engine::{Engine, SearchOptions};
+use crate::eval::EvalParams;
+use crate::limits::SearchLimits;
+
+/// One game of a tournament, players given by their index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pairing {
+    pub round: u32,
+    pub white: usize,
+    pub black: usize,
+}
+
+/// Every player meets every other player `games` times, colors alternating.
+pub fn round_robin(players: usize, games: u32) -> Vec<Pairing> {
+    let mut pairings = Vec::new();
+    for round in 0..games {
+        for first in 0..players {
+            for second in first + 1..players {
+                let (white, black) = if round % 2 == 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                pairings.push(Pairing {
+                    round: round + 1,
+                    white,
+                    black,
+                });
+            }
+        }
+    }
+    pairings
+}
+
+/// Player 0 meets every other player `games` times, colors alternating, for testing one
+/// candidate against a field of references.
+pub fn gauntlet(players: usize, games: u32) -> Vec<Pairing> {
+    let mut pairings = Vec::new();
+    for round in 0..games {
+        for opponent in 1..players {
+            let (white, black) = if round % 2 == 0 {
+                (0, opponent)
+            } else {
+                (opponent, 0)
+            };
+            pairings.push(Pairing {
+                round: round + 1,
+                white,
+                black,
+            });
+        }
+    }
+    pairings
+}
+
+/// A finished game, as returned by the function playing the pairings.
+#[derive(Clone, Debug)]
+pub struct PlayedGame {
+    pub start: Bughouse,
+    pub moves: Vec<Move>,
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Score {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f32 {
+        self.wins as f32 + self.draws as f32 / 2f32
+    }
+
+    // Variance of the points of a single game around `mean`
+    fn variance(&self, mean: f32) -> f32 {
+        (self.wins as f32 * (1f32 - mean).powi(2)
+            + self.draws as f32 * (0.5 - mean).powi(2)
+            + self.losses as f32 * mean.powi(2))
+            / self.games() as f32
+    }
+
+    /// The Elo difference the score suggests, `None` without games or with all of them
+    /// won or lost.
+    pub fn elo(&self) -> Option<f32> {
+        if self.games() == 0 {
+            return None;
+        }
+        elo(self.points() / self.games() as f32)
+    }
+
+    /// Half the width of the 95% confidence interval of [`Score::elo`].
+    pub fn elo_margin(&self) -> Option<f32> {
+        if self.games() == 0 {
+            return None;
+        }
+        let games = self.games() as f32;
+        let mean = self.points() / games;
+        let variance = self.variance(mean);
+        let spread = 1.96 * (variance / games).sqrt();
+        Some((elo(mean + spread)? - elo(mean - spread)?) / 2f32)
+    }
+
+    /// Likelihood of superiority: the chance that the player is the stronger one, from
+    /// the decisive games. `None` if every game was drawn.
+    pub fn los(&self) -> Option<f32> {
+        let decisive = (self.wins + self.losses) as f32;
+        if decisive == 0f32 {
+            return None;
+        }
+        let z = (self.wins as f32 - self.losses as f32) / (2f32 * decisive).sqrt();
+        Some(0.5 * (1f32 + erf(z)))
+    }
+
+    /// The log-likelihood ratio of the generalized SPRT between the hypotheses that the
+    /// player is `elo0` and `elo1` Elo stronger, in the normal approximation. `None` until
+    /// the results vary.
+    pub fn llr(&self, elo0: f32, elo1: f32) -> Option<f32> {
+        let games = self.games() as f32;
+        if games == 0f32 {
+            return None;
+        }
+        let mean = self.points() / games;
+        let variance = self.variance(mean);
+        if variance <= 0f32 {
+            return None;
+        }
+        let expected = |elo: f32| 1f32 / (1f32 + 10f32.powf(-elo / 400f32));
+        let (s0, s1) = (expected(elo0), expected(elo1));
+        Some(games * (s1 - s0) * (2f32 * mean - s0 - s1) / (2f32 * variance))
+    }
+}
+
+/// Where a sequential probability ratio test stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// The LLR fell below the lower bound: not `elo1` stronger
+    H0,
+    /// The LLR rose above the upper bound: at least `elo1` stronger
+    H1,
+    Continue,
+}
+
+/// A sequential probability ratio test between `elo0` and `elo1` with error rates
+/// `alpha` and `beta`, for stopping a match as soon as its result is clear.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f32,
+    pub elo1: f32,
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+impl Sprt {
+    pub fn new(elo0: f32, elo1: f32) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// The LLR bounds below which H0 and above which H1 is accepted.
+    pub fn bounds(&self) -> (f32, f32) {
+        (
+            (self.beta / (1f32 - self.alpha)).ln(),
+            ((1f32 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn verdict(&self, score: &Score) -> SprtVerdict {
+        let (lower, upper) = self.bounds();
+        match score.llr(self.elo0, self.elo1) {
+            Some(llr) if llr <= lower => SprtVerdict::H0,
+            Some(llr) if llr >= upper => SprtVerdict::H1,
+            _ => SprtVerdict::Continue,
+        }
+    }
+}
+
+// The Elo difference expected to score `score` on average
+fn elo(score: f32) -> Option<f32> {
+    if score <= 0f32 || score >= 1f32 {
+        return None;
+    }
+    Some(-400f32 * (1f32 / score - 1f32).log10())
+}
+
+// The error function, to within 1.5e-7 (Abramowitz and Stegun 7.1.26)
+fn erf(x: f32) -> f32 {
+    let t = 1f32 / (1f32 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_6
+            + t * (-0.284_496_74 + t * (1.421_413_7 + t * (-1.453_152_1 + t * 1.061_405_4))));
+    let y = 1f32 - poly * (-x * x).exp();
+    y.copysign(x)
+}
+
+/// Scores of all players so far.
+#[derive(Clone, Debug)]
+pub struct Standings {
+    names: Vec<String>,
+    scores: Vec<Score>,
+}
+
+impl Standings {
+    pub fn new(names: Vec<String>) -> Standings {
+        Standings {
+            scores: vec![Score::default(); names.len()],
+            names,
+        }
+    }
+
+    pub fn add(&mut self, pairing: &Pairing, outcome: Outcome) {
+        let (white, black) = (pairing.white, pairing.black);
+        match outcome {
+            Outcome::Decisive {
+                winner: Color::White,
+            } => {
+                self.scores[white].wins += 1;
+                self.scores[black].losses += 1;
+            }
+            Outcome::Decisive {
+                winner: Color::Black,
+            } => {
+                self.scores[black].wins += 1;
+                self.scores[white].losses += 1;
+            }
+            Outcome::Draw => {
+                self.scores[white].draws += 1;
+                self.scores[black].draws += 1;
+            }
+        }
+    }
+
+    pub fn score(&self, player: usize) -> &Score {
+        &self.scores[player]
+    }
+
+    /// Players by decreasing points, ties in the order the players were given.
+    pub fn ranking(&self) -> Vec<(&str, &Score)> {
+        let mut ranking: Vec<_> = self
+            .names
+            .iter()
+            .map(String::as_str)
+            .zip(&self.scores)
+            .collect();
+        ranking.sort_by(|a, b| b.1.points().total_cmp(&a.1.points()));
+        ranking
+    }
+}
+
+impl fmt::Display for Standings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.names.iter().map(String::len).max().unwrap_or(0);
+        writeln!(
+            f,
+            "{:>3} {:<width$} {:>6} {:>5} {:>4} {:>4} {:>4}",
+            "#",
+            "name",
+            "points",
+            "games",
+            "won",
+            "drew",
+            "lost",
+            width = width
+        )?;
+        for (rank, (name, score)) in self.ranking().into_iter().enumerate() {
+            writeln!(
+                f,
+                "{:>3} {:<width$} {:>6.1} {:>5} {:>4} {:>4} {:>4}",
+                rank + 1,
+                name,
+                score.points(),
+                score.games(),
+                score.wins,
+                score.draws,
+                score.losses,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays a schedule of pairings between contenders, engine configurations or external
+/// engines, several games at a time.
+#[derive(Clone, Debug)]
+pub struct Arena {
+    pub names: Vec<String>,
+    /// Games played at the same time
+    pub concurrency: usize,
+    /// Where each pairing's games are appended to `<first>-vs-<second>.pgn`. The games
+    /// are on one board, so PGN with the crazyhouse variant tag rather than BPGN
+    pub archive: Option<PathBuf>,
+    /// Cancelling it stops handing out games. The running ones finish and are archived,
+    /// so no archive is left with half a game.
+    pub cancel: CancelToken,
+}
+
+impl Arena {
+    pub fn new(names: Vec<String>) -> Arena {
+        Arena {
+            names,
+            concurrency: 1,
+            archive: None,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Plays all `pairings` with `play`, which is called from several threads at once.
+    /// `on_result` sees the standings after every finished game, for a live table, and
+    /// returns `false` to stop handing out games, like once an SPRT has decided.
+    pub fn run<F, U>(
+        &self,
+        pairings: Vec<Pairing>,
+        play: F,
+        mut on_result: U,
+    ) -> io::Result<Standings>
+    where
+        F: Fn(&Pairing) -> PlayedGame + Sync,
+        U: FnMut(&Pairing, &PlayedGame, &Standings) -> bool,
+    {
+        let mut standings = Standings::new(self.names.clone());
+        let queue = Mutex::new(VecDeque::from(pairings));
+        let (sender, results) = mpsc::channel();
+        thread::scope(|scope| -> io::Result<()> {
+            for _ in 0..self.concurrency.max(1) {
+                let sender = sender.clone();
+                let (queue, play) = (&queue, &play);
+                let cancel = &self.cancel;
+                scope.spawn(move || loop {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    let pairing = match queue.lock().expect("arena queue lock").pop_front() {
+                        Some(pairing) => pairing,
+                        None => break,
+                    };
+                    let game = play(&pairing);
+                    if sender.send((pairing, game)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+            for (pairing, game) in results {
+                standings.add(&pairing, game.outcome);
+                if let Err(err) = self.archive_game(&pairing, &game) {
+                    // Stop handing out games; the running ones finish on their own
+                    queue.lock().expect("arena queue lock").clear();
+                    return Err(err);
+                }
+                if !on_result(&pairing, &game, &standings) {
+                    queue.lock().expect("arena queue lock").clear();
+                }
+            }
+            Ok(())
+        })?;
+        Ok(standings)
+    }
+
+    fn archive_game(&self, pairing: &Pairing, game: &PlayedGame) -> io::Result<()> {
+        let dir = match &self.archive {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let (first, second) = (
+            pairing.white.min(pairing.black),
+            pairing.white.max(pairing.black),
+        );
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}-vs-{}.pgn",
+            file_name(&self.names[first]),
+            file_name(&self.names[second])
+        ));
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(
+            pgn(
+                game,
+                pairing.round,
+                &self.names[pairing.white],
+                &self.names[pairing.black],
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+// Player names may hold anything, file names should not
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// An engine configuration taking part in a match.
+#[derive(Clone, Debug)]
+pub struct Contender {
+    pub name: String,
+    pub params: Arc<EvalParams>,
+    pub options: SearchOptions,
+    /// Nodes searched per move
+    pub nodes: u64,
+    /// Command starting an external UCI engine that plays instead of ours, a program
+    /// followed by its arguments separated by spaces. It searches `go nodes` with
+    /// `nodes`; the weights and search options are ours only
+    pub command: Option<String>,
+}
+
+impl Contender {
+    /// Reads a configuration written `name:key=value,...`, the keys being `nodes`,
+    /// `exploration`, `drop_threat_pruning` (`true` or `false`), `rollout_depth`,
+    /// `root_strategy` (`uct` or `halving`), `weights` naming a weights file, `uci`
+    /// naming the command of an external engine and any single weight like
+    /// `pocket.knight`, which overrides the file. Settings not mentioned keep their
+    /// defaults; a bare name is the default engine.
+    pub fn parse(spec: &str) -> Result<Contender, String> {
+        let (name, settings) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut contender = Contender {
+            name: name.to_string(),
+            params: Arc::new(EvalParams::default()),
+            options: SearchOptions::default(),
+            nodes: 400,
+            command: None,
+        };
+        let mut weights = String::new();
+        let mut overrides = String::new();
+        for setting in settings.split(',').filter(|setting| !setting.is_empty()) {
+            let invalid = || format!("invalid setting for {}: {}", name, setting);
+            let (key, value) = setting.split_once('=').ok_or_else(invalid)?;
+            match key.trim() {
+                "nodes" => contender.nodes = value.parse().map_err(|_| invalid())?,
+                "exploration" => {
+                    contender.options.exploration = value.parse().map_err(|_| invalid())?
+                }
+                "drop_threat_pruning" => {
+                    contender.options.drop_threat_pruning = value.parse().map_err(|_| invalid())?
+                }
+                "rollout_depth" => {
+                    contender.options.rollout_depth = value.parse().map_err(|_| invalid())?
+                }
+                "root_strategy" => {
+                    contender.options.root_strategy = value.parse().map_err(|_| invalid())?
+                }
+                "uci" if !value.trim().is_empty() => contender.command = Some(value.to_string()),
+                "weights" => {
+                    weights = fs::read_to_string(value)
+                        .map_err(|err| format!("could not read {}: {}", value, err))?
+                }
+                key => overrides.push_str(&format!("{} = {}\n", key, value)),
+            }
+        }
+        if !weights.is_empty() || !overrides.is_empty() {
+            let text = format!("{}\n{}", weights, overrides);
+            let params = EvalParams::parse(&text).map_err(|err| format!("{}: {}", name, err))?;
+            contender.params = Arc::new(params);
+        }
+        Ok(contender)
+    }
+}
+
+// A contender's engine for one game
+enum Player {
+    Ours(Box<Engine>),
+    External(UciPlayer),
+}
+
+/// Plays one game from the start position between two contenders, each searching with
+/// an engine of its own seeded with `seed`, or started from its command. Games still
+/// going after `max_plies` plies are drawn. An external engine that can't be started,
+/// exits or answers with an illegal move loses the game.
+pub fn play_contenders(
+    white: &Contender,
+    black: &Contender,
+    seed: u64,
+    max_plies: usize,
+) -> PlayedGame {
+    let mut players = [white, black].map(|contender| match &contender.command {
+        Some(command) => UciPlayer::spawn(command).map(Player::External),
+        None => {
+            let mut engine = Engine::new(contender.params.clone());
+            engine.set_options(contender.options.clone());
+            engine.set_seed(seed);
+            Ok(Player::Ours(Box::new(engine)))
+        }
+    });
+    let start = Bughouse::default();
+    let mut position = start.clone();
+    let mut moves = Vec::new();
+    let outcome = loop {
+        if let Some(outcome) = position.outcome() {
+            break outcome;
+        }
+        if moves.len() >= max_plies {
+            break Outcome::Draw;
+        }
+        let (player, contender) = match position.turn() {
+            Color::White => (&mut players[0], white),
+            Color::Black => (&mut players[1], black),
+        };
+        // `None` when an external engine failed, which loses the game
+        let found = match player {
+            Ok(Player::Ours(engine)) => {
+                Some(engine.search(&position, SearchLimits::nodes(contender.nodes)))
+            }
+            Ok(Player::External(engine)) => engine
+                .best_move(&start, &moves, &position, contender.nodes)
+                .ok(),
+            Err(_) => None,
+        };
+        match found {
+            Some(Some(m)) => {
+                position.play_unchecked(&m);
+                moves.push(m);
+            }
+            // The search only passes when it has no move worth playing
+            Some(None) => break position.outcome().unwrap_or(Outcome::Draw),
+            None => {
+                break Outcome::Decisive {
+                    winner: !position.turn(),
+                }
+            }
+        }
+    };
+    PlayedGame {
+        start,
+        moves,
+        outcome,
+    }
+}
+
+// An external crazyhouse engine speaking UCI, running as a child process for one game
+struct UciPlayer {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciPlayer {
+    // Starts `command` and switches it to crazyhouse
+    fn spawn(command: &str) -> io::Result<UciPlayer> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::other("no engine command given"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("engine stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("engine stdout is piped"));
+        let mut player = UciPlayer {
+            child,
+            stdin,
+            stdout,
+        };
+        player.send("uci")?;
+        player.read_until("uciok")?;
+        player.send("setoption name UCI_Variant value crazyhouse")?;
+        player.send("ucinewgame")?;
+        player.send("isready")?;
+        player.read_until("readyok")?;
+        Ok(player)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    // Reads lines up to the first one starting with `prefix` and returns it
+    fn read_until(&mut self, prefix: &str) -> io::Result<String> {
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "engine exited",
+                ));
+            }
+            if line.trim().starts_with(prefix) {
+                return Ok(line.trim().to_string());
+            }
+        }
+    }
+
+    // The engine's move in `position`, reached by `moves` from `start`. `None` if it
+    // has none, an error if it answers with an illegal one
+    fn best_move(
+        &mut self,
+        start: &Bughouse,
+        moves: &[Move],
+        position: &Bughouse,
+        nodes: u64,
+    ) -> io::Result<Option<Move>> {
+        let mut command = format!("position fen {}", start.fen());
+        if !moves.is_empty() {
+            command.push_str(" moves");
+            for m in moves {
+                command.push_str(&format!(" {}", Uci::from_standard(m)));
+            }
+        }
+        self.send(&command)?;
+        self.send(&format!("go nodes {}", nodes))?;
+        let line = self.read_until("bestmove")?;
+        let answer = line.split_whitespace().nth(1).unwrap_or("0000");
+        match answer.parse::<Uci>() {
+            Ok(Uci::Null) => Ok(None),
+            _ if answer == "(none)" => Ok(None),
+            Ok(uci) => uci.to_move(position).map(Some).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("illegal move {}", answer),
+                )
+            }),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad move {}", answer),
+            )),
+        }
+    }
+}
+
+impl Drop for UciPlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+/// The game in PGN with the crazyhouse variant tag.
+pub fn pgn(game: &PlayedGame, round: u32, white: &str, black: &str) -> String {
+    let mut text = format!(
+        "[Event \"ladybug arena\"]\n[Round \"{}\"]\n[White \"{}\"]\n[Black \"{}\"]\n[Result \"{}\"]\n[Variant \"Crazyhouse\"]\n",
+        round, white, black, game.outcome
+    );
+    if game.start.board() != Bughouse::default().board() {
+        text.push_str(&format!("[FEN \"{}\"]\n[SetUp \"1\"]\n", fen(&game.start)));
+    }
+    text.push('\n');
+    let mut position = game.start.clone();
+    let mut tokens = Vec::new();
+    for m in &game.moves {
+        if position.turn() == Color::White {
+            tokens.push(format!("{}.", position.fullmoves()));
+        } else if tokens.is_empty() {
+            tokens.push(format!("{}...", position.fullmoves()));
+        }
+        if !position.is_legal(m) {
+            break;
+        }
+        tokens.push(SanPlus::from_move_and_play_unchecked(&mut position, m).to_string());
+    }
+    tokens.push(game.outcome.to_string());
+    text.push_str(&tokens.join(" "));
+    text.push_str("\n\n");
+    text
+}
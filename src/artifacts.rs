@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::paths::write_atomic;
+
+/// What an artifact is used for. Each kind lives in its own directory of the store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    /// Evaluation weights in the format of [`crate::eval::EvalParams::parse`]
+    Weights,
+    Book,
+    Experience,
+}
+
+pub const KINDS: [ArtifactKind; 3] = [
+    ArtifactKind::Weights,
+    ArtifactKind::Book,
+    ArtifactKind::Experience,
+];
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ArtifactKind::Weights => "weights",
+            ArtifactKind::Book => "book",
+            ArtifactKind::Experience => "experience",
+        })
+    }
+}
+
+impl FromStr for ArtifactKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ArtifactKind, String> {
+        KINDS
+            .iter()
+            .copied()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown artifact kind {}, expected weights, book or experience",
+                    s
+                )
+            })
+    }
+}
+
+/// The hash of an artifact's contents, which is also its name in the store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArtifactId(pub u64);
+
+impl ArtifactId {
+    pub fn of(data: &[u8]) -> ArtifactId {
+        ArtifactId(content_hash(data))
+    }
+}
+
+impl fmt::Display for ArtifactId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for ArtifactId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ArtifactId, String> {
+        match u64::from_str_radix(s, 16) {
+            Ok(hash) if s.len() == 16 => Ok(ArtifactId(hash)),
+            _ => Err(format!("invalid artifact id {}", s)),
+        }
+    }
+}
+
+/// 64 bit FNV-1a; std's hasher is not guaranteed to be stable between releases, and
+/// ids must agree across builds and machines.
+pub fn content_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug)]
+pub enum ArtifactError {
+    Io(io::Error),
+    Missing(ArtifactKind, ArtifactId),
+    /// The stored file no longer hashes to its name
+    Corrupt {
+        kind: ArtifactKind,
+        id: ArtifactId,
+        actual: ArtifactId,
+    },
+    Manifest {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactError::Io(err) => write!(f, "artifact store: {}", err),
+            ArtifactError::Missing(kind, id) => write!(f, "{} {} is not in the store", kind, id),
+            ArtifactError::Corrupt { kind, id, actual } => {
+                write!(
+                    f,
+                    "{} {} is corrupt, its contents hash to {}",
+                    kind, id, actual
+                )
+            }
+            ArtifactError::Manifest { line, message } => {
+                write!(f, "manifest line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+impl From<io::Error> for ArtifactError {
+    fn from(err: io::Error) -> Self {
+        ArtifactError::Io(err)
+    }
+}
+
+/// Models, books and experience files stored as `<dir>/<kind>/<id>`, where the id is the
+/// hash of the contents. Every load checks the hash, so a file damaged in transit or
+/// edited in place is caught instead of silently changing how the engine plays.
+#[derive(Clone, Debug)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+}
+
+impl ArtifactStore {
+    pub fn open(dir: &Path) -> Result<ArtifactStore, ArtifactError> {
+        for kind in KINDS {
+            fs::create_dir_all(dir.join(kind.to_string()))?;
+        }
+        Ok(ArtifactStore {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self, kind: ArtifactKind, id: ArtifactId) -> PathBuf {
+        self.dir.join(kind.to_string()).join(id.to_string())
+    }
+
+    pub fn contains(&self, kind: ArtifactKind, id: ArtifactId) -> bool {
+        self.path(kind, id).is_file()
+    }
+
+    /// Adds `data`, returning its id. Adding the same contents twice is a no-op.
+    pub fn put(&self, kind: ArtifactKind, data: &[u8]) -> Result<ArtifactId, ArtifactError> {
+        let id = ArtifactId::of(data);
+        let path = self.path(kind, id);
+        if !path.is_file() {
+            write_atomic(&path, data)?;
+        }
+        Ok(id)
+    }
+
+    /// Adds a copy of the file at `path`.
+    pub fn import(&self, kind: ArtifactKind, path: &Path) -> Result<ArtifactId, ArtifactError> {
+        self.put(kind, &fs::read(path)?)
+    }
+
+    /// The contents of an artifact, after checking they still match the id.
+    pub fn load(&self, kind: ArtifactKind, id: ArtifactId) -> Result<Vec<u8>, ArtifactError> {
+        let data = match fs::read(self.path(kind, id)) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(ArtifactError::Missing(kind, id))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let actual = ArtifactId::of(&data);
+        if actual != id {
+            return Err(ArtifactError::Corrupt { kind, id, actual });
+        }
+        Ok(data)
+    }
+
+    /// Checks that every artifact of `manifest` is present and intact.
+    pub fn verify(&self, manifest: &Manifest) -> Result<(), ArtifactError> {
+        for (kind, id) in manifest.entries() {
+            self.load(kind, id)?;
+        }
+        Ok(())
+    }
+}
+
+/// The artifacts an engine is configured to use, one per kind, as lines of `<kind> <id>`.
+/// Machines of a self-play cluster compare [`Manifest::id`]s to make sure they play with
+/// the same files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub weights: Option<ArtifactId>,
+    pub book: Option<ArtifactId>,
+    pub experience: Option<ArtifactId>,
+}
+
+impl Manifest {
+    pub fn get(&self, kind: ArtifactKind) -> Option<ArtifactId> {
+        match kind {
+            ArtifactKind::Weights => self.weights,
+            ArtifactKind::Book => self.book,
+            ArtifactKind::Experience => self.experience,
+        }
+    }
+
+    pub fn set(&mut self, kind: ArtifactKind, id: Option<ArtifactId>) {
+        match kind {
+            ArtifactKind::Weights => self.weights = id,
+            ArtifactKind::Book => self.book = id,
+            ArtifactKind::Experience => self.experience = id,
+        }
+    }
+
+    /// The configured artifacts, in the order of [`KINDS`].
+    pub fn entries(&self) -> impl Iterator<Item = (ArtifactKind, ArtifactId)> + '_ {
+        KINDS
+            .iter()
+            .filter_map(move |&kind| self.get(kind).map(|id| (kind, id)))
+    }
+
+    /// A hash of the whole manifest, equal on two machines exactly when they are
+    /// configured with the same artifacts.
+    pub fn id(&self) -> ArtifactId {
+        ArtifactId::of(self.to_text().as_bytes())
+    }
+
+    pub fn parse(text: &str) -> Result<Manifest, ArtifactError> {
+        let mut manifest = Manifest::default();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ArtifactError::Manifest {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (kind, id) = match line.split_once(' ') {
+                Some((kind, id)) => (
+                    kind.parse().map_err(error)?,
+                    id.trim().parse().map_err(error)?,
+                ),
+                None => return Err(error("expected `<kind> <id>`".to_string())),
+            };
+            if manifest.get(kind).is_some() {
+                return Err(error(format!("{} is listed twice", kind)));
+            }
+            manifest.set(kind, Some(id));
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.entries()
+            .map(|(kind, id)| format!("{} {}\n", kind, id))
+            .collect()
+    }
+
+    /// Reads a manifest, an absent file being an empty one.
+    pub fn load(path: &Path) -> Result<Manifest, ArtifactError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Manifest::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ArtifactError> {
+        write_atomic(path, self.to_text())?;
+        Ok(())
+    }
+}
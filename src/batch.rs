@@ -0,0 +1,524 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use shakmaty::{attacks, Bitboard, Color, Move, Outcome, Piece, Position, Role, Setup, Square};
+
+use crate::board::Bughouse;
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const FILE_H: u64 = FILE_A << 7;
+const BACKRANKS: u64 = 0xff00_0000_0000_00ff;
+
+// Roles by index, pawn to king, and the ones that can be dropped
+const ROLES: [Role; 6] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+    Role::King,
+];
+const POCKET_ROLES: usize = 5;
+
+// Playouts still going after their plies run out are decided on material, a side this
+// many pawns ahead winning
+const ADJUDICATION_MARGIN: i32 = 3;
+
+// Marks a drop in `BatchMove::from`
+const DROP: u8 = 64;
+
+// A move of one lane: `from` is `DROP` for drops, `role` the dropped or promoted role
+// and otherwise unused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BatchMove {
+    from: u8,
+    to: u8,
+    role: Option<Role>,
+}
+
+/// Uniformly random crazyhouse playouts of many copies of a position at once, as an
+/// experiment in speeding up rollouts.
+///
+/// The boards are kept as a struct of arrays, one bitboard per lane for each color and
+/// role, and all lanes advance in lockstep: every lane picks a pseudo-legal move, then
+/// one pass over all lanes finds the moves that leave the king attacked. That pass has
+/// no branches, so the compiler can vectorize it. A lane whose move turns out illegal
+/// tries another one in the next step, and a lane out of moves is mated or stalemated.
+///
+/// The rules are simplified: there is no castling or en passant, and promoted pieces
+/// keep their role when captured. Playouts still going after `max_plies` are decided
+/// on the material on the board and in the pockets. [`scalar_playout`] plays the same
+/// kind of playout one position at a time with full rules, to compare against.
+pub struct PlayoutBatch {
+    colors: [Vec<u64>; 2],
+    roles: [Vec<u64>; 6],
+    // Copies of the boards the picked moves are tried on
+    next_colors: [Vec<u64>; 2],
+    next_roles: [Vec<u64>; 6],
+    pockets: [[Vec<u8>; POCKET_ROLES]; 2],
+    white_to_move: Vec<bool>,
+    plies: Vec<u32>,
+    outcomes: Vec<Option<Outcome>>,
+    // Moves of each lane's position not tried yet, `None` until generated
+    untried: Vec<Option<Vec<BatchMove>>>,
+    picked: Vec<Option<BatchMove>>,
+    // Scratch space for the attack pass
+    attacked: Vec<u64>,
+    max_plies: u32,
+    rng: StdRng,
+}
+
+impl PlayoutBatch {
+    /// `lanes` playouts of `position`, each ending after at most `max_plies` plies.
+    pub fn new(position: &Bughouse, lanes: usize, max_plies: u32, seed: u64) -> PlayoutBatch {
+        let board = position.board();
+        let color_bits = |color: Color| vec![board.by_color(color).0; lanes];
+        let role_bits = |role: Role| vec![board.by_role(role).0; lanes];
+        let pocket = |color: Color, role: Role| {
+            let count = position
+                .pockets()
+                .map_or(0, |pockets| pockets.by_color(color).by_role(role));
+            vec![count; lanes]
+        };
+        let pockets = |color: Color| {
+            [
+                pocket(color, Role::Pawn),
+                pocket(color, Role::Knight),
+                pocket(color, Role::Bishop),
+                pocket(color, Role::Rook),
+                pocket(color, Role::Queen),
+            ]
+        };
+        let colors = [color_bits(Color::White), color_bits(Color::Black)];
+        let roles = [
+            role_bits(Role::Pawn),
+            role_bits(Role::Knight),
+            role_bits(Role::Bishop),
+            role_bits(Role::Rook),
+            role_bits(Role::Queen),
+            role_bits(Role::King),
+        ];
+        PlayoutBatch {
+            next_colors: colors.clone(),
+            next_roles: roles.clone(),
+            colors,
+            roles,
+            pockets: [pockets(Color::White), pockets(Color::Black)],
+            white_to_move: vec![position.turn() == Color::White; lanes],
+            plies: vec![0; lanes],
+            outcomes: vec![position.outcome(); lanes],
+            untried: vec![None; lanes],
+            picked: vec![None; lanes],
+            attacked: vec![0; lanes],
+            max_plies,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.plies.len()
+    }
+
+    /// The legal moves of a lane's position under the batch's rules, in no particular
+    /// order.
+    pub fn legal_moves(&self, lane: usize) -> Vec<Move> {
+        let us = self.turn(lane);
+        self.pseudo_legal_moves(lane)
+            .into_iter()
+            .filter(|&m| {
+                let (mut colors, mut roles) = self.board(lane);
+                apply(&mut colors, &mut roles, us, m);
+                let attacked = attacked_by(
+                    colors[side(!us)],
+                    colors[0] | colors[1],
+                    &roles,
+                    !us == Color::White,
+                );
+                attacked & roles[5] & colors[side(us)] == 0
+            })
+            .map(|m| self.to_move(lane, m))
+            .collect()
+    }
+
+    /// Plays one move in every lane still going. Returns whether any lane is.
+    pub fn step(&mut self) -> bool {
+        let lanes = self.lanes();
+        // Every lane still going picks a move it hasn't tried yet, on a copy of its board
+        for lane in 0..lanes {
+            self.picked[lane] = None;
+            if self.outcomes[lane].is_some() {
+                continue;
+            }
+            if self.plies[lane] >= self.max_plies {
+                self.outcomes[lane] = Some(adjudicate(self.material_balance(lane)));
+                continue;
+            }
+            if self.untried[lane].is_none() {
+                let mut moves = self.pseudo_legal_moves(lane);
+                moves.shuffle(&mut self.rng);
+                self.untried[lane] = Some(moves);
+            }
+            let m = match self.untried[lane].as_mut().and_then(Vec::pop) {
+                Some(m) => m,
+                None => {
+                    self.outcomes[lane] = Some(self.no_moves_outcome(lane));
+                    continue;
+                }
+            };
+            let us = self.turn(lane);
+            let (mut colors, mut roles) = self.board(lane);
+            apply(&mut colors, &mut roles, us, m);
+            self.next_colors[0][lane] = colors[0];
+            self.next_colors[1][lane] = colors[1];
+            for (index, bits) in roles.iter().enumerate() {
+                self.next_roles[index][lane] = *bits;
+            }
+            self.picked[lane] = Some(m);
+        }
+        // All lanes at once: the squares the opponent of the mover attacks afterwards
+        self.attack_pass();
+        let mut going = false;
+        for lane in 0..lanes {
+            let m = match self.picked[lane] {
+                Some(m) => m,
+                None => continue,
+            };
+            going = true;
+            let us = side(self.turn(lane));
+            let king = self.next_roles[5][lane] & self.next_colors[us][lane];
+            if self.attacked[lane] & king != 0 {
+                // Illegal, the lane tries another move next step
+                continue;
+            }
+            self.commit(lane, m);
+        }
+        going
+    }
+
+    /// Plays until every lane is decided and returns their outcomes.
+    pub fn run(mut self) -> Vec<Outcome> {
+        while self.step() {}
+        self.outcomes
+            .into_iter()
+            .map(|outcome| outcome.expect("every lane decided"))
+            .collect()
+    }
+
+    // The bitboards of one lane by color and role
+    fn board(&self, lane: usize) -> ([u64; 2], [u64; 6]) {
+        let mut roles = [0; 6];
+        for (index, bits) in roles.iter_mut().enumerate() {
+            *bits = self.roles[index][lane];
+        }
+        ([self.colors[0][lane], self.colors[1][lane]], roles)
+    }
+
+    fn turn(&self, lane: usize) -> Color {
+        Color::from_white(self.white_to_move[lane])
+    }
+
+    // Squares attacked by the side not to move in every lane, on the boards after the
+    // picked moves. Branch free, so it vectorizes
+    fn attack_pass(&mut self) {
+        let [white, black] = &self.next_colors;
+        let roles = &self.next_roles;
+        let (pawns, knights, bishops, rooks, queens, kings) = (
+            &roles[0], &roles[1], &roles[2], &roles[3], &roles[4], &roles[5],
+        );
+        for (lane, attacked) in self.attacked.iter_mut().enumerate() {
+            // The opponent of the mover is white when black is to move
+            let white_attacks = !self.white_to_move[lane];
+            let mask = 0u64.wrapping_sub(u64::from(white_attacks));
+            let them = (white[lane] & mask) | (black[lane] & !mask);
+            let occupied = white[lane] | black[lane];
+            let lane_roles = [
+                pawns[lane],
+                knights[lane],
+                bishops[lane],
+                rooks[lane],
+                queens[lane],
+                kings[lane],
+            ];
+            *attacked = attacked_by_masked(them, occupied, &lane_roles, mask);
+        }
+    }
+
+    fn commit(&mut self, lane: usize, m: BatchMove) {
+        let us = side(self.turn(lane));
+        let them = 1 - us;
+        // A capture goes into our pocket
+        if m.from != DROP {
+            let to = 1u64 << m.to;
+            if self.colors[them][lane] & to != 0 {
+                let captured = (0..POCKET_ROLES).find(|&index| self.roles[index][lane] & to != 0);
+                if let Some(index) = captured {
+                    self.pockets[us][index][lane] = self.pockets[us][index][lane].saturating_add(1);
+                }
+            }
+        } else if let Some(role) = m.role {
+            let index = role as usize - 1;
+            self.pockets[us][index][lane] -= 1;
+        }
+        self.colors[0][lane] = self.next_colors[0][lane];
+        self.colors[1][lane] = self.next_colors[1][lane];
+        for index in 0..6 {
+            self.roles[index][lane] = self.next_roles[index][lane];
+        }
+        self.white_to_move[lane] = !self.white_to_move[lane];
+        self.plies[lane] += 1;
+        self.untried[lane] = None;
+    }
+
+    // Mated if in check, otherwise stalemated, which is a draw
+    fn no_moves_outcome(&self, lane: usize) -> Outcome {
+        let us = self.turn(lane);
+        let (colors, roles) = self.board(lane);
+        let attacked = attacked_by(
+            colors[side(!us)],
+            colors[0] | colors[1],
+            &roles,
+            !us == Color::White,
+        );
+        if attacked & roles[5] & colors[side(us)] != 0 {
+            Outcome::Decisive { winner: !us }
+        } else {
+            Outcome::Draw
+        }
+    }
+
+    // Material of white minus that of black, board and pockets, in pawns
+    fn material_balance(&self, lane: usize) -> i32 {
+        let mut balance = 0;
+        for (index, value) in [1, 3, 3, 5, 9].iter().enumerate() {
+            let on_board = |color: usize| self.roles[index][lane] & self.colors[color][lane];
+            let count = |color: usize| {
+                on_board(color).count_ones() as i32 + i32::from(self.pockets[color][index][lane])
+            };
+            balance += value * (count(0) - count(1));
+        }
+        balance
+    }
+
+    fn pseudo_legal_moves(&self, lane: usize) -> Vec<BatchMove> {
+        let us = self.turn(lane);
+        let own = Bitboard(self.colors[side(us)][lane]);
+        let theirs = Bitboard(self.colors[side(!us)][lane]);
+        let occupied = own | theirs;
+        let mut moves = Vec::with_capacity(64);
+        for (index, &role) in ROLES.iter().enumerate() {
+            for from in own & Bitboard(self.roles[index][lane]) {
+                let targets = match role {
+                    Role::Pawn => {
+                        let mut targets = attacks::pawn_attacks(us, from) & theirs;
+                        let forward = |square: Square| square.offset(us.fold(8, -8));
+                        if let Some(one) = forward(from).filter(|&to| !occupied.contains(to)) {
+                            targets.add(one);
+                            let start = Bitboard::relative_rank(us, shakmaty::Rank::Second);
+                            if let Some(two) = forward(one).filter(|_| start.contains(from)) {
+                                if !occupied.contains(two) {
+                                    targets.add(two);
+                                }
+                            }
+                        }
+                        targets
+                    }
+                    _ => attacks::attacks(from, role.of(us), occupied) & !own,
+                };
+                for to in targets {
+                    let promotes = role == Role::Pawn && Bitboard(BACKRANKS).contains(to);
+                    if promotes {
+                        for promotion in [Role::Knight, Role::Bishop, Role::Rook, Role::Queen]
+                            .iter()
+                            .copied()
+                        {
+                            moves.push(BatchMove {
+                                from: u8::from(from),
+                                to: u8::from(to),
+                                role: Some(promotion),
+                            });
+                        }
+                    } else {
+                        moves.push(BatchMove {
+                            from: u8::from(from),
+                            to: u8::from(to),
+                            role: None,
+                        });
+                    }
+                }
+            }
+        }
+        for (index, &role) in ROLES[..POCKET_ROLES].iter().enumerate() {
+            if self.pockets[side(us)][index][lane] == 0 {
+                continue;
+            }
+            let mut squares = !occupied;
+            if role == Role::Pawn {
+                squares &= !Bitboard(BACKRANKS);
+            }
+            for to in squares {
+                moves.push(BatchMove {
+                    from: DROP,
+                    to: u8::from(to),
+                    role: Some(role),
+                });
+            }
+        }
+        moves
+    }
+
+    fn to_move(&self, lane: usize, m: BatchMove) -> Move {
+        let to = Square::new(u32::from(m.to));
+        if m.from == DROP {
+            return Move::Put {
+                role: m.role.expect("dropped role"),
+                to,
+            };
+        }
+        let role_at = |square: Square| {
+            ROLES
+                .iter()
+                .enumerate()
+                .find(|&(index, _)| self.roles[index][lane] & (1u64 << u32::from(square)) != 0)
+                .map(|(_, &role)| role)
+        };
+        let from = Square::new(u32::from(m.from));
+        Move::Normal {
+            role: role_at(from).expect("piece on from square"),
+            from,
+            capture: role_at(to),
+            to,
+            promotion: m.role,
+        }
+    }
+}
+
+// Index of `color` in the arrays of the batch, white first
+fn side(color: Color) -> usize {
+    color.fold(0, 1)
+}
+
+// Plays `m` for `us` on one lane's bitboards
+fn apply(colors: &mut [u64; 2], roles: &mut [u64; 6], us: Color, m: BatchMove) {
+    let to = 1u64 << m.to;
+    let (own, theirs) = (side(us), side(!us));
+    if m.from == DROP {
+        let index = m.role.expect("dropped role") as usize - 1;
+        colors[own] |= to;
+        roles[index] |= to;
+        return;
+    }
+    let from = 1u64 << m.from;
+    colors[theirs] &= !to;
+    for bits in roles.iter_mut() {
+        *bits &= !to;
+    }
+    let index = (0..6)
+        .find(|&index| roles[index] & from != 0)
+        .expect("piece to move");
+    roles[index] &= !from;
+    let landing = m.role.map_or(index, |promotion| promotion as usize - 1);
+    roles[landing] |= to;
+    colors[own] = (colors[own] & !from) | to;
+}
+
+// Squares attacked by the pieces in `them`, whose pawns are white ones if
+// `white_pawns` is all ones and black ones if it is zero
+#[inline(always)]
+fn attacked_by_masked(them: u64, occupied: u64, roles: &[u64; 6], white_pawns: u64) -> u64 {
+    let pawns = roles[0] & them;
+    let white_pawn_attacks = ((pawns << 7) & !FILE_H) | ((pawns << 9) & !FILE_A);
+    let black_pawn_attacks = ((pawns >> 9) & !FILE_H) | ((pawns >> 7) & !FILE_A);
+    let pawn_attacks = (white_pawn_attacks & white_pawns) | (black_pawn_attacks & !white_pawns);
+    let knights = roles[1] & them;
+    let knight_attacks = {
+        let l1 = (knights >> 1) & !FILE_H;
+        let l2 = (knights >> 2) & !(FILE_H | FILE_H >> 1);
+        let r1 = (knights << 1) & !FILE_A;
+        let r2 = (knights << 2) & !(FILE_A | FILE_A << 1);
+        let h1 = l1 | r1;
+        let h2 = l2 | r2;
+        (h1 << 16) | (h1 >> 16) | (h2 << 8) | (h2 >> 8)
+    };
+    let king = roles[5] & them;
+    let king_attacks = {
+        let sides = king | ((king << 1) & !FILE_A) | ((king >> 1) & !FILE_H);
+        (sides | (sides << 8) | (sides >> 8)) & !king
+    };
+    let empty = !occupied;
+    let straight = (roles[3] | roles[4]) & them;
+    let diagonal = (roles[2] | roles[4]) & them;
+    let straight_attacks = slide(straight, empty, !0, |b| b << 8)
+        | slide(straight, empty, !0, |b| b >> 8)
+        | slide(straight, empty, !FILE_A, |b| b << 1)
+        | slide(straight, empty, !FILE_H, |b| b >> 1);
+    let diagonal_attacks = slide(diagonal, empty, !FILE_A, |b| b << 9)
+        | slide(diagonal, empty, !FILE_H, |b| b << 7)
+        | slide(diagonal, empty, !FILE_A, |b| b >> 7)
+        | slide(diagonal, empty, !FILE_H, |b| b >> 9);
+    pawn_attacks | knight_attacks | king_attacks | straight_attacks | diagonal_attacks
+}
+
+fn attacked_by(them: u64, occupied: u64, roles: &[u64; 6], white: bool) -> u64 {
+    attacked_by_masked(them, occupied, roles, 0u64.wrapping_sub(u64::from(white)))
+}
+
+// Squares the sliders reach in one direction, stopping at the first piece, by
+// Kogge-Stone occluded fill. `wrap` removes the squares a step would wrap around to
+#[inline(always)]
+fn slide(sliders: u64, empty: u64, wrap: u64, step: impl Fn(u64) -> u64) -> u64 {
+    let mut reached = sliders;
+    let mut open = empty & wrap;
+    reached |= open & step(reached);
+    open &= step(open);
+    reached |= open & step(step(reached));
+    open &= step(step(open));
+    reached |= open & step(step(step(step(reached))));
+    step(reached) & wrap
+}
+
+fn adjudicate(balance: i32) -> Outcome {
+    if balance >= ADJUDICATION_MARGIN {
+        Outcome::Decisive {
+            winner: Color::White,
+        }
+    } else if balance <= -ADJUDICATION_MARGIN {
+        Outcome::Decisive {
+            winner: Color::Black,
+        }
+    } else {
+        Outcome::Draw
+    }
+}
+
+/// One uniformly random playout of `position` with full rules, decided like those of
+/// [`PlayoutBatch`] once `max_plies` plies are played. The scalar path the batch is
+/// measured against.
+pub fn scalar_playout<R: Rng>(position: &Bughouse, max_plies: u32, rng: &mut R) -> Outcome {
+    let mut position = position.clone();
+    for _ in 0..max_plies {
+        if let Some(outcome) = position.outcome() {
+            return outcome;
+        }
+        let moves = position.legal_moves();
+        let m = match moves.choose(rng) {
+            Some(m) => m.clone(),
+            None => return Outcome::Draw,
+        };
+        position.play_unchecked(&m);
+    }
+    if let Some(outcome) = position.outcome() {
+        return outcome;
+    }
+    let board = position.board();
+    let mut balance = 0;
+    for (role, value) in ROLES[..POCKET_ROLES].iter().zip([1, 3, 3, 5, 9].iter()) {
+        let count = |color: Color| {
+            board.by_piece(Piece { color, role: *role }).count() as i32
+                + position.pockets().map_or(0, |pockets| {
+                    i32::from(pockets.by_color(color).by_role(*role))
+                })
+        };
+        balance += value * (count(Color::White) - count(Color::Black));
+    }
+    adjudicate(balance)
+}
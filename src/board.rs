@@ -1,20 +1,217 @@
-use std::ops::Add;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
+use shakmaty::fen::{Fen, FenOpts, ParseFenError};
+use shakmaty::uci::Uci;
 use shakmaty::{
     attacks, Bitboard, Board, ByColor, Castles, CastlingMode, CastlingSide, Chess, Color,
-    FromSetup, Material, MaterialSide, Move, MoveList, Outcome, PositionErrorKinds, Rank,
-    RemainingChecks, Role, Square,
+    FromSetup, Material, Move, MoveList, Outcome, Piece, PositionErrorKinds, Rank, RemainingChecks,
+    Role, Square,
 };
 use shakmaty::{Position, Setup};
 
+use crate::drops::Pockets;
+
+const POSITION_ERRORS: [(PositionErrorKinds, &str); 10] = [
+    (PositionErrorKinds::EMPTY_BOARD, "empty board"),
+    (PositionErrorKinds::MISSING_KING, "missing king"),
+    (PositionErrorKinds::TOO_MANY_KINGS, "too many kings"),
+    (PositionErrorKinds::PAWNS_ON_BACKRANK, "pawns on back rank"),
+    (
+        PositionErrorKinds::INVALID_CASTLING_RIGHTS,
+        "invalid castling rights",
+    ),
+    (
+        PositionErrorKinds::INVALID_EP_SQUARE,
+        "invalid en passant square",
+    ),
+    (PositionErrorKinds::OPPOSITE_CHECK, "opposite check"),
+    (PositionErrorKinds::IMPOSSIBLE_CHECK, "impossible check"),
+    (
+        PositionErrorKinds::IMPOSSIBLE_MATERIAL,
+        "impossible material",
+    ),
+    (PositionErrorKinds::VARIANT, "variant rules"),
+];
+
+/// Why [`Bughouse::from_fen`] rejected a FEN.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// The text is not a FEN at all
+    Syntax(ParseFenError),
+    /// A well-formed FEN of a position that breaks the rules
+    Position(PositionErrorKinds),
+}
+
+impl FenError {
+    /// Names of the error categories, one per problem found.
+    pub fn categories(&self) -> Vec<&'static str> {
+        match self {
+            FenError::Syntax(err) => vec![match err {
+                ParseFenError::InvalidFen => "syntax: fen",
+                ParseFenError::InvalidBoard => "syntax: board",
+                ParseFenError::InvalidPocket => "syntax: pocket",
+                ParseFenError::InvalidTurn => "syntax: turn",
+                ParseFenError::InvalidCastling => "syntax: castling",
+                ParseFenError::InvalidEpSquare => "syntax: en passant square",
+                ParseFenError::InvalidRemainingChecks => "syntax: remaining checks",
+                ParseFenError::InvalidHalfmoveClock => "syntax: halfmove clock",
+                ParseFenError::InvalidFullmoves => "syntax: fullmoves",
+            }],
+            FenError::Position(kinds) => POSITION_ERRORS
+                .iter()
+                .filter(|(kind, _)| kinds.contains(*kind))
+                .map(|&(_, name)| name)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.categories().join(", "))
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Parses a crazyhouse FEN with standard castling, see [`Bughouse::from_fen`].
+pub fn parse_fen(text: &str) -> Result<Bughouse, FenError> {
+    Bughouse::from_fen(text, CastlingMode::Standard)
+}
+
+/// A move that can't be played where it was given, as it was written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IllegalMove(pub String);
+
+impl IllegalMove {
+    /// `m` written in UCI notation.
+    pub fn of(m: &Move) -> IllegalMove {
+        IllegalMove(Uci::from_standard(m).to_string())
+    }
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal move: {}", self.0)
+    }
+}
+
+impl std::error::Error for IllegalMove {}
+
+#[derive(Debug)]
 pub struct BughousePositionError {
     errors: PositionErrorKinds,
 }
 
+impl BughousePositionError {
+    pub fn kinds(&self) -> PositionErrorKinds {
+        self.errors
+    }
+}
+
+/// Optional rule changes. The default is plain crazyhouse rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rules {
+    /// Sides that may pass instead of moving, for handicap training games
+    pub pass: ByColor<bool>,
+    /// Three-check crazyhouse: giving the third check wins
+    pub three_check: bool,
+    /// A pawn dropped on its second rank that then advances two squares can't be taken
+    /// en passant. Servers differ here; lichess allows the capture
+    pub drop_blocks_en_passant: bool,
+    /// A pawn dropped on its second rank may only advance one square at a time
+    pub dropped_pawns_step_once: bool,
+    /// Pawns can't be dropped on the rank before promotion
+    pub no_pawn_drops_on_seventh: bool,
+    /// Standard chess: captured pieces leave the game instead of going to the pocket,
+    /// so nothing is ever dropped, and games are drawn by insufficient material or
+    /// after fifty moves without a capture or pawn move
+    pub standard_chess: bool,
+}
+
+impl Rules {
+    /// Squares `color` may drop pawns on. The back ranks are always excluded.
+    pub fn pawn_drop_squares(&self, color: Color) -> Bitboard {
+        let mut squares = !Bitboard::BACKRANKS;
+        if self.no_pawn_drops_on_seventh {
+            squares &= !Bitboard::relative_rank(color, Rank::Seventh);
+        }
+        squares
+    }
+}
+
+/// The drop and pawn rules a server plays by, so a position can be set up to match it
+/// without choosing each rule. Passing and three-check are not part of a preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RulePreset {
+    /// Everything allowed, the default rules
+    #[default]
+    LichessCrazyhouse,
+    /// Dropped pawns can't be taken en passant
+    FicsBughouse,
+    /// No pawn drops on the seventh rank, and dropped pawns step once
+    ChessComBughouse,
+}
+
+pub const PRESETS: [RulePreset; 3] = [
+    RulePreset::LichessCrazyhouse,
+    RulePreset::FicsBughouse,
+    RulePreset::ChessComBughouse,
+];
+
+impl RulePreset {
+    pub fn rules(self) -> Rules {
+        match self {
+            RulePreset::LichessCrazyhouse => Rules::default(),
+            RulePreset::FicsBughouse => Rules {
+                drop_blocks_en_passant: true,
+                ..Rules::default()
+            },
+            RulePreset::ChessComBughouse => Rules {
+                dropped_pawns_step_once: true,
+                no_pawn_drops_on_seventh: true,
+                ..Rules::default()
+            },
+        }
+    }
+}
+
+impl FromStr for RulePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RulePreset, String> {
+        PRESETS
+            .iter()
+            .copied()
+            .find(|preset| preset.to_string() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown rule preset: {}", s.trim()))
+    }
+}
+
+impl fmt::Display for RulePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RulePreset::LichessCrazyhouse => "lichess-crazyhouse",
+            RulePreset::FicsBughouse => "fics-bughouse",
+            RulePreset::ChessComBughouse => "chess.com-bughouse",
+        })
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Bughouse {
     chess: Chess,
-    pockets: Material,
+    pockets: Pockets,
+    rules: Rules,
+    // Only meaningful with `Rules::three_check`
+    remaining_checks: ByColor<RemainingChecks>,
+    // Pawns that entered the board by a drop and haven't moved since. Unknown for
+    // positions set up from a FEN, which are treated as having none
+    dropped_pawns: Bitboard,
+    // Result of the whole bughouse game once it was decided, possibly on the other board
+    match_result: Option<Outcome>,
 }
 
 impl Setup for Bughouse {
@@ -22,7 +219,7 @@ impl Setup for Bughouse {
         self.chess.board()
     }
     fn pockets(&self) -> Option<&Material> {
-        Some(&self.pockets)
+        Some(self.pockets.material())
     }
     fn turn(&self) -> Color {
         self.chess.turn()
@@ -34,7 +231,11 @@ impl Setup for Bughouse {
         self.chess.ep_square()
     }
     fn remaining_checks(&self) -> Option<&ByColor<RemainingChecks>> {
-        None
+        if self.rules.three_check {
+            Some(&self.remaining_checks)
+        } else {
+            None
+        }
     }
     fn halfmoves(&self) -> u32 {
         self.chess.halfmoves()
@@ -49,125 +250,915 @@ impl Bughouse {
         setup: &dyn Setup,
         mode: CastlingMode,
     ) -> Result<Bughouse, BughousePositionError> {
-        let chess = Chess::from_setup(setup, mode)
-            .map_err(|e| BughousePositionError { errors: e.kinds() })?;
-        let mut errors: PositionErrorKinds = PositionErrorKinds::empty();
-
-        let pockets = setup.pockets().cloned().unwrap_or_default();
-        if pockets
-            .count()
-            .saturating_add(chess.board().occupied().count())
-            > 64
-        {
-            errors |= PositionErrorKinds::VARIANT;
-        } else if pockets.white.kings > 0 || pockets.black.kings > 0 {
-            errors |= PositionErrorKinds::TOO_MANY_KINGS;
-        }
-
-        if pockets
-            .count()
-            .saturating_add(chess.board().occupied().count())
-            <= 64
-            && usize::from(pockets.white.pawns.saturating_add(pockets.black.pawns))
-                .saturating_add(chess.board().pawns().count())
-                <= 32
-        {
-            errors &= !PositionErrorKinds::IMPOSSIBLE_MATERIAL;
-        }
+        // Material standard chess considers impossible is checked against the pockets
+        let (chess, errors) = match Chess::from_setup(setup, mode) {
+            Ok(chess) => (chess, PositionErrorKinds::empty()),
+            Err(err) => {
+                let errors = err.kinds();
+                match err.ignore_impossible_material() {
+                    Ok(chess) => (chess, errors),
+                    Err(err) => {
+                        return Err(BughousePositionError {
+                            errors: err.kinds(),
+                        })
+                    }
+                }
+            }
+        };
+        let pockets = Pockets::new(setup.pockets().cloned().unwrap_or_default());
+        let errors = pockets.validate(chess.board(), errors);
 
         if errors != PositionErrorKinds::empty() {
             Err(BughousePositionError { errors })
         } else {
-            Ok(Bughouse { chess, pockets })
+            // A remaining checks field turns on three-check rules
+            let remaining_checks = setup.remaining_checks().cloned();
+            Ok(Bughouse {
+                chess,
+                pockets,
+                rules: Rules {
+                    three_check: remaining_checks.is_some(),
+                    ..Rules::default()
+                },
+                remaining_checks: remaining_checks.unwrap_or_default(),
+                dropped_pawns: Bitboard(0),
+                match_result: None,
+            })
         }
     }
 
-    fn our_pocket(&self) -> &MaterialSide {
-        self.pockets.by_color(self.turn())
+    /// Parses a crazyhouse FEN. Pockets come in brackets after the board,
+    /// `...RNBQKBNR[QRbn] w KQkq - 0 1`, or as a ninth rank as some servers write them,
+    /// and pieces marked `~` are promoted ones that go back to the pocket as pawns.
+    pub fn from_fen(text: &str, mode: CastlingMode) -> Result<Bughouse, FenError> {
+        let setup = Fen::from_ascii(text.trim().as_bytes()).map_err(FenError::Syntax)?;
+        Bughouse::from_setup(&setup, mode).map_err(|err| FenError::Position(err.kinds()))
     }
 
-    fn our_pocket_mut(&mut self) -> &mut MaterialSide {
-        let turn = self.turn();
-        self.pockets.by_color_mut(turn)
+    /// The FEN of the position with the pockets in brackets and promoted pieces marked
+    /// `~`, which [`Bughouse::from_fen`] reads back into the same position.
+    pub fn fen(&self) -> String {
+        FenOpts::new().promoted(true).fen(self)
     }
 
-    fn legal_put_squares(&self) -> Bitboard {
-        let checkers = self.checkers();
+    /// The same position with the colors swapped and the board flipped vertically. The
+    /// move counters restart; the rules are kept. Evaluation and search should treat
+    /// both alike.
+    pub fn mirrored(&self) -> Bughouse {
+        let mut board = Board::empty();
+        for (square, piece, promoted) in self.pieces() {
+            board.set_piece_at(
+                square.flip_vertical(),
+                piece.role.of(!piece.color),
+                promoted,
+            );
+        }
+        let swap = |pockets: &Material| ByColor {
+            white: pockets.black.clone(),
+            black: pockets.white.clone(),
+        };
+        let setup = Fen {
+            board,
+            pockets: Some(swap(self.pockets.material())),
+            turn: !self.turn(),
+            castling_rights: self.castling_rights().flip_vertical(),
+            ep_square: self.ep_square().map(Square::flip_vertical),
+            remaining_checks: None,
+            ..Fen::default()
+        };
+        let mirrored = Bughouse::from_setup(&setup, CastlingMode::Standard)
+            .expect("the mirror image of a legal position is legal");
+        Bughouse {
+            rules: self.rules.clone(),
+            remaining_checks: ByColor {
+                white: self.remaining_checks.black,
+                black: self.remaining_checks.white,
+            },
+            dropped_pawns: self.dropped_pawns.flip_vertical(),
+            match_result: self.match_result.map(|outcome| match outcome {
+                Outcome::Decisive { winner } => Outcome::Decisive { winner: !winner },
+                Outcome::Draw => Outcome::Draw,
+            }),
+            ..mirrored
+        }
+    }
 
-        if checkers.is_empty() {
-            !self.board().occupied()
-        } else if let Some(checker) = checkers.single_square() {
-            let king = self
-                .board()
-                .king_of(self.turn())
-                .expect("king in crazyhouse");
-            attacks::between(checker, king)
-        } else {
-            Bitboard(0)
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Pawns that were dropped and haven't moved since, which a FEN doesn't record.
+    pub fn dropped_pawns(&self) -> Bitboard {
+        self.dropped_pawns
+    }
+
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Switches to the rules of `preset`, keeping passing, three-check and standard
+    /// chess as they are.
+    pub fn with_preset(self, preset: RulePreset) -> Self {
+        let rules = Rules {
+            pass: self.rules.pass.clone(),
+            three_check: self.rules.three_check,
+            standard_chess: self.rules.standard_chess,
+            ..preset.rules()
+        };
+        self.with_rules(rules)
+    }
+
+    /// Checks each side still has to give to win, when playing three-check.
+    pub fn checks_remaining(&self, color: Color) -> Option<RemainingChecks> {
+        self.remaining_checks()
+            .map(|remaining| *remaining.by_color(color))
+    }
+
+    /// Whether the side to move may pass. Passing is never allowed while in check.
+    pub fn can_pass(&self) -> bool {
+        *self.rules.pass.by_color(self.turn()) && !self.is_check() && !self.is_game_over()
+    }
+
+    /// Passes the turn to the opponent, written `--` in SAN and `0000` in UCI.
+    /// Returns `false` and leaves the position unchanged if passing is not allowed.
+    pub fn pass(&mut self) -> bool {
+        if !self.can_pass() {
+            return false;
+        }
+        *self = self.swapped_turn().expect("not in check");
+        true
+    }
+
+    /// The same position with the other side to move, regardless of the rules, for
+    /// asking what the opponent threatens. `None` when in check.
+    pub fn swapped_turn(&self) -> Option<Bughouse> {
+        if self.is_check() {
+            return None;
+        }
+        let chess = match self.chess.clone().swap_turn() {
+            Ok(chess) => chess,
+            // Drops and promotions routinely produce more pieces than standard chess allows
+            Err(err) => err
+                .ignore_impossible_material()
+                .expect("swapping the turn of a legal position without check is legal"),
+        };
+        Some(Bughouse {
+            chess,
+            ..self.clone()
+        })
+    }
+
+    /// Counts the positions `depth` plies ahead, drops included, for checking move
+    /// generation against known node counts. Passes are not counted.
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|m| {
+                let mut child = self.clone();
+                child.play_unchecked(m);
+                child.perft(depth - 1)
+            })
+            .sum()
+    }
+
+    /// Moves of the side to move that checkmate immediately.
+    pub fn mating_moves(&self) -> Vec<Move> {
+        self.legal_moves()
+            .into_iter()
+            .filter(|m| {
+                let mut after = self.clone();
+                after.play_unchecked(m);
+                after.is_checkmate()
+            })
+            .collect()
+    }
+
+    /// Squares where `color` could drop a piece from its pocket and mate at once if it
+    /// were its move, the drop mate threats against the other side. Empty if the side
+    /// to move is in check and `color` is not to move.
+    pub fn drop_mates(&self, color: Color) -> Bitboard {
+        if color == self.turn() {
+            return self.drop_mate_squares();
+        }
+        match self.swapped_turn() {
+            Some(swapped) => swapped.drop_mate_squares(),
+            None => Bitboard(0),
+        }
+    }
+
+    // Drop mates of the side to move. Only checking drops can mate, and a piece checks
+    // from the squares a piece of its kind on the king's square would attack
+    fn drop_mate_squares(&self) -> Bitboard {
+        let us = self.turn();
+        let board = self.board();
+        let king = match board.king_of(!us) {
+            Some(king) => king,
+            None => return Bitboard(0),
+        };
+        let mut squares = Bitboard(0);
+        for role in [
+            Role::Pawn,
+            Role::Knight,
+            Role::Bishop,
+            Role::Rook,
+            Role::Queen,
+        ]
+        .iter()
+        .copied()
+        {
+            if self.pockets.side(us).by_role(role) == 0 {
+                continue;
+            }
+            let checks = match role {
+                Role::Pawn => attacks::pawn_attacks(!us, king),
+                _ => attacks::attacks(king, role.of(us), board.occupied()),
+            } & !board.occupied();
+            for to in checks {
+                let m = Move::Put { role, to };
+                if self.is_legal(&m) {
+                    let mut after = self.clone();
+                    after.play_unchecked(&m);
+                    if after.is_checkmate() {
+                        squares.add(to);
+                    }
+                }
+            }
+        }
+        squares
+    }
+
+    /// Whether the side to move may drop `role` on `to`, without generating all moves,
+    /// for hints while a piece is dragged out of the pocket.
+    pub fn can_drop(&self, role: Role, to: Square) -> bool {
+        !self.is_variant_end()
+            && self.pockets.can_drop(
+                self.turn(),
+                role,
+                to,
+                Pockets::legal_drop_squares(self),
+                self.rules.pawn_drop_squares(self.turn()),
+            )
+    }
+
+    /// What moving the piece on `from` to `to` would do, without generating all moves,
+    /// for hints while a piece is dragged across the board. Dragging the king onto its
+    /// castling square or onto the rook both castle.
+    pub fn can_move(&self, from: Square, to: Square) -> MoveProbe {
+        let role = match self.board().piece_at(from) {
+            Some(piece) if piece.color == self.turn() && !self.is_variant_end() => piece.role,
+            _ => return MoveProbe::Illegal,
+        };
+        if role == Role::King {
+            for side in [CastlingSide::KingSide, CastlingSide::QueenSide] {
+                let castle = self.castling_moves(side).into_iter().find(|m| match *m {
+                    Move::Castle { king, rook } => {
+                        king == from && (to == rook || to == side.king_to(self.turn()))
+                    }
+                    _ => false,
+                });
+                if let Some(castle) = castle {
+                    return MoveProbe::Legal(castle);
+                }
+            }
+        }
+        let mut candidates = self.san_candidates(role, to);
+        candidates.retain(|m| m.from() == Some(from));
+        match candidates.len() {
+            0 => MoveProbe::Illegal,
+            1 => MoveProbe::Legal(candidates.swap_remove(0)),
+            _ => MoveProbe::Promotion,
+        }
+    }
+
+    // The fifty-move rule of standard chess, applied without waiting for a claim
+    fn is_fifty_move_draw(&self) -> bool {
+        self.rules.standard_chess && self.halfmoves() >= 100
+    }
+
+    /// The same position with `pockets` in hand instead, for records that track the
+    /// holdings separately from the moves.
+    pub fn with_pockets(&self, pockets: Material) -> Result<Bughouse, BughousePositionError> {
+        let pockets = Pockets::new(pockets);
+        let errors = pockets.validate(self.board(), PositionErrorKinds::empty());
+        if errors != PositionErrorKinds::empty() {
+            return Err(BughousePositionError { errors });
         }
+        Ok(Bughouse {
+            pockets,
+            ..self.clone()
+        })
+    }
+
+    /// Plays `m` by bughouse rules, where a captured piece goes to the partner on the
+    /// other board instead of into our pocket. Returns the role to pass on.
+    pub fn play_passing_captures(&mut self, m: &Move) -> Option<Role> {
+        let turn = self.turn();
+        let captured = Pockets::captured_role(self.board(), m);
+        self.play_unchecked(m);
+        if let Some(role) = captured {
+            self.pockets.remove(turn, role);
+        }
+        captured
+    }
+
+    /// Ends the game on this board with `outcome`, as when the bughouse game it is part
+    /// of was decided. No moves are legal afterwards.
+    pub fn end_match(&mut self, outcome: Outcome) {
+        self.match_result = Some(outcome);
+    }
+
+    /// Adds a piece passed on by the partner to the pocket of `color`.
+    pub fn receive(&mut self, color: Color, role: Role) {
+        self.pockets.add(color, role);
+    }
+
+    /// Takes a piece out of the pocket of `color`, e.g. one that was counted there but
+    /// belongs to the partner. Returns `false` if there is none.
+    pub fn remove_from_pocket(&mut self, color: Color, role: Role) -> bool {
+        self.pockets.remove(color, role)
+    }
+
+    /// All pieces on the board with whether each was promoted, which matters in
+    /// crazyhouse since a captured promoted piece goes to the pocket as a pawn. Pieces
+    /// come in square order, a1, b1, ..., h8, so serializations built from them are
+    /// deterministic.
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, Piece, bool)> + '_ {
+        let board = self.board();
+        board.occupied().into_iter().filter_map(move |square| {
+            let piece = board.piece_at(square)?;
+            Some((square, piece, board.promoted().contains(square)))
+        })
     }
 
     pub fn add_material(mut self, material: Material) -> Self {
-        self.pockets = self.pockets.add(material);
+        self.pockets.add_material(material);
         self
     }
+
+    /// Generates moves that obey piece movement rules but may leave our king in check.
+    /// Castling and en passant moves are always fully legal. Filter the result with
+    /// [`Bughouse::legality_filter`], or use [`Position::legal_moves`] to do both.
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        let board = self.board();
+        let turn = self.turn();
+        let target = !self.us();
+
+        for from in self.us() & !board.pawns() {
+            let role = board.role_at(from).expect("piece on occupied square");
+            for to in board.attacks_from(from) & target {
+                moves.push(Move::Normal {
+                    role,
+                    from,
+                    capture: board.role_at(to),
+                    to,
+                    promotion: None,
+                });
+            }
+        }
+
+        for from in self.our(Role::Pawn) {
+            for to in attacks::pawn_attacks(turn, from) & self.them() {
+                push_pawn_move(&mut moves, from, to, board.role_at(to));
+            }
+        }
+        let single_moves = self.our(Role::Pawn).relative_shift(turn, 8) & !board.occupied();
+        let mut double_step_pawns = single_moves;
+        if self.rules.dropped_pawns_step_once {
+            double_step_pawns &= !self.dropped_pawns.relative_shift(turn, 8);
+        }
+        let double_moves = double_step_pawns.relative_shift(turn, 8)
+            & Bitboard::relative_rank(turn, Rank::Fourth)
+            & !board.occupied();
+        for to in single_moves {
+            if let Some(from) = to.offset(turn.fold(-8, 8)) {
+                push_pawn_move(&mut moves, from, to, None);
+            }
+        }
+        for to in double_moves {
+            if let Some(from) = to.offset(turn.fold(-16, 16)) {
+                push_pawn_move(&mut moves, from, to, None);
+            }
+        }
+
+        moves.extend(self.castling_moves(CastlingSide::KingSide));
+        moves.extend(self.castling_moves(CastlingSide::QueenSide));
+        moves.extend(self.en_passant_moves());
+
+        if !self.rules.standard_chess {
+            self.pockets.push_drops(
+                turn,
+                !board.occupied(),
+                self.rules.pawn_drop_squares(turn),
+                &mut moves,
+            );
+        }
+
+        moves
+    }
+
+    /// Prepares the check, pin and drop information needed to decide whether pseudo-legal
+    /// moves of this position are legal.
+    pub fn legality_filter(&self) -> LegalityFilter {
+        let board = self.board();
+        let king = board.king_of(self.turn()).expect("king in crazyhouse");
+        let snipers = (attacks::rook_attacks(king, Bitboard(0)) & board.rooks_and_queens()
+            | attacks::bishop_attacks(king, Bitboard(0)) & board.bishops_and_queens())
+            & self.them();
+        let mut pinned = Bitboard(0);
+        for sniper in snipers {
+            if let Some(blocker) =
+                (attacks::between(sniper, king) & board.occupied()).single_square()
+            {
+                if self.us().contains(blocker) {
+                    pinned.add(blocker);
+                }
+            }
+        }
+
+        LegalityFilter {
+            king,
+            checkers: self.checkers(),
+            pinned,
+            put_squares: Pockets::legal_drop_squares(self),
+        }
+    }
 }
 
-impl Position for Bughouse {
-    fn play_unchecked(&mut self, m: &Move) {
-        match *m {
-            Move::Normal {
-                capture: Some(capture),
-                to,
-                ..
-            } => {
-                let capture = if self.board().promoted().contains(to) {
-                    Role::Pawn
-                } else {
-                    capture
-                };
+// Random keys for Zobrist hashing, from a fixed splitmix64 sequence so hashes are the
+// same in every run
+const ZOBRIST_PIECES: usize = 0; // 2 colors x 6 roles x 64 squares
+const ZOBRIST_PROMOTED: usize = ZOBRIST_PIECES + 2 * 6 * 64;
+const ZOBRIST_DROPPED_PAWNS: usize = ZOBRIST_PROMOTED + 64;
+const ZOBRIST_CASTLING: usize = ZOBRIST_DROPPED_PAWNS + 64;
+const ZOBRIST_EP_FILE: usize = ZOBRIST_CASTLING + 64;
+// 2 colors x 5 roles x counts 0-15; larger counts share the last key
+const ZOBRIST_POCKETS: usize = ZOBRIST_EP_FILE + 8;
+const ZOBRIST_CHECKS: usize = ZOBRIST_POCKETS + 2 * 5 * 16;
+const ZOBRIST_TURN: usize = ZOBRIST_CHECKS + 2 * 4;
+const ZOBRIST_KEYS: [u64; ZOBRIST_TURN + 1] = zobrist_keys();
 
-                *self.our_pocket_mut().by_role_mut(capture) += 1;
+const fn zobrist_keys() -> [u64; ZOBRIST_TURN + 1] {
+    let mut keys = [0; ZOBRIST_TURN + 1];
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut i = 0;
+    while i < keys.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+impl Bughouse {
+    /// A 64 bit Zobrist hash of everything that decides the moves from here: pieces,
+    /// pockets, turn, castling rights, the en passant square, remaining checks and
+    /// which pawns were dropped. Move counters are left out, so transpositions hash
+    /// the same.
+    pub fn zobrist_hash(&self) -> u64 {
+        let board = self.board();
+        let mut hash = 0;
+        for square in board.occupied() {
+            let piece = board.piece_at(square).expect("piece on occupied square");
+            let index = (piece.color.fold(0, 6) + usize::from(piece.role) - 1) * 64;
+            hash ^= ZOBRIST_KEYS[ZOBRIST_PIECES + index + usize::from(square)];
+        }
+        for square in board.promoted() {
+            hash ^= ZOBRIST_KEYS[ZOBRIST_PROMOTED + usize::from(square)];
+        }
+        for square in self.dropped_pawns {
+            hash ^= ZOBRIST_KEYS[ZOBRIST_DROPPED_PAWNS + usize::from(square)];
+        }
+        for square in self.castling_rights() {
+            hash ^= ZOBRIST_KEYS[ZOBRIST_CASTLING + usize::from(square)];
+        }
+        if let Some(square) = self.ep_square() {
+            hash ^= ZOBRIST_KEYS[ZOBRIST_EP_FILE + usize::from(square.file())];
+        }
+        for (side, color) in [Color::White, Color::Black].iter().enumerate() {
+            let pocket = self.pockets.side(*color);
+            for (role, count) in [
+                pocket.pawns,
+                pocket.knights,
+                pocket.bishops,
+                pocket.rooks,
+                pocket.queens,
+            ]
+            .iter()
+            .enumerate()
+            {
+                let count = usize::from(*count).min(15);
+                hash ^= ZOBRIST_KEYS[ZOBRIST_POCKETS + (side * 5 + role) * 16 + count];
             }
-            Move::EnPassant { .. } => {
-                self.our_pocket_mut().pawns += 1;
+            if self.rules.three_check {
+                let checks = usize::from(u8::from(*self.remaining_checks.by_color(*color)));
+                hash ^= ZOBRIST_KEYS[ZOBRIST_CHECKS + side * 4 + checks.min(3)];
+            }
+        }
+        if self.turn() == Color::Black {
+            hash ^= ZOBRIST_KEYS[ZOBRIST_TURN];
+        }
+        hash
+    }
+
+    /// Whether `other` agrees with this position in everything [`Bughouse::zobrist_hash`]
+    /// covers, so a hash match can be confirmed rather than trusted.
+    pub fn transposes_to(&self, other: &Bughouse) -> bool {
+        self.board() == other.board()
+            && self.pockets == other.pockets
+            && self.turn() == other.turn()
+            && self.castling_rights() == other.castling_rights()
+            && self.ep_square().map(Square::file) == other.ep_square().map(Square::file)
+            && self.dropped_pawns == other.dropped_pawns
+            && self.rules.three_check == other.rules.three_check
+            && (!self.rules.three_check || self.remaining_checks == other.remaining_checks)
+    }
+}
+
+impl Bughouse {
+    fn track_dropped_pawns(&mut self, m: &Move) {
+        match *m {
+            Move::Put {
+                role: Role::Pawn,
+                to,
+            } => self.dropped_pawns.add(to),
+            Move::Normal { from, to, .. } => {
+                self.dropped_pawns.discard(from);
+                self.dropped_pawns.discard(to);
             }
-            Move::Put { role, .. } => {
-                *self.our_pocket_mut().by_role_mut(role) -= 1;
+            Move::EnPassant { from, to } => {
+                self.dropped_pawns.discard(from);
+                self.dropped_pawns
+                    .discard(Square::from_coords(to.file(), from.rank()));
             }
             _ => {}
         }
+    }
 
-        self.chess.play_unchecked(m);
+    // Chess has no setter for the en passant square, so the position is set up again
+    fn clear_ep_square(&mut self) {
+        let mut setup = Fen::from_setup(&self.chess);
+        setup.ep_square = None;
+        self.chess = match Chess::from_setup(&setup, CastlingMode::Standard) {
+            Ok(chess) => chess,
+            Err(err) => err
+                .ignore_impossible_material()
+                .expect("position without en passant square is legal"),
+        };
     }
+}
 
-    fn castles(&self) -> &Castles {
-        self.chess.castles()
+fn push_pawn_move(moves: &mut MoveList, from: Square, to: Square, capture: Option<Role>) {
+    if Bitboard::BACKRANKS.contains(to) {
+        for &promotion in &[Role::Queen, Role::Rook, Role::Bishop, Role::Knight] {
+            moves.push(Move::Normal {
+                role: Role::Pawn,
+                from,
+                capture,
+                to,
+                promotion: Some(promotion),
+            });
+        }
+    } else {
+        moves.push(Move::Normal {
+            role: Role::Pawn,
+            from,
+            capture,
+            to,
+            promotion: None,
+        });
     }
+}
 
-    fn legal_moves(&self) -> MoveList {
-        let mut moves = self.chess.legal_moves();
+/// The answer of [`Bughouse::can_move`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveProbe {
+    Illegal,
+    Legal(Move),
+    /// A pawn reaching the last rank, legal once the piece to promote to is chosen
+    Promotion,
+}
 
-        let pocket = self.our_pocket();
-        let targets = self.legal_put_squares();
+/// Decides legality of pseudo-legal moves of one position, see
+/// [`Bughouse::legality_filter`].
+pub struct LegalityFilter {
+    king: Square,
+    checkers: Bitboard,
+    pinned: Bitboard,
+    put_squares: Bitboard,
+}
 
-        for to in targets {
-            for &role in &[Role::Knight, Role::Bishop, Role::Rook, Role::Queen] {
-                if pocket.by_role(role) > 0 {
-                    moves.push(Move::Put { role, to });
+impl LegalityFilter {
+    /// Whether the pseudo-legal move `m` of `position` is legal. `position` must be the
+    /// position this filter was created from.
+    pub fn is_legal(&self, position: &Bughouse, m: &Move) -> bool {
+        match *m {
+            Move::Put { to, .. } => self.put_squares.contains(to),
+            // Generated fully legal
+            Move::Castle { .. } | Move::EnPassant { .. } => true,
+            Move::Normal {
+                role: Role::King,
+                from,
+                to,
+                ..
+            } => position
+                .king_attackers(to, !position.turn(), position.board().occupied() ^ from)
+                .is_empty(),
+            Move::Normal { from, to, .. } => {
+                if self.pinned.contains(from) && !attacks::aligned(from, to, self.king) {
+                    false
+                } else if self.checkers.is_empty() {
+                    true
+                } else if let Some(checker) = self.checkers.single_square() {
+                    to == checker || attacks::between(checker, self.king).contains(to)
+                } else {
+                    false
                 }
             }
         }
+    }
+}
 
-        if pocket.pawns > 0 {
-            for to in targets & !Bitboard::BACKRANKS {
-                moves.push(Move::Put {
-                    role: Role::Pawn,
-                    to,
-                });
+/// One of the two boards of a bughouse game. White on board A plays with black on
+/// board B and the other way around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BoardId {
+    A,
+    B,
+}
+
+impl BoardId {
+    pub fn other(self) -> BoardId {
+        match self {
+            BoardId::A => BoardId::B,
+            BoardId::B => BoardId::A,
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        match self {
+            BoardId::A => 0,
+            BoardId::B => 1,
+        }
+    }
+}
+
+/// What a player does on their turn in a bughouse game. Unlike a pass, waiting is not
+/// a move on the board: the turn stays with the player while their clock runs, usually
+/// until the partner sends the piece they are sitting for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move(Move),
+    Wait,
+}
+
+impl fmt::Display for Action {
+    /// The move in UCI notation, or `wait`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Move(m) => write!(f, "{}", Uci::from_standard(m)),
+            Action::Wait => f.write_str("wait"),
+        }
+    }
+}
+
+/// What came of an [`Action`] on a timed board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Played {
+    /// The move was played, passing on the role it captured, if any
+    Moved(Option<Role>),
+    /// The clock ran while the player waited
+    Waited,
+    /// The player's flag fell first, which lost the game; a move was not played
+    FlagFell,
+    /// The game was already over, with this team result as [`BughouseGame::outcome`]
+    /// gives it; the clock did not run
+    GameOver(Outcome),
+}
+
+/// A bughouse game: two linked boards, each with its own clocks, where pieces
+/// captured on one board go to the capturer's partner on the other.
+#[derive(Clone, Debug)]
+pub struct BughouseGame {
+    boards: [Bughouse; 2],
+    clocks: [ByColor<Duration>; 2],
+    // The board that finished first and how
+    outcome: Option<(BoardId, Outcome)>,
+}
+
+impl BughouseGame {
+    /// Both boards from the start position, every player with `time` on the clock.
+    pub fn new(time: Duration) -> BughouseGame {
+        let clocks = ByColor {
+            white: time,
+            black: time,
+        };
+        BughouseGame::from_boards(
+            Bughouse::default(),
+            Bughouse::default(),
+            clocks.clone(),
+            clocks,
+        )
+    }
+
+    pub fn from_boards(
+        a: Bughouse,
+        b: Bughouse,
+        clocks_a: ByColor<Duration>,
+        clocks_b: ByColor<Duration>,
+    ) -> BughouseGame {
+        BughouseGame {
+            boards: [a, b],
+            clocks: [clocks_a, clocks_b],
+            outcome: None,
+        }
+    }
+
+    /// Both boards switched to the rules of `preset`.
+    pub fn with_preset(mut self, preset: RulePreset) -> Self {
+        self.boards = self.boards.map(|board| board.with_preset(preset));
+        self
+    }
+
+    pub fn board(&self, board: BoardId) -> &Bughouse {
+        &self.boards[board.index()]
+    }
+
+    pub fn clocks(&self, board: BoardId) -> &ByColor<Duration> {
+        &self.clocks[board.index()]
+    }
+
+    /// The board and color of the partner of `color` on `board`.
+    pub fn partner(board: BoardId, color: Color) -> (BoardId, Color) {
+        (board.other(), !color)
+    }
+
+    /// The result of the game for the teams, as seen from board A: `1-0` is a win for
+    /// White on A and Black on B. Checkmate or a flag on either board decides it.
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+            .map(|(board, outcome)| BughouseGame::result_on(board, outcome, BoardId::A))
+    }
+
+    /// The board that decided the game and its result there.
+    pub fn decided_on(&self) -> Option<(BoardId, Outcome)> {
+        self.outcome
+    }
+
+    // A result on `from` as seen from `to`, where partners have opposite colors
+    fn result_on(from: BoardId, outcome: Outcome, to: BoardId) -> Outcome {
+        match outcome {
+            Outcome::Decisive { winner } if from != to => Outcome::Decisive { winner: !winner },
+            outcome => outcome,
+        }
+    }
+
+    // Ends the game on both boards
+    fn finish(&mut self, board: BoardId, outcome: Outcome) {
+        self.outcome = Some((board, outcome));
+        for other in [BoardId::A, BoardId::B] {
+            self.boards[other.index()].end_match(BughouseGame::result_on(board, outcome, other));
+        }
+    }
+
+    /// Runs the clock of the side to move on `board` for `elapsed` without a move, as a
+    /// server does while a player thinks. A flag ends the game on both boards. Returns
+    /// the team result if the game is over.
+    pub fn tick(&mut self, board: BoardId, elapsed: Duration) -> Option<Outcome> {
+        if self.outcome.is_none() {
+            let mover = self.boards[board.index()].turn();
+            let clock = self.clocks[board.index()].by_color_mut(mover);
+            *clock = clock.saturating_sub(elapsed);
+            if clock.is_zero() {
+                self.finish(board, Outcome::Decisive { winner: !mover });
             }
         }
+        self.outcome()
+    }
+
+    /// Carries out `action` on `board` after the player spent `elapsed` on it: plays the
+    /// move as [`BughouseGame::play_timed`] does, or only runs the clock when waiting.
+    /// Waiting once the game is over gives its result.
+    pub fn act(
+        &mut self,
+        board: BoardId,
+        action: &Action,
+        elapsed: Duration,
+    ) -> Result<Played, IllegalMove> {
+        match action {
+            Action::Move(m) => self.play_timed(board, m, elapsed),
+            Action::Wait => {
+                if let Some(outcome) = self.outcome() {
+                    return Ok(Played::GameOver(outcome));
+                }
+                match self.tick(board, elapsed) {
+                    Some(_) => Ok(Played::FlagFell),
+                    None => Ok(Played::Waited),
+                }
+            }
+        }
+    }
+
+    /// Plays `m` on `board` without running the clocks, passing a captured piece to the
+    /// partner. Returns the role passed on, if any.
+    pub fn play(&mut self, board: BoardId, m: &Move) -> Result<Option<Role>, IllegalMove> {
+        self.check_move(board, m)?;
+        Ok(self.play_checked(board, m))
+    }
+
+    /// Plays `m` on `board` after the mover spent `elapsed` on it. Running out of time
+    /// loses the game, and the move is not played.
+    pub fn play_timed(
+        &mut self,
+        board: BoardId,
+        m: &Move,
+        elapsed: Duration,
+    ) -> Result<Played, IllegalMove> {
+        self.check_move(board, m)?;
+        if self.tick(board, elapsed).is_some() {
+            return Ok(Played::FlagFell);
+        }
+        Ok(Played::Moved(self.play_checked(board, m)))
+    }
+
+    // No moves once either board has finished
+    fn check_move(&self, board: BoardId, m: &Move) -> Result<(), IllegalMove> {
+        if self.outcome.is_some() || !self.boards[board.index()].is_legal(m) {
+            return Err(IllegalMove::of(m));
+        }
+        Ok(())
+    }
+
+    // Plays the legal move `m`, passing on what it captures and ending the game if it
+    // decides it
+    fn play_checked(&mut self, board: BoardId, m: &Move) -> Option<Role> {
+        let index = board.index();
+        let position = &mut self.boards[index];
+        let mover = position.turn();
+        let captured = position.play_passing_captures(m);
+        let outcome = position.outcome();
+        if let Some(role) = captured {
+            let (partner_board, partner) = BughouseGame::partner(board, mover);
+            self.boards[partner_board.index()].receive(partner, role);
+        }
+        if let Some(outcome) = outcome {
+            self.finish(board, outcome);
+        }
+        captured
+    }
+}
 
+impl Position for Bughouse {
+    fn play_unchecked(&mut self, m: &Move) {
+        let turn = self.turn();
+        if !self.rules.standard_chess {
+            self.pockets.play(self.chess.board(), turn, m);
+        }
+        let double_step_of_dropped_pawn = match *m {
+            Move::Normal {
+                role: Role::Pawn,
+                from,
+                to,
+                ..
+            } => self.dropped_pawns.contains(from) && from.distance(to) == 2,
+            _ => false,
+        };
+        self.track_dropped_pawns(m);
+        self.chess.play_unchecked(m);
+        if double_step_of_dropped_pawn
+            && self.rules.drop_blocks_en_passant
+            && self.chess.ep_square().is_some()
+        {
+            self.clear_ep_square();
+        }
+        if self.rules.three_check && self.is_check() {
+            let checks = self.remaining_checks.by_color_mut(turn);
+            *checks = checks.minus_one();
+        }
+    }
+
+    fn castles(&self) -> &Castles {
+        self.chess.castles()
+    }
+
+    fn legal_moves(&self) -> MoveList {
+        if self.is_variant_end() {
+            return MoveList::new();
+        }
+        let filter = self.legality_filter();
+        let mut moves = self.pseudo_legal_moves();
+        moves.retain(|m| filter.is_legal(self, m));
         moves
     }
 
@@ -181,11 +1172,22 @@ impl Position for Bughouse {
 
     fn san_candidates(&self, role: Role, to: Square) -> MoveList {
         let mut moves = self.chess.san_candidates(role, to);
+        if self.rules.dropped_pawns_step_once && role == Role::Pawn {
+            moves.retain(|m| match *m {
+                Move::Normal { from, to, .. } => {
+                    !(self.dropped_pawns.contains(from) && from.distance(to) == 2)
+                }
+                _ => true,
+            });
+        }
 
-        if self.our_pocket().by_role(role) > 0
-            && self.legal_put_squares().contains(to)
-            && (role != Role::Pawn || !Bitboard::BACKRANKS.contains(to))
-        {
+        if self.pockets.can_drop(
+            self.turn(),
+            role,
+            to,
+            Pockets::legal_drop_squares(self),
+            self.rules.pawn_drop_squares(self.turn()),
+        ) {
             moves.push(Move::Put { role, to });
         }
 
@@ -208,14 +1210,29 @@ impl Position for Bughouse {
         }
     }
 
-    fn has_insufficient_material(&self, _color: Color) -> bool {
-        false
+    // Pieces in the pocket can always be dropped to mate, so only chess draws this way
+    fn has_insufficient_material(&self, color: Color) -> bool {
+        self.rules.standard_chess && self.chess.has_insufficient_material(color)
     }
 
     fn is_variant_end(&self) -> bool {
-        false
+        self.match_result.is_some()
+            || self.rules.three_check && self.remaining_checks.any(|checks| checks.is_zero())
+            || self.is_fifty_move_draw()
     }
     fn variant_outcome(&self) -> Option<Outcome> {
-        None
+        if self.match_result.is_some() {
+            return self.match_result;
+        }
+        // A mate on the last move still counts
+        if self.is_fifty_move_draw() && !self.chess.is_checkmate() {
+            return Some(Outcome::Draw);
+        }
+        if !self.rules.three_check {
+            return None;
+        }
+        self.remaining_checks
+            .find(|checks| checks.is_zero())
+            .map(|winner| Outcome::Decisive { winner })
     }
 }
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+use shakmaty::fen::epd;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, File, Move, Outcome, Rank, Role, Setup, Square};
+
+use crate::board::parse_fen;
+use crate::board::{BoardId, Bughouse};
+use crate::bpgn::BpgnGame;
+use crate::json::JsonValue;
+use crate::output::{json_array, JsonObject};
+use crate::paths::write_atomic;
+
+// Bytes of a Polyglot entry: key, move, weight and learn field, all big endian
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    /// A Polyglot file whose length is not a whole number of entries
+    Truncated(usize),
+    /// A JSON book, or a position or move in it, that could not be read
+    InvalidJson(String),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io(err) => write!(f, "book file: {}", err),
+            BookError::Truncated(size) => write!(
+                f,
+                "polyglot book of {} bytes is not a whole number of entries",
+                size
+            ),
+            BookError::InvalidJson(what) => write!(f, "invalid json book: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+impl From<io::Error> for BookError {
+    fn from(err: io::Error) -> Self {
+        BookError::Io(err)
+    }
+}
+
+/// A move of the book and how it fared in the games the book was built from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookMove {
+    pub uci: Uci,
+    /// Relative chance of the move being played from the book
+    pub weight: u16,
+    /// Games the move was played in, 0 if unknown
+    pub games: u32,
+    /// Points the mover scored in those games, a draw counting half
+    pub points: f32,
+}
+
+impl BookMove {
+    /// Share of the points the mover scored, `None` without games.
+    pub fn win_rate(&self) -> Option<f32> {
+        (self.games > 0).then(|| self.points / self.games as f32)
+    }
+}
+
+// The book moves of one position, with its FEN where the book format keeps it
+#[derive(Clone, Debug, Default)]
+struct BookPosition {
+    fen: Option<String>,
+    moves: Vec<BookMove>,
+}
+
+/// How a book is built from games.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildConfig {
+    /// Plies of each board that go into the book
+    pub plies: usize,
+    /// Moves played in fewer games are left out
+    pub min_games: u32,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            plies: 16,
+            min_games: 2,
+        }
+    }
+}
+
+/// An opening book for crazyhouse positions, keyed by [`Bughouse::zobrist_hash`], so
+/// the pockets are part of the position.
+///
+/// Books are read and written in two formats:
+///
+/// - Polyglot: 16 byte entries sorted by key, holding the key, the move, its weight
+///   and a learn field. Moves are encoded as in Polyglot, castling as the king taking
+///   its rook, with drops added as moves from the target square to itself whose
+///   promotion bits hold the dropped role, pawn 1 to queen 5. The learn field holds
+///   the games in its upper and the half points in its lower 16 bits. The keys are
+///   ours rather than Polyglot's, which don't cover pockets, so books of other
+///   programs don't match.
+/// - JSON: a `book` document whose `positions` each have a `fen` and `moves`, every
+///   move with `move` in UCI notation and optionally `weight`, `games` and `points`.
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    positions: HashMap<u64, BookPosition>,
+}
+
+impl OpeningBook {
+    pub fn new() -> OpeningBook {
+        OpeningBook::default()
+    }
+
+    /// Loads a book, as JSON if the file name ends in `.json` and as Polyglot otherwise.
+    pub fn load(path: &Path) -> Result<OpeningBook, BookError> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            OpeningBook::from_json(&fs::read_to_string(path)?)
+        } else {
+            OpeningBook::from_polyglot(&fs::read(path)?)
+        }
+    }
+
+    /// Saves the book in the format [`OpeningBook::load`] would read it back in.
+    pub fn save(&self, path: &Path) -> Result<(), BookError> {
+        let bytes = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            self.to_json().into_bytes()
+        } else {
+            self.to_polyglot()
+        };
+        write_atomic(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn from_polyglot(bytes: &[u8]) -> Result<OpeningBook, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::Truncated(bytes.len()));
+        }
+        let mut book = OpeningBook::new();
+        for entry in bytes.chunks_exact(ENTRY_SIZE) {
+            let key = u64::from_be_bytes(entry[0..8].try_into().expect("8 bytes"));
+            let code = u16::from_be_bytes([entry[8], entry[9]]);
+            let weight = u16::from_be_bytes([entry[10], entry[11]]);
+            let learn = u32::from_be_bytes(entry[12..16].try_into().expect("4 bytes"));
+            // Entries that aren't moves are skipped, as Polyglot does
+            let uci = match decode_move(code) {
+                Some(uci) => uci,
+                None => continue,
+            };
+            book.positions.entry(key).or_default().moves.push(BookMove {
+                uci,
+                weight,
+                games: learn >> 16,
+                points: (learn & 0xffff) as f32 / 2f32,
+            });
+        }
+        Ok(book)
+    }
+
+    pub fn to_polyglot(&self) -> Vec<u8> {
+        let mut entries: Vec<(u64, &BookMove)> = self
+            .positions
+            .iter()
+            .flat_map(|(&key, position)| position.moves.iter().map(move |m| (key, m)))
+            .collect();
+        entries
+            .sort_by(|(a, a_move), (b, b_move)| a.cmp(b).then(b_move.weight.cmp(&a_move.weight)));
+        let mut bytes = Vec::with_capacity(entries.len() * ENTRY_SIZE);
+        for (key, m) in entries {
+            let learn = m.games.min(0xffff) << 16 | ((m.points * 2f32).round() as u32).min(0xffff);
+            bytes.extend_from_slice(&key.to_be_bytes());
+            bytes.extend_from_slice(&encode_move(&m.uci).to_be_bytes());
+            bytes.extend_from_slice(&m.weight.to_be_bytes());
+            bytes.extend_from_slice(&learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_json(text: &str) -> Result<OpeningBook, BookError> {
+        let invalid = |what: &str| BookError::InvalidJson(what.to_string());
+        let document = JsonValue::parse(text).map_err(|err| invalid(&err.to_string()))?;
+        let positions = match document.get("positions") {
+            Some(JsonValue::Array(positions)) => positions,
+            _ => return Err(invalid("no positions")),
+        };
+        let mut book = OpeningBook::new();
+        for entry in positions {
+            let fen = entry
+                .get("fen")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| invalid("position without a fen"))?;
+            let position = parse_fen(fen).map_err(|err| invalid(&err.to_string()))?;
+            let moves = match entry.get("moves") {
+                Some(JsonValue::Array(moves)) => moves,
+                _ => return Err(invalid(fen)),
+            };
+            let mut parsed = Vec::new();
+            for m in moves {
+                let text = m
+                    .get("move")
+                    .and_then(JsonValue::as_str)
+                    .ok_or_else(|| invalid(fen))?;
+                let uci: Uci = text.parse().map_err(|_| invalid(text))?;
+                let number = |key: &str| m.get(key).and_then(JsonValue::as_f64);
+                parsed.push(BookMove {
+                    uci,
+                    weight: number("weight").map_or(1, |weight| weight as u16),
+                    games: number("games").map_or(0, |games| games as u32),
+                    points: number("points").unwrap_or(0f64) as f32,
+                });
+            }
+            let slot = book.positions.entry(position.zobrist_hash()).or_default();
+            slot.fen = Some(epd(&position));
+            slot.moves.extend(parsed);
+        }
+        Ok(book)
+    }
+
+    /// The book as a JSON document. Positions read from a Polyglot file have no FEN and
+    /// are left out.
+    pub fn to_json(&self) -> String {
+        let mut positions: Vec<(&String, &Vec<BookMove>)> = self
+            .positions
+            .values()
+            .filter_map(|position| Some((position.fen.as_ref()?, &position.moves)))
+            .collect();
+        positions.sort_by_key(|&(fen, _)| fen);
+        let positions = positions.into_iter().map(|(fen, moves)| {
+            let moves = moves.iter().map(|m| {
+                JsonObject::new()
+                    .string("move", &m.uci.to_string())
+                    .number("weight", m.weight)
+                    .number("games", m.games)
+                    .number("points", m.points)
+                    .to_string()
+            });
+            JsonObject::new()
+                .string("fen", fen)
+                .raw("moves", json_array(moves))
+                .to_string()
+        });
+        JsonObject::document("book")
+            .raw("positions", json_array(positions))
+            .to_string()
+    }
+
+    /// Number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// The legal book moves of `position`, most weighted first.
+    pub fn moves(&self, position: &Bughouse) -> Vec<(Move, &BookMove)> {
+        let mut moves: Vec<(Move, &BookMove)> = match self.positions.get(&position.zobrist_hash()) {
+            // A move that turns out illegal means a hash collision with another position
+            Some(entry) => entry
+                .moves
+                .iter()
+                .filter_map(|m| Some((m.uci.to_move(position).ok()?, m)))
+                .collect(),
+            None => Vec::new(),
+        };
+        moves.sort_by_key(|(_, m)| std::cmp::Reverse(m.weight));
+        moves
+    }
+
+    /// Picks a book move of `position` with probability proportional to its weight,
+    /// `None` if the book has no move with weight for it.
+    pub fn probe<R: Rng>(&self, position: &Bughouse, rng: &mut R) -> Option<Move> {
+        let moves = self.moves(position);
+        let total: u32 = moves.iter().map(|(_, m)| u32::from(m.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut target = rng.gen_range(0..total);
+        for (m, entry) in moves {
+            let weight = u32::from(entry.weight);
+            if target < weight {
+                return Some(m);
+            }
+            target -= weight;
+        }
+        None
+    }
+
+    /// Builds a book from the first plies of both boards of `games`, weighting each
+    /// move by the half points the mover scored with it as Polyglot does. Games without
+    /// a result are skipped.
+    pub fn from_games<'a, I>(games: I, config: &BuildConfig) -> OpeningBook
+    where
+        I: IntoIterator<Item = &'a BpgnGame>,
+    {
+        let mut book = OpeningBook::new();
+        for game in games {
+            let result = match game.result {
+                Some(result) => result,
+                None => continue,
+            };
+            let mut plies = [0; 2];
+            game.replay(|boards, played| {
+                let ply = &mut plies[played.board.index()];
+                *ply += 1;
+                if *ply > config.plies {
+                    return;
+                }
+                let position = boards.board(played.board);
+                let points = mover_points(result, played.board, position.turn());
+                let uci = Uci::from_chess960(&played.m);
+                let entry = book.positions.entry(position.zobrist_hash()).or_default();
+                entry.fen.get_or_insert_with(|| epd(position));
+                match entry.moves.iter_mut().find(|m| m.uci == uci) {
+                    Some(m) => {
+                        m.games += 1;
+                        m.points += points;
+                    }
+                    None => entry.moves.push(BookMove {
+                        uci,
+                        weight: 0,
+                        games: 1,
+                        points,
+                    }),
+                }
+            });
+        }
+        for position in book.positions.values_mut() {
+            position.moves.retain(|m| m.games >= config.min_games);
+            for m in &mut position.moves {
+                m.weight = (m.points * 2f32).round().min(f32::from(u16::MAX)) as u16;
+            }
+        }
+        book.positions
+            .retain(|_, position| !position.moves.is_empty());
+        book
+    }
+}
+
+// Points of the side moving on `board`. The result is that of white on board A and
+// their partner, black on board B
+fn mover_points(result: Outcome, board: BoardId, turn: Color) -> f32 {
+    let team = (board == BoardId::A) == (turn == Color::White);
+    match result {
+        Outcome::Draw => 0.5,
+        Outcome::Decisive { winner } if (winner == Color::White) == team => 1.0,
+        Outcome::Decisive { .. } => 0.0,
+    }
+}
+
+fn square_bits(square: Square) -> u16 {
+    u16::from(square.rank()) << 3 | u16::from(square.file())
+}
+
+fn bits_square(bits: u16) -> Square {
+    Square::from_coords(
+        File::new(u32::from(bits & 7)),
+        Rank::new(u32::from(bits >> 3 & 7)),
+    )
+}
+
+fn encode_move(uci: &Uci) -> u16 {
+    match *uci {
+        Uci::Normal {
+            from,
+            to,
+            promotion,
+        } => {
+            let promotion = match promotion {
+                Some(Role::Knight) => 1,
+                Some(Role::Bishop) => 2,
+                Some(Role::Rook) => 3,
+                Some(Role::Queen) => 4,
+                _ => 0,
+            };
+            promotion << 12 | square_bits(from) << 6 | square_bits(to)
+        }
+        Uci::Put { role, to } => {
+            u16::from(role as u8) << 12 | square_bits(to) << 6 | square_bits(to)
+        }
+        Uci::Null => 0,
+    }
+}
+
+fn decode_move(code: u16) -> Option<Uci> {
+    let to = bits_square(code);
+    let from = bits_square(code >> 6);
+    let extra = code >> 12 & 7;
+    if from == to {
+        let role = match extra {
+            1 => Role::Pawn,
+            2 => Role::Knight,
+            3 => Role::Bishop,
+            4 => Role::Rook,
+            5 => Role::Queen,
+            _ => return None,
+        };
+        return Some(Uci::Put { role, to });
+    }
+    let promotion = match extra {
+        0 => None,
+        1 => Some(Role::Knight),
+        2 => Some(Role::Bishop),
+        3 => Some(Role::Rook),
+        4 => Some(Role::Queen),
+        _ => return None,
+    };
+    Some(Uci::Normal {
+        from,
+        to,
+        promotion,
+    })
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use shakmaty::fen::epd;
+
+use crate::board::{parse_fen, Bughouse, FenError};
+use crate::paths::write_atomic;
+
+#[derive(Debug)]
+pub enum BookmarkError {
+    Io(io::Error),
+    /// A saved bookmark whose position doesn't read back
+    Fen(FenError),
+    /// A tag that is empty or can't be stored on one line
+    InvalidTag(String),
+}
+
+impl fmt::Display for BookmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookmarkError::Io(err) => write!(f, "bookmark storage error: {}", err),
+            BookmarkError::Fen(err) => write!(f, "invalid bookmark fen: {}", err),
+            BookmarkError::InvalidTag(tag) => write!(f, "invalid tag: {:?}", tag),
+        }
+    }
+}
+
+impl std::error::Error for BookmarkError {}
+
+impl From<io::Error> for BookmarkError {
+    fn from(err: io::Error) -> Self {
+        BookmarkError::Io(err)
+    }
+}
+
+impl From<FenError> for BookmarkError {
+    fn from(err: FenError) -> Self {
+        BookmarkError::Fen(err)
+    }
+}
+
+/// A position with the user's tags.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bookmark {
+    /// The position without move counters, so transpositions share a bookmark
+    pub epd: String,
+    pub tags: Vec<String>,
+}
+
+impl Bookmark {
+    pub fn position(&self) -> Result<Bughouse, FenError> {
+        parse_fen(&self.epd)
+    }
+}
+
+/// Tagged positions, a small personal database. When opened on a file, every change is
+/// written through to it, one bookmark per line with the EPD and tags separated by tabs.
+#[derive(Debug, Default)]
+pub struct BookmarkStore {
+    path: Option<PathBuf>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl BookmarkStore {
+    pub fn in_memory() -> BookmarkStore {
+        BookmarkStore::default()
+    }
+
+    /// Opens the store saved at `path`, or an empty one if the file doesn't exist yet.
+    pub fn open(path: &Path) -> Result<BookmarkStore, BookmarkError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut bookmarks = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut fields = line.split('\t');
+            let epd = fields.next().unwrap_or("").to_string();
+            parse_fen(&epd)?;
+            bookmarks.push(Bookmark {
+                epd,
+                tags: fields.map(str::to_string).collect(),
+            });
+        }
+        Ok(BookmarkStore {
+            path: Some(path.to_path_buf()),
+            bookmarks,
+        })
+    }
+
+    pub fn list(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// The bookmark of `position`, if it has one.
+    pub fn get(&self, position: &Bughouse) -> Option<&Bookmark> {
+        let epd = epd(position);
+        self.bookmarks.iter().find(|bookmark| bookmark.epd == epd)
+    }
+
+    /// Adds `tag` to `position`, bookmarking it if needed.
+    pub fn tag(&mut self, position: &Bughouse, tag: &str) -> Result<(), BookmarkError> {
+        let tag = tag.trim();
+        // Tabs and newlines separate the stored fields
+        if tag.is_empty() || tag.contains(['\t', '\n']) {
+            return Err(BookmarkError::InvalidTag(tag.to_string()));
+        }
+        let epd = epd(position);
+        match self
+            .bookmarks
+            .iter_mut()
+            .find(|bookmark| bookmark.epd == epd)
+        {
+            Some(bookmark) if bookmark.tags.iter().any(|t| t == tag) => return Ok(()),
+            Some(bookmark) => bookmark.tags.push(tag.to_string()),
+            None => self.bookmarks.push(Bookmark {
+                epd,
+                tags: vec![tag.to_string()],
+            }),
+        }
+        self.save()
+    }
+
+    /// Removes `tag` from `position`, dropping the bookmark once it has no tags left.
+    pub fn untag(&mut self, position: &Bughouse, tag: &str) -> Result<(), BookmarkError> {
+        let epd = epd(position);
+        for bookmark in self.bookmarks.iter_mut().filter(|b| b.epd == epd) {
+            bookmark.tags.retain(|t| t != tag.trim());
+        }
+        self.bookmarks.retain(|bookmark| !bookmark.tags.is_empty());
+        self.save()
+    }
+
+    /// Bookmarks with a tag containing `query`, ignoring case.
+    pub fn search(&self, query: &str) -> Vec<&Bookmark> {
+        let query = query.to_lowercase();
+        self.bookmarks
+            .iter()
+            .filter(|bookmark| {
+                bookmark
+                    .tags
+                    .iter()
+                    .any(|tag| tag.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    fn save(&self) -> Result<(), BookmarkError> {
+        if let Some(path) = &self.path {
+            let mut text = String::new();
+            for bookmark in &self.bookmarks {
+                text.push_str(&bookmark.epd);
+                for tag in &bookmark.tags {
+                    text.push('\t');
+                    text.push_str(tag);
+                }
+                text.push('\n');
+            }
+            write_atomic(path, text)?;
+        }
+        Ok(())
+    }
+}
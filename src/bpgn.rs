@@ -0,0 +1,561 @@
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::thread;
+use std::time::Duration;
+
+use shakmaty::san::{San, SanPlus};
+use shakmaty::{Color, Material, Move, Outcome, Setup};
+
+use crate::board::{BoardId, Bughouse, BughouseGame, IllegalMove};
+use crate::output::json_string;
+use crate::partner::{self, Inconsistency, MaterialFlow};
+use crate::seats::{Seating, SEATS};
+
+#[derive(Debug)]
+pub enum BpgnError {
+    /// Text that doesn't fit the format, around where reading stopped
+    Invalid(String),
+    IllegalMove(IllegalMove),
+}
+
+impl fmt::Display for BpgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BpgnError::Invalid(text) => write!(f, "invalid bpgn: {}", text),
+            BpgnError::IllegalMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BpgnError {}
+
+impl From<IllegalMove> for BpgnError {
+    fn from(err: IllegalMove) -> Self {
+        BpgnError::IllegalMove(err)
+    }
+}
+
+/// One move of a bughouse game as BPGN records it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BpgnMove {
+    pub board: BoardId,
+    pub m: Move,
+    /// Time left on the mover's clock after the move, from a `{179.8}` annotation
+    pub clock: Option<Duration>,
+}
+
+/// A bughouse game in BPGN: tags, then the moves of both boards in the order they were
+/// played, numbered `1A.` and `1a.` for white and black on board A and `1B.` and `1b.`
+/// on board B.
+///
+/// The result is that of the team of white on board A and black on board B, `1-0`
+/// meaning they won.
+#[derive(Clone, Debug)]
+pub struct BpgnGame {
+    pub tags: Vec<(String, String)>,
+    moves: Vec<BpgnMove>,
+    pub result: Option<Outcome>,
+    // Both boards after the moves so far
+    game: BughouseGame,
+}
+
+impl Default for BpgnGame {
+    fn default() -> Self {
+        BpgnGame::new()
+    }
+}
+
+impl BpgnGame {
+    pub fn new() -> BpgnGame {
+        BpgnGame {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: None,
+            // Clocks come from the annotations, so the boards themselves are untimed
+            game: BughouseGame::new(Duration::MAX),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a tag, replacing an earlier value.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// The players from the `WhiteA`, `BlackA`, `WhiteB` and `BlackB` tags.
+    pub fn seating(&self) -> Seating {
+        let mut seating = Seating::new();
+        for seat in SEATS {
+            if let Some(name) = self.tag(&seat.bpgn_tag()) {
+                seating.sit(seat, name);
+            }
+        }
+        seating
+    }
+
+    /// Time every player starts with, from a `TimeControl` tag like `180+0`.
+    pub fn initial_time(&self) -> Option<Duration> {
+        let control = self.tag("TimeControl")?;
+        let seconds = control.split('+').next()?;
+        seconds.trim().parse().ok().map(Duration::from_secs)
+    }
+
+    pub fn moves(&self) -> &[BpgnMove] {
+        &self.moves
+    }
+
+    /// Both boards after all moves.
+    pub fn game(&self) -> &BughouseGame {
+        &self.game
+    }
+
+    /// Plays `m` on `board`, passing a capture on to the partner.
+    pub fn push(
+        &mut self,
+        board: BoardId,
+        m: Move,
+        clock: Option<Duration>,
+    ) -> Result<(), IllegalMove> {
+        self.game.play(board, &m)?;
+        self.moves.push(BpgnMove { board, m, clock });
+        Ok(())
+    }
+
+    /// Replays the game from the start, calling `f` with both boards before each move.
+    pub fn replay(&self, mut f: impl FnMut(&BughouseGame, &BpgnMove)) {
+        let mut game = BughouseGame::new(Duration::MAX);
+        for bpgn_move in &self.moves {
+            f(&game, bpgn_move);
+            game.play(bpgn_move.board, &bpgn_move.m)
+                .expect("moves were checked when added");
+        }
+    }
+}
+
+/// Parses every game of a BPGN file. Comments other than clock annotations are skipped.
+pub fn parse(text: &str) -> Result<Vec<BpgnGame>, BpgnError> {
+    let mut games = Vec::new();
+    let mut game: Option<BpgnGame> = None;
+    let mut tokens = Tokens { rest: text };
+    while let Some(token) = tokens.next_token()? {
+        let current = game.get_or_insert_with(BpgnGame::new);
+        match token {
+            Token::Tag(name, value) => {
+                // A tag after moves starts the next game
+                if !current.moves.is_empty() || current.result.is_some() {
+                    games.extend(game.take());
+                    game.get_or_insert_with(BpgnGame::new)
+                        .set_tag(&name, &value);
+                } else {
+                    current.set_tag(&name, &value);
+                }
+            }
+            Token::Number(board, color) => {
+                let m = tokens.next_move(current.game.board(board), color)?;
+                let clock = tokens.take_clock().map(Duration::from_secs_f64);
+                current.push(board, m, clock)?;
+            }
+            Token::Result(result) => {
+                current.result = result;
+                games.extend(game.take());
+            }
+            Token::Word(word) => return Err(invalid(&word)),
+        }
+    }
+    games.extend(game);
+    Ok(games)
+}
+
+/// A game of an archive that could not be read, and where it starts.
+#[derive(Debug)]
+pub struct ParseError {
+    /// Index of the game in the archive, counting every game read or not
+    pub game: usize,
+    /// Line of the archive the game starts on, from 1
+    pub line: usize,
+    pub error: BpgnError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "game {} at line {}: {}",
+            self.game + 1,
+            self.line,
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a whole archive, skipping over games that can't be read instead of giving
+/// up on the file. Games are split apart at the tags that follow a game's moves and
+/// parsed on `threads` threads, all available cores for 0. The games come out in the
+/// order of the archive.
+pub fn parse_archive(
+    text: &str,
+    threads: usize,
+) -> impl Iterator<Item = Result<BpgnGame, ParseError>> {
+    let chunks = split_games(text);
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    let per_thread = chunks.len().div_ceil(threads).max(1);
+    let parsed: Vec<Vec<Result<Vec<BpgnGame>, BpgnError>>> = thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .chunks(per_thread)
+            .map(|chunks| {
+                scope.spawn(move || chunks.iter().map(|(_, chunk)| parse(chunk)).collect())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("bpgn parser panicked"))
+            .collect()
+    });
+    let mut index = 0;
+    let mut results = Vec::new();
+    for ((line, _), result) in chunks.iter().zip(parsed.into_iter().flatten()) {
+        match result {
+            Ok(games) => {
+                index += games.len();
+                results.extend(games.into_iter().map(Ok));
+            }
+            Err(error) => {
+                results.push(Err(ParseError {
+                    game: index,
+                    line: *line,
+                    error,
+                }));
+                index += 1;
+            }
+        }
+    }
+    results.into_iter()
+}
+
+/// One board of a bughouse game recorded on its own, as crazyhouse-style records have
+/// it: the moves of board A, each optionally followed by the holdings of both sides
+/// after it in a `{[Nb]}` annotation. Pieces from the missing partner board can only
+/// be dropped once the holdings show them.
+#[derive(Clone, Debug, Default)]
+pub struct BoardRecord {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+    /// Holdings after each move, `None` where the record has none
+    pub holdings: Vec<Option<Material>>,
+    pub result: Option<Outcome>,
+}
+
+impl BoardRecord {
+    /// The pieces that must have passed between the boards, or why the record can't be
+    /// a real bughouse game.
+    pub fn infer_flow(&self) -> Result<MaterialFlow, Inconsistency> {
+        partner::infer_flow(&Bughouse::default(), &self.moves, &self.holdings)
+    }
+}
+
+/// Parses every single-board record of a file. Comments other than clock and holdings
+/// annotations are skipped.
+pub fn parse_board_records(text: &str) -> Result<Vec<BoardRecord>, BpgnError> {
+    let mut records = Vec::new();
+    let mut record: Option<BoardRecord> = None;
+    // The board after the moves so far, holding what the record says it holds
+    let mut position = Bughouse::default();
+    let mut tokens = Tokens { rest: text };
+    while let Some(token) = tokens.next_token()? {
+        let current = record.get_or_insert_with(BoardRecord::default);
+        match token {
+            Token::Tag(name, value) => {
+                // A tag after moves starts the next record
+                if !current.moves.is_empty() || current.result.is_some() {
+                    records.extend(record.take());
+                    position = Bughouse::default();
+                    record
+                        .get_or_insert_with(BoardRecord::default)
+                        .tags
+                        .push((name, value));
+                } else {
+                    current.tags.push((name, value));
+                }
+            }
+            Token::Number(BoardId::A, color) => {
+                let m = tokens.next_move(&position, color)?;
+                tokens.take_clock();
+                let holdings = tokens.take_holdings()?;
+                position.play_passing_captures(&m);
+                if let Some(pockets) = holdings.clone() {
+                    // Holdings that don't fit are left for `infer_flow` to report
+                    if let Ok(next) = position.with_pockets(pockets) {
+                        position = next;
+                    }
+                }
+                current.moves.push(m);
+                current.holdings.push(holdings);
+            }
+            Token::Number(BoardId::B, _) => {
+                return Err(invalid("board B in a single-board record"))
+            }
+            Token::Result(result) => {
+                current.result = result;
+                records.extend(record.take());
+                position = Bughouse::default();
+            }
+            Token::Word(word) => return Err(invalid(&word)),
+        }
+    }
+    records.extend(record);
+    Ok(records)
+}
+
+/// Parses a whole archive of single-board records like [`parse_archive`], skipping
+/// over the records that can't be read.
+pub fn parse_board_archive(text: &str) -> impl Iterator<Item = Result<BoardRecord, ParseError>> {
+    let mut index = 0;
+    let mut results = Vec::new();
+    for (line, chunk) in split_games(text) {
+        match parse_board_records(chunk) {
+            Ok(records) => {
+                index += records.len();
+                results.extend(records.into_iter().map(Ok));
+            }
+            Err(error) => {
+                results.push(Err(ParseError {
+                    game: index,
+                    line,
+                    error,
+                }));
+                index += 1;
+            }
+        }
+    }
+    results.into_iter()
+}
+
+// Cuts an archive before every tag that follows movetext, pairing each piece with the
+// line it starts on
+fn split_games(text: &str) -> Vec<(usize, &str)> {
+    let mut chunks = Vec::new();
+    let mut start = (1, 0);
+    let mut in_movetext = false;
+    let mut offset = 0;
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_movetext {
+                chunks.push((start.0, &text[start.1..offset]));
+                start = (number + 1, offset);
+                in_movetext = false;
+            }
+        } else if !trimmed.is_empty() {
+            in_movetext = true;
+        }
+        offset += line.len();
+    }
+    if !text[start.1..].trim().is_empty() {
+        chunks.push((start.0, &text[start.1..]));
+    }
+    chunks
+}
+
+fn invalid(text: &str) -> BpgnError {
+    BpgnError::Invalid(text.to_string())
+}
+
+enum Token {
+    Tag(String, String),
+    Number(BoardId, Color),
+    Word(String),
+    // `None` for an unfinished game, `*`
+    Result(Option<Outcome>),
+}
+
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl Tokens<'_> {
+    // Skips whitespace and comments, except a clock annotation right after a move
+    fn skip(&mut self) -> Result<(), BpgnError> {
+        loop {
+            self.rest = self.rest.trim_start();
+            if self.rest.starts_with('{') {
+                let end = self
+                    .rest
+                    .find('}')
+                    .ok_or_else(|| invalid("unterminated comment"))?;
+                self.rest = &self.rest[end + 1..];
+            } else if self.rest.starts_with(';') {
+                let end = self.rest.find('\n').unwrap_or(self.rest.len());
+                self.rest = &self.rest[end..];
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    // A `{seconds}` annotation at the current position, which is consumed
+    fn take_clock(&mut self) -> Option<f64> {
+        let rest = self.rest.trim_start();
+        let end = rest.strip_prefix('{')?.find('}')?;
+        let seconds = rest[1..end + 1].trim().parse::<f64>().ok()?;
+        if !seconds.is_finite() || seconds < 0.0 {
+            return None;
+        }
+        self.rest = &rest[end + 2..];
+        Some(seconds)
+    }
+
+    // A `{[Nb]}` holdings annotation at the current position, which is consumed
+    fn take_holdings(&mut self) -> Result<Option<Material>, BpgnError> {
+        let rest = self.rest.trim_start();
+        let inner = match rest.strip_prefix("{[") {
+            Some(inner) => inner,
+            None => return Ok(None),
+        };
+        let end = inner
+            .find("]}")
+            .ok_or_else(|| invalid("unterminated holdings"))?;
+        let holdings = Material::from_ascii_fen(&inner.as_bytes()[..end])
+            .map_err(|_| invalid(&inner[..end]))?;
+        self.rest = &inner[end + 2..];
+        Ok(Some(holdings))
+    }
+
+    // The move after a move number, which must be `color`'s to play on `position`
+    fn next_move(&mut self, position: &Bughouse, color: Color) -> Result<Move, BpgnError> {
+        let san = self
+            .next_token()?
+            .and_then(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .ok_or_else(|| invalid("move number without a move"))?;
+        if position.turn() != color {
+            return Err(invalid(&format!("{} is not to move", san)));
+        }
+        san.trim_end_matches(['+', '#', '!', '?'])
+            .parse::<San>()
+            .ok()
+            .and_then(|parsed| parsed.to_move(position).ok())
+            .ok_or(BpgnError::IllegalMove(IllegalMove(san)))
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, BpgnError> {
+        self.skip()?;
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        if let Some(tag) = self.rest.strip_prefix('[') {
+            let end = tag.find(']').ok_or_else(|| invalid("unterminated tag"))?;
+            let (name, value) = tag[..end]
+                .trim()
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid(&tag[..end]))?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .ok_or_else(|| invalid(&tag[..end]))?;
+            self.rest = &tag[end + 1..];
+            return Ok(Some(Token::Tag(
+                name.to_string(),
+                value.replace("\\\"", "\"").replace("\\\\", "\\"),
+            )));
+        }
+        let end = self
+            .rest
+            .find(|ch: char| ch.is_whitespace() || ch == '{' || ch == '[')
+            .unwrap_or(self.rest.len());
+        let word = &self.rest[..end];
+        self.rest = &self.rest[end..];
+        Ok(Some(match word {
+            "1-0" => Token::Result(Some(Outcome::Decisive {
+                winner: Color::White,
+            })),
+            "0-1" => Token::Result(Some(Outcome::Decisive {
+                winner: Color::Black,
+            })),
+            "1/2-1/2" => Token::Result(Some(Outcome::Draw)),
+            "*" => Token::Result(None),
+            _ => match parse_number(word) {
+                Some((board, color)) => Token::Number(board, color),
+                None => Token::Word(word.to_string()),
+            },
+        }))
+    }
+}
+
+// `12A.` is white on board A, `12b.` black on board B
+fn parse_number(word: &str) -> Option<(BoardId, Color)> {
+    let word = word.strip_suffix('.')?;
+    let letter = word.chars().last()?;
+    let digits = &word[..word.len() - letter.len_utf8()];
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+    let board = match letter.to_ascii_uppercase() {
+        'A' => BoardId::A,
+        'B' => BoardId::B,
+        _ => return None,
+    };
+    let color = if letter.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((board, color))
+}
+
+impl fmt::Display for BpgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            if name != "Result" {
+                writeln!(f, "[{} {}]", name, json_string(value))?;
+            }
+        }
+        let result = self
+            .result
+            .map_or_else(|| "*".to_string(), |outcome| outcome.to_string());
+        writeln!(f, "[Result \"{}\"]", result)?;
+        writeln!(f)?;
+        let mut tokens = Vec::new();
+        self.replay(|game, bpgn_move| {
+            let position = game.board(bpgn_move.board);
+            let letter = match bpgn_move.board {
+                BoardId::A => 'A',
+                BoardId::B => 'B',
+            };
+            let letter = position.turn().fold(letter, letter.to_ascii_lowercase());
+            let mut san = SanPlus::from_move(position.clone(), &bpgn_move.m).to_string();
+            // BPGN spells out pawn drops, which SAN writes as `@e5`
+            if san.starts_with('@') {
+                san.insert(0, 'P');
+            }
+            let clock = bpgn_move.clock.map_or_else(String::new, |clock| {
+                format!("{{{:.3}}}", clock.as_secs_f64())
+            });
+            tokens.push(format!(
+                "{}{}. {}{}",
+                position.fullmoves(),
+                letter,
+                san,
+                clock
+            ));
+        });
+        tokens.push(result);
+        writeln!(f, "{}", tokens.join(" "))
+    }
+}
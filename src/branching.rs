@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use shakmaty::{Move, Position};
+
+use crate::board::Bughouse;
+use crate::insights::{GamePhase, GAME_PHASES};
+use crate::output::JsonObject;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Counts {
+    positions: u64,
+    moves: u64,
+    drops: u64,
+}
+
+/// Legal move counts by game phase over a corpus or a self-play run, the data needed to
+/// tune the progressive unpruning schedule to the variant's branching factor.
+#[derive(Clone, Debug, Default)]
+pub struct BranchingStats {
+    counts: HashMap<GamePhase, Counts>,
+}
+
+impl BranchingStats {
+    pub fn new() -> BranchingStats {
+        BranchingStats::default()
+    }
+
+    /// Counts the legal moves of `position`, reached after `ply` half moves. Finished
+    /// positions are skipped since they have no moves to choose from.
+    pub fn add_position(&mut self, position: &Bughouse, ply: usize) {
+        let moves = position.legal_moves();
+        if moves.is_empty() {
+            return;
+        }
+        let drops = moves
+            .iter()
+            .filter(|m| matches!(m, Move::Put { .. }))
+            .count();
+        let counts = self.counts.entry(GamePhase::of(position, ply)).or_default();
+        counts.positions += 1;
+        counts.moves += moves.len() as u64;
+        counts.drops += drops as u64;
+    }
+
+    /// Counts every position of a game played from `start`, stopping at the first
+    /// illegal move.
+    pub fn add_game(&mut self, start: &Bughouse, moves: &[Move]) {
+        let mut position = start.clone();
+        for (ply, m) in moves.iter().enumerate() {
+            self.add_position(&position, ply);
+            if !position.is_legal(m) {
+                return;
+            }
+            position.play_unchecked(m);
+        }
+        self.add_position(&position, moves.len());
+    }
+
+    pub fn positions(&self, phase: GamePhase) -> u64 {
+        self.counts.get(&phase).map_or(0, |counts| counts.positions)
+    }
+
+    /// Average number of legal moves in `phase`.
+    pub fn branching_factor(&self, phase: GamePhase) -> Option<f32> {
+        self.counts
+            .get(&phase)
+            .filter(|counts| counts.positions > 0)
+            .map(|counts| counts.moves as f32 / counts.positions as f32)
+    }
+
+    /// Share of the legal moves in `phase` that are drops.
+    pub fn drop_share(&self, phase: GamePhase) -> Option<f32> {
+        self.counts
+            .get(&phase)
+            .filter(|counts| counts.moves > 0)
+            .map(|counts| counts.drops as f32 / counts.moves as f32)
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let phases = GAME_PHASES
+            .iter()
+            .fold(JsonObject::new(), |object, &phase| {
+                let stats = JsonObject::new()
+                    .number("positions", self.positions(phase))
+                    .number(
+                        "branching_factor",
+                        self.branching_factor(phase).unwrap_or(f32::NAN),
+                    )
+                    .number("drop_share", self.drop_share(phase).unwrap_or(f32::NAN));
+                object.raw(&phase.to_string(), stats.to_string())
+            });
+        JsonObject::document("branching").raw("phases", phases.to_string())
+    }
+}
+
+impl fmt::Display for BranchingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for phase in GAME_PHASES {
+            if let (Some(factor), Some(drops)) =
+                (self.branching_factor(phase), self.drop_share(phase))
+            {
+                writeln!(
+                    f,
+                    "{}: {} positions, {:.1} legal moves, {:.0}% drops",
+                    phase,
+                    self.positions(phase),
+                    factor,
+                    drops * 100f32
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
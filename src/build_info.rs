@@ -0,0 +1,70 @@
+use std::fmt;
+
+use crate::artifacts::content_hash;
+use crate::eval::EvalParams;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short hash of the commit the engine was built from, `unknown` outside a git checkout
+pub const GIT_HASH: &str = env!("LADYBUG_GIT_HASH");
+/// Cargo features the engine was built with
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "unstable")]
+    "unstable",
+    #[cfg(feature = "nn")]
+    "nn",
+    #[cfg(feature = "tui")]
+    "tui",
+    #[cfg(feature = "sqlite")]
+    "sqlite",
+];
+
+/// Identifies an exact engine build and the weights it evaluates with, for protocol
+/// `id` responses and for stamping analysis output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub features: &'static [&'static str],
+    pub evaluator: String,
+}
+
+impl BuildInfo {
+    pub fn new(params: &EvalParams) -> BuildInfo {
+        BuildInfo {
+            version: VERSION,
+            git_hash: GIT_HASH,
+            features: FEATURES,
+            evaluator: evaluator_id(params),
+        }
+    }
+
+    /// Engine name with version and commit, e.g. for UCI `id name`.
+    pub fn name(&self) -> String {
+        format!("ladybug {} ({})", self.version, self.git_hash)
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(",")
+        };
+        write!(
+            f,
+            "{} features={} evaluator={}",
+            self.name(),
+            features,
+            self.evaluator
+        )
+    }
+}
+
+/// A stable identifier of a set of weights: a hash of their text form, so equal weights
+/// get the same id across builds and machines. A weights file written by
+/// [`EvalParams::to_text`] has the same hash as its id in the artifact store.
+pub fn evaluator_id(params: &EvalParams) -> String {
+    let hash = content_hash(params.to_text().as_bytes());
+    format!("weights-{:016x}", hash)
+}
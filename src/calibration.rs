@@ -0,0 +1,263 @@
+use std::fmt;
+use std::io::{self, BufRead};
+
+use shakmaty::{Outcome, Setup};
+
+use crate::output::{json_array, JsonObject};
+use crate::training::SearchRecord;
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    /// A line that is not a win probability and a score
+    InvalidPrediction(String),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(err) => write!(f, "reading predictions: {}", err),
+            CalibrationError::InvalidPrediction(line) => {
+                write!(f, "invalid prediction: {}", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+impl From<io::Error> for CalibrationError {
+    fn from(err: io::Error) -> Self {
+        CalibrationError::Io(err)
+    }
+}
+
+/// Predictions falling between two win probabilities, one point of a calibration curve.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CalibrationBin {
+    pub low: f32,
+    pub high: f32,
+    pub count: u64,
+    predicted: f64,
+    actual: f64,
+}
+
+impl CalibrationBin {
+    /// Average win probability predicted in this bin.
+    pub fn mean_predicted(&self) -> Option<f32> {
+        mean(self.predicted, self.count)
+    }
+
+    /// Average score actually reached, a win counting 1 and a draw 0.5.
+    pub fn mean_actual(&self) -> Option<f32> {
+        mean(self.actual, self.count)
+    }
+}
+
+fn mean(sum: f64, count: u64) -> Option<f32> {
+    if count > 0 {
+        Some((sum / count as f64) as f32)
+    } else {
+        None
+    }
+}
+
+/// Win probabilities reported by the engine set against the results of the games they
+/// were reported in. A well calibrated engine wins 70% of the positions it gives 70%,
+/// so every bin of the curve lies on the diagonal; the Brier score sums up how far off
+/// it is overall.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    bins: Vec<CalibrationBin>,
+    squared_error: f64,
+    log_loss: f64,
+}
+
+// Keeps the log loss finite for predictions of exactly 0 or 1
+const MIN_PROBABILITY: f64 = 1e-6;
+
+impl Calibration {
+    /// An empty report with `bins` equally wide bins.
+    pub fn new(bins: usize) -> Calibration {
+        let bins = bins.max(1);
+        Calibration {
+            bins: (0..bins)
+                .map(|i| CalibrationBin {
+                    low: i as f32 / bins as f32,
+                    high: (i + 1) as f32 / bins as f32,
+                    ..CalibrationBin::default()
+                })
+                .collect(),
+            squared_error: 0.0,
+            log_loss: 0.0,
+        }
+    }
+
+    /// Adds one prediction and the score the predicted side actually reached.
+    pub fn add(&mut self, predicted: f32, actual: f32) {
+        let predicted = predicted.clamp(0f32, 1f32);
+        let index = ((predicted * self.bins.len() as f32) as usize).min(self.bins.len() - 1);
+        let bin = &mut self.bins[index];
+        bin.count += 1;
+        bin.predicted += f64::from(predicted);
+        bin.actual += f64::from(actual);
+        let (p, y) = (f64::from(predicted), f64::from(actual));
+        self.squared_error += (p - y).powi(2);
+        let p = p.clamp(MIN_PROBABILITY, 1.0 - MIN_PROBABILITY);
+        self.log_loss -= y * p.ln() + (1.0 - y) * (1.0 - p).ln();
+    }
+
+    /// Adds the search values of a finished game, each for the side to move.
+    pub fn add_game(&mut self, records: &[SearchRecord], outcome: Outcome) {
+        for record in records {
+            let actual = match outcome {
+                Outcome::Decisive { winner } if winner == record.position.turn() => 1f32,
+                Outcome::Decisive { .. } => 0f32,
+                Outcome::Draw => 0.5,
+            };
+            self.add(record.value, actual);
+        }
+    }
+
+    /// Reads predictions from lines of `<win probability> <score>`, the score being 1,
+    /// 0.5 or 0 for the side the prediction was for. Blank lines and lines starting
+    /// with `#` are skipped.
+    pub fn read<R: BufRead>(input: R, bins: usize) -> Result<Calibration, CalibrationError> {
+        let mut calibration = Calibration::new(bins);
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || CalibrationError::InvalidPrediction(line.to_string());
+            let fields: Vec<f32> = line
+                .split_whitespace()
+                .map(|field| field.parse::<f32>().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            match fields.as_slice() {
+                &[predicted, actual]
+                    if (0f32..=1f32).contains(&predicted) && (0f32..=1f32).contains(&actual) =>
+                {
+                    calibration.add(predicted, actual)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(calibration)
+    }
+
+    pub fn bins(&self) -> &[CalibrationBin] {
+        &self.bins
+    }
+
+    pub fn count(&self) -> u64 {
+        self.bins.iter().map(|bin| bin.count).sum()
+    }
+
+    /// Mean squared difference between predicted and actual scores. 0 is perfect,
+    /// always predicting 50% scores 0.25.
+    pub fn brier_score(&self) -> Option<f32> {
+        mean(self.squared_error, self.count())
+    }
+
+    /// Mean cross entropy of the predictions, which punishes confident mistakes harder.
+    pub fn log_loss(&self) -> Option<f32> {
+        mean(self.log_loss, self.count())
+    }
+
+    /// Weighted mean distance of the bins from the diagonal.
+    pub fn calibration_error(&self) -> Option<f32> {
+        let error: f64 = self
+            .bins
+            .iter()
+            .filter(|bin| bin.count > 0)
+            .map(|bin| (bin.predicted - bin.actual).abs())
+            .sum();
+        mean(error, self.count())
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let bins = self.bins.iter().map(|bin| {
+            JsonObject::new()
+                .number("low", bin.low)
+                .number("high", bin.high)
+                .number("count", bin.count)
+                .number("predicted", bin.mean_predicted().unwrap_or(f32::NAN))
+                .number("actual", bin.mean_actual().unwrap_or(f32::NAN))
+                .to_string()
+        });
+        JsonObject::document("calibration")
+            .number("count", self.count())
+            .number("brier_score", self.brier_score().unwrap_or(f32::NAN))
+            .number("log_loss", self.log_loss().unwrap_or(f32::NAN))
+            .number(
+                "calibration_error",
+                self.calibration_error().unwrap_or(f32::NAN),
+            )
+            .raw("bins", json_array(bins))
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bin in self.bins.iter().filter(|bin| bin.count > 0) {
+            writeln!(
+                f,
+                "{:>3.0}-{:>3.0}%: {:>6} predictions, predicted {:.1}%, actual {:.1}%",
+                bin.low * 100f32,
+                bin.high * 100f32,
+                bin.count,
+                bin.mean_predicted().unwrap_or(0f32) * 100f32,
+                bin.mean_actual().unwrap_or(0f32) * 100f32
+            )?;
+        }
+        match (
+            self.brier_score(),
+            self.log_loss(),
+            self.calibration_error(),
+        ) {
+            (Some(brier), Some(log_loss), Some(error)) => writeln!(
+                f,
+                "brier score {:.4}, log loss {:.4}, calibration error {:.1}%",
+                brier,
+                log_loss,
+                error * 100f32
+            ),
+            _ => writeln!(f, "no predictions"),
+        }
+    }
+}
+
+/// The number of pawns of static evaluation per logit of win probability that fits
+/// `samples` of evaluations and actual scores best, for tuning the mapping between
+/// evaluations and win probabilities. `None` without samples.
+pub fn fit_pawns_per_logit(samples: &[(f32, f32)]) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+    let loss = |scale: f32| -> f64 {
+        samples
+            .iter()
+            .map(|&(score, actual)| {
+                let p = f64::from(1f32 / (1f32 + (-score / scale).exp()))
+                    .clamp(MIN_PROBABILITY, 1.0 - MIN_PROBABILITY);
+                let y = f64::from(actual);
+                -(y * p.ln() + (1.0 - y) * (1.0 - p).ln())
+            })
+            .sum()
+    };
+    // The loss is unimodal in the scale, so a golden section search finds the minimum
+    let (mut low, mut high) = (0.1f32, 50f32);
+    let ratio = (5f32.sqrt() - 1f32) / 2f32;
+    while high - low > 1e-3 {
+        let a = high - ratio * (high - low);
+        let b = low + ratio * (high - low);
+        if loss(a) < loss(b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    Some((low + high) / 2f32)
+}
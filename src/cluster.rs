@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A unit of self-play work handed to a worker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobSpec {
+    pub id: u64,
+    /// Identifier of the weights/network the games should be played with
+    pub net_version: String,
+    pub games: u32,
+    pub seed: u64,
+}
+
+impl JobSpec {
+    fn to_line(&self) -> String {
+        format!(
+            "job {} {} {} {}\n",
+            self.id, self.net_version, self.games, self.seed
+        )
+    }
+
+    fn from_line(line: &str) -> Option<JobSpec> {
+        let mut fields = line.split_whitespace();
+        if fields.next()? != "job" {
+            return None;
+        }
+        Some(JobSpec {
+            id: fields.next()?.parse().ok()?,
+            net_version: fields.next()?.to_string(),
+            games: fields.next()?.parse().ok()?,
+            seed: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Hands out jobs to workers and stores their uploads as `<out_dir>/job-<id>.dat`.
+///
+/// The protocol is line based, one request per connection:
+/// `request <worker>` is answered with a job line, `wait <millis>` while every job is
+/// leased out but some are not uploaded yet, or `none` once all are; and
+/// `upload <id> <length>` followed by `length` bytes is answered with `ok` or `error`.
+pub struct Coordinator {
+    pending: VecDeque<JobSpec>,
+    // Jobs handed out but not uploaded yet, with the time they were handed out
+    assigned: HashMap<u64, (JobSpec, Instant)>,
+    uploaded: HashSet<u64>,
+    out_dir: PathBuf,
+    /// Jobs not uploaded within this time are handed to the next worker that asks
+    pub lease: Duration,
+    /// How long a worker is told to wait before asking again while all jobs are leased
+    pub retry: Duration,
+    /// A connection that sends nothing for this long is dropped, so an idle client
+    /// can't hold up the others
+    pub timeout: Duration,
+}
+
+impl Coordinator {
+    pub fn new(jobs: Vec<JobSpec>, out_dir: PathBuf) -> Coordinator {
+        Coordinator {
+            pending: jobs.into(),
+            assigned: HashMap::new(),
+            uploaded: HashSet::new(),
+            out_dir,
+            lease: Duration::from_secs(60 * 60),
+            retry: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.assigned.is_empty()
+    }
+
+    fn next_job(&mut self, now: Instant) -> Option<JobSpec> {
+        let lease = self.lease;
+        let expired = self
+            .assigned
+            .iter()
+            .find(|(_, (_, since))| now.saturating_duration_since(*since) > lease)
+            .map(|(&id, _)| id);
+        let job = match expired {
+            Some(id) => self.assigned.remove(&id).map(|(job, _)| job),
+            None => self.pending.pop_front(),
+        }?;
+        self.assigned.insert(job.id, (job.clone(), now));
+        Some(job)
+    }
+
+    fn handle(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["request", ..] => match self.next_job(Instant::now()) {
+                Some(job) => writer.write_all(job.to_line().as_bytes()),
+                // A leased job may still come back to be handed out again
+                None if !self.is_done() => {
+                    writer.write_all(format!("wait {}\n", self.retry.as_millis()).as_bytes())
+                }
+                None => writer.write_all(b"none\n"),
+            },
+            ["upload", id, length] => {
+                let (id, length) = match (id.parse::<u64>(), length.parse::<u64>()) {
+                    (Ok(id), Ok(length)) => (id, length),
+                    _ => return writer.write_all(b"error malformed upload\n"),
+                };
+                let mut data = Vec::new();
+                reader.take(length).read_to_end(&mut data)?;
+                if data.len() as u64 != length {
+                    return writer.write_all(b"error truncated upload\n");
+                }
+                // A worker that outlived its lease uploads a job someone else finished
+                if self.uploaded.contains(&id) {
+                    return writer.write_all(b"ok\n");
+                }
+                if self.assigned.remove(&id).is_none() {
+                    return writer.write_all(b"error unknown job\n");
+                }
+                fs::create_dir_all(&self.out_dir)?;
+                fs::write(self.out_dir.join(format!("job-{}.dat", id)), data)?;
+                self.uploaded.insert(id);
+                writer.write_all(b"ok\n")
+            }
+            _ => writer.write_all(b"error unknown command\n"),
+        }
+    }
+
+    /// Serves workers until every job has been uploaded.
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            // A misbehaving worker must not take the coordinator down
+            let _ = self.handle(stream?);
+            if self.is_done() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn exchange<A: ToSocketAddrs>(coordinator: &A, request: &[u8]) -> io::Result<String> {
+    let mut stream = TcpStream::connect(coordinator)?;
+    stream.write_all(request)?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(response)
+}
+
+/// Fetches jobs from `coordinator` until none are left or the coordinator has shut down,
+/// runs each through `play` and uploads the produced data. Returns the number of jobs
+/// completed. While the coordinator waits on jobs leased to other workers, which may
+/// have died, the worker waits too. A failing `play` ends the worker, leaving its job
+/// to be handed out again once the lease runs out.
+pub fn run_worker<A, F>(coordinator: A, name: &str, mut play: F) -> io::Result<usize>
+where
+    A: ToSocketAddrs,
+    F: FnMut(&JobSpec) -> io::Result<Vec<u8>>,
+{
+    let mut completed = 0;
+    loop {
+        let response = match exchange(&coordinator, format!("request {}\n", name).as_bytes()) {
+            Ok(response) => response,
+            // The coordinator stops listening once all jobs are uploaded
+            Err(err)
+                if err.kind() == io::ErrorKind::ConnectionRefused
+                    || err.kind() == io::ErrorKind::ConnectionReset =>
+            {
+                return Ok(completed)
+            }
+            Err(err) => return Err(err),
+        };
+        let job = match JobSpec::from_line(&response) {
+            Some(job) => job,
+            // An empty response is a coordinator that shut down as we connected
+            None if response.trim() == "none" || response.is_empty() => return Ok(completed),
+            None => match response.trim().strip_prefix("wait ").map(str::parse) {
+                Some(Ok(millis)) => {
+                    thread::sleep(Duration::from_millis(millis));
+                    continue;
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected coordinator response: {}", response.trim()),
+                    ))
+                }
+            },
+        };
+        let data = play(&job)?;
+        let mut upload = format!("upload {} {}\n", job.id, data.len()).into_bytes();
+        upload.extend_from_slice(&data);
+        let response = exchange(&coordinator, &upload)?;
+        if response.trim() != "ok" {
+            return Err(io::Error::other(format!(
+                "upload of job {} failed: {}",
+                job.id,
+                response.trim()
+            )));
+        }
+        completed += 1;
+    }
+}
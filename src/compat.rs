@@ -0,0 +1,92 @@
+use shakmaty::fen::Fen;
+use shakmaty::{Bitboard, CastlingMode, Chess, Color, FromSetup, Role, Square};
+
+use crate::board::Bughouse;
+use crate::variant::{InvalidFen, Variant, VariantPosition};
+
+/// Reads variant names that are close enough to one the engine plays. Bughouse GUIs
+/// often announce a single board as `bughouse`, which plays like crazyhouse as long as
+/// nothing arrives from the partner.
+pub fn variant_alias(name: &str) -> Option<Variant> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "bughouse" | "house" => Some(Variant::Crazyhouse),
+        _ => name.parse().ok(),
+    }
+}
+
+/// Parses a FEN the strict parser rejects, repairing the quirks GUIs and servers are
+/// known for:
+///
+/// - empty pockets written `[-]`
+/// - pockets in a chess FEN, from a GUI that thinks the game is standard chess, which
+///   switch the position to crazyhouse
+/// - castling rights without the king or rook on its square, common in bughouse FENs
+///   that are written from the game header instead of the board
+/// - an en passant square no pawn could capture on
+pub fn lenient_fen(variant: Variant, text: &str) -> Result<VariantPosition, InvalidFen> {
+    let invalid = || InvalidFen(text.to_string());
+    let text = text.trim().replace("[-]", "[]");
+    // The castling field is read without the board and restored from it after, since
+    // rights for a missing rook already fail the parse
+    let mut fields: Vec<&str> = text.split(' ').collect();
+    let castling = match fields.get_mut(2) {
+        Some(field) => std::mem::replace(field, "-"),
+        None => "-",
+    };
+    let mut setup = Fen::from_ascii(fields.join(" ").as_bytes()).map_err(|_| invalid())?;
+    let variant = match &setup.pockets {
+        Some(pockets) if !pockets.is_empty() => Variant::Crazyhouse,
+        _ => variant,
+    };
+    setup.castling_rights = castling_rights(castling) & possible_castling_rights(&setup);
+    if let Some(position) = from_setup(variant, &setup) {
+        return Ok(position);
+    }
+    setup.ep_square = None;
+    from_setup(variant, &setup).ok_or_else(invalid)
+}
+
+fn from_setup(variant: Variant, setup: &Fen) -> Option<VariantPosition> {
+    match variant {
+        Variant::Crazyhouse => Bughouse::from_setup(setup, CastlingMode::Standard)
+            .ok()
+            .map(VariantPosition::Crazyhouse),
+        Variant::Chess => Chess::from_setup(setup, CastlingMode::Standard)
+            .ok()
+            .map(VariantPosition::Chess),
+    }
+}
+
+// The corners of standard castling rights like `KQkq`
+fn castling_rights(field: &str) -> Bitboard {
+    field
+        .chars()
+        .filter_map(|ch| match ch {
+            'K' => Some(Square::H1),
+            'Q' => Some(Square::A1),
+            'k' => Some(Square::H8),
+            'q' => Some(Square::A8),
+            _ => None,
+        })
+        .collect()
+}
+
+// Corners with a rook whose king still stands on its starting square
+fn possible_castling_rights(setup: &Fen) -> Bitboard {
+    let board = &setup.board;
+    let mut rights = Bitboard(0);
+    for (color, king, corners) in [
+        (Color::White, Square::E1, [Square::A1, Square::H1]),
+        (Color::Black, Square::E8, [Square::A8, Square::H8]),
+    ] {
+        if board.piece_at(king) != Some(color.king()) {
+            continue;
+        }
+        for corner in corners {
+            if board.piece_at(corner) == Some(Role::Rook.of(color)) {
+                rights.add(corner);
+            }
+        }
+    }
+    rights
+}
@@ -0,0 +1,259 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::engine::{Engine, RootStrategy, SearchOptions};
+use crate::eval::{EvalParams, EvalParamsError};
+
+/// The settings that shape a search, in one place. Build one from the defaults with the
+/// `with_*` methods, or load it from a TOML file:
+///
+/// ```toml
+/// exploration = 1.2
+/// root_strategy = "halving"
+/// playout_cap = 20_000
+/// max_tree_size = 1_000_000
+/// seed = 7
+/// eval_file = "weights.txt"
+///
+/// [eval]
+/// pocket.knight = 3.5
+///
+/// [eval.policy]
+/// recapture = 3
+/// ```
+///
+/// Every key is optional. `eval_file` names a weights file as read by
+/// [`EvalParams::parse`], relative to the config file, and weights in the `eval` tables
+/// override it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    /// See [`SearchOptions::exploration`]
+    pub exploration: f32,
+    /// See [`SearchOptions::root_strategy`]
+    pub root_strategy: RootStrategy,
+    /// See [`SearchOptions::playout_cap`]
+    pub playout_cap: Option<u64>,
+    /// See [`SearchOptions::max_tree_size`]
+    pub max_tree_size: Option<usize>,
+    /// Seed of the first search, see [`Engine::set_seed`]
+    pub seed: u64,
+    pub eval: EvalParams,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        let options = SearchOptions::default();
+        EngineConfig {
+            exploration: options.exploration,
+            root_strategy: options.root_strategy,
+            playout_cap: options.playout_cap,
+            max_tree_size: options.max_tree_size,
+            seed: 0,
+            eval: EvalParams::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Eval(EvalParamsError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config file: {}", err),
+            ConfigError::Parse { line, message } => {
+                write!(f, "config file line {}: {}", line, message)
+            }
+            ConfigError::Eval(err) => write!(f, "config file eval_file: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl EngineConfig {
+    pub fn with_exploration(mut self, exploration: f32) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    pub fn with_root_strategy(mut self, root_strategy: RootStrategy) -> Self {
+        self.root_strategy = root_strategy;
+        self
+    }
+
+    pub fn with_playout_cap(mut self, playouts: u64) -> Self {
+        self.playout_cap = Some(playouts);
+        self
+    }
+
+    pub fn with_max_tree_size(mut self, nodes: usize) -> Self {
+        self.max_tree_size = Some(nodes);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_eval(mut self, eval: EvalParams) -> Self {
+        self.eval = eval;
+        self
+    }
+
+    /// The default search options with the settings of this config.
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            exploration: self.exploration,
+            root_strategy: self.root_strategy,
+            playout_cap: self.playout_cap,
+            max_tree_size: self.max_tree_size,
+            ..SearchOptions::default()
+        }
+    }
+
+    /// A new engine with these settings.
+    pub fn engine(&self) -> Engine {
+        let mut engine = Engine::new(Arc::new(self.eval.clone()));
+        engine.set_options(self.search_options());
+        engine.set_seed(self.seed);
+        engine
+    }
+
+    /// Parses a config file, with `eval_file` relative to the working directory.
+    pub fn parse(text: &str) -> Result<EngineConfig, ConfigError> {
+        EngineConfig::parse_in(text, Path::new(""))
+    }
+
+    pub fn load(path: &Path) -> Result<EngineConfig, ConfigError> {
+        let text = fs::read_to_string(path)?;
+        EngineConfig::parse_in(&text, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Writes the config in the format read by [`EngineConfig::parse`], with every
+    /// weight spelled out.
+    pub fn to_toml(&self) -> String {
+        let mut text = format!("exploration = {}\n", self.exploration);
+        text.push_str(&format!("root_strategy = \"{}\"\n", self.root_strategy));
+        if let Some(playouts) = self.playout_cap {
+            text.push_str(&format!("playout_cap = {}\n", playouts));
+        }
+        if let Some(nodes) = self.max_tree_size {
+            text.push_str(&format!("max_tree_size = {}\n", nodes));
+        }
+        text.push_str(&format!("seed = {}\n\n[eval]\n", self.seed));
+        text.push_str(&self.eval.to_text());
+        text
+    }
+
+    // The subset of TOML the config needs: comments, `[table]` headers, and keys set to
+    // numbers or strings
+    fn parse_in(text: &str, base: &Path) -> Result<EngineConfig, ConfigError> {
+        let mut config = EngineConfig::default();
+        let mut table = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| ConfigError::Parse {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                table = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("expected `[table]`"))?
+                    .trim()
+                    .to_string();
+                if table != "eval" && !table.starts_with("eval.") {
+                    return Err(error("unknown table"));
+                }
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(error("expected `key = value`")),
+            };
+            if !table.is_empty() {
+                let key = format!("{}.{}", table, key);
+                let weight = key.strip_prefix("eval.").unwrap_or(&key);
+                *config
+                    .eval
+                    .weight_mut(weight)
+                    .ok_or_else(|| error("unknown weight"))? =
+                    number(value).ok_or_else(|| error("value is not a number"))?;
+                continue;
+            }
+            match key {
+                "exploration" => {
+                    config.exploration = number(value)
+                        .filter(|exploration: &f32| *exploration >= 0f32)
+                        .ok_or_else(|| error("exploration must be a number of at least 0"))?
+                }
+                "root_strategy" => {
+                    config.root_strategy = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| error("root_strategy must be \"uct\" or \"halving\""))?
+                }
+                // 0 turns a cap off, like the UCI options
+                "playout_cap" => {
+                    config.playout_cap = number(value)
+                        .ok_or_else(|| error("playout_cap must be a count"))
+                        .map(|cap| Some(cap).filter(|&cap| cap > 0))?
+                }
+                "max_tree_size" => {
+                    config.max_tree_size = number(value)
+                        .ok_or_else(|| error("max_tree_size must be a count"))
+                        .map(|cap| Some(cap).filter(|&cap| cap > 0))?
+                }
+                "seed" => {
+                    config.seed = number(value).ok_or_else(|| error("seed must be a number"))?
+                }
+                "eval_file" => {
+                    let file = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| error("eval_file must be a quoted path"))?;
+                    config.eval = EvalParams::load(&base.join(file)).map_err(ConfigError::Eval)?;
+                }
+                _ => return Err(error("unknown key")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// Everything before a `#` that is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// A TOML number, which may group digits with underscores
+fn number<T: std::str::FromStr>(value: &str) -> Option<T> {
+    value.replace('_', "").parse().ok()
+}
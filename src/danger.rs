@@ -0,0 +1,87 @@
+use shakmaty::{attacks, Bitboard, Color, MaterialSide, Role, Setup};
+
+use crate::board::Bughouse;
+
+// Roles that can be dropped with how much a checking drop of each adds to the danger.
+// Knights and queens can't be blocked, rooks and bishops often can.
+const DROP_CHECK_WEIGHTS: [(Role, f32); 5] = [
+    (Role::Pawn, 0.5),
+    (Role::Knight, 1.5),
+    (Role::Bishop, 0.75),
+    (Role::Rook, 1.0),
+    (Role::Queen, 2.0),
+];
+
+const ATTACKED_SQUARE_WEIGHT: f32 = 0.4;
+const DEFENDED_SQUARE_WEIGHT: f32 = 0.25;
+const ESCAPE_SQUARE_WEIGHT: f32 = 0.3;
+const IN_CHECK_WEIGHT: f32 = 1.0;
+// Raw danger at which the score reaches one half
+const HALF_DANGER: f32 = 4.0;
+
+/// Early warning of how close the king of `color` is to being mated, from 0 (safe) to
+/// 1 (mate is likely). `incoming` is what the opponent's partner is expected to pass
+/// on soon, counted as if it were already in the opponent's pocket.
+///
+/// Only looks at the squares around the king and the drops that would give check, so
+/// it is cheap enough to call after every move on either board, e.g. to decide when to
+/// ask the partner to sit.
+pub fn danger_score(position: &Bughouse, color: Color, incoming: &MaterialSide) -> f32 {
+    let board = position.board();
+    let king = match board.king_of(color) {
+        Some(king) => king,
+        None => return 0f32,
+    };
+    let them = !color;
+    let occupied = board.occupied();
+    let zone = attacks::king_attacks(king);
+
+    let mut danger = 0f32;
+    if !board.attacks_to(king, them, occupied).is_empty() {
+        danger += IN_CHECK_WEIGHT;
+    }
+    let mut escapes = 0;
+    for square in zone {
+        let attacked = !board.attacks_to(square, them, occupied).is_empty();
+        if attacked {
+            danger += ATTACKED_SQUARE_WEIGHT;
+            // The king is the only defender that doesn't count
+            let defenders =
+                board.attacks_to(square, color, occupied) & !Bitboard::from_square(king);
+            if !defenders.is_empty() {
+                danger -= DEFENDED_SQUARE_WEIGHT;
+            }
+        } else if !board.by_color(color).contains(square) {
+            escapes += 1;
+        }
+    }
+    danger += (3 - escapes.min(3)) as f32 * ESCAPE_SQUARE_WEIGHT;
+
+    let pocket = position
+        .pockets()
+        .map_or_else(MaterialSide::new, |pockets| pockets.by_color(them).clone())
+        + incoming;
+    let empty = !occupied;
+    for &(role, weight) in &DROP_CHECK_WEIGHTS {
+        if pocket.by_role(role) == 0 {
+            continue;
+        }
+        let mut targets = empty & attacks::attacks(king, role.of(color), occupied);
+        if role == Role::Pawn {
+            targets &= !Bitboard::BACKRANKS;
+        }
+        // A checking drop next to the king the king can't take is the classic mating
+        // pattern, one it can take is only a nuisance
+        for square in targets {
+            let supported = !board.attacks_to(square, them, occupied).is_empty();
+            danger += if zone.contains(square) && !supported {
+                weight / 2f32
+            } else {
+                weight
+            };
+        }
+    }
+
+    let danger = danger.max(0f32);
+    danger / (danger + HALF_DANGER)
+}
@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Setup};
+
+use crate::board::{BoardId, BughouseGame};
+use crate::display::{format_clock, pocket_string, BoardView};
+use crate::eval_bar::EvalUpdate;
+use crate::seats::{SearchReport, Seat, Seating, TeamAgent, SEATS};
+
+// Characters of an eval bar between its brackets
+const BAR_WIDTH: usize = 20;
+// Width of a board's column, with the space to the next one
+const COLUMN_WIDTH: usize = 36;
+
+/// A full-screen view of a bughouse game for a terminal: both boards side by side with
+/// pockets and clocks, an eval bar per board and the last search of every engine. Board
+/// B is drawn from black's side, so partners sit next to each other as at a real table.
+#[derive(Clone, Debug, Default)]
+pub struct Dashboard {
+    seating: Seating,
+    evals: [Option<EvalUpdate>; 2],
+    // Indexed like `SEATS`
+    searches: [Option<SearchReport>; 4],
+}
+
+impl Dashboard {
+    pub fn new(seating: Seating) -> Dashboard {
+        Dashboard {
+            seating,
+            ..Dashboard::default()
+        }
+    }
+
+    /// Records an eval bar reading, see [`crate::eval_bar::EvalStream`].
+    pub fn eval(&mut self, update: EvalUpdate) {
+        let index = update.board.index();
+        self.evals[index] = Some(update);
+    }
+
+    /// Records what `agent` at `seat` searched for its last move. Pass this as the
+    /// observer of [`crate::seats::play_seated_with`].
+    pub fn observe(&mut self, seat: Seat, agent: &dyn TeamAgent) {
+        if let Some(report) = agent.last_search() {
+            self.searches[seat.index()] = Some(report);
+        }
+    }
+
+    /// The screen as text, without escape codes.
+    pub fn render(&self, game: &BughouseGame) -> String {
+        let columns = [
+            self.board_lines(game, BoardId::A),
+            self.board_lines(game, BoardId::B),
+        ];
+        let mut screen = String::new();
+        for (a, b) in columns[0].iter().zip(&columns[1]) {
+            screen.push_str(&format!("{:<width$}{}\n", a, b, width = COLUMN_WIDTH));
+        }
+        screen.push('\n');
+        for (&seat, search) in SEATS.iter().zip(&self.searches) {
+            screen.push_str(&format!("{:<8} {:<16} ", seat, self.name(seat)));
+            match search {
+                Some(report) => screen.push_str(&format!(
+                    "nodes {:<8} {:>7}/s  win {:>3.0}%  best {}\n",
+                    report.analysis.nodes,
+                    report.nodes_per_second(),
+                    report.analysis.win_probability * 100f32,
+                    report.analysis.best.as_ref().map_or_else(
+                        || Uci::Null.to_string(),
+                        |m| Uci::from_standard(m).to_string()
+                    )
+                )),
+                None => screen.push_str("-\n"),
+            }
+        }
+        if let Some((board, outcome)) = game.decided_on() {
+            screen.push_str(&format!(
+                "\ngame over: {} on board {}\n",
+                outcome,
+                board_name(board)
+            ));
+        }
+        screen
+    }
+
+    /// Redraws the whole screen.
+    pub fn draw(&self, out: &mut dyn Write, game: &BughouseGame) -> io::Result<()> {
+        // Cursor home and clear, so each frame replaces the last
+        write!(out, "\x1b[H\x1b[2J{}", self.render(game))?;
+        out.flush()
+    }
+
+    fn name(&self, seat: Seat) -> &str {
+        self.seating.at(seat).unwrap_or("?")
+    }
+
+    fn board_lines(&self, game: &BughouseGame, board: BoardId) -> Vec<String> {
+        let position = game.board(board);
+        let clocks = game.clocks(board);
+        let view = BoardView::new(position).flipped(board == BoardId::B);
+        let (top, bottom) = match board {
+            BoardId::A => (Color::Black, Color::White),
+            BoardId::B => (Color::White, Color::Black),
+        };
+        let player = |color: Color| {
+            let seat = Seat { board, color };
+            let marker = if position.turn() == color { '*' } else { ' ' };
+            format!(
+                "{}{:<16} {:>6}",
+                marker,
+                self.name(seat),
+                format_clock(*clocks.by_color(color))
+            )
+        };
+        let mut lines = vec![
+            format!("board {}", board_name(board)),
+            player(top),
+            format!(" [{}]", pocket_string(&view, top)),
+        ];
+        for row in view.rows() {
+            let row: Vec<String> = row
+                .map(|square| match view.piece_at(square) {
+                    Some((piece, _)) => piece.char().to_string(),
+                    None => ".".to_string(),
+                })
+                .collect();
+            lines.push(format!(" {}", row.join(" ")));
+        }
+        lines.push(format!(" [{}]", pocket_string(&view, bottom)));
+        lines.push(player(bottom));
+        lines.push(match &self.evals[board.index()] {
+            Some(update) => eval_bar(update.white),
+            None => String::new(),
+        });
+        lines
+    }
+}
+
+// A bar filled in proportion to white's winning chances
+fn eval_bar(white: f32) -> String {
+    let filled = ((white.clamp(0f32, 1f32) * BAR_WIDTH as f32).round() as usize).min(BAR_WIDTH);
+    format!(
+        " [{}{}] {:.0}%",
+        "#".repeat(filled),
+        ".".repeat(BAR_WIDTH - filled),
+        white * 100f32
+    )
+}
+
+fn board_name(board: BoardId) -> &'static str {
+    match board {
+        BoardId::A => "A",
+        BoardId::B => "B",
+    }
+}
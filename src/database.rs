@@ -0,0 +1,707 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_double, c_int};
+use std::path::Path;
+use std::ptr;
+use std::time::Duration;
+
+use shakmaty::fen::epd;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position};
+
+use crate::board::{parse_fen, Bughouse, FenError};
+use crate::bookmarks::{Bookmark, BookmarkStore};
+use crate::drill::{Card, Drill};
+use crate::engine::Analysis;
+use crate::insights::{Analyzer, GameRecord, Insights};
+use crate::signature::{MaterialSignature, SignatureIndex};
+
+// Just the part of the SQLite C API the database needs, linked against the system's
+// libsqlite3
+mod ffi {
+    use std::os::raw::{c_char, c_double, c_int, c_void};
+
+    pub enum Sqlite3 {}
+    pub enum Stmt {}
+
+    pub const OK: c_int = 0;
+    pub const ROW: c_int = 100;
+    pub const DONE: c_int = 101;
+    pub const INTEGER: c_int = 1;
+    pub const FLOAT: c_int = 2;
+    pub const NULL: c_int = 5;
+    pub const OPEN_READWRITE: c_int = 0x02;
+    pub const OPEN_CREATE: c_int = 0x04;
+    // SQLITE_TRANSIENT: SQLite copies bound text before the call returns
+    pub const TRANSIENT: isize = -1;
+
+    #[link(name = "sqlite3")]
+    extern "C" {
+        pub fn sqlite3_open_v2(
+            filename: *const c_char,
+            db: *mut *mut Sqlite3,
+            flags: c_int,
+            vfs: *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+        pub fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+        pub fn sqlite3_exec(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            callback: *const c_void,
+            arg: *mut c_void,
+            errmsg: *mut *mut c_char,
+        ) -> c_int;
+        pub fn sqlite3_prepare_v2(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            bytes: c_int,
+            stmt: *mut *mut Stmt,
+            tail: *mut *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_bind_null(stmt: *mut Stmt, index: c_int) -> c_int;
+        pub fn sqlite3_bind_int64(stmt: *mut Stmt, index: c_int, value: i64) -> c_int;
+        pub fn sqlite3_bind_double(stmt: *mut Stmt, index: c_int, value: c_double) -> c_int;
+        pub fn sqlite3_bind_text(
+            stmt: *mut Stmt,
+            index: c_int,
+            text: *const c_char,
+            bytes: c_int,
+            destructor: isize,
+        ) -> c_int;
+        pub fn sqlite3_step(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_column_count(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_column_type(stmt: *mut Stmt, column: c_int) -> c_int;
+        pub fn sqlite3_column_int64(stmt: *mut Stmt, column: c_int) -> i64;
+        pub fn sqlite3_column_double(stmt: *mut Stmt, column: c_int) -> c_double;
+        pub fn sqlite3_column_text(stmt: *mut Stmt, column: c_int) -> *const u8;
+        pub fn sqlite3_column_bytes(stmt: *mut Stmt, column: c_int) -> c_int;
+        pub fn sqlite3_finalize(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_last_insert_rowid(db: *mut Sqlite3) -> i64;
+    }
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS positions (
+    epd TEXT PRIMARY KEY,
+    signature TEXT NOT NULL,
+    best TEXT,
+    win REAL NOT NULL,
+    nodes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS positions_by_signature ON positions (signature);
+CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    start TEXT NOT NULL,
+    moves TEXT NOT NULL,
+    clocks TEXT NOT NULL,
+    user TEXT NOT NULL,
+    outcome TEXT
+);
+CREATE TABLE IF NOT EXISTS puzzles (
+    epd TEXT PRIMARY KEY,
+    blunder TEXT NOT NULL,
+    level INTEGER NOT NULL,
+    due INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    successes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS puzzles_by_due ON puzzles (due);
+CREATE TABLE IF NOT EXISTS tags (
+    epd TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (epd, tag)
+);
+";
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// An error reported by SQLite
+    Sqlite { code: i32, message: String },
+    /// A stored row that doesn't read back, with the table it is in
+    Corrupt { table: &'static str, row: String },
+    /// A stored position that doesn't read back
+    Fen(FenError),
+    /// A tag that is empty or can't be stored on one line
+    InvalidTag(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Sqlite { code, message } => {
+                write!(f, "database error {}: {}", code, message)
+            }
+            DatabaseError::Corrupt { table, row } => {
+                write!(f, "unreadable row in {}: {}", table, row)
+            }
+            DatabaseError::Fen(err) => write!(f, "invalid stored fen: {}", err),
+            DatabaseError::InvalidTag(tag) => write!(f, "invalid tag: {:?}", tag),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<FenError> for DatabaseError {
+    fn from(err: FenError) -> Self {
+        DatabaseError::Fen(err)
+    }
+}
+
+// A column value, or a parameter bound to a statement
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl Value {
+    fn text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn real(&self) -> Option<f64> {
+        match *self {
+            Value::Real(value) => Some(value),
+            Value::Integer(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+}
+
+// An open SQLite database; the handle is only used from the owning thread
+struct Connection {
+    db: *mut ffi::Sqlite3,
+}
+
+impl Connection {
+    fn open(path: &str) -> Result<Connection, DatabaseError> {
+        let path = CString::new(path).map_err(|_| DatabaseError::Sqlite {
+            code: 0,
+            message: "path contains a nul byte".to_string(),
+        })?;
+        let mut db = ptr::null_mut();
+        // SAFETY: `path` is a nul-terminated string that outlives the call, `db` is a
+        // valid place for the handle, and a null VFS name selects the default one
+        let code = unsafe {
+            ffi::sqlite3_open_v2(
+                path.as_ptr(),
+                &mut db,
+                ffi::OPEN_READWRITE | ffi::OPEN_CREATE,
+                ptr::null(),
+            )
+        };
+        // Even a failed open may allocate a handle, which dropping the connection closes
+        let connection = Connection { db };
+        if code != ffi::OK {
+            return Err(connection.error(code));
+        }
+        Ok(connection)
+    }
+
+    fn error(&self, code: c_int) -> DatabaseError {
+        let message = if self.db.is_null() {
+            "out of memory".to_string()
+        } else {
+            // SAFETY: the handle is open, and SQLite returns a nul-terminated message
+            // that stays valid until the next call on it, after it is copied here
+            unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.db)) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        DatabaseError::Sqlite { code, message }
+    }
+
+    // Runs statements without parameters or results, like the schema
+    fn batch(&self, sql: &str) -> Result<(), DatabaseError> {
+        let sql = CString::new(sql).expect("SQL without nul bytes");
+        // SAFETY: the handle is open and `sql` is nul-terminated and outlives the call.
+        // Without a callback and an error message pointer nothing is handed back
+        let code = unsafe {
+            ffi::sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if code != ffi::OK {
+            return Err(self.error(code));
+        }
+        Ok(())
+    }
+
+    // Runs one statement with `params` bound to `?1`, `?2`, ... and returns its rows.
+    // SQL without a statement, only whitespace or comments, has no rows
+    fn query(&self, sql: &str, params: &[Value]) -> Result<Vec<Vec<Value>>, DatabaseError> {
+        let mut stmt = ptr::null_mut();
+        // SAFETY: the handle is open and SQLite reads at most `sql.len()` bytes of
+        // `sql`, so it needs no nul terminator. `stmt` is a valid place for the statement
+        let code = unsafe {
+            ffi::sqlite3_prepare_v2(
+                self.db,
+                sql.as_ptr() as *const c_char,
+                sql.len() as c_int,
+                &mut stmt,
+                ptr::null_mut(),
+            )
+        };
+        if code != ffi::OK {
+            return Err(self.error(code));
+        }
+        // SQLite succeeds without a statement when there is none in the text
+        if stmt.is_null() {
+            return Ok(Vec::new());
+        }
+        // SAFETY: `stmt` was just prepared on this connection and is finalized right
+        // after, exactly once
+        unsafe {
+            let result = self.run(stmt, params);
+            ffi::sqlite3_finalize(stmt);
+            result
+        }
+    }
+
+    // Binds `params` and steps through the rows of `stmt`.
+    //
+    // Safety: `stmt` must be a live statement prepared on this connection
+    unsafe fn run(
+        &self,
+        stmt: *mut ffi::Stmt,
+        params: &[Value],
+    ) -> Result<Vec<Vec<Value>>, DatabaseError> {
+        for (index, param) in params.iter().enumerate() {
+            let index = index as c_int + 1;
+            // SAFETY: `stmt` is live. SQLite checks the index itself, and copies bound
+            // text before returning since it is bound as transient
+            let code = unsafe {
+                match param {
+                    Value::Null => ffi::sqlite3_bind_null(stmt, index),
+                    Value::Integer(value) => ffi::sqlite3_bind_int64(stmt, index, *value),
+                    Value::Real(value) => ffi::sqlite3_bind_double(stmt, index, *value as c_double),
+                    Value::Text(text) => ffi::sqlite3_bind_text(
+                        stmt,
+                        index,
+                        text.as_ptr() as *const c_char,
+                        text.len() as c_int,
+                        ffi::TRANSIENT,
+                    ),
+                }
+            };
+            if code != ffi::OK {
+                return Err(self.error(code));
+            }
+        }
+        let mut rows = Vec::new();
+        loop {
+            // SAFETY: `stmt` is live, and a row is only read right after a step that
+            // produced one
+            match unsafe { ffi::sqlite3_step(stmt) } {
+                ffi::ROW => rows.push(unsafe { read_row(stmt) }),
+                ffi::DONE => return Ok(rows),
+                code => return Err(self.error(code)),
+            }
+        }
+    }
+
+    fn execute(&self, sql: &str, params: &[Value]) -> Result<(), DatabaseError> {
+        self.query(sql, params).map(drop)
+    }
+
+    fn last_insert_id(&self) -> i64 {
+        // SAFETY: the handle is open
+        unsafe { ffi::sqlite3_last_insert_rowid(self.db) }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: every statement is finalized by the query that prepared it, so
+        // nothing else uses the handle. Closing a null handle does nothing
+        unsafe { ffi::sqlite3_close(self.db) };
+    }
+}
+
+// The columns of the row `stmt` just stepped to.
+//
+// Safety: `stmt` must be a live statement whose last step returned a row
+unsafe fn read_row(stmt: *mut ffi::Stmt) -> Vec<Value> {
+    // SAFETY: `stmt` is live and on a row, so its columns can be read
+    let columns = unsafe { ffi::sqlite3_column_count(stmt) };
+    (0..columns)
+        // SAFETY: `column` is below the column count. The text pointer is valid for
+        // `sqlite3_column_bytes` bytes until the next step, and is copied before that
+        .map(|column| unsafe {
+            match ffi::sqlite3_column_type(stmt, column) {
+                ffi::NULL => Value::Null,
+                ffi::INTEGER => Value::Integer(ffi::sqlite3_column_int64(stmt, column)),
+                ffi::FLOAT => Value::Real(ffi::sqlite3_column_double(stmt, column)),
+                _ => {
+                    let text = ffi::sqlite3_column_text(stmt, column);
+                    if text.is_null() {
+                        return Value::Text(String::new());
+                    }
+                    let bytes = ffi::sqlite3_column_bytes(stmt, column) as usize;
+                    let text = std::slice::from_raw_parts(text, bytes);
+                    Value::Text(String::from_utf8_lossy(text).into_owned())
+                }
+            }
+        })
+        .collect()
+}
+
+/// A search result kept for a position, see [`PositionDb::record_analysis`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredAnalysis {
+    pub epd: String,
+    pub best: Option<Move>,
+    /// Chance of the side to move winning
+    pub win_probability: f32,
+    pub nodes: u64,
+}
+
+/// Analyzed positions, the user's games, drill puzzles and tagged positions in one
+/// SQLite file, in place of the flat files of [`BookmarkStore`] and [`Drill`] once a
+/// collection outgrows them. Positions are keyed by their EPD, so transpositions share
+/// their analysis, tags and puzzle.
+pub struct PositionDb {
+    connection: Connection,
+}
+
+impl PositionDb {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> Result<PositionDb, DatabaseError> {
+        PositionDb::connect(&path.to_string_lossy())
+    }
+
+    pub fn in_memory() -> Result<PositionDb, DatabaseError> {
+        PositionDb::connect(":memory:")
+    }
+
+    fn connect(path: &str) -> Result<PositionDb, DatabaseError> {
+        let connection = Connection::open(path)?;
+        connection.batch(SCHEMA)?;
+        Ok(PositionDb { connection })
+    }
+
+    /// Keeps `analysis` for `position`, replacing an earlier one only if it searched at
+    /// least as many nodes.
+    pub fn record_analysis(
+        &self,
+        position: &Bughouse,
+        analysis: &Analysis,
+    ) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            "INSERT INTO positions (epd, signature, best, win, nodes) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (epd) DO UPDATE SET best = ?3, win = ?4, nodes = ?5
+             WHERE ?5 >= positions.nodes",
+            &[
+                Value::Text(epd(position)),
+                Value::Text(MaterialSignature::of(position).to_string()),
+                analysis.best.as_ref().map_or(Value::Null, |m| {
+                    Value::Text(Uci::from_standard(m).to_string())
+                }),
+                Value::Real(f64::from(analysis.win_probability)),
+                Value::Integer(analysis.nodes as i64),
+            ],
+        )
+    }
+
+    /// The stored analysis of `position`.
+    pub fn analysis(&self, position: &Bughouse) -> Result<Option<StoredAnalysis>, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT epd, best, win, nodes FROM positions WHERE epd = ?1",
+            &[Value::Text(epd(position))],
+        )?;
+        rows.first().map(|row| read_analysis(row)).transpose()
+    }
+
+    /// Analyzed positions with the same material as `position`, itself excluded, the
+    /// most searched first. Material decides the plans in crazyhouse more than where
+    /// the pieces stand, see [`MaterialSignature`].
+    pub fn similar(
+        &self,
+        position: &Bughouse,
+        limit: usize,
+    ) -> Result<Vec<StoredAnalysis>, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT epd, best, win, nodes FROM positions WHERE signature = ?1 AND epd != ?2
+             ORDER BY nodes DESC LIMIT ?3",
+            &[
+                Value::Text(MaterialSignature::of(position).to_string()),
+                Value::Text(epd(position)),
+                Value::Integer(limit as i64),
+            ],
+        )?;
+        rows.iter().map(|row| read_analysis(row)).collect()
+    }
+
+    /// Adds one of the user's games and returns its id.
+    pub fn add_game(&self, game: &GameRecord) -> Result<i64, DatabaseError> {
+        let moves: Vec<String> = game
+            .moves
+            .iter()
+            .map(|m| Uci::from_standard(m).to_string())
+            .collect();
+        let clocks: Vec<String> = game
+            .clocks
+            .iter()
+            .map(|clock| clock.map_or_else(|| "-".to_string(), |c| c.as_millis().to_string()))
+            .collect();
+        self.connection.execute(
+            "INSERT INTO games (start, moves, clocks, user, outcome) VALUES (?1, ?2, ?3, ?4, ?5)",
+            &[
+                Value::Text(epd(&game.start)),
+                Value::Text(moves.join(" ")),
+                Value::Text(clocks.join(" ")),
+                Value::Text(game.user.fold("white", "black").to_string()),
+                game.outcome
+                    .map_or(Value::Null, |outcome| Value::Text(outcome.to_string())),
+            ],
+        )?;
+        Ok(self.connection.last_insert_id())
+    }
+
+    /// Every stored game, in the order they were added.
+    pub fn games(&self) -> Result<Vec<GameRecord>, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT start, moves, clocks, user, outcome FROM games ORDER BY id",
+            &[],
+        )?;
+        rows.iter().map(|row| read_game(row)).collect()
+    }
+
+    /// [`Insights`] over every stored game.
+    pub fn insights<A: Analyzer>(&self, analyzer: &mut A) -> Result<Insights, DatabaseError> {
+        let mut insights = Insights::new();
+        for game in self.games()? {
+            insights.add_game(&game, analyzer);
+        }
+        Ok(insights)
+    }
+
+    /// A [`SignatureIndex`] of the finished games, for looking up how similar material
+    /// balances turned out.
+    pub fn signature_index(&self) -> Result<SignatureIndex, DatabaseError> {
+        let mut index = SignatureIndex::new();
+        for game in self.games()? {
+            if let Some(outcome) = game.outcome {
+                index.add_game(&game.start, &game.moves, outcome);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Adds or updates a drill puzzle.
+    pub fn save_card(&self, card: &Card) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO puzzles (epd, blunder, level, due, attempts, successes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            &[
+                Value::Text(card.epd.clone()),
+                Value::Text(Uci::from_standard(&card.blunder).to_string()),
+                Value::Integer(card.level as i64),
+                Value::Integer(card.due as i64),
+                Value::Integer(i64::from(card.attempts)),
+                Value::Integer(i64::from(card.successes)),
+            ],
+        )
+    }
+
+    /// Puzzles due at `now`, the most overdue first.
+    pub fn due_cards(&self, now: u64) -> Result<Vec<Card>, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT epd, blunder, level, due, attempts, successes FROM puzzles
+             WHERE due <= ?1 ORDER BY due",
+            &[Value::Integer(now as i64)],
+        )?;
+        rows.iter().map(|row| read_card(row)).collect()
+    }
+
+    /// All puzzles as an in-memory [`Drill`] deck. Answers given to it are kept by
+    /// saving its cards back with [`PositionDb::save_card`].
+    pub fn drill(&self) -> Result<Drill, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT epd, blunder, level, due, attempts, successes FROM puzzles ORDER BY due",
+            &[],
+        )?;
+        let cards = rows
+            .iter()
+            .map(|row| read_card(row))
+            .collect::<Result<_, _>>()?;
+        Ok(Drill::from_cards(cards))
+    }
+
+    /// Adds `tag` to `position`, with the same rules as [`BookmarkStore::tag`].
+    pub fn tag(&self, position: &Bughouse, tag: &str) -> Result<(), DatabaseError> {
+        let tag = tag.trim();
+        if tag.is_empty() || tag.contains(['\t', '\n']) {
+            return Err(DatabaseError::InvalidTag(tag.to_string()));
+        }
+        self.connection.execute(
+            "INSERT OR IGNORE INTO tags (epd, tag) VALUES (?1, ?2)",
+            &[Value::Text(epd(position)), Value::Text(tag.to_string())],
+        )
+    }
+
+    pub fn untag(&self, position: &Bughouse, tag: &str) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            "DELETE FROM tags WHERE epd = ?1 AND tag = ?2",
+            &[
+                Value::Text(epd(position)),
+                Value::Text(tag.trim().to_string()),
+            ],
+        )
+    }
+
+    /// Tagged positions with a tag containing `query`, ignoring case, as in
+    /// [`BookmarkStore::search`].
+    pub fn search_tags(&self, query: &str) -> Result<Vec<Bookmark>, DatabaseError> {
+        let rows = self.connection.query(
+            "SELECT epd, group_concat(tag, char(9)) FROM tags WHERE epd IN
+                 (SELECT epd FROM tags WHERE instr(lower(tag), lower(?1)) > 0)
+             GROUP BY epd ORDER BY epd",
+            &[Value::Text(query.to_string())],
+        )?;
+        rows.iter()
+            .map(|row| match (row[0].text(), row[1].text()) {
+                (Some(epd), Some(tags)) => Ok(Bookmark {
+                    epd: epd.to_string(),
+                    tags: tags.split('\t').map(str::to_string).collect(),
+                }),
+                _ => Err(corrupt("tags", row)),
+            })
+            .collect()
+    }
+
+    /// Copies the bookmarks and puzzles of the flat file stores into the database.
+    pub fn import(&self, bookmarks: &BookmarkStore, drill: &Drill) -> Result<(), DatabaseError> {
+        self.connection.batch("BEGIN")?;
+        let result = (|| -> Result<(), DatabaseError> {
+            for bookmark in bookmarks.list() {
+                let position = bookmark.position()?;
+                for tag in &bookmark.tags {
+                    self.tag(&position, tag)?;
+                }
+            }
+            for card in drill.cards() {
+                self.save_card(card)?;
+            }
+            Ok(())
+        })();
+        self.connection
+            .batch(if result.is_ok() { "COMMIT" } else { "ROLLBACK" })?;
+        result
+    }
+}
+
+fn corrupt(table: &'static str, row: &[Value]) -> DatabaseError {
+    DatabaseError::Corrupt {
+        table,
+        row: format!("{:?}", row),
+    }
+}
+
+fn parse_move(position: &Bughouse, uci: &str) -> Option<Move> {
+    uci.parse::<Uci>().ok()?.to_move(position).ok()
+}
+
+fn read_analysis(row: &[Value]) -> Result<StoredAnalysis, DatabaseError> {
+    let invalid = || corrupt("positions", row);
+    let epd = row[0].text().ok_or_else(invalid)?;
+    let position = parse_fen(epd)?;
+    let best = match &row[1] {
+        Value::Null => None,
+        value => Some(
+            value
+                .text()
+                .and_then(|uci| parse_move(&position, uci))
+                .ok_or_else(invalid)?,
+        ),
+    };
+    Ok(StoredAnalysis {
+        epd: epd.to_string(),
+        best,
+        win_probability: row[2].real().ok_or_else(invalid)? as f32,
+        nodes: row[3].integer().ok_or_else(invalid)? as u64,
+    })
+}
+
+fn read_game(row: &[Value]) -> Result<GameRecord, DatabaseError> {
+    let invalid = || corrupt("games", row);
+    let start = parse_fen(row[0].text().ok_or_else(invalid)?)?;
+    let mut position = start.clone();
+    let mut moves = Vec::new();
+    for uci in row[1].text().ok_or_else(invalid)?.split_whitespace() {
+        let m = parse_move(&position, uci).ok_or_else(invalid)?;
+        position.play_unchecked(&m);
+        moves.push(m);
+    }
+    let clocks = row[2]
+        .text()
+        .ok_or_else(invalid)?
+        .split_whitespace()
+        .map(|clock| match clock {
+            "-" => Ok(None),
+            millis => millis
+                .parse()
+                .map(|millis| Some(Duration::from_millis(millis)))
+                .map_err(|_| invalid()),
+        })
+        .collect::<Result<_, _>>()?;
+    let user = match row[3].text() {
+        Some("white") => Color::White,
+        Some("black") => Color::Black,
+        _ => return Err(invalid()),
+    };
+    let outcome = match row[4].text() {
+        None => None,
+        Some("1-0") => Some(Outcome::Decisive {
+            winner: Color::White,
+        }),
+        Some("0-1") => Some(Outcome::Decisive {
+            winner: Color::Black,
+        }),
+        Some("1/2-1/2") => Some(Outcome::Draw),
+        Some(_) => return Err(invalid()),
+    };
+    Ok(GameRecord {
+        start,
+        moves,
+        clocks,
+        user,
+        outcome,
+    })
+}
+
+fn read_card(row: &[Value]) -> Result<Card, DatabaseError> {
+    let invalid = || corrupt("puzzles", row);
+    let epd = row[0].text().ok_or_else(invalid)?;
+    let position = parse_fen(epd)?;
+    let number = |index: usize| row[index].integer().filter(|&n| n >= 0).ok_or_else(invalid);
+    Ok(Card {
+        epd: epd.to_string(),
+        blunder: row[1]
+            .text()
+            .and_then(|uci| parse_move(&position, uci))
+            .ok_or_else(invalid)?,
+        level: number(2)? as usize,
+        due: number(3)? as u64,
+        attempts: number(4)? as u32,
+        successes: number(5)? as u32,
+    })
+}
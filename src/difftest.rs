@@ -0,0 +1,428 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Color, Position, Role};
+
+use crate::board::Bughouse;
+use crate::cancel::CancelToken;
+use crate::output::{json_array, JsonObject};
+
+/// Perft split by root move, as `go perft` prints it: the nodes below each legal move
+/// by its UCI notation.
+pub type Divide = BTreeMap<String, u64>;
+
+/// The perft of `position` split by root move, the same way a reference engine would.
+pub fn divide(position: &Bughouse, depth: u32) -> Divide {
+    position
+        .legal_moves()
+        .iter()
+        .map(|m| {
+            let mut child = position.clone();
+            child.play_unchecked(m);
+            let uci = m.to_uci(CastlingMode::Standard).to_string();
+            (uci, child.perft(depth.max(1) - 1))
+        })
+        .collect()
+}
+
+/// A move generator to check ours against.
+pub trait ReferenceEngine {
+    /// Perft of the crazyhouse position `fen` to `depth`, split by root move.
+    fn divide(&mut self, fen: &str, depth: u32) -> io::Result<Divide>;
+}
+
+/// A crazyhouse engine speaking UCI that supports `go perft`, like Fairy-Stockfish,
+/// running as a child process.
+pub struct UciReference {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciReference {
+    /// Starts `command`, a program followed by its arguments separated by spaces, and
+    /// switches it to crazyhouse.
+    pub fn spawn(command: &str) -> io::Result<UciReference> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::other("no reference engine given"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("engine stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("engine stdout is piped"));
+        let mut reference = UciReference {
+            child,
+            stdin,
+            stdout,
+        };
+        reference.send("uci")?;
+        reference.read_until(|line| line == "uciok")?;
+        reference.send("setoption name UCI_Variant value crazyhouse")?;
+        reference.send("isready")?;
+        reference.read_until(|line| line == "readyok")?;
+        Ok(reference)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    // Reads lines up to and including the first one `done` accepts, handing the others
+    // back
+    fn read_until<F>(&mut self, mut done: F) -> io::Result<Vec<String>>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "reference engine exited",
+                ));
+            }
+            let line = line.trim();
+            if done(line) {
+                return Ok(lines);
+            }
+            lines.push(line.to_string());
+        }
+    }
+}
+
+impl ReferenceEngine for UciReference {
+    fn divide(&mut self, fen: &str, depth: u32) -> io::Result<Divide> {
+        self.send(&format!("position fen {}", fen))?;
+        self.send(&format!("go perft {}", depth))?;
+        let lines = self.read_until(|line| line.starts_with("Nodes searched"))?;
+        // Move lines look like `e2e4: 1`, anything else is chatter
+        Ok(lines
+            .iter()
+            .filter_map(|line| {
+                let (m, nodes) = line.split_once(':')?;
+                m.trim().parse::<Uci>().ok()?;
+                Some((m.trim().to_string(), nodes.trim().parse().ok()?))
+            })
+            .collect())
+    }
+}
+
+impl Drop for UciReference {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+/// Where two divides disagree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Difference {
+    /// Moves only the reference generates
+    pub missing: Vec<String>,
+    /// Moves only we generate
+    pub extra: Vec<String>,
+    /// Moves both generate with different node counts below them: ours, then theirs
+    pub counts: Vec<(String, u64, u64)>,
+}
+
+impl Difference {
+    pub fn between(ours: &Divide, theirs: &Divide) -> Difference {
+        let mut difference = Difference::default();
+        for (m, &nodes) in ours {
+            match theirs.get(m) {
+                None => difference.extra.push(m.clone()),
+                Some(&reference) if reference != nodes => {
+                    difference.counts.push((m.clone(), nodes, reference))
+                }
+                Some(_) => {}
+            }
+        }
+        difference.missing = theirs
+            .keys()
+            .filter(|m| !ours.contains_key(*m))
+            .cloned()
+            .collect();
+        difference
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.counts.is_empty()
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.missing.is_empty() {
+            parts.push(format!("missing {}", self.missing.join(" ")));
+        }
+        if !self.extra.is_empty() {
+            parts.push(format!("extra {}", self.extra.join(" ")));
+        }
+        for (m, ours, theirs) in &self.counts {
+            parts.push(format!("{} {} nodes, reference {}", m, ours, theirs));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// A position where the move generators disagree, with the smallest position and
+/// depth found that still shows a disagreement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub fen: String,
+    pub depth: u32,
+    pub reproduction: String,
+    pub reproduction_depth: u32,
+    /// The disagreement in the reproduction
+    pub difference: Difference,
+}
+
+impl Mismatch {
+    pub fn to_json(&self) -> JsonObject {
+        let moves = |moves: &[String]| json_array(moves.iter().map(|m| format!("\"{}\"", m)));
+        let counts = self.difference.counts.iter().map(|(m, ours, theirs)| {
+            JsonObject::new()
+                .string("move", m)
+                .number("ours", *ours as f64)
+                .number("reference", *theirs as f64)
+                .to_string()
+        });
+        JsonObject::new()
+            .string("fen", &self.fen)
+            .number("depth", f64::from(self.depth))
+            .string("reproduction", &self.reproduction)
+            .number("reproduction_depth", f64::from(self.reproduction_depth))
+            .raw("missing", moves(&self.difference.missing))
+            .raw("extra", moves(&self.difference.extra))
+            .raw("counts", json_array(counts))
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mismatch at depth {} in {}", self.depth, self.fen)?;
+        writeln!(
+            f,
+            "  reduced to depth {} in {}",
+            self.reproduction_depth, self.reproduction
+        )?;
+        write!(f, "  {}", self.difference)
+    }
+}
+
+/// What a differential test covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffConfig {
+    /// Random positions to compare
+    pub positions: usize,
+    pub depth: u32,
+    /// Longest random game a position is taken from, in plies
+    pub plies: usize,
+    pub seed: u64,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        DiffConfig {
+            positions: 200,
+            depth: 2,
+            plies: 60,
+            seed: 517,
+        }
+    }
+}
+
+/// FENs of positions from random games, the same ones for the same config.
+pub fn random_positions(config: &DiffConfig) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut fens = Vec::with_capacity(config.positions);
+    while fens.len() < config.positions {
+        let mut position = Bughouse::default();
+        for _ in 0..rng.gen_range(0..=config.plies) {
+            let m = match position.legal_moves().choose(&mut rng) {
+                Some(m) => m.clone(),
+                None => break,
+            };
+            position.play_unchecked(&m);
+        }
+        fens.push(position.fen());
+    }
+    fens
+}
+
+/// Compares the random positions of `config` between our move generator and the
+/// references, one thread per reference, and returns every mismatch minimized. Each
+/// reference should be a separate engine; they share out the positions. Once `cancel`
+/// is cancelled no new position is started, and the mismatches found so far are returned.
+pub fn run(
+    config: &DiffConfig,
+    references: Vec<Box<dyn ReferenceEngine + Send>>,
+    cancel: &CancelToken,
+) -> io::Result<Vec<Mismatch>> {
+    let fens = random_positions(config);
+    let jobs = references.len().max(1);
+    let mut results: Vec<(usize, Mismatch)> = Vec::new();
+    thread::scope(|scope| {
+        let workers: Vec<_> = references
+            .into_iter()
+            .enumerate()
+            .map(|(worker, mut reference)| {
+                let fens = &fens;
+                scope.spawn(move || {
+                    let mut found = Vec::new();
+                    for index in (worker..fens.len()).step_by(jobs) {
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+                        if let Some(mismatch) =
+                            compare(reference.as_mut(), &fens[index], config.depth)?
+                        {
+                            found.push((index, mismatch));
+                        }
+                    }
+                    Ok::<_, io::Error>(found)
+                })
+            })
+            .collect();
+        for worker in workers {
+            results.extend(worker.join().expect("difftest thread panicked")?);
+        }
+        Ok::<_, io::Error>(())
+    })?;
+    results.sort_by_key(|&(index, _)| index);
+    Ok(results.into_iter().map(|(_, mismatch)| mismatch).collect())
+}
+
+/// Compares one position, minimizing it if the move generators disagree.
+pub fn compare(
+    reference: &mut dyn ReferenceEngine,
+    fen: &str,
+    depth: u32,
+) -> io::Result<Option<Mismatch>> {
+    if difference(reference, fen, depth)?.is_none() {
+        return Ok(None);
+    }
+    let (reproduction, reproduction_depth) = minimize(reference, fen, depth)?;
+    let difference = difference(reference, &reproduction, reproduction_depth)?
+        .expect("a reproduction shows the mismatch");
+    Ok(Some(Mismatch {
+        fen: fen.to_string(),
+        depth,
+        reproduction,
+        reproduction_depth,
+        difference,
+    }))
+}
+
+// The disagreement in `fen` at `depth`, `None` if there is none or we can't set the
+// position up
+fn difference(
+    reference: &mut dyn ReferenceEngine,
+    fen: &str,
+    depth: u32,
+) -> io::Result<Option<Difference>> {
+    let position = match Bughouse::from_fen(fen, CastlingMode::Standard) {
+        Ok(position) => position,
+        Err(_) => return Ok(None),
+    };
+    let difference = Difference::between(&divide(&position, depth), &reference.divide(fen, depth)?);
+    Ok(Some(difference).filter(|difference| !difference.is_empty()))
+}
+
+/// Shrinks a mismatching position: lowers the depth while the mismatch shows at a
+/// lower one, and takes pieces off the board and out of the pockets, castling rights
+/// and the en passant square away for as long as the mismatch persists.
+pub fn minimize(
+    reference: &mut dyn ReferenceEngine,
+    fen: &str,
+    depth: u32,
+) -> io::Result<(String, u32)> {
+    let (mut fen, mut depth) = (fen.to_string(), depth);
+    loop {
+        while depth > 1 && difference(reference, &fen, depth - 1)?.is_some() {
+            depth -= 1;
+        }
+        let mut reduced = None;
+        for candidate in simplifications(&fen) {
+            if difference(reference, &candidate, depth)?.is_some() {
+                reduced = Some(candidate);
+                break;
+            }
+        }
+        match reduced {
+            Some(candidate) => fen = candidate,
+            None => return Ok((fen, depth)),
+        }
+    }
+}
+
+// Legal positions one step simpler than `fen`
+fn simplifications(fen: &str) -> Vec<String> {
+    let setup = match Fen::from_ascii(fen.as_bytes()) {
+        Ok(setup) => setup,
+        Err(_) => return Vec::new(),
+    };
+    let mut candidates = Vec::new();
+    for (square, piece) in setup.board.pieces() {
+        if piece.role != Role::King {
+            let mut simpler = setup.clone();
+            simpler.board.remove_piece_at(square);
+            candidates.push(simpler);
+        }
+    }
+    if let Some(pockets) = &setup.pockets {
+        for color in [Color::White, Color::Black].iter().copied() {
+            for role in [
+                Role::Pawn,
+                Role::Knight,
+                Role::Bishop,
+                Role::Rook,
+                Role::Queen,
+            ]
+            .iter()
+            .copied()
+            {
+                if pockets.by_color(color).by_role(role) > 0 {
+                    let mut simpler = setup.clone();
+                    if let Some(pockets) = &mut simpler.pockets {
+                        *pockets.by_color_mut(color).by_role_mut(role) -= 1;
+                    }
+                    candidates.push(simpler);
+                }
+            }
+        }
+    }
+    if setup.castling_rights.any() {
+        candidates.push(Fen {
+            castling_rights: Default::default(),
+            ..setup.clone()
+        });
+    }
+    if setup.ep_square.is_some() {
+        candidates.push(Fen {
+            ep_square: None,
+            ..setup.clone()
+        });
+    }
+    candidates
+        .iter()
+        .filter_map(|setup| Bughouse::from_setup(setup, CastlingMode::Standard).ok())
+        .map(|position| position.fen())
+        .collect()
+}
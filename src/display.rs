@@ -0,0 +1,134 @@
+use std::fmt;
+use std::time::Duration;
+
+use shakmaty::{ByColor, Color, File, Material, Piece, Rank, Role, Setup, Square};
+
+use crate::board::Bughouse;
+
+/// A snapshot of everything shown for one board: pieces, pockets, side to move and
+/// optionally the clocks. Shared by the text and SVG renderers.
+#[derive(Clone, Debug)]
+pub struct BoardView {
+    /// Indexed by square; the flag marks promoted pieces
+    pub squares: [Option<(Piece, bool)>; 64],
+    pub pockets: Material,
+    pub turn: Color,
+    pub clocks: Option<ByColor<Duration>>,
+    /// Draw the board from black's point of view
+    pub flipped: bool,
+}
+
+impl BoardView {
+    pub fn new(position: &Bughouse) -> BoardView {
+        let mut squares = [None; 64];
+        for (square, piece, promoted) in position.pieces() {
+            squares[usize::from(square)] = Some((piece, promoted));
+        }
+        BoardView {
+            squares,
+            pockets: position.pockets().cloned().unwrap_or_default(),
+            turn: position.turn(),
+            clocks: None,
+            flipped: false,
+        }
+    }
+
+    pub fn with_clocks(mut self, white: Duration, black: Duration) -> BoardView {
+        self.clocks = Some(ByColor { white, black });
+        self
+    }
+
+    pub fn flipped(mut self, flipped: bool) -> BoardView {
+        self.flipped = flipped;
+        self
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<(Piece, bool)> {
+        self.squares[usize::from(square)]
+    }
+
+    /// Squares in drawing order: rows top to bottom, each row left to right.
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = Square>> {
+        let flipped = self.flipped;
+        (0..8u32).map(move |row| {
+            (0..8u32).map(move |column| {
+                let (file, rank) = if flipped {
+                    (7 - column, row)
+                } else {
+                    (column, 7 - row)
+                };
+                Square::from_coords(File::new(file), Rank::new(rank))
+            })
+        })
+    }
+
+    /// Column and row of `square` in drawing order, see [`BoardView::rows`].
+    pub fn coords(&self, square: Square) -> (u32, u32) {
+        let (file, rank) = (u32::from(square.file()), u32::from(square.rank()));
+        if self.flipped {
+            (7 - file, rank)
+        } else {
+            (file, 7 - rank)
+        }
+    }
+
+    /// Pocket contents of `color` as (role, count) pairs, most valuable first.
+    pub fn pocket(&self, color: Color) -> Vec<(Role, u8)> {
+        let side = self.pockets.by_color(color);
+        [
+            Role::Queen,
+            Role::Rook,
+            Role::Bishop,
+            Role::Knight,
+            Role::Pawn,
+        ]
+        .iter()
+        .map(|&role| (role, side.by_role(role)))
+        .filter(|&(_, count)| count > 0)
+        .collect()
+    }
+}
+
+pub fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub(crate) fn pocket_string(view: &BoardView, color: Color) -> String {
+    view.pocket(color)
+        .iter()
+        .map(|&(role, count)| format!("{}{}", role.of(color).char(), count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl fmt::Display for BoardView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (top, bottom) = if self.flipped {
+            (Color::White, Color::Black)
+        } else {
+            (Color::Black, Color::White)
+        };
+        writeln!(f, "[{}]", pocket_string(self, top))?;
+        for row in self.rows() {
+            for square in row {
+                let ch = match self.piece_at(square) {
+                    Some((piece, _)) => piece.char(),
+                    None => '.',
+                };
+                write!(f, "{}", ch)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "[{}]", pocket_string(self, bottom))?;
+        if let Some(clocks) = &self.clocks {
+            write!(
+                f,
+                "\nwhite {} black {}",
+                format_clock(clocks.white),
+                format_clock(clocks.black)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use shakmaty::fen::epd;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position, Setup};
+
+use crate::board::{parse_fen, Bughouse, FenError};
+use crate::insights::{Analyzer, GameRecord};
+use crate::paths::write_atomic;
+
+const DAY: u64 = 24 * 60 * 60;
+// Days until a card is asked again, by box. A right answer moves the card up a box, a
+// wrong one sends it back to the first.
+const INTERVALS: [u64; 6] = [0, 1, 3, 7, 16, 35];
+
+#[derive(Debug)]
+pub enum DrillError {
+    Io(io::Error),
+    /// A saved card whose position doesn't read back
+    Fen(FenError),
+    /// A card line with missing or unreadable fields
+    InvalidCard(String),
+}
+
+impl fmt::Display for DrillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrillError::Io(err) => write!(f, "drill storage error: {}", err),
+            DrillError::Fen(err) => write!(f, "invalid drill fen: {}", err),
+            DrillError::InvalidCard(line) => write!(f, "invalid drill card: {}", line),
+        }
+    }
+}
+
+impl std::error::Error for DrillError {}
+
+impl From<io::Error> for DrillError {
+    fn from(err: io::Error) -> Self {
+        DrillError::Io(err)
+    }
+}
+
+impl From<FenError> for DrillError {
+    fn from(err: FenError) -> Self {
+        DrillError::Fen(err)
+    }
+}
+
+/// A position where the user blundered, asked again later as a quiz.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Card {
+    /// The position before the blunder, without move counters
+    pub epd: String,
+    pub blunder: Move,
+    /// Leitner box, starting at 0
+    pub level: usize,
+    /// Unix time in seconds from which the card is due
+    pub due: u64,
+    pub attempts: u32,
+    pub successes: u32,
+}
+
+impl Card {
+    pub fn position(&self) -> Result<Bughouse, FenError> {
+        parse_fen(&self.epd)
+    }
+}
+
+/// Outcome of answering a card.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Correct,
+    /// The blunder played in the game, again
+    Repeated,
+    /// A different move the analyzer also flags as a blunder
+    Blunder,
+    Illegal,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Correct => "correct",
+            Verdict::Repeated => "repeated",
+            Verdict::Blunder => "blunder",
+            Verdict::Illegal => "illegal",
+        })
+    }
+}
+
+/// What solving a card takes, for drilling one skill at a time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motif {
+    /// Every good move is a drop, the skill unique to crazyhouse
+    Drop,
+    /// A move mates at once
+    Mate,
+}
+
+pub const MOTIFS: [Motif; 2] = [Motif::Drop, Motif::Mate];
+
+impl Motif {
+    /// Whether the puzzle of `position`, where `blunder` was played, has this motif.
+    /// Good moves are the ones [`Drill::answer`] accepts.
+    pub fn matches<A: Analyzer>(
+        self,
+        position: &Bughouse,
+        blunder: &Move,
+        analyzer: &mut A,
+    ) -> bool {
+        match self {
+            Motif::Drop => {
+                let mut good = position
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|m| m != blunder && !analyzer.is_blunder(position, m))
+                    .peekable();
+                good.peek().is_some() && good.all(|m| matches!(m, Move::Put { .. }))
+            }
+            Motif::Mate => !position.mating_moves().is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Motif {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Motif::Drop => "drop",
+            Motif::Mate => "mate",
+        })
+    }
+}
+
+impl FromStr for Motif {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Motif, String> {
+        match s.trim() {
+            "drop" | "drops" => Ok(Motif::Drop),
+            "mate" => Ok(Motif::Mate),
+            _ => Err(format!("unknown motif: {}", s.trim())),
+        }
+    }
+}
+
+/// The motifs of the puzzle in `position`, where `blunder` was played.
+pub fn motifs<A: Analyzer>(position: &Bughouse, blunder: &Move, analyzer: &mut A) -> Vec<Motif> {
+    MOTIFS
+        .iter()
+        .copied()
+        .filter(|motif| motif.matches(position, blunder, analyzer))
+        .collect()
+}
+
+/// The moves of `game` where the user blundered according to `analyzer`, with the
+/// position each was played in.
+pub fn find_blunders<A: Analyzer>(game: &GameRecord, analyzer: &mut A) -> Vec<(Bughouse, Move)> {
+    let mut blunders = Vec::new();
+    let mut position = game.start.clone();
+    for m in &game.moves {
+        if !position.is_legal(m) {
+            break;
+        }
+        if position.turn() == game.user && analyzer.is_blunder(&position, m) {
+            blunders.push((position.clone(), m.clone()));
+        }
+        position.play_unchecked(m);
+    }
+    blunders
+}
+
+/// Spaced repetition deck of the user's blunders. When opened on a file, every change is
+/// written through to it, one card per line with tab separated fields.
+#[derive(Debug, Default)]
+pub struct Drill {
+    path: Option<PathBuf>,
+    cards: Vec<Card>,
+}
+
+impl Drill {
+    pub fn in_memory() -> Drill {
+        Drill::default()
+    }
+
+    /// A deck of `cards` kept in memory, e.g. as read from a database.
+    pub fn from_cards(mut cards: Vec<Card>) -> Drill {
+        for card in &mut cards {
+            card.level = card.level.min(INTERVALS.len() - 1);
+        }
+        Drill { path: None, cards }
+    }
+
+    /// Opens the deck saved at `path`, or an empty one if the file doesn't exist yet.
+    pub fn open(path: &Path) -> Result<Drill, DrillError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut cards = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            cards.push(parse_card(line)?);
+        }
+        Ok(Drill {
+            path: Some(path.to_path_buf()),
+            cards,
+        })
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// Adds the blunder `m` in `position` as a card due right away. A position already
+    /// in the deck is not added twice.
+    pub fn add(&mut self, position: &Bughouse, m: &Move, now: u64) -> Result<(), DrillError> {
+        let epd = epd(position);
+        if self.cards.iter().any(|card| card.epd == epd) {
+            return Ok(());
+        }
+        self.cards.push(Card {
+            epd,
+            blunder: m.clone(),
+            level: 0,
+            due: now,
+            attempts: 0,
+            successes: 0,
+        });
+        self.save()
+    }
+
+    /// Adds every blunder the user made in `game`, only those whose puzzle has `motif`
+    /// if given. Returns how many were added.
+    pub fn add_game<A: Analyzer>(
+        &mut self,
+        game: &GameRecord,
+        analyzer: &mut A,
+        motif: Option<Motif>,
+        now: u64,
+    ) -> Result<usize, DrillError> {
+        let mut blunders = find_blunders(game, analyzer);
+        blunders
+            .retain(|(position, m)| motif.is_none_or(|motif| motif.matches(position, m, analyzer)));
+        for (position, m) in &blunders {
+            self.add(position, m, now)?;
+        }
+        Ok(blunders.len())
+    }
+
+    /// The index of the most overdue card, if any is due at `now`.
+    pub fn next_due(&self, now: u64) -> Option<usize> {
+        self.cards
+            .iter()
+            .enumerate()
+            .filter(|(_, card)| card.due <= now)
+            .min_by_key(|(_, card)| card.due)
+            .map(|(index, _)| index)
+    }
+
+    /// Like [`Drill::next_due`], but only among cards whose puzzle has `motif`.
+    pub fn next_due_matching<A: Analyzer>(
+        &self,
+        now: u64,
+        motif: Motif,
+        analyzer: &mut A,
+    ) -> Result<Option<usize>, DrillError> {
+        let mut due: Vec<(usize, &Card)> = self
+            .cards
+            .iter()
+            .enumerate()
+            .filter(|(_, card)| card.due <= now)
+            .collect();
+        due.sort_by_key(|(_, card)| card.due);
+        for (index, card) in due {
+            if motif.matches(&card.position()?, &card.blunder, analyzer) {
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks the user's `answer` to card `index` and reschedules the card. An answer
+    /// is correct if it is legal, differs from the original blunder and `analyzer`
+    /// does not flag it as a blunder either.
+    pub fn answer<A: Analyzer>(
+        &mut self,
+        index: usize,
+        answer: &Move,
+        analyzer: &mut A,
+        now: u64,
+    ) -> Result<Verdict, DrillError> {
+        let card = &self.cards[index];
+        let position = card.position()?;
+        let verdict = if !position.is_legal(answer) {
+            Verdict::Illegal
+        } else if *answer == card.blunder {
+            Verdict::Repeated
+        } else if analyzer.is_blunder(&position, answer) {
+            Verdict::Blunder
+        } else {
+            Verdict::Correct
+        };
+        // Illegal input is a typo rather than an answer
+        if verdict == Verdict::Illegal {
+            return Ok(verdict);
+        }
+        let card = &mut self.cards[index];
+        card.attempts += 1;
+        if verdict == Verdict::Correct {
+            card.successes += 1;
+            card.level = (card.level + 1).min(INTERVALS.len() - 1);
+        } else {
+            card.level = 0;
+        }
+        card.due = now + INTERVALS[card.level] * DAY;
+        self.save()?;
+        Ok(verdict)
+    }
+
+    /// Share of all answers given so far that were correct.
+    pub fn success_rate(&self) -> Option<f32> {
+        let attempts: u32 = self.cards.iter().map(|card| card.attempts).sum();
+        let successes: u32 = self.cards.iter().map(|card| card.successes).sum();
+        if attempts == 0 {
+            None
+        } else {
+            Some(successes as f32 / attempts as f32)
+        }
+    }
+
+    fn save(&self) -> Result<(), DrillError> {
+        if let Some(path) = &self.path {
+            let mut text = String::new();
+            for card in &self.cards {
+                text.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\n",
+                    card.epd,
+                    Uci::from_standard(&card.blunder),
+                    card.level,
+                    card.due,
+                    card.attempts,
+                    card.successes
+                ));
+            }
+            write_atomic(path, text)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_card(line: &str) -> Result<Card, DrillError> {
+    let invalid = || DrillError::InvalidCard(line.to_string());
+    let fields: Vec<&str> = line.split('\t').collect();
+    let (epd, blunder, level, due, attempts, successes) = match fields.as_slice() {
+        [epd, blunder, level, due, attempts, successes] => {
+            (*epd, *blunder, *level, *due, *attempts, *successes)
+        }
+        _ => return Err(invalid()),
+    };
+    let position = parse_fen(epd)?;
+    let blunder = blunder
+        .parse::<Uci>()
+        .ok()
+        .and_then(|uci| uci.to_move(&position).ok())
+        .ok_or_else(invalid)?;
+    Ok(Card {
+        epd: epd.to_string(),
+        blunder,
+        level: level
+            .parse::<usize>()
+            .map_err(|_| invalid())?
+            .min(INTERVALS.len() - 1),
+        due: due.parse().map_err(|_| invalid())?,
+        attempts: attempts.parse().map_err(|_| invalid())?,
+        successes: successes.parse().map_err(|_| invalid())?,
+    })
+}
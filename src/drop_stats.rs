@@ -0,0 +1,156 @@
+use std::fmt;
+
+use shakmaty::{Move, Role};
+
+use crate::output::JsonObject;
+
+// Roles that can be dropped, in the order of the counts below
+const ROLES: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+
+fn index(role: Role) -> Option<usize> {
+    ROLES.iter().position(|&r| r == role)
+}
+
+/// How often each role's drops show up in principal variations and among the top root
+/// candidates, over one search or summed over many. Shows the engine's style, and roles
+/// whose drops never come up point at a bias of the priors or the rollout policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DropStats {
+    pv_moves: u64,
+    pv_drops: [u64; 5],
+    candidates: u64,
+    candidate_drops: [u64; 5],
+}
+
+impl DropStats {
+    pub fn new() -> DropStats {
+        DropStats::default()
+    }
+
+    /// Counts the drops of a principal variation.
+    pub fn add_pv(&mut self, pv: &[Move]) {
+        self.pv_moves += pv.len() as u64;
+        count_drops(&mut self.pv_drops, pv);
+    }
+
+    /// Counts the drops among the best root moves of a search.
+    pub fn add_candidates(&mut self, candidates: &[Move]) {
+        self.candidates += candidates.len() as u64;
+        count_drops(&mut self.candidate_drops, candidates);
+    }
+
+    /// Adds the counts of another search.
+    pub fn merge(&mut self, other: &DropStats) {
+        self.pv_moves += other.pv_moves;
+        self.candidates += other.candidates;
+        for i in 0..ROLES.len() {
+            self.pv_drops[i] += other.pv_drops[i];
+            self.candidate_drops[i] += other.candidate_drops[i];
+        }
+    }
+
+    /// Drops of `role` in principal variations.
+    pub fn pv_drops(&self, role: Role) -> u64 {
+        index(role).map_or(0, |i| self.pv_drops[i])
+    }
+
+    /// Drops of `role` among the top candidates.
+    pub fn candidate_drops(&self, role: Role) -> u64 {
+        index(role).map_or(0, |i| self.candidate_drops[i])
+    }
+
+    /// Share of principal variation moves that drop `role`.
+    pub fn pv_share(&self, role: Role) -> Option<f32> {
+        share(self.pv_drops(role), self.pv_moves)
+    }
+
+    /// Share of top candidates that drop `role`.
+    pub fn candidate_share(&self, role: Role) -> Option<f32> {
+        share(self.candidate_drops(role), self.candidates)
+    }
+
+    /// Roles never dropped in any principal variation or top candidate counted.
+    pub fn unexplored(&self) -> Vec<Role> {
+        ROLES
+            .iter()
+            .copied()
+            .filter(|&role| self.pv_drops(role) == 0 && self.candidate_drops(role) == 0)
+            .collect()
+    }
+
+    /// The counts on one line, only naming roles that were dropped, for `info string`:
+    /// `pv 2/9 P@ 1 N@ 1, candidates 1/5 P@ 1`.
+    pub fn summary(&self) -> String {
+        let roles = |counts: &[u64; 5]| {
+            ROLES
+                .iter()
+                .zip(counts)
+                .filter(|(_, &count)| count > 0)
+                .map(|(role, count)| format!(" {}@ {}", role.upper_char(), count))
+                .collect::<String>()
+        };
+        format!(
+            "pv {}/{}{}, candidates {}/{}{}",
+            self.pv_drops.iter().sum::<u64>(),
+            self.pv_moves,
+            roles(&self.pv_drops),
+            self.candidate_drops.iter().sum::<u64>(),
+            self.candidates,
+            roles(&self.candidate_drops)
+        )
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let roles = ROLES.iter().fold(JsonObject::new(), |object, &role| {
+            let stats = JsonObject::new()
+                .number("pv", self.pv_drops(role))
+                .number("candidates", self.candidate_drops(role));
+            object.raw(&role.char().to_string(), stats.to_string())
+        });
+        JsonObject::document("drop_stats")
+            .number("pv_moves", self.pv_moves)
+            .number("candidates", self.candidates)
+            .raw("roles", roles.to_string())
+    }
+}
+
+fn count_drops(counts: &mut [u64; 5], moves: &[Move]) {
+    for m in moves {
+        if let Move::Put { role, .. } = m {
+            if let Some(i) = index(*role) {
+                counts[i] += 1;
+            }
+        }
+    }
+}
+
+fn share(count: u64, total: u64) -> Option<f32> {
+    if total > 0 {
+        Some(count as f32 / total as f32)
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for DropStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &role in &ROLES {
+            writeln!(
+                f,
+                "{}@: {} in pv ({:.0}%), {} in candidates ({:.0}%)",
+                role.upper_char(),
+                self.pv_drops(role),
+                self.pv_share(role).unwrap_or(0f32) * 100f32,
+                self.candidate_drops(role),
+                self.candidate_share(role).unwrap_or(0f32) * 100f32
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::ops::Add;
+
+use shakmaty::{
+    attacks, Bitboard, Board, Color, Material, MaterialSide, Move, MoveList, Position,
+    PositionErrorKinds, Role, Square,
+};
+
+// Pawns are handled separately because of the back rank restriction
+const PIECE_DROP_ROLES: [Role; 4] = [Role::Knight, Role::Bishop, Role::Rook, Role::Queen];
+
+/// Pocket bookkeeping and drop rules, independent of the variant played on the board.
+///
+/// A pocketed variant wraps a shakmaty position together with `Pockets`, calls
+/// [`Pockets::play`] before forwarding each move to the wrapped position, and adds
+/// [`Pockets::push_drops`] on [`Pockets::legal_drop_squares`] to its move generation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pockets {
+    material: Material,
+}
+
+impl Pockets {
+    pub fn new(material: Material) -> Pockets {
+        Pockets { material }
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn side(&self, color: Color) -> &MaterialSide {
+        self.material.by_color(color)
+    }
+
+    pub fn add(&mut self, color: Color, role: Role) {
+        *self.material.by_color_mut(color).by_role_mut(role) += 1;
+    }
+
+    /// Takes one `role` out of the pocket of `color`. Returns `false` if there is none.
+    pub fn remove(&mut self, color: Color, role: Role) -> bool {
+        let count = self.material.by_color_mut(color).by_role_mut(role);
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    pub fn add_material(&mut self, material: Material) {
+        self.material = self.material.clone().add(material);
+    }
+
+    /// The role that goes into a pocket when `m` is played on `board`, if it captures.
+    /// Promoted pieces turn back into pawns.
+    pub fn captured_role(board: &Board, m: &Move) -> Option<Role> {
+        match *m {
+            Move::Normal {
+                capture: Some(capture),
+                to,
+                ..
+            } => Some(if board.promoted().contains(to) {
+                Role::Pawn
+            } else {
+                capture
+            }),
+            Move::EnPassant { .. } => Some(Role::Pawn),
+            _ => None,
+        }
+    }
+
+    /// Updates the pockets for `m`, made by `color` on `board`. Must be called before the
+    /// move is played on the board. Captured pieces go to the capturer's pocket.
+    pub fn play(&mut self, board: &Board, color: Color, m: &Move) {
+        if let Some(role) = Pockets::captured_role(board, m) {
+            self.add(color, role);
+        }
+        if let Move::Put { role, .. } = *m {
+            *self.material.by_color_mut(color).by_role_mut(role) -= 1;
+        }
+    }
+
+    /// Squares where the side to move can drop without leaving its king in check.
+    pub fn legal_drop_squares<P: Position>(position: &P) -> Bitboard {
+        let checkers = position.checkers();
+
+        if checkers.is_empty() {
+            !position.board().occupied()
+        } else if let Some(checker) = checkers.single_square() {
+            let king = position
+                .board()
+                .king_of(position.turn())
+                .expect("king in pocketed variant");
+            attacks::between(checker, king)
+        } else {
+            Bitboard(0)
+        }
+    }
+
+    /// Whether `color` has `role` in hand and may put it on `to`, given the squares
+    /// `targets` that are open for drops and the squares `pawn_squares` the rules allow
+    /// pawns on, which never include the back ranks.
+    pub fn can_drop(
+        &self,
+        color: Color,
+        role: Role,
+        to: Square,
+        targets: Bitboard,
+        pawn_squares: Bitboard,
+    ) -> bool {
+        role != Role::King
+            && self.side(color).by_role(role) > 0
+            && targets.contains(to)
+            && (role != Role::Pawn || (pawn_squares & !Bitboard::BACKRANKS).contains(to))
+    }
+
+    /// Appends every drop `color` can make onto `targets`, pawns only onto
+    /// `pawn_squares`.
+    pub fn push_drops(
+        &self,
+        color: Color,
+        targets: Bitboard,
+        pawn_squares: Bitboard,
+        moves: &mut MoveList,
+    ) {
+        let pocket = self.side(color);
+        for to in targets {
+            for &role in &PIECE_DROP_ROLES {
+                if pocket.by_role(role) > 0 {
+                    moves.push(Move::Put { role, to });
+                }
+            }
+        }
+        if pocket.pawns > 0 {
+            for to in targets & pawn_squares & !Bitboard::BACKRANKS {
+                moves.push(Move::Put {
+                    role: Role::Pawn,
+                    to,
+                });
+            }
+        }
+    }
+
+    /// Adjusts the errors found when setting up the wrapped position for the pieces in
+    /// hand: the combined material must fit, kings can't be in hand, and extra material
+    /// that standard chess would reject is fine.
+    pub fn validate(&self, board: &Board, mut errors: PositionErrorKinds) -> PositionErrorKinds {
+        let pockets = &self.material;
+        let total = pockets.count().saturating_add(board.occupied().count());
+        if total > 64 {
+            errors |= PositionErrorKinds::VARIANT;
+        } else if pockets.white.kings > 0 || pockets.black.kings > 0 {
+            errors |= PositionErrorKinds::TOO_MANY_KINGS;
+        }
+
+        if total <= 64
+            && usize::from(pockets.white.pawns.saturating_add(pockets.black.pawns))
+                .saturating_add(board.pawns().count())
+                <= 32
+        {
+            errors &= !PositionErrorKinds::IMPOSSIBLE_MATERIAL;
+        }
+        errors
+    }
+}
@@ -1,23 +1,297 @@
-use std::ops::{Not, Index, IndexMut};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::{Index, IndexMut};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use rand::prelude::SliceRandom;
-use shakmaty::{Color, Outcome, Position};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::san::{San, SanPlus};
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Move, Outcome, Position, Setup};
 
-use crate::board::Bughouse;
+use crate::board::{parse_fen, Bughouse, FenError, IllegalMove};
+use crate::build_info::BuildInfo;
+use crate::cancel::CancelToken;
+use crate::drop_stats::DropStats;
+use crate::eval::{evaluate_with_clocks, EvalParams};
+use crate::explain::{explain, Continuation, Explanation};
+use crate::limits::{SearchControl, SearchLimits, StopReason};
+#[cfg(feature = "nn")]
+use crate::nn::{Network, NetworkPriors};
+use crate::paths::write_atomic;
+use crate::prior::{EvalPriors, PriorSource};
+use crate::reservation::{Reservation, ReservationMessage, ReservedPriors};
+use crate::rollout::{MoveHistory, RolloutPolicy, RolloutStats};
+use crate::sparring::{SparringPriors, Theme};
+use crate::trace::{Phase, Trace};
+use crate::trades::{trade_penalty, TradePriors};
+use crate::training::SearchRecord;
 
 struct Node {
     side_that_moved: Color,
     position: Bughouse,
+    // Plies below the root. Transpositions are only merged at the same depth, which
+    // keeps repetitions from closing cycles
+    ply: usize,
     wins: f32,
     simulations: i32,
-    children: Vec<NodeId>,
+    children: Vec<Edge>,
+    proof: Option<Proof>,
 }
 
-#[derive(Copy, Clone)]
+// A result the search has proven for the side that moved into a node: a win or a loss
+// by mate, that many plies after the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Proof {
+    Win(u32),
+    Loss(u32),
+}
+
+// A move from a node to the node it leads to. Transpositions make several edges lead
+// to one node, so the move and its prior belong to the edge
+#[derive(Clone)]
+struct Edge {
+    // `None` for a pass
+    m: Option<Move>,
+    // Probability assigned by the prior source when the parent was expanded
+    prior: f32,
+    node: NodeId,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 struct NodeId(usize);
 
+/// How the search spreads its iterations over the root moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootStrategy {
+    /// PUCT all the way down, which minimizes regret over the whole search
+    Uct,
+    /// Sequential halving: a fixed budget is split into rounds, each giving the
+    /// surviving root moves equal playouts and dropping the worse half. Aims at
+    /// picking the best move with few playouts rather than at minimizing regret
+    SequentialHalving,
+    /// Gumbel root search: samples this many root moves from the priors perturbed by
+    /// Gumbel noise and narrows them down with sequential halving. Explores without
+    /// root noise and stays sound with very few playouts
+    #[cfg(feature = "nn")]
+    Gumbel { considered: usize },
+}
+
+/// Reads `uct`, `halving` or, with the `nn` feature, `gumbel <moves>`.
+impl std::str::FromStr for RootStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RootStrategy, String> {
+        match s.trim().split_once(' ') {
+            #[cfg(feature = "nn")]
+            Some(("gumbel", considered)) => considered
+                .trim()
+                .parse()
+                .map(|considered| RootStrategy::Gumbel { considered })
+                .map_err(|_| format!("invalid root strategy: {}", s)),
+            None if s.trim() == "uct" => Ok(RootStrategy::Uct),
+            None if s.trim() == "halving" => Ok(RootStrategy::SequentialHalving),
+            _ => Err(format!("unknown root strategy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RootStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RootStrategy::Uct => f.write_str("uct"),
+            RootStrategy::SequentialHalving => f.write_str("halving"),
+            #[cfg(feature = "nn")]
+            RootStrategy::Gumbel { considered } => write!(f, "gumbel {}", considered),
+        }
+    }
+}
+
+/// Settings of a search tree that outlive a single search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// Factor applied to the visit and win counts of a tree each time it is reused for
+    /// a later move, so statistics gathered long ago with different pockets fade out.
+    /// 1 keeps them unchanged.
+    pub visit_decay: f32,
+    /// Keep the tree after a search so the next one can start from the subtree of its
+    /// position, see [`Engine`]
+    pub reuse_tree: bool,
+    /// Training mode steering the search towards positions with this theme rather
+    /// than towards the best moves
+    pub sparring: Option<Theme>,
+    /// Plies a rollout plays before it is adjudicated by the static evaluation
+    pub rollout_depth: usize,
+    /// Extra plies a rollout may play past `rollout_depth` while every move gives
+    /// check, so mating attacks are not cut off halfway
+    pub check_extension: usize,
+    /// Rollouts end early, adjudicated like at `rollout_depth`, once the static
+    /// evaluation is off by more than this many pawns, checked every few plies
+    pub rollout_cutoff: Option<f32>,
+    /// Remaining time of both sides at the root, counted by the clock term of the
+    /// evaluation when adjudicating rollouts
+    pub clocks: Option<ByColor<Duration>>,
+    /// Danger score of our partner's king on the other board when playing a match, see
+    /// [`crate::danger::danger_score`]. Makes trades less attractive as it grows
+    pub partner_danger: Option<f32>,
+    pub root_strategy: RootStrategy,
+    /// Weight of the prior against the value in PUCT selection; larger values explore
+    /// more
+    pub exploration: f32,
+    /// Rollouts answer drop mate threats, see [`RolloutPolicy::prune_drop_threats`]
+    pub drop_threat_pruning: bool,
+    /// Noise mixed into the root priors at the start of each search, so self-play
+    /// games explore moves the priors would rule out
+    pub root_noise: Option<RootNoise>,
+    /// Most playouts of one search, whatever its limits allow
+    pub playout_cap: Option<u64>,
+    /// Most nodes the tree may hold; the search stops once it is full. Bounds the
+    /// memory of long searches, at roughly a kilobyte per node
+    pub max_tree_size: Option<usize>,
+    /// Pieces the partner reserved for the side to move at the root, searched as if
+    /// they were in hand with their drops discounted, see [`ReservedPriors`]
+    pub reservations: Vec<Reservation>,
+    /// Network giving the priors and, in place of rollouts, the values of new nodes
+    #[cfg(feature = "nn")]
+    pub network: Option<Arc<Network>>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            visit_decay: 1.0,
+            reuse_tree: true,
+            sparring: None,
+            rollout_depth: 200,
+            check_extension: 20,
+            rollout_cutoff: Some(15.0),
+            clocks: None,
+            partner_danger: None,
+            root_strategy: RootStrategy::Uct,
+            exploration: PUCT_C,
+            drop_threat_pruning: false,
+            root_noise: None,
+            playout_cap: None,
+            max_tree_size: None,
+            reservations: Vec::new(),
+            #[cfg(feature = "nn")]
+            network: None,
+        }
+    }
+}
+
+/// Dirichlet noise for the root priors, as in AlphaZero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RootNoise {
+    /// Concentration of the Dirichlet distribution; small values put most of the noise
+    /// on a few moves
+    pub alpha: f32,
+    /// Share of the noise in the noisy priors
+    pub fraction: f32,
+}
+
+impl Default for RootNoise {
+    fn default() -> Self {
+        RootNoise {
+            alpha: 0.3,
+            fraction: 0.25,
+        }
+    }
+}
+
+// Static evaluations closer to 0 than this adjudicate a rollout as drawn
+const ADJUDICATION_DRAW_MARGIN: f32 = 0.5;
+
+// Plies between checks of `SearchOptions::rollout_cutoff`, since evaluating is costly
+// next to playing a move
+const CUTOFF_INTERVAL: usize = 8;
+
+/// Restricts which moves the search considers at the root, for targeted analysis.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RootFilter {
+    /// Only these moves are searched, like UCI `searchmoves`
+    pub only: Option<Vec<Move>>,
+    /// These moves are not searched, to find the best alternative to them
+    pub exclude: Vec<Move>,
+}
+
+impl RootFilter {
+    pub fn allows(&self, m: &Move) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(m)) && !self.exclude.contains(m)
+    }
+
+    /// Passing is a root move like any other, but cannot be listed
+    pub fn allows_pass(&self) -> bool {
+        self.only.is_none()
+    }
+}
+
+/// Parses a list of moves in SAN or UCI notation separated by commas or spaces, like
+/// `e4,N@f3` or `e2e4 N@f3`, checking they are legal in `position`. A pass can't be
+/// listed.
+pub fn parse_move_list(position: &Bughouse, text: &str) -> Result<Vec<Move>, IllegalMove> {
+    text.split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| parse_move(position, token)?.ok_or_else(|| IllegalMove(token.to_string())))
+        .collect()
+}
+
+/// Parses a move in SAN or UCI notation, checking it is legal in `position`. A pass,
+/// `--` in SAN and `0000` in UCI, is `None` and only legal where the rules allow it.
+pub fn parse_move(position: &Bughouse, token: &str) -> Result<Option<Move>, IllegalMove> {
+    let illegal = || IllegalMove(token.to_string());
+    if token == "--" || token == "0000" {
+        return if position.can_pass() {
+            Ok(None)
+        } else {
+            Err(illegal())
+        };
+    }
+    let san = token
+        .parse::<San>()
+        .ok()
+        .and_then(|san| san.to_move(position).ok());
+    let uci = || {
+        token
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(position).ok())
+    };
+    san.or_else(uci).map(Some).ok_or_else(illegal)
+}
+
+/// `m` in SAN with its check or mate suffix, `None` being a pass written `--`.
+pub fn to_san(position: &Bughouse, m: Option<&Move>) -> SanPlus {
+    match m {
+        Some(m) => SanPlus::from_move(position.clone(), m),
+        None => SanPlus {
+            san: San::Null,
+            suffix: None,
+        },
+    }
+}
+
 struct Tree {
-    nodes: Vec<Node>
+    nodes: Vec<Node>,
+    options: SearchOptions,
+    root_filter: RootFilter,
+    policy: RolloutPolicy,
+    params: Arc<EvalParams>,
+    // Sent along with the kept tree when a search runs on a thread of its own
+    priors: Box<dyn PriorSource + Send>,
+    // Nodes by Zobrist hash and depth, so transpositions share one node
+    table: HashMap<(u64, usize), NodeId>,
+    rng: StdRng,
+    log: SearchLog,
+    // Set while replaying a log: the search is cancelled once it has run as many
+    // iterations as the logged one
+    replay_stop: Option<(usize, CancelToken)>,
+    trace: Option<Trace>,
 }
 impl Index<NodeId> for Tree {
     type Output = Node;
@@ -31,81 +305,339 @@ impl IndexMut<NodeId> for Tree {
     }
 }
 
+// Progressive unpruning schedule: a node starts with its best few children by prior
+// and gains another one each time its visit count grows by a constant factor.
+const UNPRUNED_AT_START: usize = 2;
+const UNPRUNE_SCALE: f32 = 4.0;
+const UNPRUNE_GROWTH: f32 = 1.4;
+
+// Default of `SearchOptions::exploration`
+const PUCT_C: f32 = 1.5;
+
+// Selection score of a proven win, less its plies, far above any PUCT score
+const PROVEN_WIN: f32 = 1e6;
+
+// Playouts of sequential halving when the search has no node limit
+const HALVING_BUDGET: u64 = 1000;
+
+// Constants of the value transform in Gumbel root search, as in the Gumbel MuZero paper
+#[cfg(feature = "nn")]
+const GUMBEL_C_VISIT: f32 = 50.0;
+#[cfg(feature = "nn")]
+const GUMBEL_C_SCALE: f32 = 1.0;
+
+/// The move picked by Gumbel root search and the improved policy to train towards.
+#[cfg(feature = "nn")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GumbelChoice {
+    /// `None` is a pass
+    pub m: Option<Move>,
+    /// Probability of every root move, `None` being a pass
+    pub policy: Vec<(Option<Move>, f32)>,
+}
+
+fn unpruned_children(simulations: i32) -> usize {
+    let grown = (1f32 + simulations as f32 / UNPRUNE_SCALE).ln() / UNPRUNE_GROWTH.ln();
+    UNPRUNED_AT_START + grown as usize
+}
+
 impl Tree {
+    fn new(root: Bughouse, params: Arc<EvalParams>, seed: u64) -> Tree {
+        let log = SearchLog::new(seed, root.fen(), (*params).clone());
+        let mut tree = Tree {
+            nodes: Vec::new(),
+            options: SearchOptions::default(),
+            root_filter: RootFilter::default(),
+            policy: RolloutPolicy::default(),
+            priors: Box::new(EvalPriors {
+                params: params.clone(),
+            }),
+            params,
+            table: HashMap::new(),
+            rng: StdRng::seed_from_u64(seed),
+            log,
+            replay_stop: None,
+            trace: None,
+        };
+        tree.push_node(Node {
+            // The root counts as having been reached by the opponent's move
+            side_that_moved: !root.turn(),
+            position: root,
+            ply: 0,
+            wins: 0f32,
+            simulations: 0,
+            children: vec![],
+            proof: None,
+        });
+        tree
+    }
+
+    fn set_options(&mut self, options: SearchOptions) {
+        let base: Box<dyn PriorSource + Send> = Box::new(EvalPriors {
+            params: self.params.clone(),
+        });
+        #[cfg(feature = "nn")]
+        let base: Box<dyn PriorSource + Send> = match &options.network {
+            Some(network) => Box::new(NetworkPriors {
+                network: network.clone(),
+            }),
+            None => base,
+        };
+        let priors: Box<dyn PriorSource + Send> = match options.sparring {
+            Some(theme) => Box::new(SparringPriors {
+                inner: base,
+                theme,
+                strength: 1.0,
+            }),
+            None => base,
+        };
+        let priors: Box<dyn PriorSource + Send> = if options.reservations.is_empty() {
+            priors
+        } else {
+            Box::new(ReservedPriors::new(
+                priors,
+                self[NodeId(0)].position.turn(),
+                options.reservations.clone(),
+            ))
+        };
+        let penalty = trade_penalty(&self.params.trades, options.partner_danger);
+        self.priors = if penalty > 0f32 {
+            Box::new(TradePriors {
+                inner: priors,
+                params: self.params.clone(),
+                color: self[NodeId(0)].position.turn(),
+                penalty,
+            })
+        } else {
+            priors
+        };
+        self.policy.prune_drop_threats = options.drop_threat_pruning;
+        self.options = options;
+    }
+
+    // Root children depend on the filter, so changing it discards the search so far
+    fn set_root_filter(&mut self, filter: RootFilter) {
+        if filter != self.root_filter {
+            self.nodes.truncate(1);
+            self.table.retain(|_, node| node.0 == 0);
+            let root = &mut self[NodeId(0)];
+            root.children.clear();
+            root.wins = 0f32;
+            root.simulations = 0;
+            root.proof = None;
+            self.root_filter = filter;
+        }
+    }
+
+    // The kept statistics were gathered with the old weights and stay; the priors pick
+    // up the new ones with the next options
+    fn set_params(&mut self, params: Arc<EvalParams>) {
+        self.params = params;
+    }
+
+    fn rollout_stats(&self) -> &RolloutStats {
+        self.policy.stats()
+    }
+
     fn push_node(&mut self, node: Node) -> NodeId {
         let idx = self.nodes.len();
+        self.table
+            .insert((node.position.zobrist_hash(), node.ply), NodeId(idx));
         self.nodes.push(node);
         NodeId(idx)
     }
+
+    fn children(&self, node_id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self[node_id].children.iter().map(|edge| edge.node)
+    }
+
+    // The edge from `parent` to its child `child`
+    fn edge(&self, parent: NodeId, child: NodeId) -> &Edge {
+        self[parent]
+            .children
+            .iter()
+            .find(|edge| edge.node == child)
+            .expect("child of parent")
+    }
+    // PUCT: a child's value plus an exploration bonus proportional to its prior, which
+    // shrinks as the child is visited. Children not visited yet are valued as their
+    // parent, so a high prior is what gets them tried first. Children proven lost are
+    // left out and proven wins come first, the shortest first
     fn select_next(&self, node_id: NodeId) -> Option<NodeId> {
         let node = &self[node_id];
-        let exploration_constant = 1.414; // sqrt(2) is theoretically ideal, but in practice this value is adjusted to maximize strength
-        let uct = |child_id: NodeId| {
-            let child = &self[child_id];
-            if child.simulations == 0 {
-                // Suggestions from around the internet say that the UCT score for unvisited nodes should be very high
-                f32::MAX
+        let sqrt_visits = (node.simulations.max(1) as f32).sqrt();
+        // The parent's wins count for the side that moved into it, the opponent of the
+        // side choosing among its children
+        let first_play = if node.simulations > 0 {
+            1f32 - node.wins / node.simulations as f32
+        } else {
+            0.5
+        };
+        let puct = |edge: &Edge| {
+            let child = &self[edge.node];
+            if let Some(Proof::Win(plies)) = child.proof {
+                return PROVEN_WIN - plies as f32;
+            }
+            let value = if child.simulations == 0 {
+                first_play
             } else {
                 child.wins / child.simulations as f32
-                    + exploration_constant
-                        * ((node.simulations as f32).ln() / child.simulations as f32).sqrt()
-            }
+            };
+            value
+                + self.options.exploration * edge.prior * sqrt_visits
+                    / (1 + child.simulations) as f32
         };
         node.children
             .iter()
+            .filter(|edge| !matches!(self[edge.node].proof, Some(Proof::Loss(_))))
+            .take(unpruned_children(node.simulations))
             .fold(
-                (None, -1f32),
-                |(highest_uct_child, highest_uct): (Option<NodeId>, f32), &child_id| {
-                    let uct_score = uct(child_id);
-                    if uct_score > highest_uct {
-                        (Some(child_id), uct_score)
+                (None, f32::NEG_INFINITY),
+                |(best, best_score): (Option<NodeId>, f32), edge| {
+                    let score = puct(edge);
+                    if score > best_score {
+                        (Some(edge.node), score)
                     } else {
-                        (highest_uct_child, highest_uct)
+                        (best, best_score)
                     }
                 },
             )
             .0
     }
 
-    // Selects an array of nodes from the root down to a leaf
+    // Selects an array of nodes from the root down to a leaf or a proven node
     fn select_branch(&self, root: NodeId) -> Vec<NodeId> {
         let mut branch = vec![root];
         while let Some(next) = self.select_next(*branch.last().unwrap()) {
             branch.push(next);
+            if self[next].proof.is_some() {
+                break;
+            }
         }
         branch
     }
 
+    // Children are stored in order of decreasing prior, which is the order in which
+    // progressive unpruning makes them available to selection. A child position that
+    // is already in the tree at the same depth is shared rather than added again.
     fn expand_tree(&mut self, node_id: NodeId) {
-        let node = &mut self[node_id];
-        let children: Vec<_> = node.position.legal_moves().iter().map(|legal_move| {
-            Node {
-                side_that_moved: node.side_that_moved.not(),
-                position: node
+        let node = &self[node_id];
+        let is_root = node_id.0 == 0;
+        let mut legal_moves = node.position.legal_moves();
+        if is_root {
+            legal_moves.retain(|m| self.root_filter.allows(m));
+        }
+        let priors = self.priors.priors(&node.position, &legal_moves);
+        let mut children: Vec<(Option<Move>, f32, Bughouse)> = legal_moves
+            .iter()
+            .zip(priors)
+            .map(|(legal_move, prior)| {
+                let position = node
                     .position
                     .clone()
-                    .play(&legal_move)
-                    .expect("Illegal move played from legal move list"),
-                wins: 0f32,
-                simulations: 0,
-                children: vec![],
-            }
-        }).collect();
-        let children_ids: Vec<_> = children.into_iter().map(|node| self.push_node(node)).collect();
+                    .play(legal_move)
+                    .expect("Illegal move played from legal move list");
+                (Some(legal_move.clone()), prior, position)
+            })
+            .collect();
+        // Passing is only possible in handicap games; rollouts never pass
+        let mut passed = node.position.clone();
+        if (!is_root || self.root_filter.allows_pass()) && passed.pass() {
+            let prior = children
+                .iter()
+                .map(|(_, prior, _)| *prior)
+                .fold(1f32, f32::min);
+            children.push((None, prior, passed));
+        }
+        children.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let side_that_moved = !node.side_that_moved;
+        let ply = node.ply + 1;
+        let edges: Vec<Edge> = children
+            .into_iter()
+            .map(|(m, prior, position)| {
+                // A hash collision must not merge two different positions
+                let node = match self.table.get(&(position.zobrist_hash(), ply)) {
+                    Some(&existing) if self[existing].position.transposes_to(&position) => existing,
+                    _ => self.push_node(Node {
+                        side_that_moved,
+                        position,
+                        ply,
+                        wins: 0f32,
+                        simulations: 0,
+                        children: vec![],
+                        proof: None,
+                    }),
+                };
+                Edge { m, prior, node }
+            })
+            .collect();
 
-        self[node_id].children.extend(children_ids);
+        self[node_id].children.extend(edges);
     }
 
-    fn simulate(position: Bughouse) -> Outcome {
-        let mut simulation_board = position.clone();
-        loop {
-            if let Some(random_move) = simulation_board
-                .legal_moves()
-                .choose(&mut rand::thread_rng())
-            {
+    // Recent moves on the path from the root to `leaf`, as context for the rollout policy
+    fn history(&self, branch: &[NodeId]) -> MoveHistory {
+        let mut history = MoveHistory::default();
+        for pair in branch.windows(2) {
+            if let Some(m) = &self.edge(pair[0], pair[1]).m {
+                history.push(m.clone(), self[pair[1]].position.is_check());
+            }
+        }
+        history
+    }
+
+    // `ply` is the depth of `position` below the search root
+    fn simulate(&mut self, position: Bughouse, ply: usize, mut history: MoveHistory) -> Outcome {
+        let mut simulation_board = position;
+        let mut played = Vec::new();
+        let outcome = loop {
+            let in_checking_sequence = history.last().is_some_and(|last| last.gave_check);
+            if played.len() >= self.options.rollout_depth {
+                let extended = played.len() - self.options.rollout_depth;
+                // Checking sequences run on until they stop or the extension is used up
+                if !in_checking_sequence || extended >= self.options.check_extension {
+                    if let Some(outcome) = simulation_board.outcome() {
+                        break outcome;
+                    }
+                    break adjudicate(
+                        &simulation_board,
+                        &self.params,
+                        self.options.clocks.as_ref(),
+                    );
+                }
+            }
+            if let Some(cutoff) = self.options.rollout_cutoff {
+                if !played.is_empty()
+                    && played.len() % CUTOFF_INTERVAL == 0
+                    && !in_checking_sequence
+                {
+                    let clocks = self.options.clocks.as_ref();
+                    if evaluate_with_clocks(&simulation_board, &self.params, clocks).abs() > cutoff
+                    {
+                        break simulation_board.outcome().unwrap_or_else(|| {
+                            adjudicate(&simulation_board, &self.params, clocks)
+                        });
+                    }
+                }
+            }
+            let legal_moves = simulation_board.legal_moves();
+            if let Some(chosen_move) = self.policy.choose(
+                &simulation_board,
+                &legal_moves,
+                ply + played.len(),
+                &history,
+                &self.params,
+                &mut self.rng,
+            ) {
+                played.push((
+                    simulation_board.turn(),
+                    history.last().map(|last| last.m.clone()),
+                    chosen_move.clone(),
+                ));
                 simulation_board = simulation_board
-                    .play(random_move)
+                    .play(&chosen_move)
                     .expect("Illegal move played from legal move list");
+                history.push(chosen_move, simulation_board.is_check());
             } else if let Some(outcome) = simulation_board.outcome() {
                 break outcome;
             } else {
@@ -113,29 +645,1670 @@ impl Tree {
                     "No legal moves were found, but the game is not over (this should be impossible)"
                 );
             }
+        };
+        self.policy.learn(ply, &played, outcome);
+        outcome
+    }
+
+    // Scores the last node of `branch`: by the result of a finished game, by the
+    // network if there is one or else by a rollout. Returns the score of the side that
+    // moved into the node
+    fn playout(&mut self, branch: &[NodeId]) -> f32 {
+        let last = *branch.last().expect("Branch should not be empty");
+        let side = self[last].side_that_moved;
+        match self[last].proof {
+            Some(Proof::Win(_)) => return 1f32,
+            Some(Proof::Loss(_)) => return 0f32,
+            None => {}
+        }
+        // Finished games need no rollout
+        if let Some(outcome) = self[last].position.outcome() {
+            return reward(side, outcome);
+        }
+        #[cfg(feature = "nn")]
+        if let Some(network) = self.options.network.clone() {
+            let value = self.timed(Phase::Rollout, |tree| network.value(&tree[last].position));
+            // The value is that of the side to move
+            return 1f32 - value;
+        }
+        let history = self.history(branch);
+        let position = self[last].position.clone();
+        let ply = branch.len() - 1;
+        let result = self.timed(Phase::Rollout, |tree| tree.simulate(position, ply, history));
+        reward(side, result)
+    }
+
+    // Counts `score`, that of the side that moved into the last node, for every node
+    // of `branch`, each from the side that moved into it
+    fn backpropagate(&mut self, branch: &[NodeId], score: f32) {
+        let side = self[*branch.last().expect("Branch should not be empty")].side_that_moved;
+        for &node_id in branch {
+            let node = &mut self[node_id];
+            node.wins += if node.side_that_moved == side {
+                score
+            } else {
+                1f32 - score
+            };
+            node.simulations += 1;
+        }
+    }
+
+    // Proves the last node of `branch` if its game is over, then carries proofs up the
+    // branch as far as they go. A node is lost for the side that moved into it once the
+    // side to move has a proven win, by the shortest one, and won once every move of
+    // the side to move is proven lost, by the longest defence. Draws are not proven.
+    fn prove(&mut self, branch: &[NodeId]) {
+        let last = *branch.last().expect("Branch should not be empty");
+        if self[last].proof.is_none() {
+            if let Some(Outcome::Decisive { winner }) = self[last].position.outcome() {
+                self[last].proof = Some(if winner == self[last].side_that_moved {
+                    Proof::Win(0)
+                } else {
+                    Proof::Loss(0)
+                });
+            }
+        }
+        for &node_id in branch.iter().rev().skip(1) {
+            match self.proof_from_children(node_id) {
+                Some(proof) => self[node_id].proof = Some(proof),
+                None => break,
+            }
+        }
+    }
+
+    fn proof_from_children(&self, node_id: NodeId) -> Option<Proof> {
+        let children = &self[node_id].children;
+        if children.is_empty() {
+            return None;
+        }
+        let mut shortest_win: Option<u32> = None;
+        let mut longest_loss = Some(0);
+        for edge in children {
+            match self[edge.node].proof {
+                Some(Proof::Win(plies)) => {
+                    shortest_win = Some(shortest_win.map_or(plies, |win| win.min(plies)))
+                }
+                Some(Proof::Loss(plies)) => longest_loss = longest_loss.map(|loss| plies.max(loss)),
+                None => longest_loss = None,
+            }
+        }
+        match (shortest_win, longest_loss) {
+            (Some(plies), _) => Some(Proof::Loss(plies + 1)),
+            (None, Some(plies)) => Some(Proof::Win(plies + 1)),
+            (None, None) => None,
+        }
+    }
+
+    // Whether the side to move at `root` has a proven mate
+    fn mate_proven(&self, root: NodeId) -> bool {
+        matches!(self[root].proof, Some(Proof::Loss(_)))
+    }
+
+    // The root child to play: the shortest proven mate, else the most visited child
+    // not proven lost, else the longest defence
+    fn best_child(&self, root: NodeId) -> Option<NodeId> {
+        self[root]
+            .children
+            .iter()
+            .max_by_key(|edge| self.root_move(edge).rank())
+            .map(|edge| edge.node)
+    }
+
+    // Runs one iteration through the root child `child`, selecting as usual below it.
+    // Returns the depth of the simulated node.
+    fn root_playout(&mut self, root: NodeId, child: NodeId) -> usize {
+        let branch = self.timed(Phase::Selection, |tree| {
+            let mut branch = vec![root];
+            // A proven child is scored by its proof
+            if tree[child].proof.is_some() {
+                branch.push(child);
+            } else {
+                branch.extend(tree.select_branch(child));
+            }
+            branch
+        });
+        self.iterate(branch)
+    }
+
+    // Searches until `control` says to stop, allocating iterations as the root
+    // strategy says, and returns the chosen root child
+    fn run(&mut self, root: NodeId, control: &SearchControl) -> Option<NodeId> {
+        self.log.begin(LoggedSearch {
+            moves: Vec::new(),
+            params: Some((*self.params).clone()),
+            options: self.options.clone(),
+            filter: self.root_filter.clone(),
+            budget: control.limits().nodes,
+            expansions: Vec::new(),
+        });
+        if self[root].children.is_empty() {
+            self.expand_tree(root);
+        }
+        if let Some(noise) = self.options.root_noise {
+            self.add_root_noise(root, noise);
+        }
+        // A mate in one is the only mate the search can prove on its own
+        if self
+            .children(root)
+            .any(|child| self[child].position.is_checkmate())
+        {
+            control.report_mate();
+        }
+        let chosen = match self.options.root_strategy {
+            RootStrategy::SequentialHalving => self.sequential_halving(root, control),
+            RootStrategy::Uct => {
+                while control.should_stop().is_none() && !self.is_capped(control) {
+                    let depth = self.execute_mcts(root);
+                    control.add_iteration(depth);
+                    if self.mate_proven(root) {
+                        control.report_mate();
+                    }
+                }
+                self.best_child(root)
+            }
+            #[cfg(feature = "nn")]
+            RootStrategy::Gumbel { considered } => self.gumbel_search(root, control, considered),
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.flush();
+        }
+        // A proven mate beats whatever the statistics say
+        match self.best_child(root) {
+            Some(child) if matches!(self[child].proof, Some(Proof::Win(_))) => Some(child),
+            _ => chosen,
+        }
+    }
+
+    // Whether the playout cap or the tree size cap of the options is reached
+    fn is_capped(&self, control: &SearchControl) -> bool {
+        self.options
+            .playout_cap
+            .is_some_and(|cap| control.nodes() >= cap)
+            || self
+                .options
+                .max_tree_size
+                .is_some_and(|cap| self.nodes.len() >= cap)
+    }
+
+    // Mixes Dirichlet noise into the priors of the root's children, keeping them
+    // ordered by prior for progressive unpruning
+    fn add_root_noise(&mut self, root: NodeId, noise: RootNoise) {
+        let count = self[root].children.len();
+        let samples = dirichlet(&mut self.rng, noise.alpha, count);
+        let children = &mut self[root].children;
+        for (edge, sample) in children.iter_mut().zip(samples) {
+            edge.prior = (1f32 - noise.fraction) * edge.prior + noise.fraction * sample;
+        }
+        children.sort_by(|a, b| b.prior.total_cmp(&a.prior));
+    }
+
+    // Sequential halving over the root moves. Returns the surviving move, `None` if the
+    // root has no moves.
+    fn sequential_halving(&mut self, root: NodeId, control: &SearchControl) -> Option<NodeId> {
+        if self[root].children.is_empty() {
+            self.expand_tree(root);
+        }
+        let children = self.children(root).collect();
+        self.halve(root, children, control, &|tree, id| tree.mean(id))
+    }
+
+    fn mean(&self, node_id: NodeId) -> f32 {
+        self[node_id].wins / self[node_id].simulations.max(1) as f32
+    }
+
+    // Rounds of sequential halving over `survivors`, ranked by `score` after each round.
+    // The playout budget is the node limit; other limits cut the rounds short.
+    fn halve(
+        &mut self,
+        root: NodeId,
+        mut survivors: Vec<NodeId>,
+        control: &SearchControl,
+        score: &dyn Fn(&Tree, NodeId) -> f32,
+    ) -> Option<NodeId> {
+        let budget = control.limits().nodes.unwrap_or(HALVING_BUDGET) as usize;
+        let rounds = (survivors.len() as f32).log2().ceil().max(1f32) as usize;
+        'rounds: while survivors.len() > 1 {
+            let per_child = (budget / (rounds * survivors.len())).max(1);
+            for &child in &survivors {
+                for _ in 0..per_child {
+                    if control
+                        .should_stop()
+                        .is_some_and(|reason| reason != StopReason::Nodes)
+                        || self.is_capped(control)
+                    {
+                        break 'rounds;
+                    }
+                    let depth = self.root_playout(root, child);
+                    control.add_iteration(depth);
+                }
+            }
+            survivors.sort_by(|&a, &b| score(self, b).total_cmp(&score(self, a)));
+            survivors.truncate(survivors.len().div_ceil(2));
+        }
+        survivors.sort_by(|&a, &b| score(self, b).total_cmp(&score(self, a)));
+        survivors.first().copied()
+    }
+
+    // Gumbel root search: samples `considered` root moves without replacement from the
+    // priors perturbed by Gumbel noise and narrows them down with sequential halving
+    #[cfg(feature = "nn")]
+    fn gumbel_search(
+        &mut self,
+        root: NodeId,
+        control: &SearchControl,
+        considered: usize,
+    ) -> Option<NodeId> {
+        use rand::Rng;
+
+        if self[root].children.is_empty() {
+            self.expand_tree(root);
+        }
+        let children = self[root].children.clone();
+        if children.is_empty() {
+            return None;
+        }
+        let mut perturbed: Vec<(NodeId, f32)> = children
+            .iter()
+            .map(|edge| {
+                let uniform: f32 = self.rng.gen_range(f32::EPSILON..1f32);
+                let gumbel = -(-uniform.ln()).ln();
+                (edge.node, gumbel + edge.prior.max(f32::MIN_POSITIVE).ln())
+            })
+            .collect();
+        perturbed.sort_by(|a, b| b.1.total_cmp(&a.1));
+        perturbed.truncate(considered.max(1));
+        let noise: Vec<(usize, f32)> = perturbed.iter().map(|&(id, g)| (id.0, g)).collect();
+        let candidates = perturbed.iter().map(|&(id, _)| id).collect();
+        let score = |tree: &Tree, id: NodeId| {
+            let perturbed = noise
+                .iter()
+                .find(|&&(index, _)| index == id.0)
+                .map_or(f32::MIN, |&(_, g)| g);
+            perturbed + tree.gumbel_sigma(root, tree.mean(id))
+        };
+        self.halve(root, candidates, control, &score)
+    }
+
+    // The improved policy of Gumbel root search, from the priors and the completed
+    // values of the root moves
+    #[cfg(feature = "nn")]
+    fn gumbel_policy(&self, root: NodeId) -> Vec<(Option<Move>, f32)> {
+        let children = &self[root].children;
+        // Unvisited moves are completed with the root's value for the side to move
+        let root_value = 1f32 - self.mean(root);
+        let logits: Vec<f32> = children
+            .iter()
+            .map(|edge| {
+                let q = if self[edge.node].simulations > 0 {
+                    self.mean(edge.node)
+                } else {
+                    root_value
+                };
+                edge.prior.max(f32::MIN_POSITIVE).ln() + self.gumbel_sigma(root, q)
+            })
+            .collect();
+        let max = logits.iter().copied().fold(f32::MIN, f32::max);
+        let weights: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let total: f32 = weights.iter().sum();
+        children
+            .iter()
+            .zip(weights)
+            .map(|(edge, weight)| (edge.m.clone(), weight / total))
+            .collect()
+    }
+
+    // Monotone transform putting values on the scale of the logits, growing with the
+    // visits so that well searched values outweigh the priors
+    #[cfg(feature = "nn")]
+    fn gumbel_sigma(&self, root: NodeId, q: f32) -> f32 {
+        let max_visits = self[root]
+            .children
+            .iter()
+            .map(|edge| self[edge.node].simulations)
+            .max()
+            .unwrap_or(0);
+        (GUMBEL_C_VISIT + max_visits as f32) * GUMBEL_C_SCALE * q
+    }
+
+    // Makes the node for `position` the root if it is among the first few plies of the
+    // tree, like after the opponent replied to the move the last search picked. Returns
+    // false if the tree holds no such node.
+    fn reroot(&mut self, position: &Bughouse) -> bool {
+        let hash = position.zobrist_hash();
+        // The hash only narrows it down; a collision would search the wrong subtree
+        let matches = |node: &Node| {
+            node.position.zobrist_hash() == hash
+                && node.position.transposes_to(position)
+                && node.position.rules() == position.rules()
+        };
+        let mut frontier = vec![(NodeId(0), Vec::new())];
+        for depth in 0..=REUSE_DEPTH {
+            let mut next = Vec::new();
+            for (node_id, moves) in frontier {
+                if matches(&self[node_id]) {
+                    return depth == 0 || self.advance(&moves);
+                }
+                for edge in &self[node_id].children {
+                    let mut line: Vec<Option<Move>> = moves.clone();
+                    line.push(edge.m.clone());
+                    next.push((edge.node, line));
+                }
+            }
+            frontier = next;
+        }
+        false
+    }
+
+    // Makes the node reached by `moves` from the root the new root, keeping its subtree
+    // with decayed statistics and dropping the rest. Returns false and leaves the tree
+    // unchanged if that line was never expanded. The search log notes the moves, so a
+    // replay advances along with it.
+    fn advance(&mut self, moves: &[Option<Move>]) -> bool {
+        let mut new_root = NodeId(0);
+        for m in moves {
+            match self[new_root]
+                .children
+                .iter()
+                .find(|edge| edge.m.as_ref() == m.as_ref())
+            {
+                Some(edge) => new_root = edge.node,
+                None => return false,
+            }
+        }
+
+        let decay = self.options.visit_decay;
+        let depth = self[new_root].ply;
+        let mut old_nodes: Vec<Option<Node>> = self.nodes.drain(..).map(Some).collect();
+        self.table.clear();
+        // Breadth first, handing out new ids in the order nodes are queued, which is the
+        // order they are pushed in. A shared node is queued once and keeps its first id
+        let mut new_ids = HashMap::from([(new_root.0, NodeId(0))]);
+        let mut queue = VecDeque::from(vec![new_root]);
+        while let Some(old_id) = queue.pop_front() {
+            let mut node = old_nodes[old_id.0]
+                .take()
+                .expect("tree nodes are queued once");
+            for edge in &mut node.children {
+                let old_child = edge.node;
+                let next_id = NodeId(new_ids.len());
+                edge.node = *new_ids.entry(old_child.0).or_insert_with(|| {
+                    queue.push_back(old_child);
+                    next_id
+                });
+            }
+            node.ply -= depth;
+            node.wins *= decay;
+            node.simulations = (node.simulations as f32 * decay) as i32;
+            self.push_node(node);
+        }
+
+        // A new root's children were expanded without a filter. Advancing by no moves
+        // keeps the root, whose children went through the filter
+        if !moves.is_empty() {
+            self.root_filter = RootFilter::default();
+        }
+        self.log.advance(moves);
+        true
+    }
+
+    // Root visit counts and value for exporting training data
+    fn record(&self, root: NodeId) -> SearchRecord {
+        let node = &self[root];
+        let visits = node
+            .children
+            .iter()
+            .map(|edge| (edge.m.clone(), self[edge.node].simulations.max(0) as u32))
+            .collect();
+        // Wins are counted for the side that moved into the root
+        let value = if node.simulations > 0 {
+            1f32 - node.wins / node.simulations as f32
+        } else {
+            0.5
+        };
+        SearchRecord {
+            position: node.position.clone(),
+            visits,
+            policy: None,
+            value,
+        }
+    }
+
+    // The most visited line below the root's child for `m`, for explaining the move
+    fn continuation(&self, root: NodeId, m: &Move) -> Option<Continuation> {
+        let most_visited = |node_id: NodeId| {
+            self[node_id]
+                .children
+                .iter()
+                .filter(|edge| self[edge.node].simulations > 0)
+                .max_by_key(|edge| self[edge.node].simulations)
+        };
+        let child = self[root]
+            .children
+            .iter()
+            .find(|edge| edge.m.as_ref() == Some(m))?
+            .node;
+        let mut moves = Vec::new();
+        let mut node_id = child;
+        // A pass ends the line since it has no move to show
+        while let Some(edge) = most_visited(node_id) {
+            match &edge.m {
+                Some(m) => moves.push(m.clone()),
+                None => break,
+            }
+            node_id = edge.node;
+        }
+        Some(Continuation {
+            moves,
+            visit_share: self[child].simulations as f32 / self[root].simulations.max(1) as f32,
+        })
+    }
+
+    // Drops in the most visited line and among the `top` most visited root moves
+    fn drop_stats(&self, root: NodeId, top: usize) -> DropStats {
+        let mut by_visits: Vec<&Edge> = self[root].children.iter().collect();
+        by_visits.sort_by_key(|edge| std::cmp::Reverse(self[edge.node].simulations));
+        let candidates: Vec<Move> = by_visits
+            .iter()
+            .take(top)
+            .filter_map(|edge| edge.m.clone())
+            .collect();
+        let mut pv = Vec::new();
+        if let Some(best) = by_visits.first() {
+            if let Some(m) = &best.m {
+                pv.push(m.clone());
+                pv.extend(self.continuation(root, m).map_or(Vec::new(), |c| c.moves));
+            }
+        }
+        let mut stats = DropStats::new();
+        stats.add_pv(&pv);
+        stats.add_candidates(&candidates);
+        stats
+    }
+
+    // Logs the node an iteration expanded or, at the root, played out from
+    fn log_iteration(&mut self, node: NodeId) {
+        self.log.push(node.0);
+        if let Some((iterations, cancel)) = &self.replay_stop {
+            let logged = self
+                .log
+                .searches
+                .last()
+                .map_or(0, |search| search.expansions.len());
+            // A full log stops growing, so its last search can't be told apart from here on
+            if logged >= *iterations || self.log.truncated {
+                cancel.cancel();
+            }
+        }
+    }
+
+    // Times `f` as `phase` of the current iteration if tracing is on
+    fn timed<T>(&mut self, phase: Phase, f: impl FnOnce(&mut Self) -> T) -> T {
+        if self.trace.is_none() {
+            return f(self);
+        }
+        let start = Instant::now();
+        let result = f(self);
+        if let Some(trace) = &mut self.trace {
+            trace.record(phase, start.elapsed());
+        }
+        result
+    }
+
+    // Searches from the root until `control` says to stop and sums up the tree
+    fn analyse(&mut self, control: &SearchControl) -> Analysis {
+        let root = NodeId(0);
+        let best = self
+            .run(root, control)
+            .map(|best| self.root_move(self.edge(root, best)));
+        summarize(&self[root].position, best, control)
+    }
+
+    // A root move with the statistics of its child, for merging trees
+    fn root_move(&self, edge: &Edge) -> RootMove {
+        let node = &self[edge.node];
+        // Plies from the root are one more than from the child
+        let mate = match node.proof {
+            Some(Proof::Win(plies)) => Some((plies as i32 + 2) / 2),
+            Some(Proof::Loss(plies)) => Some(-((plies as i32 + 2) / 2)),
+            None => None,
+        };
+        RootMove {
+            m: edge.m.clone(),
+            wins: node.wins,
+            simulations: node.simulations,
+            mate,
+        }
+    }
+
+    // The root FEN and seed, then one line per node in index order with a parent, the
+    // move from there, its prior and its statistics. Parents come before their
+    // children, so positions can be replayed from the root. Further edges into shared
+    // nodes follow as `edge <parent> <move> <prior> <child>`.
+    fn to_checkpoint(&self) -> String {
+        let mut parents: Vec<Option<(usize, &Edge)>> = vec![None; self.nodes.len()];
+        for (id, node) in self.nodes.iter().enumerate() {
+            for edge in &node.children {
+                let parent = &mut parents[edge.node.0];
+                if parent.is_none() && id < edge.node.0 {
+                    *parent = Some((id, edge));
+                }
+            }
+        }
+        let uci = |m: &Option<Move>| m.as_ref().map_or(Uci::Null, Uci::from_standard).to_string();
+        let mut text = format!(
+            "fen {}\nseed {}\n",
+            self[NodeId(0)].position.fen(),
+            self.log.seed
+        );
+        for (node, parent) in self.nodes.iter().zip(&parents) {
+            let (parent, m, prior) = match parent {
+                Some((parent, edge)) => (parent.to_string(), uci(&edge.m), edge.prior),
+                None => ("-".to_string(), "-".to_string(), 1f32),
+            };
+            text.push_str(&format!(
+                "node {} {} {} {} {}\n",
+                parent, m, prior, node.wins, node.simulations
+            ));
+        }
+        for (id, node) in self.nodes.iter().enumerate() {
+            for edge in &node.children {
+                if parents[edge.node.0].map(|(parent, _)| parent) != Some(id) {
+                    text.push_str(&format!(
+                        "edge {} {} {} {}\n",
+                        id,
+                        uci(&edge.m),
+                        edge.prior,
+                        edge.node.0
+                    ));
+                }
+            }
+        }
+        text
+    }
+
+    fn from_checkpoint(text: &str, params: Arc<EvalParams>) -> Result<Tree, CheckpointError> {
+        let invalid = |line: &str| CheckpointError::Invalid(line.to_string());
+        let mut lines = text.lines();
+        let root = match lines.next().and_then(|line| line.strip_prefix("fen ")) {
+            Some(root) => parse_fen(root)?,
+            None => return Err(invalid("missing fen")),
+        };
+        let seed: u64 = lines
+            .next()
+            .and_then(|line| line.strip_prefix("seed "))
+            .and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| invalid("missing seed"))?;
+        let mut tree = Tree::new(root, params, seed);
+        let node_id = |tree: &Tree, id: &str, line: &str| {
+            id.parse::<usize>()
+                .ok()
+                .filter(|&id| id < tree.nodes.len())
+                .map(NodeId)
+                .ok_or_else(|| invalid(line))
+        };
+        for (index, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if let ["edge", parent, m, prior, child] = fields.as_slice() {
+                let parent = node_id(&tree, parent, line)?;
+                let child = node_id(&tree, child, line)?;
+                let prior = prior.parse::<f32>().map_err(|_| invalid(line))?;
+                let (m, _) = replay_edge(&tree[parent].position, m)?;
+                tree[parent].children.push(Edge {
+                    m,
+                    prior,
+                    node: child,
+                });
+                continue;
+            }
+            let (parent, m, prior, wins, simulations) = match fields.as_slice() {
+                ["node", parent, m, prior, wins, simulations] => (
+                    *parent,
+                    *m,
+                    prior.parse::<f32>().map_err(|_| invalid(line))?,
+                    wins.parse::<f32>().map_err(|_| invalid(line))?,
+                    simulations.parse::<i32>().map_err(|_| invalid(line))?,
+                ),
+                _ => return Err(invalid(line)),
+            };
+            let node_id = if index == 0 {
+                if parent != "-" {
+                    return Err(invalid(line));
+                }
+                NodeId(0)
+            } else {
+                let parent = node_id(&tree, parent, line)?;
+                let (m, position) = replay_edge(&tree[parent].position, m)?;
+                let child = tree.push_node(Node {
+                    side_that_moved: !tree[parent].side_that_moved,
+                    position,
+                    ply: tree[parent].ply + 1,
+                    wins: 0f32,
+                    simulations: 0,
+                    children: vec![],
+                    proof: None,
+                });
+                tree[parent].children.push(Edge {
+                    m,
+                    prior,
+                    node: child,
+                });
+                child
+            };
+            let node = &mut tree[node_id];
+            node.wins = wins;
+            node.simulations = simulations;
+        }
+        // Edges into shared nodes were read after the rest
+        for node in &mut tree.nodes {
+            node.children.sort_by(|a, b| b.prior.total_cmp(&a.prior));
+        }
+        // Rollouts after a resume should not repeat those of the first stretch
+        let resumed = seed.wrapping_add(tree[NodeId(0)].simulations as u64);
+        tree.rng = StdRng::seed_from_u64(resumed);
+        Ok(tree)
+    }
+
+    // Runs one iteration: selects a leaf, expands it, plays out a rollout from one of
+    // its new children and backs the result up to the root. Returns the depth of the
+    // simulated node.
+    fn execute_mcts(&mut self, root: NodeId) -> usize {
+        let branch = self.timed(Phase::Selection, |tree| tree.select_branch(root));
+        self.iterate(branch)
+    }
+
+    // The rest of an iteration once `branch` is selected from the root down to a leaf
+    fn iterate(&mut self, mut branch: Vec<NodeId>) -> usize {
+        let leaf = *branch.last().expect("Branch should not be empty");
+        self.log_iteration(leaf);
+        // Proven nodes are scored by their proof and grow no further
+        if self[leaf].proof.is_none() {
+            self.timed(Phase::Expansion, |tree| tree.expand_tree(leaf));
+            if let Some(child) = self.select_next(leaf) {
+                branch.push(child);
+            }
+        }
+        let score = self.playout(&branch);
+        self.timed(Phase::Backpropagation, |tree| {
+            tree.backpropagate(&branch, score);
+            tree.prove(&branch);
+        });
+        if let Some(trace) = &mut self.trace {
+            trace.end_iteration();
+        }
+        branch.len() - 1
+    }
+}
+
+// Iterations of a search given no limits at all, which would otherwise never end
+const DEFAULT_BUDGET: u64 = 1000;
+
+// Plies below the root of the last search that a new search may start from
+const REUSE_DEPTH: usize = 2;
+
+/// Picks moves by Monte Carlo tree search. A single threaded engine keeps its tree
+/// between searches: when the next position is in the first plies of that tree, say
+/// after the opponent's reply, the search continues from that subtree rather than
+/// starting over. Searching on several threads always starts from fresh trees.
+pub struct Engine {
+    params: Arc<EvalParams>,
+    options: SearchOptions,
+    seed: u64,
+    threads: usize,
+    root_filter: RootFilter,
+    tree: Option<Tree>,
+}
+
+// A clone starts without the kept tree
+impl Clone for Engine {
+    fn clone(&self) -> Engine {
+        Engine {
+            params: self.params.clone(),
+            options: self.options.clone(),
+            seed: self.seed,
+            threads: self.threads,
+            root_filter: self.root_filter.clone(),
+            tree: None,
         }
     }
+}
+
+impl fmt::Debug for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Engine")
+            .field("options", &self.options)
+            .field("seed", &self.seed)
+            .field("threads", &self.threads)
+            .field(
+                "kept_nodes",
+                &self.tree.as_ref().map(|tree| tree.nodes.len()),
+            )
+            .finish()
+    }
+}
+
+impl Engine {
+    pub fn new(params: Arc<EvalParams>) -> Engine {
+        Engine {
+            params,
+            options: SearchOptions::default(),
+            seed: 0,
+            threads: 1,
+            root_filter: RootFilter::default(),
+            tree: None,
+        }
+    }
+
+    /// The weights the engine evaluates with.
+    pub fn params(&self) -> &Arc<EvalParams> {
+        &self.params
+    }
+
+    /// Swaps the weights for the following searches, say for a new `EvalFile`. Unlike
+    /// starting over with a new engine, the kept tree stays along with its statistics.
+    pub fn set_params(&mut self, params: Arc<EvalParams>) {
+        if let Some(tree) = &mut self.tree {
+            tree.set_params(params.clone());
+        }
+        self.params = params;
+    }
+
+    /// Forgets the tree kept from the last search, say for a new game.
+    pub fn clear_tree(&mut self) {
+        self.tree = None;
+    }
 
-    fn backpropagate(branch: Vec<&mut Node>, result: Outcome) {
-        for node in branch {
-            node.wins += match result {
-                Outcome::Decisive { winner } => {
-                    if winner == node.side_that_moved {
-                        1f32
+    /// The opponent's most visited reply to `m` in the kept tree, the move to ponder on
+    /// after playing `m`.
+    pub fn expected_reply(&self, m: &Move) -> Option<Move> {
+        let tree = self.tree.as_ref()?;
+        tree.continuation(NodeId(0), m)?.moves.into_iter().next()
+    }
+
+    /// Explains the legal move `m` in `position`, with what the kept tree says about
+    /// it if the tree is of `position`, see [`explain`].
+    pub fn explain(&self, position: &Bughouse, m: &Move) -> Explanation {
+        let continuation = self
+            .tree
+            .as_ref()
+            .filter(|tree| {
+                let root = &tree[NodeId(0)].position;
+                root.zobrist_hash() == position.zobrist_hash() && root.transposes_to(position)
+            })
+            .and_then(|tree| tree.continuation(NodeId(0), m));
+        explain(position, m, &self.params, continuation)
+    }
+
+    pub fn set_options(&mut self, options: SearchOptions) {
+        self.options = options;
+    }
+
+    /// Restricts the root moves of the following searches, say to what the partner
+    /// asked for. The root moves are expanded through the filter, so changing it
+    /// discards the whole kept tree but its root position.
+    pub fn set_root_filter(&mut self, filter: RootFilter) {
+        self.root_filter = filter;
+    }
+
+    /// Root visit counts and value of the last search, for training data. `None` if the
+    /// tree was not kept, because of [`SearchOptions::reuse_tree`] or several threads.
+    pub fn last_record(&self) -> Option<SearchRecord> {
+        self.tree.as_ref().map(|tree| tree.record(NodeId(0)))
+    }
+
+    /// How often the killer and countermove heuristics picked the rollout moves of the
+    /// kept tree, `None` if no tree was kept.
+    pub fn rollout_stats(&self) -> Option<&RolloutStats> {
+        self.tree.as_ref().map(Tree::rollout_stats)
+    }
+
+    /// Drops in the principal variation and among the `top` most visited root moves of
+    /// the last search, `None` if its tree was not kept.
+    pub fn drop_stats(&self, top: usize) -> Option<DropStats> {
+        self.tree
+            .as_ref()
+            .map(|tree| tree.drop_stats(NodeId(0), top))
+    }
+
+    /// The log of every search on the kept tree, to replay them exactly. `None` if no
+    /// tree was kept.
+    pub fn search_log(&self) -> Option<&SearchLog> {
+        self.tree.as_ref().map(|tree| &tree.log)
+    }
+
+    /// Threads searching in parallel, each on a tree of its own. At least one.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Seeds the rollouts of the next search. Each search moves on to the next unused
+    /// seeds, so a sequence of searches is reproducible from the first one.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Searches `position` within `budget` and returns the move to play, `None` if
+    /// there is none or the best move is a pass. Without any limit the search stops
+    /// after a default number of iterations.
+    pub fn search(&mut self, position: &Bughouse, budget: SearchLimits) -> Option<Move> {
+        self.analyse(position, budget).best
+    }
+
+    /// Like [`Engine::search`], but runs until `control` says to stop, so it can be
+    /// cancelled from another thread and its progress followed.
+    pub fn search_with(&mut self, position: &Bughouse, control: &SearchControl) -> Option<Move> {
+        self.analyse_with(position, control).best
+    }
+
+    /// Searches like [`Engine::search`] and also reports how good the position is.
+    pub fn analyse(&mut self, position: &Bughouse, mut budget: SearchLimits) -> Analysis {
+        if budget.nodes.is_none() && budget.time.is_none() && budget.depth.is_none() {
+            budget.nodes = Some(DEFAULT_BUDGET);
+        }
+        self.analyse_with(position, &SearchControl::new(budget, CancelToken::new()))
+    }
+
+    pub fn analyse_with(&mut self, position: &Bughouse, control: &SearchControl) -> Analysis {
+        if self.threads > 1 {
+            return self.analyse_parallel(position, control);
+        }
+        let mut tree = self.tree_for(position);
+        let analysis = tree.analyse(control);
+        if self.options.reuse_tree {
+            self.tree = Some(tree);
+        }
+        analysis
+    }
+
+    // The kept tree rerooted at `position` if it holds it, else a new tree, set up for
+    // the next search
+    fn tree_for(&mut self, position: &Bughouse) -> Tree {
+        let reused = match self.tree.take() {
+            Some(mut tree) if self.options.reuse_tree => tree.reroot(position).then_some(tree),
+            _ => None,
+        };
+        let mut tree =
+            reused.unwrap_or_else(|| Tree::new(position.clone(), self.params.clone(), self.seed));
+        self.seed = self.seed.wrapping_add(1);
+        tree.set_options(self.options.clone());
+        tree.set_root_filter(self.root_filter.clone());
+        tree
+    }
+
+    /// Searches `position` with Gumbel root search over `considered` sampled root moves,
+    /// returning the move and the improved policy to train towards. Keeps the tree like
+    /// [`Engine::analyse_with`].
+    #[cfg(feature = "nn")]
+    pub fn gumbel_search(
+        &mut self,
+        position: &Bughouse,
+        control: &SearchControl,
+        considered: usize,
+    ) -> Option<GumbelChoice> {
+        let mut tree = self.tree_for(position);
+        tree.set_options(SearchOptions {
+            root_strategy: RootStrategy::Gumbel { considered },
+            ..self.options.clone()
+        });
+        let root = NodeId(0);
+        let choice = tree.run(root, control).map(|chosen| GumbelChoice {
+            m: tree.edge(root, chosen).m.clone(),
+            policy: tree.gumbel_policy(root),
+        });
+        if self.options.reuse_tree {
+            self.tree = Some(tree);
+        }
+        choice
+    }
+
+    // Root parallelization: every thread grows a tree of its own with its own seed,
+    // all sharing `control` and so the budget. The root moves are merged by adding
+    // up their statistics across the trees.
+    fn analyse_parallel(&mut self, position: &Bughouse, control: &SearchControl) -> Analysis {
+        let seeds: Vec<u64> = (0..self.threads as u64)
+            .map(|thread| self.seed.wrapping_add(thread))
+            .collect();
+        self.seed = self.seed.wrapping_add(self.threads as u64);
+        let (params, options, filter) = (&self.params, &self.options, &self.root_filter);
+        let mut merged: Vec<RootMove> = Vec::new();
+        thread::scope(|scope| {
+            let workers: Vec<_> = seeds
+                .into_iter()
+                .map(|seed| {
+                    scope.spawn(move || {
+                        let mut tree = Tree::new(position.clone(), params.clone(), seed);
+                        tree.set_options(options.clone());
+                        tree.set_root_filter(filter.clone());
+                        let root = NodeId(0);
+                        tree.run(root, control);
+                        tree[root]
+                            .children
+                            .iter()
+                            .map(|edge| tree.root_move(edge))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for worker in workers {
+                for root_move in worker.join().expect("search thread panicked") {
+                    match merged.iter_mut().find(|merged| merged.m == root_move.m) {
+                        Some(merged) => {
+                            merged.wins += root_move.wins;
+                            merged.simulations += root_move.simulations;
+                            // Either proof holds; the smaller is the shorter mate or the
+                            // longer defence
+                            merged.mate = match (merged.mate, root_move.mate) {
+                                (Some(a), Some(b)) => Some(a.min(b)),
+                                (a, b) => a.or(b),
+                            };
+                        }
+                        None => merged.push(root_move),
+                    }
+                }
+            }
+        });
+        let best = merged.into_iter().max_by_key(RootMove::rank);
+        summarize(position, best, control)
+    }
+}
+
+// A root move and its statistics; `m` is `None` for a pass
+struct RootMove {
+    m: Option<Move>,
+    wins: f32,
+    simulations: i32,
+    // Moves to a proven mate, negative when the side to move is mated
+    mate: Option<i32>,
+}
+
+impl RootMove {
+    // Orders root moves for playing: proven mates, the shortest first, then moves not
+    // proven lost by visits, then lost moves, the longest defence first
+    fn rank(&self) -> (u8, i32, i32) {
+        match self.mate {
+            Some(moves) if moves > 0 => (2, -moves, self.simulations),
+            Some(moves) => (0, -moves, self.simulations),
+            None => (1, 0, self.simulations),
+        }
+    }
+}
+
+fn summarize(position: &Bughouse, best: Option<RootMove>, control: &SearchControl) -> Analysis {
+    let win_probability = match &best {
+        Some(RootMove {
+            mate: Some(moves), ..
+        }) => {
+            if *moves > 0 {
+                1f32
+            } else {
+                0f32
+            }
+        }
+        Some(best) if best.simulations > 0 => best.wins / best.simulations as f32,
+        // Mated or stalemated, or stopped before the first rollout
+        _ => match position.outcome() {
+            Some(outcome) => reward(position.turn(), outcome),
+            None => 0.5,
+        },
+    };
+    Analysis {
+        mate: best.as_ref().and_then(|best| best.mate),
+        best: best.and_then(|best| best.m),
+        win_probability,
+        nodes: control.nodes(),
+    }
+}
+
+/// An analysis of one position that may go on for days. The tree is kept between
+/// stretches of searching and can be checkpointed to disk, so the analysis survives
+/// restarts. What the rollout policy learned is not saved and is relearned on resume.
+pub struct LongAnalysis {
+    tree: Tree,
+}
+
+impl LongAnalysis {
+    pub fn new(position: &Bughouse, params: Arc<EvalParams>, seed: u64) -> LongAnalysis {
+        LongAnalysis {
+            tree: Tree::new(position.clone(), params, seed),
+        }
+    }
+
+    /// Continues an analysis from a checkpoint written by [`LongAnalysis::checkpoint`].
+    pub fn resume(path: &Path, params: Arc<EvalParams>) -> Result<LongAnalysis, CheckpointError> {
+        let tree = Tree::from_checkpoint(&fs::read_to_string(path)?, params)?;
+        Ok(LongAnalysis { tree })
+    }
+
+    pub fn position(&self) -> &Bughouse {
+        &self.tree[NodeId(0)].position
+    }
+
+    /// Iterations over all stretches so far.
+    pub fn iterations(&self) -> u64 {
+        self.tree[NodeId(0)].simulations as u64
+    }
+
+    /// Restricts the root moves of the following stretches. Like
+    /// [`Engine::set_root_filter`], changing it discards the tree searched so far.
+    pub fn set_root_filter(&mut self, filter: RootFilter) {
+        self.tree.set_root_filter(filter);
+    }
+
+    /// Times the phases of the following iterations, see [`Trace`], or stops timing
+    /// them and drops the trace so far.
+    pub fn set_tracing(&mut self, on: bool) {
+        self.tree.trace = if on { Some(Trace::new()) } else { None };
+    }
+
+    /// Phase timings of the stretches searched since tracing was turned on.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.tree.trace.as_ref()
+    }
+
+    /// The log of the stretches searched since the analysis started or resumed.
+    pub fn search_log(&self) -> &SearchLog {
+        &self.tree.log
+    }
+
+    /// Moves the analysis on to the position after `m`, `None` passing, keeping the
+    /// part of the tree below it. After a move the tree never expanded it starts over.
+    pub fn play(&mut self, m: Option<&Move>) {
+        if self.tree.advance(&[m.cloned()]) {
+            return;
+        }
+        let mut position = self.position().clone();
+        match m {
+            Some(m) => position.play_unchecked(m),
+            None => {
+                position.pass();
+            }
+        }
+        let params = Arc::clone(&self.tree.params);
+        self.tree = Tree::new(position, params, self.tree.log.seed);
+    }
+
+    /// Searches on until `control` says to stop.
+    pub fn run(&mut self, control: &SearchControl) -> Analysis {
+        self.tree.analyse(control)
+    }
+
+    /// Saves the tree to `path`, replacing the previous checkpoint only once the new
+    /// one is complete.
+    pub fn checkpoint(&self, path: &Path) -> Result<(), CheckpointError> {
+        write_atomic(path, self.tree.to_checkpoint())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// A line of the checkpoint that doesn't read back
+    Invalid(String),
+    Fen(FenError),
+    /// A move of the tree that can't be played from its parent
+    IllegalMove(IllegalMove),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "analysis checkpoint: {}", err),
+            CheckpointError::Invalid(line) => write!(f, "invalid analysis checkpoint: {}", line),
+            CheckpointError::Fen(err) => write!(f, "invalid checkpoint fen: {}", err),
+            CheckpointError::IllegalMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<FenError> for CheckpointError {
+    fn from(err: FenError) -> Self {
+        CheckpointError::Fen(err)
+    }
+}
+
+impl From<IllegalMove> for CheckpointError {
+    fn from(err: IllegalMove) -> Self {
+        CheckpointError::IllegalMove(err)
+    }
+}
+
+/// What a search found out about a position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    /// The move to play, `None` if there is none or the best move is a pass
+    pub best: Option<Move>,
+    /// Chance of the side to move winning, from the rollouts through the best move, or
+    /// 1 and 0 once a mate is proven
+    pub win_probability: f32,
+    /// Moves to a mate the search has proven through the best move, negative when the
+    /// side to move is the one mated
+    pub mate: Option<i32>,
+    pub nodes: u64,
+}
+
+// A sample of the symmetric Dirichlet distribution over `count` outcomes
+fn dirichlet(rng: &mut StdRng, alpha: f32, count: usize) -> Vec<f32> {
+    let mut samples: Vec<f32> = (0..count).map(|_| gamma(rng, alpha)).collect();
+    let total: f32 = samples.iter().sum();
+    if total > 0f32 {
+        samples.iter_mut().for_each(|sample| *sample /= total);
+    }
+    samples
+}
+
+// A sample of the gamma distribution with scale 1, by Marsaglia and Tsang's method
+fn gamma(rng: &mut StdRng, alpha: f32) -> f32 {
+    use rand::Rng;
+
+    if alpha < 1f32 {
+        let boost = rng.gen_range(f32::EPSILON..1f32).powf(1f32 / alpha);
+        return gamma(rng, alpha + 1f32) * boost;
+    }
+    let d = alpha - 1f32 / 3f32;
+    let c = 1f32 / (9f32 * d).sqrt();
+    loop {
+        // A standard normal sample by the Box-Muller transform
+        let (u1, u2): (f32, f32) = (rng.gen_range(f32::EPSILON..1f32), rng.gen());
+        let x = (-2f32 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+        let v = (1f32 + c * x).powi(3);
+        if v <= 0f32 {
+            continue;
+        }
+        let u: f32 = rng.gen_range(f32::EPSILON..1f32);
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+// Score of a finished game for the side that made the move into a node
+fn reward(side_that_moved: Color, result: Outcome) -> f32 {
+    match result {
+        Outcome::Decisive { winner } => {
+            if winner == side_that_moved {
+                1f32
+            } else {
+                0f32
+            }
+        }
+        Outcome::Draw => 0.5f32,
+    }
+}
+
+// Plays the checkpoint move `m` from `position`, returning it and the position after
+fn replay_edge(position: &Bughouse, m: &str) -> Result<(Option<Move>, Bughouse), IllegalMove> {
+    let mut position = position.clone();
+    let illegal = || IllegalMove(m.to_string());
+    match m.parse::<Uci>().map_err(|_| illegal())? {
+        Uci::Null => {
+            if !position.pass() {
+                return Err(illegal());
+            }
+            Ok((None, position))
+        }
+        uci => {
+            let m = uci.to_move(&position).map_err(|_| illegal())?;
+            position.play_unchecked(&m);
+            Ok((Some(m), position))
+        }
+    }
+}
+
+// Ends a rollout that ran into the depth cap by the static evaluation
+fn adjudicate(
+    position: &Bughouse,
+    params: &EvalParams,
+    clocks: Option<&ByColor<Duration>>,
+) -> Outcome {
+    let score = evaluate_with_clocks(position, params, clocks);
+    if score > ADJUDICATION_DRAW_MARGIN {
+        Outcome::Decisive {
+            winner: position.turn(),
+        }
+    } else if score < -ADJUDICATION_DRAW_MARGIN {
+        Outcome::Decisive {
+            winner: !position.turn(),
+        }
+    } else {
+        Outcome::Draw
+    }
+}
+
+// Iterations a search log holds at most, some 8 MB, after which it stops growing
+const MAX_LOGGED_ITERATIONS: usize = 1 << 20;
+
+/// Everything needed to rebuild a search tree exactly: the RNG seed, the weights, the
+/// root position and every search made on the tree since, with its options and the
+/// node each iteration expanded. The network of [`SearchOptions::network`] is not
+/// recorded, so searches with one only replay with rollouts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchLog {
+    pub seed: u64,
+    pub root_fen: String,
+    pub params: EvalParams,
+    pub searches: Vec<LoggedSearch>,
+    /// Set once the log held [`MAX_LOGGED_ITERATIONS`] and stopped growing. It then
+    /// replays the iterations it holds
+    pub truncated: bool,
+    // Moves the tree advanced by since the last search
+    pending: Vec<Option<Move>>,
+    iterations: usize,
+}
+
+/// One search of a [`SearchLog`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoggedSearch {
+    /// Moves from the root of the search before to the root of this one, along which
+    /// the tree was kept; `None` is a pass
+    pub moves: Vec<Option<Move>>,
+    /// The weights, when they changed since the search before, say by
+    /// [`Engine::set_params`]
+    pub params: Option<EvalParams>,
+    pub options: SearchOptions,
+    pub filter: RootFilter,
+    /// The node limit, which paces sequential halving
+    pub budget: Option<u64>,
+    /// The node each iteration expanded or, at the root, played out from
+    pub expansions: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    InvalidLog(String),
+    /// The replayed search expanded a different node than the logged one
+    Diverged {
+        iteration: usize,
+        logged: usize,
+        replayed: usize,
+    },
+    /// The replayed search stopped before running all its logged iterations
+    Ended {
+        iteration: usize,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::InvalidLog(message) => write!(f, "invalid search log: {}", message),
+            ReplayError::Diverged {
+                iteration,
+                logged,
+                replayed,
+            } => write!(
+                f,
+                "replay diverged at iteration {}: logged expansion of node {}, replay expanded {}",
+                iteration, logged, replayed
+            ),
+            ReplayError::Ended { iteration } => {
+                write!(f, "replay stopped early at iteration {}", iteration)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl SearchLog {
+    fn new(seed: u64, root_fen: String, params: EvalParams) -> SearchLog {
+        SearchLog {
+            seed,
+            root_fen,
+            params,
+            searches: Vec::new(),
+            truncated: false,
+            pending: Vec::new(),
+            iterations: 0,
+        }
+    }
+
+    fn begin(&mut self, mut search: LoggedSearch) {
+        if self.truncated || self.iterations >= MAX_LOGGED_ITERATIONS {
+            self.truncated = true;
+            return;
+        }
+        search.moves = std::mem::take(&mut self.pending);
+        let current = self
+            .searches
+            .iter()
+            .rev()
+            .find_map(|search| search.params.as_ref())
+            .unwrap_or(&self.params);
+        if search.params.as_ref() == Some(current) {
+            search.params = None;
+        }
+        self.searches.push(search);
+    }
+
+    fn push(&mut self, node: usize) {
+        if self.iterations >= MAX_LOGGED_ITERATIONS {
+            self.truncated = true;
+        }
+        if self.truncated {
+            return;
+        }
+        if let Some(search) = self.searches.last_mut() {
+            search.expansions.push(node);
+            self.iterations += 1;
+        }
+    }
+
+    fn advance(&mut self, moves: &[Option<Move>]) {
+        self.pending.extend(moves.iter().cloned());
+    }
+
+    /// Text form: `seed` and `fen` lines and the weights file contents, then every
+    /// search as a `search` line followed by `moves`, `weight`, `budget`, `option`,
+    /// `only`, `exclude` and `expand` lines, and `truncated` at the end of a full log.
+    /// A search with `weight` lines runs with those weights, written like the weights
+    /// file, from then on. An
+    /// `engine` line records the build that wrote the log and is ignored when parsing.
+    pub fn to_text(&self) -> String {
+        let uci = |m: &Option<Move>| m.as_ref().map_or(Uci::Null, Uci::from_standard).to_string();
+        let moves = |moves: &[Move]| {
+            moves
+                .iter()
+                .map(|m| Uci::from_standard(m).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut text = format!(
+            "engine {}\nseed {}\nfen {}\n",
+            BuildInfo::new(&self.params),
+            self.seed,
+            self.root_fen
+        );
+        text.push_str(&self.params.to_text());
+        for search in &self.searches {
+            text.push_str("search\n");
+            if !search.moves.is_empty() {
+                let line: Vec<String> = search.moves.iter().map(uci).collect();
+                text.push_str(&format!("moves {}\n", line.join(" ")));
+            }
+            if let Some(params) = &search.params {
+                for line in params.to_text().lines() {
+                    text.push_str(&format!("weight {}\n", line));
+                }
+            }
+            if let Some(budget) = search.budget {
+                text.push_str(&format!("budget {}\n", budget));
+            }
+            for (name, value) in options_to_text(&search.options) {
+                text.push_str(&format!("option {} {}\n", name, value));
+            }
+            if let Some(only) = &search.filter.only {
+                text.push_str(&format!("only {}\n", moves(only)));
+            }
+            if !search.filter.exclude.is_empty() {
+                text.push_str(&format!("exclude {}\n", moves(&search.filter.exclude)));
+            }
+            for expansion in &search.expansions {
+                text.push_str(&format!("expand {}\n", expansion));
+            }
+        }
+        if self.truncated {
+            text.push_str("truncated\n");
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<SearchLog, ReplayError> {
+        let invalid = |message: &str| ReplayError::InvalidLog(message.to_string());
+        let mut seed = None;
+        let mut root_fen = None;
+        let mut weights = String::new();
+        let mut searches: Vec<LoggedSearch> = Vec::new();
+        // The `weight` lines of each search
+        let mut search_weights: Vec<String> = Vec::new();
+        let mut truncated = false;
+        // The root of the search being read, for reading its moves
+        let mut position: Option<Bughouse> = None;
+        let to_move = |position: &Bughouse, token: &str| match token.parse::<Uci>() {
+            Ok(Uci::Null) => Ok(None),
+            Ok(uci) => uci
+                .to_move(position)
+                .map(Some)
+                .map_err(|_| invalid(&format!("illegal move {}", token))),
+            Err(_) => Err(invalid(&format!("bad move {}", token))),
+        };
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match (key, searches.last_mut()) {
+                ("engine", _) => {}
+                ("seed", _) => seed = Some(value.parse().map_err(|_| invalid("bad seed"))?),
+                ("fen", _) => {
+                    position = Some(parse_fen(value).map_err(|err| invalid(&err.to_string()))?);
+                    root_fen = Some(value.to_string());
+                }
+                ("search", _) => {
+                    searches.push(LoggedSearch {
+                        moves: Vec::new(),
+                        params: None,
+                        options: SearchOptions::default(),
+                        filter: RootFilter::default(),
+                        budget: None,
+                        expansions: Vec::new(),
+                    });
+                    search_weights.push(String::new());
+                }
+                ("truncated", _) => truncated = true,
+                ("moves", Some(search)) => {
+                    let position = position.as_mut().ok_or_else(|| invalid("missing fen"))?;
+                    for token in value.split_whitespace() {
+                        let m = to_move(position, token)?;
+                        match &m {
+                            Some(m) => position.play_unchecked(m),
+                            None => {
+                                position.pass();
+                            }
+                        }
+                        search.moves.push(m);
+                    }
+                }
+                ("weight", Some(_)) => {
+                    let weights = search_weights.last_mut().expect("a search");
+                    weights.push_str(value);
+                    weights.push('\n');
+                }
+                ("budget", Some(search)) => {
+                    search.budget = Some(value.parse().map_err(|_| invalid("bad budget"))?)
+                }
+                ("option", Some(search)) => {
+                    let (name, value) = value.split_once(' ').unwrap_or((value, ""));
+                    set_option(&mut search.options, name, value)
+                        .ok_or_else(|| invalid(&format!("bad option {}", name)))?;
+                }
+                ("only" | "exclude", Some(search)) => {
+                    let position = position.as_ref().ok_or_else(|| invalid("missing fen"))?;
+                    let moves = value
+                        .split_whitespace()
+                        .map(|token| to_move(position, token)?.ok_or_else(|| invalid("pass")))
+                        .collect::<Result<Vec<Move>, _>>()?;
+                    if key == "only" {
+                        search.filter.only = Some(moves);
                     } else {
-                        0f32
+                        search.filter.exclude = moves;
                     }
                 }
-                Outcome::Draw => 0.5f32,
+                ("expand", Some(search)) => search
+                    .expansions
+                    .push(value.parse().map_err(|_| invalid("bad expansion"))?),
+                (
+                    "moves" | "weight" | "budget" | "option" | "only" | "exclude" | "expand",
+                    None,
+                ) => return Err(invalid(&format!("{} before the first search", key))),
+                _ => {
+                    weights.push_str(line);
+                    weights.push('\n');
+                }
             }
         }
+        for (search, weights) in searches.iter_mut().zip(search_weights) {
+            if !weights.is_empty() {
+                let params =
+                    EvalParams::parse(&weights).map_err(|err| invalid(&err.to_string()))?;
+                search.params = Some(params);
+            }
+        }
+        let iterations = searches.iter().map(|search| search.expansions.len()).sum();
+        Ok(SearchLog {
+            seed: seed.ok_or_else(|| invalid("missing seed"))?,
+            root_fen: root_fen.ok_or_else(|| invalid("missing fen"))?,
+            params: EvalParams::parse(&weights).map_err(|err| invalid(&err.to_string()))?,
+            searches,
+            truncated,
+            pending: Vec::new(),
+            iterations,
+        })
     }
 
-    fn execute_mcts(&mut self, root: NodeId) {
-        let mut branch = self.select_branch(root);
-        let leaf = branch.pop().expect("Branch should not be empty");
-        self.expand_tree(leaf);
-        //
-        branch.push(leaf);
+    /// Rebuilds the tree from the log, running every search again with its options
+    /// and checking that every iteration expands the logged node.
+    pub fn replay(&self) -> Result<(), ReplayError> {
+        let root =
+            parse_fen(&self.root_fen).map_err(|err| ReplayError::InvalidLog(err.to_string()))?;
+        let mut tree = Tree::new(root, Arc::new(self.params.clone()), self.seed);
+        let mut first = 0;
+        for search in &self.searches {
+            if !search.moves.is_empty() && !tree.advance(&search.moves) {
+                return Err(ReplayError::InvalidLog(
+                    "moves past the kept tree".to_string(),
+                ));
+            }
+            if let Some(params) = &search.params {
+                tree.set_params(Arc::new(params.clone()));
+            }
+            tree.set_options(search.options.clone());
+            tree.set_root_filter(search.filter.clone());
+            let cancel = CancelToken::new();
+            if search.expansions.is_empty() {
+                cancel.cancel();
+            }
+            tree.replay_stop = Some((search.expansions.len(), cancel.clone()));
+            let limits = SearchLimits {
+                nodes: search.budget,
+                ..SearchLimits::default()
+            };
+            tree.run(NodeId(0), &SearchControl::new(limits, cancel));
+            let replayed = &tree.log.searches.last().expect("a search").expansions;
+            for (index, &logged) in search.expansions.iter().enumerate() {
+                let iteration = first + index;
+                match replayed.get(index) {
+                    Some(&replayed) if replayed != logged => {
+                        return Err(ReplayError::Diverged {
+                            iteration,
+                            logged,
+                            replayed,
+                        })
+                    }
+                    Some(_) => {}
+                    None => return Err(ReplayError::Ended { iteration }),
+                }
+            }
+            first += search.expansions.len();
+        }
+        Ok(())
+    }
+}
+
+// The options a log records, as names and values, with a `reservation` for each
+// reservation. The network is left out
+fn options_to_text(options: &SearchOptions) -> Vec<(&'static str, String)> {
+    fn optional<T: ToString>(value: Option<T>) -> String {
+        value.map_or_else(|| "none".to_string(), |value| value.to_string())
+    }
+    let mut text = vec![
+        ("visit_decay", options.visit_decay.to_string()),
+        ("reuse_tree", options.reuse_tree.to_string()),
+        ("sparring", optional(options.sparring)),
+        ("rollout_depth", options.rollout_depth.to_string()),
+        ("check_extension", options.check_extension.to_string()),
+        ("rollout_cutoff", optional(options.rollout_cutoff)),
+        (
+            "clocks",
+            optional(options.clocks.as_ref().map(|clocks| {
+                format!("{} {}", clocks.white.as_millis(), clocks.black.as_millis())
+            })),
+        ),
+        ("partner_danger", optional(options.partner_danger)),
+        ("root_strategy", options.root_strategy.to_string()),
+        ("exploration", options.exploration.to_string()),
+        (
+            "drop_threat_pruning",
+            options.drop_threat_pruning.to_string(),
+        ),
+        (
+            "root_noise",
+            optional(
+                options
+                    .root_noise
+                    .map(|noise| format!("{} {}", noise.alpha, noise.fraction)),
+            ),
+        ),
+        ("playout_cap", optional(options.playout_cap)),
+        ("max_tree_size", optional(options.max_tree_size)),
+    ];
+    for &reservation in &options.reservations {
+        // A reserve message without its keyword
+        let message = ReservationMessage::Reserve(reservation).to_string();
+        let fields = message.strip_prefix("reserve ").expect("a reserve message");
+        text.push(("reservation", fields.to_string()));
+    }
+    text
+}
+
+// Reads back one option written by `options_to_text`, `None` if it is malformed
+fn set_option(options: &mut SearchOptions, name: &str, value: &str) -> Option<()> {
+    fn optional<T: std::str::FromStr>(value: &str) -> Option<Option<T>> {
+        match value {
+            "none" => Some(None),
+            _ => value.parse().ok().map(Some),
+        }
     }
-}
\ No newline at end of file
+    fn pair<T: std::str::FromStr>(value: &str) -> Option<(T, T)> {
+        let (first, second) = value.split_once(' ')?;
+        Some((first.parse().ok()?, second.parse().ok()?))
+    }
+    match name {
+        "visit_decay" => options.visit_decay = value.parse().ok()?,
+        "reuse_tree" => options.reuse_tree = value.parse().ok()?,
+        "sparring" => options.sparring = optional(value)?,
+        "rollout_depth" => options.rollout_depth = value.parse().ok()?,
+        "check_extension" => options.check_extension = value.parse().ok()?,
+        "rollout_cutoff" => options.rollout_cutoff = optional(value)?,
+        "clocks" if value == "none" => options.clocks = None,
+        "clocks" => {
+            let (white, black) = pair(value)?;
+            options.clocks = Some(ByColor {
+                white: Duration::from_millis(white),
+                black: Duration::from_millis(black),
+            });
+        }
+        "partner_danger" => options.partner_danger = optional(value)?,
+        "root_strategy" => options.root_strategy = value.parse().ok()?,
+        "exploration" => options.exploration = value.parse().ok()?,
+        "drop_threat_pruning" => options.drop_threat_pruning = value.parse().ok()?,
+        "root_noise" if value == "none" => options.root_noise = None,
+        "root_noise" => {
+            let (alpha, fraction) = pair(value)?;
+            options.root_noise = Some(RootNoise { alpha, fraction });
+        }
+        "playout_cap" => options.playout_cap = optional(value)?,
+        "max_tree_size" => options.max_tree_size = optional(value)?,
+        "reservation" => match format!("reserve {}", value).parse().ok()? {
+            ReservationMessage::Reserve(reservation) => options.reservations.push(reservation),
+            ReservationMessage::Cancel(_) => return None,
+        },
+        _ => return None,
+    }
+    Some(())
+}
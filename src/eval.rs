@@ -0,0 +1,596 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use shakmaty::{attacks, Bitboard, ByColor, Color, Role, Setup};
+
+use crate::board::Bughouse;
+use crate::prior::MoveClass;
+
+/// Per-role weights, in pawns. Kings are never captured or dropped and have no value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoleValues {
+    pub pawn: f32,
+    pub knight: f32,
+    pub bishop: f32,
+    pub rook: f32,
+    pub queen: f32,
+}
+
+impl RoleValues {
+    pub fn by_role(&self, role: Role) -> f32 {
+        match role {
+            Role::Pawn => self.pawn,
+            Role::Knight => self.knight,
+            Role::Bishop => self.bishop,
+            Role::Rook => self.rook,
+            Role::Queen => self.queen,
+            Role::King => 0f32,
+        }
+    }
+
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "pawn" => Some(&mut self.pawn),
+            "knight" => Some(&mut self.knight),
+            "bishop" => Some(&mut self.bishop),
+            "rook" => Some(&mut self.rook),
+            "queen" => Some(&mut self.queen),
+            _ => None,
+        }
+    }
+}
+
+/// Bonuses added to a move's base weight of 1 when picking moves in playouts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolicyWeights {
+    /// Capturing back on the square of the opponent's last capture
+    pub recapture: f32,
+    /// Giving check
+    pub check: f32,
+    /// Giving check when our previous move gave check as well
+    pub follow_up_check: f32,
+    /// Playing the reply that won an earlier playout against the same move
+    pub countermove: f32,
+    /// Playing a quiet move that won earlier playouts at the same ply
+    pub killer: f32,
+    /// Divides the weight of moves that lose material to a likely premove by one plus
+    /// this value, for blitz play. Off at 0 since the check is expensive
+    pub premove_trap: f32,
+}
+
+impl PolicyWeights {
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "recapture" => Some(&mut self.recapture),
+            "check" => Some(&mut self.check),
+            "follow_up_check" => Some(&mut self.follow_up_check),
+            "countermove" => Some(&mut self.countermove),
+            "killer" => Some(&mut self.killer),
+            "premove_trap" => Some(&mut self.premove_trap),
+            _ => None,
+        }
+    }
+}
+
+/// Relative prior weight of each move class when a node is expanded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriorWeights {
+    pub check: f32,
+    pub capture: f32,
+    pub central_drop: f32,
+    pub edge_drop: f32,
+    pub quiet: f32,
+    /// Multiplies a move's weight by e to the power of this times the static evaluation
+    /// it gains, capped at [`MAX_PRIOR_GAIN`] pawns either way. Off at 0
+    pub eval_gain: f32,
+    /// Multiplies a capture's weight by the victim's value over the attacker's to the
+    /// power of this, so captures are tried most valuable victim, least valuable
+    /// attacker first. Off at 0
+    pub mvv_lva: f32,
+    /// Added to 1 to multiply the weight of drops that attack the squares around the
+    /// enemy king
+    pub king_drop: f32,
+}
+
+/// The most static evaluation a move's prior counts as gained or lost, in pawns.
+pub const MAX_PRIOR_GAIN: f32 = 8.0;
+
+impl PriorWeights {
+    pub fn by_class(&self, class: MoveClass) -> f32 {
+        match class {
+            MoveClass::Check => self.check,
+            MoveClass::Capture => self.capture,
+            MoveClass::CentralDrop => self.central_drop,
+            MoveClass::EdgeDrop => self.edge_drop,
+            MoveClass::Quiet => self.quiet,
+        }
+    }
+
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "check" => Some(&mut self.check),
+            "capture" => Some(&mut self.capture),
+            "central_drop" => Some(&mut self.central_drop),
+            "edge_drop" => Some(&mut self.edge_drop),
+            "quiet" => Some(&mut self.quiet),
+            "eval_gain" => Some(&mut self.eval_gain),
+            "mvv_lva" => Some(&mut self.mvv_lva),
+            "king_drop" => Some(&mut self.king_drop),
+            _ => None,
+        }
+    }
+}
+
+/// Penalties for a king the opponent can attack by dropping pieces in hand, in pawns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KingSafetyWeights {
+    /// Each empty square next to the king that we don't defend, while the opponent has
+    /// anything in hand to drop there
+    pub open_square: f32,
+    /// Each empty square a knight in the opponent's hand could give check from
+    pub knight_drop_check: f32,
+    /// Each empty square a bishop or queen in the opponent's hand could give check from
+    /// along a diagonal
+    pub diagonal_drop_check: f32,
+}
+
+impl KingSafetyWeights {
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "open_square" => Some(&mut self.open_square),
+            "knight_drop_check" => Some(&mut self.knight_drop_check),
+            "diagonal_drop_check" => Some(&mut self.diagonal_drop_check),
+            _ => None,
+        }
+    }
+}
+
+/// Discourages trades, since in bughouse the piece the opponent takes back goes to the
+/// opponent's partner. Applied by [`crate::trades::TradePriors`] to the captures of the
+/// side searching that can be taken back, in proportion to the pocket value of the
+/// capturing piece.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TradeWeights {
+    /// Penalty per pawn handed over in every game, a style knob for single-board play.
+    /// Off at 0
+    pub style: f32,
+    /// Penalty per pawn added when our partner's king is in full danger on the other
+    /// board, scaled by its danger score in match mode
+    pub partner_danger: f32,
+}
+
+impl TradeWeights {
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "style" => Some(&mut self.style),
+            "partner_danger" => Some(&mut self.partner_danger),
+            _ => None,
+        }
+    }
+}
+
+/// Weight of the clock difference, since in bughouse time is worth as much as material.
+/// Only used when the clocks are known.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockWeights {
+    /// Pawns per second of clock advantage. Off at 0
+    pub per_second: f32,
+    /// The most the clock term can add or take away
+    pub cap: f32,
+}
+
+impl ClockWeights {
+    fn by_name_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "per_second" => Some(&mut self.per_second),
+            "cap" => Some(&mut self.cap),
+            _ => None,
+        }
+    }
+}
+
+/// Tunable weights used by the evaluation and rollout policy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    /// Value of a piece standing on the board
+    pub board: RoleValues,
+    /// Value of a piece in the pocket, which can be dropped anywhere
+    pub pocket: RoleValues,
+    /// Value of each square a piece on the board attacks
+    pub mobility: RoleValues,
+    /// Value of each square a piece in hand could attack right after being dropped
+    pub drop_mobility: RoleValues,
+    pub policy: PolicyWeights,
+    pub prior: PriorWeights,
+    pub king_safety: KingSafetyWeights,
+    pub trades: TradeWeights,
+    pub clock: ClockWeights,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            board: RoleValues {
+                pawn: 1.0,
+                knight: 3.0,
+                bishop: 3.0,
+                rook: 5.0,
+                queen: 9.0,
+            },
+            pocket: RoleValues {
+                pawn: 1.5,
+                knight: 3.5,
+                bishop: 3.25,
+                rook: 5.0,
+                queen: 9.0,
+            },
+            mobility: RoleValues {
+                pawn: 0.02,
+                knight: 0.04,
+                bishop: 0.03,
+                rook: 0.02,
+                queen: 0.01,
+            },
+            drop_mobility: RoleValues {
+                pawn: 0.002,
+                knight: 0.008,
+                bishop: 0.006,
+                rook: 0.004,
+                queen: 0.002,
+            },
+            policy: PolicyWeights {
+                recapture: 3.0,
+                check: 1.0,
+                follow_up_check: 1.0,
+                countermove: 2.0,
+                killer: 1.0,
+                premove_trap: 0.0,
+            },
+            prior: PriorWeights {
+                check: 8.0,
+                capture: 6.0,
+                central_drop: 3.0,
+                edge_drop: 1.5,
+                quiet: 1.0,
+                eval_gain: 0.25,
+                mvv_lva: 0.5,
+                king_drop: 1.0,
+            },
+            king_safety: KingSafetyWeights {
+                open_square: 0.15,
+                knight_drop_check: 0.2,
+                diagonal_drop_check: 0.1,
+            },
+            trades: TradeWeights {
+                style: 0.0,
+                partner_danger: 0.3,
+            },
+            clock: ClockWeights {
+                per_second: 0.0,
+                cap: 3.0,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EvalParamsError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalParamsError::Io(err) => write!(f, "could not read weights file: {}", err),
+            EvalParamsError::Parse { line, message } => {
+                write!(f, "weights file line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalParamsError {}
+
+impl From<io::Error> for EvalParamsError {
+    fn from(err: io::Error) -> Self {
+        EvalParamsError::Io(err)
+    }
+}
+
+const ROLES: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+const ROLE_NAMES: &[&str] = &["pawn", "knight", "bishop", "rook", "queen"];
+
+// Every weight of the file format, used to write the complete parameter set out
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("board", ROLE_NAMES),
+    ("pocket", ROLE_NAMES),
+    ("mobility", ROLE_NAMES),
+    ("drop_mobility", ROLE_NAMES),
+    (
+        "policy",
+        &[
+            "recapture",
+            "check",
+            "follow_up_check",
+            "countermove",
+            "killer",
+            "premove_trap",
+        ],
+    ),
+    (
+        "prior",
+        &[
+            "check",
+            "capture",
+            "central_drop",
+            "edge_drop",
+            "quiet",
+            "eval_gain",
+            "mvv_lva",
+            "king_drop",
+        ],
+    ),
+    (
+        "king_safety",
+        &["open_square", "knight_drop_check", "diagonal_drop_check"],
+    ),
+    ("trades", &["style", "partner_danger"]),
+    ("clock", &["per_second", "cap"]),
+];
+
+impl EvalParams {
+    /// Writes all weights in the format read by [`EvalParams::parse`].
+    pub fn to_text(&self) -> String {
+        let mut params = self.clone();
+        let mut text = String::new();
+        for (section, names) in SECTIONS {
+            for name in names.iter() {
+                let key = format!("{}.{}", section, name);
+                let value = *params.weight_mut(&key).expect("known weight");
+                text.push_str(&format!("{} = {}\n", key, value));
+            }
+        }
+        text
+    }
+
+    /// Parses a weights file. Each non-empty line has the form `section.name = value`,
+    /// e.g. `pocket.knight = 3.5` or `policy.recapture = 3`; `#` starts a comment.
+    /// Weights that are not mentioned keep their default value.
+    pub fn parse(text: &str) -> Result<EvalParams, EvalParamsError> {
+        let mut params = EvalParams::default();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: &str| EvalParamsError::Parse {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(error("expected `key = value`")),
+            };
+            let value: f32 = value.parse().map_err(|_| error("value is not a number"))?;
+            *params
+                .weight_mut(key)
+                .ok_or_else(|| error("unknown weight"))? = value;
+        }
+        Ok(params)
+    }
+
+    pub fn load(path: &Path) -> Result<EvalParams, EvalParamsError> {
+        EvalParams::parse(&fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn weight_mut(&mut self, key: &str) -> Option<&mut f32> {
+        let (section, name) = key.split_once('.')?;
+        match section {
+            "board" => self.board.by_name_mut(name),
+            "pocket" => self.pocket.by_name_mut(name),
+            "mobility" => self.mobility.by_name_mut(name),
+            "drop_mobility" => self.drop_mobility.by_name_mut(name),
+            "policy" => self.policy.by_name_mut(name),
+            "prior" => self.prior.by_name_mut(name),
+            "king_safety" => self.king_safety.by_name_mut(name),
+            "trades" => self.trades.by_name_mut(name),
+            "clock" => self.clock.by_name_mut(name),
+            _ => None,
+        }
+    }
+}
+
+/// Static evaluation of `position` in pawns from the point of view of the side to move:
+/// material on the board and in hand plus mobility, less the danger to the king from
+/// drops.
+pub fn evaluate(position: &Bughouse, params: &EvalParams) -> f32 {
+    let us = position.turn();
+    side_score(position, us, params) - side_score(position, !us, params)
+}
+
+/// [`evaluate`] plus the clock advantage of the side to move, when `clocks` holds the
+/// remaining time of both sides.
+pub fn evaluate_with_clocks(
+    position: &Bughouse,
+    params: &EvalParams,
+    clocks: Option<&ByColor<Duration>>,
+) -> f32 {
+    let score = evaluate(position, params);
+    match clocks {
+        Some(clocks) => score + clock_score(clocks, position.turn(), params),
+        None => score,
+    }
+}
+
+/// Value in pawns of the time `color` has more on its clock than the opponent.
+pub fn clock_score(clocks: &ByColor<Duration>, color: Color, params: &EvalParams) -> f32 {
+    let ours = clocks.by_color(color).as_secs_f32();
+    let theirs = clocks.by_color(!color).as_secs_f32();
+    let cap = params.clock.cap.abs();
+    ((ours - theirs) * params.clock.per_second).clamp(-cap, cap)
+}
+
+fn side_score(position: &Bughouse, color: Color, params: &EvalParams) -> f32 {
+    let board = position.board();
+    let pocket = position
+        .pockets()
+        .expect("crazyhouse pockets")
+        .by_color(color);
+    let mut score = mobility(position, color, params) - king_danger(position, color, params);
+    for &role in &ROLES {
+        score += (board.by_piece(role.of(color)).count() as f32) * params.board.by_role(role);
+        score += f32::from(pocket.by_role(role)) * params.pocket.by_role(role);
+    }
+    score
+}
+
+/// How exposed the king of `color` is to the pieces the opponent holds: open squares
+/// next to it, and squares a knight, bishop or queen could be dropped on with check.
+pub fn king_danger(position: &Bughouse, color: Color, params: &EvalParams) -> f32 {
+    let board = position.board();
+    let king = match board.king_of(color) {
+        Some(king) => king,
+        None => return 0f32,
+    };
+    let theirs = position
+        .pockets()
+        .expect("crazyhouse pockets")
+        .by_color(!color);
+    let empty = !board.occupied();
+    let weights = &params.king_safety;
+    let mut danger = 0f32;
+    if theirs.count() > 0 {
+        let defended = board
+            .by_color(color)
+            .into_iter()
+            .filter(|&square| square != king)
+            .fold(Bitboard(0), |defended, square| {
+                defended | board.attacks_from(square)
+            });
+        let open = attacks::king_attacks(king) & empty & !defended;
+        danger += open.count() as f32 * weights.open_square;
+    }
+    if theirs.knights > 0 {
+        let checks = attacks::knight_attacks(king) & empty;
+        danger += checks.count() as f32 * weights.knight_drop_check;
+    }
+    if theirs.bishops > 0 || theirs.queens > 0 {
+        let checks = attacks::bishop_attacks(king, board.occupied()) & empty;
+        danger += checks.count() as f32 * weights.diagonal_drop_check;
+    }
+    danger
+}
+
+/// Mobility of `color`: the squares its pieces on the board attack, not counting its
+/// own pieces, plus for each role in hand the squares a piece of that role could attack
+/// from any square it may be dropped on.
+pub fn mobility(position: &Bughouse, color: Color, params: &EvalParams) -> f32 {
+    let board = position.board();
+    let ours = board.by_color(color);
+    let mut score = 0f32;
+    for from in ours & !board.kings() {
+        let role = board.role_at(from).expect("piece on occupied square");
+        let attacked = board.attacks_from(from) & !ours;
+        score += attacked.count() as f32 * params.mobility.by_role(role);
+    }
+
+    let pocket = position
+        .pockets()
+        .expect("crazyhouse pockets")
+        .by_color(color);
+    let empty = !board.occupied();
+    for &role in &ROLES {
+        if pocket.by_role(role) == 0 {
+            continue;
+        }
+        let drop_squares = if role == Role::Pawn {
+            empty & !Bitboard::BACKRANKS
+        } else {
+            empty
+        };
+        let controllable = drop_squares
+            .into_iter()
+            .fold(Bitboard(0), |controlled, square| {
+                controlled | attacks::attacks(square, role.of(color), board.occupied())
+            })
+            & !ours;
+        score += controllable.count() as f32 * params.drop_mobility.by_role(role);
+    }
+    score
+}
+
+/// Shared, swappable handle to the current weights.
+///
+/// Searches take a snapshot with [`EvalHandle::params`], so reloading the weights file
+/// while a search tree is alive only affects evaluations made after the reload.
+#[derive(Clone, Debug, Default)]
+pub struct EvalHandle {
+    inner: Arc<RwLock<EvalHandleInner>>,
+}
+
+#[derive(Debug, Default)]
+struct EvalHandleInner {
+    path: Option<PathBuf>,
+    params: Arc<EvalParams>,
+}
+
+impl EvalHandle {
+    pub fn new(params: EvalParams) -> Self {
+        EvalHandle {
+            inner: Arc::new(RwLock::new(EvalHandleInner {
+                path: None,
+                params: Arc::new(params),
+            })),
+        }
+    }
+
+    pub fn params(&self) -> Arc<EvalParams> {
+        self.inner
+            .read()
+            .expect("eval weights lock poisoned")
+            .params
+            .clone()
+    }
+
+    pub fn set_params(&self, params: EvalParams) {
+        self.inner
+            .write()
+            .expect("eval weights lock poisoned")
+            .params = Arc::new(params);
+    }
+
+    /// Loads weights from `path` and remembers it for later calls to [`EvalHandle::reload`].
+    /// On error the current weights are kept.
+    pub fn load(&self, path: &Path) -> Result<(), EvalParamsError> {
+        let params = EvalParams::load(path)?;
+        let mut inner = self.inner.write().expect("eval weights lock poisoned");
+        inner.path = Some(path.to_path_buf());
+        inner.params = Arc::new(params);
+        Ok(())
+    }
+
+    /// Re-reads the last loaded weights file. Does nothing if no file was loaded.
+    pub fn reload(&self) -> Result<(), EvalParamsError> {
+        let path = self
+            .inner
+            .read()
+            .expect("eval weights lock poisoned")
+            .path
+            .clone();
+        match path {
+            Some(path) => self.load(&path),
+            None => Ok(()),
+        }
+    }
+}
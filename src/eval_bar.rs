@@ -0,0 +1,118 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use shakmaty::{Move, Setup};
+
+use crate::board::{BoardId, Bughouse};
+use crate::cancel::CancelToken;
+use crate::engine::Engine;
+use crate::eval::EvalParams;
+use crate::limits::SearchLimits;
+
+/// How often and how hard the eval bars are recomputed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalBarConfig {
+    pub interval: Duration,
+    /// Search iterations per update. Small, so an update is cheap next to the players'
+    /// searches and arrives quickly
+    pub nodes: u64,
+}
+
+impl Default for EvalBarConfig {
+    fn default() -> Self {
+        EvalBarConfig {
+            interval: Duration::from_millis(200),
+            nodes: 64,
+        }
+    }
+}
+
+/// One eval bar reading for a spectator front-end.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalUpdate {
+    pub board: BoardId,
+    /// Chance of white winning on this board, so the bar does not flip with the turn
+    pub white: f32,
+    pub best: Option<Move>,
+}
+
+// Latest position of each board, and whether it has been evaluated yet
+type Boards = [Option<(Bughouse, bool)>; 2];
+
+/// Evaluates the boards of a live match on a thread of its own, independent of the
+/// engines playing it. The match reports every new position with
+/// [`EvalStream::update`]; each interval the boards that changed since the last reading
+/// are searched with a fixed node budget and the results sent to the sink.
+pub struct EvalStream {
+    boards: Arc<Mutex<Boards>>,
+    cancel: CancelToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EvalStream {
+    pub fn start(
+        params: Arc<EvalParams>,
+        config: EvalBarConfig,
+        sink: Sender<EvalUpdate>,
+    ) -> EvalStream {
+        let boards: Arc<Mutex<Boards>> = Arc::default();
+        let cancel = CancelToken::new();
+        let handle = {
+            let boards = boards.clone();
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let mut engine = Engine::new(params);
+                while !cancel.is_cancelled() {
+                    let started = Instant::now();
+                    for board in [BoardId::A, BoardId::B] {
+                        let position = match &mut boards.lock().expect("eval bar lock poisoned")
+                            [board.index()]
+                        {
+                            Some((position, evaluated @ false)) => {
+                                *evaluated = true;
+                                position.clone()
+                            }
+                            _ => continue,
+                        };
+                        let analysis = engine.analyse(&position, SearchLimits::nodes(config.nodes));
+                        let update = EvalUpdate {
+                            board,
+                            white: position
+                                .turn()
+                                .fold(analysis.win_probability, 1f32 - analysis.win_probability),
+                            best: analysis.best,
+                        };
+                        // Nobody is watching any more
+                        if sink.send(update).is_err() {
+                            return;
+                        }
+                    }
+                    thread::sleep(config.interval.saturating_sub(started.elapsed()));
+                }
+            })
+        };
+        EvalStream {
+            boards,
+            cancel,
+            handle: Some(handle),
+        }
+    }
+
+    /// Reports the current position of `board`. Positions replaced before the next
+    /// reading are never evaluated.
+    pub fn update(&self, board: BoardId, position: &Bughouse) {
+        self.boards.lock().expect("eval bar lock poisoned")[board.index()] =
+            Some((position.clone(), false));
+    }
+}
+
+impl Drop for EvalStream {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
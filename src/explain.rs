@@ -0,0 +1,124 @@
+use std::fmt;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::drops::Pockets;
+use crate::eval::EvalParams;
+
+/// The line the search expects after a move, read off the search tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Continuation {
+    /// Most visited replies, starting with the opponent's answer to the move
+    pub moves: Vec<Move>,
+    /// Share of the root's simulations that went through the move
+    pub visit_share: f32,
+}
+
+/// Why a move is good, in terms a coach or an annotation can put into words.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// The search spent most of its effort on this move and expects this line
+    DominantContinuation(Continuation),
+    /// The opponent could have mated with `threat` if we had not moved
+    ThreatPrevented { threat: Move },
+    /// Net material won, counting the captured piece leaving the board and joining
+    /// our pocket
+    MaterialGained { value: f32 },
+    /// We threaten to mate with `mate` unless the opponent does something about it
+    MateThreat { mate: Move },
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::DominantContinuation(continuation) => {
+                let moves: Vec<String> = continuation
+                    .moves
+                    .iter()
+                    .map(|m| Uci::from_standard(m).to_string())
+                    .collect();
+                write!(
+                    f,
+                    "expects {} ({:.0}% of the search)",
+                    moves.join(" "),
+                    continuation.visit_share * 100f32
+                )
+            }
+            Reason::ThreatPrevented { threat } => {
+                write!(f, "stops the mate {}", Uci::from_standard(threat))
+            }
+            Reason::MaterialGained { value } => write!(f, "wins {:.1} pawns", value),
+            Reason::MateThreat { mate } => {
+                write!(f, "threatens mate with {}", Uci::from_standard(mate))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub m: Move,
+    pub reasons: Vec<Reason>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Uci::from_standard(&self.m))?;
+        if self.reasons.is_empty() {
+            return write!(f, ": no reason found");
+        }
+        let reasons: Vec<String> = self.reasons.iter().map(Reason::to_string).collect();
+        write!(f, ": {}", reasons.join(", "))
+    }
+}
+
+// A continuation only explains a move if the search clearly preferred it
+const DOMINANT_SHARE: f32 = 0.5;
+
+/// Explains the legal move `m` in `position`. `continuation` is what the search tree
+/// says about the move, if it was searched.
+pub fn explain(
+    position: &Bughouse,
+    m: &Move,
+    params: &EvalParams,
+    continuation: Option<Continuation>,
+) -> Explanation {
+    let mut reasons = Vec::new();
+    let after = position.clone().play(m).expect("legal move to explain");
+
+    if let Some(continuation) = continuation {
+        if continuation.visit_share >= DOMINANT_SHARE {
+            reasons.push(Reason::DominantContinuation(continuation));
+        }
+    }
+
+    // What the opponent threatened is what they could do if it were their move now
+    if let Some(threats) = position.swapped_turn().map(|pos| pos.mating_moves()) {
+        if !threats.is_empty() && after.mating_moves().is_empty() {
+            reasons.push(Reason::ThreatPrevented {
+                threat: threats[0].clone(),
+            });
+        }
+    }
+
+    if let Some(role) = Pockets::captured_role(position.board(), m) {
+        let captured = m.capture().expect("capturing move");
+        reasons.push(Reason::MaterialGained {
+            value: params.board.by_role(captured) + params.pocket.by_role(role),
+        });
+    }
+
+    if let Some(mate) = after
+        .swapped_turn()
+        .and_then(|pos| pos.mating_moves().into_iter().next())
+    {
+        reasons.push(Reason::MateThreat { mate });
+    }
+
+    Explanation {
+        m: m.clone(),
+        reasons,
+    }
+}
@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use shakmaty::san::San;
+use shakmaty::{ByColor, Color, Move, Position, Role, Setup};
+
+use crate::board::{parse_fen, Bughouse, FenError, RulePreset};
+use crate::build_info::BuildInfo;
+use crate::cancel::CancelToken;
+use crate::engine::{Engine, RootFilter, SearchOptions};
+use crate::eval::EvalParams;
+use crate::game::{Decision, GameAdapter, GameManager};
+use crate::reservation::{Reservation, ReservationMessage, Reservations};
+use crate::resign::ResignPolicy;
+use crate::time::TimeManager;
+
+pub const FICS_HOST: &str = "freechess.org";
+pub const FICS_PORT: u16 = 5000;
+
+// Telnet's "interpret as command" byte, and the commands that take an option byte
+const IAC: u8 = 255;
+const WILL: u8 = 251;
+const DONT: u8 = 254;
+
+// How often a connected client waiting for the server checks for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum FicsError {
+    /// The connection failed or the server turned us away
+    Io(io::Error),
+    /// A board the server sent that doesn't make a position
+    Fen(FenError),
+}
+
+impl fmt::Display for FicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FicsError::Io(err) => write!(f, "fics connection: {}", err),
+            FicsError::Fen(err) => write!(f, "invalid board from fics: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for FicsError {}
+
+impl From<io::Error> for FicsError {
+    fn from(err: io::Error) -> Self {
+        FicsError::Io(err)
+    }
+}
+
+impl From<FenError> for FicsError {
+    fn from(err: FenError) -> Self {
+        FicsError::Fen(err)
+    }
+}
+
+/// Who to log in as and what to play. Without a partner the client seeks crazyhouse,
+/// since bughouse seeks need a team.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FicsConfig {
+    /// A registered handle, or `guest` for an unregistered one the server picks
+    pub login: String,
+    pub password: Option<String>,
+    pub partner: Option<String>,
+    /// The seek's clock, in minutes and seconds per move
+    pub initial: u32,
+    pub increment: u32,
+    pub games: usize,
+    /// Resigns games the search gives up on, `None` to always play on
+    pub resign: Option<ResignPolicy>,
+}
+
+impl Default for FicsConfig {
+    fn default() -> Self {
+        FicsConfig {
+            login: "guest".to_string(),
+            password: None,
+            partner: None,
+            initial: 2,
+            increment: 0,
+            games: 1,
+            resign: Some(ResignPolicy::default()),
+        }
+    }
+}
+
+const POCKET_ROLES: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+
+/// A board update in style 12, the machine readable format FICS sends after every move
+/// once `set style 12` is on:
+///
+/// `<12> rnbqkbnr pppppppp -------- -------- ----P--- -------- PPPP-PPP RNBQKBNR B 4 1 1 1 1 0 7 alice bob -1 2 0 39 39 120 120 1 P/e2-e4 (0:00) e4 0`
+///
+/// Pockets are not part of it; they come in the `<b1>` lines, see [`Holdings`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style12 {
+    /// The ranks from 8 down to 1, `-` for empty squares
+    pub ranks: Vec<String>,
+    pub turn: Color,
+    /// The file of a pawn that just moved two squares
+    pub double_push: Option<u8>,
+    /// White short, white long, black short, black long
+    pub castling: [bool; 4],
+    pub halfmoves: u32,
+    pub game: u32,
+    pub white: String,
+    pub black: String,
+    /// 1 when it is our move in our game, -1 when it is the opponent's, 0 when observing
+    pub relation: i32,
+    /// Seconds left; they go negative when a flag falls
+    pub white_clock: i64,
+    pub black_clock: i64,
+    pub move_number: u32,
+    /// The last move in SAN, `none` at the start
+    pub last_move: String,
+}
+
+impl Style12 {
+    pub fn parse(line: &str) -> Option<Style12> {
+        let fields: Vec<&str> = line
+            .trim()
+            .strip_prefix("<12>")?
+            .split_whitespace()
+            .collect();
+        if fields.len() < 29 || fields[..8].iter().any(|rank| rank.len() != 8) {
+            return None;
+        }
+        let flag = |index: usize| fields[index] == "1";
+        Some(Style12 {
+            ranks: fields[..8].iter().map(|rank| rank.to_string()).collect(),
+            turn: match fields[8] {
+                "W" => Color::White,
+                "B" => Color::Black,
+                _ => return None,
+            },
+            double_push: match fields[9].parse::<i8>().ok()? {
+                file @ 0..=7 => Some(file as u8),
+                _ => None,
+            },
+            castling: [flag(10), flag(11), flag(12), flag(13)],
+            halfmoves: fields[14].parse().ok()?,
+            game: fields[15].parse().ok()?,
+            white: fields[16].to_string(),
+            black: fields[17].to_string(),
+            relation: fields[18].parse().ok()?,
+            white_clock: fields[23].parse().ok()?,
+            black_clock: fields[24].parse().ok()?,
+            move_number: fields[25].parse().ok()?,
+            last_move: fields[28].to_string(),
+        })
+    }
+
+    /// Whether we play this game and it is our move.
+    pub fn is_our_move(&self) -> bool {
+        self.relation == 1
+    }
+
+    /// The position as a FEN, with the pockets of `holdings` if there are any.
+    pub fn fen(&self, holdings: Option<&Holdings>) -> String {
+        let mut board = Vec::new();
+        for rank in &self.ranks {
+            let mut text = String::new();
+            let mut empty = 0;
+            for ch in rank.chars() {
+                if ch == '-' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                text.push(ch);
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            board.push(text);
+        }
+        let pockets = holdings.map_or(String::new(), |holdings| {
+            format!(
+                "[{}{}]",
+                holdings.white.to_ascii_uppercase(),
+                holdings.black.to_ascii_lowercase()
+            )
+        });
+        let castling: String = "KQkq"
+            .chars()
+            .zip(self.castling.iter())
+            .filter(|(_, allowed)| **allowed)
+            .map(|(ch, _)| ch)
+            .collect();
+        let en_passant = match self.double_push {
+            Some(file) => format!("{}{}", (b'a' + file) as char, self.turn.fold('6', '3')),
+            None => "-".to_string(),
+        };
+        format!(
+            "{}{} {} {} {} {} {}",
+            board.join("/"),
+            pockets,
+            self.turn.fold('w', 'b'),
+            if castling.is_empty() { "-" } else { &castling },
+            en_passant,
+            self.halfmoves,
+            self.move_number.max(1)
+        )
+    }
+
+    /// Our seconds left, if it is our move.
+    pub fn our_clock(&self) -> i64 {
+        self.turn.fold(self.white_clock, self.black_clock)
+    }
+}
+
+/// The pockets of one board, as FICS sends them to bughouse and crazyhouse players:
+/// `<b1> game 12 white [PNB] black [pq]`, sometimes followed by `<- BQ` naming the
+/// piece that just arrived from the partner's board.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holdings {
+    pub game: u32,
+    pub white: String,
+    pub black: String,
+}
+
+impl Holdings {
+    /// How many pieces of `role` `color` holds.
+    pub fn count(&self, color: Color, role: Role) -> usize {
+        color
+            .fold(&self.white, &self.black)
+            .chars()
+            .filter(|&ch| Role::from_char(ch) == Some(role))
+            .count()
+    }
+
+    pub fn parse(line: &str) -> Option<Holdings> {
+        let rest = line.trim().strip_prefix("<b1>")?.trim_start();
+        let (game, rest) = rest.strip_prefix("game")?.trim_start().split_once(' ')?;
+        let (white, rest) = rest
+            .trim_start()
+            .strip_prefix("white")?
+            .trim_start()
+            .strip_prefix('[')?
+            .split_once(']')?;
+        let (black, _) = rest
+            .trim_start()
+            .strip_prefix("black")?
+            .trim_start()
+            .strip_prefix('[')?
+            .split_once(']')?;
+        Some(Holdings {
+            game: game.parse().ok()?,
+            white: white.to_string(),
+            black: black.to_string(),
+        })
+    }
+}
+
+/// A line from the server, as far as the client cares.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FicsEvent {
+    LoginPrompt,
+    PasswordPrompt,
+    /// Asks to press return to log in as the guest handle it names
+    GuestPrompt,
+    InvalidPassword,
+    SessionStart(String),
+    Board(Style12),
+    Holdings(Holdings),
+    /// `{Game 12 (alice vs. bob) Creating rated bughouse match.}`
+    GameStart {
+        game: u32,
+        players: [String; 2],
+        variant: String,
+    },
+    /// `{Game 12 (alice vs. bob) bob resigns} 1-0`
+    GameEnd {
+        game: u32,
+        result: String,
+    },
+    PartnerOffer(String),
+    /// Both `alice agrees to be your partner.` and `You agree to be alice's partner.`
+    Partnered(String),
+    PartnerTell(String),
+    Other,
+}
+
+impl FicsEvent {
+    pub fn parse(line: &str) -> FicsEvent {
+        let line = line.trim();
+        if line == "login:" {
+            return FicsEvent::LoginPrompt;
+        }
+        if line == "password:" {
+            return FicsEvent::PasswordPrompt;
+        }
+        if line.starts_with("Press return to enter the server as") {
+            return FicsEvent::GuestPrompt;
+        }
+        if line.starts_with("**** Invalid password") {
+            return FicsEvent::InvalidPassword;
+        }
+        if let Some(rest) = line.strip_prefix("**** Starting FICS session as ") {
+            let handle = rest.split(|ch: char| !ch.is_ascii_alphanumeric()).next();
+            return FicsEvent::SessionStart(handle.unwrap_or("").to_string());
+        }
+        if let Some(board) = Style12::parse(line) {
+            return FicsEvent::Board(board);
+        }
+        if let Some(holdings) = Holdings::parse(line) {
+            return FicsEvent::Holdings(holdings);
+        }
+        if let Some(event) = game_line(line) {
+            return event;
+        }
+        if let Some((name, _)) = line.split_once(" offers to be your bughouse partner") {
+            return FicsEvent::PartnerOffer(name.to_string());
+        }
+        if let Some((name, _)) = line.split_once(" agrees to be your partner") {
+            return FicsEvent::Partnered(name.to_string());
+        }
+        if let Some(rest) = line.strip_prefix("You agree to be ") {
+            if let Some((name, _)) = rest.split_once("'s partner") {
+                return FicsEvent::Partnered(name.to_string());
+            }
+        }
+        if let Some((_, message)) = line.split_once(" (your partner) tells you: ") {
+            return FicsEvent::PartnerTell(message.trim().to_string());
+        }
+        FicsEvent::Other
+    }
+}
+
+// `{Game <n> (<white> vs. <black>) <text>} <result>`
+fn game_line(line: &str) -> Option<FicsEvent> {
+    let rest = line.strip_prefix("{Game ")?;
+    let (game, rest) = rest.split_once(' ')?;
+    let game = game.parse().ok()?;
+    let (players, rest) = rest.strip_prefix('(')?.split_once(')')?;
+    let (white, black) = players.split_once(" vs. ")?;
+    let (text, result) = rest.split_once('}')?;
+    let result = result.trim();
+    if result.is_empty() {
+        let variant = text
+            .trim()
+            .strip_prefix("Creating ")?
+            .strip_suffix(" match.")?;
+        Some(FicsEvent::GameStart {
+            game,
+            players: [white.to_string(), black.to_string()],
+            variant: variant.split_whitespace().last()?.to_string(),
+        })
+    } else {
+        Some(FicsEvent::GameEnd {
+            game,
+            result: result.to_string(),
+        })
+    }
+}
+
+/// A FICS session: logs in, seeks games and plays the moves of the engine in them.
+///
+/// The server talks telnet, with prompts that don't end in a newline, so lines are split
+/// on the prompts as well and telnet commands are dropped. Partner messages `sit` and
+/// `go` hold and release our next move, as in [`crate::xboard::XboardEngine`]. The
+/// partner's `reserve` and `cancel` messages, see [`ReservationMessage`], announce
+/// pieces to search as if already in hand until they arrive.
+///
+/// Cancelling `shutdown` resigns the running game and logs out. Reads must time out now
+/// and then for it to be noticed while the server is quiet, as they do after
+/// [`FicsClient::connect`].
+pub struct FicsClient<R, W> {
+    input: BufReader<R>,
+    output: W,
+    config: FicsConfig,
+    pub time: TimeManager,
+    pub shutdown: CancelToken,
+    manager: GameManager<Engine>,
+    handle: Option<String>,
+    holdings: HashMap<u32, Holdings>,
+    game: Option<u32>,
+    bughouse: bool,
+    sitting: bool,
+    // The last board where it was our move, waiting while we sit
+    pending: Option<Style12>,
+    // The game and move number we last moved in, so refreshed boards aren't answered twice
+    moved: Option<(u32, u32, Color)>,
+    // Our color in the running game, once we had a move
+    color: Option<Color>,
+    reservations: Reservations,
+    played: usize,
+}
+
+impl FicsClient<TcpStream, TcpStream> {
+    /// Connects to `host`, normally [`FICS_HOST`] on [`FICS_PORT`].
+    pub fn connect(
+        host: &str,
+        port: u16,
+        config: FicsConfig,
+        params: Arc<EvalParams>,
+    ) -> Result<FicsClient<TcpStream, TcpStream>, FicsError> {
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(FicsClient::new(stream.try_clone()?, stream, config, params))
+    }
+}
+
+impl<R: Read, W: Write> FicsClient<R, W> {
+    pub fn new(input: R, output: W, config: FicsConfig, params: Arc<EvalParams>) -> Self {
+        FicsClient {
+            input: BufReader::new(input),
+            output,
+            config,
+            time: TimeManager::default(),
+            shutdown: CancelToken::new(),
+            manager: GameManager::new(Engine::new(params), None, Bughouse::default()),
+            handle: None,
+            holdings: HashMap::new(),
+            game: None,
+            bughouse: false,
+            sitting: false,
+            pending: None,
+            moved: None,
+            color: None,
+            reservations: Reservations::new(),
+            played: 0,
+        }
+    }
+
+    /// The handle the server gave us, once logged in.
+    pub fn handle(&self) -> Option<&str> {
+        self.handle.as_deref()
+    }
+
+    /// The games finished so far.
+    pub fn games_played(&self) -> usize {
+        self.played
+    }
+
+    /// Reads from the server until it hangs up, the configured number of games is
+    /// played or `shutdown` is cancelled, and logs out.
+    pub fn run(&mut self) -> Result<(), FicsError> {
+        while let Some(line) = self.read_line()? {
+            if !self.handle_line(&line)? {
+                self.send("quit")?;
+                return Ok(());
+            }
+        }
+        if self.shutdown.is_cancelled() {
+            if self.game.is_some() {
+                self.send("resign")?;
+            }
+            self.send("quit")?;
+        }
+        Ok(())
+    }
+
+    /// Handles one line from the server. Returns `false` once enough games are played.
+    pub fn handle_line(&mut self, line: &str) -> Result<bool, FicsError> {
+        match FicsEvent::parse(line) {
+            FicsEvent::LoginPrompt => {
+                let login = self.config.login.clone();
+                self.send(&login)?;
+            }
+            FicsEvent::PasswordPrompt => {
+                let password = self.config.password.clone().unwrap_or_default();
+                self.send(&password)?;
+            }
+            FicsEvent::GuestPrompt => self.send("")?,
+            FicsEvent::InvalidPassword => {
+                return Err(FicsError::Io(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "FICS rejected the password",
+                )))
+            }
+            FicsEvent::SessionStart(handle) => {
+                self.handle = Some(handle);
+                self.send("set style 12")?;
+                self.send("set bell 0")?;
+                self.send("set seek 0")?;
+                let name = BuildInfo::new(self.manager.searcher().params()).name();
+                self.send(&format!("set interface {}", name))?;
+                match self.config.partner.clone() {
+                    Some(partner) => self.send(&format!("partner {}", partner))?,
+                    None => self.seek()?,
+                }
+            }
+            FicsEvent::PartnerOffer(name) => {
+                if self.config.partner.as_deref() == Some(name.as_str()) {
+                    self.send(&format!("partner {}", name))?;
+                }
+            }
+            FicsEvent::Partnered(_) => self.seek()?,
+            FicsEvent::GameStart {
+                game,
+                players,
+                variant,
+            } => {
+                if players
+                    .iter()
+                    .any(|player| Some(player.as_str()) == self.handle())
+                {
+                    self.game = Some(game);
+                    self.bughouse = variant == "bughouse";
+                    self.sitting = false;
+                    self.pending = None;
+                    self.color = None;
+                    self.reservations = Reservations::new();
+                }
+            }
+            FicsEvent::GameEnd { game, .. } => {
+                if self.game == Some(game) {
+                    self.game = None;
+                    self.pending = None;
+                    self.holdings.clear();
+                    self.played += 1;
+                    if self.played >= self.config.games {
+                        return Ok(false);
+                    }
+                    self.seek()?;
+                }
+            }
+            FicsEvent::Holdings(holdings) => {
+                // A piece arriving from the partner settles their reservations
+                let before = self.holdings.get(&holdings.game);
+                if let (Some(color), Some(before)) = (self.color, before) {
+                    let arrived = POCKET_ROLES
+                        .iter()
+                        .copied()
+                        .find(|&role| holdings.count(color, role) > before.count(color, role));
+                    if self.game == Some(holdings.game) && arrived.is_some() {
+                        self.reservations.partner_moved(color, arrived);
+                    }
+                }
+                self.holdings.insert(holdings.game, holdings);
+            }
+            FicsEvent::Board(board) => {
+                if board.is_our_move() && self.game.is_none_or(|game| game == board.game) {
+                    self.game = Some(board.game);
+                    // Our color is known from our first move on
+                    if self.color.is_none() {
+                        self.manager.start(Some(board.turn), Bughouse::default());
+                    }
+                    self.color = Some(board.turn);
+                    self.pending = Some(board);
+                    self.think()?;
+                }
+            }
+            FicsEvent::PartnerTell(message) => match message.to_ascii_lowercase().as_str() {
+                "sit" => {
+                    self.sitting = true;
+                    self.send("ptell sitting")?;
+                }
+                "go" | "move" => {
+                    self.sitting = false;
+                    self.send("ptell going")?;
+                    self.think()?;
+                }
+                _ => {
+                    if let Ok(reservation) = message.parse::<ReservationMessage>() {
+                        self.reservations.receive(reservation);
+                    }
+                }
+            },
+            FicsEvent::Other => {}
+        }
+        Ok(true)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        send(&mut self.output, line)
+    }
+
+    fn seek(&mut self) -> io::Result<()> {
+        let variant = if self.config.partner.is_some() {
+            "bughouse"
+        } else {
+            "crazyhouse"
+        };
+        let seek = format!(
+            "seek {} {} {}",
+            self.config.initial, self.config.increment, variant
+        );
+        self.send(&seek)
+    }
+
+    // Plays a move on the pending board unless we are sitting
+    fn think(&mut self) -> Result<(), FicsError> {
+        if self.sitting {
+            return Ok(());
+        }
+        let board = match self.pending.take() {
+            Some(board) => board,
+            None => return Ok(()),
+        };
+        let key = (board.game, board.move_number, board.turn);
+        if self.moved == Some(key) {
+            return Ok(());
+        }
+        let mut position = parse_fen(&board.fen(self.holdings.get(&board.game)))?;
+        if self.bughouse {
+            position = position.with_preset(RulePreset::FicsBughouse);
+        }
+        let seconds = |clock: i64| Duration::from_secs(clock.max(0) as u64);
+        let clocks = ByColor {
+            white: seconds(board.white_clock),
+            black: seconds(board.black_clock),
+        };
+        let increment = Duration::from_secs(u64::from(self.config.increment));
+        self.manager.time = self.time.clone();
+        self.manager.shutdown = self.shutdown.clone();
+        self.manager.resign = self.config.resign.clone();
+        // Reserved pieces are searched as if in hand, but the move played must be
+        // playable now
+        let reserved: Vec<Reservation> =
+            self.reservations.active(position.turn()).copied().collect();
+        let engine = self.manager.searcher_mut();
+        engine.set_root_filter(RootFilter {
+            only: (!reserved.is_empty()).then(|| position.legal_moves().to_vec()),
+            exclude: Vec::new(),
+        });
+        engine.set_options(SearchOptions {
+            reservations: reserved,
+            ..SearchOptions::default()
+        });
+        let searched = self.reservations.tentative(&position);
+        self.manager.sync(position, Some(clocks), increment);
+        let mut adapter = FicsMoves {
+            output: &mut self.output,
+        };
+        // On shutdown nothing is played and the game is resigned instead
+        let decision = self.manager.think_on(&searched, &mut adapter)?;
+        if let Some(Decision::Play(Some(_)) | Decision::Resign) = decision {
+            self.moved = Some(key);
+        }
+        Ok(())
+    }
+
+    // The next line or prompt, with telnet commands and carriage returns dropped, or
+    // `None` once the server hangs up or shutdown is requested
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        loop {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None => {
+                    return Ok((!line.is_empty() && !self.shutdown.is_cancelled())
+                        .then(|| String::from_utf8_lossy(&line).into()))
+                }
+            };
+            match byte {
+                IAC => {
+                    if let Some(command) = self.read_byte()? {
+                        if (WILL..=DONT).contains(&command) {
+                            self.read_byte()?;
+                        }
+                    }
+                }
+                b'\r' => {}
+                b'\n' => return Ok(Some(String::from_utf8_lossy(&line).into())),
+                ch => {
+                    line.push(ch);
+                    // The command prompt comes before the server's next message
+                    if line == b"fics% " {
+                        line.clear();
+                    } else if is_prompt(&line) {
+                        return Ok(Some(String::from_utf8_lossy(&line).into()));
+                    }
+                }
+            }
+        }
+    }
+
+    // The next byte from the server, waiting out read timeouts, or `None` once it hangs
+    // up or shutdown is requested
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        loop {
+            if self.shutdown.is_cancelled() {
+                return Ok(None);
+            }
+            match self.input.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// Sends the engine's moves to the server
+struct FicsMoves<'a, W> {
+    output: &'a mut W,
+}
+
+impl<W: Write> GameAdapter for FicsMoves<'_, W> {
+    type Error = io::Error;
+
+    fn play(&mut self, position: &Bughouse, m: Option<&Move>) -> io::Result<()> {
+        match m {
+            Some(m) => send(self.output, &San::from_move(position, m).to_string()),
+            // FICS has no passing
+            None => Ok(()),
+        }
+    }
+
+    fn resign(&mut self) -> io::Result<()> {
+        send(self.output, "resign")
+    }
+}
+
+fn send<W: Write>(output: &mut W, line: &str) -> io::Result<()> {
+    writeln!(output, "{}", line)?;
+    output.flush()
+}
+
+fn is_prompt(line: &[u8]) -> bool {
+    line == b"login: "
+        || line == b"password: "
+        || (line.starts_with(b"Press return to enter the server as") && line.ends_with(b"\":"))
+}
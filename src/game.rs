@@ -0,0 +1,381 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use shakmaty::{ByColor, Color, Move, Outcome, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::cancel::CancelToken;
+use crate::engine::Engine;
+use crate::limits::{SearchControl, SearchLimits};
+use crate::opponent::OpponentModel;
+use crate::remote::RemoteEngine;
+use crate::resign::ResignPolicy;
+use crate::time::TimeManager;
+
+/// Where a game driven by [`GameManager`] stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    /// The opponent is to move
+    Waiting,
+    /// The opponent is to move and the engine thinks ahead on their time, see
+    /// [`GameManager::ponder`]
+    Pondering,
+    /// The engine is to move
+    Thinking,
+    /// `None` when the game ended without a result, e.g. with nothing left to play
+    GameOver(Option<Outcome>),
+}
+
+/// What a [`Searcher`] made of a position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Searched {
+    /// The move to play, `None` to pass or when there is nothing to play
+    pub best: Option<Move>,
+    /// Chance of the side to move winning, if the searcher knows it
+    pub win_probability: Option<f32>,
+}
+
+/// Anything that can pick a move: the local search, a remote engine, a test double.
+pub trait Searcher {
+    /// Searches `position` within `limits`, returning early once `cancel` is cancelled.
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        cancel: &CancelToken,
+    ) -> io::Result<Searched>;
+
+    /// Thinks about `position`, with the opponent to move, until `cancel` is cancelled.
+    /// Only pays off for searchers that keep what they found for the next search.
+    fn ponder(&mut self, position: &Bughouse, cancel: &CancelToken) -> io::Result<()> {
+        self.search(position, &SearchLimits::default(), cancel)
+            .map(drop)
+    }
+
+    /// Called when a new game starts, to forget what belongs to the last one.
+    fn new_game(&mut self) {}
+}
+
+impl<S: Searcher + ?Sized> Searcher for &mut S {
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        cancel: &CancelToken,
+    ) -> io::Result<Searched> {
+        (**self).search(position, limits, cancel)
+    }
+
+    fn ponder(&mut self, position: &Bughouse, cancel: &CancelToken) -> io::Result<()> {
+        (**self).ponder(position, cancel)
+    }
+
+    fn new_game(&mut self) {
+        (**self).new_game()
+    }
+}
+
+impl Searcher for Engine {
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        cancel: &CancelToken,
+    ) -> io::Result<Searched> {
+        let control = SearchControl::new(limits.clone(), cancel.clone());
+        let analysis = self.analyse_with(position, &control);
+        Ok(Searched {
+            best: analysis.best,
+            win_probability: Some(analysis.win_probability),
+        })
+    }
+
+    fn new_game(&mut self) {
+        self.clear_tree();
+    }
+}
+
+// Only time limits can be sent to the remote engine
+impl Searcher for RemoteEngine {
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        cancel: &CancelToken,
+    ) -> io::Result<Searched> {
+        let time = limits.time.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "remote searches need a time limit",
+            )
+        })?;
+        Ok(Searched {
+            best: RemoteEngine::search(self, position, time, cancel)?,
+            win_probability: None,
+        })
+    }
+
+    // The remote engine starts every search afresh
+    fn ponder(&mut self, _: &Bughouse, _: &CancelToken) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What the engine does on its move.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    /// Plays the move, `None` passing
+    Play(Option<Move>),
+    Resign,
+    /// Leaves the game without a result, when there is neither a move nor a pass
+    Abandon,
+}
+
+/// The other side of a game driven by [`GameManager`]: a server connection, a GUI
+/// protocol, or `()` when nobody else takes part, as in self-play.
+pub trait GameAdapter {
+    type Error: From<io::Error>;
+
+    /// Sends the engine's move in `position`, `None` passing.
+    fn play(&mut self, position: &Bughouse, m: Option<&Move>) -> Result<(), Self::Error>;
+
+    fn resign(&mut self) -> Result<(), Self::Error>;
+}
+
+impl GameAdapter for () {
+    type Error = io::Error;
+
+    fn play(&mut self, _: &Bughouse, _: Option<&Move>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn resign(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Drives one game at a time from start to end: keeps the position and clocks, searches
+/// when it is the engine's move and hands the move to a [`GameAdapter`], moving through
+/// the [`GameState`]s on the way. Front-ends share it instead of each running a game
+/// loop of their own.
+///
+/// Where a server owns the game, its position and clocks are taken over with
+/// [`GameManager::sync`] whenever it sends them, and win over what the manager worked
+/// out from its own moves. In bughouse that is also how pieces arriving from the
+/// partner's board get into the pockets.
+pub struct GameManager<S> {
+    searcher: S,
+    color: Option<Color>,
+    position: Bughouse,
+    clocks: Option<ByColor<Duration>>,
+    increment: Duration,
+    state: GameState,
+    pub time: TimeManager,
+    /// Limits every search in place of the clock. Without clocks or limits a search
+    /// runs until `shutdown` is cancelled
+    pub limits: Option<SearchLimits>,
+    /// Resigns games the searcher gives up on, `None` to always play on
+    pub resign: Option<ResignPolicy>,
+    /// Sets the pace of [`TimeManager::allot`]
+    pub opponent: Option<OpponentModel>,
+    /// Enter [`GameState::Pondering`] instead of [`GameState::Waiting`]
+    pub ponder: bool,
+    /// Stops the running search, which then plays nothing and leaves it to the caller
+    /// to leave the game
+    pub shutdown: CancelToken,
+}
+
+impl<S: Searcher> GameManager<S> {
+    /// A game from `start` with the engine playing `color`, or both sides for `None`.
+    pub fn new(searcher: S, color: Option<Color>, start: Bughouse) -> GameManager<S> {
+        let mut manager = GameManager {
+            searcher,
+            color,
+            position: start.clone(),
+            clocks: None,
+            increment: Duration::ZERO,
+            state: GameState::Waiting,
+            time: TimeManager::default(),
+            limits: None,
+            resign: None,
+            opponent: None,
+            ponder: false,
+            shutdown: CancelToken::new(),
+        };
+        manager.start(color, start);
+        manager
+    }
+
+    /// Starts the next game, see [`Searcher::new_game`].
+    pub fn start(&mut self, color: Option<Color>, start: Bughouse) {
+        self.searcher.new_game();
+        self.color = color;
+        self.position = start;
+        self.clocks = None;
+        self.increment = Duration::ZERO;
+        self.state = self.next_state();
+    }
+
+    /// Takes over the position and clocks as the server keeps them, `increment` being
+    /// what the engine gains per move.
+    pub fn sync(
+        &mut self,
+        position: Bughouse,
+        clocks: Option<ByColor<Duration>>,
+        increment: Duration,
+    ) {
+        self.position = position;
+        self.clocks = clocks;
+        self.increment = increment;
+        self.state = self.next_state();
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    /// The side the engine plays, `None` for both.
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    pub fn position(&self) -> &Bughouse {
+        &self.position
+    }
+
+    pub fn clocks(&self) -> Option<&ByColor<Duration>> {
+        self.clocks.as_ref()
+    }
+
+    pub fn searcher(&self) -> &S {
+        &self.searcher
+    }
+
+    /// For setting up the searcher before [`GameManager::think`].
+    pub fn searcher_mut(&mut self) -> &mut S {
+        &mut self.searcher
+    }
+
+    fn next_state(&self) -> GameState {
+        if self.position.is_game_over() {
+            GameState::GameOver(self.position.outcome())
+        } else if self.color.is_none_or(|color| color == self.position.turn()) {
+            GameState::Thinking
+        } else if self.ponder {
+            GameState::Pondering
+        } else {
+            GameState::Waiting
+        }
+    }
+
+    /// Searches and acts on the engine's move, see [`GameManager::think_on`].
+    pub fn think<A: GameAdapter>(&mut self, adapter: &mut A) -> Result<Option<Decision>, A::Error> {
+        let position = self.position.clone();
+        self.think_on(&position, adapter)
+    }
+
+    /// Searches `searched` in place of the game position, say with pieces the partner
+    /// promised already in hand, and sends what the engine decides through `adapter`.
+    /// The move must be playable in the game position. Returns `None` without doing
+    /// anything unless it is the engine's move, or after a shutdown.
+    pub fn think_on<A: GameAdapter>(
+        &mut self,
+        searched: &Bughouse,
+        adapter: &mut A,
+    ) -> Result<Option<Decision>, A::Error> {
+        let started = Instant::now();
+        let found = match self.search_on(searched)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let decision = self.decide(&found);
+        self.act(decision.clone(), started.elapsed(), adapter)?;
+        Ok(Some(decision))
+    }
+
+    /// Searches `searched` for the engine's move, within the limits or else the time
+    /// the clock allows, without acting on it. Returns `None` unless it is the engine's
+    /// move, or after a shutdown.
+    pub fn search_on(&mut self, searched: &Bughouse) -> io::Result<Option<Searched>> {
+        if self.state != GameState::Thinking {
+            return Ok(None);
+        }
+        let limits = match (&self.limits, &self.clocks) {
+            (Some(limits), _) => limits.clone(),
+            (None, Some(clocks)) => SearchLimits::time(self.time.allot(
+                *clocks.by_color(self.position.turn()),
+                self.increment,
+                self.opponent.as_ref(),
+            )),
+            (None, None) => SearchLimits::default(),
+        };
+        let found = self.searcher.search(searched, &limits, &self.shutdown)?;
+        if self.shutdown.is_cancelled() {
+            return Ok(None);
+        }
+        Ok(Some(found))
+    }
+
+    /// What to make of `found`: resigning as the [`ResignPolicy`] says, playing the best
+    /// move, passing when the searcher found passing best, and abandoning the game when
+    /// there is neither a move nor a pass.
+    pub fn decide(&self, found: &Searched) -> Decision {
+        let gives_up = match (&self.resign, found.win_probability) {
+            (Some(policy), Some(win_probability)) => policy.should_resign(win_probability),
+            _ => false,
+        };
+        match &found.best {
+            _ if gives_up => Decision::Resign,
+            Some(m) => Decision::Play(Some(m.clone())),
+            None if self.position.can_pass() => Decision::Play(None),
+            None => Decision::Abandon,
+        }
+    }
+
+    /// Carries out `decision` for the side to move, which took `used` of its clock, and
+    /// sends it through `adapter`. Does nothing unless it is the engine's move.
+    pub fn act<A: GameAdapter>(
+        &mut self,
+        decision: Decision,
+        used: Duration,
+        adapter: &mut A,
+    ) -> Result<(), A::Error> {
+        if self.state != GameState::Thinking {
+            return Ok(());
+        }
+        let mover = self.position.turn();
+        match decision {
+            Decision::Play(m) => {
+                adapter.play(&self.position, m.as_ref())?;
+                // Until the server says otherwise
+                if let Some(clocks) = &mut self.clocks {
+                    let clock = clocks.by_color_mut(mover);
+                    *clock = clock.saturating_sub(used) + self.increment;
+                }
+                match m {
+                    Some(m) => self.position.play_unchecked(&m),
+                    None => {
+                        self.position.pass();
+                    }
+                }
+                self.state = self.next_state();
+            }
+            Decision::Resign => {
+                adapter.resign()?;
+                self.state = GameState::GameOver(Some(Outcome::Decisive { winner: !mover }));
+            }
+            Decision::Abandon => self.state = GameState::GameOver(None),
+        }
+        Ok(())
+    }
+
+    /// Thinks on the opponent's time while they are to move, until `stop` is cancelled,
+    /// say once their move arrives, see [`Searcher::ponder`]. Returns right away in any
+    /// other state.
+    pub fn ponder(&mut self, stop: &CancelToken) -> io::Result<()> {
+        if self.state != GameState::Pondering {
+            return Ok(());
+        }
+        self.searcher.ponder(&self.position, stop)
+    }
+}
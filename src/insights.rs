@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position, Role, Setup, Square};
+
+use crate::board::{BoardId, Bughouse};
+use crate::bpgn::BpgnGame;
+use crate::engine::Engine;
+use crate::eval::EvalParams;
+use crate::limits::SearchLimits;
+use crate::output::{json_array, JsonObject};
+use crate::premove::capture_loss;
+use crate::seats::{Seat, SEATS};
+use crate::traps::TrapBook;
+
+/// One of the user's games, as read from their archive.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub start: Bughouse,
+    pub moves: Vec<Move>,
+    /// The mover's remaining clock after each move, where known
+    pub clocks: Vec<Option<Duration>>,
+    /// The side the user played
+    pub user: Color,
+    pub outcome: Option<Outcome>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+pub const GAME_PHASES: [GamePhase; 3] = [
+    GamePhase::Opening,
+    GamePhase::Middlegame,
+    GamePhase::Endgame,
+];
+
+impl GamePhase {
+    /// The phase of `position`, reached after `ply` half moves. Crazyhouse material never
+    /// leaves the game, so the phase goes by move number and by how much has been
+    /// traded off the board into the pockets.
+    pub fn of(position: &Bughouse, ply: usize) -> GamePhase {
+        if ply < 20 {
+            GamePhase::Opening
+        } else if position.board().occupied().count() > 16 {
+            GamePhase::Middlegame
+        } else {
+            GamePhase::Endgame
+        }
+    }
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GamePhase::Opening => "opening",
+            GamePhase::Middlegame => "middlegame",
+            GamePhase::Endgame => "endgame",
+        })
+    }
+}
+
+/// Decides whether a move of the user was a blunder. Full analysis plugs in a search
+/// with its budget; [`StaticAnalyzer`] needs no search at all.
+pub trait Analyzer {
+    fn is_blunder(&mut self, position: &Bughouse, m: &Move) -> bool;
+}
+
+/// Flags moves after which the opponent mates at once or wins at least `threshold`
+/// pawns of material with a capture.
+pub struct StaticAnalyzer {
+    pub params: EvalParams,
+    pub threshold: f32,
+}
+
+impl Default for StaticAnalyzer {
+    fn default() -> Self {
+        StaticAnalyzer {
+            params: EvalParams::default(),
+            threshold: 2.0,
+        }
+    }
+}
+
+impl Analyzer for StaticAnalyzer {
+    fn is_blunder(&mut self, position: &Bughouse, m: &Move) -> bool {
+        let after = match position.clone().play(m) {
+            Ok(after) => after,
+            Err(_) => return false,
+        };
+        if !after.mating_moves().is_empty() {
+            return true;
+        }
+        after
+            .legal_moves()
+            .iter()
+            .filter(|reply| reply.is_capture())
+            .any(|reply| capture_loss(&after, reply, &self.params) >= self.threshold)
+    }
+}
+
+/// Flags moves that leave the opponent at least `threshold` more likely to win than
+/// the engine's own choice would, searching `nodes` playouts for each.
+pub struct SearchAnalyzer {
+    pub engine: Engine,
+    pub nodes: u64,
+    pub threshold: f32,
+}
+
+impl SearchAnalyzer {
+    pub fn new(params: Arc<EvalParams>, nodes: u64) -> SearchAnalyzer {
+        SearchAnalyzer {
+            engine: Engine::new(params),
+            nodes,
+            threshold: 0.2,
+        }
+    }
+
+    // The opponent's chance of winning after `m`. Both moves of a comparison are judged
+    // from the opponent's side, since a search favors the side it searches for
+    fn reply_chance(&mut self, position: &Bughouse, m: &Move) -> f32 {
+        let after = match position.clone().play(m) {
+            Ok(after) => after,
+            Err(_) => return 0f32,
+        };
+        match after.outcome() {
+            Some(Outcome::Decisive { winner }) => f32::from(u8::from(winner == after.turn())),
+            Some(Outcome::Draw) => 0.5,
+            None => {
+                self.engine
+                    .analyse(&after, SearchLimits::nodes(self.nodes))
+                    .win_probability
+            }
+        }
+    }
+}
+
+impl Analyzer for SearchAnalyzer {
+    fn is_blunder(&mut self, position: &Bughouse, m: &Move) -> bool {
+        let best = match self
+            .engine
+            .analyse(position, SearchLimits::nodes(self.nodes))
+            .best
+        {
+            Some(best) if best != *m => best,
+            _ => return false,
+        };
+        self.reply_chance(position, m) - self.reply_chance(position, &best) >= self.threshold
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Score {
+    games: u32,
+    points: f32,
+}
+
+/// Aggregate statistics over a user's games, for a personal improvement report.
+#[derive(Clone, Debug, Default)]
+pub struct Insights {
+    pub games: u32,
+    // Moves and blunders by phase
+    moves: HashMap<GamePhase, (u32, u32)>,
+    /// Lost games in which the user had less than `TIME_TROUBLE` left at the end
+    pub time_trouble_losses: u32,
+    pub losses: u32,
+    drops: HashMap<(Role, Square), u32>,
+    // Keyed by the first two moves in UCI notation
+    openings: HashMap<String, Score>,
+    // Keyed by trap name
+    traps: HashMap<String, u32>,
+}
+
+const TIME_TROUBLE: Duration = Duration::from_secs(10);
+
+impl Insights {
+    pub fn new() -> Insights {
+        Insights::default()
+    }
+
+    pub fn add_game<A: Analyzer>(&mut self, game: &GameRecord, analyzer: &mut A) {
+        self.games += 1;
+        let mut position = game.start.clone();
+        let mut last_clock = None;
+        for (ply, m) in game.moves.iter().enumerate() {
+            if position.turn() == game.user {
+                self.add_move(&position, ply, m, analyzer);
+                if let Some(Some(clock)) = game.clocks.get(ply) {
+                    last_clock = Some(*clock);
+                }
+            }
+            if !position.is_legal(m) {
+                break;
+            }
+            position.play_unchecked(m);
+        }
+        self.add_result(game.user, game.outcome, last_clock, &game.moves);
+    }
+
+    /// Adds the board `user` played on in a BPGN game, with the pockets the partners
+    /// filled. Returns `false`, adding nothing, if `user` is none of the four players.
+    pub fn add_bpgn<A: Analyzer>(&mut self, game: &BpgnGame, user: &str, analyzer: &mut A) -> bool {
+        let seat = match seat_of(game, user) {
+            Some(seat) => seat,
+            None => return false,
+        };
+        self.games += 1;
+        let mut moves = Vec::new();
+        let mut last_clock = None;
+        game.replay(|boards, bpgn_move| {
+            if bpgn_move.board != seat.board {
+                return;
+            }
+            let position = boards.board(seat.board);
+            if position.turn() == seat.color {
+                self.add_move(position, moves.len(), &bpgn_move.m, analyzer);
+                last_clock = bpgn_move.clock.or(last_clock);
+            }
+            moves.push(bpgn_move.m.clone());
+        });
+        // The result is that of white on board A and black on board B
+        let outcome = game.result.map(|outcome| match outcome {
+            Outcome::Decisive { winner } if seat.board == BoardId::B => {
+                Outcome::Decisive { winner: !winner }
+            }
+            outcome => outcome,
+        });
+        self.add_result(seat.color, outcome, last_clock, &moves);
+        true
+    }
+
+    // Counts a move of the user, played in `position` after `ply` half moves
+    fn add_move<A: Analyzer>(
+        &mut self,
+        position: &Bughouse,
+        ply: usize,
+        m: &Move,
+        analyzer: &mut A,
+    ) {
+        let phase = GamePhase::of(position, ply);
+        let blunder = analyzer.is_blunder(position, m);
+        let counts = self.moves.entry(phase).or_default();
+        counts.0 += 1;
+        counts.1 += u32::from(blunder);
+        if let Move::Put { role, to } = *m {
+            *self.drops.entry((role, to)).or_default() += 1;
+        }
+    }
+
+    // Scores a finished game of the user, who had `last_clock` left after their last
+    // move, by the opening of its first two `moves`
+    fn add_result(
+        &mut self,
+        user: Color,
+        outcome: Option<Outcome>,
+        last_clock: Option<Duration>,
+        moves: &[Move],
+    ) {
+        let points = match outcome {
+            Some(Outcome::Decisive { winner }) if winner == user => 1f32,
+            Some(Outcome::Decisive { .. }) => 0f32,
+            Some(Outcome::Draw) => 0.5,
+            None => return,
+        };
+        if points == 0f32 {
+            self.losses += 1;
+            if last_clock.is_some_and(|clock| clock < TIME_TROUBLE) {
+                self.time_trouble_losses += 1;
+            }
+        }
+        let opening: Vec<String> = moves
+            .iter()
+            .take(2)
+            .map(|m| Uci::from_standard(m).to_string())
+            .collect();
+        let score = self.openings.entry(opening.join(" ")).or_default();
+        score.games += 1;
+        score.points += points;
+    }
+
+    /// Counts the known opening traps the user fell for in `game`.
+    pub fn add_traps(&mut self, game: &GameRecord, book: &TrapBook) {
+        let mut position = game.start.clone();
+        for m in &game.moves {
+            if !position.is_legal(m) {
+                break;
+            }
+            if position.turn() == game.user {
+                if let Some(trap) = book.fallen_for(&position, m) {
+                    *self.traps.entry(trap.name.clone()).or_default() += 1;
+                }
+            }
+            position.play_unchecked(m);
+        }
+    }
+
+    /// Counts the known opening traps `user` fell for on their board of a BPGN game.
+    /// Returns `false`, adding nothing, if `user` is none of the four players.
+    pub fn add_bpgn_traps(&mut self, game: &BpgnGame, user: &str, book: &TrapBook) -> bool {
+        let seat = match seat_of(game, user) {
+            Some(seat) => seat,
+            None => return false,
+        };
+        game.replay(|boards, bpgn_move| {
+            let position = boards.board(seat.board);
+            if bpgn_move.board == seat.board && position.turn() == seat.color {
+                if let Some(trap) = book.fallen_for(position, &bpgn_move.m) {
+                    *self.traps.entry(trap.name.clone()).or_default() += 1;
+                }
+            }
+        });
+        true
+    }
+
+    /// Traps the user fell for with how often, most frequent first.
+    pub fn traps(&self) -> Vec<(&str, u32)> {
+        let mut traps: Vec<_> = self
+            .traps
+            .iter()
+            .map(|(name, &count)| (name.as_str(), count))
+            .collect();
+        traps.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        traps
+    }
+
+    /// Share of the user's moves in `phase` that were blunders.
+    pub fn blunder_rate(&self, phase: GamePhase) -> Option<f32> {
+        match self.moves.get(&phase) {
+            Some(&(moves, blunders)) if moves > 0 => Some(blunders as f32 / moves as f32),
+            _ => None,
+        }
+    }
+
+    /// The most played drops, most frequent first.
+    pub fn favorite_drops(&self, count: usize) -> Vec<((Role, Square), u32)> {
+        let mut drops: Vec<_> = self.drops.iter().map(|(&k, &v)| (k, v)).collect();
+        drops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        drops.truncate(count);
+        drops
+    }
+
+    /// Games and score per opening, most played first.
+    pub fn openings(&self) -> Vec<(&str, u32, f32)> {
+        let mut openings: Vec<_> = self
+            .openings
+            .iter()
+            .map(|(name, score)| {
+                (
+                    name.as_str(),
+                    score.games,
+                    score.points / score.games as f32,
+                )
+            })
+            .collect();
+        openings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        openings
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let blunder_rates = GAME_PHASES
+            .iter()
+            .fold(JsonObject::new(), |object, &phase| {
+                object.number(
+                    &phase.to_string(),
+                    self.blunder_rate(phase).unwrap_or(f32::NAN),
+                )
+            });
+        let drops = self
+            .favorite_drops(5)
+            .into_iter()
+            .map(|((role, square), count)| {
+                JsonObject::new()
+                    .string("drop", &format!("{}@{}", role.upper_char(), square))
+                    .number("count", count)
+                    .to_string()
+            });
+        let openings = self.openings().into_iter().map(|(opening, games, score)| {
+            JsonObject::new()
+                .string("moves", opening)
+                .number("games", games)
+                .number("score", score)
+                .to_string()
+        });
+        let traps = self.traps().into_iter().map(|(name, count)| {
+            JsonObject::new()
+                .string("trap", name)
+                .number("count", count)
+                .to_string()
+        });
+        JsonObject::document("insights")
+            .number("games", self.games)
+            .raw("blunder_rates", blunder_rates.to_string())
+            .number("losses", self.losses)
+            .number("time_trouble_losses", self.time_trouble_losses)
+            .raw("favorite_drops", json_array(drops))
+            .raw("openings", json_array(openings))
+            .raw("traps", json_array(traps))
+    }
+}
+
+// Where `user` sat in `game`
+fn seat_of(game: &BpgnGame, user: &str) -> Option<Seat> {
+    let seating = game.seating();
+    SEATS
+        .iter()
+        .copied()
+        .find(|&seat| seating.at(seat) == Some(user))
+}
+
+impl fmt::Display for Insights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "games: {}", self.games)?;
+        for phase in GAME_PHASES {
+            if let Some(rate) = self.blunder_rate(phase) {
+                writeln!(f, "blunder rate in the {}: {:.1}%", phase, rate * 100f32)?;
+            }
+        }
+        writeln!(
+            f,
+            "losses in time trouble: {} of {}",
+            self.time_trouble_losses, self.losses
+        )?;
+        for ((role, square), count) in self.favorite_drops(5) {
+            writeln!(f, "drop {}@{}: {}", role.upper_char(), square, count)?;
+        }
+        for (opening, games, score) in self.openings() {
+            writeln!(
+                f,
+                "opening {}: {} games, {:.0}%",
+                opening,
+                games,
+                score * 100f32
+            )?;
+        }
+        for (trap, count) in self.traps() {
+            writeln!(f, "fell for the {} trap {} times", trap, count)?;
+        }
+        Ok(())
+    }
+}
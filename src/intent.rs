@@ -0,0 +1,234 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::{Color, Position, Role, Setup};
+
+use crate::board::{Bughouse, BughouseGame};
+use crate::drops::Pockets;
+use crate::engine::RootFilter;
+use crate::seats::Seat;
+use crate::sitting::mate_after_arrival;
+
+// Roles that can reach a pocket, cheapest first, since those are the easiest for the
+// partner to win
+const DROPPABLE: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+
+// Danger of the partner's king assumed while they report a mate threat, the top of the
+// scale of `crate::danger::danger_score`
+const THREATENED_DANGER: f32 = 1.0;
+
+/// A request or warning between bughouse partners, the structured form of the usual
+/// ptells. The text form is what goes over the wire: `need n`, `no q`, `mate`, `sit`
+/// and `go`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartnerIntent {
+    /// A piece of this role would give us a mating drop, so capture one if you can
+    Need(Role),
+    /// A piece of this role would give our opponent a mating drop, so don't let one
+    /// of yours be taken
+    Avoid(Role),
+    /// Our opponent can mate us with their next move
+    MateThreat,
+    /// Stop moving until told to go, so no more material reaches our opponent
+    Sit,
+    /// Move again after a sit
+    Go,
+}
+
+impl fmt::Display for PartnerIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartnerIntent::Need(role) => write!(f, "need {}", role.char()),
+            PartnerIntent::Avoid(role) => write!(f, "no {}", role.char()),
+            PartnerIntent::MateThreat => f.write_str("mate"),
+            PartnerIntent::Sit => f.write_str("sit"),
+            PartnerIntent::Go => f.write_str("go"),
+        }
+    }
+}
+
+impl FromStr for PartnerIntent {
+    type Err = String;
+
+    /// The text form, and the phrasings players commonly use instead: a bare piece like
+    /// `n` or `give me a knight` for a need, `don't trade queens` for an avoid.
+    fn from_str(s: &str) -> Result<PartnerIntent, String> {
+        let text = s.trim().to_ascii_lowercase();
+        let role = |name: &str| parse_role(name).ok_or_else(|| format!("unknown piece: {}", s));
+        match text.as_str() {
+            "mate" | "mate threat" | "help" => return Ok(PartnerIntent::MateThreat),
+            "sit" | "wait" => return Ok(PartnerIntent::Sit),
+            "go" | "move" => return Ok(PartnerIntent::Go),
+            _ => {}
+        }
+        if let Some(role) = parse_role(&text) {
+            return Ok(PartnerIntent::Need(role));
+        }
+        for prefix in ["need ", "give me "].iter() {
+            if let Some(name) = text.strip_prefix(prefix) {
+                return role(name).map(PartnerIntent::Need);
+            }
+        }
+        for prefix in [
+            "no ",
+            "don't trade ",
+            "dont trade ",
+            "don't give ",
+            "dont give ",
+        ]
+        .iter()
+        {
+            if let Some(name) = text.strip_prefix(prefix) {
+                return role(name).map(PartnerIntent::Avoid);
+            }
+        }
+        Err(format!("unknown partner message: {}", s))
+    }
+}
+
+// A piece by letter or name, singular or plural, with or without an article
+fn parse_role(text: &str) -> Option<Role> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("a ")
+        .or_else(|| text.strip_prefix("an "))
+        .unwrap_or(text);
+    let text = text
+        .strip_suffix('s')
+        .filter(|t| t.len() > 1)
+        .unwrap_or(text);
+    match text {
+        "p" | "pawn" => Some(Role::Pawn),
+        "n" | "knight" => Some(Role::Knight),
+        "b" | "bishop" => Some(Role::Bishop),
+        "r" | "rook" => Some(Role::Rook),
+        "q" | "queen" => Some(Role::Queen),
+        _ => None,
+    }
+}
+
+/// What `seat` should tell its partner about its own board. On our move, the
+/// cheapest piece that would give a mating drop is asked for. When the opponent can
+/// mate already that is reported; otherwise every piece that would give the opponent
+/// a mating drop is to be avoided, and the partner is asked to sit while there is any.
+pub fn intents(game: &BughouseGame, seat: Seat) -> Vec<PartnerIntent> {
+    let position = game.board(seat.board);
+    let mut intents = Vec::new();
+    if position.is_game_over() {
+        return intents;
+    }
+    if position.turn() == seat.color {
+        let need = DROPPABLE
+            .iter()
+            .copied()
+            .find(|&role| mate_after_arrival(position, role).is_some());
+        intents.extend(need.map(PartnerIntent::Need));
+    }
+    // Threats are what the opponent could do if it were their move
+    let theirs = if position.turn() == seat.color {
+        match position.swapped_turn() {
+            Some(swapped) => swapped,
+            None => return intents,
+        }
+    } else {
+        position.clone()
+    };
+    if !theirs.mating_moves().is_empty() {
+        intents.push(PartnerIntent::MateThreat);
+        return intents;
+    }
+    let avoid: Vec<PartnerIntent> = DROPPABLE
+        .iter()
+        .copied()
+        .filter(|&role| mate_after_arrival(&theirs, role).is_some())
+        .map(PartnerIntent::Avoid)
+        .collect();
+    if !avoid.is_empty() {
+        intents.extend(avoid);
+        intents.push(PartnerIntent::Sit);
+    }
+    intents
+}
+
+/// How the partner's requests bear on our next search.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Constraints {
+    /// Root moves to search, for [`crate::engine::Engine::set_root_filter`]
+    pub filter: RootFilter,
+    /// For [`crate::engine::SearchOptions::partner_danger`]
+    pub partner_danger: Option<f32>,
+    /// Hold our move until the partner says go
+    pub sit: bool,
+}
+
+/// Turns the partner's `requests`, oldest first, into constraints on our search of
+/// `position`. A needed piece restricts the search to captures of it, if there are
+/// any. A piece to avoid rules out the moves that let the opponent capture one of ours,
+/// unless every move does. A mate threat makes trades unattractive, and the last of
+/// `sit` and `go` decides whether to move at all. Constraints that would leave no move
+/// to search are dropped.
+pub fn constraints(position: &Bughouse, requests: &[PartnerIntent]) -> Constraints {
+    let legal = position.legal_moves();
+    let us = position.turn();
+    let mut constraints = Constraints::default();
+    let mut needed = Vec::new();
+    for request in requests {
+        match *request {
+            PartnerIntent::Need(role) => {
+                for m in &legal {
+                    if Pockets::captured_role(position.board(), m) == Some(role)
+                        && !needed.contains(m)
+                    {
+                        needed.push(m.clone());
+                    }
+                }
+            }
+            PartnerIntent::Avoid(role) => {
+                let exposing: Vec<_> = legal
+                    .iter()
+                    .filter(|m| {
+                        let mut after = position.clone();
+                        after.play_unchecked(m);
+                        exposes(&after, us, role)
+                    })
+                    .cloned()
+                    .collect();
+                if exposing.len() < legal.len() {
+                    for m in exposing {
+                        if !constraints.filter.exclude.contains(&m) {
+                            constraints.filter.exclude.push(m);
+                        }
+                    }
+                }
+            }
+            PartnerIntent::MateThreat => constraints.partner_danger = Some(THREATENED_DANGER),
+            PartnerIntent::Sit => constraints.sit = true,
+            PartnerIntent::Go => constraints.sit = false,
+        }
+    }
+    if !needed.is_empty() {
+        constraints.filter.only = Some(needed);
+    }
+    if !legal.iter().any(|m| constraints.filter.allows(m)) {
+        constraints.filter.only = None;
+        if !legal.iter().any(|m| constraints.filter.allows(m)) {
+            constraints.filter = RootFilter::default();
+        }
+    }
+    constraints
+}
+
+// Whether the side to move in `position` can capture a piece of `role` of `color`
+fn exposes(position: &Bughouse, color: Color, role: Role) -> bool {
+    position.turn() != color
+        && position
+            .legal_moves()
+            .iter()
+            .any(|m| Pockets::captured_role(position.board(), m) == Some(role))
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+
+// Deeper documents are rejected rather than parsed on an ever deeper stack
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON document, for the few places that read JSON, like the Lichess event
+/// streams. Writing goes through [`crate::output::JsonObject`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Members in document order
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// A document that isn't valid JSON, as it was given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError(pub String);
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid json: {}", self.0)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl JsonValue {
+    pub fn parse(text: &str) -> Result<JsonValue, JsonError> {
+        let mut parser = Parser {
+            text,
+            rest: text.as_bytes(),
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if !parser.rest.is_empty() {
+            return Err(parser.invalid());
+        }
+        Ok(value)
+    }
+
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Follows `keys` through nested objects.
+    pub fn path(&self, keys: &[&str]) -> Option<&JsonValue> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            JsonValue::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            JsonValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    rest: &'a [u8],
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn invalid(&self) -> JsonError {
+        JsonError(self.text.to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((b' ' | b'\t' | b'\n' | b'\r', rest)) = self.rest.split_first() {
+            self.rest = rest;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        match self.rest.split_first() {
+            Some((&first, rest)) if first == byte => {
+                self.rest = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        match self.rest.strip_prefix(word.as_bytes()) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(value)
+            }
+            None => Err(self.invalid()),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, JsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.invalid());
+        }
+        self.depth += 1;
+        let value = self.nested();
+        self.depth -= 1;
+        value
+    }
+
+    fn nested(&mut self) -> Result<JsonValue, JsonError> {
+        self.skip_whitespace();
+        match self.rest.first() {
+            Some(b'n') => self.keyword("null", JsonValue::Null),
+            Some(b't') => self.keyword("true", JsonValue::Bool(true)),
+            Some(b'f') => self.keyword("false", JsonValue::Bool(false)),
+            Some(b'"') => self.string().map(JsonValue::String),
+            Some(b'[') => {
+                self.rest = &self.rest[1..];
+                let mut values = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        values.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.invalid());
+                        }
+                    }
+                }
+                Ok(JsonValue::Array(values))
+            }
+            Some(b'{') => {
+                self.rest = &self.rest[1..];
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return Err(self.invalid());
+                        }
+                        members.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.invalid());
+                        }
+                    }
+                }
+                Ok(JsonValue::Object(members))
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, JsonError> {
+        let end = self
+            .rest
+            .iter()
+            .position(|byte| !matches!(byte, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+            .unwrap_or(self.rest.len());
+        let number = std::str::from_utf8(&self.rest[..end])
+            .ok()
+            .and_then(|number| number.parse::<f64>().ok())
+            .ok_or_else(|| self.invalid())?;
+        self.rest = &self.rest[end..];
+        Ok(JsonValue::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        if !self.eat(b'"') {
+            return Err(self.invalid());
+        }
+        let mut bytes = Vec::new();
+        loop {
+            let (&byte, rest) = self.rest.split_first().ok_or_else(|| self.invalid())?;
+            self.rest = rest;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let (&escape, rest) = self.rest.split_first().ok_or_else(|| self.invalid())?;
+                    self.rest = rest;
+                    let ch = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.invalid()),
+                    };
+                    bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.invalid())
+    }
+
+    // The code point after `\u`, combining surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.rest.starts_with(b"\\u") {
+                return Err(self.invalid());
+            }
+            self.rest = &self.rest[2..];
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xd800) << 10) + low.wrapping_sub(0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.invalid())
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.rest.get(..4).ok_or_else(|| self.invalid())?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.invalid())?;
+        self.rest = &self.rest[4..];
+        Ok(code)
+    }
+}
@@ -0,0 +1,101 @@
+//! A bughouse and crazyhouse engine.
+//!
+//! The stable core, which only changes in a compatible way within a major version, is
+//! re-exported at the crate root:
+//!
+//! - [`Bughouse`], the position of one board with its pockets, with move generation
+//!   through [`shakmaty::Position`]
+//! - [`parse_fen`] and [`Bughouse::fen`] for FENs with pockets, and [`parse_move_list`]
+//!   for moves in SAN or UCI notation
+//! - [`Engine`], searching positions within [`SearchLimits`] and reporting an
+//!   [`Analysis`], with the evaluation weights of [`EvalParams`]
+//!
+//! Experimental APIs, the neural network evaluator and the match mode library, are
+//! only available with the `unstable` feature and may change in any release. The `nn`
+//! feature turns it on. The `match` command of the binary is built either way.
+//! Everything else is public for the tools built on the crate, but not yet promised to
+//! stay as it is.
+
+pub mod access;
+#[cfg(feature = "unstable")]
+pub mod arena;
+// The `match` command of the binary plays through the arena whatever the features, so
+// without `unstable` it is built but left out of the documented API
+#[cfg(not(feature = "unstable"))]
+#[doc(hidden)]
+pub mod arena;
+pub mod artifacts;
+#[cfg(feature = "unstable")]
+pub mod batch;
+pub mod board;
+pub mod book;
+pub mod bookmarks;
+pub mod bpgn;
+pub mod branching;
+pub mod build_info;
+pub mod calibration;
+pub mod cancel;
+pub mod cluster;
+pub mod compat;
+pub mod config;
+pub mod danger;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod difftest;
+pub mod display;
+pub mod drill;
+pub mod drop_stats;
+pub mod drops;
+pub mod engine;
+pub mod eval;
+pub mod eval_bar;
+pub mod explain;
+pub mod fics;
+pub mod game;
+pub mod insights;
+pub mod intent;
+pub mod json;
+pub mod lichess;
+pub mod limits;
+pub mod match_log;
+#[cfg(feature = "nn")]
+pub mod nn;
+pub mod opponent;
+pub mod output;
+pub mod partner;
+pub mod paths;
+pub mod premove;
+pub mod prior;
+pub mod protocol;
+pub mod remote;
+pub mod reservation;
+pub mod resign;
+pub mod rollout;
+pub mod script;
+pub mod seats;
+pub mod selfcheck;
+pub mod selfplay;
+pub mod session;
+pub mod shutdown;
+pub mod signature;
+pub mod sitting;
+pub mod sparring;
+pub mod svg;
+pub mod team;
+pub mod time;
+pub mod trace;
+pub mod trades;
+pub mod training;
+pub mod traps;
+pub mod two_board;
+pub mod uci;
+pub mod validate;
+pub mod variant;
+pub mod xboard;
+
+pub use board::{parse_fen, Bughouse};
+pub use engine::{parse_move_list, Analysis, Engine};
+pub use eval::EvalParams;
+pub use limits::SearchLimits;
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, CastlingMode, Color, Move, Position, Setup};
+
+use crate::board::{Bughouse, FenError, IllegalMove, RulePreset};
+use crate::build_info::BuildInfo;
+use crate::cancel::CancelToken;
+use crate::engine::Engine;
+use crate::eval::EvalParams;
+use crate::game::{Decision, GameAdapter, GameManager, GameState};
+use crate::json::{JsonError, JsonValue};
+use crate::opponent::OpponentModel;
+use crate::protocol::command_lines;
+use crate::resign::ResignPolicy;
+use crate::time::TimeManager;
+
+pub const LICHESS_URL: &str = "https://lichess.org";
+
+// How often the event loop checks for shutdown while the stream is quiet
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum LichessError {
+    /// A request that failed, or a stream that broke off
+    Io(io::Error),
+    /// A reply that is not the JSON expected
+    Json(JsonError),
+    /// A game that starts from a position we can't read
+    Fen(FenError),
+}
+
+impl fmt::Display for LichessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LichessError::Io(err) => write!(f, "lichess request failed: {}", err),
+            LichessError::Json(err) => write!(f, "{}", err),
+            LichessError::Fen(err) => write!(f, "invalid initial fen: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LichessError {}
+
+impl From<io::Error> for LichessError {
+    fn from(err: io::Error) -> Self {
+        LichessError::Io(err)
+    }
+}
+
+impl From<FenError> for LichessError {
+    fn from(err: FenError) -> Self {
+        LichessError::Fen(err)
+    }
+}
+
+impl From<JsonError> for LichessError {
+    fn from(err: JsonError) -> Self {
+        LichessError::Json(err)
+    }
+}
+
+/// Which challenges the bot accepts. Only crazyhouse is ever accepted, and only games
+/// with a clock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengeFilter {
+    pub min_initial: Duration,
+    pub max_initial: Duration,
+    pub max_increment: Duration,
+    pub rated: bool,
+    pub casual: bool,
+}
+
+impl Default for ChallengeFilter {
+    fn default() -> Self {
+        ChallengeFilter {
+            min_initial: Duration::from_secs(60),
+            max_initial: Duration::from_secs(30 * 60),
+            max_increment: Duration::from_secs(30),
+            rated: true,
+            casual: true,
+        }
+    }
+}
+
+impl ChallengeFilter {
+    /// The reason to decline `challenge` with, as Lichess names them, or `None` to accept
+    /// it.
+    pub fn decline_reason(&self, challenge: &Challenge) -> Option<&'static str> {
+        if challenge.variant != "crazyhouse" {
+            return Some("variant");
+        }
+        let (initial, increment) = match (challenge.initial, challenge.increment) {
+            (Some(initial), Some(increment)) => (initial, increment),
+            _ => return Some("timeControl"),
+        };
+        if initial < self.min_initial {
+            Some("tooFast")
+        } else if initial > self.max_initial || increment > self.max_increment {
+            Some("tooSlow")
+        } else if challenge.rated && !self.rated {
+            Some("casual")
+        } else if !challenge.rated && !self.casual {
+            Some("rated")
+        } else {
+            None
+        }
+    }
+}
+
+/// A challenge from the event stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    pub id: String,
+    pub challenger: String,
+    pub variant: String,
+    pub rated: bool,
+    /// `None` for correspondence and unlimited games
+    pub initial: Option<Duration>,
+    pub increment: Option<Duration>,
+}
+
+impl Challenge {
+    pub fn from_json(json: &JsonValue) -> Option<Challenge> {
+        let seconds = |key| {
+            json.path(&["timeControl", key])
+                .and_then(JsonValue::as_f64)
+                .map(Duration::from_secs_f64)
+        };
+        Some(Challenge {
+            id: json.get("id")?.as_str()?.to_string(),
+            challenger: json
+                .path(&["challenger", "id"])
+                .and_then(JsonValue::as_str)
+                .unwrap_or("")
+                .to_string(),
+            variant: json.path(&["variant", "key"])?.as_str()?.to_string(),
+            rated: json
+                .get("rated")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false),
+            initial: seconds("limit"),
+            increment: seconds("increment"),
+        })
+    }
+}
+
+/// The requests the bot makes of the Lichess Bot API. Streams are newline delimited
+/// JSON, with empty lines to keep the connection alive.
+pub trait BotApi: Clone + Send + 'static {
+    /// The id of the bot account the token belongs to.
+    fn account_id(&self) -> Result<String, LichessError>;
+    fn stream_events(&self) -> Result<Box<dyn BufRead + Send>, LichessError>;
+    fn stream_game(&self, game: &str) -> Result<Box<dyn BufRead + Send>, LichessError>;
+    fn accept(&self, challenge: &str) -> Result<(), LichessError>;
+    fn decline(&self, challenge: &str, reason: &str) -> Result<(), LichessError>;
+    fn play(&self, game: &str, uci: &str) -> Result<(), LichessError>;
+    fn resign(&self, game: &str) -> Result<(), LichessError>;
+    /// Ends a game without a result, only possible before both sides have moved.
+    fn abort(&self, game: &str) -> Result<(), LichessError>;
+}
+
+/// The Bot API over HTTPS, through `curl` so no TLS stack has to be built in. The
+/// token is handed to curl on stdin rather than the command line, where other users of
+/// the machine could read it.
+#[derive(Clone, Debug)]
+pub struct CurlApi {
+    base_url: String,
+    token: String,
+    user_agent: String,
+}
+
+impl CurlApi {
+    pub fn new(token: &str) -> CurlApi {
+        CurlApi::with_url(LICHESS_URL, token)
+    }
+
+    pub fn with_url(base_url: &str, token: &str) -> CurlApi {
+        CurlApi {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.trim().to_string(),
+            // The name has no part that depends on the weights
+            user_agent: BuildInfo::new(&EvalParams::default()).name(),
+        }
+    }
+
+    fn spawn(&self, method: &str, path: &str, body: Option<&str>) -> io::Result<Child> {
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--fail", "--no-buffer"])
+            .args(["--header", "@-", "--request", method])
+            .arg(format!("{}{}", self.base_url, path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(body) = body {
+            command.args(["--data", body]);
+        }
+        let mut child = command.spawn()?;
+        let mut stdin = child.stdin.take().expect("curl stdin is piped");
+        writeln!(stdin, "Authorization: Bearer {}", self.token)?;
+        // Lichess asks bots to say what they run
+        writeln!(stdin, "User-Agent: {}", self.user_agent)?;
+        Ok(child)
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> Result<String, LichessError> {
+        let output = self.spawn(method, path, body)?.wait_with_output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr);
+            let message = format!("{} {}: {}", method, path, message.trim());
+            return Err(io::Error::other(message).into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn stream(&self, path: &str) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        let mut child = self.spawn("GET", path, None)?;
+        let stdout = child.stdout.take().expect("curl stdout is piped");
+        Ok(Box::new(CurlStream {
+            child,
+            stdout: BufReader::new(stdout),
+        }))
+    }
+}
+
+impl BotApi for CurlApi {
+    fn account_id(&self) -> Result<String, LichessError> {
+        let text = self.request("GET", "/api/account", None)?;
+        JsonValue::parse(&text)?
+            .get("id")
+            .and_then(JsonValue::as_str)
+            .map(str::to_string)
+            .ok_or(LichessError::Json(JsonError(text)))
+    }
+
+    fn stream_events(&self) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        self.stream("/api/stream/event")
+    }
+
+    fn stream_game(&self, game: &str) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        self.stream(&format!("/api/bot/game/stream/{}", game))
+    }
+
+    fn accept(&self, challenge: &str) -> Result<(), LichessError> {
+        let path = format!("/api/challenge/{}/accept", challenge);
+        self.request("POST", &path, None).map(drop)
+    }
+
+    fn decline(&self, challenge: &str, reason: &str) -> Result<(), LichessError> {
+        let path = format!("/api/challenge/{}/decline", challenge);
+        let body = format!("reason={}", reason);
+        self.request("POST", &path, Some(&body)).map(drop)
+    }
+
+    fn play(&self, game: &str, uci: &str) -> Result<(), LichessError> {
+        let path = format!("/api/bot/game/{}/move/{}", game, uci);
+        self.request("POST", &path, None).map(drop)
+    }
+
+    fn resign(&self, game: &str) -> Result<(), LichessError> {
+        let path = format!("/api/bot/game/{}/resign", game);
+        self.request("POST", &path, None).map(drop)
+    }
+
+    fn abort(&self, game: &str) -> Result<(), LichessError> {
+        let path = format!("/api/bot/game/{}/abort", game);
+        self.request("POST", &path, None).map(drop)
+    }
+}
+
+// The output of a streaming curl, which is stopped when the stream is dropped
+struct CurlStream {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Read for CurlStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl BufRead for CurlStream {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.stdout.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.stdout.consume(amount)
+    }
+}
+
+impl Drop for CurlStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Plays crazyhouse on Lichess as a bot account: accepts the challenges `filter` lets
+/// through while fewer than `max_games` games are running, and plays each game on a
+/// thread of its own.
+///
+/// Cancelling `shutdown` ends [`LichessBot::run`]: running games are aborted if they
+/// have barely started and resigned otherwise, rather than left to time out.
+#[derive(Clone)]
+pub struct LichessBot<A> {
+    api: A,
+    pub filter: ChallengeFilter,
+    pub max_games: usize,
+    pub time: TimeManager,
+    pub shutdown: CancelToken,
+    /// Resigns games the search gives up on, `None` to always play on
+    pub resign: Option<ResignPolicy>,
+    /// Thinks on the opponent's time, see [`GameManager::ponder`]
+    pub ponder: bool,
+    params: Arc<EvalParams>,
+    // The running games by id, with the plies played in each
+    games: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl<A: BotApi> LichessBot<A> {
+    pub fn new(api: A, params: Arc<EvalParams>) -> LichessBot<A> {
+        LichessBot {
+            api,
+            filter: ChallengeFilter::default(),
+            max_games: 1,
+            time: TimeManager::default(),
+            shutdown: CancelToken::new(),
+            resign: Some(ResignPolicy::default()),
+            ponder: false,
+            params,
+            games: Arc::default(),
+        }
+    }
+
+    /// Follows the event stream until it ends or `shutdown` is cancelled.
+    pub fn run(&self) -> Result<(), LichessError> {
+        let account = self.api.account_id()?;
+        // Read on a thread of its own so shutdown is noticed while the stream is quiet
+        let events = self.api.stream_events()?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in command_lines(events) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        loop {
+            if self.shutdown.is_cancelled() {
+                self.leave_games();
+                return Ok(());
+            }
+            let line = match lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A broken event is skipped; the stream goes on with whole ones
+            let event = match JsonValue::parse(&line) {
+                Ok(event) => event,
+                Err(err) => {
+                    eprintln!("skipping event: {}", err);
+                    continue;
+                }
+            };
+            match event.get("type").and_then(JsonValue::as_str) {
+                Some("challenge") => {
+                    let challenge = match event.get("challenge").and_then(Challenge::from_json) {
+                        Some(challenge) => challenge,
+                        None => {
+                            eprintln!("skipping event: {}", JsonError(line));
+                            continue;
+                        }
+                    };
+                    // Our own challenges to others show up here too
+                    if challenge.challenger != account {
+                        self.answer(&challenge)?;
+                    }
+                }
+                Some("gameStart") => {
+                    let game = match event
+                        .path(&["game", "gameId"])
+                        .or_else(|| event.path(&["game", "id"]))
+                        .and_then(JsonValue::as_str)
+                    {
+                        Some(game) => game.to_string(),
+                        None => {
+                            eprintln!("skipping event: {}", JsonError(line));
+                            continue;
+                        }
+                    };
+                    self.games
+                        .lock()
+                        .expect("lichess games lock")
+                        .insert(game.clone(), 0);
+                    let bot = self.clone();
+                    let account = account.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = bot.play_game(&game, &account) {
+                            eprintln!("game {}: {}", game, err);
+                        }
+                        bot.games.lock().expect("lichess games lock").remove(&game);
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Aborts the running games where both sides have yet to move, and resigns the
+    /// others. Failures are reported and don't stop the rest.
+    pub fn leave_games(&self) {
+        let games: Vec<(String, usize)> = self
+            .games
+            .lock()
+            .expect("lichess games lock")
+            .iter()
+            .map(|(game, &plies)| (game.clone(), plies))
+            .collect();
+        for (game, plies) in games {
+            let result = if plies < 2 {
+                self.api.abort(&game)
+            } else {
+                self.api.resign(&game)
+            };
+            if let Err(err) = result {
+                eprintln!("game {}: {}", game, err);
+            }
+        }
+    }
+
+    /// Accepts or declines `challenge`.
+    pub fn answer(&self, challenge: &Challenge) -> Result<(), LichessError> {
+        let running = self.games.lock().expect("lichess games lock").len();
+        let reason = match self.filter.decline_reason(challenge) {
+            None if running >= self.max_games || self.shutdown.is_cancelled() => Some("later"),
+            reason => reason,
+        };
+        match reason {
+            Some(reason) => self.api.decline(&challenge.id, reason),
+            None => self.api.accept(&challenge.id),
+        }
+    }
+
+    /// Plays the game `game` as the account `account` until it is over. The opponent's
+    /// moves and clock usage feed an [`OpponentModel`] that sets our pace and biases
+    /// the search towards their style.
+    pub fn play_game(&self, game: &str, account: &str) -> Result<(), LichessError> {
+        let engine = Engine::new(self.params.clone());
+        let mut manager = GameManager::new(engine, Some(Color::White), Bughouse::default());
+        manager.time = self.time.clone();
+        manager.resign = self.resign.clone();
+        manager.ponder = self.ponder;
+        manager.shutdown = self.shutdown.clone();
+        let mut adapter = LichessMoves {
+            api: &self.api,
+            game,
+        };
+        let mut start = Bughouse::default();
+        let mut color = Color::White;
+        let mut opponent = OpponentModel::new(Color::Black);
+        // Plies the model has seen, and the opponent's clock in the last state
+        let mut observed = 0;
+        let mut opponent_clock = None;
+        // Read on a thread of its own so the engine can ponder while the stream is quiet
+        let stream = self.api.stream_game(game)?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in command_lines(stream) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        loop {
+            let line = if manager.state() == GameState::Pondering {
+                // Until the next state arrives
+                let stop = CancelToken::new();
+                thread::scope(|scope| {
+                    let pondering = scope.spawn(|| manager.ponder(&stop));
+                    let line = lines.recv();
+                    stop.cancel();
+                    pondering.join().expect("pondering thread panicked")?;
+                    Ok::<_, io::Error>(line)
+                })?
+            } else {
+                lines.recv()
+            };
+            let line = match line {
+                Ok(line) => line?,
+                Err(_) => return Ok(()),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Every state carries all the moves, so after a broken one the next whole
+            // one puts the game right again
+            let event = match JsonValue::parse(&line) {
+                Ok(event) => event,
+                Err(err) => {
+                    eprintln!("game {}: skipping event: {}", game, err);
+                    continue;
+                }
+            };
+            let state = match event.get("type").and_then(JsonValue::as_str) {
+                Some("gameFull") => {
+                    let fen = event
+                        .get("initialFen")
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or("startpos");
+                    start = match fen {
+                        "startpos" => Bughouse::default(),
+                        fen => Bughouse::from_fen(fen, CastlingMode::Standard)?,
+                    }
+                    .with_preset(RulePreset::LichessCrazyhouse);
+                    let black = event.path(&["black", "id"]).and_then(JsonValue::as_str);
+                    color = if black == Some(account) {
+                        Color::Black
+                    } else {
+                        Color::White
+                    };
+                    manager.start(Some(color), start.clone());
+                    opponent = OpponentModel::new(!color);
+                    observed = 0;
+                    opponent_clock = None;
+                    match event.get("state") {
+                        Some(state) => state,
+                        None => continue,
+                    }
+                }
+                Some("gameState") => &event,
+                _ => continue,
+            };
+            match state.get("status").and_then(JsonValue::as_str) {
+                Some("started") => {}
+                Some(_) => return Ok(()),
+                None => continue,
+            }
+            let moves = state.get("moves").and_then(JsonValue::as_str).unwrap_or("");
+            self.games
+                .lock()
+                .expect("lichess games lock")
+                .insert(game.to_string(), moves.split_whitespace().count());
+            let position = match replay(&start, moves) {
+                Ok(position) => position,
+                Err(err) => {
+                    eprintln!("game {}: skipping state: {}", game, err);
+                    continue;
+                }
+            };
+            let millis = |key: &str| {
+                // A clock that is no number of milliseconds counts as empty
+                let millis = state
+                    .get(key)
+                    .and_then(JsonValue::as_f64)
+                    .filter(|millis| millis.is_finite() && *millis >= 0.0)
+                    .unwrap_or(0.0);
+                Duration::from_millis(millis as u64)
+            };
+            let clock = |side: Color| match side {
+                Color::White => (millis("wtime"), millis("winc")),
+                Color::Black => (millis("btime"), millis("binc")),
+            };
+            let plies = moves.split_whitespace().count();
+            if plies > observed {
+                // The clocks only tell how long a single new move of the opponent took
+                let (now, increment) = clock(!color);
+                let time_used = opponent_clock
+                    .filter(|_| plies == observed + 1 && position.turn() == color)
+                    .map(|before: Duration| (before + increment).saturating_sub(now));
+                observe(&mut opponent, &start, moves, observed, time_used);
+                observed = plies;
+            }
+            opponent_clock = Some(clock(!color).0);
+            let clocks = ByColor {
+                white: clock(Color::White).0,
+                black: clock(Color::Black).0,
+            };
+            manager.sync(position, Some(clocks), clock(color).1);
+            if manager.state() != GameState::Thinking {
+                continue;
+            }
+            manager.opponent = Some(opponent.clone());
+            manager
+                .searcher_mut()
+                .set_params(Arc::new(opponent.bias(&self.params)));
+            match manager.think(&mut adapter)? {
+                // On shutdown the game is resigned instead
+                None => return Ok(()),
+                Some(Decision::Resign) => return Ok(()),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+// Sends the engine's moves in one game
+struct LichessMoves<'a, A> {
+    api: &'a A,
+    game: &'a str,
+}
+
+impl<A: BotApi> GameAdapter for LichessMoves<'_, A> {
+    type Error = LichessError;
+
+    fn play(&mut self, _: &Bughouse, m: Option<&Move>) -> Result<(), LichessError> {
+        match m {
+            Some(m) => self.api.play(self.game, &Uci::from_standard(m).to_string()),
+            // Lichess crazyhouse has no passing
+            None => Ok(()),
+        }
+    }
+
+    fn resign(&mut self) -> Result<(), LichessError> {
+        self.api.resign(self.game)
+    }
+}
+
+// Teaches `model` the UCI `moves` from `start` on from ply `from`, which are known to
+// be legal, the last one taking `time_used`
+fn observe(
+    model: &mut OpponentModel,
+    start: &Bughouse,
+    moves: &str,
+    from: usize,
+    time_used: Option<Duration>,
+) {
+    let moves: Vec<&str> = moves.split_whitespace().collect();
+    let mut position = start.clone();
+    for (ply, uci) in moves.iter().enumerate() {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&position).ok())
+        {
+            Some(m) => m,
+            None => return,
+        };
+        if ply >= from {
+            let last = ply + 1 == moves.len();
+            model.observe(&position, &m, time_used.filter(|_| last));
+        }
+        position.play_unchecked(&m);
+    }
+}
+
+// The position after the UCI `moves` from `start`
+fn replay(start: &Bughouse, moves: &str) -> Result<Bughouse, IllegalMove> {
+    let mut position = start.clone();
+    for uci in moves.split_whitespace() {
+        let m = uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|parsed| parsed.to_move(&position).ok())
+            .ok_or_else(|| IllegalMove(uci.to_string()))?;
+        position.play_unchecked(&m);
+    }
+    Ok(position)
+}
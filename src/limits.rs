@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancelToken;
+
+/// When a search has to stop. Limits left at `None` don't apply; a search without any
+/// limit runs until it is cancelled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Search iterations, each adding one node to the tree
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+    /// Deepest selected line, as a proxy for the depth of alpha-beta engines
+    pub depth: Option<usize>,
+    /// Stop as soon as a forced mate for the side to move is proven
+    pub stop_on_mate: bool,
+}
+
+impl SearchLimits {
+    pub fn nodes(nodes: u64) -> SearchLimits {
+        SearchLimits {
+            nodes: Some(nodes),
+            ..SearchLimits::default()
+        }
+    }
+
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            time: Some(time),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+/// Why a search stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Nodes,
+    Time,
+    Depth,
+    Mate,
+    Cancelled,
+}
+
+// Reading the clock on every iteration would cost more than the iteration
+const TIME_CHECK_INTERVAL: u64 = 32;
+
+/// The limits of one search together with its progress, shared by all threads working
+/// on it. Every check is a few atomic loads.
+#[derive(Debug)]
+pub struct SearchControl {
+    limits: SearchLimits,
+    start: Instant,
+    cancel: CancelToken,
+    nodes: AtomicU64,
+    depth: AtomicUsize,
+    mate_found: AtomicBool,
+    out_of_time: AtomicBool,
+}
+
+impl SearchControl {
+    pub fn new(limits: SearchLimits, cancel: CancelToken) -> SearchControl {
+        SearchControl {
+            limits,
+            start: Instant::now(),
+            cancel,
+            nodes: AtomicU64::new(0),
+            depth: AtomicUsize::new(0),
+            mate_found: AtomicBool::new(false),
+            out_of_time: AtomicBool::new(false),
+        }
+    }
+
+    pub fn limits(&self) -> &SearchLimits {
+        &self.limits
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    /// Deepest line selected so far.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Counts one finished iteration that selected a line `depth` plies deep.
+    pub fn add_iteration(&self, depth: usize) {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        self.depth.fetch_max(depth, Ordering::Relaxed);
+        if nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            if let Some(time) = self.limits.time {
+                if self.start.elapsed() >= time {
+                    self.out_of_time.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Records that the side to move has a proven forced mate.
+    pub fn report_mate(&self) {
+        self.mate_found.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mate_found(&self) -> bool {
+        self.mate_found.load(Ordering::Relaxed)
+    }
+
+    /// The reason to stop, if any limit has been reached.
+    pub fn should_stop(&self) -> Option<StopReason> {
+        if self.cancel.is_cancelled() {
+            Some(StopReason::Cancelled)
+        } else if self.out_of_time.load(Ordering::Relaxed) {
+            Some(StopReason::Time)
+        } else if self.limits.nodes.is_some_and(|nodes| self.nodes() >= nodes) {
+            Some(StopReason::Nodes)
+        } else if self.limits.depth.is_some_and(|depth| self.depth() >= depth) {
+            Some(StopReason::Depth)
+        } else if self.limits.stop_on_mate && self.mate_found() {
+            Some(StopReason::Mate)
+        } else {
+            None
+        }
+    }
+}
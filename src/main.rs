@@ -1,12 +1,505 @@
-use std::ops::Add;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use board::Bughouse;
-use shakmaty::{fen::epd, ByColor, MaterialSide, Move, Position, Role, Setup, Square};
+use ladybug::access::AccessControl;
+use ladybug::arena::{play_contenders, round_robin, Arena, Contender, Pairing, Sprt, SprtVerdict};
+use ladybug::artifacts::{ArtifactKind, ArtifactStore, Manifest};
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::book::{BuildConfig, OpeningBook};
+use ladybug::bpgn;
+use ladybug::build_info::{evaluator_id, BuildInfo};
+use ladybug::calibration::Calibration;
+use ladybug::cancel::CancelToken;
+use ladybug::cluster::{run_worker, Coordinator, JobSpec};
+use ladybug::config::EngineConfig;
+use ladybug::difftest::{self, DiffConfig, ReferenceEngine, UciReference};
+use ladybug::drill::{Drill, Motif, Verdict};
+use ladybug::engine::{parse_move_list, Engine, LongAnalysis, RootFilter, RootNoise};
+use ladybug::eval::{EvalHandle, EvalParams};
+use ladybug::fics::{FicsClient, FicsConfig, FICS_HOST, FICS_PORT};
+use ladybug::insights::{Insights, SearchAnalyzer, StaticAnalyzer};
+use ladybug::lichess::{ChallengeFilter, CurlApi, LichessBot};
+use ladybug::limits::{SearchControl, SearchLimits};
+use ladybug::output::{json_array, json_string, Format, JsonObject};
+use ladybug::paths::{write_atomic, AppPaths};
+use ladybug::prior::EvalPriors;
+use ladybug::protocol::{detect_protocol, Protocol};
+use ladybug::remote::{serve_remote, serve_remote_with_access};
+use ladybug::resign::ResignPolicy;
+use ladybug::script::ScriptRunner;
+use ladybug::selfcheck;
+use ladybug::selfplay::{self, SelfPlayConfig};
+use ladybug::session::{Session, SessionStore};
+use ladybug::shutdown;
+use ladybug::svg;
+#[cfg(feature = "tui")]
+use ladybug::team::Blend;
+use ladybug::training::{ExportOptions, TrainingExporter};
+use ladybug::traps::TrapBook;
+use ladybug::uci::UciEngine;
+use ladybug::validate::{validate_fens, validate_records};
+use ladybug::xboard::XboardEngine;
+use shakmaty::fen::epd;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position, Role, Square};
 
-mod board;
-mod engine;
+type CliResult = Result<(), Box<dyn std::error::Error>>;
 
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--version") {
+        println!("{}", BuildInfo::new(&EvalParams::default()));
+        return;
+    }
+    if let Err(err) = shutdown::install() {
+        eprintln!("could not install signal handlers: {}", err);
+    }
+    let shutdown = CancelToken::new();
+    shutdown::cancel_on_shutdown(shutdown.clone());
+    let result = take_format(&mut args).and_then(|format| {
+        let verbose = args.iter().any(|arg| arg == "--verbose");
+        let records = args.iter().any(|arg| arg == "--records");
+        let checkpoint = take_value(&mut args, "--checkpoint", ANALYZE_USAGE)?;
+        let every = take_value(&mut args, "--every", ANALYZE_USAGE)?;
+        let resume = take_value(&mut args, "--resume-analysis", ANALYZE_USAGE)?;
+        let trace_out = take_value(&mut args, "--trace-out", ANALYZE_USAGE)?;
+        let only = take_value(&mut args, "--only", ANALYZE_USAGE)?;
+        let bins = take_value(&mut args, "--bins", CALIBRATE_USAGE)?;
+        let theme = take_value(&mut args, "--theme", "--theme needs one of drop, mate")?;
+        let token_file = take_value(&mut args, "--token-file", LICHESS_USAGE)?;
+        let min_initial = take_value(&mut args, "--min-initial", LICHESS_USAGE)?;
+        let max_initial = take_value(&mut args, "--max-initial", LICHESS_USAGE)?;
+        let max_increment = take_value(&mut args, "--max-increment", LICHESS_USAGE)?;
+        let games = take_value(&mut args, "--games", LICHESS_USAGE)?;
+        let casual_only = args.iter().any(|arg| arg == "--casual-only");
+        let ponder = args.iter().any(|arg| arg == "--ponder");
+        let flipped = args.iter().any(|arg| arg == "--flipped");
+        let gauntlet = args.iter().any(|arg| arg == "--gauntlet");
+        let login = take_value(&mut args, "--login", FICS_USAGE)?;
+        let password_file = take_value(&mut args, "--password-file", FICS_USAGE)?;
+        let partner = take_value(&mut args, "--partner", FICS_USAGE)?;
+        let initial = take_value(&mut args, "--initial", FICS_USAGE)?;
+        let increment = take_value(&mut args, "--increment", FICS_USAGE)?;
+        let store = take_value(&mut args, "--store", ARTIFACTS_USAGE)?;
+        let manifest = take_value(&mut args, "--manifest", ARTIFACTS_USAGE)?;
+        let positions = take_value(&mut args, "--positions", DIFFTEST_USAGE)?;
+        let depth = take_value(&mut args, "--depth", DIFFTEST_USAGE)?;
+        let jobs = take_value(&mut args, "--jobs", DIFFTEST_USAGE)?;
+        let seed = take_value(&mut args, "--seed", "--seed needs a number")?;
+        let concurrency = take_value(&mut args, "--concurrency", MATCH_USAGE)?;
+        let archive = take_value(&mut args, "--archive", MATCH_USAGE)?;
+        let sprt = take_value(&mut args, "--sprt", MATCH_USAGE)?;
+        let dirichlet = take_value(&mut args, "--dirichlet", SELFPLAY_USAGE)?;
+        let temperature = take_value(&mut args, "--temperature", SELFPLAY_USAGE)?;
+        let temperature_plies = take_value(&mut args, "--temperature-plies", SELFPLAY_USAGE)?;
+        #[cfg(feature = "nn")]
+        let gumbel = take_value(&mut args, "--gumbel", SELFPLAY_USAGE)?;
+        let resign = take_value(&mut args, "--resign", "--resign needs an expected score")?;
+        let nodes = take_value(&mut args, "--nodes", "--nodes needs a count")?;
+        let plies = take_value(&mut args, "--plies", BOOK_USAGE)?;
+        let min_games = take_value(&mut args, "--min-games", BOOK_USAGE)?;
+        #[cfg(feature = "tui")]
+        let clock = take_value(&mut args, "--clock", WATCH_USAGE)?;
+        #[cfg(feature = "tui")]
+        let objective = take_value(&mut args, "--objective", WATCH_USAGE)?;
+        let name = take_value(&mut args, "--name", WORKER_USAGE)?;
+        let tokens = take_value(&mut args, "--tokens", REMOTE_USAGE)?;
+        let config = take_value(&mut args, "--config", "--config needs a file")?;
+        args.retain(|arg| !arg.starts_with("--"));
+        let paths = AppPaths::detect();
+        // These modes poll `shutdown` to flush their files or resign their games. The
+        // others keep nothing worth flushing or block on input, so they exit at once
+        let polls = matches!(
+            args.first().map(String::as_str),
+            Some("analyze" | "difftest" | "fics" | "lichess" | "match" | "selfplay")
+        );
+        if !polls {
+            shutdown::exit_on_shutdown();
+        }
+        match args.first().map(String::as_str) {
+            Some("analyze") => {
+                let every = match every {
+                    Some(minutes) => minutes.parse::<f64>().map_err(|_| ANALYZE_USAGE)?,
+                    None => 10f64,
+                };
+                // Long analyses are saved somewhere unless told where
+                let checkpoint = match checkpoint.or_else(|| resume.clone()) {
+                    Some(path) => PathBuf::from(path),
+                    None => {
+                        paths.create()?;
+                        paths.autosaves().join("analysis.checkpoint")
+                    }
+                };
+                let analysis = start_analysis(
+                    &args[1..].join(" "),
+                    resume.map(PathBuf::from),
+                    only.as_deref(),
+                )?;
+                analyze(
+                    analysis,
+                    Some(checkpoint),
+                    trace_out.map(PathBuf::from),
+                    Duration::from_secs_f64(every.max(0f64) * 60f64),
+                    format,
+                    &shutdown,
+                )
+            }
+            Some("artifacts") => {
+                paths.create()?;
+                artifacts(
+                    &args[1..],
+                    &store.map_or_else(|| paths.artifacts(), PathBuf::from),
+                    &manifest.map_or_else(|| paths.manifest(), PathBuf::from),
+                    format,
+                )
+            }
+            Some("book") => {
+                let mut config = BuildConfig::default();
+                if let Some(plies) = plies {
+                    config.plies = plies.parse().map_err(|_| BOOK_USAGE)?;
+                }
+                if let Some(min_games) = min_games {
+                    config.min_games = min_games.parse().map_err(|_| BOOK_USAGE)?;
+                }
+                book(&args[1..], &config, format)
+            }
+            Some("calibrate") => match args.get(1) {
+                Some(path) => {
+                    let bins = match bins {
+                        Some(bins) => bins.parse().map_err(|_| CALIBRATE_USAGE)?,
+                        None => 10,
+                    };
+                    calibrate(Path::new(path), bins, format)
+                }
+                None => Err(CALIBRATE_USAGE.into()),
+            },
+            Some("coordinator") => match (args.get(1), args.get(2)) {
+                (Some(addr), Some(out_dir)) => {
+                    let jobs = match &jobs {
+                        Some(jobs) => jobs.parse().map_err(|_| COORDINATOR_USAGE)?,
+                        None => 10,
+                    };
+                    let games = match &games {
+                        Some(games) => games.parse().map_err(|_| COORDINATOR_USAGE)?,
+                        None => 10,
+                    };
+                    let seed = match &seed {
+                        Some(seed) => seed.parse().map_err(|_| COORDINATOR_USAGE)?,
+                        None => 0,
+                    };
+                    coordinator(addr, Path::new(out_dir), jobs, games, seed, format)
+                }
+                _ => Err(COORDINATOR_USAGE.into()),
+            },
+            Some("difftest") if args.len() > 1 => {
+                let mut config = DiffConfig::default();
+                if let Some(positions) = positions {
+                    config.positions = positions.parse().map_err(|_| DIFFTEST_USAGE)?;
+                }
+                if let Some(depth) = depth {
+                    config.depth = depth.parse().map_err(|_| DIFFTEST_USAGE)?;
+                }
+                if let Some(seed) = seed {
+                    config.seed = seed.parse().map_err(|_| DIFFTEST_USAGE)?;
+                }
+                let jobs = match jobs {
+                    Some(jobs) => jobs.parse().map_err(|_| DIFFTEST_USAGE)?,
+                    None => thread::available_parallelism().map_or(1, usize::from),
+                };
+                run_difftest(&args[1..].join(" "), &config, jobs, format, &shutdown)
+            }
+            Some("difftest") => Err(DIFFTEST_USAGE.into()),
+            Some("drill") => drill(
+                &match args.get(1) {
+                    Some(path) => PathBuf::from(path),
+                    None => {
+                        paths.create()?;
+                        paths.drill()
+                    }
+                },
+                theme.as_deref().map(str::parse).transpose()?,
+                format,
+            ),
+            Some("fics") => {
+                let mut config = FicsConfig {
+                    partner,
+                    ..FicsConfig::default()
+                };
+                if let Some(login) = login {
+                    config.login = login;
+                }
+                if let Some(minutes) = initial {
+                    config.initial = minutes.parse().map_err(|_| FICS_USAGE)?;
+                }
+                if let Some(seconds) = increment {
+                    config.increment = seconds.parse().map_err(|_| FICS_USAGE)?;
+                }
+                if let Some(games) = &games {
+                    config.games = games.parse().map_err(|_| FICS_USAGE)?;
+                }
+                if let Some(threshold) = &resign {
+                    config.resign = parse_resign(threshold, FICS_USAGE)?;
+                }
+                fics(config, password_file.map(PathBuf::from), &shutdown)
+            }
+            Some("insights") => match (args.get(1), args.get(2)) {
+                (Some(archive), Some(player)) => {
+                    let nodes = match &nodes {
+                        Some(nodes) => nodes.parse().map_err(|_| INSIGHTS_USAGE)?,
+                        None => 200,
+                    };
+                    let traps = TrapBook::open(&paths.traps())?;
+                    insights(Path::new(archive), player, nodes, &traps, format)
+                }
+                _ => Err(INSIGHTS_USAGE.into()),
+            },
+            Some("lichess") => {
+                let mut filter = ChallengeFilter::default();
+                if let Some(seconds) = min_initial {
+                    filter.min_initial = parse_seconds(&seconds, LICHESS_USAGE)?;
+                }
+                if let Some(seconds) = max_initial {
+                    filter.max_initial = parse_seconds(&seconds, LICHESS_USAGE)?;
+                }
+                if let Some(seconds) = max_increment {
+                    filter.max_increment = parse_seconds(&seconds, LICHESS_USAGE)?;
+                }
+                filter.rated = !casual_only;
+                let games = match games {
+                    Some(games) => games.parse().map_err(|_| LICHESS_USAGE)?,
+                    None => 1,
+                };
+                let resign = match &resign {
+                    Some(threshold) => parse_resign(threshold, LICHESS_USAGE)?,
+                    None => Some(ResignPolicy::default()),
+                };
+                lichess(
+                    token_file.map(PathBuf::from),
+                    filter,
+                    games,
+                    resign,
+                    ponder,
+                    &shutdown,
+                )
+            }
+            Some("match") if args.len() >= 3 => {
+                let games = match games {
+                    Some(games) => games.parse().map_err(|_| MATCH_USAGE)?,
+                    None => 100,
+                };
+                let concurrency = match concurrency {
+                    Some(concurrency) => concurrency.parse().map_err(|_| MATCH_USAGE)?,
+                    None => thread::available_parallelism().map_or(1, usize::from),
+                };
+                let contenders = args[1..]
+                    .iter()
+                    .map(|spec| Contender::parse(spec))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut arena = Arena::new(
+                    contenders
+                        .iter()
+                        .map(|contender| contender.name.clone())
+                        .collect(),
+                );
+                arena.concurrency = concurrency;
+                arena.archive = archive.map(PathBuf::from);
+                arena.cancel = shutdown.clone();
+                let sprt = match sprt {
+                    // The test is of the first contender against the second
+                    Some(_) if contenders.len() > 2 => {
+                        return Err("--sprt needs exactly two contenders".into())
+                    }
+                    Some(bounds) => {
+                        let (elo0, elo1) = bounds.split_once(',').ok_or(MATCH_USAGE)?;
+                        Some(Sprt::new(
+                            elo0.trim().parse().map_err(|_| MATCH_USAGE)?,
+                            elo1.trim().parse().map_err(|_| MATCH_USAGE)?,
+                        ))
+                    }
+                    None => None,
+                };
+                let pairings = if gauntlet {
+                    ladybug::arena::gauntlet(contenders.len(), games)
+                } else {
+                    round_robin(contenders.len(), games)
+                };
+                run_match(&arena, &contenders, pairings, sprt, format)
+            }
+            Some("match") => Err(MATCH_USAGE.into()),
+            Some("paths") => {
+                show_paths(&paths, format);
+                Ok(())
+            }
+            Some("remote") => match args.get(1) {
+                Some(addr) => remote(
+                    addr,
+                    config.as_deref().map(Path::new),
+                    tokens.as_deref().map(Path::new),
+                ),
+                None => Err(REMOTE_USAGE.into()),
+            },
+            Some("script") => {
+                paths.create()?;
+                script(args.get(1).map(Path::new), &paths.traps())
+            }
+            Some("selfcheck") => selfcheck(format),
+            Some("session") => {
+                let nodes = match nodes {
+                    Some(nodes) => nodes.parse().map_err(|_| SESSION_USAGE)?,
+                    None => 10_000,
+                };
+                session(&args[1..], &paths.sessions(), nodes, format)
+            }
+            Some("selfplay") => {
+                let output = args.get(1).ok_or(SELFPLAY_USAGE)?;
+                let mut config = SelfPlayConfig::default();
+                if let Some(games) = games {
+                    config.games = games.parse().map_err(|_| SELFPLAY_USAGE)?;
+                }
+                if let Some(nodes) = nodes {
+                    config.nodes = nodes.parse().map_err(|_| SELFPLAY_USAGE)?;
+                }
+                if let Some(alpha) = dirichlet {
+                    let alpha: f32 = alpha.parse().map_err(|_| SELFPLAY_USAGE)?;
+                    // A concentration of 0 turns the noise off
+                    config.noise = Some(RootNoise {
+                        alpha,
+                        ..RootNoise::default()
+                    })
+                    .filter(|noise| noise.alpha > 0f32);
+                }
+                if let Some(temperature) = temperature {
+                    config.temperature = temperature.parse().map_err(|_| SELFPLAY_USAGE)?;
+                }
+                if let Some(plies) = temperature_plies {
+                    config.temperature_plies = plies.parse().map_err(|_| SELFPLAY_USAGE)?;
+                }
+                if let Some(seed) = seed {
+                    config.seed = seed.parse().map_err(|_| SELFPLAY_USAGE)?;
+                }
+                #[cfg(feature = "nn")]
+                if let Some(considered) = gumbel {
+                    config.gumbel = Some(considered.parse().map_err(|_| SELFPLAY_USAGE)?);
+                }
+                if let Some(threshold) = &resign {
+                    config.resign = parse_resign(threshold, SELFPLAY_USAGE)?;
+                }
+                selfplay(Path::new(output), &config, format, &shutdown)
+            }
+            Some("svg") => {
+                let nodes = match nodes {
+                    Some(nodes) => nodes.parse().map_err(|_| SVG_USAGE)?,
+                    None => 1000,
+                };
+                svg(&args[1..], nodes, flipped, format)
+            }
+            Some("validate") => match args.get(1) {
+                Some(path) if records => check_records(Path::new(path), format),
+                Some(path) => validate(Path::new(path), verbose, format),
+                None => Err("usage: ladybug validate <file> [--verbose] [--records]".into()),
+            },
+            #[cfg(feature = "tui")]
+            Some("watch") => {
+                let nodes = match nodes {
+                    Some(nodes) => nodes.parse().map_err(|_| WATCH_USAGE)?,
+                    None => 400,
+                };
+                let clock = match clock {
+                    Some(seconds) => parse_seconds(&seconds, WATCH_USAGE)?,
+                    None => Duration::from_secs(120),
+                };
+                let objective = objective
+                    .map(|objective| objective.parse::<Blend>())
+                    .transpose()?;
+                watch(nodes, clock, objective)
+            }
+            Some("worker") => match args.get(1) {
+                Some(addr) => {
+                    let nodes = match &nodes {
+                        Some(nodes) => nodes.parse().map_err(|_| WORKER_USAGE)?,
+                        None => SelfPlayConfig::default().nodes,
+                    };
+                    let name = name.unwrap_or_else(|| format!("worker-{}", process::id()));
+                    worker(addr, &name, nodes, format)
+                }
+                None => Err(WORKER_USAGE.into()),
+            },
+            Some("demo") => {
+                demo();
+                Ok(())
+            }
+            _ => serve(config.as_deref().map(Path::new)),
+        }
+    });
+    if let Err(err) = &result {
+        eprintln!("{}", err);
+    }
+    // A mode that wound down for a signal exits the way a killed process would
+    if let Some(signal) = shutdown::requested() {
+        process::exit(signal.exit_code());
+    }
+    if result.is_err() {
+        process::exit(1);
+    }
+}
+
+// Removes `flag` and the value after it from the arguments, returning the value
+fn take_value(
+    args: &mut Vec<String>,
+    flag: &str,
+    usage: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => {
+            let value = args.get(index + 1).ok_or(usage)?.clone();
+            args.drain(index..index + 2);
+            Ok(Some(value))
+        }
+        None => Ok(None),
+    }
+}
+
+// Removes `--format <format>` from the arguments, defaulting to human readable output
+fn take_format(args: &mut Vec<String>) -> Result<Format, Box<dyn std::error::Error>> {
+    match take_value(args, "--format", "--format needs one of human, json")? {
+        Some(format) => Ok(format.parse()?),
+        None => Ok(Format::Human),
+    }
+}
+
+// Speaks the protocol the GUI starts with on stdin, with the settings of `config`
+fn serve(config: Option<&Path>) -> CliResult {
+    let config = match config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    match detect_protocol(&mut input)? {
+        Some((Protocol::Uci, first)) => {
+            let mut engine = UciEngine::new(io::stdout(), EvalHandle::default());
+            engine.set_config(config);
+            engine.run(io::Cursor::new(format!("{}\n", first)).chain(input))?;
+            Ok(())
+        }
+        Some((Protocol::Xboard, first)) => {
+            // Only the weights, the XBoard driver has no search settings yet
+            let mut engine = XboardEngine::new(io::stdout(), EvalHandle::new(config.eval));
+            engine.run(io::Cursor::new(format!("{}\n", first)).chain(input))?;
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+fn demo() {
     let mut x = Bughouse::default();
     x = x
         .play(&Move::Normal {
@@ -19,3 +512,982 @@ fn main() {
         .expect("Illegal move");
     println!("{}", epd(&x));
 }
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+const ANALYZE_USAGE: &str = "usage: ladybug analyze <fen> [--checkpoint <file>] [--every <minutes>] [--trace-out <file>] [--only <moves>] | ladybug analyze --resume-analysis <file> [--trace-out <file>] [--only <moves>]";
+
+// A new analysis of `fen` or one resumed from a checkpoint, searching only the root
+// moves of `only`, like `e4,N@f3`, if given
+fn start_analysis(
+    fen: &str,
+    resume: Option<PathBuf>,
+    only: Option<&str>,
+) -> Result<LongAnalysis, Box<dyn std::error::Error>> {
+    let params = Arc::new(EvalParams::default());
+    let mut analysis = match resume {
+        Some(path) => LongAnalysis::resume(&path, params)?,
+        None if fen.is_empty() => return Err(ANALYZE_USAGE.into()),
+        None => LongAnalysis::new(&parse_fen(fen)?, params, unix_time()),
+    };
+    if let Some(moves) = only {
+        let moves = parse_move_list(analysis.position(), moves)?;
+        analysis.set_root_filter(RootFilter {
+            only: Some(moves),
+            ..RootFilter::default()
+        });
+    }
+    Ok(analysis)
+}
+
+// Analyzes until interrupted, saving the tree to `checkpoint` and the phase timings to
+// `trace_out` after every stretch of `every`
+fn analyze(
+    mut analysis: LongAnalysis,
+    checkpoint: Option<PathBuf>,
+    trace_out: Option<PathBuf>,
+    every: Duration,
+    format: Format,
+    shutdown: &CancelToken,
+) -> CliResult {
+    analysis.set_tracing(trace_out.is_some());
+    loop {
+        // Shutdown ends the running stretch early, which is then checkpointed as usual
+        let control = SearchControl::new(SearchLimits::time(every), shutdown.clone());
+        let result = analysis.run(&control);
+        if let Some(path) = &checkpoint {
+            analysis.checkpoint(path)?;
+        }
+        // Rewritten after every stretch, so it covers the whole analysis
+        if let (Some(path), Some(trace)) = (&trace_out, analysis.trace()) {
+            trace.write(path)?;
+        }
+        let best = result.best.map_or_else(
+            || Uci::Null.to_string(),
+            |m| Uci::from_standard(&m).to_string(),
+        );
+        match (format, result.mate) {
+            (Format::Human, Some(moves)) => println!(
+                "iterations {} best {} mate {}",
+                analysis.iterations(),
+                best,
+                moves
+            ),
+            (Format::Human, None) => println!(
+                "iterations {} best {} win {:.1}%",
+                analysis.iterations(),
+                best,
+                result.win_probability * 100f32
+            ),
+            (Format::Json, mate) => {
+                let mut object = JsonObject::document("analysis")
+                    .number("iterations", analysis.iterations())
+                    .string("best", &best)
+                    .number("win_probability", result.win_probability);
+                if let Some(moves) = mate {
+                    object = object.number("mate", moves);
+                }
+                println!("{}", object)
+            }
+        }
+        if analysis.position().is_game_over() || shutdown.is_cancelled() {
+            return Ok(());
+        }
+    }
+}
+
+// Quizzes the user on every due card of the deck at `path` until none are left, only
+// on cards with the motif `theme` if given
+fn drill(path: &Path, theme: Option<Motif>, format: Format) -> CliResult {
+    let mut deck = Drill::open(path)?;
+    let mut analyzer = StaticAnalyzer::default();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let due = match theme {
+            Some(motif) => deck.next_due_matching(unix_time(), motif, &mut analyzer)?,
+            None => deck.next_due(unix_time()),
+        };
+        let index = match due {
+            Some(index) => index,
+            None => break,
+        };
+        let card = &deck.cards()[index];
+        let position = card.position()?;
+        match format {
+            Format::Human => {
+                println!("{}", card.epd);
+                print!("your move: ");
+                io::stdout().flush()?;
+            }
+            Format::Json => println!(
+                "{}",
+                JsonObject::document("drill_card").string("epd", &card.epd)
+            ),
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let answer = match parse_move_list(&position, &line) {
+            Ok(moves) if !moves.is_empty() => moves[0].clone(),
+            Ok(_) => continue,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let blunder = Uci::from_standard(&card.blunder).to_string();
+        let verdict = deck.answer(index, &answer, &mut analyzer, unix_time())?;
+        match format {
+            Format::Human => match verdict {
+                Verdict::Correct => println!("correct"),
+                Verdict::Repeated => println!("that is the move played in the game"),
+                Verdict::Blunder => println!("also a blunder; the game move was {}", blunder),
+                Verdict::Illegal => println!("illegal move"),
+            },
+            Format::Json => println!(
+                "{}",
+                JsonObject::document("drill_answer")
+                    .string("verdict", &verdict.to_string())
+                    .string("game_move", &blunder)
+            ),
+        }
+    }
+    match (format, deck.success_rate()) {
+        (Format::Human, Some(rate)) => println!("success rate: {:.0}%", rate * 100f32),
+        (Format::Human, None) => {}
+        (Format::Json, rate) => println!(
+            "{}",
+            JsonObject::document("drill_summary").number("success_rate", rate.unwrap_or(f32::NAN))
+        ),
+    }
+    Ok(())
+}
+
+// Seconds as a duration, negative values counting as zero
+fn parse_seconds(value: &str, usage: &str) -> Result<Duration, Box<dyn std::error::Error>> {
+    let seconds = value.parse::<f64>().map_err(|_| usage)?;
+    Ok(Duration::from_secs_f64(seconds.max(0f64)))
+}
+
+// A resign threshold in expected score, 0 for never resigning
+fn parse_resign(
+    value: &str,
+    usage: &str,
+) -> Result<Option<ResignPolicy>, Box<dyn std::error::Error>> {
+    let threshold = value
+        .parse::<f32>()
+        .ok()
+        .filter(|threshold| (0f32..=1f32).contains(threshold))
+        .ok_or(usage)?;
+    Ok(Some(ResignPolicy::default().with_threshold(threshold)).filter(|_| threshold > 0f32))
+}
+
+const FICS_USAGE: &str = "usage: ladybug fics [--login <handle>] [--password-file <file>] [--partner <handle>] [--initial <minutes>] [--increment <seconds>] [--games <count>] [--resign <expected score>]";
+
+// Plays on FICS, as a guest unless a handle is given; the password comes from
+// `$FICS_PASSWORD` or `password_file`
+fn fics(
+    mut config: FicsConfig,
+    password_file: Option<PathBuf>,
+    shutdown: &CancelToken,
+) -> CliResult {
+    config.password = match (std::env::var("FICS_PASSWORD"), password_file) {
+        (_, Some(path)) => Some(std::fs::read_to_string(path)?.trim().to_string()),
+        (Ok(password), None) => Some(password),
+        (Err(_), None) => None,
+    };
+    config.games = config.games.max(1);
+    let mut client = FicsClient::connect(
+        FICS_HOST,
+        FICS_PORT,
+        config,
+        Arc::new(EvalParams::default()),
+    )?;
+    client.shutdown = shutdown.clone();
+    client.run()?;
+    Ok(())
+}
+
+const LICHESS_USAGE: &str = "usage: ladybug lichess [--token-file <file>] [--min-initial <seconds>] [--max-initial <seconds>] [--max-increment <seconds>] [--games <count>] [--casual-only] [--resign <expected score>] [--ponder]";
+
+// Plays on Lichess as the bot account of the token in `$LICHESS_TOKEN` or `token_file`
+fn lichess(
+    token_file: Option<PathBuf>,
+    filter: ChallengeFilter,
+    games: usize,
+    resign: Option<ResignPolicy>,
+    ponder: bool,
+    shutdown: &CancelToken,
+) -> CliResult {
+    let token = match (std::env::var("LICHESS_TOKEN"), token_file) {
+        (_, Some(path)) => std::fs::read_to_string(path)?,
+        (Ok(token), None) => token,
+        (Err(_), None) => return Err(LICHESS_USAGE.into()),
+    };
+    let mut bot = LichessBot::new(CurlApi::new(&token), Arc::new(EvalParams::default()));
+    bot.filter = filter;
+    bot.max_games = games.max(1);
+    bot.resign = resign;
+    bot.ponder = ponder;
+    bot.shutdown = shutdown.clone();
+    bot.run()?;
+    Ok(())
+}
+
+const ARTIFACTS_USAGE: &str = "usage: ladybug artifacts add <weights|book|experience> <file> | ladybug artifacts verify [--store <dir>] [--manifest <file>]";
+
+// Adds a file to the artifact store and the manifest, or checks that everything the
+// manifest lists is in the store and intact
+fn artifacts(args: &[String], store: &Path, manifest_path: &Path, format: Format) -> CliResult {
+    let store = ArtifactStore::open(store)?;
+    let mut manifest = Manifest::load(manifest_path)?;
+    match args {
+        [command, kind, file] if command == "add" => {
+            let kind: ArtifactKind = kind.parse()?;
+            let id = store.import(kind, Path::new(file))?;
+            manifest.set(kind, Some(id));
+            manifest.save(manifest_path)?;
+            match format {
+                Format::Human => println!("{} {}", kind, id),
+                Format::Json => println!(
+                    "{}",
+                    JsonObject::document("artifact")
+                        .string("kind", &kind.to_string())
+                        .string("id", &id.to_string())
+                ),
+            }
+        }
+        [command] if command == "verify" => {
+            store.verify(&manifest)?;
+            match format {
+                Format::Human => println!("manifest {} ok", manifest.id()),
+                Format::Json => println!(
+                    "{}",
+                    JsonObject::document("manifest").string("id", &manifest.id().to_string())
+                ),
+            }
+        }
+        _ => return Err(ARTIFACTS_USAGE.into()),
+    }
+    Ok(())
+}
+
+const BOOK_USAGE: &str = "usage: ladybug book build <bpgn file> <book> [--plies <count>] [--min-games <count>] | ladybug book probe <book> [fen]";
+
+// Builds an opening book from a BPGN archive, or lists the book moves of a position,
+// the start position by default
+fn book(args: &[String], config: &BuildConfig, format: Format) -> CliResult {
+    match args {
+        [command, archive, output] if command == "build" => {
+            let text = std::fs::read_to_string(archive)?;
+            let mut games = Vec::new();
+            let mut skipped = 0;
+            for game in bpgn::parse_archive(&text, 0) {
+                match game {
+                    Ok(game) => games.push(game),
+                    Err(err) => {
+                        eprintln!("skipping {}", err);
+                        skipped += 1;
+                    }
+                }
+            }
+            let book = OpeningBook::from_games(&games, config);
+            book.save(Path::new(output))?;
+            match format {
+                Format::Human => println!(
+                    "{} positions from {} games, {} skipped",
+                    book.len(),
+                    games.len(),
+                    skipped
+                ),
+                Format::Json => println!(
+                    "{}",
+                    JsonObject::document("book_build")
+                        .number("positions", book.len())
+                        .number("games", games.len())
+                        .number("skipped", skipped)
+                ),
+            }
+        }
+        [command, path, fen @ ..] if command == "probe" => {
+            let book = OpeningBook::load(Path::new(path))?;
+            let position = match fen {
+                [] => Bughouse::default(),
+                fen => parse_fen(&fen.join(" "))?,
+            };
+            let moves = book.moves(&position);
+            match format {
+                Format::Human => {
+                    for (m, entry) in &moves {
+                        let win_rate = match entry.win_rate() {
+                            Some(rate) => format!("{:.1}%", rate * 100f32),
+                            None => "-".to_string(),
+                        };
+                        println!(
+                            "{:<6} weight {:<5} games {:<5} score {}",
+                            Uci::from_standard(m).to_string(),
+                            entry.weight,
+                            entry.games,
+                            win_rate
+                        );
+                    }
+                }
+                Format::Json => {
+                    let moves = moves.iter().map(|(m, entry)| {
+                        let mut object = JsonObject::new()
+                            .string("move", &Uci::from_standard(m).to_string())
+                            .number("weight", entry.weight)
+                            .number("games", entry.games);
+                        if let Some(rate) = entry.win_rate() {
+                            object = object.number("win_rate", rate);
+                        }
+                        object.to_string()
+                    });
+                    println!(
+                        "{}",
+                        JsonObject::document("book_moves").raw("moves", json_array(moves))
+                    );
+                }
+            }
+        }
+        _ => return Err(BOOK_USAGE.into()),
+    }
+    Ok(())
+}
+
+const INSIGHTS_USAGE: &str = "usage: ladybug insights <bpgn file> <player> [--nodes <per move>]";
+
+// Reports on the games `player` played in a BPGN archive, judging each of their moves
+// with searches of `nodes` playouts and counting the traps of `traps` they fell for
+fn insights(
+    archive: &Path,
+    player: &str,
+    nodes: u64,
+    traps: &TrapBook,
+    format: Format,
+) -> CliResult {
+    let text = std::fs::read_to_string(archive)?;
+    let mut insights = Insights::new();
+    let mut analyzer = SearchAnalyzer::new(Arc::new(EvalParams::default()), nodes);
+    for game in bpgn::parse_archive(&text, 0) {
+        match game {
+            Ok(game) => {
+                insights.add_bpgn(&game, player, &mut analyzer);
+                insights.add_bpgn_traps(&game, player, traps);
+            }
+            Err(err) => eprintln!("skipping {}", err),
+        }
+    }
+    if insights.games == 0 {
+        return Err(format!("no games of {} in {}", player, archive.display()).into());
+    }
+    match format {
+        Format::Human => print!("{}", insights),
+        Format::Json => println!("{}", insights.to_json()),
+    }
+    Ok(())
+}
+
+// Lists where the engine keeps its files
+fn show_paths(paths: &AppPaths, format: Format) {
+    match format {
+        Format::Human => {
+            for (name, path) in paths.entries() {
+                println!("{:<10} {}", name, path.display());
+            }
+        }
+        Format::Json => {
+            let object = paths
+                .entries()
+                .into_iter()
+                .fold(JsonObject::document("paths"), |object, (name, path)| {
+                    object.string(name, &path.to_string_lossy())
+                });
+            println!("{}", object);
+        }
+    }
+}
+
+const REMOTE_USAGE: &str =
+    "usage: ladybug remote <address:port> [--config <file>] [--tokens <file>]";
+
+// Serves searches to `RemoteEngine` clients, such as a UCI front-end with the
+// `RemoteEngine` option, keeping one engine and its tree between searches. With a
+// tokens file only its clients are served, within their quotas
+fn remote(addr: &str, config: Option<&Path>, tokens: Option<&Path>) -> CliResult {
+    let config = match config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    let mut engine = config.engine();
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("serving searches on {}", listener.local_addr()?);
+    let search = |position: &Bughouse, time, cancel: &CancelToken| {
+        let control = SearchControl::new(SearchLimits::time(time), cancel.clone());
+        engine.analyse_with(position, &control).best
+    };
+    match tokens {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            let mut access = AccessControl::parse_clients(&text, Instant::now())?;
+            serve_remote_with_access(&listener, &mut access, search)?;
+        }
+        None => serve_remote(&listener, search)?,
+    }
+    Ok(())
+}
+
+// Runs a script file, stopping at the first failing command, or without one reads
+// commands from stdin, reporting failures and carrying on
+fn script(path: Option<&Path>, traps: &Path) -> CliResult {
+    let mut runner = ScriptRunner::new(io::stdout()).with_traps(TrapBook::open(traps)?);
+    match path {
+        Some(path) => runner.run(BufReader::new(File::open(path)?))?,
+        None => {
+            for line in io::stdin().lock().lines() {
+                if let Err(err) = runner.execute(&line?) {
+                    eprintln!("{}", err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const SESSION_USAGE: &str = "usage: ladybug session create <id> [fen] | ladybug session play <id> <moves> | ladybug session query <id> [--nodes <count>] | ladybug session destroy <id> | ladybug session list";
+
+// Manages the named analysis sessions kept in `dir`: starts one, plays moves in it,
+// searches its current position on for `nodes` more iterations, or ends it
+fn session(args: &[String], dir: &Path, nodes: u64, format: Format) -> CliResult {
+    let mut store = SessionStore::open(dir, Arc::new(EvalParams::default()))?;
+    let show = |id: &str, session: &Session| match format {
+        Format::Human => println!("{} {}", id, session.position().fen()),
+        Format::Json => {
+            let moves = session
+                .san_moves()
+                .iter()
+                .map(|san| json_string(&san.to_string()))
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                JsonObject::document("session")
+                    .string("id", id)
+                    .string("fen", &session.position().fen())
+                    .raw("moves", json_array(moves))
+            )
+        }
+    };
+    match args {
+        [command, id, fen @ ..] if command == "create" => {
+            let start = match fen {
+                [] => Bughouse::default(),
+                fen => parse_fen(&fen.join(" "))?,
+            };
+            show(id, store.create(id, start)?);
+        }
+        [command, id, moves @ ..] if command == "play" => {
+            let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+            show(id, store.play(id, &moves)?);
+        }
+        [command, id] if command == "query" => {
+            let control = SearchControl::new(SearchLimits::nodes(nodes), CancelToken::new());
+            let result = store.query(id, &control)?;
+            let iterations = store.analysis(id).map_or(0, LongAnalysis::iterations);
+            let best = result.best.map_or_else(
+                || Uci::Null.to_string(),
+                |m| Uci::from_standard(&m).to_string(),
+            );
+            match format {
+                Format::Human => println!(
+                    "iterations {} best {} win {:.1}%",
+                    iterations,
+                    best,
+                    result.win_probability * 100f32
+                ),
+                Format::Json => {
+                    let mut object = JsonObject::document("analysis")
+                        .number("iterations", iterations)
+                        .string("best", &best)
+                        .number("win_probability", result.win_probability);
+                    if let Some(moves) = result.mate {
+                        object = object.number("mate", moves);
+                    }
+                    println!("{}", object)
+                }
+            }
+        }
+        [command, id] if command == "destroy" => store.destroy(id)?,
+        [command] if command == "list" => {
+            let mut ids: Vec<&str> = store.ids().collect();
+            ids.sort_unstable();
+            match format {
+                Format::Human => ids.iter().for_each(|id| println!("{}", id)),
+                Format::Json => println!(
+                    "{}",
+                    JsonObject::document("sessions")
+                        .raw("ids", json_array(ids.iter().map(|id| json_string(id))))
+                ),
+            }
+        }
+        _ => return Err(SESSION_USAGE.into()),
+    }
+    Ok(())
+}
+
+const SVG_USAGE: &str = "usage: ladybug svg frames <bpgn file> <output dir> [game number] | ladybug svg position <fen> [--nodes <count>] [--flipped]";
+
+// Renders the `number`th game of a BPGN file, the first by default, into one SVG file
+// per ply showing both boards, pockets and clocks, or prints a position with the best
+// move of a search of `nodes` playouts and the likeliest drops marked
+fn svg(args: &[String], nodes: u64, flipped: bool, format: Format) -> CliResult {
+    match args {
+        [command, fen @ ..] if command == "position" && !fen.is_empty() => {
+            let position = parse_fen(&fen.join(" "))?;
+            let params = Arc::new(EvalParams::default());
+            let mut engine = Engine::new(Arc::clone(&params));
+            let control = SearchControl::new(SearchLimits::nodes(nodes), CancelToken::new());
+            let options = svg::SvgOptions {
+                flipped,
+                best_move: engine.analyse_with(&position, &control).best,
+                drop_heat: svg::drop_heat(&position, &EvalPriors { params }),
+            };
+            print!("{}", svg::render_svg(&position, &options));
+        }
+        [command, archive, dir, number @ ..] if command == "frames" => {
+            let number: usize = match number {
+                [] => 1,
+                [number] => number.parse().map_err(|_| SVG_USAGE)?,
+                _ => return Err(SVG_USAGE.into()),
+            };
+            let games = bpgn::parse(&std::fs::read_to_string(archive)?)?;
+            let game = number
+                .checked_sub(1)
+                .and_then(|index| games.get(index))
+                .ok_or_else(|| format!("{} has {} games", archive, games.len()))?;
+            let frames = svg::match_frames(game, game.initial_time());
+            let paths = svg::write_frames(&frames, Path::new(dir))?;
+            match format {
+                Format::Human => println!("{} frames in {}", paths.len(), dir),
+                Format::Json => println!(
+                    "{}",
+                    JsonObject::document("svg_frames")
+                        .number("frames", paths.len())
+                        .string("dir", dir)
+                ),
+            }
+        }
+        _ => return Err(SVG_USAGE.into()),
+    }
+    Ok(())
+}
+
+// Runs the internal consistency checks, failing if any of them does
+fn selfcheck(format: Format) -> CliResult {
+    let results = selfcheck::run();
+    match format {
+        Format::Human => {
+            for result in &results {
+                match &result.failure {
+                    None => println!("ok    {}", result.name),
+                    Some(failure) => println!("FAIL  {}: {}", result.name, failure),
+                }
+            }
+        }
+        Format::Json => println!("{}", selfcheck::results_json(&results)),
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} checks failed", failed, results.len()).into());
+    }
+    Ok(())
+}
+
+const DIFFTEST_USAGE: &str = "usage: ladybug difftest <engine command> [--positions <count>] [--depth <plies>] [--jobs <count>] [--seed <seed>]";
+
+// Compares our move generation with a reference engine's, failing on any mismatch
+fn run_difftest(
+    command: &str,
+    config: &DiffConfig,
+    jobs: usize,
+    format: Format,
+    shutdown: &CancelToken,
+) -> CliResult {
+    let references = (0..jobs.max(1))
+        .map(|_| {
+            UciReference::spawn(command)
+                .map(|reference| Box::new(reference) as Box<dyn ReferenceEngine + Send>)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mismatches = difftest::run(config, references, shutdown)?;
+    match format {
+        Format::Human => {
+            for mismatch in &mismatches {
+                println!("{}", mismatch);
+            }
+            println!(
+                "{} positions at depth {}, {} mismatches",
+                config.positions,
+                config.depth,
+                mismatches.len()
+            );
+        }
+        Format::Json => {
+            let object = JsonObject::document("difftest")
+                .number("positions", config.positions as f64)
+                .number("depth", f64::from(config.depth))
+                .raw(
+                    "mismatches",
+                    json_array(
+                        mismatches
+                            .iter()
+                            .map(|mismatch| mismatch.to_json().to_string()),
+                    ),
+                );
+            println!("{}", object);
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(format!("{} positions mismatch", mismatches.len()).into());
+    }
+    Ok(())
+}
+
+const MATCH_USAGE: &str = "usage: ladybug match <name[:key=value,...]> <name[:key=value,...]>... [--gauntlet] [--games <count>] [--concurrency <count>] [--archive <dir>] [--sprt <elo0>,<elo1>]";
+
+// Games still going after this many plies are drawn
+const MATCH_MAX_PLIES: usize = 400;
+
+// Plays the pairings between the contenders, until the SPRT decides if there is one.
+// Two contenders are compared by elo as well
+fn run_match(
+    arena: &Arena,
+    contenders: &[Contender],
+    pairings: Vec<Pairing>,
+    sprt: Option<Sprt>,
+    format: Format,
+) -> CliResult {
+    let standings = arena.run(
+        pairings,
+        |pairing| {
+            play_contenders(
+                &contenders[pairing.white],
+                &contenders[pairing.black],
+                u64::from(pairing.round),
+                MATCH_MAX_PLIES,
+            )
+        },
+        |pairing, game, standings| {
+            if format == Format::Human {
+                println!(
+                    "game {}: {} - {} {}",
+                    pairing.round,
+                    arena.names[pairing.white],
+                    arena.names[pairing.black],
+                    game.outcome
+                );
+            }
+            sprt.is_none_or(|sprt| sprt.verdict(standings.score(0)) == SprtVerdict::Continue)
+        },
+    )?;
+    let head_to_head = contenders.len() == 2;
+    let score = standings.score(0);
+    let llr = sprt.and_then(|sprt| Some((sprt, score.llr(sprt.elo0, sprt.elo1)?)));
+    let percent = |value: Option<f32>| value.map(|value| value * 100f32);
+    match format {
+        Format::Human => {
+            print!("{}", standings);
+            if head_to_head {
+                let elo = match (score.elo(), score.elo_margin()) {
+                    (Some(elo), Some(margin)) => format!("{:+.1} +/- {:.1}", elo, margin),
+                    (Some(elo), None) => format!("{:+.1}", elo),
+                    _ => "unknown".to_string(),
+                };
+                let los = match percent(score.los()) {
+                    Some(los) => format!("{:.1}%", los),
+                    None => "unknown".to_string(),
+                };
+                println!(
+                    "{} vs {}: elo {}, los {}",
+                    arena.names[0], arena.names[1], elo, los
+                );
+            }
+            if let Some((sprt, llr)) = llr {
+                let (lower, upper) = sprt.bounds();
+                let verdict = match sprt.verdict(score) {
+                    SprtVerdict::H0 => "H0 accepted",
+                    SprtVerdict::H1 => "H1 accepted",
+                    SprtVerdict::Continue => "undecided",
+                };
+                println!(
+                    "sprt [{}, {}]: llr {:.2} ({:.2}, {:.2}), {}",
+                    sprt.elo0, sprt.elo1, llr, lower, upper, verdict
+                );
+            }
+        }
+        Format::Json => {
+            let ranking = standings.ranking().into_iter().map(|(name, score)| {
+                JsonObject::new()
+                    .string("name", name)
+                    .number("points", f64::from(score.points()))
+                    .number("wins", f64::from(score.wins))
+                    .number("draws", f64::from(score.draws))
+                    .number("losses", f64::from(score.losses))
+                    .to_string()
+            });
+            let mut object = JsonObject::document("match").raw("standings", json_array(ranking));
+            if head_to_head {
+                object = object
+                    .string("first", &arena.names[0])
+                    .string("second", &arena.names[1])
+                    .number("wins", f64::from(score.wins))
+                    .number("draws", f64::from(score.draws))
+                    .number("losses", f64::from(score.losses));
+                if let Some(elo) = score.elo() {
+                    object = object.number("elo", f64::from(elo));
+                }
+                if let Some(margin) = score.elo_margin() {
+                    object = object.number("elo_margin", f64::from(margin));
+                }
+                if let Some(los) = score.los() {
+                    object = object.number("los", f64::from(los));
+                }
+            }
+            if let Some((_, llr)) = llr {
+                object = object.number("llr", f64::from(llr));
+            }
+            println!("{}", object);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "nn"))]
+const SELFPLAY_USAGE: &str = "usage: ladybug selfplay <output file> [--games <count>] [--nodes <per move>] [--dirichlet <alpha>] [--temperature <t>] [--temperature-plies <plies>] [--seed <seed>] [--resign <expected score>]";
+#[cfg(feature = "nn")]
+const SELFPLAY_USAGE: &str = "usage: ladybug selfplay <output file> [--games <count>] [--nodes <per move>] [--dirichlet <alpha>] [--temperature <t>] [--temperature-plies <plies>] [--seed <seed>] [--gumbel <moves>] [--resign <expected score>]";
+
+// Plays the engine against itself and writes the searches as training data
+fn selfplay(
+    output: &Path,
+    config: &SelfPlayConfig,
+    format: Format,
+    shutdown: &CancelToken,
+) -> CliResult {
+    let params = Arc::new(EvalParams::default());
+    let mut exporter =
+        TrainingExporter::new(ExportOptions::default(), &params, &config.search_options());
+    let mut results = [0u32; 3];
+    selfplay::run(config, params, &mut exporter, |game, played| {
+        let (result, slot) = match played.outcome {
+            Outcome::Decisive {
+                winner: Color::White,
+            } => ("1-0", 0),
+            Outcome::Decisive {
+                winner: Color::Black,
+            } => ("0-1", 2),
+            Outcome::Draw => ("1/2-1/2", 1),
+        };
+        results[slot] += 1;
+        if format == Format::Human {
+            println!(
+                "game {}: {} in {} plies",
+                game + 1,
+                result,
+                played.records.len()
+            );
+        }
+        // The games so far are written out below either way
+        !shutdown.is_cancelled()
+    });
+    write_atomic(output, exporter.to_text())?;
+    match format {
+        Format::Human => println!(
+            "+{} ={} -{}; {}",
+            results[0],
+            results[1],
+            results[2],
+            exporter.dedup_stats()
+        ),
+        Format::Json => {
+            let stats = exporter.dedup_stats();
+            let object = JsonObject::document("selfplay")
+                .number("white_wins", f64::from(results[0]))
+                .number("draws", f64::from(results[1]))
+                .number("black_wins", f64::from(results[2]))
+                .number("games", f64::from(stats.games))
+                .number("duplicate_games", f64::from(stats.duplicate_games))
+                .number("positions", f64::from(stats.positions));
+            println!("{}", object);
+        }
+    }
+    Ok(())
+}
+
+const COORDINATOR_USAGE: &str = "usage: ladybug coordinator <address:port> <output dir> [--jobs <count>] [--games <per job>] [--seed <seed>]";
+
+// Hands out self-play jobs with the default weights until workers have uploaded all
+// of them to `out_dir`
+fn coordinator(
+    addr: &str,
+    out_dir: &Path,
+    jobs: u64,
+    games: u32,
+    seed: u64,
+    format: Format,
+) -> CliResult {
+    let net_version = evaluator_id(&EvalParams::default());
+    // Each job plays its own run of seeds
+    let specs = (0..jobs)
+        .map(|id| JobSpec {
+            id,
+            net_version: net_version.clone(),
+            games,
+            seed: seed.wrapping_add(id.wrapping_mul(u64::from(games))),
+        })
+        .collect();
+    let listener = TcpListener::bind(addr)?;
+    Coordinator::new(specs, out_dir.to_path_buf()).serve(&listener)?;
+    match format {
+        Format::Human => println!("{} jobs uploaded to {}", jobs, out_dir.display()),
+        Format::Json => println!(
+            "{}",
+            JsonObject::document("cluster")
+                .number("jobs", jobs)
+                .string("output", &out_dir.to_string_lossy())
+        ),
+    }
+    Ok(())
+}
+
+const WORKER_USAGE: &str =
+    "usage: ladybug worker <address:port> [--name <name>] [--nodes <per move>]";
+
+// Plays the self-play jobs of a coordinator until there are none left, refusing jobs
+// for other weights than ours
+fn worker(addr: &str, name: &str, nodes: u64, format: Format) -> CliResult {
+    let params = Arc::new(EvalParams::default());
+    let net_version = evaluator_id(&params);
+    let completed = run_worker(addr, name, |job| {
+        if job.net_version != net_version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "job {} is for weights {}, this worker has {}",
+                    job.id, job.net_version, net_version
+                ),
+            ));
+        }
+        let config = SelfPlayConfig {
+            games: job.games,
+            nodes,
+            seed: job.seed,
+            ..SelfPlayConfig::default()
+        };
+        let mut exporter =
+            TrainingExporter::new(ExportOptions::default(), &params, &config.search_options());
+        selfplay::run(&config, params.clone(), &mut exporter, |_, _| true);
+        Ok(exporter.to_text().into_bytes())
+    })?;
+    match format {
+        Format::Human => println!("{} jobs completed", completed),
+        Format::Json => println!(
+            "{}",
+            JsonObject::document("worker").number("completed", completed)
+        ),
+    }
+    Ok(())
+}
+
+const CALIBRATE_USAGE: &str = "usage: ladybug calibrate <file> [--bins <count>]";
+
+// Reports how well the win probabilities in a file of predictions and results match
+fn calibrate(path: &Path, bins: usize, format: Format) -> CliResult {
+    let calibration = Calibration::read(BufReader::new(File::open(path)?), bins)?;
+    match format {
+        Format::Human => print!("{}", calibration),
+        Format::Json => println!("{}", calibration.to_json()),
+    }
+    Ok(())
+}
+
+// Checks a file of FENs, listing the invalid ones with `verbose`
+fn validate(path: &Path, verbose: bool, format: Format) -> CliResult {
+    let report = validate_fens(BufReader::new(File::open(path)?))?;
+    if format == Format::Json {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+    if verbose {
+        for (line, fen, problem) in &report.invalid {
+            println!("{}: {}: {}", line, fen, problem);
+        }
+    }
+    print!("{}", report);
+    Ok(())
+}
+
+// Checks an archive of single-board records, listing the games that can't be real
+// bughouse games
+fn check_records(path: &Path, format: Format) -> CliResult {
+    let report = validate_records(&std::fs::read_to_string(path)?);
+    if format == Format::Json {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+    for (game, reason) in &report.corrupt {
+        println!("game {}: {}", game + 1, reason);
+    }
+    print!("{}", report);
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+const WATCH_USAGE: &str =
+    "usage: ladybug watch [--nodes <per move>] [--clock <seconds>] [--objective <blend>]";
+
+// Plays a bughouse game between four copies of the engine on the terminal dashboard,
+// each weighing both boards by `objective` if given
+#[cfg(feature = "tui")]
+fn watch(nodes: u64, clock: Duration, objective: Option<Blend>) -> CliResult {
+    use ladybug::board::BughouseGame;
+    use ladybug::dashboard::Dashboard;
+    use ladybug::engine::Engine;
+    use ladybug::eval_bar::{EvalBarConfig, EvalStream};
+    use ladybug::seats::{play_seated_with, EngineAgent, Seating, TeamAgent, SEATS};
+    use ladybug::team::TeamObjective;
+    use std::sync::mpsc;
+
+    let params = Arc::new(EvalParams::default());
+    let mut agents = SEATS.map(|seat| {
+        let mut agent = EngineAgent::new(
+            &format!("ladybug {}", seat),
+            Engine::new(params.clone()),
+            SearchLimits::nodes(nodes),
+        );
+        agent.objective = objective.map(|blend| Box::new(blend) as Box<dyn TeamObjective>);
+        Box::new(agent) as Box<dyn TeamAgent>
+    });
+    let mut dashboard = Dashboard::new(Seating::of(&agents));
+    let (sink, updates) = mpsc::channel();
+    let evals = EvalStream::start(params, EvalBarConfig::default(), sink);
+    let mut game = BughouseGame::new(clock);
+    let stdout = io::stdout();
+    play_seated_with(&mut game, &mut agents, |game, seat, agent| {
+        evals.update(seat.board, game.board(seat.board));
+        for update in updates.try_iter() {
+            dashboard.eval(update);
+        }
+        dashboard.observe(seat, agent);
+        // A failed redraw is not worth stopping the game for
+        let _ = dashboard.draw(&mut stdout.lock(), game);
+    })?;
+    dashboard.draw(&mut stdout.lock(), &game)?;
+    Ok(())
+}
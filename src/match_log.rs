@@ -0,0 +1,323 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Material, Move, Outcome, Piece, Position, Role, Setup};
+
+use crate::board::{parse_fen, Bughouse, FenError, IllegalMove};
+
+#[derive(Debug)]
+pub enum MatchLogError {
+    Io(io::Error),
+    /// A log file that is already there when creating one
+    AlreadyExists(String),
+    /// A log whose start position doesn't read back
+    Fen(FenError),
+    /// An event that can't be read, or can't happen in the state it is applied to
+    InvalidEvent(String),
+    IllegalMove(IllegalMove),
+}
+
+impl fmt::Display for MatchLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchLogError::Io(err) => write!(f, "match log error: {}", err),
+            MatchLogError::AlreadyExists(path) => write!(f, "match log already exists: {}", path),
+            MatchLogError::Fen(err) => write!(f, "invalid match log fen: {}", err),
+            MatchLogError::InvalidEvent(line) => write!(f, "invalid match event: {}", line),
+            MatchLogError::IllegalMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MatchLogError {}
+
+impl From<io::Error> for MatchLogError {
+    fn from(err: io::Error) -> Self {
+        MatchLogError::Io(err)
+    }
+}
+
+impl From<FenError> for MatchLogError {
+    fn from(err: FenError) -> Self {
+        MatchLogError::Fen(err)
+    }
+}
+
+impl From<IllegalMove> for MatchLogError {
+    fn from(err: IllegalMove) -> Self {
+        MatchLogError::IllegalMove(err)
+    }
+}
+
+/// Something that happened during a match. Drops are moves like any other.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatchEvent {
+    /// A move by the side to move and the time it took
+    Move { m: Move, elapsed: Duration },
+    /// A piece passed to `color` by its partner on the other board
+    Transfer { color: Color, role: Role },
+    /// Authoritative clock readings, e.g. from the server
+    Clock(ByColor<Duration>),
+    /// Chat or a system message
+    Message { from: String, text: String },
+    /// Result decided by the arbiter rather than on the board: flag fall, resignation,
+    /// adjudication
+    Result(Outcome),
+}
+
+/// The state of a match after some of its events.
+#[derive(Clone, Debug)]
+pub struct MatchState {
+    pub position: Bughouse,
+    pub clocks: ByColor<Duration>,
+    pub messages: Vec<(String, String)>,
+    pub outcome: Option<Outcome>,
+}
+
+impl MatchState {
+    pub fn new(position: Bughouse, clocks: ByColor<Duration>) -> MatchState {
+        MatchState {
+            position,
+            clocks,
+            messages: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    pub(crate) fn apply(&mut self, event: &MatchEvent) -> Result<(), MatchLogError> {
+        match event {
+            MatchEvent::Move { m, elapsed } => {
+                if self.outcome.is_some() || !self.position.is_legal(m) {
+                    return Err(IllegalMove::of(m).into());
+                }
+                let clock = self.clocks.by_color_mut(self.position.turn());
+                *clock = clock.saturating_sub(*elapsed);
+                self.position.play_unchecked(m);
+                self.outcome = self.position.outcome();
+            }
+            MatchEvent::Transfer { color, role } => {
+                let mut material = Material::new();
+                *material.by_piece_mut(role.of(*color)) += 1;
+                self.position = self.position.clone().add_material(material);
+            }
+            MatchEvent::Clock(clocks) => self.clocks = clocks.clone(),
+            MatchEvent::Message { from, text } => {
+                // Both have to fit on one line of the log, split at the first space
+                if from.is_empty() || from.contains(char::is_whitespace) || text.contains('\n') {
+                    return Err(MatchLogError::InvalidEvent(format_event(event)));
+                }
+                self.messages.push((from.clone(), text.clone()));
+            }
+            MatchEvent::Result(outcome) => {
+                if self.outcome.is_some() {
+                    return Err(MatchLogError::InvalidEvent(format_event(event)));
+                }
+                self.outcome = Some(*outcome);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append-only record of a match: the start position and clocks, then every event in
+/// order. Any earlier state can be rebuilt with [`MatchLog::replay`], which is what
+/// spectators joining late and game replays are built on.
+///
+/// When backed by a file, each event is appended as one line as soon as it is pushed,
+/// so a crash loses at most the event being written.
+#[derive(Debug)]
+pub struct MatchLog {
+    start: MatchState,
+    events: Vec<MatchEvent>,
+    current: MatchState,
+    file: Option<File>,
+}
+
+impl MatchLog {
+    pub fn new(start: Bughouse, clocks: ByColor<Duration>) -> MatchLog {
+        let start = MatchState::new(start, clocks);
+        MatchLog {
+            current: start.clone(),
+            start,
+            events: Vec::new(),
+            file: None,
+        }
+    }
+
+    /// Starts a new log at `path`, which must not exist yet.
+    pub fn create(
+        path: &Path,
+        start: Bughouse,
+        clocks: ByColor<Duration>,
+    ) -> Result<MatchLog, MatchLogError> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => {
+                    MatchLogError::AlreadyExists(path.display().to_string())
+                }
+                _ => err.into(),
+            })?;
+        file.write_all(
+            format!(
+                "start {} {} {}\n",
+                clocks.white.as_millis(),
+                clocks.black.as_millis(),
+                start.fen()
+            )
+            .as_bytes(),
+        )?;
+        let mut log = MatchLog::new(start, clocks);
+        log.file = Some(file);
+        Ok(log)
+    }
+
+    /// Opens the log at `path` to read it and append to it.
+    pub fn open(path: &Path) -> Result<MatchLog, MatchLogError> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or("");
+        let invalid = || MatchLogError::InvalidEvent(first.to_string());
+        let mut fields = first
+            .strip_prefix("start ")
+            .ok_or_else(invalid)?
+            .splitn(3, ' ');
+        let mut millis = || {
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .map(Duration::from_millis)
+                .ok_or_else(invalid)
+        };
+        let clocks = ByColor {
+            white: millis()?,
+            black: millis()?,
+        };
+        let start = parse_fen(fields.next().unwrap_or(""))?;
+        let mut log = MatchLog::new(start, clocks);
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let event = parse_event(&log.current.position, line)?;
+            log.current.apply(&event)?;
+            log.events.push(event);
+        }
+        log.file = Some(OpenOptions::new().append(true).open(path)?);
+        Ok(log)
+    }
+
+    pub fn events(&self) -> &[MatchEvent] {
+        &self.events
+    }
+
+    /// The state after all events.
+    pub fn current(&self) -> &MatchState {
+        &self.current
+    }
+
+    /// Appends `event` if it can happen in the current state, e.g. moves must be legal.
+    pub fn push(&mut self, event: MatchEvent) -> Result<(), MatchLogError> {
+        let mut next = self.current.clone();
+        next.apply(&event)?;
+        if let Some(file) = &mut self.file {
+            file.write_all(format!("{}\n", format_event(&event)).as_bytes())?;
+        }
+        self.current = next;
+        self.events.push(event);
+        Ok(())
+    }
+
+    /// The state after the first `index` events, `None` if there are fewer.
+    pub fn replay(&self, index: usize) -> Option<MatchState> {
+        let events = self.events.get(..index)?;
+        let mut state = self.start.clone();
+        for event in events {
+            state.apply(event).expect("logged events replay");
+        }
+        Some(state)
+    }
+}
+
+fn format_event(event: &MatchEvent) -> String {
+    match event {
+        MatchEvent::Move { m, elapsed } => {
+            format!("move {} {}", Uci::from_standard(m), elapsed.as_millis())
+        }
+        MatchEvent::Transfer { color, role } => {
+            format!("transfer {}", role.of(*color).char())
+        }
+        MatchEvent::Clock(clocks) => format!(
+            "clock {} {}",
+            clocks.white.as_millis(),
+            clocks.black.as_millis()
+        ),
+        MatchEvent::Message { from, text } => format!("message {} {}", from, text),
+        MatchEvent::Result(outcome) => format!("result {}", outcome),
+    }
+}
+
+fn parse_event(position: &Bughouse, line: &str) -> Result<MatchEvent, MatchLogError> {
+    let invalid = || MatchLogError::InvalidEvent(line.to_string());
+    let (kind, rest) = line.split_once(' ').ok_or_else(invalid)?;
+    let millis = |field: Option<&str>| {
+        field
+            .and_then(|field| field.parse().ok())
+            .map(Duration::from_millis)
+            .ok_or_else(invalid)
+    };
+    match kind {
+        "move" => {
+            let mut fields = rest.split(' ');
+            let uci = fields.next().unwrap_or("");
+            let m = uci
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(position).ok())
+                .ok_or_else(|| IllegalMove(uci.to_string()))?;
+            Ok(MatchEvent::Move {
+                m,
+                elapsed: millis(fields.next())?,
+            })
+        }
+        "transfer" => {
+            let piece = rest
+                .chars()
+                .next()
+                .and_then(Piece::from_char)
+                .ok_or_else(invalid)?;
+            Ok(MatchEvent::Transfer {
+                color: piece.color,
+                role: piece.role,
+            })
+        }
+        "clock" => {
+            let mut fields = rest.split(' ');
+            Ok(MatchEvent::Clock(ByColor {
+                white: millis(fields.next())?,
+                black: millis(fields.next())?,
+            }))
+        }
+        "message" => {
+            let (from, text) = rest.split_once(' ').unwrap_or((rest, ""));
+            Ok(MatchEvent::Message {
+                from: from.to_string(),
+                text: text.to_string(),
+            })
+        }
+        "result" => match rest.trim() {
+            "1-0" => Ok(MatchEvent::Result(Outcome::Decisive {
+                winner: Color::White,
+            })),
+            "0-1" => Ok(MatchEvent::Result(Outcome::Decisive {
+                winner: Color::Black,
+            })),
+            "1/2-1/2" => Ok(MatchEvent::Result(Outcome::Draw)),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
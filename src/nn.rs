@@ -0,0 +1,348 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shakmaty::{Color, Move, Role, Setup, Square};
+
+use crate::board::Bughouse;
+use crate::paths::write_atomic;
+use crate::prior::PriorSource;
+
+const ROLES: [Role; 6] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+    Role::King,
+];
+
+// Roles that can be in a pocket
+const POCKET_ROLES: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+
+/// Planes of 64 squares in the input: one per piece type of the side to move, one per
+/// piece type of the opponent and one of promoted pieces.
+pub const PLANES: usize = 2 * ROLES.len() + 1;
+
+/// Length of the input vector of [`encode_position`].
+pub const INPUT_SIZE: usize = PLANES * 64 + 2 * POCKET_ROLES.len() + 1;
+
+/// Number of policy outputs, see [`move_index`].
+pub const POLICY_SIZE: usize = 64 * 64 + POCKET_ROLES.len() * 64;
+
+const MAGIC: &[u8; 4] = b"LBNN";
+const VERSION: u32 = 1;
+
+// Squares from the side to move's point of view, so the network only ever sees
+// positions with the side to move playing up the board
+fn orient(square: Square, turn: Color) -> usize {
+    match turn {
+        Color::White => usize::from(square),
+        Color::Black => usize::from(square) ^ 56,
+    }
+}
+
+fn pocket_slot(role: Role) -> usize {
+    match role {
+        Role::Pawn => 0,
+        Role::Knight => 1,
+        Role::Bishop => 2,
+        Role::Rook => 3,
+        Role::Queen => 4,
+        Role::King => panic!("kings are never in a pocket"),
+    }
+}
+
+/// The network input for `position`, from the point of view of the side to move:
+///
+/// - [`PLANES`] planes of 64 squares, holding 1 where the piece is: the pawns, knights,
+///   bishops, rooks, queens and king of the side to move, then those of the opponent,
+///   then promoted pieces of either side. Rank 1 is the side to move's back rank, so
+///   black positions are flipped vertically
+/// - the pocket counts of the side to move, pawns to queens, then the opponent's
+/// - 1 if white is to move, else 0
+///
+/// Training pipelines should encode with this function rather than a copy of it, so
+/// a network sees the same input in training and in play.
+pub fn encode_position(position: &Bughouse) -> Vec<f32> {
+    let mut input = vec![0f32; INPUT_SIZE];
+    let turn = position.turn();
+    let board = position.board();
+    for (side, color) in [turn, !turn].iter().copied().enumerate() {
+        for (index, &role) in ROLES.iter().enumerate() {
+            let plane = (side * ROLES.len() + index) * 64;
+            for square in board.by_piece(role.of(color)) {
+                input[plane + orient(square, turn)] = 1f32;
+            }
+        }
+    }
+    let promoted = (PLANES - 1) * 64;
+    for square in board.promoted() {
+        input[promoted + orient(square, turn)] = 1f32;
+    }
+    let pockets = PLANES * 64;
+    if let Some(material) = position.pockets() {
+        for (side, color) in [turn, !turn].iter().copied().enumerate() {
+            let pocket = material.by_color(color);
+            for (index, &role) in POCKET_ROLES.iter().enumerate() {
+                input[pockets + side * POCKET_ROLES.len() + index] =
+                    f32::from(pocket.by_role(role));
+            }
+        }
+    }
+    if turn == Color::White {
+        input[INPUT_SIZE - 1] = 1f32;
+    }
+    input
+}
+
+/// The policy output of `m` in `position`, squares oriented like in
+/// [`encode_position`]. Board moves are indexed by their from and to square, castling
+/// by the king's and rook's square, drops by the piece and target square after the
+/// 4096 board moves. Promotions share the index of the pawn move.
+pub fn move_index(position: &Bughouse, m: &Move) -> usize {
+    let turn = position.turn();
+    match *m {
+        Move::Normal { from, to, .. } | Move::EnPassant { from, to } => {
+            orient(from, turn) * 64 + orient(to, turn)
+        }
+        Move::Castle { king, rook } => orient(king, turn) * 64 + orient(rook, turn),
+        Move::Put { role, to } => 64 * 64 + pocket_slot(role) * 64 + orient(to, turn),
+    }
+}
+
+#[derive(Debug)]
+pub enum NnError {
+    Io(io::Error),
+    /// The file does not start like a network file
+    NotANetwork,
+    Version(u32),
+    /// The file holds a different number of weights than its header announces
+    Size {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for NnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NnError::Io(err) => write!(f, "network file: {}", err),
+            NnError::NotANetwork => write!(f, "not a network file"),
+            NnError::Version(version) => write!(f, "unsupported network version {}", version),
+            NnError::Size { expected, actual } => write!(
+                f,
+                "network file holds {} bytes of weights, expected {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NnError {}
+
+impl From<io::Error> for NnError {
+    fn from(err: io::Error) -> Self {
+        NnError::Io(err)
+    }
+}
+
+/// What the network makes of a position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkOutput {
+    /// Expected score of the side to move, from 0 for a loss to 1 for a win
+    pub value: f32,
+    /// Probability of each of the moves asked about, in their order
+    pub policy: Vec<f32>,
+}
+
+/// A small network with one hidden layer of ReLUs feeding a value head and a policy
+/// head. Inputs are mostly zero, so like NNUE the hidden layer only adds up the rows
+/// of the inputs that are set, and the policy head only computes the outputs of the
+/// legal moves.
+///
+/// The file format is the magic `LBNN`, then the version and the hidden layer size as
+/// little endian `u32`, then little endian `f32` weights: input weights by input,
+/// hidden biases, value weights, value bias, policy weights by move and policy biases.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    hidden: usize,
+    input_weights: Vec<f32>,
+    hidden_biases: Vec<f32>,
+    value_weights: Vec<f32>,
+    value_bias: f32,
+    policy_weights: Vec<f32>,
+    policy_biases: Vec<f32>,
+}
+
+impl Network {
+    /// A network with `hidden` hidden units and small random weights, the starting
+    /// point of a training run.
+    pub fn random(hidden: usize, seed: u64) -> Network {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut weights = |count: usize, fan_in: usize| -> Vec<f32> {
+            let scale = 1f32 / (fan_in as f32).sqrt();
+            (0..count).map(|_| rng.gen_range(-scale..scale)).collect()
+        };
+        Network {
+            hidden,
+            input_weights: weights(INPUT_SIZE * hidden, INPUT_SIZE),
+            hidden_biases: vec![0f32; hidden],
+            value_weights: weights(hidden, hidden),
+            value_bias: 0f32,
+            policy_weights: weights(POLICY_SIZE * hidden, hidden),
+            policy_biases: vec![0f32; POLICY_SIZE],
+        }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    pub fn load(path: &Path) -> Result<Network, NnError> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), NnError> {
+        write_atomic(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NnError> {
+        if bytes.len() < 12 || &bytes[..4] != MAGIC {
+            return Err(NnError::NotANetwork);
+        }
+        let header = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        let version = header(4);
+        if version != VERSION {
+            return Err(NnError::Version(version));
+        }
+        let hidden = header(8) as usize;
+        let expected = 4 * weight_count(hidden);
+        let body = &bytes[12..];
+        if body.len() != expected {
+            return Err(NnError::Size {
+                expected,
+                actual: body.len(),
+            });
+        }
+        let mut floats = body
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        let mut take = |count: usize| -> Vec<f32> { floats.by_ref().take(count).collect() };
+        let input_weights = take(INPUT_SIZE * hidden);
+        let hidden_biases = take(hidden);
+        let value_weights = take(hidden);
+        let value_bias = take(1)[0];
+        let policy_weights = take(POLICY_SIZE * hidden);
+        let policy_biases = take(POLICY_SIZE);
+        Ok(Network {
+            hidden,
+            input_weights,
+            hidden_biases,
+            value_weights,
+            value_bias,
+            policy_weights,
+            policy_biases,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + 4 * weight_count(self.hidden));
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+        let floats = self
+            .input_weights
+            .iter()
+            .chain(&self.hidden_biases)
+            .chain(&self.value_weights)
+            .chain(std::iter::once(&self.value_bias))
+            .chain(&self.policy_weights)
+            .chain(&self.policy_biases);
+        for float in floats {
+            bytes.extend_from_slice(&float.to_le_bytes());
+        }
+        bytes
+    }
+
+    // Activations of the hidden layer for an encoded position
+    fn hidden_layer(&self, input: &[f32]) -> Vec<f32> {
+        let mut hidden = self.hidden_biases.clone();
+        for (index, &x) in input.iter().enumerate() {
+            if x == 0f32 {
+                continue;
+            }
+            let row = &self.input_weights[index * self.hidden..(index + 1) * self.hidden];
+            for (unit, weight) in hidden.iter_mut().zip(row) {
+                *unit += x * weight;
+            }
+        }
+        hidden.iter_mut().for_each(|unit| *unit = unit.max(0f32));
+        hidden
+    }
+
+    fn value_of(&self, hidden: &[f32]) -> f32 {
+        let logit = self.value_bias + dot(&self.value_weights, hidden);
+        1f32 / (1f32 + (-logit).exp())
+    }
+
+    /// The expected score of the side to move, from 0 for a loss to 1 for a win.
+    pub fn value(&self, position: &Bughouse) -> f32 {
+        self.value_of(&self.hidden_layer(&encode_position(position)))
+    }
+
+    /// The value of `position` and the policy over `moves`, which must be legal there.
+    pub fn evaluate(&self, position: &Bughouse, moves: &[Move]) -> NetworkOutput {
+        let hidden = self.hidden_layer(&encode_position(position));
+        let logits: Vec<f32> = moves
+            .iter()
+            .map(|m| {
+                let index = move_index(position, m);
+                let row = &self.policy_weights[index * self.hidden..(index + 1) * self.hidden];
+                self.policy_biases[index] + dot(row, &hidden)
+            })
+            .collect();
+        let max = logits.iter().copied().fold(f32::MIN, f32::max);
+        let mut policy: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+        let total: f32 = policy.iter().sum();
+        if total > 0f32 {
+            policy.iter_mut().for_each(|p| *p /= total);
+        }
+        NetworkOutput {
+            value: self.value_of(&hidden),
+            policy,
+        }
+    }
+}
+
+fn weight_count(hidden: usize) -> usize {
+    INPUT_SIZE * hidden + hidden + hidden + 1 + POLICY_SIZE * hidden + POLICY_SIZE
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Priors from the policy head of a network.
+pub struct NetworkPriors {
+    pub network: Arc<Network>,
+}
+
+impl PriorSource for NetworkPriors {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        self.network.evaluate(position, moves).policy
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use shakmaty::{Color, Move, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::eval::EvalParams;
+
+// Weight of the newest move in the running average of the opponent's move time
+const MOVE_TIME_SMOOTHING: f32 = 0.3;
+
+/// What we have learned about the opponent's style in the current game, updated after
+/// each of their moves.
+#[derive(Clone, Debug)]
+pub struct OpponentModel {
+    color: Color,
+    moves: u32,
+    // Checks and captures
+    forcing_moves: u32,
+    drops: u32,
+    // Moves made with something in the pocket
+    drop_chances: u32,
+    move_time: Option<Duration>,
+}
+
+impl OpponentModel {
+    pub fn new(color: Color) -> OpponentModel {
+        OpponentModel {
+            color,
+            moves: 0,
+            forcing_moves: 0,
+            drops: 0,
+            drop_chances: 0,
+            move_time: None,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Learns from the opponent playing `m` in `before`, taking `time_used` off their
+    /// clock if the clock is known. Moves by the other side are ignored.
+    pub fn observe(&mut self, before: &Bughouse, m: &Move, time_used: Option<Duration>) {
+        if before.turn() != self.color {
+            return;
+        }
+        self.moves += 1;
+        let mut after = before.clone();
+        after.play_unchecked(m);
+        if m.is_capture() || after.is_check() {
+            self.forcing_moves += 1;
+        }
+        let pockets = before.pockets().expect("crazyhouse pockets");
+        if pockets.by_color(self.color).count() > 0 {
+            self.drop_chances += 1;
+            if let Move::Put { .. } = m {
+                self.drops += 1;
+            }
+        }
+        if let Some(used) = time_used {
+            self.move_time = Some(match self.move_time {
+                Some(average) => {
+                    average.mul_f32(1f32 - MOVE_TIME_SMOOTHING) + used.mul_f32(MOVE_TIME_SMOOTHING)
+                }
+                None => used,
+            });
+        }
+    }
+
+    /// Share of checks and captures among the opponent's moves, starting from an even
+    /// guess before anything was seen.
+    pub fn aggressiveness(&self) -> f32 {
+        (self.forcing_moves + 1) as f32 / (self.moves + 2) as f32
+    }
+
+    /// How often the opponent drops when they have something in hand, starting from an
+    /// even guess.
+    pub fn drop_tendency(&self) -> f32 {
+        (self.drops + 1) as f32 / (self.drop_chances + 2) as f32
+    }
+
+    /// Recent average time the opponent spends per move, if clock usage was observed.
+    pub fn average_move_time(&self) -> Option<Duration> {
+        self.move_time
+    }
+
+    /// `params` with the rollout policy leaning towards the opponent's style: forcing
+    /// moves get more weight against aggressive players and pieces in hand are worth
+    /// more against players who drop them quickly. An unknown opponent leaves `params`
+    /// unchanged.
+    pub fn bias(&self, params: &EvalParams) -> EvalParams {
+        let mut biased = params.clone();
+        // Both factors are 1 for the initial even guesses
+        let aggression = 2f32 * self.aggressiveness();
+        let drops = 0.5 + self.drop_tendency();
+        biased.policy.check *= aggression;
+        biased.policy.follow_up_check *= aggression;
+        biased.policy.recapture *= aggression;
+        for value in [
+            &mut biased.pocket.pawn,
+            &mut biased.pocket.knight,
+            &mut biased.pocket.bishop,
+            &mut biased.pocket.rook,
+            &mut biased.pocket.queen,
+        ] {
+            *value *= drops;
+        }
+        biased
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// Version of the JSON documents written by the subcommands. Bumped whenever a field
+/// changes meaning or goes away; new fields may appear without a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How a subcommand prints its results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Text meant to be read
+    #[default]
+    Human,
+    /// One JSON document per result, for scripts
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Human => "human",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// `text` as a JSON string literal.
+pub fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Builds a JSON object field by field, keeping the fields in insertion order.
+#[derive(Clone, Debug, Default)]
+pub struct JsonObject {
+    fields: Vec<(String, String)>,
+}
+
+impl JsonObject {
+    pub fn new() -> JsonObject {
+        JsonObject::default()
+    }
+
+    /// An object starting with the `schema_version` and `type` fields every document
+    /// written by a subcommand has.
+    pub fn document(kind: &str) -> JsonObject {
+        JsonObject::new()
+            .number("schema_version", SCHEMA_VERSION)
+            .string("type", kind)
+    }
+
+    pub fn string(self, key: &str, value: &str) -> JsonObject {
+        self.raw(key, json_string(value))
+    }
+
+    /// Adds a number. Non-finite floats, which JSON can't represent, become `null`.
+    pub fn number<T: Display>(self, key: &str, value: T) -> JsonObject {
+        let text = value.to_string();
+        let finite = text.parse::<f64>().is_ok_and(f64::is_finite);
+        self.raw(key, if finite { text } else { "null".to_string() })
+    }
+
+    pub fn boolean(self, key: &str, value: bool) -> JsonObject {
+        self.raw(key, value.to_string())
+    }
+
+    /// Adds a value that is already JSON, such as a nested object or an array.
+    pub fn raw(mut self, key: &str, json: String) -> JsonObject {
+        self.fields.push((key.to_string(), json));
+        self
+    }
+}
+
+impl Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (index, (key, value)) in self.fields.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{}", json_string(key), value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// JSON array of already serialized values.
+pub fn json_array<I: IntoIterator<Item = String>>(values: I) -> String {
+    format!("[{}]", values.into_iter().collect::<Vec<_>>().join(","))
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+use shakmaty::{Color, Material, Move, Position, Role, Setup};
+
+use crate::board::Bughouse;
+use crate::drops::Pockets;
+
+const ROLES: [Role; 5] = [
+    Role::Pawn,
+    Role::Knight,
+    Role::Bishop,
+    Role::Rook,
+    Role::Queen,
+];
+
+// Pieces of one color in two chess sets, the most a bughouse team can ever hold
+const TWO_SETS: [u8; 5] = [16, 4, 4, 4, 2];
+
+/// A piece passed between the recorded board and the partner board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    /// Ply of the recorded board after which the transfer was noticed
+    pub ply: usize,
+    /// The side of the recorded board that received or gave away the piece
+    pub color: Color,
+    pub role: Role,
+}
+
+/// The material flow between the boards implied by one board's record.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaterialFlow {
+    /// Pieces that showed up in a pocket, which must have been captured on the partner
+    /// board
+    pub received: Vec<Transfer>,
+    /// Pieces captured on the recorded board, which go to the capturer's partner
+    pub sent: Vec<Transfer>,
+}
+
+impl MaterialFlow {
+    /// Everything `color` received from the partner board over the game.
+    pub fn received_by(&self, color: Color) -> Material {
+        let mut material = Material::new();
+        for transfer in self.received.iter().filter(|t| t.color == color) {
+            *material.by_color_mut(color).by_role_mut(transfer.role) += 1;
+        }
+        material
+    }
+}
+
+/// Why a single-board record cannot be a real bughouse game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    IllegalMove {
+        ply: usize,
+    },
+    /// A piece left a pocket without being dropped
+    VanishedPiece {
+        ply: usize,
+        color: Color,
+        role: Role,
+    },
+    /// The holdings don't fit on the board at all
+    ImpossibleHoldings {
+        ply: usize,
+    },
+    /// A side holds more of a role than two chess sets contain
+    TooMuchMaterial {
+        ply: usize,
+        color: Color,
+        role: Role,
+    },
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Inconsistency::IllegalMove { ply } => write!(f, "illegal move at ply {}", ply),
+            Inconsistency::ImpossibleHoldings { ply } => {
+                write!(f, "impossible holdings at ply {}", ply)
+            }
+            Inconsistency::VanishedPiece { ply, color, role } => write!(
+                f,
+                "{} {} left the pocket without a drop at ply {}",
+                color.fold("white", "black"),
+                role.char(),
+                ply
+            ),
+            Inconsistency::TooMuchMaterial { ply, color, role } => write!(
+                f,
+                "{} has more than two sets of {} at ply {}",
+                color.fold("white", "black"),
+                role.char(),
+                ply
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Inconsistency {}
+
+/// Reconstructs which pieces must have come from and gone to the partner board, given
+/// the moves of one board and the holdings after each move where the record has them.
+/// Pocket gains not explained by the board are partner captures; captures on the
+/// board feed the partner instead of the capturer.
+pub fn infer_flow(
+    start: &Bughouse,
+    moves: &[Move],
+    holdings: &[Option<Material>],
+) -> Result<MaterialFlow, Inconsistency> {
+    let mut flow = MaterialFlow::default();
+    let mut position = start.clone();
+    for (index, m) in moves.iter().enumerate() {
+        let ply = index + 1;
+        let turn = position.turn();
+        if !position.is_legal(m) {
+            return Err(Inconsistency::IllegalMove { ply });
+        }
+        let before = position.pockets().cloned().unwrap_or_default();
+        if let Some(role) = Pockets::captured_role(position.board(), m) {
+            flow.sent.push(Transfer {
+                ply,
+                color: turn,
+                role,
+            });
+        }
+        position.play_unchecked(m);
+
+        // Only the drop itself changes the pockets on the board of a bughouse game
+        let mut expected = before;
+        if let Move::Put { role, .. } = *m {
+            *expected.by_color_mut(turn).by_role_mut(role) -= 1;
+        }
+        let recorded = match holdings.get(index).cloned().flatten() {
+            Some(recorded) => recorded,
+            None => expected.clone(),
+        };
+        for &color in &[Color::White, Color::Black] {
+            for &role in &ROLES {
+                let had = expected.by_color(color).by_role(role);
+                let has = recorded.by_color(color).by_role(role);
+                if has < had {
+                    return Err(Inconsistency::VanishedPiece { ply, color, role });
+                }
+                for _ in had..has {
+                    flow.received.push(Transfer { ply, color, role });
+                }
+            }
+        }
+        position = position
+            .with_pockets(recorded)
+            .map_err(|_| Inconsistency::ImpossibleHoldings { ply })?;
+        check_material(&position, ply)?;
+    }
+    Ok(flow)
+}
+
+// Counts promoted pieces as the pawns they were
+fn check_material(position: &Bughouse, ply: usize) -> Result<(), Inconsistency> {
+    let board = position.board();
+    let pockets = position.pockets().cloned().unwrap_or_default();
+    for &color in &[Color::White, Color::Black] {
+        let ours = board.by_color(color);
+        for (&role, &limit) in ROLES.iter().zip(&TWO_SETS) {
+            let mut count = (board.by_role(role) & ours & !board.promoted()).count();
+            if role == Role::Pawn {
+                count += (board.promoted() & ours).count();
+            }
+            count += usize::from(pockets.by_color(color).by_role(role));
+            if count > usize::from(limit) {
+                return Err(Inconsistency::TooMuchMaterial { ply, color, role });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP: &str = "ladybug";
+
+/// Operating system conventions for where applications keep their files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// The XDG base directories of Linux and the BSDs
+    Unix,
+    MacOs,
+    Windows,
+}
+
+impl Platform {
+    pub fn current() -> Platform {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Unix
+        }
+    }
+}
+
+/// Where the engine keeps its files: settings in `config`, files it can recreate in
+/// `cache` and everything else it persists in `data`. Setting `LADYBUG_HOME` puts all
+/// three below one directory, for portable installs and tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppPaths {
+    pub config: PathBuf,
+    pub cache: PathBuf,
+    pub data: PathBuf,
+}
+
+impl AppPaths {
+    /// The directories of this machine. Without a home directory to go by they fall
+    /// back to `.ladybug` in the working directory.
+    pub fn detect() -> AppPaths {
+        AppPaths::from_env(Platform::current(), |name| std::env::var_os(name))
+            .unwrap_or_else(|| AppPaths::below(Path::new(".ladybug")))
+    }
+
+    /// The directories for `platform`, reading environment variables through `var`.
+    /// `None` if the variables that locate the home directory are missing.
+    pub fn from_env<F>(platform: Platform, var: F) -> Option<AppPaths>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        // Empty variables count as unset, as the XDG specification asks
+        let var = |name: &str| {
+            var(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        if let Some(home) = var("LADYBUG_HOME") {
+            return Some(AppPaths::below(&home));
+        }
+        match platform {
+            Platform::Unix => {
+                let home = var("HOME");
+                let xdg = |name: &str, fallback: &str| {
+                    var(name).or_else(|| home.as_ref().map(|home| home.join(fallback)))
+                };
+                Some(AppPaths {
+                    config: xdg("XDG_CONFIG_HOME", ".config")?.join(APP),
+                    cache: xdg("XDG_CACHE_HOME", ".cache")?.join(APP),
+                    data: xdg("XDG_DATA_HOME", ".local/share")?.join(APP),
+                })
+            }
+            Platform::MacOs => {
+                let library = var("HOME")?.join("Library");
+                Some(AppPaths {
+                    config: library.join("Application Support").join(APP),
+                    cache: library.join("Caches").join(APP),
+                    data: library.join("Application Support").join(APP),
+                })
+            }
+            Platform::Windows => {
+                let roaming = var("APPDATA")?.join(APP);
+                let local =
+                    var("LOCALAPPDATA").map_or_else(|| roaming.clone(), |local| local.join(APP));
+                Some(AppPaths {
+                    config: roaming.clone(),
+                    cache: local.join("cache"),
+                    data: roaming,
+                })
+            }
+        }
+    }
+
+    fn below(home: &Path) -> AppPaths {
+        AppPaths {
+            config: home.join("config"),
+            cache: home.join("cache"),
+            data: home.join("data"),
+        }
+    }
+
+    /// The [`crate::artifacts::ArtifactStore`] of models, books and experience files.
+    pub fn artifacts(&self) -> PathBuf {
+        self.data.join("artifacts")
+    }
+
+    /// The [`crate::artifacts::Manifest`] naming the artifacts in use.
+    pub fn manifest(&self) -> PathBuf {
+        self.config.join("manifest.txt")
+    }
+
+    /// Opening books not yet in the artifact store.
+    pub fn books(&self) -> PathBuf {
+        self.data.join("books")
+    }
+
+    /// The deck of [`crate::drill::Drill`].
+    pub fn drill(&self) -> PathBuf {
+        self.data.join("drill.txt")
+    }
+
+    /// The tagged positions of [`crate::bookmarks::BookmarkStore`].
+    pub fn bookmarks(&self) -> PathBuf {
+        self.data.join("bookmarks.txt")
+    }
+
+    /// The opening traps of [`crate::traps::TrapBook`].
+    pub fn traps(&self) -> PathBuf {
+        self.data.join("traps.txt")
+    }
+
+    /// The SQLite position database.
+    pub fn database(&self) -> PathBuf {
+        self.data.join("positions.sqlite")
+    }
+
+    /// Named analysis sessions of [`crate::session::SessionStore`] and their trees.
+    pub fn sessions(&self) -> PathBuf {
+        self.data.join("sessions")
+    }
+
+    /// Checkpoints of long analyses and interrupted sessions.
+    pub fn autosaves(&self) -> PathBuf {
+        self.cache.join("autosave")
+    }
+
+    /// Every directory and file above by name, in the order `ladybug paths` lists them.
+    pub fn entries(&self) -> Vec<(&'static str, PathBuf)> {
+        vec![
+            ("config", self.config.clone()),
+            ("cache", self.cache.clone()),
+            ("data", self.data.clone()),
+            ("artifacts", self.artifacts()),
+            ("manifest", self.manifest()),
+            ("books", self.books()),
+            ("drill", self.drill()),
+            ("bookmarks", self.bookmarks()),
+            ("traps", self.traps()),
+            ("database", self.database()),
+            ("sessions", self.sessions()),
+            ("autosaves", self.autosaves()),
+        ]
+    }
+
+    /// Creates the directories, so the files in them can be written.
+    pub fn create(&self) -> io::Result<()> {
+        for dir in [
+            &self.config,
+            &self.cache,
+            &self.data,
+            &self.books(),
+            &self.autosaves(),
+        ] {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so a
+/// crash or an interrupted run never leaves a truncated file behind.
+pub fn write_atomic<C: AsRef<[u8]>>(path: &Path, contents: C) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,88 @@
+use shakmaty::{Move, Position, Role};
+
+use crate::board::Bughouse;
+use crate::eval::EvalParams;
+
+/// A move that loses to a premove the opponent has probably already entered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PremoveTrap {
+    pub premove: Move,
+    /// Material lost to the premove, infinite if it mates
+    pub loss: f32,
+}
+
+/// Opponent moves likely to be premoved in `position`, where we are to move and
+/// `last_move` was the opponent's: recaptures on the square they just captured on and
+/// captures of pieces worth at least the capturing piece. Premoves are entered before
+/// the opponent sees our reply, so they can only be based on the current position.
+pub fn likely_premoves(
+    position: &Bughouse,
+    last_move: Option<&Move>,
+    params: &EvalParams,
+) -> Vec<Move> {
+    let theirs = match position.swapped_turn() {
+        Some(theirs) => theirs,
+        None => return Vec::new(),
+    };
+    let recapture_square = last_move.filter(|m| m.is_capture()).map(Move::to);
+    theirs
+        .legal_moves()
+        .into_iter()
+        .filter(|m| match m.capture() {
+            Some(captured) => {
+                Some(m.to()) == recapture_square
+                    || value(params, captured) >= value(params, m.role())
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// Checks whether playing `m` runs into one of the [`likely_premoves`], i.e. the
+/// premove stays legal and wins material or mates. Returns the most costly such
+/// premove.
+pub fn premove_trap(
+    position: &Bughouse,
+    m: &Move,
+    last_move: Option<&Move>,
+    params: &EvalParams,
+) -> Option<PremoveTrap> {
+    let after = position.clone().play(m).ok()?;
+    likely_premoves(position, last_move, params)
+        .into_iter()
+        // A premove whose target moved away or changed is not played, and captures
+        // only lose material if the captured piece is still ours
+        .filter(|premove| after.is_legal(premove))
+        .map(|premove| {
+            let loss = capture_loss(&after, &premove, params);
+            PremoveTrap { premove, loss }
+        })
+        .filter(|trap| trap.loss > 0f32)
+        .max_by(|a, b| a.loss.total_cmp(&b.loss))
+}
+
+// Material the side not to move in `after` loses to the capture `premove`, counting a
+// recapture of the capturing piece
+pub(crate) fn capture_loss(after: &Bughouse, premove: &Move, params: &EvalParams) -> f32 {
+    let captured = premove.capture().expect("capturing move");
+    let mut reply = after.clone();
+    reply.play_unchecked(premove);
+    if reply.is_checkmate() {
+        return f32::INFINITY;
+    }
+    let defended = reply.legal_moves().iter().any(|m| m.to() == premove.to());
+    let gained_back = if defended {
+        value(params, premove.role())
+    } else {
+        0f32
+    };
+    value(params, captured) - gained_back
+}
+
+fn value(params: &EvalParams, role: Role) -> f32 {
+    match role {
+        // The king is never really traded
+        Role::King => f32::INFINITY,
+        role => params.board.by_role(role),
+    }
+}
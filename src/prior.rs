@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use shakmaty::attacks;
+use shakmaty::{Bitboard, Move, Position, Role, Setup, Square};
+
+use crate::board::Bughouse;
+use crate::eval::{evaluate, EvalParams, PriorWeights, MAX_PRIOR_GAIN};
+
+// c3-f6
+const CENTER: Bitboard = Bitboard(0x0000_3c3c_3c3c_0000);
+
+/// Coarse move categories used for domain-knowledge priors, strongest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveClass {
+    Check,
+    Capture,
+    CentralDrop,
+    EdgeDrop,
+    Quiet,
+}
+
+impl MoveClass {
+    pub fn of(position: &Bughouse, m: &Move) -> MoveClass {
+        let mut after = position.clone();
+        after.play_unchecked(m);
+        if after.is_check() {
+            MoveClass::Check
+        } else if m.is_capture() {
+            MoveClass::Capture
+        } else if let Move::Put { to, .. } = *m {
+            if is_central(to) {
+                MoveClass::CentralDrop
+            } else {
+                MoveClass::EdgeDrop
+            }
+        } else {
+            MoveClass::Quiet
+        }
+    }
+}
+
+fn is_central(square: Square) -> bool {
+    CENTER.contains(square)
+}
+
+// Piece values for ordering captures; the king only ever captures as the attacker,
+// and last
+fn ordering_value(role: Role) -> f32 {
+    match role {
+        Role::Pawn => 1.0,
+        Role::Knight | Role::Bishop => 3.0,
+        Role::Rook => 5.0,
+        Role::Queen => 9.0,
+        Role::King => 12.0,
+    }
+}
+
+/// The unnormalized prior of `m`: the weight of its [`MoveClass`], with captures
+/// ordered by MVV-LVA and drops against the enemy king's surroundings boosted.
+pub fn move_weight(weights: &PriorWeights, position: &Bughouse, m: &Move) -> f32 {
+    let mut weight = weights.by_class(MoveClass::of(position, m));
+    if let Some(victim) = m.capture() {
+        let ratio = ordering_value(victim) / ordering_value(m.role());
+        weight *= ratio.powf(weights.mvv_lva);
+    }
+    if let Move::Put { role, to } = *m {
+        let board = position.board();
+        if let Some(king) = board.king_of(!position.turn()) {
+            let zone = attacks::king_attacks(king).with(king);
+            let reach = attacks::attacks(to, role.of(position.turn()), board.occupied());
+            if (reach & zone).any() {
+                weight *= 1f32 + weights.king_drop;
+            }
+        }
+    }
+    weight
+}
+
+/// Assigns prior probabilities to the moves of a freshly expanded node.
+///
+/// The search only relies on this trait, so the hand-written [`PriorWeights`] table can
+/// be swapped for a learned policy.
+pub trait PriorSource {
+    /// Returns one prior per move, in the same order. Priors sum to 1.
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32>;
+}
+
+impl<P: PriorSource + ?Sized> PriorSource for Box<P> {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        (**self).priors(position, moves)
+    }
+}
+
+impl PriorSource for PriorWeights {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        let mut priors: Vec<f32> = moves
+            .iter()
+            .map(|m| move_weight(self, position, m))
+            .collect();
+        let total: f32 = priors.iter().sum();
+        if total > 0f32 {
+            priors.iter_mut().for_each(|prior| *prior /= total);
+        }
+        priors
+    }
+}
+
+/// The move class weights of [`PriorWeights`], each scaled by how much the move gains
+/// by the static evaluation, so expansion tries the moves that look best first.
+pub struct EvalPriors {
+    pub params: Arc<EvalParams>,
+}
+
+impl PriorSource for EvalPriors {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        let weights = &self.params.prior;
+        if weights.eval_gain == 0f32 {
+            return weights.priors(position, moves);
+        }
+        let before = evaluate(position, &self.params);
+        let mut priors: Vec<f32> = moves
+            .iter()
+            .map(|m| {
+                let mut after = position.clone();
+                after.play_unchecked(m);
+                // The evaluation after the move is from the opponent's point of view
+                let gain = (-evaluate(&after, &self.params) - before)
+                    .clamp(-MAX_PRIOR_GAIN, MAX_PRIOR_GAIN);
+                move_weight(weights, position, m) * (weights.eval_gain * gain).exp()
+            })
+            .collect();
+        let total: f32 = priors.iter().sum();
+        if total > 0f32 {
+            priors.iter_mut().for_each(|prior| *prior /= total);
+        }
+        priors
+    }
+}
@@ -0,0 +1,50 @@
+use std::io::{self, BufRead};
+
+/// GUI protocols the engine can speak.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Uci,
+    Xboard,
+}
+
+impl Protocol {
+    /// Recognizes the protocol from the first command a GUI sends: `uci` for UCI,
+    /// `xboard` or `protover` for xboard.
+    pub fn detect(line: &str) -> Option<Protocol> {
+        match line.split_whitespace().next()? {
+            "uci" => Some(Protocol::Uci),
+            "xboard" | "protover" => Some(Protocol::Xboard),
+            _ => None,
+        }
+    }
+}
+
+/// Reads lines until one identifies the protocol, returning it together with that line
+/// so the front-end can handle it as its first command. Blank and unrecognized lines
+/// before it are skipped. Returns `None` at the end of input.
+pub fn detect_protocol<R: BufRead>(input: &mut R) -> io::Result<Option<(Protocol, String)>> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Some(protocol) = Protocol::detect(&text) {
+            return Ok(Some((protocol, text.trim().to_string())));
+        }
+    }
+}
+
+/// The lines of `input`, for the front-ends to read commands from. Bytes that are not
+/// UTF-8 turn into `U+FFFD` rather than ending the session, so a garbled line is just
+/// an unknown command.
+pub fn command_lines<R: BufRead>(input: R) -> impl Iterator<Item = io::Result<String>> {
+    input.split(b'\n').map(|line| {
+        line.map(|bytes| {
+            String::from_utf8_lossy(&bytes)
+                .trim_end_matches('\r')
+                .to_string()
+        })
+    })
+}
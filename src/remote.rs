@@ -0,0 +1,234 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shakmaty::uci::Uci;
+use shakmaty::Move;
+
+use crate::access::AccessControl;
+use crate::board::parse_fen;
+use crate::board::Bughouse;
+use crate::cancel::CancelToken;
+
+// How often a waiting client checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Runs searches on a remote machine, so a front-end on a weak computer can use a
+/// strong one transparently.
+///
+/// The protocol is line based over one long-lived connection: `search <millis> <fen>`
+/// is answered with `bestmove <uci>`, `0000` if there is no move. `stop` makes the
+/// running search answer right away with the best move found so far. Servers with
+/// access control expect `auth <token>` first and answer refused searches with
+/// `error <reason>`.
+#[derive(Debug)]
+pub struct RemoteEngine {
+    addr: String,
+    /// Sent with `auth` on connecting, for servers with access control
+    pub token: Option<String>,
+    /// Connection attempts per search before giving up
+    pub retries: u32,
+    pub retry_delay: Duration,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl RemoteEngine {
+    pub fn new(addr: &str) -> RemoteEngine {
+        RemoteEngine {
+            addr: addr.to_string(),
+            token: None,
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            connection: None,
+        }
+    }
+
+    fn connect(&mut self) -> io::Result<&mut BufReader<TcpStream>> {
+        if self.connection.is_none() {
+            let mut stream = TcpStream::connect(&self.addr)?;
+            stream.set_read_timeout(Some(POLL_INTERVAL))?;
+            if let Some(token) = &self.token {
+                stream.write_all(format!("auth {}\n", token).as_bytes())?;
+            }
+            self.connection = Some(BufReader::new(stream));
+        }
+        Ok(self.connection.as_mut().expect("just connected"))
+    }
+
+    /// Searches `position` remotely for at most `time`, reconnecting if the connection
+    /// was lost. Cancelling `cancel` stops the remote search early.
+    pub fn search(
+        &mut self,
+        position: &Bughouse,
+        time: Duration,
+        cancel: &CancelToken,
+    ) -> io::Result<Option<Move>> {
+        let request = format!("search {} {}\n", time.as_millis(), position.fen());
+        let mut attempt = 0;
+        loop {
+            match self.try_search(&request, cancel) {
+                Ok(uci) => {
+                    return match uci.parse::<Uci>() {
+                        Ok(Uci::Null) => Ok(None),
+                        Ok(uci) => uci.to_move(position).map(Some).map_err(|_| {
+                            io::Error::new(io::ErrorKind::InvalidData, "illegal remote move")
+                        }),
+                        Err(_) => Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("bad remote move: {}", uci),
+                        )),
+                    };
+                }
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => return Err(err),
+                Err(err) => {
+                    self.connection = None;
+                    attempt += 1;
+                    if attempt > self.retries || cancel.is_cancelled() {
+                        return Err(err);
+                    }
+                    thread::sleep(self.retry_delay);
+                }
+            }
+        }
+    }
+
+    fn try_search(&mut self, request: &str, cancel: &CancelToken) -> io::Result<String> {
+        let connection = self.connect()?;
+        connection.get_mut().write_all(request.as_bytes())?;
+        let mut stop_sent = false;
+        let mut line = String::new();
+        loop {
+            if cancel.is_cancelled() && !stop_sent {
+                connection.get_mut().write_all(b"stop\n")?;
+                stop_sent = true;
+            }
+            match connection.read_line(&mut line) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) if line.ends_with('\n') => break,
+                Ok(_) => {}
+                Err(err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => return Err(err),
+            }
+        }
+        match line.trim().strip_prefix("bestmove ") {
+            Some(uci) => Ok(uci.to_string()),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected remote response: {}", line.trim()),
+            )),
+        }
+    }
+}
+
+/// Serves [`RemoteEngine`] clients one connection at a time, running each requested
+/// search through `search`, which must return early once its token is cancelled.
+pub fn serve_remote<F>(listener: &TcpListener, mut search: F) -> io::Result<()>
+where
+    F: FnMut(&Bughouse, Duration, &CancelToken) -> Option<Move>,
+{
+    for stream in listener.incoming() {
+        // A client going away must not take the server down
+        let _ = serve_connection(stream?, None, &mut search);
+    }
+    Ok(())
+}
+
+/// Like [`serve_remote`], but only for the clients of `access`: each search must be
+/// admitted, runs for no longer than the client's remaining engine time and is
+/// charged to the client afterwards.
+pub fn serve_remote_with_access<F>(
+    listener: &TcpListener,
+    access: &mut AccessControl,
+    mut search: F,
+) -> io::Result<()>
+where
+    F: FnMut(&Bughouse, Duration, &CancelToken) -> Option<Move>,
+{
+    for stream in listener.incoming() {
+        let _ = serve_connection(stream?, Some(&mut *access), &mut search);
+    }
+    Ok(())
+}
+
+fn serve_connection<F>(
+    stream: TcpStream,
+    mut access: Option<&mut AccessControl>,
+    search: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(&Bughouse, Duration, &CancelToken) -> Option<Move>,
+{
+    let mut writer = stream.try_clone()?;
+    let current = Arc::new(Mutex::new(CancelToken::new()));
+    let (sender, requests) = mpsc::channel();
+    // Reads on its own thread so `stop` arrives while a search is running
+    let reader_current = Arc::clone(&current);
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim() == "stop" {
+                reader_current.lock().expect("cancel token lock").cancel();
+            } else if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut token = String::new();
+    for line in requests {
+        if let Some(rest) = line.trim().strip_prefix("auth ") {
+            token = rest.trim().to_string();
+            continue;
+        }
+        let mut fields = line.trim().splitn(3, ' ');
+        let request = match (fields.next(), fields.next(), fields.next()) {
+            (Some("search"), Some(millis), Some(fen)) => millis
+                .parse()
+                .ok()
+                .zip(parse_fen(fen).ok())
+                .map(|(millis, position)| (Duration::from_millis(millis), position)),
+            _ => None,
+        };
+        let (time, position) = match request {
+            Some(request) => request,
+            None => {
+                writer.write_all(b"error malformed request\n")?;
+                continue;
+            }
+        };
+        let ticket = match access.as_deref_mut() {
+            Some(access) => match access.admit(&token, Instant::now()) {
+                Ok(ticket) => Some(ticket),
+                Err(err) => {
+                    writer.write_all(format!("error {}\n", err).as_bytes())?;
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let time = ticket
+            .as_ref()
+            .map_or(time, |ticket| time.min(ticket.engine_time));
+        let cancel = CancelToken::new();
+        *current.lock().expect("cancel token lock") = cancel.clone();
+        let started = Instant::now();
+        let best = search(&position, time, &cancel);
+        if let (Some(access), Some(ticket)) = (access.as_deref_mut(), ticket) {
+            access.finish(ticket, started.elapsed());
+        }
+        let uci = match best {
+            Some(m) => Uci::from_standard(&m),
+            None => Uci::Null,
+        };
+        writer.write_all(format!("bestmove {}\n", uci).as_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::{Color, Material, Move, Piece, Role, Setup};
+
+use crate::board::Bughouse;
+use crate::prior::PriorSource;
+
+/// A piece the partner expects to pass on soon, e.g. because they intend to capture a
+/// knight with their next move. Until it arrives the search may drop it tentatively.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reservation {
+    pub id: u64,
+    /// Who receives the piece
+    pub color: Color,
+    pub role: Role,
+    /// Factor between 0 and 1 applied to the priors of drops that need the piece,
+    /// reflecting how sure the partner is
+    pub discount: f32,
+}
+
+/// Messages team agents send each other about reservations, one per line:
+/// `reserve <id> <piece> <discount>` and `cancel <id>`, the piece as in FEN.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReservationMessage {
+    Reserve(Reservation),
+    Cancel(u64),
+}
+
+impl fmt::Display for ReservationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReservationMessage::Reserve(reservation) => write!(
+                f,
+                "reserve {} {} {}",
+                reservation.id,
+                reservation.role.of(reservation.color).char(),
+                reservation.discount
+            ),
+            ReservationMessage::Cancel(id) => write!(f, "cancel {}", id),
+        }
+    }
+}
+
+impl FromStr for ReservationMessage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ReservationMessage, String> {
+        let invalid = || format!("invalid reservation message: {}", s.trim());
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        match fields.as_slice() {
+            ["reserve", id, piece, discount] => {
+                let mut chars = piece.chars();
+                let piece = match (chars.next().and_then(Piece::from_char), chars.next()) {
+                    (Some(piece), None) if piece.role != Role::King => piece,
+                    _ => return Err(invalid()),
+                };
+                let discount: f32 = discount.parse().map_err(|_| invalid())?;
+                if !(0f32..=1f32).contains(&discount) {
+                    return Err(invalid());
+                }
+                Ok(ReservationMessage::Reserve(Reservation {
+                    id: id.parse().map_err(|_| invalid())?,
+                    color: piece.color,
+                    role: piece.role,
+                    discount,
+                }))
+            }
+            ["cancel", id] => Ok(ReservationMessage::Cancel(
+                id.parse().map_err(|_| invalid())?,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Reservations announced by the partner and not yet settled. Each one lasts until the
+/// partner's next move: the capture either happens and the piece arrives through the
+/// normal transfer, or it doesn't and the reservation is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct Reservations {
+    next_id: u64,
+    active: Vec<Reservation>,
+}
+
+impl Reservations {
+    pub fn new() -> Reservations {
+        Reservations::default()
+    }
+
+    /// Reserves `role` for `color` and returns the message announcing it.
+    pub fn reserve(&mut self, color: Color, role: Role, discount: f32) -> ReservationMessage {
+        let reservation = Reservation {
+            id: self.next_id,
+            color,
+            role,
+            discount: discount.clamp(0f32, 1f32),
+        };
+        self.next_id += 1;
+        self.active.push(reservation);
+        ReservationMessage::Reserve(reservation)
+    }
+
+    /// Applies a message from the partner's agent.
+    pub fn receive(&mut self, message: ReservationMessage) {
+        match message {
+            ReservationMessage::Reserve(reservation) => {
+                self.active.retain(|active| active.id != reservation.id);
+                self.active.push(reservation);
+            }
+            ReservationMessage::Cancel(id) => {
+                self.cancel(id);
+            }
+        }
+    }
+
+    /// Withdraws a reservation, returning whether it was active.
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let before = self.active.len();
+        self.active.retain(|active| active.id != id);
+        self.active.len() != before
+    }
+
+    /// Settles the reservations for `color` after the partner's move, which captured
+    /// `captured`. Returns the reservations that were not kept, one reservation of the
+    /// captured role counting as kept.
+    pub fn partner_moved(&mut self, color: Color, captured: Option<Role>) -> Vec<Reservation> {
+        let mut kept = captured;
+        let (settled, active) = self
+            .active
+            .drain(..)
+            .partition::<Vec<_>, _>(|reservation| reservation.color == color);
+        self.active = active;
+        settled
+            .into_iter()
+            .filter(|reservation| {
+                if kept == Some(reservation.role) {
+                    kept = None;
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect()
+    }
+
+    pub fn active(&self, color: Color) -> impl Iterator<Item = &Reservation> {
+        self.active
+            .iter()
+            .filter(move |reservation| reservation.color == color)
+    }
+
+    /// `position` with the reserved pieces of the side to move added to its pocket, for
+    /// searching as if they had arrived.
+    pub fn tentative(&self, position: &Bughouse) -> Bughouse {
+        let mut material = Material::new();
+        for reservation in self.active(position.turn()) {
+            *material.by_piece_mut(reservation.role.of(reservation.color)) += 1;
+        }
+        position.clone().add_material(material)
+    }
+
+    /// Priors for searching the [`Reservations::tentative`] position of `color`: drops
+    /// that need a reserved piece get the reservation's discount.
+    pub fn priors<P>(&self, inner: P, color: Color) -> ReservedPriors<P> {
+        ReservedPriors::new(inner, color, self.active(color).copied().collect())
+    }
+}
+
+/// Wraps another prior source, discounting drops of pieces that are only reserved.
+pub struct ReservedPriors<P> {
+    pub inner: P,
+    color: Color,
+    reserved: Vec<Reservation>,
+}
+
+impl<P> ReservedPriors<P> {
+    /// Discounts the drops of `color` that need one of the `reserved` pieces.
+    pub fn new(inner: P, color: Color, reserved: Vec<Reservation>) -> ReservedPriors<P> {
+        ReservedPriors {
+            inner,
+            color,
+            reserved,
+        }
+    }
+}
+
+impl<P: PriorSource> PriorSource for ReservedPriors<P> {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        let mut priors = self.inner.priors(position, moves);
+        if position.turn() != self.color {
+            return priors;
+        }
+        let pocket = position
+            .pockets()
+            .expect("crazyhouse pockets")
+            .by_color(self.color);
+        for (prior, m) in priors.iter_mut().zip(moves) {
+            if let Move::Put { role, .. } = *m {
+                let reserved: Vec<f32> = self
+                    .reserved
+                    .iter()
+                    .filter(|reservation| reservation.role == role)
+                    .map(|reservation| reservation.discount)
+                    .collect();
+                // Only when every piece of the role in hand is a reserved one
+                if !reserved.is_empty() && usize::from(pocket.by_role(role)) <= reserved.len() {
+                    *prior *= reserved.iter().copied().fold(0f32, f32::max);
+                }
+            }
+        }
+        let total: f32 = priors.iter().sum();
+        if total > 0f32 {
+            priors.iter_mut().for_each(|prior| *prior /= total);
+        }
+        priors
+    }
+}
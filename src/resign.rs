@@ -0,0 +1,124 @@
+use rand::Rng;
+use shakmaty::{Color, Outcome};
+
+/// Resign rule for self-play that keeps itself honest: a share of the games that would
+/// be resigned are played out instead, and the threshold is adjusted whenever those
+/// show the resigning side going on to draw or win too often.
+///
+/// Games against outside opponents only consult the threshold, see
+/// [`ResignPolicy::should_resign`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResignPolicy {
+    /// The side to move resigns when the search gives it less than this expected score
+    pub threshold: f32,
+    /// Share of games that ignore resignations so they can be audited
+    pub audit_rate: f32,
+    /// Acceptable share of audited resignations that would not have lost
+    pub max_false_positive_rate: f32,
+    audited: u32,
+    false_positives: u32,
+}
+
+// Audited games needed before the threshold is adjusted
+const MIN_AUDITS: u32 = 20;
+const THRESHOLD_STEP: f32 = 0.005;
+
+impl Default for ResignPolicy {
+    fn default() -> Self {
+        ResignPolicy {
+            threshold: 0.05,
+            audit_rate: 0.1,
+            max_false_positive_rate: 0.05,
+            audited: 0,
+            false_positives: 0,
+        }
+    }
+}
+
+impl ResignPolicy {
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_audit_rate(mut self, audit_rate: f32) -> Self {
+        self.audit_rate = audit_rate;
+        self
+    }
+
+    /// Starts tracking a new game, randomly picking it for auditing.
+    pub fn start_game<R: Rng>(&self, rng: &mut R) -> GameResign {
+        GameResign {
+            threshold: self.threshold,
+            audit: rng.gen::<f32>() < self.audit_rate,
+            would_have_resigned: None,
+        }
+    }
+
+    /// Whether the side to move resigns a game against an outside opponent, with an
+    /// expected score of `value`. Such games are never audited, as playing on would
+    /// cost real results.
+    pub fn should_resign(&self, value: f32) -> bool {
+        value < self.threshold
+    }
+
+    /// Takes the result of a finished game into account, adjusting the threshold once
+    /// enough audited games are in.
+    pub fn finish_game(&mut self, game: &GameResign, outcome: Outcome) {
+        let resigned = match (game.audit, game.would_have_resigned) {
+            (true, Some(color)) => color,
+            _ => return,
+        };
+        self.audited += 1;
+        if outcome.winner() != Some(!resigned) {
+            self.false_positives += 1;
+        }
+        if self.audited >= MIN_AUDITS {
+            if self.false_positive_rate() > Some(self.max_false_positive_rate) {
+                self.threshold = (self.threshold - THRESHOLD_STEP).max(0f32);
+            } else {
+                self.threshold += THRESHOLD_STEP;
+            }
+            self.audited = 0;
+            self.false_positives = 0;
+        }
+    }
+
+    /// Share of audited would-be resignations in the current window that did not lose.
+    pub fn false_positive_rate(&self) -> Option<f32> {
+        if self.audited == 0 {
+            None
+        } else {
+            Some(self.false_positives as f32 / self.audited as f32)
+        }
+    }
+}
+
+/// Resign bookkeeping of one self-play game, see [`ResignPolicy::start_game`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResign {
+    threshold: f32,
+    audit: bool,
+    would_have_resigned: Option<Color>,
+}
+
+impl GameResign {
+    pub fn is_audited(&self) -> bool {
+        self.audit
+    }
+
+    /// Called after each search with the expected score of the side to move. Returns
+    /// whether that side resigns; audited games never resign but remember who would
+    /// have.
+    pub fn should_resign(&mut self, turn: Color, value: f32) -> bool {
+        if value >= self.threshold {
+            return false;
+        }
+        if self.audit {
+            self.would_have_resigned.get_or_insert(turn);
+            false
+        } else {
+            true
+        }
+    }
+}
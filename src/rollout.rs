@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use rand::Rng;
+use shakmaty::{Color, Move, MoveList, Outcome, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::eval::EvalParams;
+use crate::premove::premove_trap;
+
+// Enough to see our own previous move and the opponent's reply to it
+const HISTORY_LEN: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayedMove {
+    pub m: Move,
+    pub gave_check: bool,
+}
+
+/// The last few moves leading to a position, most recent last.
+#[derive(Clone, Debug, Default)]
+pub struct MoveHistory {
+    moves: VecDeque<PlayedMove>,
+}
+
+impl MoveHistory {
+    pub fn push(&mut self, m: Move, gave_check: bool) {
+        if self.moves.len() == HISTORY_LEN {
+            self.moves.pop_front();
+        }
+        self.moves.push_back(PlayedMove { m, gave_check });
+    }
+
+    /// The most recent move, made by the opponent of the side to move
+    pub fn last(&self) -> Option<&PlayedMove> {
+        self.moves.back()
+    }
+
+    /// The move before that, made by the side to move
+    pub fn our_last(&self) -> Option<&PlayedMove> {
+        self.moves.iter().rev().nth(1)
+    }
+}
+
+/// Remembers, for each opponent move, a reply that won a previous rollout.
+#[derive(Clone, Debug)]
+pub struct CountermoveTable {
+    // Indexed by color of the replying side, role moved and destination square
+    table: Vec<Option<Move>>,
+}
+
+impl Default for CountermoveTable {
+    fn default() -> Self {
+        CountermoveTable {
+            table: vec![None; 2 * 6 * 64],
+        }
+    }
+}
+
+impl CountermoveTable {
+    fn index(color: Color, previous: &Move) -> usize {
+        let role = usize::from(previous.role()) - 1;
+        (color as usize * 6 + role) * 64 + usize::from(previous.to())
+    }
+
+    pub fn get(&self, color: Color, previous: &Move) -> Option<&Move> {
+        self.table[Self::index(color, previous)].as_ref()
+    }
+
+    pub fn set(&mut self, color: Color, previous: &Move, reply: Move) {
+        self.table[Self::index(color, previous)] = Some(reply);
+    }
+}
+
+// Playouts rarely get longer than this; deeper plies share the last slot
+const MAX_PLY: usize = 256;
+
+/// Two quiet moves per ply that were played by the winner of earlier playouts.
+#[derive(Clone, Debug)]
+pub struct KillerTable {
+    table: Vec<[Option<Move>; 2]>,
+}
+
+impl Default for KillerTable {
+    fn default() -> Self {
+        KillerTable {
+            table: vec![[None, None]; MAX_PLY],
+        }
+    }
+}
+
+impl KillerTable {
+    pub fn get(&self, ply: usize) -> &[Option<Move>; 2] {
+        &self.table[ply.min(MAX_PLY - 1)]
+    }
+
+    pub fn insert(&mut self, ply: usize, m: Move) {
+        let killers = &mut self.table[ply.min(MAX_PLY - 1)];
+        if killers[0].as_ref() != Some(&m) {
+            killers[1] = killers[0].replace(m);
+        }
+    }
+
+    fn contains(&self, ply: usize, m: &Move) -> bool {
+        self.get(ply)
+            .iter()
+            .any(|killer| killer.as_ref() == Some(m))
+    }
+}
+
+/// How often the move tables had a suggestion, and how often that suggestion was
+/// legal in the playout position.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RolloutStats {
+    pub countermove_probes: u64,
+    pub countermove_hits: u64,
+    pub killer_probes: u64,
+    pub killer_hits: u64,
+}
+
+impl RolloutStats {
+    pub fn countermove_hit_rate(&self) -> f32 {
+        self.countermove_hits as f32 / self.countermove_probes.max(1) as f32
+    }
+
+    pub fn killer_hit_rate(&self) -> f32 {
+        self.killer_hits as f32 / self.killer_probes.max(1) as f32
+    }
+}
+
+// One line, as sent in `info string rollouts`
+impl fmt::Display for RolloutStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "countermoves {}/{} ({:.0}%), killers {}/{} ({:.0}%)",
+            self.countermove_hits,
+            self.countermove_probes,
+            self.countermove_hit_rate() * 100f32,
+            self.killer_hits,
+            self.killer_probes,
+            self.killer_hit_rate() * 100f32
+        )
+    }
+}
+
+/// Chooses moves during playouts, preferring moves that follow up on recent play
+/// over uniformly random ones. The move tables are shared by all playouts of a search.
+#[derive(Clone, Debug, Default)]
+pub struct RolloutPolicy {
+    countermoves: CountermoveTable,
+    killers: KillerTable,
+    stats: RolloutStats,
+    /// While the opponent threatens to mate with a drop, leave out quiet moves that
+    /// don't stop it, so playouts defend instead of losing to the first drop
+    pub prune_drop_threats: bool,
+}
+
+// A quiet move after which the opponent still has a drop mate
+fn ignores_drop_threat(position: &Bughouse, m: &Move) -> bool {
+    if m.is_capture() {
+        return false;
+    }
+    let mut after = position.clone();
+    after.play_unchecked(m);
+    !after.is_check() && after.drop_mates(after.turn()).any()
+}
+
+impl RolloutPolicy {
+    pub fn stats(&self) -> &RolloutStats {
+        &self.stats
+    }
+
+    fn weight(
+        &self,
+        position: &Bughouse,
+        m: &Move,
+        ply: usize,
+        history: &MoveHistory,
+        params: &EvalParams,
+    ) -> f32 {
+        let mut weight = 1f32;
+        let gives_check = {
+            let mut after = position.clone();
+            after.play_unchecked(m);
+            after.is_check()
+        };
+        if gives_check {
+            weight += params.policy.check;
+            if history.our_last().is_some_and(|last| last.gave_check) {
+                weight += params.policy.follow_up_check;
+            }
+        }
+        if let Some(last) = history.last() {
+            if m.is_capture() && last.m.is_capture() && m.to() == last.m.to() {
+                weight += params.policy.recapture;
+            }
+            if self.countermoves.get(position.turn(), &last.m) == Some(m) {
+                weight += params.policy.countermove;
+            }
+        }
+        if self.killers.contains(ply, m) {
+            weight += params.policy.killer;
+        }
+        if params.policy.premove_trap > 0f32
+            && premove_trap(position, m, history.last().map(|last| &last.m), params).is_some()
+        {
+            weight /= 1f32 + params.policy.premove_trap;
+        }
+        weight
+    }
+
+    fn record_probes(
+        &mut self,
+        position: &Bughouse,
+        moves: &MoveList,
+        ply: usize,
+        history: &MoveHistory,
+    ) {
+        if let Some(countermove) = history
+            .last()
+            .and_then(|last| self.countermoves.get(position.turn(), &last.m))
+            .cloned()
+        {
+            self.stats.countermove_probes += 1;
+            if moves.contains(&countermove) {
+                self.stats.countermove_hits += 1;
+            }
+        }
+        let killers = self.killers.get(ply);
+        let probes = killers.iter().flatten().count() as u64;
+        let hits = killers
+            .iter()
+            .flatten()
+            .filter(|&killer| moves.contains(killer))
+            .count() as u64;
+        self.stats.killer_probes += probes;
+        self.stats.killer_hits += hits;
+    }
+
+    /// Picks one of `moves` at random, weighted by the policy bonuses. `ply` is the
+    /// distance from the search root. See [`RolloutPolicy::prune_drop_threats`].
+    pub fn choose<R: Rng>(
+        &mut self,
+        position: &Bughouse,
+        moves: &MoveList,
+        ply: usize,
+        history: &MoveHistory,
+        params: &EvalParams,
+        rng: &mut R,
+    ) -> Option<Move> {
+        self.record_probes(position, moves, ply, history);
+        let threatened = self.prune_drop_threats && position.drop_mates(!position.turn()).any();
+        let mut candidates: Vec<&Move> = moves
+            .iter()
+            .filter(|m| !threatened || !ignores_drop_threat(position, m))
+            .collect();
+        // Every move loses to a drop, so any of them will do
+        if candidates.is_empty() {
+            candidates = moves.iter().collect();
+        }
+        let weights: Vec<f32> = candidates
+            .iter()
+            .map(|m| self.weight(position, m, ply, history, params))
+            .collect();
+        let mut target = rng.gen::<f32>() * weights.iter().sum::<f32>();
+        for (m, weight) in candidates.iter().zip(weights) {
+            if target < weight {
+                return Some((*m).clone());
+            }
+            target -= weight;
+        }
+        candidates.last().map(|m| (*m).clone())
+    }
+
+    /// Learns countermoves and killers from a finished playout. `moves` holds, for every
+    /// ply starting at `first_ply`, the side that moved, the move it replied to and the
+    /// move itself.
+    pub fn learn(
+        &mut self,
+        first_ply: usize,
+        moves: &[(Color, Option<Move>, Move)],
+        outcome: Outcome,
+    ) {
+        if let Outcome::Decisive { winner } = outcome {
+            for (ply, (color, previous, m)) in (first_ply..).zip(moves) {
+                if *color != winner {
+                    continue;
+                }
+                if let Some(previous) = previous {
+                    self.countermoves.set(*color, previous, m.clone());
+                }
+                if !m.is_capture() {
+                    self.killers.insert(ply, m.clone());
+                }
+            }
+        }
+    }
+}
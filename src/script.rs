@@ -0,0 +1,319 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Position, Setup};
+
+use crate::board::parse_fen;
+use crate::board::Bughouse;
+use crate::engine::{parse_move_list, Analysis, Engine};
+use crate::eval::EvalParams;
+use crate::limits::SearchLimits;
+use crate::session::Session;
+use crate::traps::{Trap, TrapBook};
+
+/// A command of a script that failed, by line number from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Runs analysis scripts, one command per line. Lines starting with `#` are comments.
+///
+/// - `startpos` and `setfen <fen>` start over from a position
+/// - `play <move>...` plays moves in SAN or UCI notation, `--` or `0000` passing
+/// - `nodes <count>` and `seed <seed>` set up later searches
+/// - `analyze` searches the current position and prints the result
+/// - `explain [move]` prints why the move, by default the best of the last analysis,
+///   is good, see [`crate::explain`], and warns if it falls for a known trap
+/// - `trap <name> <bait> <refutation> [punishment...]` adds the trap set by `bait` to
+///   the trap book, punished by the moves given or else by the engine's reply
+/// - `export <file>` writes a script replaying the moves so far
+/// - `echo <text>` prints the text
+/// - `assert fen <fen>`, `assert turn <white|black>`, `assert legal <move>`,
+///   `assert illegal <move>`, `assert best <move>` and `assert outcome <result>` check
+///   the state, `best` against the last analysis and `outcome` taking `*` for a game
+///   still going
+///
+/// Searches are seeded, so a script prints the same every time it runs.
+pub struct ScriptRunner<W> {
+    session: Session,
+    engine: Engine,
+    nodes: u64,
+    last: Option<Analysis>,
+    traps: TrapBook,
+    output: W,
+}
+
+impl<W: Write> ScriptRunner<W> {
+    pub fn new(output: W) -> ScriptRunner<W> {
+        ScriptRunner {
+            session: Session::new(Bughouse::default()),
+            engine: Engine::new(Arc::new(EvalParams::default())),
+            nodes: 400,
+            last: None,
+            traps: TrapBook::in_memory(),
+            output,
+        }
+    }
+
+    /// Warns of and adds to the traps of `traps` instead of an empty book.
+    pub fn with_traps(mut self, traps: TrapBook) -> Self {
+        self.traps = traps;
+        self
+    }
+
+    pub fn traps(&self) -> &TrapBook {
+        &self.traps
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Runs every line of `input`, stopping at the first command that fails.
+    pub fn run<R: BufRead>(&mut self, input: R) -> Result<(), ScriptError> {
+        for (index, line) in input.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+            let line = line.map_err(|err| error(err.to_string()))?;
+            self.execute(&line).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one command.
+    pub fn execute(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.starts_with('#') {
+            return Ok(());
+        }
+        let (command, rest) = match line.split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => Ok(()),
+            "startpos" => {
+                self.reset(Bughouse::default());
+                Ok(())
+            }
+            "setfen" => {
+                let position = parse_fen(rest).map_err(|err| err.to_string())?;
+                self.reset(position);
+                Ok(())
+            }
+            "play" => {
+                for token in rest.split_whitespace() {
+                    self.session
+                        .play_notation(token)
+                        .map_err(|err| err.to_string())?;
+                }
+                self.last = None;
+                Ok(())
+            }
+            "nodes" => {
+                self.nodes = rest
+                    .parse()
+                    .map_err(|_| format!("invalid node count: {}", rest))?;
+                Ok(())
+            }
+            "seed" => {
+                let seed = rest
+                    .parse()
+                    .map_err(|_| format!("invalid seed: {}", rest))?;
+                self.engine.set_seed(seed);
+                Ok(())
+            }
+            "analyze" => self.analyze(),
+            "explain" => self.explain(rest),
+            "trap" => self.add_trap(rest),
+            "export" if !rest.is_empty() => {
+                std::fs::write(rest, self.replay_script()).map_err(|err| err.to_string())
+            }
+            "echo" => self.print(rest),
+            "assert" => self.check(rest),
+            _ => Err(format!("unknown command: {}", line)),
+        }
+    }
+
+    fn reset(&mut self, position: Bughouse) {
+        self.session = Session::new(position);
+        self.engine.clear_tree();
+        self.last = None;
+    }
+
+    fn parse_move(&self, token: &str) -> Result<Move, String> {
+        parse_move_list(self.session.position(), token)
+            .map_err(|err| err.to_string())?
+            .pop()
+            .ok_or_else(|| format!("no move given: {}", token))
+    }
+
+    fn print(&mut self, text: &str) -> Result<(), String> {
+        writeln!(self.output, "{}", text).map_err(|err: io::Error| err.to_string())
+    }
+
+    fn analyze(&mut self) -> Result<(), String> {
+        let position = self.session.position().clone();
+        let analysis = self
+            .engine
+            .analyse(&position, SearchLimits::nodes(self.nodes));
+        let best = match &analysis.best {
+            Some(m) => Uci::from_standard(m).to_string(),
+            None => "none".to_string(),
+        };
+        let text = format!(
+            "best {} win {:.3} nodes {}",
+            best, analysis.win_probability, analysis.nodes
+        );
+        self.last = Some(analysis);
+        self.print(&text)
+    }
+
+    fn explain(&mut self, token: &str) -> Result<(), String> {
+        let m = if token.is_empty() {
+            self.last
+                .as_ref()
+                .and_then(|analysis| analysis.best.clone())
+                .ok_or("no move to explain")?
+        } else {
+            self.parse_move(token)?
+        };
+        let explanation = self.engine.explain(self.session.position(), &m);
+        self.print(&explanation.to_string())?;
+        let warning = self
+            .traps
+            .fallen_for(self.session.position(), &m)
+            .map(|trap| {
+                format!(
+                    "falls for the {} trap, play {} instead",
+                    trap.name,
+                    Uci::from_standard(&trap.refutation)
+                )
+            });
+        match warning {
+            Some(warning) => self.print(&warning),
+            None => Ok(()),
+        }
+    }
+
+    fn add_trap(&mut self, args: &str) -> Result<(), String> {
+        let usage = "usage: trap <name> <bait> <refutation> [punishment...]";
+        let mut tokens = args.split_whitespace();
+        let (name, bait, refutation) = match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some(name), Some(bait), Some(refutation)) => (name, bait, refutation),
+            _ => return Err(usage.to_string()),
+        };
+        let position = self.session.position().clone();
+        let bait = self.parse_move(bait)?;
+        let refutation = self.parse_move(refutation)?;
+        let mut line = position.clone();
+        line.play_unchecked(&bait);
+        let mut punishment = Vec::new();
+        for token in tokens {
+            let m = parse_move_list(&line, token)
+                .map_err(|err| err.to_string())?
+                .pop()
+                .ok_or_else(|| format!("no move given: {}", token))?;
+            line.play_unchecked(&m);
+            punishment.push(m);
+        }
+        if punishment.is_empty() {
+            let reply = self
+                .engine
+                .analyse(&line, SearchLimits::nodes(self.nodes))
+                .best
+                .ok_or("the bait leaves no reply to punish it")?;
+            punishment.push(reply);
+        }
+        let trap = Trap::new(name, &position, bait, punishment, refutation)
+            .map_err(|err| err.to_string())?;
+        self.traps.add(trap).map_err(|err| err.to_string())
+    }
+
+    // A script that sets up the start position and replays the moves
+    fn replay_script(&self) -> String {
+        let moves: Vec<String> = self
+            .session
+            .moves()
+            .iter()
+            .map(|m| match m {
+                Some(m) => Uci::from_standard(m).to_string(),
+                None => Uci::Null.to_string(),
+            })
+            .collect();
+        let mut script = format!("setfen {}\n", self.session.start().fen());
+        if !moves.is_empty() {
+            script.push_str(&format!("play {}\n", moves.join(" ")));
+        }
+        script
+    }
+
+    fn check(&self, condition: &str) -> Result<(), String> {
+        let (what, expected) = condition.split_once(' ').unwrap_or((condition, ""));
+        let expected = expected.trim();
+        let position = self.session.position();
+        let failed =
+            |actual: String| Err(format!("expected {} {}, got {}", what, expected, actual));
+        match what {
+            "fen" => {
+                let fen = position.fen();
+                if fen == expected {
+                    Ok(())
+                } else {
+                    failed(fen)
+                }
+            }
+            "turn" => {
+                let turn = match position.turn() {
+                    Color::White => "white",
+                    Color::Black => "black",
+                };
+                if turn == expected {
+                    Ok(())
+                } else {
+                    failed(turn.to_string())
+                }
+            }
+            "legal" => self.parse_move(expected).map(|_| ()),
+            "illegal" => match self.parse_move(expected) {
+                Ok(_) => failed("a legal move".to_string()),
+                Err(_) => Ok(()),
+            },
+            "best" => {
+                let analysis = self.last.as_ref().ok_or("no analysis to check")?;
+                let m = self.parse_move(expected)?;
+                match &analysis.best {
+                    Some(best) if *best == m => Ok(()),
+                    Some(best) => failed(Uci::from_standard(best).to_string()),
+                    None => failed("none".to_string()),
+                }
+            }
+            "outcome" => {
+                let outcome = match position.outcome() {
+                    Some(outcome) => outcome.to_string(),
+                    None => "*".to_string(),
+                };
+                if outcome == expected {
+                    Ok(())
+                } else {
+                    failed(outcome)
+                }
+            }
+            _ => Err(format!("unknown assertion: {}", condition)),
+        }
+    }
+}
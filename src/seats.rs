@@ -0,0 +1,373 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use shakmaty::{Color, Move, Outcome, Setup};
+
+use crate::board::{Action, BoardId, BughouseGame, IllegalMove};
+use crate::engine::{Analysis, Engine};
+use crate::limits::SearchLimits;
+use crate::output::json_string;
+use crate::sitting::{sit_for_mate, sit_for_partner, PARTNER_DANGER};
+use crate::team::{best_for_team, TeamObjective};
+
+// Most searched moves a team objective chooses among
+const TEAM_CANDIDATES: usize = 4;
+
+/// One of the four places at a bughouse game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Seat {
+    pub board: BoardId,
+    pub color: Color,
+}
+
+pub const SEATS: [Seat; 4] = [
+    Seat {
+        board: BoardId::A,
+        color: Color::White,
+    },
+    Seat {
+        board: BoardId::A,
+        color: Color::Black,
+    },
+    Seat {
+        board: BoardId::B,
+        color: Color::White,
+    },
+    Seat {
+        board: BoardId::B,
+        color: Color::Black,
+    },
+];
+
+impl Seat {
+    // Position in `SEATS`
+    pub(crate) fn index(self) -> usize {
+        self.board.index() * 2 + self.color.fold(0, 1)
+    }
+
+    pub fn partner(self) -> Seat {
+        let (board, color) = BughouseGame::partner(self.board, self.color);
+        Seat { board, color }
+    }
+
+    // Tag name in BPGN headers, e.g. `WhiteA`
+    pub(crate) fn bpgn_tag(self) -> String {
+        format!(
+            "{}{}",
+            self.color.fold("White", "Black"),
+            match self.board {
+                BoardId::A => "A",
+                BoardId::B => "B",
+            }
+        )
+    }
+}
+
+impl fmt::Display for Seat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            match self.board {
+                BoardId::A => "A",
+                BoardId::B => "B",
+            },
+            self.color.fold("white", "black")
+        )
+    }
+}
+
+impl FromStr for Seat {
+    type Err = String;
+
+    /// `A-white`, `b-black` and so on.
+    fn from_str(s: &str) -> Result<Seat, String> {
+        SEATS
+            .iter()
+            .copied()
+            .find(|seat| seat.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown seat: {}", s))
+    }
+}
+
+/// A player of a bughouse game, engine or human. Seats are assigned from outside, so
+/// mixed teams only differ in which agents they are given.
+pub trait TeamAgent {
+    fn name(&self) -> &str;
+
+    /// Humans are asked for their moves through a front-end instead of searched for.
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    /// Picks a move for `seat`, whose turn it is. `None` resigns.
+    fn choose_move(&mut self, game: &BughouseGame, seat: Seat) -> io::Result<Option<Move>>;
+
+    /// Picks a move for `seat` or decides to wait for a piece from the partner. `None`
+    /// resigns. Agents that never sit only implement [`TeamAgent::choose_move`].
+    fn choose_action(&mut self, game: &BughouseGame, seat: Seat) -> io::Result<Option<Action>> {
+        Ok(self.choose_move(game, seat)?.map(Action::Move))
+    }
+
+    /// The search behind the last move, for agents that search.
+    fn last_search(&self) -> Option<SearchReport> {
+        None
+    }
+}
+
+/// Engine output for one move, as shown next to the boards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchReport {
+    pub analysis: Analysis,
+    pub elapsed: Duration,
+}
+
+impl SearchReport {
+    pub fn nodes_per_second(&self) -> u64 {
+        match self.elapsed.as_secs_f64() {
+            seconds if seconds > 0f64 => (self.analysis.nodes as f64 / seconds) as u64,
+            _ => 0,
+        }
+    }
+}
+
+/// The engine as a player, searching every move with the same limits.
+pub struct EngineAgent {
+    pub name: String,
+    pub engine: Engine,
+    pub limits: SearchLimits,
+    /// Wait for the partner's next capture when it gives a mate in one, see
+    /// [`sit_for_mate`]
+    pub sitting: bool,
+    /// Wait while the partner's king is in danger, see [`sit_for_partner`]
+    pub defensive_sits: bool,
+    /// Choose among the most searched moves by what they do for both boards, see
+    /// [`best_for_team`]. `None` plays the best move for our board alone
+    pub objective: Option<Box<dyn TeamObjective>>,
+    last: Option<SearchReport>,
+}
+
+impl EngineAgent {
+    pub fn new(name: &str, engine: Engine, limits: SearchLimits) -> EngineAgent {
+        EngineAgent {
+            name: name.to_string(),
+            engine,
+            limits,
+            sitting: true,
+            defensive_sits: true,
+            objective: None,
+            last: None,
+        }
+    }
+}
+
+impl EngineAgent {
+    // The most searched moves of the last search weighed by `objective`, `None` without
+    // an objective or a kept tree
+    fn team_choice(&self, game: &BughouseGame, seat: Seat) -> Option<Move> {
+        let objective = self.objective.as_ref()?;
+        let mut visits: Vec<(Move, u32)> = self
+            .engine
+            .last_record()?
+            .visits
+            .into_iter()
+            .filter_map(|(m, visits)| Some((m?, visits)))
+            .collect();
+        visits.sort_by_key(|&(_, visits)| std::cmp::Reverse(visits));
+        let candidates: Vec<Move> = visits
+            .into_iter()
+            .take(TEAM_CANDIDATES)
+            .map(|(m, _)| m)
+            .collect();
+        best_for_team(
+            objective.as_ref(),
+            game.board(seat.board),
+            game.board(seat.partner().board),
+            &candidates,
+            self.engine.params(),
+        )
+    }
+}
+
+impl TeamAgent for EngineAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose_move(&mut self, game: &BughouseGame, seat: Seat) -> io::Result<Option<Move>> {
+        let started = Instant::now();
+        let analysis = self
+            .engine
+            .analyse(game.board(seat.board), self.limits.clone());
+        // A proven mate on our board is the best thing for the team too
+        let best = match analysis.mate {
+            Some(mate) if mate > 0 => analysis.best.clone(),
+            _ => self
+                .team_choice(game, seat)
+                .or_else(|| analysis.best.clone()),
+        };
+        self.last = Some(SearchReport {
+            analysis,
+            elapsed: started.elapsed(),
+        });
+        Ok(best)
+    }
+
+    fn choose_action(&mut self, game: &BughouseGame, seat: Seat) -> io::Result<Option<Action>> {
+        if self.sitting {
+            // The partner's board gets an engine of its own so ours keeps its tree
+            let mut partner = Engine::new(self.engine.params().clone());
+            if sit_for_mate(game, seat, &mut partner, self.limits.clone()).is_some() {
+                return Ok(Some(Action::Wait));
+            }
+        }
+        if self.defensive_sits && sit_for_partner(game, seat, PARTNER_DANGER) {
+            return Ok(Some(Action::Wait));
+        }
+        Ok(self.choose_move(game, seat)?.map(Action::Move))
+    }
+
+    fn last_search(&self) -> Option<SearchReport> {
+        self.last.clone()
+    }
+}
+
+/// Who sits where, by agent name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Seating {
+    // Indexed like `SEATS`
+    seats: [Option<String>; 4],
+}
+
+impl Seating {
+    pub fn new() -> Seating {
+        Seating::default()
+    }
+
+    /// Seats `name` at `seat`, replacing whoever sat there.
+    pub fn sit(&mut self, seat: Seat, name: &str) {
+        self.seats[seat.index()] = Some(name.to_string());
+    }
+
+    /// The seating of `agents`, given in the order of [`SEATS`].
+    pub fn of(agents: &[Box<dyn TeamAgent>; 4]) -> Seating {
+        let mut seating = Seating::new();
+        for (&seat, agent) in SEATS.iter().zip(agents) {
+            seating.sit(seat, agent.name());
+        }
+        seating
+    }
+
+    /// Parses assignments like `A-white=ladybug,B-black=alice`.
+    pub fn parse(text: &str) -> Result<Seating, String> {
+        let mut seating = Seating::new();
+        for assignment in text.split(',').filter(|part| !part.trim().is_empty()) {
+            let (seat, name) = assignment
+                .split_once('=')
+                .ok_or_else(|| format!("expected seat=name: {}", assignment))?;
+            seating.sit(seat.parse()?, name.trim());
+        }
+        Ok(seating)
+    }
+
+    pub fn at(&self, seat: Seat) -> Option<&str> {
+        self.seats[seat.index()].as_deref()
+    }
+
+    /// Seats nobody sits at yet.
+    pub fn empty_seats(&self) -> Vec<Seat> {
+        SEATS
+            .iter()
+            .copied()
+            .filter(|&seat| self.at(seat).is_none())
+            .collect()
+    }
+
+    /// The seat tags of a BPGN header, `[WhiteA "name"]` and so on.
+    pub fn bpgn_tags(&self) -> String {
+        SEATS
+            .iter()
+            .filter_map(|&seat| {
+                self.at(seat)
+                    .map(|name| format!("[{} {}]\n", seat.bpgn_tag(), json_string(name)))
+            })
+            .collect()
+    }
+}
+
+/// Why a seated game stopped before its end.
+#[derive(Debug)]
+pub enum PlayError {
+    /// An agent could not choose, e.g. a remote player went away
+    Io(io::Error),
+    /// An agent chose a move the game doesn't allow
+    IllegalMove(IllegalMove),
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::Io(err) => write!(f, "agent failed: {}", err),
+            PlayError::IllegalMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PlayError {}
+
+impl From<io::Error> for PlayError {
+    fn from(err: io::Error) -> Self {
+        PlayError::Io(err)
+    }
+}
+
+impl From<IllegalMove> for PlayError {
+    fn from(err: IllegalMove) -> Self {
+        PlayError::IllegalMove(err)
+    }
+}
+
+/// Plays `game` to the end with `agents`, one per seat in the order of [`SEATS`].
+/// Boards take turns asking for a move whenever their side to move has one, so a slow
+/// agent holds up both boards; a real server lets them run independently.
+pub fn play_seated(
+    game: &mut BughouseGame,
+    agents: &mut [Box<dyn TeamAgent>; 4],
+) -> Result<Option<(BoardId, Outcome)>, PlayError> {
+    play_seated_with(game, agents, |_, _, _| ())
+}
+
+/// Like [`play_seated`], calling `observe` with the game, the seat that just moved and
+/// its agent after every action, so a front-end can follow the game as it is played.
+/// Agents' thinking time runs their clocks, and an agent that waits is asked again
+/// after the other board has moved.
+pub fn play_seated_with<F>(
+    game: &mut BughouseGame,
+    agents: &mut [Box<dyn TeamAgent>; 4],
+    mut observe: F,
+) -> Result<Option<(BoardId, Outcome)>, PlayError>
+where
+    F: FnMut(&BughouseGame, Seat, &dyn TeamAgent),
+{
+    while game.outcome().is_none() {
+        for board in [BoardId::A, BoardId::B] {
+            let color = game.board(board).turn();
+            let seat = Seat { board, color };
+            let agent = &mut agents[seat.index()];
+            let started = Instant::now();
+            match agent.choose_action(game, seat)? {
+                Some(action) => {
+                    game.act(board, &action, started.elapsed())?;
+                    observe(game, seat, agent.as_ref());
+                }
+                None => return Ok(Some((board, Outcome::Decisive { winner: !color }))),
+            }
+            if game.outcome().is_some() {
+                break;
+            }
+        }
+    }
+    Ok(game.decided_on())
+}
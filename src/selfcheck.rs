@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use shakmaty::san::San;
+use shakmaty::{CastlingMode, Position};
+
+use crate::board::Bughouse;
+use crate::engine::Engine;
+use crate::eval::{evaluate, EvalParams};
+use crate::limits::SearchLimits;
+use crate::output::{json_array, JsonObject};
+
+type Check = fn() -> Result<(), String>;
+
+/// The self-checks by name, in the order they run. Each compares two ways of arriving
+/// at the same answer, so a failure points at a bug rather than at a weak engine.
+pub const CHECKS: [(&str, Check); 6] = [
+    ("perft", perft),
+    ("fen round trip", fen_round_trip),
+    ("san round trip", san_round_trip),
+    ("hash", hash),
+    ("eval symmetry", eval_symmetry),
+    ("search reproducibility", search_reproducibility),
+];
+
+/// How one check went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    /// What went wrong, `None` if the check passed
+    pub failure: Option<String>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let object = JsonObject::new()
+            .string("name", self.name)
+            .boolean("passed", self.passed());
+        match &self.failure {
+            Some(failure) => object.string("failure", failure),
+            None => object,
+        }
+    }
+}
+
+/// Runs every check of [`CHECKS`].
+pub fn run() -> Vec<CheckResult> {
+    CHECKS
+        .iter()
+        .map(|&(name, check)| CheckResult {
+            name,
+            failure: check().err(),
+        })
+        .collect()
+}
+
+pub fn results_json(results: &[CheckResult]) -> JsonObject {
+    JsonObject::document("selfcheck")
+        .boolean("passed", results.iter().all(CheckResult::passed))
+        .raw(
+            "checks",
+            json_array(results.iter().map(|result| result.to_json().to_string())),
+        )
+}
+
+// Positions of random games from a fixed seed, so every run checks the same ones
+fn sample_positions() -> Vec<Bughouse> {
+    let mut rng = StdRng::seed_from_u64(516);
+    let mut positions = Vec::new();
+    for _ in 0..4 {
+        let mut position = Bughouse::default();
+        for _ in 0..40 {
+            let m = match position.legal_moves().choose(&mut rng) {
+                Some(m) => m.clone(),
+                None => break,
+            };
+            position.play_unchecked(&m);
+            positions.push(position.clone());
+        }
+    }
+    positions
+}
+
+// Node counts of the crazyhouse perft suite
+fn perft() -> Result<(), String> {
+    let suite: [(&str, &[u64]); 2] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            &[20, 400, 8902],
+        ),
+        ("2k5/8/8/8/8/8/8/4K3[Qn] w - - 0 1", &[67, 3083]),
+    ];
+    for (fen, counts) in suite {
+        let position = parse(fen)?;
+        for (depth, &expected) in (1..).zip(counts) {
+            let nodes = position.perft(depth);
+            if nodes != expected {
+                return Err(format!(
+                    "{} at depth {}: {} nodes, expected {}",
+                    fen, depth, nodes, expected
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn fen_round_trip() -> Result<(), String> {
+    for position in sample_positions() {
+        let fen = position.fen();
+        let again = parse(&fen)?.fen();
+        if again != fen {
+            return Err(format!("{} reads back as {}", fen, again));
+        }
+    }
+    Ok(())
+}
+
+fn san_round_trip() -> Result<(), String> {
+    for position in sample_positions() {
+        for m in position.legal_moves() {
+            let san = San::from_move(&position, &m).to_string();
+            let again = san
+                .parse::<San>()
+                .ok()
+                .and_then(|san| san.to_move(&position).ok());
+            if again.as_ref() != Some(&m) {
+                return Err(format!(
+                    "{} in {} reads back as {:?}",
+                    san,
+                    position.fen(),
+                    again
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The hash of a position reached by moves against the hash of the same position set
+// up from scratch. Dropped pawns are part of the hash but not of the FEN, so positions
+// with some on the board are left out
+fn hash() -> Result<(), String> {
+    let positions = sample_positions().into_iter();
+    for position in positions.filter(|position| position.dropped_pawns().is_empty()) {
+        let fresh = parse(&position.fen())?;
+        if fresh.zobrist_hash() != position.zobrist_hash() {
+            return Err(format!(
+                "hash of {} depends on how it was reached",
+                position.fen()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn eval_symmetry() -> Result<(), String> {
+    let params = EvalParams::default();
+    for position in sample_positions() {
+        let original = evaluate(&position, &params);
+        let mirrored = evaluate(&position.mirrored(), &params);
+        if (original - mirrored).abs() > 1e-3 {
+            return Err(format!(
+                "{} evaluates to {} but its mirror image to {}",
+                position.fen(),
+                original,
+                mirrored
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Two single threaded searches with the same seed must agree exactly
+fn search_reproducibility() -> Result<(), String> {
+    let params = Arc::new(EvalParams::default());
+    let position = sample_positions().into_iter().nth(20).unwrap_or_default();
+    let search = || {
+        let mut engine = Engine::new(params.clone());
+        engine.set_seed(516);
+        engine.analyse(&position, SearchLimits::nodes(300))
+    };
+    let (first, second) = (search(), search());
+    if first != second {
+        return Err(format!(
+            "{} searched twice gave {:?} and {:?}",
+            position.fen(),
+            first,
+            second
+        ));
+    }
+    Ok(())
+}
+
+fn parse(fen: &str) -> Result<Bughouse, String> {
+    Bughouse::from_fen(fen, CastlingMode::Standard).map_err(|err| err.to_string())
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shakmaty::{Move, Outcome, Setup};
+
+use crate::board::Bughouse;
+#[cfg(feature = "nn")]
+use crate::engine::RootStrategy;
+use crate::engine::{Engine, RootNoise, SearchOptions};
+use crate::eval::EvalParams;
+use crate::game::{Decision, GameManager, GameState, Searched};
+#[cfg(feature = "nn")]
+use crate::limits::SearchControl;
+use crate::limits::SearchLimits;
+use crate::resign::{GameResign, ResignPolicy};
+use crate::training::{SearchRecord, TrainingExporter};
+
+/// How self-play games are played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfPlayConfig {
+    pub games: u32,
+    /// Nodes searched per move
+    pub nodes: u64,
+    pub noise: Option<RootNoise>,
+    /// Moves of the first `temperature_plies` plies are drawn with probability
+    /// proportional to their visits raised to `1 / temperature`; later ones and all
+    /// moves at temperature 0 are the most visited
+    pub temperature: f32,
+    pub temperature_plies: usize,
+    /// Games still going after this many plies are scored as draws
+    pub max_plies: usize,
+    pub seed: u64,
+    /// Searches with [`RootStrategy::Gumbel`] over this many sampled root moves, in
+    /// place of PUCT and the root noise
+    #[cfg(feature = "nn")]
+    pub gumbel: Option<usize>,
+    /// Resigns games the search gives up on; [`run`] adjusts the threshold from the
+    /// audited games as it goes
+    pub resign: Option<ResignPolicy>,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            games: 100,
+            nodes: 800,
+            noise: Some(RootNoise::default()),
+            temperature: 1.0,
+            temperature_plies: 30,
+            max_plies: 400,
+            seed: 0,
+            #[cfg(feature = "nn")]
+            gumbel: None,
+            resign: None,
+        }
+    }
+}
+
+impl SelfPlayConfig {
+    /// The search options the games are played with.
+    pub fn search_options(&self) -> SearchOptions {
+        #[cfg(feature = "nn")]
+        if let Some(considered) = self.gumbel {
+            return SearchOptions {
+                root_strategy: RootStrategy::Gumbel { considered },
+                ..SearchOptions::default()
+            };
+        }
+        SearchOptions {
+            root_noise: self.noise,
+            ..SearchOptions::default()
+        }
+    }
+}
+
+/// A self-play game: what the search found before each move, and the result.
+#[derive(Clone, Debug)]
+pub struct SelfPlayGame {
+    pub records: Vec<SearchRecord>,
+    pub outcome: Outcome,
+    /// Resign bookkeeping, for [`ResignPolicy::finish_game`]
+    pub resign: Option<GameResign>,
+}
+
+// Picks the move to play from the root visits, `None` being a pass
+fn pick(
+    visits: &[(Option<Move>, u32)],
+    temperature: f32,
+    rng: &mut StdRng,
+) -> Option<Option<Move>> {
+    if temperature <= 0f32 {
+        return visits
+            .iter()
+            .max_by_key(|&&(_, count)| count)
+            .map(|(m, _)| m.clone());
+    }
+    let weights: Vec<f32> = visits
+        .iter()
+        .map(|&(_, count)| (count as f32).powf(1f32 / temperature))
+        .collect();
+    let total: f32 = weights.iter().sum();
+    if total <= 0f32 {
+        return visits.first().map(|(m, _)| m.clone());
+    }
+    let mut target = rng.gen_range(0f32..total);
+    for ((m, _), weight) in visits.iter().zip(weights) {
+        if target < weight {
+            return Some(m.clone());
+        }
+        target -= weight;
+    }
+    visits.last().map(|(m, _)| m.clone())
+}
+
+// Searches the position to move in, returning the record to train on. `None` after a
+// shutdown or when the tree was not kept.
+fn search(manager: &mut GameManager<&mut Engine>) -> Option<SearchRecord> {
+    let position = manager.position().clone();
+    match manager.search_on(&position) {
+        Ok(Some(_)) => manager.searcher().last_record(),
+        _ => None,
+    }
+}
+
+// Searches with Gumbel root search instead, returning the record along with the move
+// the search chose, if any. The record trains towards the improved policy rather than
+// the visits the root moves were narrowed down by.
+#[cfg(feature = "nn")]
+fn gumbel_search(
+    manager: &mut GameManager<&mut Engine>,
+    nodes: u64,
+    considered: usize,
+) -> Option<(SearchRecord, Option<Option<Move>>)> {
+    let position = manager.position().clone();
+    let control = SearchControl::new(SearchLimits::nodes(nodes), manager.shutdown.clone());
+    let choice = manager
+        .searcher_mut()
+        .gumbel_search(&position, &control, considered);
+    if manager.shutdown.is_cancelled() {
+        return None;
+    }
+    let mut record = manager.searcher().last_record()?;
+    let chosen = choice.map(|choice| {
+        record.policy = Some(choice.policy);
+        choice.m
+    });
+    Some((record, chosen))
+}
+
+/// Plays game number `game` of `config` with `engine` against itself. Each game draws
+/// its seeds from the config's seed and its number, so games can be replayed one by one.
+pub fn play_game(engine: &mut Engine, config: &SelfPlayConfig, game: u32) -> SelfPlayGame {
+    let seed = config.seed.wrapping_add(u64::from(game));
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut resign = config
+        .resign
+        .as_ref()
+        .map(|policy| policy.start_game(&mut rng));
+    engine.set_seed(seed);
+    let mut manager = GameManager::new(engine, None, Bughouse::default());
+    manager.limits = Some(SearchLimits::nodes(config.nodes));
+    let mut records = Vec::new();
+    let outcome = loop {
+        if let GameState::GameOver(outcome) = manager.state() {
+            break outcome.unwrap_or(Outcome::Draw);
+        }
+        if records.len() >= config.max_plies {
+            break Outcome::Draw;
+        }
+        #[cfg(feature = "nn")]
+        let searched = match config.gumbel {
+            Some(considered) => gumbel_search(&mut manager, config.nodes, considered),
+            None => search(&mut manager).map(|record| (record, None)),
+        };
+        #[cfg(not(feature = "nn"))]
+        let searched = search(&mut manager).map(|record| (record, None));
+        let (record, chosen) = match searched {
+            Some(searched) => searched,
+            None => break Outcome::Draw,
+        };
+        let turn = manager.position().turn();
+        let resigns = resign
+            .as_mut()
+            .is_some_and(|resign| resign.should_resign(turn, record.value));
+        let decision = if resigns {
+            Decision::Resign
+        } else {
+            let temperature = if records.len() < config.temperature_plies {
+                config.temperature
+            } else {
+                0f32
+            };
+            match chosen.or_else(|| pick(&record.visits, temperature, &mut rng)) {
+                Some(m) => Decision::Play(m),
+                // Passes if it can, else there is nothing to play
+                None => manager.decide(&Searched {
+                    best: None,
+                    win_probability: None,
+                }),
+            }
+        };
+        records.push(record);
+        // Nobody else takes part, so there is nothing to fail
+        let _ = manager.act(decision, Duration::ZERO, &mut ());
+    };
+    SelfPlayGame {
+        records,
+        outcome,
+        resign,
+    }
+}
+
+/// Plays the games of `config`, adding each to `exporter` and handing it to `progress`
+/// along with its number. `progress` returns `false` to stop before the next game, say
+/// on shutdown, leaving the games played so far in `exporter`.
+pub fn run<F>(
+    config: &SelfPlayConfig,
+    params: Arc<EvalParams>,
+    exporter: &mut TrainingExporter,
+    mut progress: F,
+) where
+    F: FnMut(u32, &SelfPlayGame) -> bool,
+{
+    let mut engine = Engine::new(params);
+    engine.set_options(config.search_options());
+    // The resign threshold moves with the audited games
+    let mut config = config.clone();
+    for game in 0..config.games {
+        let played = play_game(&mut engine, &config, game);
+        if let (Some(policy), Some(resign)) = (&mut config.resign, &played.resign) {
+            policy.finish_game(resign, played.outcome);
+        }
+        exporter.add_game(&played.records, played.outcome);
+        if !progress(game, &played) {
+            break;
+        }
+    }
+}
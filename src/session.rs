@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use shakmaty::san::SanPlus;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position};
+
+use crate::board::{parse_fen, Bughouse, FenError, IllegalMove};
+use crate::engine::{parse_move, to_san, Analysis, CheckpointError, LongAnalysis};
+use crate::eval::EvalParams;
+use crate::limits::SearchControl;
+use crate::paths::write_atomic;
+
+const EXTENSION: &str = "session";
+// Every session's analysis starts from the same seed, so its searches are reproducible
+const SEED: u64 = 0;
+
+/// A long-lived analysis session: a start position and the moves played from it.
+#[derive(Clone, Debug)]
+pub struct Session {
+    start: Bughouse,
+    // `None` is a pass
+    moves: Vec<Option<Move>>,
+    position: Bughouse,
+}
+
+impl Session {
+    pub fn new(start: Bughouse) -> Session {
+        Session {
+            position: start.clone(),
+            start,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn start(&self) -> &Bughouse {
+        &self.start
+    }
+
+    /// The moves played, `None` standing for a pass.
+    pub fn moves(&self) -> &[Option<Move>] {
+        &self.moves
+    }
+
+    /// The position after all moves of the session.
+    pub fn position(&self) -> &Bughouse {
+        &self.position
+    }
+
+    pub fn play(&mut self, m: &Move) -> Result<(), SessionError> {
+        if !self.position.is_legal(m) {
+            return Err(IllegalMove::of(m).into());
+        }
+        self.position.play_unchecked(m);
+        self.moves.push(Some(m.clone()));
+        Ok(())
+    }
+
+    /// Plays a move in UCI notation. `0000` passes, if the rules allow it.
+    pub fn play_uci(&mut self, uci: &str) -> Result<(), SessionError> {
+        let illegal = || SessionError::IllegalMove(IllegalMove(uci.to_string()));
+        match uci.parse::<Uci>().map_err(|_| illegal())? {
+            Uci::Null => {
+                if !self.position.pass() {
+                    return Err(illegal());
+                }
+                self.moves.push(None);
+                Ok(())
+            }
+            parsed => {
+                let m = parsed.to_move(&self.position).map_err(|_| illegal())?;
+                self.play(&m)
+            }
+        }
+    }
+
+    /// Plays a move in SAN or UCI notation. `--` and `0000` pass, if the rules allow it.
+    pub fn play_notation(&mut self, text: &str) -> Result<(), SessionError> {
+        match parse_move(&self.position, text)? {
+            Some(m) => self.play(&m),
+            None => {
+                self.position.pass();
+                self.moves.push(None);
+                Ok(())
+            }
+        }
+    }
+
+    /// The moves played in SAN, passes written `--`.
+    pub fn san_moves(&self) -> Vec<SanPlus> {
+        let mut position = self.start.clone();
+        self.moves
+            .iter()
+            .map(|m| {
+                let san = to_san(&position, m.as_ref());
+                match m {
+                    Some(m) => position.play_unchecked(m),
+                    None => {
+                        position.pass();
+                    }
+                }
+                san
+            })
+            .collect()
+    }
+
+    // First line is the start FEN, second line the moves in UCI notation
+    fn serialize(&self) -> String {
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| match m {
+                Some(m) => Uci::from_standard(m).to_string(),
+                None => Uci::Null.to_string(),
+            })
+            .collect();
+        format!("{}\n{}\n", self.start.fen(), moves.join(" "))
+    }
+
+    fn deserialize(text: &str) -> Result<Session, SessionError> {
+        let mut lines = text.lines();
+        let start = parse_fen(lines.next().unwrap_or(""))?;
+        let mut session = Session::new(start);
+        for uci in lines.next().unwrap_or("").split_whitespace() {
+            session.play_uci(uci)?;
+        }
+        Ok(session)
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    InvalidId(String),
+    UnknownSession(String),
+    AlreadyExists(String),
+    /// A saved session whose start position doesn't read back
+    Fen(FenError),
+    IllegalMove(IllegalMove),
+    /// A saved tree that doesn't read back
+    Checkpoint(CheckpointError),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(err) => write!(f, "session storage error: {}", err),
+            SessionError::InvalidId(id) => write!(f, "invalid session id: {}", id),
+            SessionError::UnknownSession(id) => write!(f, "no such session: {}", id),
+            SessionError::AlreadyExists(id) => write!(f, "session already exists: {}", id),
+            SessionError::Fen(err) => write!(f, "invalid fen: {}", err),
+            SessionError::IllegalMove(err) => write!(f, "{}", err),
+            SessionError::Checkpoint(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<FenError> for SessionError {
+    fn from(err: FenError) -> Self {
+        SessionError::Fen(err)
+    }
+}
+
+impl From<IllegalMove> for SessionError {
+    fn from(err: IllegalMove) -> Self {
+        SessionError::IllegalMove(err)
+    }
+}
+
+impl From<CheckpointError> for SessionError {
+    fn from(err: CheckpointError) -> Self {
+        SessionError::Checkpoint(err)
+    }
+}
+
+impl From<io::Error> for SessionError {
+    fn from(err: io::Error) -> Self {
+        SessionError::Io(err)
+    }
+}
+
+/// Named analysis sessions keyed by game id, each with a [`LongAnalysis`] of its
+/// current position that moves along with the game. When opened on a directory, every
+/// change is written through to `<dir>/<id>.session` and the tree to `<dir>/<id>.tree`,
+/// so sessions and what was searched for them survive restarts.
+pub struct SessionStore {
+    dir: Option<PathBuf>,
+    params: Arc<EvalParams>,
+    sessions: HashMap<String, (Session, LongAnalysis)>,
+}
+
+impl SessionStore {
+    pub fn in_memory(params: Arc<EvalParams>) -> SessionStore {
+        SessionStore {
+            dir: None,
+            params,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Opens a store backed by `dir`, loading all sessions saved there. A session
+    /// whose tree is missing or was saved for another position starts a new one.
+    pub fn open(dir: &Path, params: Arc<EvalParams>) -> Result<SessionStore, SessionError> {
+        fs::create_dir_all(dir)?;
+        let mut sessions = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                let session = Session::deserialize(&fs::read_to_string(&path)?)?;
+                let tree = tree_path(dir, id);
+                let resumed = if tree.exists() {
+                    Some(LongAnalysis::resume(&tree, Arc::clone(&params))?)
+                } else {
+                    None
+                };
+                let analysis = resumed
+                    .filter(|analysis| analysis.position().fen() == session.position().fen())
+                    .unwrap_or_else(|| {
+                        LongAnalysis::new(session.position(), Arc::clone(&params), SEED)
+                    });
+                sessions.insert(id.to_string(), (session, analysis));
+            }
+        }
+        Ok(SessionStore {
+            dir: Some(dir.to_path_buf()),
+            params,
+            sessions,
+        })
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.sessions.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Session> {
+        self.sessions.get(id).map(|(session, _)| session)
+    }
+
+    /// The analysis of the session's current position.
+    pub fn analysis(&self, id: &str) -> Option<&LongAnalysis> {
+        self.sessions.get(id).map(|(_, analysis)| analysis)
+    }
+
+    pub fn create(&mut self, id: &str, start: Bughouse) -> Result<&Session, SessionError> {
+        validate_id(id)?;
+        if self.sessions.contains_key(id) {
+            return Err(SessionError::AlreadyExists(id.to_string()));
+        }
+        let analysis = LongAnalysis::new(&start, Arc::clone(&self.params), SEED);
+        let session = Session::new(start);
+        self.save(id, &session, &analysis)?;
+        let (session, _) = self
+            .sessions
+            .entry(id.to_string())
+            .or_insert((session, analysis));
+        Ok(session)
+    }
+
+    /// Plays moves in SAN or UCI notation, moving the analysis along with them. Either
+    /// all moves are applied or none.
+    pub fn play(&mut self, id: &str, moves: &[&str]) -> Result<&Session, SessionError> {
+        let (session, analysis) = self
+            .sessions
+            .get_mut(id)
+            .ok_or_else(|| SessionError::UnknownSession(id.to_string()))?;
+        let mut played = session.clone();
+        for text in moves {
+            played.play_notation(text)?;
+        }
+        for m in &played.moves()[session.moves().len()..] {
+            analysis.play(m.as_ref());
+        }
+        *session = played;
+        let (session, analysis) = &self.sessions[id];
+        self.save(id, session, analysis)?;
+        Ok(session)
+    }
+
+    /// Searches the session's current position on until `control` says to stop,
+    /// saving the grown tree.
+    pub fn query(&mut self, id: &str, control: &SearchControl) -> Result<Analysis, SessionError> {
+        let (_, analysis) = self
+            .sessions
+            .get_mut(id)
+            .ok_or_else(|| SessionError::UnknownSession(id.to_string()))?;
+        let result = analysis.run(control);
+        let (session, analysis) = &self.sessions[id];
+        self.save(id, session, analysis)?;
+        Ok(result)
+    }
+
+    pub fn destroy(&mut self, id: &str) -> Result<(), SessionError> {
+        self.sessions
+            .remove(id)
+            .ok_or_else(|| SessionError::UnknownSession(id.to_string()))?;
+        if let Some(dir) = &self.dir {
+            fs::remove_file(session_path(dir, id))?;
+            fs::remove_file(tree_path(dir, id))?;
+        }
+        Ok(())
+    }
+
+    // The tree first: a crash in between leaves a tree the session is behind of,
+    // which `open` replaces
+    fn save(
+        &self,
+        id: &str,
+        session: &Session,
+        analysis: &LongAnalysis,
+    ) -> Result<(), SessionError> {
+        if let Some(dir) = &self.dir {
+            analysis.checkpoint(&tree_path(dir, id))?;
+            write_atomic(&session_path(dir, id), session.serialize())?;
+        }
+        Ok(())
+    }
+}
+
+fn session_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.{}", id, EXTENSION))
+}
+
+fn tree_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.tree", id))
+}
+
+// Ids double as file names
+fn validate_id(id: &str) -> Result<(), SessionError> {
+    let valid = !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(SessionError::InvalidId(id.to_string()))
+    }
+}
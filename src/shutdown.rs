@@ -0,0 +1,117 @@
+use std::io;
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::CancelToken;
+
+/// Signals that ask the engine to shut down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGINT, e.g. Ctrl-C
+    Interrupt,
+    /// SIGTERM, e.g. from a service manager
+    Terminate,
+}
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+impl Signal {
+    fn number(self) -> i32 {
+        match self {
+            Signal::Interrupt => SIGINT,
+            Signal::Terminate => SIGTERM,
+        }
+    }
+
+    fn from_number(number: i32) -> Option<Signal> {
+        match number {
+            SIGINT => Some(Signal::Interrupt),
+            SIGTERM => Some(Signal::Terminate),
+            _ => None,
+        }
+    }
+
+    /// The exit code shells use for a process ended by this signal.
+    pub fn exit_code(self) -> i32 {
+        128 + self.number()
+    }
+}
+
+// The first signal received, 0 while none was
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+// How often waiting threads look for a signal
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[cfg(unix)]
+extern "C" fn handle(number: libc::c_int) {
+    // Only async-signal-safe work here. A second signal means the user is done waiting
+    // for a clean shutdown.
+    if RECEIVED
+        .compare_exchange(0, number, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        unsafe { libc::_exit(128 + number) };
+    }
+}
+
+/// Catches SIGINT and SIGTERM so long-running modes can stop their searches, flush
+/// their files and close connections before exiting. A second signal exits at once.
+/// Does nothing on platforms without POSIX signals.
+#[cfg(unix)]
+pub fn install() -> io::Result<()> {
+    for &number in &[SIGINT, SIGTERM] {
+        // SAFETY: the handler only touches an atomic and calls `_exit`
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(number, &action, std::ptr::null_mut())
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install() -> io::Result<()> {
+    Ok(())
+}
+
+/// The signal asking for shutdown, if one arrived. Cheap enough to check in loops.
+pub fn requested() -> Option<Signal> {
+    Signal::from_number(RECEIVED.load(Ordering::SeqCst))
+}
+
+/// Blocks until a shutdown signal arrives.
+pub fn wait() -> Signal {
+    loop {
+        if let Some(signal) = requested() {
+            return signal;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Cancels `token` once a shutdown signal arrives, so a running search returns its
+/// best move so far instead of being killed, and the modes polling it can flush their
+/// files or resign their games before returning.
+pub fn cancel_on_shutdown(token: CancelToken) {
+    thread::spawn(move || {
+        wait();
+        token.cancel();
+    });
+}
+
+/// Exits as soon as a shutdown signal arrives, with the signal's exit code. For modes
+/// that keep nothing worth flushing and block where they can't poll, like reading
+/// commands from stdin.
+pub fn exit_on_shutdown() {
+    thread::spawn(|| process::exit(wait().exit_code()));
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use shakmaty::{Color, Material, Move, Outcome, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::prior::MoveClass;
+
+const MOVE_CLASSES: [MoveClass; 5] = [
+    MoveClass::Check,
+    MoveClass::Capture,
+    MoveClass::CentralDrop,
+    MoveClass::EdgeDrop,
+    MoveClass::Quiet,
+];
+
+/// The material on the board and in the pockets, ignoring where it stands. Positions
+/// with the same signature tend to share plans, which makes it a coarse substitute for
+/// the endgame tablebases this variant doesn't have.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialSignature {
+    pub board: Material,
+    pub pockets: Material,
+}
+
+impl MaterialSignature {
+    pub fn of(position: &Bughouse) -> MaterialSignature {
+        MaterialSignature {
+            board: position.board().material(),
+            pockets: position.pockets().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for MaterialSignature {
+    /// Board material as in `KQPvKR`, followed by the pockets in FEN notation
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.board, self.pockets.fen())
+    }
+}
+
+/// Results of the games in which a signature occurred.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SignatureStats {
+    pub games: u32,
+    pub white_wins: u32,
+    pub black_wins: u32,
+    /// How often the eventual winner played each kind of move from this signature,
+    /// indexed like [`MoveClass`]
+    winner_moves: [u32; 5],
+}
+
+impl SignatureStats {
+    /// Score of `color` in the games with this signature, draws counting half.
+    pub fn score(&self, color: Color) -> Option<f32> {
+        if self.games == 0 {
+            return None;
+        }
+        let wins = color.fold(self.white_wins, self.black_wins);
+        let draws = self.games - self.white_wins - self.black_wins;
+        Some((wins as f32 + draws as f32 / 2f32) / self.games as f32)
+    }
+
+    /// What winners typically did from this signature: kinds of moves with the share of
+    /// the winners' moves they make up, most common first.
+    pub fn typical_plans(&self) -> Vec<(MoveClass, f32)> {
+        let total: u32 = self.winner_moves.iter().sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        let mut plans: Vec<(MoveClass, f32)> = MOVE_CLASSES
+            .iter()
+            .zip(&self.winner_moves)
+            .filter(|(_, &count)| count > 0)
+            .map(|(&class, &count)| (class, count as f32 / total as f32))
+            .collect();
+        plans.sort_by(|a, b| b.1.total_cmp(&a.1));
+        plans
+    }
+}
+
+/// Per-signature statistics gathered from a corpus of games.
+#[derive(Clone, Debug, Default)]
+pub struct SignatureIndex {
+    stats: HashMap<MaterialSignature, SignatureStats>,
+}
+
+impl SignatureIndex {
+    pub fn new() -> SignatureIndex {
+        SignatureIndex::default()
+    }
+
+    /// Adds a finished game played from `start`. Each signature counts once per game
+    /// however long it lasted.
+    pub fn add_game(&mut self, start: &Bughouse, moves: &[Move], outcome: Outcome) {
+        let mut seen = HashMap::new();
+        let mut position = start.clone();
+        for m in moves {
+            let signature = MaterialSignature::of(&position);
+            let winner_moves = seen.entry(signature).or_insert([0u32; 5]);
+            if outcome.winner() == Some(position.turn()) {
+                let class = MoveClass::of(&position, m);
+                let index = MOVE_CLASSES.iter().position(|&c| c == class);
+                winner_moves[index.expect("known move class")] += 1;
+            }
+            position.play_unchecked(m);
+        }
+        seen.entry(MaterialSignature::of(&position))
+            .or_insert([0u32; 5]);
+
+        for (signature, winner_moves) in seen {
+            let stats = self.stats.entry(signature).or_default();
+            stats.games += 1;
+            match outcome.winner() {
+                Some(Color::White) => stats.white_wins += 1,
+                Some(Color::Black) => stats.black_wins += 1,
+                None => {}
+            }
+            for (total, count) in stats.winner_moves.iter_mut().zip(winner_moves) {
+                *total += count;
+            }
+        }
+    }
+
+    pub fn get(&self, signature: &MaterialSignature) -> Option<&SignatureStats> {
+        self.stats.get(signature)
+    }
+
+    /// Statistics for the signature of `position`.
+    pub fn lookup(&self, position: &Bughouse) -> Option<&SignatureStats> {
+        self.get(&MaterialSignature::of(position))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use shakmaty::{Color, Material, MaterialSide, Move, Position, Role, Setup};
+
+use crate::board::{Bughouse, BughouseGame};
+use crate::danger::danger_score;
+use crate::drops::Pockets;
+use crate::engine::Engine;
+use crate::limits::SearchLimits;
+use crate::seats::Seat;
+
+/// Least time left on our clock for sitting to be considered; below it the wait
+/// itself is the bigger risk.
+pub const SIT_RESERVE: Duration = Duration::from_secs(5);
+
+/// Danger score of the partner's king from which [`sit_for_partner`] sits.
+pub const PARTNER_DANGER: f32 = 0.6;
+
+/// The piece the partner of `seat` is expected to capture with their next move, found
+/// by searching their board with `limits`. `None` when it is not the partner's turn or
+/// their best move captures nothing.
+pub fn expected_capture(
+    game: &BughouseGame,
+    seat: Seat,
+    engine: &mut Engine,
+    limits: SearchLimits,
+) -> Option<Role> {
+    let partner = seat.partner();
+    let position = game.board(partner.board);
+    if position.turn() != partner.color || position.is_game_over() {
+        return None;
+    }
+    let best = engine.analyse(position, limits).best?;
+    Pockets::captured_role(position.board(), &best)
+}
+
+/// A drop of `role` that would mate at once if the piece arrived in the pocket of the
+/// side to move. `None` if a move already mates, since then there is nothing to wait
+/// for.
+pub fn mate_after_arrival(position: &Bughouse, role: Role) -> Option<Move> {
+    if !position.mating_moves().is_empty() {
+        return None;
+    }
+    let mut material = Material::new();
+    *material.by_piece_mut(role.of(position.turn())) += 1;
+    position
+        .clone()
+        .add_material(material)
+        .mating_moves()
+        .into_iter()
+        .find(|m| matches!(*m, Move::Put { role: dropped, .. } if dropped == role))
+}
+
+/// Whether `seat` should sit instead of moving: its partner is about to capture a piece
+/// that gives a mate-in-one drop, and there is time on the clock to wait for it.
+/// Returns the drop to play once the piece arrives.
+pub fn sit_for_mate(
+    game: &BughouseGame,
+    seat: Seat,
+    engine: &mut Engine,
+    limits: SearchLimits,
+) -> Option<Move> {
+    let position = game.board(seat.board);
+    if position.turn() != seat.color || *game.clocks(seat.board).by_color(seat.color) < SIT_RESERVE
+    {
+        return None;
+    }
+    let role = expected_capture(game, seat, engine, limits)?;
+    mate_after_arrival(position, role)
+}
+
+/// Whether `seat` should sit to keep pieces away from the side attacking its partner's
+/// king: the partner's [`danger_score`] reaches `threshold` counting every piece of
+/// ours our opponent attacks as on its way to the attacker, and we have more time than
+/// our opponent, who can't move until we do.
+pub fn sit_for_partner(game: &BughouseGame, seat: Seat, threshold: f32) -> bool {
+    let position = game.board(seat.board);
+    let clocks = game.clocks(seat.board);
+    let ours = *clocks.by_color(seat.color);
+    if position.turn() != seat.color || ours < SIT_RESERVE || ours <= *clocks.by_color(!seat.color)
+    {
+        return false;
+    }
+    let partner = seat.partner();
+    let attacked = game.board(partner.board);
+    if attacked.is_game_over() {
+        return false;
+    }
+    let incoming = exposed(position, !seat.color);
+    danger_score(attacked, partner.color, &incoming) >= threshold
+}
+
+// The pieces `them` attacks on `position`, promoted ones counting as the pawns they
+// would be passed on as
+fn exposed(position: &Bughouse, them: Color) -> MaterialSide {
+    let board = position.board();
+    let mut exposed = MaterialSide::new();
+    for square in board.by_color(!them) & !board.kings() {
+        if board.attacks_to(square, them, board.occupied()).is_empty() {
+            continue;
+        }
+        let role = match board.role_at(square) {
+            Some(_) if board.promoted().contains(square) => Role::Pawn,
+            Some(role) => role,
+            None => continue,
+        };
+        *exposed.by_role_mut(role) += 1;
+    }
+    exposed
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::{attacks, Move, Position, Setup};
+
+use crate::board::Bughouse;
+use crate::prior::PriorSource;
+
+/// Skills a sparring engine steers the game towards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    /// Attacking the enemy king with pieces and drops
+    KingAttack,
+    /// Keeping many pieces in hand, where deciding between dropping and holding matters
+    PieceHoarding,
+}
+
+impl Theme {
+    /// How strongly playing `m` in `position` leads towards the theme, 0 if not at all.
+    pub fn score(self, position: &Bughouse, m: &Move) -> f32 {
+        let mover = position.turn();
+        let mut after = position.clone();
+        after.play_unchecked(m);
+        match self {
+            Theme::KingAttack => {
+                let king = match after.board().king_of(!mover) {
+                    Some(king) => king,
+                    None => return 0f32,
+                };
+                let board = after.board();
+                let zone = attacks::king_attacks(king).with(king);
+                let attacks: usize = zone
+                    .into_iter()
+                    .map(|square| board.attacks_to(square, mover, board.occupied()).count())
+                    .sum();
+                let check_bonus = if after.is_check() { 2 } else { 0 };
+                (attacks + check_bonus) as f32
+            }
+            Theme::PieceHoarding => {
+                let pockets = after.pockets().expect("crazyhouse pockets");
+                pockets.count() as f32
+            }
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Theme::KingAttack => "king-attack",
+            Theme::PieceHoarding => "piece-hoarding",
+        })
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Theme, String> {
+        match s {
+            "king-attack" => Ok(Theme::KingAttack),
+            "piece-hoarding" => Ok(Theme::PieceHoarding),
+            _ => Err(format!("unknown sparring theme: {}", s)),
+        }
+    }
+}
+
+/// Training mode priors: wraps another prior source and shifts probability towards
+/// moves that lead to positions with the chosen theme, so the search spends its
+/// effort there instead of on the objectively best moves.
+pub struct SparringPriors<P> {
+    pub inner: P,
+    pub theme: Theme,
+    /// How much the theme counts; at 1 the most thematic move's prior doubles
+    pub strength: f32,
+}
+
+impl<P: PriorSource> PriorSource for SparringPriors<P> {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        let scores: Vec<f32> = moves
+            .iter()
+            .map(|m| self.theme.score(position, m))
+            .collect();
+        let best = scores.iter().copied().fold(0f32, f32::max);
+        let mut priors = self.inner.priors(position, moves);
+        if best > 0f32 {
+            for (prior, score) in priors.iter_mut().zip(scores) {
+                *prior *= 1f32 + self.strength * score / best;
+            }
+        }
+        let total: f32 = priors.iter().sum();
+        if total > 0f32 {
+            priors.iter_mut().for_each(|prior| *prior /= total);
+        }
+        priors
+    }
+}
@@ -0,0 +1,299 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use shakmaty::{ByColor, Color, Move, Piece, Position, Role, Setup, Square};
+
+use crate::board::{BoardId, Bughouse, BughouseGame};
+use crate::bpgn::BpgnGame;
+use crate::display::{format_clock, BoardView};
+use crate::prior::PriorSource;
+
+pub const SQUARE_SIZE: u32 = 40;
+const POCKET_HEIGHT: u32 = 32;
+const BOARD_GAP: u32 = 24;
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+
+/// Width and height of one board including its pockets.
+pub fn board_size() -> (u32, u32) {
+    (8 * SQUARE_SIZE, 8 * SQUARE_SIZE + 2 * POCKET_HEIGHT)
+}
+
+fn glyph(piece: Piece) -> char {
+    match (piece.color, piece.role) {
+        (Color::White, Role::King) => '\u{2654}',
+        (Color::White, Role::Queen) => '\u{2655}',
+        (Color::White, Role::Rook) => '\u{2656}',
+        (Color::White, Role::Bishop) => '\u{2657}',
+        (Color::White, Role::Knight) => '\u{2658}',
+        (Color::White, Role::Pawn) => '\u{2659}',
+        (Color::Black, Role::King) => '\u{265a}',
+        (Color::Black, Role::Queen) => '\u{265b}',
+        (Color::Black, Role::Rook) => '\u{265c}',
+        (Color::Black, Role::Bishop) => '\u{265d}',
+        (Color::Black, Role::Knight) => '\u{265e}',
+        (Color::Black, Role::Pawn) => '\u{265f}',
+    }
+}
+
+fn pocket_row(out: &mut String, view: &BoardView, color: Color, x: u32, y: u32) {
+    let mut offset = x;
+    for (role, count) in view.pocket(color) {
+        let _ = write!(
+            out,
+            r#"<text x="{}" y="{}" font-size="{}">{}</text><text x="{}" y="{}" font-size="12">{}</text>"#,
+            offset,
+            y + POCKET_HEIGHT - 6,
+            POCKET_HEIGHT - 4,
+            glyph(role.of(color)),
+            offset + POCKET_HEIGHT - 6,
+            y + POCKET_HEIGHT - 4,
+            count
+        );
+        offset += POCKET_HEIGHT + 8;
+    }
+    if let Some(clocks) = &view.clocks {
+        let _ = write!(
+            out,
+            r#"<text x="{}" y="{}" font-size="18" text-anchor="end" font-family="monospace"{}>{}</text>"#,
+            x + 8 * SQUARE_SIZE,
+            y + POCKET_HEIGHT - 8,
+            if view.turn == color {
+                r#" font-weight="bold""#
+            } else {
+                ""
+            },
+            format_clock(*clocks.by_color(color))
+        );
+    }
+}
+
+/// Draws one board with its pockets and clocks, top left corner at (`x`, `y`).
+pub fn board_group(view: &BoardView, x: u32, y: u32) -> String {
+    let mut out = String::new();
+    let (top, bottom) = if view.flipped {
+        (Color::White, Color::Black)
+    } else {
+        (Color::Black, Color::White)
+    };
+    let _ = write!(out, r#"<g transform="translate({},{})">"#, x, y);
+    pocket_row(&mut out, view, top, 0, 0);
+    for (row, squares) in view.rows().enumerate() {
+        for (column, square) in squares.enumerate() {
+            let (sx, sy) = (
+                column as u32 * SQUARE_SIZE,
+                POCKET_HEIGHT + row as u32 * SQUARE_SIZE,
+            );
+            let fill = if square.is_light() {
+                LIGHT_SQUARE
+            } else {
+                DARK_SQUARE
+            };
+            let _ = write!(
+                out,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                sx, sy, SQUARE_SIZE, SQUARE_SIZE, fill
+            );
+            if let Some((piece, promoted)) = view.piece_at(square) {
+                let _ = write!(
+                    out,
+                    r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle">{}</text>"#,
+                    sx + SQUARE_SIZE / 2,
+                    sy + SQUARE_SIZE - 7,
+                    SQUARE_SIZE - 6,
+                    glyph(piece)
+                );
+                if promoted {
+                    let _ = write!(
+                        out,
+                        r##"<circle cx="{}" cy="{}" r="3" fill="#c33"/>"##,
+                        sx + SQUARE_SIZE - 6,
+                        sy + 6
+                    );
+                }
+            }
+        }
+    }
+    pocket_row(&mut out, view, bottom, 0, POCKET_HEIGHT + 8 * SQUARE_SIZE);
+    out.push_str("</g>");
+    out
+}
+
+/// Renders boards side by side into a standalone SVG document.
+pub fn frame_svg(boards: &[BoardView]) -> String {
+    let (width, height) = board_size();
+    let count = boards.len() as u32;
+    let total_width = count * width + count.saturating_sub(1) * BOARD_GAP;
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        total_width, height
+    );
+    for (index, view) in boards.iter().enumerate() {
+        out.push_str(&board_group(view, index as u32 * (width + BOARD_GAP), 0));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Writes one numbered SVG file per frame (`frame-0000.svg`, ...) into `dir`, e.g. to be
+/// assembled into an animation. Each frame holds the boards of the match at that point.
+pub fn write_frames(frames: &[Vec<BoardView>], dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, boards)| {
+            let path = dir.join(format!("frame-{:04}.svg", index));
+            fs::write(&path, frame_svg(boards))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// The frames of a recorded match for [`write_frames`], one before the first move and
+/// one after every move: board A from white's side next to board B from black's, so
+/// partners sit on the same side. Clocks start at `initial` and follow the clock
+/// annotations of the moves; without a start time they are left out.
+pub fn match_frames(game: &BpgnGame, initial: Option<Duration>) -> Vec<Vec<BoardView>> {
+    let mut clocks = initial.map(|time| {
+        let both = ByColor {
+            white: time,
+            black: time,
+        };
+        [both.clone(), both]
+    });
+    let views = |boards: &BughouseGame, clocks: &Option<[ByColor<Duration>; 2]>| {
+        [BoardId::A, BoardId::B]
+            .iter()
+            .map(|&board| {
+                let view = BoardView::new(boards.board(board)).flipped(board == BoardId::B);
+                match clocks {
+                    Some(clocks) => {
+                        let clocks = &clocks[board.index()];
+                        view.with_clocks(clocks.white, clocks.black)
+                    }
+                    None => view,
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut frames = Vec::with_capacity(game.moves().len() + 1);
+    game.replay(|boards, played| {
+        frames.push(views(boards, &clocks));
+        if let (Some(clocks), Some(time)) = (&mut clocks, played.clock) {
+            *clocks[played.board.index()].by_color_mut(boards.board(played.board).turn()) = time;
+        }
+    });
+    frames.push(views(game.game(), &clocks));
+    frames
+}
+
+/// Analysis overlays for [`render_svg`].
+#[derive(Clone, Debug, Default)]
+pub struct SvgOptions {
+    /// Draw the board from black's point of view
+    pub flipped: bool,
+    /// Move to highlight with an arrow, or a ring on the target square for drops
+    pub best_move: Option<Move>,
+    /// Drop candidate squares with a strength between 0 and 1, shaded accordingly
+    pub drop_heat: Vec<(Square, f32)>,
+}
+
+/// Heat for [`SvgOptions::drop_heat`] from the priors of the legal drops: each square
+/// gets the prior of its likeliest drop, relative to the likeliest drop of all.
+pub fn drop_heat<P: PriorSource + ?Sized>(position: &Bughouse, priors: &P) -> Vec<(Square, f32)> {
+    let moves: Vec<Move> = position
+        .legal_moves()
+        .into_iter()
+        .filter(|m| matches!(m, Move::Put { .. }))
+        .collect();
+    if moves.is_empty() {
+        return Vec::new();
+    }
+    let mut heat = [0f32; 64];
+    for (m, prior) in moves.iter().zip(priors.priors(position, &moves)) {
+        let square = &mut heat[usize::from(m.to())];
+        *square = square.max(prior);
+    }
+    let hottest = heat.iter().copied().fold(0f32, f32::max);
+    if hottest <= 0f32 {
+        return Vec::new();
+    }
+    (0..64)
+        .filter(|&index| heat[index as usize] > 0f32)
+        .map(|index| (Square::new(index), heat[index as usize] / hottest))
+        .collect()
+}
+
+fn square_center(view: &BoardView, square: Square) -> (u32, u32) {
+    let (column, row) = view.coords(square);
+    (
+        column * SQUARE_SIZE + SQUARE_SIZE / 2,
+        POCKET_HEIGHT + row * SQUARE_SIZE + SQUARE_SIZE / 2,
+    )
+}
+
+fn overlays(out: &mut String, view: &BoardView, options: &SvgOptions) {
+    for &(square, heat) in &options.drop_heat {
+        let (column, row) = view.coords(square);
+        let _ = write!(
+            out,
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#e03020" fill-opacity="{:.2}"/>"##,
+            column * SQUARE_SIZE,
+            POCKET_HEIGHT + row * SQUARE_SIZE,
+            SQUARE_SIZE,
+            SQUARE_SIZE,
+            heat.clamp(0f32, 1f32) * 0.6
+        );
+    }
+    match &options.best_move {
+        Some(Move::Put { to, .. }) => {
+            let (x, y) = square_center(view, *to);
+            let _ = write!(
+                out,
+                r##"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="#15781b" stroke-width="4" stroke-opacity="0.8"/>"##,
+                x,
+                y,
+                SQUARE_SIZE / 2 - 3
+            );
+        }
+        Some(m) => {
+            if let Some(from) = m.from() {
+                // Castling moves are encoded as king takes rook; point at the king's target
+                let to = match (m.castling_side(), view.piece_at(from)) {
+                    (Some(side), Some((king, _))) => side.king_to(king.color),
+                    _ => m.to(),
+                };
+                let ((x1, y1), (x2, y2)) = (square_center(view, from), square_center(view, to));
+                let _ = write!(
+                    out,
+                    r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#15781b" stroke-width="8" stroke-opacity="0.8" marker-end="url(#arrowhead)"/>"##,
+                    x1, y1, x2, y2
+                );
+            }
+        }
+        None => {}
+    }
+}
+
+/// Renders a single position with pockets and optional analysis overlays into a
+/// standalone SVG document.
+pub fn render_svg(position: &Bughouse, options: &SvgOptions) -> String {
+    let view = BoardView::new(position).flipped(options.flipped);
+    let (width, height) = board_size();
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    );
+    out.push_str(
+        r##"<defs><marker id="arrowhead" markerWidth="4" markerHeight="4" refX="2" refY="2" orient="auto"><path d="M0,0 L4,2 L0,4 z" fill="#15781b"/></marker></defs>"##,
+    );
+    out.push_str(&board_group(&view, 0, 0));
+    overlays(&mut out, &view, options);
+    out.push_str("</svg>\n");
+    out
+}
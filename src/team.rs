@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shakmaty::{Color, Move, Setup};
+
+use crate::board::Bughouse;
+use crate::eval::{evaluate, EvalParams};
+
+// Evaluation in pawns that corresponds to odds of e to 1
+const PAWNS_PER_LOGIT: f32 = 4.0;
+
+/// Win probability of the side an evaluation in pawns is for.
+pub fn win_probability(score: f32) -> f32 {
+    1f32 / (1f32 + (-score / PAWNS_PER_LOGIT).exp())
+}
+
+/// The evaluation in pawns a win probability corresponds to, the inverse of
+/// [`win_probability`]. Certain wins and losses are capped at odds of a million to one.
+pub fn pawns(win_probability: f32) -> f32 {
+    let p = win_probability.clamp(1e-6, 1f32 - 1e-6);
+    PAWNS_PER_LOGIT * (p / (1f32 - p)).ln()
+}
+
+/// How a team agent weighs the two boards: turns our win probability on our own board
+/// and our partner's on theirs into one number to maximize. Any function of the two
+/// works, so objectives can be tried out without touching the search.
+pub trait TeamObjective: Send + Sync {
+    fn blend(&self, own: f32, partner: f32) -> f32;
+}
+
+impl<F: Fn(f32, f32) -> f32 + Send + Sync> TeamObjective for F {
+    fn blend(&self, own: f32, partner: f32) -> f32 {
+        self(own, partner)
+    }
+}
+
+/// The objectives that can be chosen by name, e.g. on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    /// Weighted mean, `own_weight` going to our own board
+    Average { own_weight: f32 },
+    /// The worse of the two boards; the team is only as strong as its weakest board
+    Weakest,
+    /// Between the mean (sharpness 0) and the weakest board (sharpness to infinity)
+    Softmin { sharpness: f32 },
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Blend::Average { own_weight: 0.5 }
+    }
+}
+
+impl TeamObjective for Blend {
+    fn blend(&self, own: f32, partner: f32) -> f32 {
+        match *self {
+            Blend::Average { own_weight } => own_weight * own + (1f32 - own_weight) * partner,
+            Blend::Weakest => own.min(partner),
+            Blend::Softmin { sharpness } => {
+                let weight = |p: f32| (-sharpness * p).exp();
+                let (own_weight, partner_weight) = (weight(own), weight(partner));
+                (own_weight * own + partner_weight * partner) / (own_weight + partner_weight)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Blend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Blend::Average { own_weight } => write!(f, "average:{}", own_weight),
+            Blend::Weakest => f.write_str("weakest"),
+            Blend::Softmin { sharpness } => write!(f, "softmin:{}", sharpness),
+        }
+    }
+}
+
+impl FromStr for Blend {
+    type Err = String;
+
+    /// `average:<own weight>`, `weakest` or `softmin:<sharpness>`.
+    fn from_str(s: &str) -> Result<Blend, String> {
+        let invalid = || format!("unknown team objective: {}", s);
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s, None),
+        };
+        let number = || -> Result<f32, String> {
+            parameter
+                .and_then(|parameter| parameter.parse().ok())
+                .filter(|value: &f32| value.is_finite())
+                .ok_or_else(invalid)
+        };
+        match name {
+            "average" => {
+                let own_weight = number()?;
+                if !(0f32..=1f32).contains(&own_weight) {
+                    return Err(invalid());
+                }
+                Ok(Blend::Average { own_weight })
+            }
+            "weakest" if parameter.is_none() => Ok(Blend::Weakest),
+            "softmin" => Ok(Blend::Softmin {
+                sharpness: number()?.max(0f32),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Evaluation of `position` in pawns for `color`, whoever is to move
+fn score_for(position: &Bughouse, color: Color, params: &EvalParams) -> f32 {
+    let score = evaluate(position, params);
+    if position.turn() == color {
+        score
+    } else {
+        -score
+    }
+}
+
+/// Team value of both boards for a team playing `own_color` on `own` and the other
+/// color on `partner`, as `objective` blends their win probabilities.
+pub fn team_value(
+    objective: &dyn TeamObjective,
+    own: &Bughouse,
+    own_color: Color,
+    partner: &Bughouse,
+    params: &EvalParams,
+) -> f32 {
+    objective.blend(
+        win_probability(score_for(own, own_color, params)),
+        win_probability(score_for(partner, !own_color, params)),
+    )
+}
+
+/// The move among `candidates` that is best for the team by `objective`, for the side
+/// to move on `own`. Each is played with the piece it captures passed on to the
+/// partner on `partner`, and both boards are scored with [`team_value`], so a move
+/// that is worse on our board can win out by what it does for the partner's.
+pub fn best_for_team(
+    objective: &dyn TeamObjective,
+    own: &Bughouse,
+    partner: &Bughouse,
+    candidates: &[Move],
+    params: &EvalParams,
+) -> Option<Move> {
+    let color = own.turn();
+    candidates
+        .iter()
+        .map(|m| {
+            let mut after = own.clone();
+            let mut partner = partner.clone();
+            if let Some(role) = after.play_passing_captures(m) {
+                partner.receive(!color, role);
+            }
+            (m, team_value(objective, &after, color, &partner, params))
+        })
+        .fold(None, |best: Option<(&Move, f32)>, (m, value)| match best {
+            Some((_, best_value)) if best_value >= value => best,
+            _ => Some((m, value)),
+        })
+        .map(|(m, _)| m.clone())
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::opponent::OpponentModel;
+
+/// Decides how long to think about a move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeManager {
+    /// Moves the remaining time is expected to last for
+    pub moves_to_go: u32,
+    /// Kept in reserve so lag never flags us
+    pub safety_margin: Duration,
+    pub min_time: Duration,
+}
+
+impl Default for TimeManager {
+    fn default() -> Self {
+        TimeManager {
+            moves_to_go: 30,
+            safety_margin: Duration::from_millis(500),
+            min_time: Duration::from_millis(50),
+        }
+    }
+}
+
+impl TimeManager {
+    /// Search time for the next move with `remaining` on our clock and `increment`
+    /// added after each move. Against an opponent known to move faster than our
+    /// budget we follow their pace down to half of it, since in bughouse falling
+    /// behind on the clock hurts the partner too.
+    pub fn allot(
+        &self,
+        remaining: Duration,
+        increment: Duration,
+        opponent: Option<&OpponentModel>,
+    ) -> Duration {
+        let usable = remaining.saturating_sub(self.safety_margin);
+        let mut budget = usable / self.moves_to_go.max(1) + increment;
+        if let Some(pace) = opponent.and_then(OpponentModel::average_move_time) {
+            budget = budget.min(pace.max(budget / 2));
+        }
+        budget.max(self.min_time).min(usable)
+    }
+}
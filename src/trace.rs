@@ -0,0 +1,142 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Parts of a search iteration that are timed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Selection,
+    Expansion,
+    Rollout,
+    Backpropagation,
+}
+
+const PHASES: [Phase; 4] = [
+    Phase::Selection,
+    Phase::Expansion,
+    Phase::Rollout,
+    Phase::Backpropagation,
+];
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Selection => "selection",
+            Phase::Expansion => "expansion",
+            Phase::Rollout => "rollout",
+            Phase::Backpropagation => "backpropagation",
+        }
+    }
+}
+
+// One complete event of the chrome tracing format, times in microseconds
+#[derive(Clone, Debug)]
+struct Event {
+    name: String,
+    start: u64,
+    duration: u64,
+}
+
+/// Collects search phase timings and writes them in the chrome tracing format, for
+/// viewing in `chrome://tracing` or Perfetto.
+///
+/// Single iterations are too short to look at, so the time spent in each phase is
+/// summed over batches of [`Trace::batch_size`] iterations. Each batch becomes one
+/// span with the phases laid out one after another inside it.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub batch_size: u32,
+    start: Instant,
+    events: Vec<Event>,
+    batch_start: Instant,
+    batch_iterations: u32,
+    batch_phases: [Duration; 4],
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace::new()
+    }
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        let now = Instant::now();
+        Trace {
+            batch_size: 64,
+            start: now,
+            events: Vec::new(),
+            batch_start: now,
+            batch_iterations: 0,
+            batch_phases: [Duration::from_secs(0); 4],
+        }
+    }
+
+    fn micros_since_start(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.start).as_micros() as u64
+    }
+
+    /// Adds `elapsed` to the time spent in `phase` in the current batch.
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let index = PHASES
+            .iter()
+            .position(|&p| p == phase)
+            .expect("known phase");
+        self.batch_phases[index] += elapsed;
+    }
+
+    /// Marks the end of an iteration, closing the batch when it is full.
+    pub fn end_iteration(&mut self) {
+        self.batch_iterations += 1;
+        if self.batch_iterations >= self.batch_size {
+            self.flush();
+        }
+    }
+
+    /// Closes the current batch early, e.g. when a search stops.
+    pub fn flush(&mut self) {
+        if self.batch_iterations == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let start = self.micros_since_start(self.batch_start);
+        self.events.push(Event {
+            name: format!("{} iterations", self.batch_iterations),
+            start,
+            duration: self.micros_since_start(now).saturating_sub(start),
+        });
+        let mut phase_start = start;
+        for (phase, elapsed) in PHASES.iter().zip(self.batch_phases) {
+            let duration = elapsed.as_micros() as u64;
+            self.events.push(Event {
+                name: phase.name().to_string(),
+                start: phase_start,
+                duration,
+            });
+            phase_start += duration;
+        }
+        self.batch_start = now;
+        self.batch_iterations = 0;
+        self.batch_phases = [Duration::from_secs(0); 4];
+    }
+
+    /// The trace as a chrome tracing JSON document.
+    pub fn to_json(&self) -> String {
+        let events: Vec<String> = self
+            .events
+            .iter()
+            .map(|event| {
+                format!(
+                    "{{\"name\":\"{}\",\"cat\":\"search\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":1}}",
+                    event.name, event.start, event.duration
+                )
+            })
+            .collect();
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use shakmaty::{Color, Move, Position, Role, Setup};
+
+use crate::board::Bughouse;
+use crate::eval::{EvalParams, TradeWeights};
+use crate::prior::PriorSource;
+
+/// What a capture hands the opponent's partner if it turns into a trade: the pocket
+/// value of the capturing piece when the opponent can take it back, 0 for any other
+/// move. A promoted piece counts as the pawn it goes back to the pocket as.
+pub fn exposed_material(position: &Bughouse, m: &Move, params: &EvalParams) -> f32 {
+    if !m.is_capture() {
+        return 0f32;
+    }
+    let mut after = position.clone();
+    after.play_unchecked(m);
+    let board = after.board();
+    let to = m.to();
+    if board
+        .attacks_to(to, after.turn(), board.occupied())
+        .is_empty()
+    {
+        return 0f32;
+    }
+    let role = match board.role_at(to) {
+        Some(_) if board.promoted().contains(to) => Role::Pawn,
+        Some(role) => role,
+        None => return 0f32,
+    };
+    params.pocket.by_role(role)
+}
+
+/// Penalty per pawn of exposed material: the style setting plus the partner term
+/// scaled by `partner_danger`, the danger score of our partner's king when playing a
+/// match.
+pub fn trade_penalty(weights: &TradeWeights, partner_danger: Option<f32>) -> f32 {
+    let danger = partner_danger.unwrap_or(0f32).clamp(0f32, 1f32);
+    (weights.style + weights.partner_danger * danger).max(0f32)
+}
+
+/// Wraps another prior source, dividing the prior of each trade offered by `color` by
+/// one plus the penalty times the material it exposes. The opponent's moves are left
+/// alone: trades feeding their partner is what they are after.
+pub struct TradePriors<P> {
+    pub inner: P,
+    pub params: Arc<EvalParams>,
+    pub color: Color,
+    /// See [`trade_penalty`]
+    pub penalty: f32,
+}
+
+impl<P: PriorSource> PriorSource for TradePriors<P> {
+    fn priors(&self, position: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        let mut priors = self.inner.priors(position, moves);
+        if position.turn() != self.color || self.penalty <= 0f32 {
+            return priors;
+        }
+        for (prior, m) in priors.iter_mut().zip(moves) {
+            *prior /= 1f32 + self.penalty * exposed_material(position, m, &self.params);
+        }
+        let total: f32 = priors.iter().sum();
+        if total > 0f32 {
+            priors.iter_mut().for_each(|prior| *prior /= total);
+        }
+        priors
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use shakmaty::fen::{epd, fen};
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Outcome, Setup};
+
+use crate::board::Bughouse;
+use crate::build_info::BuildInfo;
+use crate::engine::SearchOptions;
+use crate::eval::EvalParams;
+
+/// What the search found in one position of a game.
+#[derive(Clone, Debug)]
+pub struct SearchRecord {
+    pub position: Bughouse,
+    /// Visits of each root move, `None` being a pass
+    pub visits: Vec<(Option<Move>, u32)>,
+    /// Improved policy to train towards in place of the visits, as Gumbel root search
+    /// gives one
+    pub policy: Option<Vec<(Option<Move>, f32)>>,
+    /// Expected score of the side to move according to the search, from 0 to 1
+    pub value: f32,
+}
+
+/// How visit counts turn into the policy the network is trained towards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PolicyTarget {
+    /// Each move's share of the root visits
+    Visits,
+    /// Visit counts raised to `1 / temperature` before normalizing; below 1 sharpens
+    /// the target towards the most visited move
+    Temperature(f32),
+}
+
+impl fmt::Display for PolicyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyTarget::Visits => write!(f, "visits"),
+            PolicyTarget::Temperature(temperature) => write!(f, "temperature {}", temperature),
+        }
+    }
+}
+
+/// Filters against near-identical data from low-temperature self-play.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DedupOptions {
+    /// Skip games whose positions all repeat an earlier game
+    pub games: bool,
+    /// Skip positions already exported from another game
+    pub positions: bool,
+    /// Plies identifying an opening for `max_per_opening`
+    pub opening_plies: usize,
+    /// Games kept per opening, unlimited if `None`
+    pub max_per_opening: Option<u32>,
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        DedupOptions {
+            games: true,
+            positions: false,
+            opening_plies: 8,
+            max_per_opening: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    pub games: u32,
+    pub duplicate_games: u32,
+    /// Games skipped because their opening reached `max_per_opening`
+    pub opening_limited: u32,
+    pub positions: u32,
+    pub duplicate_positions: u32,
+}
+
+impl fmt::Display for DedupStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} games, {} duplicates, {} over the opening limit; {} positions, {} duplicates",
+            self.games,
+            self.duplicate_games,
+            self.opening_limited,
+            self.positions,
+            self.duplicate_positions
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub policy: PolicyTarget,
+    /// Weight of the game result in the value target, the rest being the search value
+    pub outcome_weight: f32,
+    pub dedup: DedupOptions,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            policy: PolicyTarget::Visits,
+            outcome_weight: 1.0,
+            dedup: DedupOptions::default(),
+        }
+    }
+}
+
+// Hashes the positions of a game, which identify it as well as its moves do
+fn hash_positions(fens: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    fens.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Turns searched games into training samples. The output starts with `#` lines
+/// recording the build, export options, search options and weights, so the training
+/// pipeline can reweight the data later. Every other line is one position:
+/// `<fen>;<uci>:<probability> ...;<value>`.
+#[derive(Clone, Debug)]
+pub struct TrainingExporter {
+    options: ExportOptions,
+    header: String,
+    samples: Vec<String>,
+    games: HashSet<u64>,
+    openings: HashMap<u64, u32>,
+    positions: HashSet<String>,
+    stats: DedupStats,
+}
+
+impl TrainingExporter {
+    pub fn new(
+        options: ExportOptions,
+        params: &EvalParams,
+        search: &SearchOptions,
+    ) -> TrainingExporter {
+        let mut header = format!(
+            "# engine {}\n# policy_target {}\n# outcome_weight {}\n# visit_decay {}\n",
+            BuildInfo::new(params),
+            options.policy,
+            options.outcome_weight,
+            search.visit_decay
+        );
+        if let Some(theme) = search.sparring {
+            header.push_str(&format!("# sparring {}\n", theme));
+        }
+        for line in params.to_text().lines() {
+            header.push_str(&format!("# {}\n", line));
+        }
+        TrainingExporter {
+            options,
+            header,
+            samples: Vec::new(),
+            games: HashSet::new(),
+            openings: HashMap::new(),
+            positions: HashSet::new(),
+            stats: DedupStats::default(),
+        }
+    }
+
+    fn policy(&self, visits: &[(Option<Move>, u32)]) -> Vec<f32> {
+        let weights: Vec<f32> = visits
+            .iter()
+            .map(|&(_, count)| match self.options.policy {
+                PolicyTarget::Visits => count as f32,
+                PolicyTarget::Temperature(temperature) => {
+                    (count as f32).powf(1f32 / temperature.max(f32::EPSILON))
+                }
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total > 0f32 {
+            weights.iter().map(|weight| weight / total).collect()
+        } else {
+            weights
+        }
+    }
+
+    pub fn dedup_stats(&self) -> &DedupStats {
+        &self.stats
+    }
+
+    /// Adds the positions of a game that ended with `outcome`. Returns `false` if the
+    /// game was skipped as a duplicate.
+    pub fn add_game(&mut self, records: &[SearchRecord], outcome: Outcome) -> bool {
+        let dedup = &self.options.dedup;
+        let fens: Vec<String> = records.iter().map(|record| fen(&record.position)).collect();
+        self.stats.games += 1;
+        if dedup.games && !self.games.insert(hash_positions(&fens)) {
+            self.stats.duplicate_games += 1;
+            return false;
+        }
+        if let Some(max) = dedup.max_per_opening {
+            let opening = hash_positions(&fens[..dedup.opening_plies.min(fens.len())]);
+            let count = self.openings.entry(opening).or_insert(0);
+            if *count >= max {
+                self.stats.opening_limited += 1;
+                return false;
+            }
+            *count += 1;
+        }
+
+        for (record, fen) in records.iter().zip(fens) {
+            self.stats.positions += 1;
+            // Move counters don't make a position different
+            if self.options.dedup.positions && !self.positions.insert(epd(&record.position)) {
+                self.stats.duplicate_positions += 1;
+                continue;
+            }
+            let result = match outcome {
+                Outcome::Decisive { winner } if winner == record.position.turn() => 1f32,
+                Outcome::Decisive { .. } => 0f32,
+                Outcome::Draw => 0.5,
+            };
+            let weight = self.options.outcome_weight;
+            let value = weight * result + (1f32 - weight) * record.value;
+            let targets: Vec<(&Option<Move>, f32)> = match &record.policy {
+                Some(policy) => policy.iter().map(|(m, p)| (m, *p)).collect(),
+                None => record
+                    .visits
+                    .iter()
+                    .map(|(m, _)| m)
+                    .zip(self.policy(&record.visits))
+                    .collect(),
+            };
+            let policy: Vec<String> = targets
+                .into_iter()
+                .map(|(m, probability)| {
+                    let uci = match m {
+                        Some(m) => Uci::from_standard(m),
+                        None => Uci::Null,
+                    };
+                    format!("{}:{:.4}", uci, probability)
+                })
+                .collect();
+            self.samples
+                .push(format!("{};{};{:.4}", fen, policy.join(" "), value));
+        }
+        true
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = self.header.clone();
+        for sample in &self.samples {
+            text.push_str(sample);
+            text.push('\n');
+        }
+        text
+    }
+}
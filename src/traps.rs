@@ -0,0 +1,214 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use shakmaty::fen::epd;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position};
+
+use crate::board::{parse_fen, Bughouse, FenError, IllegalMove};
+use crate::paths::write_atomic;
+
+#[derive(Debug)]
+pub enum TrapError {
+    Io(io::Error),
+    /// A saved trap whose position doesn't read back
+    Fen(FenError),
+    /// A trap line with missing fields, or a name that can't be stored
+    InvalidTrap(String),
+    /// A move of the trap that can't be played in its line
+    IllegalMove(IllegalMove),
+}
+
+impl fmt::Display for TrapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapError::Io(err) => write!(f, "trap book storage error: {}", err),
+            TrapError::Fen(err) => write!(f, "invalid trap fen: {}", err),
+            TrapError::InvalidTrap(line) => write!(f, "invalid trap: {}", line),
+            TrapError::IllegalMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for TrapError {}
+
+impl From<io::Error> for TrapError {
+    fn from(err: io::Error) -> Self {
+        TrapError::Io(err)
+    }
+}
+
+impl From<FenError> for TrapError {
+    fn from(err: FenError) -> Self {
+        TrapError::Fen(err)
+    }
+}
+
+impl From<IllegalMove> for TrapError {
+    fn from(err: IllegalMove) -> Self {
+        TrapError::IllegalMove(err)
+    }
+}
+
+/// An opening trap: a natural looking move that loses by force, and how to avoid it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trap {
+    /// Short name for reports, like `B@h6`
+    pub name: String,
+    /// The position the trap is set in, without move counters
+    pub epd: String,
+    /// The move that falls for the trap
+    pub bait: Move,
+    /// How the opponent punishes it, starting with their reply to the bait
+    pub punishment: Vec<Move>,
+    /// What to play instead of the bait
+    pub refutation: Move,
+}
+
+impl Trap {
+    /// Builds a trap from analysis, checking that all moves are legal in order.
+    pub fn new(
+        name: &str,
+        position: &Bughouse,
+        bait: Move,
+        punishment: Vec<Move>,
+        refutation: Move,
+    ) -> Result<Trap, TrapError> {
+        let illegal = |m: &Move| TrapError::IllegalMove(IllegalMove::of(m));
+        if name.trim().is_empty() || name.contains(['\t', '\n']) {
+            return Err(TrapError::InvalidTrap(name.to_string()));
+        }
+        if !position.is_legal(&refutation) {
+            return Err(illegal(&refutation));
+        }
+        let mut line = position.clone();
+        for m in std::iter::once(&bait).chain(&punishment) {
+            if !line.is_legal(m) {
+                return Err(illegal(m));
+            }
+            line.play_unchecked(m);
+        }
+        Ok(Trap {
+            name: name.trim().to_string(),
+            epd: epd(position),
+            bait,
+            punishment,
+            refutation,
+        })
+    }
+
+    pub fn position(&self) -> Result<Bughouse, FenError> {
+        parse_fen(&self.epd)
+    }
+}
+
+fn uci_list(moves: &[Move]) -> String {
+    moves
+        .iter()
+        .map(|m| Uci::from_standard(m).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Known opening traps keyed by position. When opened on a file, every change is
+/// written through to it, one trap per line: EPD, name, bait, punishment line and
+/// refutation, separated by tabs, moves in UCI notation.
+#[derive(Debug, Default)]
+pub struct TrapBook {
+    path: Option<PathBuf>,
+    traps: Vec<Trap>,
+}
+
+impl TrapBook {
+    pub fn in_memory() -> TrapBook {
+        TrapBook::default()
+    }
+
+    /// Opens the book saved at `path`, or an empty one if the file doesn't exist yet.
+    pub fn open(path: &Path) -> Result<TrapBook, TrapError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut traps = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            traps.push(parse_trap(line)?);
+        }
+        Ok(TrapBook {
+            path: Some(path.to_path_buf()),
+            traps,
+        })
+    }
+
+    pub fn list(&self) -> &[Trap] {
+        &self.traps
+    }
+
+    /// Adds `trap`, replacing a trap with the same position and bait.
+    pub fn add(&mut self, trap: Trap) -> Result<(), TrapError> {
+        self.traps
+            .retain(|known| known.epd != trap.epd || known.bait != trap.bait);
+        self.traps.push(trap);
+        self.save()
+    }
+
+    /// Traps set in `position`.
+    pub fn traps_in(&self, position: &Bughouse) -> Vec<&Trap> {
+        let epd = epd(position);
+        self.traps.iter().filter(|trap| trap.epd == epd).collect()
+    }
+
+    /// The trap `m` falls for in `position`, if any.
+    pub fn fallen_for(&self, position: &Bughouse, m: &Move) -> Option<&Trap> {
+        self.traps_in(position)
+            .into_iter()
+            .find(|trap| trap.bait == *m)
+    }
+
+    fn save(&self) -> Result<(), TrapError> {
+        if let Some(path) = &self.path {
+            let mut text = String::new();
+            for trap in &self.traps {
+                text.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\n",
+                    trap.epd,
+                    trap.name,
+                    Uci::from_standard(&trap.bait),
+                    uci_list(&trap.punishment),
+                    Uci::from_standard(&trap.refutation)
+                ));
+            }
+            write_atomic(path, text)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_trap(line: &str) -> Result<Trap, TrapError> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let (fen, name, bait, punishment, refutation) = match fields.as_slice() {
+        [fen, name, bait, punishment, refutation] => (*fen, *name, *bait, *punishment, *refutation),
+        _ => return Err(TrapError::InvalidTrap(line.to_string())),
+    };
+    let position = parse_fen(fen)?;
+    let parse = |position: &Bughouse, uci: &str| {
+        uci.parse::<Uci>()
+            .ok()
+            .and_then(|parsed| parsed.to_move(position).ok())
+            .ok_or_else(|| IllegalMove(uci.to_string()))
+    };
+    let bait = parse(&position, bait)?;
+    let refutation = parse(&position, refutation)?;
+    let mut line = position.clone();
+    line.play_unchecked(&bait);
+    let mut moves = Vec::new();
+    for uci in punishment.split_whitespace() {
+        let m = parse(&line, uci)?;
+        line.play_unchecked(&m);
+        moves.push(m);
+    }
+    Trap::new(name, &position, bait, moves, refutation)
+}
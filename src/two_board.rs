@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use shakmaty::{Move, Outcome, Position, Setup};
+
+use crate::board::{BoardId, IllegalMove};
+use crate::drops::Pockets;
+use crate::match_log::{MatchEvent, MatchLogError, MatchState};
+
+/// An event on one board, stamped with the match time it happened at as seen by the
+/// server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedEvent {
+    pub board: BoardId,
+    /// Time since the start of the match
+    pub at: Duration,
+    pub event: MatchEvent,
+}
+
+impl TimedEvent {
+    pub fn new(board: BoardId, at: Duration, event: MatchEvent) -> TimedEvent {
+        TimedEvent { board, at, event }
+    }
+
+    /// A move event, the common case.
+    pub fn play(board: BoardId, at: Duration, m: Move, elapsed: Duration) -> TimedEvent {
+        TimedEvent::new(board, at, MatchEvent::Move { m, elapsed })
+    }
+}
+
+/// Puts events from both boards into the order they count in: by match time, then
+/// board A before board B, then in the order they arrived. The order only depends on
+/// the events, so every replay of the same events ends in the same state.
+pub fn order_events(events: &mut [TimedEvent]) {
+    // Stable, so arrival order breaks the remaining ties
+    events.sort_by_key(|event| (event.at, event.board));
+}
+
+/// Both boards of a bughouse match. Captures go to the partner on the other board
+/// instead of to the capturer's own pocket, and the first board to finish decides the
+/// match.
+#[derive(Clone, Debug)]
+pub struct TwoBoardMatch {
+    boards: [MatchState; 2],
+    outcome: Option<(BoardId, Outcome)>,
+    // Order key of the last event applied
+    last: Option<(Duration, BoardId)>,
+}
+
+impl TwoBoardMatch {
+    pub fn new(a: MatchState, b: MatchState) -> TwoBoardMatch {
+        TwoBoardMatch {
+            boards: [a, b],
+            outcome: None,
+            last: None,
+        }
+    }
+
+    /// Replays `events` from the start states in the order of [`order_events`],
+    /// whatever order they arrived in.
+    pub fn replay(
+        a: MatchState,
+        b: MatchState,
+        mut events: Vec<TimedEvent>,
+    ) -> Result<TwoBoardMatch, MatchLogError> {
+        order_events(&mut events);
+        let mut game = TwoBoardMatch::new(a, b);
+        for event in &events {
+            game.apply(event)?;
+        }
+        Ok(game)
+    }
+
+    pub fn board(&self, board: BoardId) -> &MatchState {
+        &self.boards[board.index()]
+    }
+
+    /// The board that ended the match and its result there.
+    pub fn outcome(&self) -> Option<(BoardId, Outcome)> {
+        self.outcome
+    }
+
+    /// Applies one event, which must not come before events already applied. Once
+    /// either board has finished, moves are refused on both.
+    pub fn apply(&mut self, event: &TimedEvent) -> Result<(), MatchLogError> {
+        let key = (event.at, event.board);
+        if self.last.is_some_and(|last| key < last) {
+            return Err(MatchLogError::InvalidEvent(format!(
+                "{:?} on board {:?} out of order",
+                event.event, event.board
+            )));
+        }
+        let index = event.board.index();
+        if let MatchEvent::Move { m, .. } = &event.event {
+            if self.outcome.is_some() {
+                return Err(IllegalMove::of(m).into());
+            }
+        }
+        let state = &mut self.boards[index];
+        let mover = state.position.turn();
+        let captured = match &event.event {
+            MatchEvent::Move { m, .. } if state.position.is_legal(m) => {
+                Pockets::captured_role(state.position.board(), m)
+            }
+            _ => None,
+        };
+        state.apply(&event.event)?;
+        self.last = Some(key);
+        if let Some(role) = captured {
+            // Crazyhouse rules put the piece into the capturer's own pocket
+            state.position.remove_from_pocket(mover, role);
+            // The partner of the capturer has the other color on the other board
+            self.boards[1 - index].apply(&MatchEvent::Transfer {
+                color: !mover,
+                role,
+            })?;
+        }
+        if self.outcome.is_none() {
+            if let Some(outcome) = self.boards[index].outcome {
+                self.outcome = Some((event.board, outcome));
+            }
+        }
+        Ok(())
+    }
+}
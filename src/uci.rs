@@ -0,0 +1,657 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Setup};
+
+use crate::board::{Bughouse, RulePreset, PRESETS};
+use crate::book::OpeningBook;
+use crate::build_info::BuildInfo;
+use crate::cancel::CancelToken;
+use crate::compat;
+use crate::config::EngineConfig;
+use crate::engine::{Analysis, Engine, RootFilter, SearchOptions};
+use crate::eval::EvalHandle;
+use crate::limits::{SearchControl, SearchLimits};
+#[cfg(feature = "nn")]
+use crate::nn::Network;
+use crate::protocol::command_lines;
+use crate::remote::RemoteEngine;
+use crate::resign::ResignPolicy;
+use crate::team::pawns;
+use crate::time::TimeManager;
+use crate::variant::{Variant, VariantPosition};
+
+const MAX_THREADS: usize = 256;
+
+// How often an infinite search that has run out of work checks for `stop`
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Time given to a remote search when `go` limits nodes or depth, which the remote
+// protocol can't express
+const REMOTE_DEFAULT_TIME: Duration = Duration::from_secs(1);
+
+// Most visited root moves counted in the drop statistics sent after each search
+const DROP_CANDIDATES: usize = 5;
+
+/// The engine side of the UCI protocol, for GUIs and match runners like cutechess-cli.
+///
+/// Searches run on their own thread so `stop` and `isready` are answered while they
+/// think. Moves are written in UCI notation, drops as `P@e4`. `go searchmoves`
+/// restricts the search to the moves listed and `go excludemoves` leaves them out.
+/// The search tree is kept from move to move, and `go ponder` searches the expected
+/// position during the opponent's time until `ponderhit` turns it into the real
+/// search. Each search reports the drops among its best moves as `info string drops`,
+/// see [`crate::drop_stats::DropStats::summary`], and how often the rollout move
+/// tables were of use as `info string rollouts`. With a `BookFile`, positions in the
+/// book are answered with a book move without searching. With a `RemoteEngine`
+/// address, timed searches run on that [`RemoteEngine`] server instead, and locally
+/// if it can't be reached. The `ReloadEvalFile` button re-reads the last `EvalFile`,
+/// so retuned weights apply to the next search without restarting the engine.
+///
+/// The search settings of [`EngineConfig`] are the options `Exploration`,
+/// `RootStrategy`, `PlayoutCap`, `MaxTreeSize` and `Seed`, and `ConfigFile` loads all
+/// of them at once along with the weights. A seed of 0 seeds every search from the
+/// time.
+///
+/// A `position` the engine can't set up is reported with `info string`, and searches
+/// answer `bestmove 0000` until the GUI sends one that works, rather than playing on
+/// from a position the GUI isn't in.
+pub struct UciEngine<W> {
+    output: Arc<Mutex<W>>,
+    eval: EvalHandle,
+    time: TimeManager,
+    threads: usize,
+    // The weights are in `eval`, which `EvalFile` may replace
+    config: EngineConfig,
+    preset: RulePreset,
+    // Repair near-miss FENs and variant names instead of rejecting them
+    compatibility: bool,
+    position: VariantPosition,
+    // Why the last `position` command was rejected
+    position_error: Option<String>,
+    // Kept between searches for its tree, handed to the search thread meanwhile
+    engine: Option<Engine>,
+    search: Option<Search>,
+    // Set with the `BookFile` option, probed before every search
+    book: Option<OpeningBook>,
+    // Set with the `RemoteEngine` option, shared with the search thread
+    remote: Option<Arc<Mutex<RemoteEngine>>>,
+    // Set with the `ResignThreshold` option; the GUI decides whether to act on it
+    resign: Option<ResignPolicy>,
+    // Set with the `NetworkFile` option, replacing rollouts and the static priors
+    #[cfg(feature = "nn")]
+    network: Option<Arc<Network>>,
+}
+
+// A search running on its own thread, which hands the engine back when done
+struct Search {
+    cancel: CancelToken,
+    // Set on `ponderhit`, which ends the ponder search without a best move
+    silent: Arc<AtomicBool>,
+    handle: JoinHandle<Engine>,
+    // The `go ponder` command without `ponder`, to search with once the move is played
+    ponder: Option<Vec<String>>,
+}
+
+impl<W: Write + Send + 'static> UciEngine<W> {
+    pub fn new(output: W, eval: EvalHandle) -> UciEngine<W> {
+        UciEngine {
+            output: Arc::new(Mutex::new(output)),
+            eval,
+            time: TimeManager::default(),
+            threads: 1,
+            config: EngineConfig::default(),
+            preset: RulePreset::default(),
+            compatibility: true,
+            position: VariantPosition::start(Variant::default()),
+            position_error: None,
+            engine: None,
+            search: None,
+            book: None,
+            remote: None,
+            resign: None,
+            #[cfg(feature = "nn")]
+            network: None,
+        }
+    }
+
+    /// Applies `config`, including its weights, to the following searches.
+    pub fn set_config(&mut self, config: EngineConfig) {
+        self.eval.set_params(config.eval.clone());
+        self.config = config;
+        self.engine = None;
+    }
+
+    /// Handles commands from `input` until `quit` or the end of input.
+    pub fn run<R: BufRead>(&mut self, input: R) -> io::Result<()> {
+        for line in command_lines(input) {
+            if !self.handle(&line?)? {
+                break;
+            }
+        }
+        self.stop();
+        Ok(())
+    }
+
+    /// Handles one command. Returns `false` once the GUI has sent `quit`. Unknown
+    /// commands are ignored, as the protocol asks.
+    pub fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("uci") => {
+                let name = BuildInfo::new(&self.eval.params()).name();
+                self.send(&format!("id name {}", name))?;
+                self.send("id author the ladybug developers")?;
+                self.send(&Variant::uci_option())?;
+                self.send("option name EvalFile type string default <empty>")?;
+                self.send("option name ReloadEvalFile type button")?;
+                self.send("option name BookFile type string default <empty>")?;
+                self.send("option name RemoteEngine type string default <empty>")?;
+                #[cfg(feature = "nn")]
+                self.send("option name NetworkFile type string default <empty>")?;
+                self.send(&format!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                ))?;
+                self.send(&format!(
+                    "option name Exploration type string default {}",
+                    self.config.exploration
+                ))?;
+                self.send(&format!(
+                    "option name RootStrategy type combo default {} var uct var halving",
+                    self.config.root_strategy
+                ))?;
+                self.send("option name PlayoutCap type spin default 0 min 0 max 1000000000")?;
+                self.send("option name MaxTreeSize type spin default 0 min 0 max 1000000000")?;
+                self.send("option name Seed type spin default 0 min 0 max 2147483647")?;
+                self.send("option name ResignThreshold type spin default 0 min 0 max 100")?;
+                self.send("option name ConfigFile type string default <empty>")?;
+                self.send(&rules_option())?;
+                self.send("option name Compatibility type check default true")?;
+                self.send("option name Ponder type check default false")?;
+                self.send("uciok")?;
+            }
+            Some("isready") => self.send("readyok")?,
+            Some("ucinewgame") => {
+                self.stop();
+                self.position =
+                    VariantPosition::start(self.position.variant()).with_preset(self.preset);
+                self.position_error = None;
+                if let Some(engine) = &mut self.engine {
+                    engine.clear_tree();
+                }
+            }
+            Some("setoption") => {
+                self.stop();
+                if let Err(err) = self.set_option(line) {
+                    self.send(&format!("info string {}", err))?;
+                }
+            }
+            Some("position") => {
+                self.stop();
+                match self.parse_position(words.collect()) {
+                    Ok(position) => {
+                        self.position = position;
+                        self.position_error = None;
+                    }
+                    Err(err) => {
+                        self.send(&format!("info string {}", err))?;
+                        self.position_error = Some(err);
+                    }
+                }
+            }
+            Some("go") => {
+                self.stop();
+                match &self.position_error {
+                    Some(err) => {
+                        self.send(&format!("info string no position to search: {}", err))?;
+                        self.send("bestmove 0000")?;
+                    }
+                    None => self.go(words.collect()),
+                }
+            }
+            Some("ponderhit") => self.ponderhit(),
+            Some("stop") => self.stop(),
+            Some("quit") => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn send(&self, line: &str) -> io::Result<()> {
+        send(&self.output, line)
+    }
+
+    // `setoption name <name> value <value>`, where both may contain spaces
+    fn set_option(&mut self, line: &str) -> Result<(), String> {
+        let rest = line.trim().strip_prefix("setoption").unwrap_or("").trim();
+        let rest = rest
+            .strip_prefix("name")
+            .ok_or_else(|| format!("malformed setoption: {}", line.trim()))?;
+        let (name, value) = match rest.split_once(" value ") {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (rest.trim(), ""),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "uci_variant" => {
+                let variant = match compat::variant_alias(value) {
+                    Some(variant) if self.compatibility => variant,
+                    _ => value.parse()?,
+                };
+                if variant != self.position.variant() {
+                    self.position = VariantPosition::start(variant).with_preset(self.preset);
+                    self.position_error = None;
+                }
+                Ok(())
+            }
+            "rules" => {
+                self.preset = value.parse()?;
+                self.position = self.position.clone().with_preset(self.preset);
+                Ok(())
+            }
+            "compatibility" => {
+                self.compatibility = value
+                    .parse()
+                    .map_err(|_| format!("invalid compatibility setting: {}", value))?;
+                Ok(())
+            }
+            // Whether the GUI lets the engine ponder, which it asks for with `go ponder`
+            "ponder" => value
+                .parse::<bool>()
+                .map(|_| ())
+                .map_err(|_| format!("invalid ponder setting: {}", value)),
+            "evalfile" if value.is_empty() || value == "<empty>" => Ok(()),
+            "evalfile" => self
+                .eval
+                .load(Path::new(value))
+                .map_err(|err| err.to_string()),
+            "reloadevalfile" => self.eval.reload().map_err(|err| err.to_string()),
+            "bookfile" if value.is_empty() || value == "<empty>" => {
+                self.book = None;
+                Ok(())
+            }
+            "bookfile" => {
+                let book = OpeningBook::load(Path::new(value)).map_err(|err| err.to_string())?;
+                self.book = Some(book);
+                Ok(())
+            }
+            "remoteengine" if value.is_empty() || value == "<empty>" => {
+                self.remote = None;
+                Ok(())
+            }
+            // Connects lazily, so a server that is down only shows once searching
+            "remoteengine" => {
+                self.remote = Some(Arc::new(Mutex::new(RemoteEngine::new(value))));
+                Ok(())
+            }
+            // The kept tree was grown with the old network's values
+            #[cfg(feature = "nn")]
+            "networkfile" if value.is_empty() || value == "<empty>" => {
+                self.network = None;
+                self.engine = None;
+                Ok(())
+            }
+            #[cfg(feature = "nn")]
+            "networkfile" => {
+                let network = Network::load(Path::new(value)).map_err(|err| err.to_string())?;
+                self.network = Some(Arc::new(network));
+                self.engine = None;
+                Ok(())
+            }
+            "threads" => {
+                self.threads = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|threads| (1..=MAX_THREADS).contains(threads))
+                    .ok_or_else(|| format!("invalid thread count: {}", value))?;
+                Ok(())
+            }
+            "exploration" => {
+                self.config.exploration = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|exploration| *exploration >= 0f32)
+                    .ok_or_else(|| format!("invalid exploration constant: {}", value))?;
+                Ok(())
+            }
+            "rootstrategy" => {
+                self.config.root_strategy = value.parse()?;
+                Ok(())
+            }
+            // 0 for no cap, since spin options can't be empty
+            "playoutcap" => {
+                let cap = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid playout cap: {}", value))?;
+                self.config.playout_cap = Some(cap).filter(|&cap| cap > 0);
+                Ok(())
+            }
+            "maxtreesize" => {
+                let cap = value
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid tree size: {}", value))?;
+                self.config.max_tree_size = Some(cap).filter(|&cap| cap > 0);
+                Ok(())
+            }
+            // Searches go on from the seed, so a new one needs a new engine
+            "seed" => {
+                self.config.seed = value
+                    .parse()
+                    .map_err(|_| format!("invalid seed: {}", value))?;
+                self.engine = None;
+                Ok(())
+            }
+            // A percentage of expected score, 0 for never
+            "resignthreshold" => {
+                let percent = value
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&percent| percent <= 100)
+                    .ok_or_else(|| format!("invalid resign threshold: {}", value))?;
+                self.resign = Some(percent).filter(|&percent| percent > 0).map(|percent| {
+                    ResignPolicy::default().with_threshold(f32::from(percent) / 100f32)
+                });
+                Ok(())
+            }
+            "configfile" if value.is_empty() || value == "<empty>" => Ok(()),
+            "configfile" => {
+                let config = EngineConfig::load(Path::new(value)).map_err(|err| err.to_string())?;
+                self.set_config(config);
+                Ok(())
+            }
+            _ => Err(format!("unknown option: {}", name)),
+        }
+    }
+
+    // `startpos` or `fen <fen>`, optionally followed by `moves <move>...`
+    fn parse_position(&self, words: Vec<&str>) -> Result<VariantPosition, String> {
+        let variant = self.position.variant();
+        let moves_at = words
+            .iter()
+            .position(|&word| word == "moves")
+            .unwrap_or(words.len());
+        let mut position = match words.first() {
+            Some(&"startpos") => VariantPosition::start(variant),
+            Some(&"fen") => {
+                let fen = words[1..moves_at].join(" ");
+                match VariantPosition::from_fen(variant, &fen) {
+                    Ok(position) => position,
+                    Err(_) if self.compatibility => {
+                        compat::lenient_fen(variant, &fen).map_err(|err| err.to_string())?
+                    }
+                    Err(err) => return Err(err.to_string()),
+                }
+            }
+            _ => return Err(format!("malformed position: {}", words.join(" "))),
+        }
+        .with_preset(self.preset);
+        for uci in words.iter().skip(moves_at + 1) {
+            position.play_uci(uci).map_err(|err| err.to_string())?;
+        }
+        Ok(position)
+    }
+
+    // Starts a search with the limits of a `go` command. `go ponder` searches without
+    // limits until `ponderhit` or `stop`
+    fn go(&mut self, words: Vec<&str>) {
+        let ponder = words.contains(&"ponder").then(|| {
+            words
+                .iter()
+                .filter(|&&word| word != "ponder")
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>()
+        });
+        let mut limits = SearchLimits::default();
+        let mut time = self.time.clone();
+        let mut clocks = [None; 2];
+        let mut increments = [Duration::ZERO; 2];
+        let mut infinite = false;
+        let mut filter = RootFilter::default();
+        let position = self.position.to_bughouse();
+        let mut words = words.into_iter().peekable();
+        while let Some(word) = words.next() {
+            if word == "infinite" {
+                infinite = true;
+                continue;
+            }
+            // The moves run up to the next word that is not a legal move
+            if word == "searchmoves" || word == "excludemoves" {
+                let mut moves = Vec::new();
+                while let Some(m) = words.peek().and_then(|word| legal_move(&position, word)) {
+                    moves.push(m);
+                    words.next();
+                }
+                if word == "searchmoves" {
+                    filter.only = Some(moves);
+                } else {
+                    filter.exclude = moves;
+                }
+                continue;
+            }
+            if word == "ponder" {
+                continue;
+            }
+            let value = match words.next().and_then(|value| value.parse::<u64>().ok()) {
+                Some(value) => value,
+                None => continue,
+            };
+            let millis = Duration::from_millis(value);
+            match word {
+                "movetime" => limits.time = Some(millis),
+                "nodes" => limits.nodes = Some(value),
+                "depth" => limits.depth = Some(value as usize),
+                "wtime" => clocks[0] = Some(millis),
+                "btime" => clocks[1] = Some(millis),
+                "winc" => increments[0] = millis,
+                "binc" => increments[1] = millis,
+                "movestogo" => time.moves_to_go = value as u32,
+                _ => {}
+            }
+        }
+        let filtered = filter != RootFilter::default();
+        // Book moves are played at once, unless the GUI wants the search to go on or
+        // restricts the moves
+        if !infinite && ponder.is_none() && !filtered {
+            let book_move = self
+                .book
+                .as_ref()
+                .and_then(|book| book.probe(&position, &mut StdRng::seed_from_u64(seed())));
+            if let Some(m) = book_move {
+                let _ = self.send("info string book move");
+                let _ = self.send(&format!("bestmove {}", Uci::from_standard(&m)));
+                return;
+            }
+        }
+        let side = position.turn().fold(0, 1);
+        if limits.time.is_none() {
+            if let Some(remaining) = clocks[side] {
+                limits.time = Some(time.allot(remaining, increments[side], None));
+            }
+        }
+        let pondering = ponder.is_some();
+        if pondering {
+            limits = SearchLimits::default();
+        }
+        // Searches that wait for `stop` have no time to hand over, and the remote
+        // protocol has no way to restrict the moves
+        let remote = match &self.remote {
+            Some(remote) if !infinite && !pondering && !filtered => {
+                Some((remote.clone(), limits.time.unwrap_or(REMOTE_DEFAULT_TIME)))
+            }
+            _ => None,
+        };
+
+        let cancel = CancelToken::new();
+        let control = SearchControl::new(limits, cancel.clone());
+        let params = self.eval.params();
+        let mut engine = match self.engine.take() {
+            // A new `EvalFile` swaps the weights but keeps the tree
+            Some(mut engine) => {
+                if !Arc::ptr_eq(engine.params(), &params) {
+                    engine.set_params(params);
+                }
+                engine
+            }
+            None => {
+                let mut engine = Engine::new(params);
+                engine.set_seed(self.config.seed);
+                engine
+            }
+        };
+        if self.config.seed == 0 {
+            engine.set_seed(seed());
+        }
+        engine.set_threads(self.threads);
+        engine.set_root_filter(filter);
+        engine.set_options(SearchOptions {
+            #[cfg(feature = "nn")]
+            network: self.network.clone(),
+            ..self.config.search_options()
+        });
+        let output = self.output.clone();
+        let resign = self.resign.clone();
+        let token = cancel.clone();
+        let silent = Arc::new(AtomicBool::new(false));
+        let quiet = silent.clone();
+        let handle = thread::spawn(move || {
+            if let Some((remote, time)) = remote {
+                let result = remote
+                    .lock()
+                    .expect("remote engine lock poisoned")
+                    .search(&position, time, &token);
+                match result {
+                    Ok(best) => {
+                        let best = best.map_or(Uci::Null, |m| Uci::from_standard(&m));
+                        let _ = send(&output, &format!("bestmove {}", best));
+                        return engine;
+                    }
+                    Err(err) => {
+                        let _ = send(
+                            &output,
+                            &format!("info string remote search failed, searching here: {}", err),
+                        );
+                    }
+                }
+            }
+            let analysis = engine.analyse_with(&position, &control);
+            let best = analysis.best.clone();
+            // An infinite or ponder search may only answer once it is told to stop
+            while (infinite || pondering) && !token.is_cancelled() {
+                thread::sleep(POLL_INTERVAL);
+            }
+            if quiet.load(Ordering::Relaxed) {
+                return engine;
+            }
+            let reply = best.as_ref().and_then(|m| engine.expected_reply(m));
+            if let Some(drops) = engine.drop_stats(DROP_CANDIDATES) {
+                let _ = send(&output, &format!("info string drops {}", drops.summary()));
+            }
+            if let Some(rollouts) = engine.rollout_stats() {
+                let _ = send(&output, &format!("info string rollouts {}", rollouts));
+            }
+            let best = best.map_or_else(
+                || "0000".to_string(),
+                |m| Uci::from_standard(&m).to_string(),
+            );
+            let _ = send(
+                &output,
+                &format!(
+                    "info depth {} score {} nodes {} time {}",
+                    control.depth(),
+                    score(&analysis),
+                    control.nodes(),
+                    control.elapsed().as_millis()
+                ),
+            );
+            if resign.is_some_and(|policy| policy.should_resign(analysis.win_probability)) {
+                let _ = send(&output, "info string resign");
+            }
+            let answer = match reply {
+                Some(reply) => format!("bestmove {} ponder {}", best, Uci::from_standard(&reply)),
+                None => format!("bestmove {}", best),
+            };
+            let _ = send(&output, &answer);
+            engine
+        });
+        self.search = Some(Search {
+            cancel,
+            silent,
+            handle,
+            ponder,
+        });
+    }
+
+    // The opponent played the move pondered on. The ponder search stops without an
+    // answer and the real search continues on its tree with the limits of `go ponder`
+    fn ponderhit(&mut self) {
+        let words = match self.search.as_mut().and_then(|search| search.ponder.take()) {
+            Some(words) => words,
+            None => return,
+        };
+        if let Some(search) = &self.search {
+            search.silent.store(true, Ordering::Relaxed);
+        }
+        self.stop();
+        self.go(words.iter().map(String::as_str).collect());
+    }
+
+    // Stops the running search, if any, once it has sent its best move
+    fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancel.cancel();
+            if let Ok(engine) = search.handle.join() {
+                self.engine = Some(engine);
+            }
+        }
+    }
+}
+
+// `word` as a legal move in `position`, for the move lists of `go`
+fn legal_move(position: &Bughouse, word: &str) -> Option<Move> {
+    word.parse::<Uci>().ok()?.to_move(position).ok()
+}
+
+// The rule presets offered as the `Rules` option
+fn rules_option() -> String {
+    let mut option = format!(
+        "option name Rules type combo default {}",
+        RulePreset::default()
+    );
+    for preset in PRESETS {
+        option.push_str(&format!(" var {}", preset));
+    }
+    option
+}
+
+// `mate <moves>` once the search has proven a mate, else centipawns on the scale of
+// the evaluation
+fn score(analysis: &Analysis) -> String {
+    match analysis.mate {
+        Some(moves) => format!("mate {}", moves),
+        None => format!(
+            "cp {}",
+            (pawns(analysis.win_probability) * 100f32).round() as i32
+        ),
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, line: &str) -> io::Result<()> {
+    let mut output = output.lock().expect("uci output lock poisoned");
+    writeln!(output, "{}", line)?;
+    output.flush()
+}
+
+// Playouts differ from search to search, like they would between games
+pub(crate) fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
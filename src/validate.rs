@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use shakmaty::CastlingMode;
+
+use crate::board::{Bughouse, FenError};
+use crate::bpgn::parse_board_archive;
+use crate::output::{json_array, json_string, JsonObject};
+
+/// Checks one FEN, with pockets and promoted markers as in crazyhouse FENs.
+pub fn check_fen(text: &str) -> Result<Bughouse, FenError> {
+    Bughouse::from_fen(text, CastlingMode::Standard)
+}
+
+/// Results of checking a file of FENs.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub total: u32,
+    pub valid: u32,
+    /// Line number, FEN and problem of every invalid line
+    pub invalid: Vec<(usize, String, FenError)>,
+    categories: BTreeMap<&'static str, u32>,
+}
+
+impl ValidationReport {
+    /// Invalid FENs per error category. A FEN with several problems counts in each.
+    pub fn categories(&self) -> impl Iterator<Item = (&'static str, u32)> + '_ {
+        self.categories.iter().map(|(&name, &count)| (name, count))
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let categories = self
+            .categories()
+            .fold(JsonObject::new(), |object, (name, count)| {
+                object.number(name, count)
+            });
+        let invalid = self.invalid.iter().map(|(line, fen, problem)| {
+            JsonObject::new()
+                .number("line", line)
+                .string("fen", fen)
+                .raw(
+                    "categories",
+                    json_array(problem.categories().into_iter().map(json_string)),
+                )
+                .to_string()
+        });
+        JsonObject::document("validate")
+            .number("total", self.total)
+            .number("valid", self.valid)
+            .raw("categories", categories.to_string())
+            .raw("invalid", json_array(invalid))
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} fens, {} valid", self.total, self.valid)?;
+        for (category, count) in self.categories() {
+            writeln!(f, "{}: {}", category, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks one FEN per line of `input`. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn validate_fens<R: BufRead>(input: R) -> io::Result<ValidationReport> {
+    let mut report = ValidationReport::default();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        report.total += 1;
+        match check_fen(text) {
+            Ok(_) => report.valid += 1,
+            Err(problem) => {
+                for category in problem.categories() {
+                    *report.categories.entry(category).or_default() += 1;
+                }
+                report.invalid.push((index + 1, text.to_string(), problem));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Results of checking an archive of single-board records.
+#[derive(Clone, Debug, Default)]
+pub struct RecordReport {
+    pub total: u32,
+    /// Games whose material flow fits a real bughouse game
+    pub consistent: u32,
+    /// Index in the archive and reason of every game that can't be read or can't be a
+    /// real game
+    pub corrupt: Vec<(usize, String)>,
+}
+
+impl RecordReport {
+    pub fn to_json(&self) -> JsonObject {
+        let corrupt = self.corrupt.iter().map(|(game, reason)| {
+            JsonObject::new()
+                .number("game", game + 1)
+                .string("reason", reason)
+                .to_string()
+        });
+        JsonObject::document("validate-records")
+            .number("total", self.total)
+            .number("consistent", self.consistent)
+            .raw("corrupt", json_array(corrupt))
+    }
+}
+
+impl fmt::Display for RecordReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} games, {} consistent", self.total, self.consistent)
+    }
+}
+
+/// Checks every single-board record of an archive, reconstructing the pieces passed to
+/// and from the partner board and flagging the games where they don't add up.
+pub fn validate_records(text: &str) -> RecordReport {
+    let mut report = RecordReport::default();
+    for (game, result) in parse_board_archive(text).enumerate() {
+        report.total += 1;
+        let reason = match result {
+            Ok(record) => match record.infer_flow() {
+                Ok(_) => {
+                    report.consistent += 1;
+                    continue;
+                }
+                Err(inconsistency) => inconsistency.to_string(),
+            },
+            Err(err) => err.error.to_string(),
+        };
+        report.corrupt.push((game, reason));
+    }
+    report
+}
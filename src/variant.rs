@@ -0,0 +1,196 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use shakmaty::fen::{fen, Fen};
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Chess, FromSetup, Move, MoveList, Outcome, Position, Setup};
+
+use crate::board::{Bughouse, IllegalMove, RulePreset, Rules};
+use crate::eval::{evaluate, EvalParams};
+
+/// A FEN that is not a position of the variant it was given for, as it was written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidFen(pub String);
+
+impl Display for InvalidFen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fen: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidFen {}
+
+/// Variants the engine plays, chosen with the `UCI_Variant` option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// Single board crazyhouse, the engine's own game
+    #[default]
+    Crazyhouse,
+    /// Standard chess, for analysis. No drops and nothing goes to the pockets
+    Chess,
+}
+
+pub const VARIANTS: [Variant; 2] = [Variant::Crazyhouse, Variant::Chess];
+
+impl Variant {
+    /// The option declaration sent in reply to `uci`.
+    pub fn uci_option() -> String {
+        let mut option = format!(
+            "option name UCI_Variant type combo default {}",
+            Variant::default()
+        );
+        for variant in VARIANTS {
+            option.push_str(&format!(" var {}", variant));
+        }
+        option
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    // GUIs disagree on the name of standard chess
+    fn from_str(s: &str) -> Result<Variant, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "crazyhouse" | "zh" => Ok(Variant::Crazyhouse),
+            "chess" | "standard" | "normal" => Ok(Variant::Chess),
+            _ => Err(format!("unsupported variant: {}", s.trim())),
+        }
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Chess => "chess",
+        })
+    }
+}
+
+/// A position of any supported variant, dispatching move generation and evaluation to
+/// the rules of that variant.
+#[derive(Clone, Debug)]
+pub enum VariantPosition {
+    Crazyhouse(Bughouse),
+    Chess(Chess),
+}
+
+impl VariantPosition {
+    pub fn start(variant: Variant) -> VariantPosition {
+        match variant {
+            Variant::Crazyhouse => VariantPosition::Crazyhouse(Bughouse::default()),
+            Variant::Chess => VariantPosition::Chess(Chess::default()),
+        }
+    }
+
+    /// Parses a FEN of `variant`. Pockets are only accepted in crazyhouse.
+    pub fn from_fen(variant: Variant, text: &str) -> Result<VariantPosition, InvalidFen> {
+        let invalid = || InvalidFen(text.to_string());
+        let setup = Fen::from_ascii(text.trim().as_bytes()).map_err(|_| invalid())?;
+        match variant {
+            Variant::Crazyhouse => Bughouse::from_setup(&setup, CastlingMode::Standard)
+                .map(VariantPosition::Crazyhouse)
+                .map_err(|_| invalid()),
+            Variant::Chess => {
+                if setup.pockets().is_some_and(|pockets| !pockets.is_empty()) {
+                    return Err(invalid());
+                }
+                Chess::from_setup(&setup, CastlingMode::Standard)
+                    .map(VariantPosition::Chess)
+                    .map_err(|_| invalid())
+            }
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        match self {
+            VariantPosition::Crazyhouse(_) => Variant::Crazyhouse,
+            VariantPosition::Chess(_) => Variant::Chess,
+        }
+    }
+
+    pub fn legal_moves(&self) -> MoveList {
+        match self {
+            VariantPosition::Crazyhouse(position) => position.legal_moves(),
+            VariantPosition::Chess(position) => position.legal_moves(),
+        }
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        match self {
+            VariantPosition::Crazyhouse(position) => position.outcome(),
+            VariantPosition::Chess(position) => position.outcome(),
+        }
+    }
+
+    pub fn play(&mut self, m: &Move) -> Result<(), IllegalMove> {
+        let legal = match self {
+            VariantPosition::Crazyhouse(position) => position.is_legal(m),
+            VariantPosition::Chess(position) => position.is_legal(m),
+        };
+        if !legal {
+            return Err(IllegalMove::of(m));
+        }
+        match self {
+            VariantPosition::Crazyhouse(position) => position.play_unchecked(m),
+            VariantPosition::Chess(position) => position.play_unchecked(m),
+        }
+        Ok(())
+    }
+
+    /// Plays a move in UCI notation.
+    pub fn play_uci(&mut self, uci: &str) -> Result<(), IllegalMove> {
+        let illegal = || IllegalMove(uci.to_string());
+        let parsed = uci.parse::<Uci>().map_err(|_| illegal())?;
+        let m = match self {
+            VariantPosition::Crazyhouse(position) => parsed.to_move(position),
+            VariantPosition::Chess(position) => parsed.to_move(position),
+        }
+        .map_err(|_| illegal())?;
+        self.play(&m)
+    }
+
+    pub fn fen(&self) -> String {
+        match self {
+            VariantPosition::Crazyhouse(position) => position.fen(),
+            VariantPosition::Chess(position) => fen(position),
+        }
+    }
+
+    /// Switches a crazyhouse position to the rules of `preset`. Chess has no drops, so
+    /// presets don't apply to it.
+    pub fn with_preset(self, preset: RulePreset) -> VariantPosition {
+        match self {
+            VariantPosition::Crazyhouse(position) => {
+                VariantPosition::Crazyhouse(position.with_preset(preset))
+            }
+            VariantPosition::Chess(position) => VariantPosition::Chess(position),
+        }
+    }
+
+    /// The position to search. Chess positions play by [`Rules::standard_chess`], so
+    /// the search generates no drops and knows the chess draws.
+    pub fn to_bughouse(&self) -> Bughouse {
+        match self {
+            VariantPosition::Crazyhouse(position) => position.clone(),
+            VariantPosition::Chess(position) => {
+                Bughouse::from_setup(position, CastlingMode::Standard)
+                    .expect("legal chess position is legal crazyhouse")
+                    .with_rules(Rules {
+                        standard_chess: true,
+                        ..Rules::default()
+                    })
+            }
+        }
+    }
+
+    /// Static evaluation from the point of view of the side to move. A chess position
+    /// is evaluated as crazyhouse with empty pockets, so only the board terms count.
+    pub fn evaluate(&self, params: &EvalParams) -> f32 {
+        match self {
+            VariantPosition::Crazyhouse(position) => evaluate(position, params),
+            VariantPosition::Chess(_) => evaluate(&self.to_bughouse(), params),
+        }
+    }
+}
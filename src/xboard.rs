@@ -0,0 +1,344 @@
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Material, Move, Position, Role, Setup};
+
+use crate::board::parse_fen;
+use crate::board::{Bughouse, RulePreset};
+use crate::build_info::BuildInfo;
+use crate::engine::{Engine, SearchOptions};
+use crate::eval::EvalHandle;
+use crate::intent::{constraints, PartnerIntent};
+use crate::limits::SearchLimits;
+use crate::protocol::command_lines;
+use crate::time::TimeManager;
+use crate::uci::seed;
+
+// Sent in reply to `protover` after the engine's name, before `done=1`
+const FEATURES: &str = "variants=\"bughouse,crazyhouse\" setboard=1 usermove=1 ping=1 playother=1 colors=0 san=0 sigint=0 sigterm=0";
+
+/// The engine side of the XBoard protocol, also known as CECP, for playing bughouse on
+/// FICS through adapters like `zippy` and xboard's ICS mode.
+///
+/// Only one board is seen. In `variant bughouse` captures go to the partner instead of
+/// our pocket, and the pockets come from the `holding` commands the adapter sends after
+/// every change on either board. The partner is announced with `partner <name>`, and
+/// their messages arrive as `ptell`: `sit` makes the engine hold its next move until
+/// `go`, the usual bughouse calls. Other requests, like `need n` or `no q`, constrain
+/// the search of the next move, see [`crate::intent::constraints`]. Messages to the
+/// partner go out as `tellics ptell`.
+///
+/// Searches run on the reading thread, so commands sent while the engine thinks wait
+/// until it has moved.
+pub struct XboardEngine<W> {
+    output: W,
+    eval: EvalHandle,
+    engine: Option<Engine>,
+    time: TimeManager,
+    position: Bughouse,
+    bughouse: bool,
+    // The side the engine plays, `None` in force mode
+    engine_color: Option<Color>,
+    // Set with `st`, `sd` and `time`/`otim`; `level` gives the increment
+    move_time: Option<Duration>,
+    depth: Option<usize>,
+    clock: Option<Duration>,
+    increment: Duration,
+    partner: Option<String>,
+    // The partner asked us to wait before moving
+    sitting: bool,
+    // Partner requests for our next move, oldest first
+    requests: Vec<PartnerIntent>,
+}
+
+impl<W: Write> XboardEngine<W> {
+    pub fn new(output: W, eval: EvalHandle) -> XboardEngine<W> {
+        XboardEngine {
+            output,
+            eval,
+            engine: None,
+            time: TimeManager::default(),
+            position: Bughouse::default(),
+            bughouse: false,
+            engine_color: Some(Color::Black),
+            move_time: None,
+            depth: None,
+            clock: None,
+            increment: Duration::ZERO,
+            partner: None,
+            sitting: false,
+            requests: Vec::new(),
+        }
+    }
+
+    /// The position on our board.
+    pub fn position(&self) -> &Bughouse {
+        &self.position
+    }
+
+    /// The partner named by the last `partner` command.
+    pub fn partner(&self) -> Option<&str> {
+        self.partner.as_deref()
+    }
+
+    /// Whether the partner asked us to sit.
+    pub fn is_sitting(&self) -> bool {
+        self.sitting
+    }
+
+    /// The partner's requests that will constrain the search of our next move.
+    pub fn requests(&self) -> &[PartnerIntent] {
+        &self.requests
+    }
+
+    /// Handles commands from `input` until `quit` or the end of input.
+    pub fn run<R: BufRead>(&mut self, input: R) -> io::Result<()> {
+        for line in command_lines(input) {
+            if !self.handle(&line?)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles one command. Returns `false` once the GUI has sent `quit`. Commands the
+    /// engine doesn't know are answered with `Error (unknown command)`.
+    pub fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let line = line.trim();
+        let (command, rest) = match line.split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" | "xboard" | "accepted" | "rejected" | "random" | "post" | "nopost" | "hard"
+            | "easy" | "computer" | "name" | "rating" | "ics" | "result" => {}
+            "protover" => {
+                self.send("feature done=0")?;
+                let name = BuildInfo::new(&self.eval.params()).name();
+                self.send(&format!("feature myname=\"{}\" {}", name, FEATURES))?;
+                self.send("feature done=1")?;
+            }
+            "ping" => self.send(&format!("pong {}", rest))?,
+            "new" => {
+                self.position = Bughouse::default();
+                self.bughouse = false;
+                self.engine_color = Some(Color::Black);
+                self.move_time = None;
+                self.depth = None;
+                self.sitting = false;
+                self.requests.clear();
+                if let Some(engine) = &mut self.engine {
+                    engine.clear_tree();
+                }
+            }
+            "variant" => match rest {
+                "bughouse" => {
+                    self.bughouse = true;
+                    self.position = self.position.clone().with_preset(RulePreset::FicsBughouse);
+                }
+                "crazyhouse" => self.bughouse = false,
+                _ => self.error("unsupported variant", rest)?,
+            },
+            "force" => self.engine_color = None,
+            "go" => {
+                self.engine_color = Some(self.position.turn());
+                self.think()?;
+            }
+            "playother" => self.engine_color = Some(!self.position.turn()),
+            "setboard" => match parse_fen(rest) {
+                Ok(position) => self.set_position(position),
+                Err(err) => self.error("bad fen", &err.to_string())?,
+            },
+            "holding" => {
+                if let Err(err) = self.set_holding(rest) {
+                    self.error(&err, rest)?;
+                }
+            }
+            "usermove" => self.user_move(rest)?,
+            "st" => match rest
+                .parse::<f64>()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            {
+                Some(time) => self.move_time = Some(time),
+                None => self.error("bad time", rest)?,
+            },
+            "sd" => match rest.parse() {
+                Ok(depth) => self.depth = Some(depth),
+                Err(_) => self.error("bad depth", rest)?,
+            },
+            "level" => self.set_level(rest)?,
+            // Centiseconds on our clock
+            "time" => {
+                self.clock = rest
+                    .parse()
+                    .ok()
+                    .map(|cs: u64| Duration::from_millis(cs.saturating_mul(10)))
+            }
+            // The opponent's clock doesn't change how long we think
+            "otim" => {}
+            "partner" => {
+                self.partner = (!rest.is_empty()).then(|| rest.to_string());
+                self.sitting = false;
+                self.requests.clear();
+            }
+            "ptell" => self.partner_tell(rest)?,
+            "quit" => return Ok(false),
+            _ => self.error("unknown command", line)?,
+        }
+        Ok(true)
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", line)?;
+        self.output.flush()
+    }
+
+    fn error(&mut self, kind: &str, what: &str) -> io::Result<()> {
+        self.send(&format!("Error ({}): {}", kind, what))
+    }
+
+    fn set_position(&mut self, position: Bughouse) {
+        self.position = if self.bughouse {
+            position.with_preset(RulePreset::FicsBughouse)
+        } else {
+            position
+        };
+        if let Some(engine) = &mut self.engine {
+            engine.clear_tree();
+        }
+    }
+
+    // `[PNB] [rq]`, the white then the black pocket, optionally followed by the piece
+    // that just arrived like `WP`, which the pockets already count
+    fn set_holding(&mut self, text: &str) -> Result<(), String> {
+        let mut rest = text;
+        let mut material = Material::new();
+        for color in [Color::White, Color::Black].iter().copied() {
+            let (pocket, after) = rest
+                .trim_start()
+                .strip_prefix('[')
+                .and_then(|inside| inside.split_once(']'))
+                .ok_or("missing pocket")?;
+            rest = after;
+            for ch in pocket.trim().chars() {
+                let role = Role::from_char(ch.to_ascii_lowercase()).ok_or("bad piece")?;
+                *material.by_piece_mut(role.of(color)) += 1;
+            }
+        }
+        self.position = self
+            .position
+            .with_pockets(material)
+            .map_err(|_| "impossible holding".to_string())?;
+        Ok(())
+    }
+
+    fn set_level(&mut self, text: &str) -> io::Result<()> {
+        // `level <moves> <minutes[:seconds]> <increment>`
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        match fields.as_slice() {
+            [moves, _, increment] => {
+                let increment = increment
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds.max(0f64)).ok());
+                match (moves.parse::<u32>(), increment) {
+                    (Ok(moves), Some(increment)) => {
+                        self.time.moves_to_go = if moves == 0 { 30 } else { moves };
+                        self.increment = increment;
+                        Ok(())
+                    }
+                    _ => self.error("bad level", text),
+                }
+            }
+            _ => self.error("bad level", text),
+        }
+    }
+
+    fn user_move(&mut self, text: &str) -> io::Result<()> {
+        let m = match text
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&self.position).ok())
+        {
+            Some(m) => m,
+            None => return self.send(&format!("Illegal move: {}", text)),
+        };
+        self.play(&m);
+        self.think()
+    }
+
+    fn play(&mut self, m: &Move) {
+        if self.bughouse {
+            self.position.play_passing_captures(m);
+        } else {
+            self.position.play_unchecked(m);
+        }
+    }
+
+    // `sit` and `go` from the partner take effect at once; other requests are kept for
+    // our next move, and chat is ignored
+    fn partner_tell(&mut self, text: &str) -> io::Result<()> {
+        match text.parse::<PartnerIntent>() {
+            Ok(PartnerIntent::Sit) => {
+                self.sitting = true;
+                self.send("tellics ptell sitting")
+            }
+            Ok(PartnerIntent::Go) => {
+                self.sitting = false;
+                self.send("tellics ptell going")?;
+                self.think()
+            }
+            Ok(intent) => {
+                if !self.requests.contains(&intent) {
+                    self.requests.push(intent);
+                }
+                Ok(())
+            }
+            Err(_) => Ok(()),
+        }
+    }
+
+    // Searches and plays a move if it is the engine's turn and nothing holds it back
+    fn think(&mut self) -> io::Result<()> {
+        if self.engine_color != Some(self.position.turn())
+            || self.sitting
+            || self.position.is_game_over()
+        {
+            return Ok(());
+        }
+        let limits = SearchLimits {
+            depth: self.depth,
+            time: self.move_time.or_else(|| {
+                self.clock
+                    .map(|clock| self.time.allot(clock, self.increment, None))
+            }),
+            ..SearchLimits::default()
+        };
+        let params = self.eval.params();
+        let mut engine = match self.engine.take() {
+            Some(engine) if Arc::ptr_eq(engine.params(), &params) => engine,
+            _ => Engine::new(params),
+        };
+        engine.set_seed(seed());
+        let constraints = constraints(&self.position, &self.requests);
+        engine.set_root_filter(constraints.filter);
+        engine.set_options(SearchOptions {
+            partner_danger: constraints.partner_danger,
+            ..SearchOptions::default()
+        });
+        let best = engine.search(&self.position, limits);
+        self.engine = Some(engine);
+        // Requests are answered by the move they were made for
+        self.requests.clear();
+        match best {
+            Some(m) => {
+                self.play(&m);
+                self.send(&format!("move {}", Uci::from_standard(&m)))
+            }
+            None => self.send("resign"),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ladybug::access::{AccessControl, AccessError, ClientQuota};
+use ladybug::board::Bughouse;
+use ladybug::cancel::CancelToken;
+use ladybug::remote::{serve_remote_with_access, RemoteEngine};
+use shakmaty::Position;
+
+fn quota() -> ClientQuota {
+    ClientQuota {
+        requests_per_minute: 60,
+        burst: 2,
+        engine_time_per_hour: Duration::from_secs(10),
+        max_queued: 1,
+    }
+}
+
+#[test]
+fn requests_need_a_known_token() {
+    let now = Instant::now();
+    let mut access = AccessControl::new();
+    access.add_client("secret", "alice", quota(), now);
+    assert_eq!(
+        access.admit("guess", now).unwrap_err(),
+        AccessError::Unauthorized
+    );
+    let ticket = access.admit("secret", now).unwrap();
+    assert_eq!(ticket.client, "alice");
+    assert_eq!(ticket.engine_time, Duration::from_secs(10));
+    access.finish(ticket, Duration::from_secs(0));
+
+    access.remove_client("secret");
+    assert_eq!(
+        access.admit("secret", now).unwrap_err(),
+        AccessError::Unauthorized
+    );
+}
+
+#[test]
+fn bursts_are_rate_limited_until_tokens_refill() {
+    let now = Instant::now();
+    let mut access = AccessControl::new();
+    access.add_client("t", "alice", quota(), now);
+    for _ in 0..2 {
+        let ticket = access.admit("t", now).unwrap();
+        access.finish(ticket, Duration::from_secs(0));
+    }
+    match access.admit("t", now) {
+        Err(AccessError::RateLimited { retry_after }) => {
+            assert_eq!(retry_after, Duration::from_secs(1))
+        }
+        other => panic!("expected a rate limit, got {:?}", other),
+    }
+    // One request a second at 60 a minute
+    assert!(access.admit("t", now + Duration::from_secs(1)).is_ok());
+}
+
+#[test]
+fn requests_in_progress_are_limited() {
+    let now = Instant::now();
+    let mut access = AccessControl::new();
+    access.add_client("t", "alice", quota(), now);
+    let ticket = access.admit("t", now).unwrap();
+    assert_eq!(access.admit("t", now).unwrap_err(), AccessError::QueueFull);
+    access.finish(ticket, Duration::from_secs(0));
+    assert!(access.admit("t", now).is_ok());
+}
+
+#[test]
+fn engine_time_is_charged_until_the_hour_is_over() {
+    let now = Instant::now();
+    let mut access = AccessControl::new();
+    access.add_client("t", "alice", quota(), now);
+    let ticket = access.admit("t", now).unwrap();
+    access.finish(ticket, Duration::from_secs(4));
+    let ticket = access.admit("t", now).unwrap();
+    assert_eq!(ticket.engine_time, Duration::from_secs(6));
+    access.finish(ticket, Duration::from_secs(6));
+
+    let later = now + Duration::from_secs(60);
+    match access.admit("t", later) {
+        Err(AccessError::QuotaExhausted { retry_after }) => {
+            assert_eq!(retry_after, Duration::from_secs(59 * 60))
+        }
+        other => panic!("expected an exhausted quota, got {:?}", other),
+    }
+    let next_hour = now + Duration::from_secs(60 * 60);
+    assert_eq!(
+        access.admit("t", next_hour).unwrap().engine_time,
+        Duration::from_secs(10)
+    );
+}
+
+#[test]
+fn clients_are_read_from_token_lines() {
+    let now = Instant::now();
+    let text = "# token name seconds\nabc alice\n\ndef bob 30\n";
+    let mut access = AccessControl::parse_clients(text, now).unwrap();
+    assert_eq!(access.admit("abc", now).unwrap().client, "alice");
+    assert_eq!(
+        access.admit("def", now).unwrap().engine_time,
+        Duration::from_secs(30)
+    );
+    for invalid in ["abc", "abc alice many", "abc alice 1 2"] {
+        assert!(AccessControl::parse_clients(invalid, now).is_err());
+    }
+}
+
+// Serves a stand-in engine that plays its first legal move, reporting the time each
+// search was given
+fn serve(access: AccessControl) -> (String, mpsc::Receiver<Duration>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (sender, times) = mpsc::channel();
+    thread::spawn(move || {
+        let mut access = access;
+        serve_remote_with_access(&listener, &mut access, |position, time, _| {
+            let _ = sender.send(time);
+            position.legal_moves().first().cloned()
+        })
+    });
+    (addr, times)
+}
+
+#[test]
+fn the_remote_server_only_searches_for_its_clients() {
+    let mut access = AccessControl::new();
+    let quota = ClientQuota {
+        engine_time_per_hour: Duration::from_millis(300),
+        ..quota()
+    };
+    access.add_client("secret", "alice", quota, Instant::now());
+    let (addr, times) = serve(access);
+    let position = Bughouse::default();
+    let cancel = CancelToken::new();
+
+    let mut stranger = RemoteEngine::new(&addr);
+    stranger.retries = 0;
+    let err = stranger
+        .search(&position, Duration::from_secs(1), &cancel)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("unknown or missing token"),
+        "{}",
+        err
+    );
+    drop(stranger);
+
+    // Searches get no more than the client's remaining engine time
+    let mut client = RemoteEngine::new(&addr);
+    client.token = Some("secret".to_string());
+    let best = client
+        .search(&position, Duration::from_secs(1), &cancel)
+        .unwrap();
+    assert_eq!(best, position.legal_moves().first().cloned());
+    assert!(times.recv().unwrap() <= Duration::from_millis(300));
+}
+
+#[test]
+fn refused_searches_are_answered_with_the_reason() {
+    let mut access = AccessControl::new();
+    let quota = ClientQuota {
+        burst: 1,
+        requests_per_minute: 1,
+        ..quota()
+    };
+    access.add_client("t", "alice", quota, Instant::now());
+    let (addr, _times) = serve(access);
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    let fen = Bughouse::default().fen();
+    write!(stream, "auth t\nsearch 10 {}\nsearch 10 {}\n", fen, fen).unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    assert!(lines.next().unwrap().unwrap().starts_with("bestmove "));
+    assert!(lines
+        .next()
+        .unwrap()
+        .unwrap()
+        .starts_with("error rate limited"));
+}
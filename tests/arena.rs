@@ -0,0 +1,152 @@
+#![cfg(feature = "unstable")]
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ladybug::arena::{
+    play_contenders, round_robin, Arena, Contender, PlayedGame, Score, Sprt, SprtVerdict,
+};
+use ladybug::board::Bughouse;
+use ladybug::engine::RootStrategy;
+use shakmaty::{Color, Outcome};
+
+#[test]
+fn scores_give_elo_and_los() {
+    let even = Score {
+        wins: 10,
+        draws: 5,
+        losses: 10,
+    };
+    assert_eq!(even.elo(), Some(0.0));
+    assert!((even.los().unwrap() - 0.5).abs() < 1e-6);
+
+    let ahead = Score {
+        wins: 30,
+        draws: 40,
+        losses: 10,
+    };
+    // 62.5% is about 89 Elo
+    assert!((ahead.elo().unwrap() - 88.7).abs() < 0.1);
+    assert!(ahead.elo_margin().unwrap() > 0.0);
+    assert!(ahead.los().unwrap() > 0.99);
+
+    let sweep = Score {
+        wins: 4,
+        draws: 0,
+        losses: 0,
+    };
+    assert_eq!(sweep.elo(), None);
+    assert_eq!(Score::default().los(), None);
+}
+
+#[test]
+fn contenders_parse_settings_and_weights() {
+    let contender = Contender::parse("wide:exploration=3,nodes=50,pocket.knight=4").unwrap();
+    assert_eq!(contender.name, "wide");
+    assert_eq!(contender.options.exploration, 3.0);
+    assert_eq!(contender.nodes, 50);
+    assert_eq!(contender.params.pocket.knight, 4.0);
+
+    let halving = Contender::parse("halving:root_strategy=halving").unwrap();
+    assert_eq!(
+        halving.options.root_strategy,
+        RootStrategy::SequentialHalving
+    );
+
+    assert_eq!(Contender::parse("base").unwrap().nodes, 400);
+    assert!(Contender::parse("bad:root_strategy=best").is_err());
+    assert!(Contender::parse("bad:nodes").is_err());
+    assert!(Contender::parse("bad:pocket.unicorn=1").is_err());
+}
+
+#[test]
+fn external_engines_play_over_uci() {
+    let external = Contender::parse(&format!(
+        "ext:uci={},nodes=20",
+        env!("CARGO_BIN_EXE_ladybug")
+    ))
+    .unwrap();
+    let ours = Contender::parse("ours:nodes=20").unwrap();
+    let game = play_contenders(&external, &ours, 1, 6);
+    assert_eq!(game.moves.len(), 6);
+    assert_eq!(game.outcome, Outcome::Draw);
+
+    // An engine that doesn't start loses when it is to move
+    let broken = Contender::parse("broken:uci=/nonexistent/engine").unwrap();
+    let game = play_contenders(&ours, &broken, 1, 6);
+    assert_eq!(game.moves.len(), 1);
+    assert_eq!(
+        game.outcome,
+        Outcome::Decisive {
+            winner: Color::White
+        }
+    );
+}
+
+#[test]
+fn long_games_are_drawn() {
+    let fast = Contender::parse("fast:nodes=10").unwrap();
+    let game = play_contenders(&fast, &fast, 3, 6);
+    assert!(game.moves.len() <= 6);
+    if game.moves.len() == 6 {
+        assert_eq!(game.outcome, Outcome::Draw);
+    }
+}
+
+#[test]
+fn sprt_stops_once_the_result_is_clear() {
+    let sprt = Sprt::new(0.0, 10.0);
+    let (lower, upper) = sprt.bounds();
+    assert!(lower < 0.0 && upper > 0.0);
+    assert_eq!(sprt.verdict(&Score::default()), SprtVerdict::Continue);
+
+    let close = Score {
+        wins: 11,
+        draws: 20,
+        losses: 10,
+    };
+    assert_eq!(sprt.verdict(&close), SprtVerdict::Continue);
+    let stronger = Score {
+        wins: 600,
+        draws: 400,
+        losses: 400,
+    };
+    assert!(stronger.llr(0.0, 10.0).unwrap() >= upper);
+    assert_eq!(sprt.verdict(&stronger), SprtVerdict::H1);
+    let weaker = Score {
+        wins: 400,
+        draws: 400,
+        losses: 600,
+    };
+    assert_eq!(sprt.verdict(&weaker), SprtVerdict::H0);
+}
+
+#[test]
+fn cancelling_finishes_and_archives_the_running_game_only() {
+    let dir = std::env::temp_dir().join(format!("ladybug-arena-{}", std::process::id()));
+    let mut arena = Arena::new(vec!["a".to_string(), "b".to_string()]);
+    arena.archive = Some(dir.clone());
+    let cancel = arena.cancel.clone();
+    let played = AtomicUsize::new(0);
+    let standings = arena
+        .run(
+            round_robin(2, 4),
+            |_| {
+                // A signal arriving in the middle of the first game
+                played.fetch_add(1, Ordering::SeqCst);
+                cancel.cancel();
+                PlayedGame {
+                    start: Bughouse::default(),
+                    moves: Vec::new(),
+                    outcome: Outcome::Draw,
+                }
+            },
+            |_, _, _| true,
+        )
+        .unwrap();
+    let archived = fs::read_to_string(dir.join("a-vs-b.pgn"));
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(played.load(Ordering::SeqCst), 1);
+    assert_eq!(standings.score(0).games(), 1);
+    assert_eq!(archived.unwrap().matches("[Result ").count(), 1);
+}
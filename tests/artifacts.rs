@@ -0,0 +1,47 @@
+use std::fs;
+use std::process;
+
+use ladybug::artifacts::{ArtifactError, ArtifactKind, ArtifactStore, Manifest};
+use ladybug::build_info::evaluator_id;
+use ladybug::eval::EvalParams;
+
+#[test]
+fn artifacts_are_checked_on_load() {
+    let dir = std::env::temp_dir().join(format!("ladybug-artifacts-{}", process::id()));
+    let store = ArtifactStore::open(&dir).unwrap();
+    let weights = EvalParams::default().to_text();
+    let id = store
+        .put(ArtifactKind::Weights, weights.as_bytes())
+        .unwrap();
+    assert_eq!(
+        evaluator_id(&EvalParams::default()),
+        format!("weights-{}", id)
+    );
+    assert_eq!(
+        store.load(ArtifactKind::Weights, id).unwrap(),
+        weights.as_bytes()
+    );
+    assert!(matches!(
+        store.load(ArtifactKind::Book, id),
+        Err(ArtifactError::Missing(ArtifactKind::Book, _))
+    ));
+
+    fs::write(store.path(ArtifactKind::Weights, id), "pawn = 1").unwrap();
+    assert!(matches!(
+        store.load(ArtifactKind::Weights, id),
+        Err(ArtifactError::Corrupt { .. })
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn manifests_round_trip() {
+    let text = "weights 00000000000000ff\nbook 0123456789abcdef\n";
+    let manifest = Manifest::parse(text).unwrap();
+    assert_eq!(manifest.to_text(), text);
+    assert_eq!(manifest.experience, None);
+    assert_ne!(manifest.id(), Manifest::default().id());
+    assert!(Manifest::parse("weights 1\n").is_err());
+    assert!(Manifest::parse("model 00000000000000ff\n").is_err());
+    assert!(Manifest::parse(&format!("{}{}", text, text)).is_err());
+}
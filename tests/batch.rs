@@ -0,0 +1,67 @@
+#![cfg(feature = "unstable")]
+
+use std::collections::BTreeSet;
+
+use ladybug::batch::{scalar_playout, PlayoutBatch};
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Outcome, Position};
+
+fn uci_set(moves: &[Move]) -> BTreeSet<String> {
+    moves
+        .iter()
+        .map(|m| Uci::from_standard(m).to_string())
+        .collect()
+}
+
+#[test]
+fn batch_moves_match_the_move_generator() {
+    let mut rng = StdRng::seed_from_u64(521);
+    for _ in 0..20 {
+        let mut position = Bughouse::default();
+        for _ in 0..40 {
+            // The batch knows no castling or en passant
+            let expected: Vec<Move> = position
+                .legal_moves()
+                .into_iter()
+                .filter(|m| !m.is_castle() && !m.is_en_passant())
+                .collect();
+            let batch = PlayoutBatch::new(&position, 1, 0, 0);
+            assert_eq!(
+                uci_set(&batch.legal_moves(0)),
+                uci_set(&expected),
+                "{}",
+                position.fen()
+            );
+            match expected.choose(&mut rng) {
+                Some(m) => position.play_unchecked(m),
+                None => break,
+            }
+        }
+    }
+}
+
+#[test]
+fn every_lane_is_decided() {
+    let position = parse_fen("6k1/5ppp/8/8/8/8/5PPP/6K1[Rr] w - - 0 1").unwrap();
+    let outcomes = PlayoutBatch::new(&position, 33, 60, 1).run();
+    assert_eq!(outcomes.len(), 33);
+    // A mated side has nothing left to play
+    let mated = parse_fen("R5k1/5ppp/8/8/8/8/8/6K1[] b - - 0 1").unwrap();
+    let outcomes = PlayoutBatch::new(&mated, 4, 60, 1).run();
+    assert!(outcomes.iter().all(|outcome| *outcome
+        == Outcome::Decisive {
+            winner: shakmaty::Color::White
+        }));
+    // Without plies left the material decides, here a rook up
+    let ahead = parse_fen("6k1/8/8/8/8/8/8/R5K1[] b - - 0 1").unwrap();
+    let mut rng = StdRng::seed_from_u64(2);
+    assert_eq!(
+        PlayoutBatch::new(&ahead, 1, 0, 0).run(),
+        [scalar_playout(&ahead, 0, &mut rng)]
+    );
+}
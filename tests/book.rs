@@ -0,0 +1,86 @@
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::book::{BuildConfig, OpeningBook};
+use ladybug::bpgn::parse;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::uci::Uci;
+
+const GAMES: &str = r#"[Result "1-0"]
+
+1A. e4 1B. d4 1a. e5 1b. d5 1-0
+
+[Result "0-1"]
+
+1A. e4 1B. e4 1a. c5 1b. e5 0-1
+"#;
+
+fn uci(text: &str) -> Uci {
+    text.parse().unwrap()
+}
+
+#[test]
+fn books_built_from_games_score_both_boards() {
+    let games = parse(GAMES).unwrap();
+    let config = BuildConfig {
+        plies: 1,
+        min_games: 1,
+    };
+    let book = OpeningBook::from_games(&games, &config);
+    let moves = book.moves(&Bughouse::default());
+    // e4 won on board A in the first game and on board B in the second, d4 lost, and
+    // black's replies are past the first ply
+    let stats: Vec<(String, u16, u32)> = moves
+        .iter()
+        .map(|(_, entry)| (entry.uci.to_string(), entry.weight, entry.games))
+        .collect();
+    assert_eq!(
+        stats,
+        vec![("e2e4".to_string(), 4, 3), ("d2d4".to_string(), 0, 1)]
+    );
+    assert_eq!(moves[0].1.win_rate(), Some(2.0 / 3.0));
+
+    let strict = OpeningBook::from_games(&games, &BuildConfig::default());
+    assert_eq!(strict.moves(&Bughouse::default()).len(), 1);
+}
+
+#[test]
+fn polyglot_and_json_books_round_trip_drops_and_castling() {
+    let json = r#"{"positions":[
+        {"fen":"r3k2r/8/8/8/8/8/8/R3K2R[Nq] w KQkq - 0 1",
+         "moves":[{"move":"e1h1","weight":5},{"move":"N@e6","weight":3,"games":4,"points":2.5}]}
+    ]}"#;
+    let position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[Nq] w KQkq - 0 1").unwrap();
+    let book = OpeningBook::from_json(json).unwrap();
+    let expected: Vec<_> = book
+        .moves(&position)
+        .into_iter()
+        .map(|(m, entry)| (m, entry.clone()))
+        .collect();
+    assert_eq!(expected.len(), 2);
+    assert_eq!(expected[1].1.uci, uci("N@e6"));
+
+    let polyglot = OpeningBook::from_polyglot(&book.to_polyglot()).unwrap();
+    let reread = OpeningBook::from_json(&book.to_json()).unwrap();
+    for other in [polyglot, reread].iter() {
+        let moves: Vec<_> = other
+            .moves(&position)
+            .into_iter()
+            .map(|(m, entry)| (m, entry.clone()))
+            .collect();
+        assert_eq!(moves, expected);
+    }
+    assert!(OpeningBook::from_polyglot(&[0; 17]).is_err());
+}
+
+#[test]
+fn probes_pick_weighted_book_moves() {
+    let json = r#"{"positions":[{"fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        "moves":[{"move":"e2e4","weight":1},{"move":"g1f3","weight":0}]}]}"#;
+    let book = OpeningBook::from_json(json).unwrap();
+    let mut rng = StdRng::seed_from_u64(1);
+    let m = book.probe(&Bughouse::default(), &mut rng).unwrap();
+    assert_eq!(Uci::from_standard(&m), uci("e2e4"));
+    let after = parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR[] b KQkq - 0 1").unwrap();
+    assert_eq!(book.probe(&after, &mut rng), None);
+}
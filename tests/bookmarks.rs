@@ -0,0 +1,80 @@
+use std::fs;
+use std::process;
+
+use ladybug::board::{parse_fen, Bughouse};
+use ladybug::bookmarks::{BookmarkError, BookmarkStore};
+use shakmaty::Setup;
+
+const SICILIAN: &str = "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR[] w KQkq - 0 2";
+
+#[test]
+fn tags_are_kept_across_reopening() {
+    let path = std::env::temp_dir().join(format!("ladybug-bookmarks-{}.tsv", process::id()));
+    let sicilian = parse_fen(SICILIAN).unwrap();
+    {
+        let mut store = BookmarkStore::open(&path).unwrap();
+        assert!(store.list().is_empty());
+        store.tag(&sicilian, "sicilian").unwrap();
+        store.tag(&sicilian, "Open games").unwrap();
+        store.tag(&Bughouse::default(), "start").unwrap();
+    }
+
+    let mut store = BookmarkStore::open(&path).unwrap();
+    assert_eq!(store.list().len(), 2);
+    let bookmark = store.get(&sicilian).unwrap();
+    assert_eq!(bookmark.tags, ["sicilian", "Open games"]);
+    assert_eq!(bookmark.position().unwrap().board(), sicilian.board());
+
+    store.untag(&Bughouse::default(), "start").unwrap();
+    assert!(BookmarkStore::open(&path)
+        .unwrap()
+        .get(&Bughouse::default())
+        .is_none());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn transpositions_share_a_bookmark() {
+    let mut store = BookmarkStore::in_memory();
+    store
+        .tag(&parse_fen(SICILIAN).unwrap(), "sicilian")
+        .unwrap();
+    // Same position, other move counters
+    let later =
+        parse_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR[] w KQkq - 4 6").unwrap();
+    store.tag(&later, "sicilian").unwrap();
+    store.tag(&later, "c5").unwrap();
+    assert_eq!(store.list().len(), 1);
+    assert_eq!(store.get(&later).unwrap().tags, ["sicilian", "c5"]);
+}
+
+#[test]
+fn searches_match_tags_ignoring_case() {
+    let mut store = BookmarkStore::in_memory();
+    store
+        .tag(&parse_fen(SICILIAN).unwrap(), "Sicilian Defence")
+        .unwrap();
+    store.tag(&Bughouse::default(), "start").unwrap();
+    let found = store.search("sicilian");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].tags, ["Sicilian Defence"]);
+    assert!(store.search("french").is_empty());
+
+    for tag in ["", "  ", "two\tfields"] {
+        assert!(matches!(
+            store.tag(&Bughouse::default(), tag),
+            Err(BookmarkError::InvalidTag(_))
+        ));
+    }
+}
+
+#[test]
+fn corrupt_files_are_rejected() {
+    let path = std::env::temp_dir().join(format!("ladybug-bookmarks-bad-{}.tsv", process::id()));
+    fs::write(&path, "not a position\ttag\n").unwrap();
+    assert!(matches!(
+        BookmarkStore::open(&path),
+        Err(BookmarkError::Fen(_))
+    ));
+    fs::remove_file(&path).unwrap();
+}
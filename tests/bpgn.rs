@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use ladybug::board::BoardId;
+use ladybug::bpgn::{parse, parse_archive, BpgnGame};
+use ladybug::seats::Seat;
+use shakmaty::{Color, Outcome, Role, Setup};
+
+// Black on board A takes a pawn, which black's partner drops on board B
+const GAME: &str = r#"[Event "casual bughouse match"]
+[WhiteA "alice"]
+[BlackA "bob"]
+[WhiteB "carol"]
+[BlackB "dave"]
+[TimeControl "180+0"]
+[Result "0-1"]
+
+1A. e4{179.8} 1B. d4{179.5} 1a. d5{179.1} {C:nice} 1b. Nf6{178.0}
+2A. Nc3{178.2} 2a. dxe4{177.9} 2B. P@e5{176.4} 0-1
+"#;
+
+#[test]
+fn parses_moves_clocks_and_tags() {
+    let games = parse(GAME).unwrap();
+    assert_eq!(games.len(), 1);
+    let game = &games[0];
+    assert_eq!(game.tag("TimeControl"), Some("180+0"));
+    assert_eq!(
+        game.seating().at(Seat {
+            board: BoardId::B,
+            color: Color::White
+        }),
+        Some("carol")
+    );
+    assert_eq!(
+        game.result,
+        Some(Outcome::Decisive {
+            winner: Color::Black
+        })
+    );
+    assert_eq!(game.moves().len(), 7);
+    assert_eq!(game.moves()[0].clock, Some(Duration::from_millis(179_800)));
+    // The pawn went to white on board B and was dropped again
+    let b = game.game().board(BoardId::B);
+    assert!(b.pockets().unwrap().is_empty());
+    assert_eq!(
+        b.board()
+            .piece_at("e5".parse().unwrap())
+            .map(|piece| piece.role),
+        Some(Role::Pawn)
+    );
+}
+
+#[test]
+fn round_trips() {
+    let game = &parse(GAME).unwrap()[0];
+    let text = game.to_string();
+    assert!(text.contains("2a. dxe4{177.900} 2B. P@e5{176.400} 0-1"));
+    let again: Vec<BpgnGame> = parse(&text).unwrap();
+    assert_eq!(again[0].moves(), game.moves());
+    assert_eq!(again[0].tags, game.tags);
+}
+
+#[test]
+fn rejects_moves_out_of_turn_and_illegal_drops() {
+    assert!(parse("1a. e5").is_err());
+    // Nothing has been captured yet
+    assert!(parse("1A. P@e4").is_err());
+}
+
+#[test]
+fn archives_skip_broken_games() {
+    let broken = "[Event \"dirty\"]\n\n1A. e4 1a. Ke7 2A. Qxf7 *\n";
+    let archive = format!("{}\n{}\n{}\n{}", GAME, broken, GAME, GAME);
+    for threads in [1, 2, 0] {
+        let results: Vec<_> = parse_archive(&archive, threads).collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok() && results[2].is_ok() && results[3].is_ok());
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!(error.game, 1);
+        assert_eq!(error.line, GAME.lines().count() + 2);
+        assert_eq!(results[3].as_ref().unwrap().moves().len(), 7);
+    }
+}
@@ -0,0 +1,61 @@
+use ladybug::board::{parse_fen, Bughouse};
+use ladybug::branching::BranchingStats;
+use ladybug::engine::parse_move_list;
+use ladybug::insights::GamePhase;
+use shakmaty::{Move, Position};
+
+// The moves of `san`, played one after the other from the start
+fn game(san: &str) -> Vec<Move> {
+    let mut position = Bughouse::default();
+    san.split_whitespace()
+        .map(|token| {
+            let m = parse_move_list(&position, token).unwrap().remove(0);
+            position.play_unchecked(&m);
+            m
+        })
+        .collect()
+}
+
+#[test]
+fn moves_and_drops_are_counted_by_phase() {
+    let mut stats = BranchingStats::new();
+    stats.add_position(&Bughouse::default(), 0);
+    assert_eq!(stats.positions(GamePhase::Opening), 1);
+    assert_eq!(stats.branching_factor(GamePhase::Opening), Some(20.0));
+    assert_eq!(stats.drop_share(GamePhase::Opening), Some(0.0));
+
+    // Five king moves and a knight drop on each of the 62 empty squares
+    let endgame = parse_fen("4k3/8/8/8/8/8/8/4K3[N] w - - 0 1").unwrap();
+    stats.add_position(&endgame, 60);
+    assert_eq!(stats.branching_factor(GamePhase::Endgame), Some(67.0));
+    assert_eq!(stats.drop_share(GamePhase::Endgame), Some(62.0 / 67.0));
+
+    // A full board after the opening plies is a middlegame
+    stats.add_position(&Bughouse::default(), 30);
+    assert_eq!(stats.positions(GamePhase::Middlegame), 1);
+    assert_eq!(stats.positions(GamePhase::Opening), 1);
+}
+
+#[test]
+fn games_count_every_position_that_has_moves() {
+    let mut stats = BranchingStats::new();
+    // The mated position at the end has nothing to choose from
+    stats.add_game(&Bughouse::default(), &game("f3 e5 g4 Qh4#"));
+    assert_eq!(stats.positions(GamePhase::Opening), 4);
+    assert_eq!(stats.positions(GamePhase::Endgame), 0);
+    assert_eq!(stats.branching_factor(GamePhase::Endgame), None);
+
+    let text = stats.to_string();
+    assert!(text.starts_with("opening: 4 positions"), "{}", text);
+    assert!(!text.contains("endgame"), "{}", text);
+    let json = stats.to_json().to_string();
+    assert!(json.contains("\"type\":\"branching\""), "{}", json);
+    assert!(json.contains("\"opening\":{\"positions\":4,"), "{}", json);
+    assert!(
+        json.contains(
+            "\"endgame\":{\"positions\":0,\"branching_factor\":null,\"drop_share\":null}"
+        ),
+        "{}",
+        json
+    );
+}
@@ -0,0 +1,26 @@
+use ladybug::build_info::{BuildInfo, FEATURES};
+use ladybug::eval::EvalParams;
+
+#[test]
+fn features_name_every_cargo_feature_built_in() {
+    let built = [
+        ("unstable", cfg!(feature = "unstable")),
+        ("nn", cfg!(feature = "nn")),
+        ("tui", cfg!(feature = "tui")),
+        ("sqlite", cfg!(feature = "sqlite")),
+    ];
+    for (feature, on) in built {
+        assert_eq!(FEATURES.contains(&feature), on, "{}", feature);
+    }
+    assert_eq!(FEATURES.len(), built.iter().filter(|(_, on)| *on).count());
+
+    let info = BuildInfo::new(&EvalParams::default()).to_string();
+    let listed = info
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("features="))
+        .unwrap();
+    match FEATURES {
+        [] => assert_eq!(listed, "none"),
+        features => assert_eq!(listed, features.join(",")),
+    }
+}
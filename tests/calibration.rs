@@ -0,0 +1,35 @@
+use ladybug::calibration::{fit_pawns_per_logit, Calibration};
+
+#[test]
+fn scores_and_bins() {
+    let text = "# predicted score\n0.9 1\n0.9 1\n0.8 0\n\n0.5 0.5\n0.1 0\n";
+    let calibration = Calibration::read(text.as_bytes(), 10).unwrap();
+    assert_eq!(calibration.count(), 5);
+    // (0.01 + 0.01 + 0.64 + 0 + 0.01) / 5
+    assert!((calibration.brier_score().unwrap() - 0.134).abs() < 1e-6);
+    let top = &calibration.bins()[9];
+    assert_eq!(top.count, 2);
+    assert_eq!(top.mean_actual(), Some(1f32));
+    assert_eq!(calibration.bins()[8].mean_actual(), Some(0f32));
+    assert!(calibration.to_string().contains("brier score 0.1340"));
+}
+
+#[test]
+fn malformed_predictions_are_rejected() {
+    assert!(Calibration::read("1.5 1\n".as_bytes(), 10).is_err());
+    assert!(Calibration::read("0.5\n".as_bytes(), 10).is_err());
+    assert_eq!(Calibration::new(10).brier_score(), None);
+}
+
+#[test]
+fn fit_recovers_the_scale() {
+    let samples: Vec<(f32, f32)> = (-40..=40)
+        .map(|fifths| {
+            let score = fifths as f32 / 5f32;
+            (score, 1f32 / (1f32 + (-score / 3f32).exp()))
+        })
+        .collect();
+    let scale = fit_pawns_per_logit(&samples).unwrap();
+    assert!((scale - 3f32).abs() < 0.01, "fitted {}", scale);
+    assert_eq!(fit_pawns_per_logit(&[]), None);
+}
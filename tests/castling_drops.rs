@@ -0,0 +1,86 @@
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingSide, Move, Position, Rank, Role, Setup, Square};
+
+fn play(position: &mut Bughouse, moves: &str) {
+    for uci in moves.split_whitespace() {
+        let m = uci
+            .parse::<Uci>()
+            .expect("valid uci")
+            .to_move(position)
+            .unwrap_or_else(|_| panic!("{} is legal", uci));
+        position.play_unchecked(&m);
+    }
+}
+
+fn castles(position: &Bughouse, side: CastlingSide) -> bool {
+    !position.castling_moves(side).is_empty()
+}
+
+#[test]
+fn dropped_rook_does_not_regain_castling_rights() {
+    let mut position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[R] w KQkq - 0 1").unwrap();
+    play(&mut position, "h1h2 a8a7 h2h3 a7a8");
+    assert!(!position.castling_rights().contains(Square::H1));
+    // The rook gets back home, another one is dropped next to it
+    play(&mut position, "h3h1 a8a7 R@g1 a7a8");
+    assert!(!position.castling_rights().contains(Square::H1));
+    assert!(!castles(&position, CastlingSide::KingSide));
+}
+
+#[test]
+fn drop_on_vacated_rook_square_does_not_restore_rights() {
+    let mut position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[R] w KQkq - 0 1").unwrap();
+    play(&mut position, "a1a2 a8a7 R@a1 a7a8");
+    assert!(!position.castling_rights().contains(Square::A1));
+    assert!(!castles(&position, CastlingSide::QueenSide));
+    assert!(castles(&position, CastlingSide::KingSide));
+}
+
+#[test]
+fn captured_rook_loses_rights_even_if_replaced_by_drop() {
+    let mut position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+    play(&mut position, "h8h1 e1d2 R@h8 d2d3");
+    assert!(!position.castling_rights().contains(Square::H1));
+    assert!(!position.castling_rights().contains(Square::H8));
+    assert!(!castles(&position, CastlingSide::KingSide));
+    assert!(castles(&position, CastlingSide::QueenSide));
+}
+
+#[test]
+fn drop_in_the_castling_path_blocks_castling() {
+    let mut position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[Nn] w KQkq - 0 1").unwrap();
+    assert!(castles(&position, CastlingSide::KingSide));
+    play(&mut position, "N@f1 N@b8");
+    assert!(!castles(&position, CastlingSide::KingSide));
+    assert!(castles(&position, CastlingSide::QueenSide));
+    // Rights are kept, castling is possible again once the path clears
+    assert!(position.castling_rights().contains(Square::H1));
+    assert!(position.castling_rights().contains(Square::A8));
+    play(&mut position, "f1g3 b8c6");
+    assert!(castles(&position, CastlingSide::KingSide));
+}
+
+#[test]
+fn drop_attacking_the_castling_path_prevents_castling() {
+    let mut position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[b] b KQkq - 0 1").unwrap();
+    // The bishop on h3 covers f1, the square the king passes
+    play(&mut position, "B@h3");
+    assert!(!castles(&position, CastlingSide::KingSide));
+    assert!(castles(&position, CastlingSide::QueenSide));
+    assert!(!position.legal_moves().iter().any(|m| matches!(
+        m,
+        Move::Castle { rook, .. } if *rook == Square::H1
+    )));
+}
+
+#[test]
+fn back_rank_drops_follow_pawn_rules() {
+    let position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R[Pp] w KQkq - 0 1").unwrap();
+    let drops_on_back_rank = position
+        .legal_moves()
+        .into_iter()
+        .any(|m| matches!(m, Move::Put { role: Role::Pawn, to } if to.rank() == Rank::First));
+    assert!(!drops_on_back_rank);
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::cancel::CancelToken;
+use ladybug::engine::{parse_move_list, LongAnalysis, RootFilter};
+use ladybug::eval::EvalParams;
+use ladybug::limits::{SearchControl, SearchLimits};
+
+fn control(nodes: u64) -> SearchControl {
+    SearchControl::new(SearchLimits::nodes(nodes), CancelToken::new())
+}
+
+#[test]
+fn resumed_analysis_continues_the_tree() {
+    let dir = std::env::temp_dir().join(format!("ladybug-checkpoint-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let first = dir.join("first.tree");
+    let second = dir.join("second.tree");
+    let params = Arc::new(EvalParams::default());
+    // An opening with a pawn in each pocket
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R[Pp] w KQkq - 4 4")
+            .unwrap();
+    let mut analysis = LongAnalysis::new(&position, params.clone(), 7);
+    analysis.run(&control(200));
+    analysis.checkpoint(&first).unwrap();
+
+    let mut resumed = LongAnalysis::resume(&first, params).unwrap();
+    assert_eq!(resumed.iterations(), analysis.iterations());
+    resumed.checkpoint(&second).unwrap();
+    assert_eq!(
+        fs::read_to_string(&first).unwrap(),
+        fs::read_to_string(&second).unwrap()
+    );
+    resumed.run(&control(100));
+    assert_eq!(resumed.iterations(), analysis.iterations() + 100);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn traced_analyses_time_every_phase() {
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R[Pp] w KQkq - 4 4")
+            .unwrap();
+    let mut analysis = LongAnalysis::new(&position, Arc::new(EvalParams::default()), 7);
+    analysis.run(&control(20));
+    assert!(analysis.trace().is_none());
+
+    analysis.set_tracing(true);
+    analysis.run(&control(100));
+    let json = analysis.trace().unwrap().to_json();
+    assert!(json.starts_with("{\"traceEvents\":["));
+    // 100 iterations make one full batch of 64 and one closed when the stretch ended
+    assert!(json.contains("\"name\":\"64 iterations\""), "{}", json);
+    assert!(json.contains("\"name\":\"36 iterations\""), "{}", json);
+    for phase in ["selection", "expansion", "rollout", "backpropagation"] {
+        assert_eq!(json.matches(&format!("\"name\":\"{}\"", phase)).count(), 2);
+    }
+}
+
+#[test]
+fn analyses_may_be_restricted_to_some_root_moves() {
+    // 1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6, where the queen mates on f7
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+    let mut analysis = LongAnalysis::new(&position, Arc::new(EvalParams::default()), 7);
+    assert_eq!(analysis.run(&control(200)).mate, Some(1));
+
+    let only = parse_move_list(&position, "d3,Nf3").unwrap();
+    analysis.set_root_filter(RootFilter {
+        only: Some(only.clone()),
+        ..RootFilter::default()
+    });
+    // The tree searched without the filter is gone
+    assert_eq!(analysis.iterations(), 0);
+    let result = analysis.run(&control(200));
+    assert!(only.contains(&result.best.unwrap()));
+    assert_eq!(result.mate, None);
+}
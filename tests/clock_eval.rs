@@ -0,0 +1,72 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::engine::{Engine, SearchOptions};
+use ladybug::eval::{clock_score, evaluate, evaluate_with_clocks, EvalParams};
+use ladybug::limits::SearchLimits;
+use shakmaty::{ByColor, Color};
+
+fn clocks(white: u64, black: u64) -> ByColor<Duration> {
+    ByColor {
+        white: Duration::from_secs(white),
+        black: Duration::from_secs(black),
+    }
+}
+
+fn clock_params(per_second: f32) -> EvalParams {
+    let mut params = EvalParams::default();
+    params.clock.per_second = per_second;
+    params
+}
+
+#[test]
+fn clock_advantage_counts_like_material_up_to_the_cap() {
+    let params = clock_params(0.1);
+    assert_eq!(clock_score(&clocks(30, 20), Color::White, &params), 1.0);
+    assert_eq!(clock_score(&clocks(30, 20), Color::Black, &params), -1.0);
+    // Capped at three pawns by default
+    assert_eq!(clock_score(&clocks(100, 10), Color::White, &params), 3.0);
+    assert_eq!(clock_score(&clocks(10, 100), Color::White, &params), -3.0);
+    // Off by default
+    let off = EvalParams::default();
+    assert_eq!(clock_score(&clocks(100, 10), Color::White, &off), 0.0);
+
+    let position =
+        parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[] w KQkq - 2 3").unwrap();
+    let material = evaluate(&position, &params);
+    assert_eq!(evaluate_with_clocks(&position, &params, None), material);
+    assert_eq!(
+        evaluate_with_clocks(&position, &params, Some(&clocks(30, 20))),
+        material + 1.0
+    );
+}
+
+#[test]
+fn clock_weights_round_trip_through_the_weights_file() {
+    let mut params = clock_params(0.25);
+    params.clock.cap = 5.0;
+    let text = params.to_text();
+    assert_eq!(EvalParams::parse(&text).unwrap(), params);
+}
+
+#[test]
+fn searches_with_known_clocks_favour_the_side_with_more_time() {
+    // Bare kings, so only the clocks tell the sides apart
+    let position = parse_fen("k7/8/8/8/8/8/8/K7[] w - - 0 1").unwrap();
+    let search = |clocks: Option<ByColor<Duration>>| {
+        let mut engine = Engine::new(Arc::new(clock_params(0.1)));
+        engine.set_options(SearchOptions {
+            rollout_depth: 0,
+            check_extension: 0,
+            clocks,
+            ..SearchOptions::default()
+        });
+        engine
+            .analyse(&position, SearchLimits::nodes(100))
+            .win_probability
+    };
+    assert_eq!(search(None), 0.5);
+    assert!(search(Some(clocks(60, 10))) > 0.9);
+    assert!(search(Some(clocks(10, 60))) < 0.1);
+}
@@ -0,0 +1,111 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use ladybug::cluster::{run_worker, Coordinator, JobSpec};
+
+fn jobs(count: u64) -> Vec<JobSpec> {
+    (0..count)
+        .map(|id| JobSpec {
+            id,
+            net_version: "weights-test".to_string(),
+            games: 1,
+            seed: id,
+        })
+        .collect()
+}
+
+fn out_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ladybug-cluster-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Serves `coordinator` on a thread, returning its address
+fn serve(mut coordinator: Coordinator) -> (String, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let handle = thread::spawn(move || coordinator.serve(&listener).unwrap());
+    (addr, handle)
+}
+
+fn request(addr: &str, line: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(line.as_bytes()).unwrap();
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).unwrap();
+    response
+}
+
+fn data(job: &JobSpec) -> io::Result<Vec<u8>> {
+    Ok(format!("games of job {}", job.id).into_bytes())
+}
+
+#[test]
+fn idle_clients_are_dropped_after_the_timeout() {
+    let dir = out_dir("idle");
+    let mut coordinator = Coordinator::new(jobs(2), dir.clone());
+    coordinator.timeout = Duration::from_millis(100);
+    let (addr, handle) = serve(coordinator);
+    // Connects and never says a word
+    let _idle = TcpStream::connect(&addr).unwrap();
+    assert_eq!(run_worker(&addr, "w", data).unwrap(), 2);
+    handle.join().unwrap();
+    assert_eq!(
+        std::fs::read(dir.join("job-1.dat")).unwrap(),
+        b"games of job 1"
+    );
+}
+
+#[test]
+fn jobs_of_dead_workers_go_to_the_living() {
+    let dir = out_dir("death");
+    let mut coordinator = Coordinator::new(jobs(3), dir.clone());
+    coordinator.lease = Duration::from_millis(300);
+    coordinator.retry = Duration::from_millis(20);
+    let (addr, handle) = serve(coordinator);
+    // A worker takes a job and dies before uploading it
+    assert!(request(&addr, "request doomed\n").starts_with("job 0"));
+    // The survivor does the other jobs, waits out the lease instead of leaving, then
+    // does the dead worker's job too
+    assert_eq!(run_worker(&addr, "survivor", data).unwrap(), 3);
+    handle.join().unwrap();
+    for id in 0..3 {
+        assert!(dir.join(format!("job-{}.dat", id)).exists(), "job {}", id);
+    }
+}
+
+#[test]
+fn late_uploads_of_reassigned_jobs_are_accepted() {
+    let dir = out_dir("late");
+    let mut coordinator = Coordinator::new(jobs(2), dir.clone());
+    coordinator.lease = Duration::from_millis(50);
+    let (addr, handle) = serve(coordinator);
+    assert!(request(&addr, "request slow\n").starts_with("job 0"));
+    thread::sleep(Duration::from_millis(100));
+    // The expired lease goes out before the pending job
+    assert!(request(&addr, "request quick\n").starts_with("job 0"));
+    assert_eq!(request(&addr, "upload 0 3\nnew"), "ok\n");
+    // The slow worker finds out nothing went wrong and carries on
+    assert_eq!(request(&addr, "upload 0 3\nold"), "ok\n");
+    assert_eq!(request(&addr, "upload 7 0\n"), "error unknown job\n");
+    assert_eq!(run_worker(&addr, "slow", data).unwrap(), 1);
+    handle.join().unwrap();
+    assert_eq!(std::fs::read(dir.join("job-0.dat")).unwrap(), b"new");
+}
+
+#[test]
+fn failing_workers_leave_their_job_leased() {
+    let dir = out_dir("failing");
+    let mut coordinator = Coordinator::new(jobs(1), dir.clone());
+    coordinator.lease = Duration::from_millis(100);
+    coordinator.retry = Duration::from_millis(20);
+    let (addr, handle) = serve(coordinator);
+    let err = run_worker(&addr, "broken", |_| Err(io::Error::other("crashed"))).unwrap_err();
+    assert_eq!(err.to_string(), "crashed");
+    assert_eq!(run_worker(&addr, "fixed", data).unwrap(), 1);
+    handle.join().unwrap();
+    assert!(dir.join("job-0.dat").exists());
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::process;
+
+use ladybug::board::parse_fen;
+use ladybug::config::{ConfigError, EngineConfig};
+use ladybug::engine::RootStrategy;
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+
+#[test]
+fn config_files_set_the_search_and_the_weights() {
+    let config = EngineConfig::parse(
+        "# tuned for blitz\n\
+         exploration = 1.2\n\
+         root_strategy = \"halving\"\n\
+         playout_cap = 20_000\n\
+         max_tree_size = 0\n\
+         seed = 7\n\
+         \n\
+         [eval]\n\
+         pocket.knight = 3.5\n\
+         \n\
+         [eval.policy]\n\
+         recapture = 3 # up from the default\n",
+    )
+    .unwrap();
+    let mut eval = EvalParams::default();
+    eval.pocket.knight = 3.5;
+    eval.policy.recapture = 3.0;
+    assert_eq!(
+        config,
+        EngineConfig::default()
+            .with_exploration(1.2)
+            .with_root_strategy(RootStrategy::SequentialHalving)
+            .with_playout_cap(20_000)
+            .with_seed(7)
+            .with_eval(eval)
+    );
+    assert_eq!(EngineConfig::parse(&config.to_toml()).unwrap(), config);
+}
+
+#[test]
+fn weights_files_are_found_next_to_the_config() {
+    let dir = std::env::temp_dir().join(format!("ladybug-config-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("weights.txt"), "board.rook = 6\n").unwrap();
+    fs::write(
+        dir.join("engine.toml"),
+        "eval_file = \"weights.txt\"\n[eval]\nboard.queen = 10\n",
+    )
+    .unwrap();
+    let config = EngineConfig::load(&dir.join("engine.toml"));
+    fs::remove_dir_all(&dir).unwrap();
+    let eval = config.unwrap().eval;
+    assert_eq!(eval.board.rook, 6.0);
+    assert_eq!(eval.board.queen, 10.0);
+}
+
+#[test]
+fn mistakes_are_reported_by_line() {
+    for (text, line) in [
+        ("exploration = -1", 1),
+        ("seed = 7\nthreads = 4", 2),
+        ("[search]", 1),
+        ("[eval]\npocket.king = 1", 2),
+        ("eval_file = weights.txt", 1),
+        ("seed = 7\nroot_strategy = \"best\"", 2),
+    ]
+    .iter()
+    {
+        match EngineConfig::parse(text) {
+            Err(ConfigError::Parse { line: at, .. }) => assert_eq!(at, *line, "{}", text),
+            other => panic!("{}: {:?}", text, other),
+        }
+    }
+}
+
+#[test]
+fn the_playout_cap_ends_the_search() {
+    let position =
+        parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+    let mut engine = EngineConfig::default()
+        .with_playout_cap(50)
+        .with_seed(1)
+        .engine();
+    let analysis = engine.analyse(&position, SearchLimits::nodes(10_000));
+    assert!(analysis.nodes <= 50, "{} playouts", analysis.nodes);
+}
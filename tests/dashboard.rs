@@ -0,0 +1,52 @@
+#![cfg(feature = "tui")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::{BoardId, BughouseGame};
+use ladybug::dashboard::Dashboard;
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::eval_bar::EvalUpdate;
+use ladybug::limits::SearchLimits;
+use ladybug::seats::{EngineAgent, Seat, Seating, TeamAgent};
+use shakmaty::Color;
+
+#[test]
+fn dashboard_shows_both_boards_evals_and_searches() {
+    let mut game = BughouseGame::new(Duration::from_secs(90));
+    let seat = Seat {
+        board: BoardId::A,
+        color: Color::White,
+    };
+    let mut agent = EngineAgent::new(
+        "engine",
+        Engine::new(Arc::new(EvalParams::default())),
+        SearchLimits::nodes(50),
+    );
+    let m = agent.choose_move(&game, seat).unwrap().unwrap();
+    game.play(BoardId::A, &m).unwrap();
+
+    let mut seating = Seating::new();
+    seating.sit(seat, "engine");
+    let mut dashboard = Dashboard::new(seating);
+    dashboard.observe(seat, &agent);
+    dashboard.eval(EvalUpdate {
+        board: BoardId::B,
+        white: 0.5,
+        best: None,
+    });
+    let screen = dashboard.render(&game);
+    assert!(screen.contains("board A"));
+    assert!(screen.contains("board B"));
+    assert!(screen.contains("1:30"));
+    assert!(screen.contains("[##########..........] 50%"));
+    // Board B is drawn from black's side: its first rank is at the top
+    assert!(screen.lines().nth(3).unwrap().ends_with("R N B K Q B N R"));
+    let search = screen
+        .lines()
+        .find(|line| line.starts_with("A-white"))
+        .unwrap();
+    assert!(search.contains("engine") && search.contains("nodes"));
+    assert!(!screen.contains("game over"));
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "sqlite")]
+
+use std::time::Duration;
+
+use ladybug::board::Bughouse;
+use ladybug::database::PositionDb;
+use ladybug::drill::Drill;
+use ladybug::engine::Analysis;
+use ladybug::insights::{GameRecord, StaticAnalyzer};
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position};
+
+fn play(position: &Bughouse, uci: &str) -> (Move, Bughouse) {
+    let m = uci.parse::<Uci>().unwrap().to_move(position).unwrap();
+    (m.clone(), position.clone().play(&m).unwrap())
+}
+
+#[test]
+fn analyses_puzzles_and_tags_survive_reopening() {
+    let path = std::env::temp_dir().join(format!("ladybug-db-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let start = Bughouse::default();
+    let (e4, after_e4) = play(&start, "e2e4");
+    let (_, after_d4) = play(&start, "d2d4");
+    {
+        let db = PositionDb::open(&path).unwrap();
+        let analysis = |nodes| Analysis {
+            best: Some(e4.clone()),
+            win_probability: 0.5,
+            mate: None,
+            nodes,
+        };
+        db.record_analysis(&start, &analysis(100)).unwrap();
+        // A shallower search does not replace a deeper one
+        db.record_analysis(&start, &analysis(10)).unwrap();
+        db.record_analysis(
+            &after_d4,
+            &Analysis {
+                best: None,
+                ..analysis(20)
+            },
+        )
+        .unwrap();
+
+        let mut drill = Drill::in_memory();
+        drill.add(&after_e4, &play(&after_e4, "f7f6").0, 0).unwrap();
+        db.import(&Default::default(), &drill).unwrap();
+        db.tag(&after_e4, "Open games").unwrap();
+        db.tag(&after_e4, "king pawn").unwrap();
+        db.tag(&after_d4, "closed").unwrap();
+    }
+
+    let db = PositionDb::open(&path).unwrap();
+    assert_eq!(db.analysis(&start).unwrap().unwrap().nodes, 100);
+    let similar = db.similar(&after_e4, 5).unwrap();
+    // Same material, the most searched first
+    let nodes: Vec<u64> = similar.iter().map(|analysis| analysis.nodes).collect();
+    assert_eq!(nodes, [100, 20]);
+    assert_eq!(similar[1].best, None);
+    assert_eq!(db.due_cards(0).unwrap().len(), 1);
+    assert_eq!(db.drill().unwrap().cards().len(), 1);
+    let found = db.search_tags("OPEN").unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].tags, ["Open games", "king pawn"]);
+    db.untag(&after_e4, "Open games").unwrap();
+    assert!(db.search_tags("open").unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn games_feed_insights_and_signatures() {
+    let db = PositionDb::in_memory().unwrap();
+    let start = Bughouse::default();
+    let mut position = start.clone();
+    let mut moves = Vec::new();
+    for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+        let (m, after) = play(&position, uci);
+        moves.push(m);
+        position = after;
+    }
+    let game = GameRecord {
+        start: start.clone(),
+        clocks: vec![Some(Duration::from_millis(59_500)), None, None, None],
+        moves,
+        user: Color::White,
+        outcome: Some(Outcome::Decisive {
+            winner: Color::Black,
+        }),
+    };
+    assert_eq!(db.add_game(&game).unwrap(), 1);
+    let games = db.games().unwrap();
+    assert_eq!(games[0].moves, game.moves);
+    assert_eq!(games[0].clocks, game.clocks);
+    assert_eq!(games[0].outcome, game.outcome);
+    let insights = db.insights(&mut StaticAnalyzer::default()).unwrap();
+    assert_eq!((insights.games, insights.losses), (1, 1));
+    let stats = db.signature_index().unwrap();
+    assert_eq!(stats.lookup(&start).unwrap().black_wins, 1);
+}
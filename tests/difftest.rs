@@ -0,0 +1,52 @@
+use std::io;
+
+use ladybug::board::Bughouse;
+use ladybug::cancel::CancelToken;
+use ladybug::difftest::{self, divide, DiffConfig, Divide, ReferenceEngine};
+use shakmaty::CastlingMode;
+
+// Our own move generator, optionally without knight drops
+struct Reference {
+    knight_drops: bool,
+}
+
+impl ReferenceEngine for Reference {
+    fn divide(&mut self, fen: &str, depth: u32) -> io::Result<Divide> {
+        let position = Bughouse::from_fen(fen, CastlingMode::Standard)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        let mut moves = divide(&position, depth);
+        if !self.knight_drops {
+            moves.retain(|m, _| !m.starts_with("N@"));
+        }
+        Ok(moves)
+    }
+}
+
+#[test]
+fn agreeing_generators_have_no_mismatches() {
+    let config = DiffConfig {
+        positions: 12,
+        ..DiffConfig::default()
+    };
+    let references: Vec<Box<dyn ReferenceEngine + Send>> = vec![
+        Box::new(Reference { knight_drops: true }),
+        Box::new(Reference { knight_drops: true }),
+    ];
+    assert_eq!(
+        difftest::run(&config, references, &CancelToken::new()).unwrap(),
+        vec![]
+    );
+}
+
+#[test]
+fn mismatches_are_minimized() {
+    let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R[Nb] w KQkq - 4 4";
+    let mut reference = Reference {
+        knight_drops: false,
+    };
+    let mismatch = difftest::compare(&mut reference, fen, 2).unwrap().unwrap();
+    assert_eq!(mismatch.reproduction_depth, 1);
+    assert_eq!(mismatch.reproduction, "4k3/8/8/8/8/8/8/4K3[N] w - - 4 4");
+    assert!(mismatch.difference.missing.is_empty());
+    assert!(mismatch.difference.extra.contains(&"N@e2".to_string()));
+}
@@ -0,0 +1,49 @@
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::drill::{motifs, Drill, Motif};
+use ladybug::insights::StaticAnalyzer;
+use shakmaty::uci::Uci;
+use shakmaty::Move;
+
+fn puzzle(fen: &str, blunder: &str) -> (Bughouse, Move) {
+    let position = parse_fen(fen).unwrap();
+    let m = blunder.parse::<Uci>().unwrap().to_move(&position).unwrap();
+    (position, m)
+}
+
+// Back rank check that only a dropped knight can block
+const BACK_RANK: &str = "6k1/8/8/8/8/8/6PP/r6K[N] w - - 0 1";
+// The queen mates on f7
+const SCHOLAR: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+
+#[test]
+fn detects_motifs() {
+    let mut analyzer = StaticAnalyzer::default();
+    let (position, blunder) = puzzle(BACK_RANK, "N@b1");
+    assert_eq!(
+        motifs(&position, &blunder, &mut analyzer),
+        vec![Motif::Drop]
+    );
+    let (position, blunder) = puzzle(SCHOLAR, "h5h4");
+    assert_eq!(
+        motifs(&position, &blunder, &mut analyzer),
+        vec![Motif::Mate]
+    );
+    assert_eq!("drops".parse(), Ok(Motif::Drop));
+}
+
+#[test]
+fn drills_only_matching_cards() {
+    let mut analyzer = StaticAnalyzer::default();
+    let mut deck = Drill::in_memory();
+    for (fen, blunder) in [(SCHOLAR, "h5h4"), (BACK_RANK, "N@b1")] {
+        let (position, m) = puzzle(fen, blunder);
+        deck.add(&position, &m, 0).unwrap();
+    }
+    assert_eq!(deck.next_due(10), Some(0));
+    assert_eq!(
+        deck.next_due_matching(10, Motif::Drop, &mut analyzer)
+            .unwrap(),
+        Some(1)
+    );
+}
@@ -0,0 +1,53 @@
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::eval::EvalParams;
+use ladybug::rollout::{MoveHistory, RolloutPolicy};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::{Bitboard, Color, Position, Square};
+
+fn back_rank(position: &Bughouse) -> Bitboard {
+    position.drop_mates(Color::White)
+}
+
+#[test]
+fn drop_mates_are_found_for_either_side() {
+    let expected: Bitboard = [Square::A8, Square::B8, Square::C8, Square::D8, Square::E8]
+        .iter()
+        .copied()
+        .collect();
+    let to_move = parse_fen("6k1/5ppp/8/8/8/8/8/4K3[R] w - - 0 1").unwrap();
+    assert_eq!(back_rank(&to_move), expected);
+    // The same threat, waiting for white's turn
+    let threat = parse_fen("6k1/5ppp/8/8/8/8/8/4K3[R] b - - 0 1").unwrap();
+    assert_eq!(back_rank(&threat), expected);
+    assert!(threat.drop_mates(Color::Black).is_empty());
+
+    let luft = parse_fen("6k1/5pp1/7p/8/8/8/8/4K3[R] w - - 0 1").unwrap();
+    assert!(back_rank(&luft).is_empty());
+}
+
+#[test]
+fn pruned_playouts_defend_against_drop_mates() {
+    let position = parse_fen("6k1/5ppp/8/8/8/8/8/4K3[R] b - - 0 1").unwrap();
+    let moves = position.legal_moves();
+    let params = EvalParams::default();
+    let mut policy = RolloutPolicy::default();
+    policy.prune_drop_threats = true;
+    let mut rng = StdRng::seed_from_u64(519);
+    for _ in 0..50 {
+        let m = policy
+            .choose(
+                &position,
+                &moves,
+                0,
+                &MoveHistory::default(),
+                &params,
+                &mut rng,
+            )
+            .unwrap();
+        let mut after = position.clone();
+        after.play_unchecked(&m);
+        assert!(back_rank(&after).is_empty(), "{} allows a drop mate", m);
+    }
+}
@@ -0,0 +1,139 @@
+use ladybug::board::parse_fen;
+use ladybug::drops::Pockets;
+use shakmaty::fen::Fen;
+use shakmaty::{
+    Bitboard, Board, CastlingMode, Chess, Color, Material, Move, MoveList, PositionErrorKinds,
+    Role, Setup, Square,
+};
+
+fn chess(fen: &str) -> Chess {
+    fen.parse::<Fen>()
+        .unwrap()
+        .position(CastlingMode::Standard)
+        .unwrap()
+}
+
+#[test]
+fn captures_fill_the_pocket_and_drops_empty_it() {
+    // The queen on h8 is a promoted pawn
+    let position = parse_fen("4k2Q~/8/8/8/8/8/7r/4K3[] b - - 0 1").unwrap();
+    let takes_queen = Move::Normal {
+        role: Role::Rook,
+        from: Square::H2,
+        capture: Some(Role::Queen),
+        to: Square::H8,
+        promotion: None,
+    };
+    assert_eq!(
+        Pockets::captured_role(position.board(), &takes_queen),
+        Some(Role::Pawn)
+    );
+
+    let mut pockets = Pockets::default();
+    pockets.play(position.board(), Color::Black, &takes_queen);
+    assert_eq!(pockets.side(Color::Black).pawns, 1);
+    let drop = Move::Put {
+        role: Role::Pawn,
+        to: Square::E4,
+    };
+    pockets.play(position.board(), Color::Black, &drop);
+    assert_eq!(pockets.material(), &Material::default());
+    assert!(!pockets.remove(Color::Black, Role::Pawn));
+
+    let en_passant = Move::EnPassant {
+        from: Square::E5,
+        to: Square::D6,
+    };
+    assert_eq!(
+        Pockets::captured_role(&Board::default(), &en_passant),
+        Some(Role::Pawn)
+    );
+}
+
+#[test]
+fn drop_squares_block_a_single_check_only() {
+    // Works on plain chess positions as well as on Bughouse
+    let quiet = chess("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    assert_eq!(
+        Pockets::legal_drop_squares(&quiet),
+        !quiet.board().occupied()
+    );
+
+    let checked = chess("4k3/8/8/8/8/8/8/r3K3 w - - 0 1");
+    assert_eq!(
+        Pockets::legal_drop_squares(&checked),
+        Bitboard::from(Square::B1) | Bitboard::from(Square::C1) | Bitboard::from(Square::D1)
+    );
+
+    let double_check = chess("4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1");
+    assert_eq!(Pockets::legal_drop_squares(&double_check), Bitboard(0));
+}
+
+#[test]
+fn pawn_drops_stay_off_the_back_ranks() {
+    let mut pockets = Pockets::default();
+    pockets.add(Color::White, Role::Pawn);
+    pockets.add(Color::White, Role::Knight);
+    let everywhere = !Bitboard(0);
+    assert!(pockets.can_drop(Color::White, Role::Pawn, Square::E4, everywhere, everywhere));
+    assert!(!pockets.can_drop(Color::White, Role::Pawn, Square::E8, everywhere, everywhere));
+    assert!(pockets.can_drop(
+        Color::White,
+        Role::Knight,
+        Square::E8,
+        everywhere,
+        everywhere
+    ));
+    assert!(!pockets.can_drop(
+        Color::White,
+        Role::Bishop,
+        Square::E4,
+        everywhere,
+        everywhere
+    ));
+    assert!(!pockets.can_drop(Color::Black, Role::Pawn, Square::E4, everywhere, everywhere));
+
+    let mut moves = MoveList::new();
+    pockets.push_drops(Color::White, everywhere, everywhere, &mut moves);
+    let pawn_drops = moves
+        .iter()
+        .filter(|m| {
+            matches!(
+                m,
+                Move::Put {
+                    role: Role::Pawn,
+                    ..
+                }
+            )
+        })
+        .count();
+    assert_eq!(pawn_drops, 48);
+    assert_eq!(moves.len(), 48 + 64);
+}
+
+#[test]
+fn validation_counts_the_pieces_in_hand() {
+    let board = Board::default();
+    let mut pockets = Pockets::default();
+    pockets.add(Color::White, Role::Queen);
+    // A tenth queen is impossible in chess but fine with a pocket
+    assert_eq!(
+        pockets.validate(&board, PositionErrorKinds::IMPOSSIBLE_MATERIAL),
+        PositionErrorKinds::empty()
+    );
+
+    pockets.add(Color::Black, Role::King);
+    assert_eq!(
+        pockets.validate(&board, PositionErrorKinds::empty()),
+        PositionErrorKinds::TOO_MANY_KINGS
+    );
+
+    // Two more sets of pieces in hand leave no room on the board
+    let mut overfull = Pockets::default();
+    overfull.add_material(Board::default().material());
+    overfull.add_material(Board::default().material());
+    assert_eq!(
+        overfull.validate(&board, PositionErrorKinds::empty()),
+        PositionErrorKinds::VARIANT
+    );
+}
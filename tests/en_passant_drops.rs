@@ -0,0 +1,80 @@
+use ladybug::board::parse_fen;
+use ladybug::board::{Bughouse, Rules};
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position, Setup, Square};
+
+fn play(position: &mut Bughouse, moves: &str) {
+    for uci in moves.split_whitespace() {
+        let m = uci
+            .parse::<Uci>()
+            .expect("valid uci")
+            .to_move(position)
+            .unwrap_or_else(|_| panic!("{} is legal", uci));
+        position.play_unchecked(&m);
+    }
+}
+
+fn can_take_en_passant(position: &Bughouse) -> bool {
+    position
+        .legal_moves()
+        .iter()
+        .any(|m| matches!(m, Move::EnPassant { .. }))
+}
+
+// Black's pawn on e4 waits for White to drop a pawn on d2 and push it two squares
+const FEN: &str = "4k3/8/8/8/4p3/8/8/4K3[P] w - - 0 1";
+
+fn with_rule(drop_blocks_en_passant: bool) -> Bughouse {
+    parse_fen(FEN).unwrap().with_rules(Rules {
+        drop_blocks_en_passant,
+        ..Rules::default()
+    })
+}
+
+#[test]
+fn dropped_pawn_can_be_taken_en_passant_by_default() {
+    let mut position = with_rule(false);
+    play(&mut position, "P@d2 e8d8 d2d4");
+    assert_eq!(position.ep_square(), Some(Square::D3));
+    assert!(can_take_en_passant(&position));
+}
+
+#[test]
+fn rule_protects_dropped_pawn_from_en_passant() {
+    let mut position = with_rule(true);
+    play(&mut position, "P@d2 e8d8 d2d4");
+    assert_eq!(position.ep_square(), None);
+    assert!(!can_take_en_passant(&position));
+    assert!(!position.is_legal(&Move::EnPassant {
+        from: Square::E4,
+        to: Square::D3
+    }));
+}
+
+#[test]
+fn rule_leaves_original_pawns_alone() {
+    let mut position = parse_fen("4k3/8/8/8/4p3/8/3P4/4K3 w - - 0 1")
+        .unwrap()
+        .with_rules(Rules {
+            drop_blocks_en_passant: true,
+            ..Rules::default()
+        });
+    play(&mut position, "d2d4");
+    assert_eq!(position.ep_square(), Some(Square::D3));
+    assert!(can_take_en_passant(&position));
+}
+
+#[test]
+fn other_pawns_can_still_be_taken_after_a_protected_double_step() {
+    let mut position = parse_fen("4k3/8/8/8/4p3/8/5P2/4K3[P] w - - 0 1")
+        .unwrap()
+        .with_rules(Rules {
+            drop_blocks_en_passant: true,
+            ..Rules::default()
+        });
+    play(&mut position, "P@d2 e8d8 d2d4 d8e8");
+    assert!(!can_take_en_passant(&position));
+    play(&mut position, "f2f4");
+    assert_eq!(position.ep_square(), Some(Square::F3));
+    assert!(can_take_en_passant(&position));
+}
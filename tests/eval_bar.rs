@@ -0,0 +1,38 @@
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::board::{BoardId, Bughouse};
+use ladybug::eval::EvalParams;
+use ladybug::eval_bar::{EvalBarConfig, EvalStream};
+
+#[test]
+fn changed_boards_are_evaluated_once() {
+    let (sink, updates) = mpsc::channel();
+    let stream = EvalStream::start(
+        Arc::new(EvalParams::default()),
+        EvalBarConfig {
+            interval: Duration::from_millis(10),
+            nodes: 8,
+        },
+        sink,
+    );
+    // Black is mated, so white wins on board B
+    let mated =
+        parse_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+    stream.update(BoardId::A, &Bughouse::default());
+    stream.update(BoardId::B, &mated);
+    let timeout = Duration::from_secs(10);
+    let mut boards = [
+        updates.recv_timeout(timeout).unwrap(),
+        updates.recv_timeout(timeout).unwrap(),
+    ];
+    boards.sort_by_key(|update| update.board);
+    assert!((0f32..=1f32).contains(&boards[0].white));
+    assert_eq!(boards[1].white, 1f32);
+    assert_eq!(boards[1].best, None);
+    // Nothing changed since
+    assert!(updates.recv_timeout(Duration::from_millis(100)).is_err());
+    drop(stream);
+}
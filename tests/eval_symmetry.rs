@@ -0,0 +1,69 @@
+use ladybug::board::Bughouse;
+use ladybug::eval::{evaluate, mobility, EvalParams};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use shakmaty::{Color, Position};
+
+fn random_positions(count: usize) -> Vec<Bughouse> {
+    let mut rng = StdRng::seed_from_u64(475);
+    let mut positions = Vec::new();
+    while positions.len() < count {
+        let mut position = Bughouse::default();
+        for _ in 0..60 {
+            let moves = position.legal_moves();
+            let m = match moves.choose(&mut rng) {
+                Some(m) => m.clone(),
+                None => break,
+            };
+            position.play_unchecked(&m);
+            positions.push(position.clone());
+        }
+    }
+    positions.truncate(count);
+    positions
+}
+
+#[test]
+fn evaluation_is_color_symmetric() {
+    let params = EvalParams::default();
+    for position in random_positions(500) {
+        let mirrored = position.mirrored();
+        let (original, flipped) = (evaluate(&position, &params), evaluate(&mirrored, &params));
+        assert!(
+            (original - flipped).abs() < 1e-3,
+            "{} vs {} for {:?}",
+            original,
+            flipped,
+            shakmaty::fen::fen(&position)
+        );
+    }
+}
+
+#[test]
+fn mobility_is_color_symmetric() {
+    let params = EvalParams::default();
+    for position in random_positions(500) {
+        let mirrored = position.mirrored();
+        for &color in &[Color::White, Color::Black] {
+            let original = mobility(&position, color, &params);
+            let flipped = mobility(&mirrored, !color, &params);
+            assert!((original - flipped).abs() < 1e-3);
+        }
+    }
+}
+
+#[test]
+fn pieces_in_hand_add_drop_mobility() {
+    let params = EvalParams::default();
+    let empty_hands = Bughouse::default();
+    let knight_in_hand = Bughouse::default().add_material("N".parse().unwrap());
+    assert!(
+        mobility(&knight_in_hand, Color::White, &params)
+            > mobility(&empty_hands, Color::White, &params)
+    );
+    assert_eq!(
+        mobility(&knight_in_hand, Color::Black, &params),
+        mobility(&empty_hands, Color::Black, &params)
+    );
+}
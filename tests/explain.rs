@@ -0,0 +1,103 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::explain::{explain, Reason};
+use ladybug::limits::SearchLimits;
+use ladybug::script::ScriptRunner;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position, Role};
+
+// Black to move could smother the white king with a knight drop on f2
+const SMOTHER_THREAT: &str = "6k1/8/8/8/8/8/6PP/6RK[n] w - - 0 1";
+// White to move mates with a knight drop on f7
+const SMOTHER: &str = "6rk/6pp/8/8/8/8/8/K7[N] w - - 0 1";
+
+fn play(position: &Bughouse, uci: &str) -> Move {
+    uci.parse::<Uci>().unwrap().to_move(position).unwrap()
+}
+
+#[test]
+fn captures_win_the_piece_and_what_it_brings_to_the_pocket() {
+    let params = EvalParams::default();
+    let mut position = Bughouse::default();
+    for uci in ["e2e4", "d7d5"] {
+        let m = play(&position, uci);
+        position.play_unchecked(&m);
+    }
+    let capture = play(&position, "e4d5");
+    let explanation = explain(&position, &capture, &params, None);
+    let value = params.board.by_role(Role::Pawn) + params.pocket.by_role(Role::Pawn);
+    assert_eq!(explanation.reasons, [Reason::MaterialGained { value }]);
+    assert!(explanation.to_string().starts_with("e4d5: wins "));
+
+    let quiet = play(&position, "g1f3");
+    let explanation = explain(&position, &quiet, &params, None);
+    assert!(explanation.reasons.is_empty());
+    assert_eq!(explanation.to_string(), "g1f3: no reason found");
+}
+
+#[test]
+fn threats_prevented_and_created_are_named() {
+    let params = EvalParams::default();
+    let position = parse_fen(SMOTHER_THREAT).unwrap();
+    let guard = play(&position, "g1f1");
+    let explanation = explain(&position, &guard, &params, None);
+    let threat = parse_fen(&SMOTHER_THREAT.replace(" w ", " b "))
+        .map(|swapped| play(&swapped, "N@f2"))
+        .unwrap();
+    assert_eq!(explanation.reasons, [Reason::ThreatPrevented { threat }]);
+    assert_eq!(explanation.to_string(), "g1f1: stops the mate N@f2");
+
+    let position = parse_fen(SMOTHER).unwrap();
+    let waiting = play(&position, "a1b1");
+    let explanation = explain(&position, &waiting, &params, None);
+    let mate = play(&position, "N@f7");
+    assert_eq!(explanation.reasons, [Reason::MateThreat { mate }]);
+}
+
+#[test]
+fn searched_moves_are_explained_with_the_line_the_engine_expects() {
+    let position = parse_fen(SMOTHER).unwrap();
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    let best = engine
+        .analyse(&position, SearchLimits::nodes(300))
+        .best
+        .unwrap();
+    let explanation = engine.explain(&position, &best);
+    assert!(explanation
+        .reasons
+        .iter()
+        .any(|reason| matches!(reason, Reason::DominantContinuation(_))));
+
+    // The tree is of another position, so it has nothing to say
+    let other = Bughouse::default();
+    let quiet = play(&other, "g1f3");
+    assert!(engine.explain(&other, &quiet).reasons.is_empty());
+}
+
+#[test]
+fn scripts_explain_the_best_move_or_the_one_given() {
+    let script = format!(
+        "setfen {}\nexplain a1b1\nnodes 100\nanalyze\nexplain\n",
+        SMOTHER
+    );
+    let mut output = Vec::new();
+    ScriptRunner::new(&mut output)
+        .run(Cursor::new(script))
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "a1b1: threatens mate with N@f7");
+    assert!(lines[1].starts_with("best N@f7 "));
+    assert!(lines[2].starts_with("N@f7: "));
+
+    let mut runner = ScriptRunner::new(Vec::new());
+    assert_eq!(
+        runner.execute("explain"),
+        Err("no move to explain".to_string())
+    );
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::build_info::BuildInfo;
+use ladybug::eval::EvalParams;
+use ladybug::fics::{FicsClient, FicsConfig, FicsEvent, Holdings, Style12};
+use ladybug::resign::ResignPolicy;
+use shakmaty::san::San;
+use shakmaty::{Color, Position, Role, Setup};
+
+const AFTER_E4: &str = "<12> rnbqkbnr pppppppp -------- -------- ----P--- -------- PPPP-PPP RNBQKBNR B 4 1 1 1 1 0 7 alice GuestABCD 1 2 0 39 39 118 120 1 P/e2-e4 (0:02) e4 1";
+
+#[test]
+fn style12_boards_become_fens_with_the_pockets_of_their_game() {
+    let board = Style12::parse(AFTER_E4).unwrap();
+    assert_eq!(board.game, 7);
+    assert_eq!(board.turn, Color::Black);
+    assert_eq!(board.double_push, Some(4));
+    assert!(board.is_our_move());
+    assert_eq!(board.our_clock(), 120);
+    assert_eq!(board.last_move, "e4");
+    assert_eq!(
+        board.fen(None),
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+    );
+
+    let holdings = Holdings::parse("<b1> game 7 white [NP] black [] <- WN").unwrap();
+    assert_eq!(holdings.game, 7);
+    assert_eq!(holdings.white, "NP");
+    assert_eq!(holdings.black, "");
+    let position = parse_fen(&board.fen(Some(&holdings))).unwrap();
+    assert_eq!(position.turn(), Color::Black);
+    assert_eq!(position.pockets().unwrap().white.by_role(Role::Knight), 1);
+}
+
+#[test]
+fn game_and_partner_lines_are_recognized() {
+    assert_eq!(
+        FicsEvent::parse("{Game 7 (alice vs. GuestABCD) Creating unrated bughouse match.}"),
+        FicsEvent::GameStart {
+            game: 7,
+            players: ["alice".to_string(), "GuestABCD".to_string()],
+            variant: "bughouse".to_string(),
+        }
+    );
+    assert_eq!(
+        FicsEvent::parse("{Game 7 (alice vs. GuestABCD) alice resigns} 0-1"),
+        FicsEvent::GameEnd {
+            game: 7,
+            result: "0-1".to_string()
+        }
+    );
+    assert_eq!(
+        FicsEvent::parse("carol (your partner) tells you: sit"),
+        FicsEvent::PartnerTell("sit".to_string())
+    );
+    assert_eq!(
+        FicsEvent::parse("**** Starting FICS session as GuestABCD(U) ****"),
+        FicsEvent::SessionStart("GuestABCD".to_string())
+    );
+}
+
+// Logs in as a guest through the telnet prompts, seeks, answers the board where it is
+// our move and logs out when the game ends
+#[test]
+fn plays_a_guest_session_from_login_to_game_end() {
+    let server = format!(
+        "\u{ff}\u{fb}\u{1}Welcome to FICS\n\rlogin: \
+         \"GuestABCD\" is not a registered name.\n\r\
+         Press return to enter the server as \"GuestABCD\": \n\r\
+         **** Starting FICS session as GuestABCD(U) ****\n\r\
+         fics% {{Game 7 (alice vs. GuestABCD) Creating unrated crazyhouse match.}}\n\r\
+         fics% <b1> game 7 white [] black []\n\r\
+         {}\n\r\
+         {}\n\r\
+         {{Game 7 (alice vs. GuestABCD) alice resigns}} 0-1\n\r\
+         fics% ",
+        AFTER_E4, AFTER_E4
+    );
+    let server: Vec<u8> = server
+        .chars()
+        .map(|ch| if ch == '\u{ff}' { 255 } else { ch as u8 })
+        .collect();
+    let mut sent = Vec::new();
+    let mut client = FicsClient::new(
+        server.as_slice(),
+        &mut sent,
+        FicsConfig::default(),
+        Arc::new(EvalParams::default()),
+    );
+    client.run().unwrap();
+    assert_eq!(client.handle(), Some("GuestABCD"));
+    assert_eq!(client.games_played(), 1);
+    drop(client);
+
+    let sent = String::from_utf8(sent).unwrap();
+    let lines: Vec<&str> = sent.lines().collect();
+    assert_eq!(&lines[..2], &["guest", ""]);
+    assert!(lines.contains(&"set style 12"));
+    let name = BuildInfo::new(&EvalParams::default()).name();
+    assert!(lines.contains(&format!("set interface {}", name).as_str()));
+    assert!(lines.contains(&"seek 2 0 crazyhouse"));
+    assert_eq!(lines.last(), Some(&"quit"));
+    // One move for the board, even though it came twice
+    let position = parse_fen(&Style12::parse(AFTER_E4).unwrap().fen(None)).unwrap();
+    let moves: Vec<&&str> = lines
+        .iter()
+        .filter(|line| {
+            position
+                .legal_moves()
+                .iter()
+                .any(|m| San::from_move(&position, m).to_string() == **line)
+        })
+        .collect();
+    assert_eq!(moves.len(), 1);
+}
+
+#[test]
+fn shutdown_resigns_the_running_game_and_logs_out() {
+    let mut sent = Vec::new();
+    let mut client = FicsClient::new(
+        &b""[..],
+        &mut sent,
+        FicsConfig::default(),
+        Arc::new(EvalParams::default()),
+    );
+    client
+        .handle_line("**** Starting FICS session as GuestABCD(U) ****")
+        .unwrap();
+    client
+        .handle_line("{Game 7 (alice vs. GuestABCD) Creating unrated crazyhouse match.}")
+        .unwrap();
+    client.shutdown.cancel();
+    client.run().unwrap();
+    drop(client);
+
+    let sent = String::from_utf8(sent).unwrap();
+    let lines: Vec<&str> = sent.lines().collect();
+    assert_eq!(&lines[lines.len() - 2..], &["resign", "quit"]);
+}
+
+#[test]
+fn boards_below_the_resign_threshold_are_resigned() {
+    let mut sent = Vec::new();
+    let config = FicsConfig {
+        resign: Some(ResignPolicy::default().with_threshold(1f32)),
+        ..FicsConfig::default()
+    };
+    let mut client = FicsClient::new(&b""[..], &mut sent, config, Arc::new(EvalParams::default()));
+    client
+        .handle_line("**** Starting FICS session as GuestABCD(U) ****")
+        .unwrap();
+    client
+        .handle_line("{Game 7 (alice vs. GuestABCD) Creating unrated crazyhouse match.}")
+        .unwrap();
+    client.handle_line(AFTER_E4).unwrap();
+    // The board coming again is not resigned twice
+    client.handle_line(AFTER_E4).unwrap();
+    drop(client);
+
+    let sent = String::from_utf8(sent).unwrap();
+    let resigns = sent.lines().filter(|line| *line == "resign").count();
+    assert_eq!(resigns, 1);
+}
+
+#[test]
+fn reserved_pieces_are_searched_but_only_played_once_they_arrive() {
+    // Black has only the king, and a knight on f2 would be mate
+    let board = "<12> ------k- -------- -------- -------- -------- -------- ------PP ------RK B -1 0 0 0 0 0 7 alice GuestABCD 1 2 0 9 0 118 120 30 R/a1-g1 (0:02) Rg1 1";
+    let mut sent = Vec::new();
+    let mut client = FicsClient::new(
+        &b""[..],
+        &mut sent,
+        FicsConfig {
+            resign: None,
+            ..FicsConfig::default()
+        },
+        Arc::new(EvalParams::default()),
+    );
+    client
+        .handle_line("**** Starting FICS session as GuestABCD(U) ****")
+        .unwrap();
+    client
+        .handle_line("{Game 7 (alice vs. GuestABCD) Creating unrated crazyhouse match.}")
+        .unwrap();
+    client
+        .handle_line("bob (your partner) tells you: reserve 0 n 1")
+        .unwrap();
+    client.handle_line(board).unwrap();
+    drop(client);
+
+    let sent = String::from_utf8(sent).unwrap();
+    let played = sent.lines().last().unwrap();
+    let position = parse_fen(&Style12::parse(board).unwrap().fen(None)).unwrap();
+    assert!(position
+        .legal_moves()
+        .iter()
+        .any(|m| San::from_move(&position, m).to_string() == played));
+}
@@ -0,0 +1,303 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ladybug::board::{Bughouse, Rules};
+use ladybug::cancel::CancelToken;
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::game::{Decision, GameAdapter, GameManager, GameState, Searched, Searcher};
+use ladybug::limits::SearchLimits;
+use ladybug::resign::ResignPolicy;
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Move, Outcome, Position, Setup};
+
+// Plays the first legal move, or what it is told to, and remembers every search
+#[derive(Default)]
+struct Scripted {
+    best: Option<Option<Move>>,
+    win_probability: Option<f32>,
+    searches: Vec<(Bughouse, SearchLimits)>,
+    new_games: usize,
+}
+
+impl Searcher for Scripted {
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        _: &CancelToken,
+    ) -> io::Result<Searched> {
+        self.searches.push((position.clone(), limits.clone()));
+        let best = match &self.best {
+            Some(best) => best.clone(),
+            None => position.legal_moves().first().cloned(),
+        };
+        Ok(Searched {
+            best,
+            win_probability: self.win_probability,
+        })
+    }
+
+    fn new_game(&mut self) {
+        self.new_games += 1;
+    }
+}
+
+// What a server would have received
+#[derive(Default)]
+struct Recorder {
+    moves: Vec<Option<String>>,
+    resigned: bool,
+}
+
+impl GameAdapter for Recorder {
+    type Error = io::Error;
+
+    fn play(&mut self, position: &Bughouse, m: Option<&Move>) -> io::Result<()> {
+        if let Some(m) = m {
+            assert!(position.is_legal(m));
+        }
+        self.moves
+            .push(m.map(|m| Uci::from_standard(m).to_string()));
+        Ok(())
+    }
+
+    fn resign(&mut self) -> io::Result<()> {
+        self.resigned = true;
+        Ok(())
+    }
+}
+
+fn scripted(color: Option<Color>, start: Bughouse) -> GameManager<Scripted> {
+    let mut manager = GameManager::new(Scripted::default(), color, start);
+    manager.limits = Some(SearchLimits::nodes(100));
+    manager
+}
+
+fn after(moves: &[&str]) -> Bughouse {
+    let mut position = Bughouse::default();
+    for uci in moves {
+        let m = uci.parse::<Uci>().unwrap().to_move(&position).unwrap();
+        position.play_unchecked(&m);
+    }
+    position
+}
+
+fn clocks(white: u64, black: u64) -> ByColor<Duration> {
+    ByColor {
+        white: Duration::from_secs(white),
+        black: Duration::from_secs(black),
+    }
+}
+
+#[test]
+fn games_move_between_thinking_and_waiting() {
+    let mut manager = scripted(Some(Color::White), Bughouse::default());
+    assert_eq!(manager.searcher().new_games, 1);
+    assert_eq!(manager.state(), GameState::Thinking);
+    let mut server = Recorder::default();
+    let decision = manager.think(&mut server).unwrap();
+    assert!(matches!(decision, Some(Decision::Play(Some(_)))));
+    assert_eq!(server.moves.len(), 1);
+    assert_eq!(manager.position().turn(), Color::Black);
+    assert_eq!(manager.state(), GameState::Waiting);
+    // Nothing to do on the opponent's move
+    assert_eq!(manager.think(&mut server).unwrap(), None);
+    assert_eq!(manager.searcher().searches.len(), 1);
+
+    // The server's word on the position and clocks wins
+    let first = server.moves[0].clone().unwrap();
+    let position = after(&[&first, "e7e5"]);
+    manager.sync(
+        position.clone(),
+        Some(clocks(170, 175)),
+        Duration::from_secs(2),
+    );
+    assert_eq!(manager.state(), GameState::Thinking);
+    assert_eq!(manager.position().board(), position.board());
+    manager.think(&mut server).unwrap();
+    assert_eq!(server.moves.len(), 2);
+    assert_eq!(manager.searcher().searches[1].0.board(), position.board());
+    // Our clock gained the increment, less the time the search took
+    let white = manager.clocks().unwrap().white;
+    assert!(white > Duration::from_secs(170) && white <= Duration::from_secs(172));
+    assert_eq!(manager.clocks().unwrap().black, Duration::from_secs(175));
+
+    manager.start(Some(Color::Black), Bughouse::default());
+    assert_eq!(manager.searcher().new_games, 2);
+    assert_eq!(manager.state(), GameState::Waiting);
+    assert_eq!(manager.clocks(), None);
+}
+
+#[test]
+fn clocks_set_the_pace_without_limits() {
+    let mut manager = scripted(Some(Color::White), Bughouse::default());
+    manager.limits = None;
+    manager.think(&mut Recorder::default()).unwrap();
+    // Without clocks the search runs until it is stopped
+    assert_eq!(manager.searcher().searches[0].1, SearchLimits::default());
+
+    manager.start(Some(Color::White), Bughouse::default());
+    manager.sync(Bughouse::default(), Some(clocks(60, 60)), Duration::ZERO);
+    manager.think(&mut Recorder::default()).unwrap();
+    let time = manager.searcher().searches[1].1.time.unwrap();
+    assert!(time > Duration::ZERO && time < Duration::from_secs(60));
+}
+
+#[test]
+fn finished_games_are_over() {
+    // Fool's mate
+    let mated = after(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+    let mut manager = scripted(Some(Color::White), mated);
+    assert_eq!(
+        manager.state(),
+        GameState::GameOver(Some(Outcome::Decisive {
+            winner: Color::Black
+        }))
+    );
+    assert_eq!(manager.think(&mut Recorder::default()).unwrap(), None);
+    assert!(manager.searcher().searches.is_empty());
+}
+
+#[test]
+fn lost_games_are_resigned() {
+    let mut manager = scripted(Some(Color::White), Bughouse::default());
+    manager.searcher_mut().win_probability = Some(0.01);
+    manager.resign = Some(ResignPolicy::default().with_threshold(0.05));
+    let mut server = Recorder::default();
+    assert_eq!(manager.think(&mut server).unwrap(), Some(Decision::Resign));
+    assert!(server.resigned && server.moves.is_empty());
+    assert_eq!(
+        manager.state(),
+        GameState::GameOver(Some(Outcome::Decisive {
+            winner: Color::Black
+        }))
+    );
+
+    // Searchers that can't tell how the game stands never resign
+    let mut manager = scripted(Some(Color::White), Bughouse::default());
+    manager.resign = Some(ResignPolicy::default().with_threshold(1.0));
+    assert!(matches!(
+        manager.think(&mut Recorder::default()).unwrap(),
+        Some(Decision::Play(Some(_)))
+    ));
+}
+
+#[test]
+fn passes_are_played_and_nothing_to_play_abandons() {
+    let handicap = Bughouse::default().with_rules(Rules {
+        pass: ByColor {
+            white: true,
+            black: false,
+        },
+        ..Rules::default()
+    });
+    let mut manager = scripted(Some(Color::White), handicap);
+    manager.searcher_mut().best = Some(None);
+    let mut server = Recorder::default();
+    assert_eq!(
+        manager.think(&mut server).unwrap(),
+        Some(Decision::Play(None))
+    );
+    assert_eq!(server.moves, [None]);
+    assert_eq!(manager.position().turn(), Color::Black);
+    assert_eq!(manager.state(), GameState::Waiting);
+
+    // Without the pass rule there is nothing to play
+    let mut manager = scripted(Some(Color::White), Bughouse::default());
+    manager.searcher_mut().best = Some(None);
+    let mut server = Recorder::default();
+    assert_eq!(manager.think(&mut server).unwrap(), Some(Decision::Abandon));
+    assert!(server.moves.is_empty());
+    assert_eq!(manager.state(), GameState::GameOver(None));
+}
+
+#[test]
+fn shutdown_plays_nothing() {
+    let mut manager = scripted(None, Bughouse::default());
+    manager.shutdown.cancel();
+    let mut server = Recorder::default();
+    assert_eq!(manager.think(&mut server).unwrap(), None);
+    assert!(server.moves.is_empty() && !server.resigned);
+    assert_eq!(manager.state(), GameState::Thinking);
+}
+
+#[test]
+fn both_sides_are_played_without_a_color() {
+    let mut manager = scripted(None, Bughouse::default());
+    for _ in 0..4 {
+        assert_eq!(manager.state(), GameState::Thinking);
+        manager.think(&mut ()).unwrap();
+    }
+    assert_eq!(manager.searcher().searches.len(), 4);
+    assert_eq!(manager.position().turn(), Color::White);
+}
+
+// Searches until it is stopped, like a search without limits
+#[derive(Default)]
+struct Patient {
+    pondered: Vec<Bughouse>,
+}
+
+impl Searcher for Patient {
+    fn search(
+        &mut self,
+        position: &Bughouse,
+        limits: &SearchLimits,
+        cancel: &CancelToken,
+    ) -> io::Result<Searched> {
+        if *limits == SearchLimits::default() {
+            while !cancel.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            self.pondered.push(position.clone());
+        }
+        Ok(Searched {
+            best: position.legal_moves().first().cloned(),
+            win_probability: None,
+        })
+    }
+}
+
+#[test]
+fn pondering_thinks_on_the_opponents_time() {
+    let mut manager = GameManager::new(Patient::default(), Some(Color::White), Bughouse::default());
+    manager.limits = Some(SearchLimits::nodes(100));
+    manager.ponder = true;
+    manager.think(&mut ()).unwrap();
+    assert_eq!(manager.state(), GameState::Pondering);
+
+    let stop = CancelToken::new();
+    let stopper = stop.clone();
+    let opponent = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        stopper.cancel();
+    });
+    manager.ponder(&stop).unwrap();
+    opponent.join().unwrap();
+    let pondered = &manager.searcher().pondered;
+    assert_eq!(pondered.len(), 1);
+    assert_eq!(pondered[0].turn(), Color::Black);
+
+    // Only while the opponent is to move
+    manager.sync(after(&["e2e4", "e7e5"]), None, Duration::ZERO);
+    assert_eq!(manager.state(), GameState::Thinking);
+    manager.ponder(&CancelToken::new()).unwrap();
+    assert_eq!(manager.searcher().pondered.len(), 1);
+}
+
+#[test]
+fn the_engine_plays_through_the_manager() {
+    let engine = Engine::new(Arc::new(EvalParams::default()));
+    let mut manager = GameManager::new(engine, Some(Color::White), Bughouse::default());
+    manager.limits = Some(SearchLimits::nodes(200));
+    manager.resign = Some(ResignPolicy::default());
+    let mut recorder = Recorder::default();
+    let decision = manager.think(&mut recorder).unwrap();
+    assert!(matches!(decision, Some(Decision::Play(Some(_)))));
+    assert_eq!(recorder.moves.len(), 1);
+    assert_eq!(manager.state(), GameState::Waiting);
+    assert_eq!(manager.position().turn(), Color::Black);
+}
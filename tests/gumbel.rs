@@ -0,0 +1,166 @@
+#![cfg(feature = "nn")]
+
+use std::sync::Arc;
+
+use ladybug::board::{parse_fen, Bughouse, Rules};
+use ladybug::cancel::CancelToken;
+use ladybug::engine::{parse_move_list, Engine, RootFilter, RootStrategy, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::{SearchControl, SearchLimits};
+use ladybug::selfplay::{play_game, SelfPlayConfig};
+use ladybug::training::{ExportOptions, TrainingExporter};
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Position};
+
+fn engine() -> Engine {
+    Engine::new(Arc::new(EvalParams::default()))
+}
+
+#[test]
+fn gumbel_root_search_agrees_with_puct_on_a_forced_move() {
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+    let puct = engine()
+        .search(&position, SearchLimits::nodes(300))
+        .unwrap();
+
+    let mut gumbel = engine();
+    gumbel.set_options(SearchOptions {
+        root_strategy: RootStrategy::Gumbel { considered: 16 },
+        ..SearchOptions::default()
+    });
+    let analysis = gumbel.analyse(&position, SearchLimits::nodes(300));
+    assert_eq!(analysis.best, Some(puct));
+    assert!(analysis.nodes <= 300);
+
+    // The improved policy favours the mate as well
+    let choice = engine()
+        .gumbel_search(
+            &position,
+            &SearchControl::new(SearchLimits::nodes(300), CancelToken::new()),
+            16,
+        )
+        .unwrap();
+    assert_eq!(choice.m.as_ref(), analysis.best.as_ref());
+    let total: f32 = choice.policy.iter().map(|(_, p)| p).sum();
+    assert!((total - 1f32).abs() < 1e-3);
+    let mate = choice
+        .policy
+        .iter()
+        .find(|(m, _)| m.as_ref() == analysis.best.as_ref())
+        .unwrap()
+        .1;
+    assert!(choice.policy.iter().all(|&(_, p)| p <= mate));
+}
+
+#[test]
+fn gumbel_search_chooses_a_pass_when_nothing_else_is_allowed() {
+    // White may pass but has every move excluded
+    let position = Bughouse::default().with_rules(Rules {
+        pass: ByColor {
+            white: true,
+            black: false,
+        },
+        ..Rules::default()
+    });
+    let mut engine = engine();
+    engine.set_root_filter(RootFilter {
+        only: None,
+        exclude: position.legal_moves().into_iter().collect(),
+    });
+    let control = SearchControl::new(SearchLimits::nodes(50), CancelToken::new());
+    let choice = engine.gumbel_search(&position, &control, 4).unwrap();
+    assert_eq!(choice.m, None);
+    assert_eq!(choice.policy.len(), 1);
+    assert_eq!(choice.policy[0].0, None);
+
+    // Listing the moves to search leaves the pass out
+    let e4 = parse_move_list(&position, "e4").unwrap();
+    engine.set_root_filter(RootFilter {
+        only: Some(e4.clone()),
+        exclude: Vec::new(),
+    });
+    let choice = engine.gumbel_search(&position, &control, 4).unwrap();
+    assert_eq!(choice.m.as_ref(), e4.first());
+    assert!(choice.policy.iter().all(|(m, _)| m.is_some()));
+}
+
+#[test]
+fn gumbel_games_search_without_root_noise() {
+    let config = SelfPlayConfig {
+        games: 1,
+        nodes: 30,
+        max_plies: 12,
+        seed: 7,
+        gumbel: Some(8),
+        ..SelfPlayConfig::default()
+    };
+    let options = config.search_options();
+    assert_eq!(
+        options.root_strategy,
+        RootStrategy::Gumbel { considered: 8 }
+    );
+    assert_eq!(options.root_noise, None);
+
+    let mut engine = engine();
+    engine.set_options(options);
+    let game = play_game(&mut engine, &config, 0);
+    assert!(!game.records.is_empty());
+}
+
+#[test]
+fn gumbel_games_train_on_the_improved_policy_and_play_its_move() {
+    let config = SelfPlayConfig {
+        games: 1,
+        nodes: 30,
+        max_plies: 4,
+        seed: 7,
+        gumbel: Some(8),
+        ..SelfPlayConfig::default()
+    };
+    let mut player = engine();
+    player.set_options(config.search_options());
+    let game = play_game(&mut player, &config, 0);
+    assert!(game.records.len() >= 2);
+    for record in &game.records {
+        let total: f32 = record.policy.as_ref().unwrap().iter().map(|(_, p)| p).sum();
+        assert!((total - 1f32).abs() < 1e-3);
+    }
+
+    // The same search from the same seed gives the first record and move
+    let mut replay = engine();
+    replay.set_options(config.search_options());
+    replay.set_seed(config.seed);
+    let start = Bughouse::default();
+    let control = SearchControl::new(SearchLimits::nodes(config.nodes), CancelToken::new());
+    let choice = replay.gumbel_search(&start, &control, 8).unwrap();
+    assert_eq!(game.records[0].policy.as_ref(), Some(&choice.policy));
+    let mut played = start;
+    match &choice.m {
+        Some(m) => played.play_unchecked(m),
+        None => assert!(played.pass()),
+    }
+    assert_eq!(game.records[1].position.fen(), played.fen());
+
+    // The export trains towards that policy rather than the visits
+    let params = Arc::new(EvalParams::default());
+    let mut exporter =
+        TrainingExporter::new(ExportOptions::default(), &params, &config.search_options());
+    exporter.add_game(&game.records[..1], game.outcome);
+    let sample = exporter
+        .to_text()
+        .lines()
+        .find(|line| !line.starts_with('#'))
+        .unwrap()
+        .to_string();
+    let exported = sample.split(';').nth(1).unwrap();
+    assert_eq!(
+        exported.split(' ').count(),
+        choice.policy.len(),
+        "{}",
+        exported
+    );
+    let (m, p) = &choice.policy[0];
+    let uci = m.as_ref().map_or(Uci::Null, Uci::from_standard);
+    assert!(exported.contains(&format!("{}:{:.4}", uci, p)));
+}
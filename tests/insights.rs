@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use ladybug::board::Bughouse;
+use ladybug::bpgn::parse;
+use ladybug::eval::EvalParams;
+use ladybug::insights::{Analyzer, GamePhase, Insights, SearchAnalyzer, StaticAnalyzer};
+use shakmaty::uci::Uci;
+use shakmaty::{Position, Role};
+
+// Black on board A takes a pawn, which black's partner drops on board B, and the team
+// of black on board A wins
+const GAME: &str = r#"[WhiteA "alice"]
+[BlackA "bob"]
+[WhiteB "carol"]
+[BlackB "dave"]
+[Result "0-1"]
+
+1A. e4{179.8} 1B. d4{179.5} 1a. d5{179.1} 1b. Nf6{8.0}
+2A. Nc3{178.2} 2a. dxe4{177.9} 2B. P@e5{176.4} 0-1
+"#;
+
+fn insights_of(player: &str) -> Insights {
+    let mut insights = Insights::new();
+    for game in parse(GAME).unwrap() {
+        insights.add_bpgn(&game, player, &mut StaticAnalyzer::default());
+    }
+    insights
+}
+
+#[test]
+fn bpgn_games_count_for_the_board_the_player_sat_at() {
+    assert_eq!(insights_of("nobody").games, 0);
+
+    // Carol played on board B with the pawn from her partner's pocket, and won
+    let carol = insights_of("carol");
+    assert_eq!((carol.games, carol.losses), (1, 0));
+    assert_eq!(
+        carol.favorite_drops(1),
+        [((Role::Pawn, "e5".parse().unwrap()), 1)]
+    );
+    assert_eq!(carol.openings(), [("d2d4 g8f6", 1, 1f32)]);
+    assert!(carol.blunder_rate(GamePhase::Opening).is_some());
+    assert_eq!(carol.blunder_rate(GamePhase::Endgame), None);
+
+    // The result is that of white on board A and black on board B
+    let dave = insights_of("dave");
+    assert_eq!(
+        (dave.games, dave.losses, dave.time_trouble_losses),
+        (1, 1, 1)
+    );
+    assert_eq!(dave.openings(), [("d2d4 g8f6", 1, 0f32)]);
+    let alice = insights_of("alice");
+    assert_eq!(
+        (alice.games, alice.losses, alice.time_trouble_losses),
+        (1, 1, 0)
+    );
+    assert_eq!(alice.openings(), [("e2e4 d7d5", 1, 0f32)]);
+    assert!(alice.to_string().contains("losses in time trouble: 0 of 1"));
+}
+
+#[test]
+fn searched_blunders_allow_what_the_engine_would_not() {
+    let mut analyzer = SearchAnalyzer::new(Arc::new(EvalParams::default()), 300);
+    let mut position = Bughouse::default();
+    for uci in ["f2f3", "e7e5"] {
+        let m = uci.parse::<Uci>().unwrap().to_move(&position).unwrap();
+        position = position.play(&m).unwrap();
+    }
+    // 2. g4 allows Qh4 mate
+    let mated = "g2g4".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    let develop = "b1c3".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    assert!(analyzer.is_blunder(&position, &mated));
+    assert!(!analyzer.is_blunder(&position, &develop));
+}
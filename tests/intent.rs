@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::board::{BoardId, BughouseGame};
+use ladybug::intent::{constraints, intents, PartnerIntent};
+use ladybug::seats::Seat;
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Position, Role};
+
+const WHITE_ON_A: Seat = Seat {
+    board: BoardId::A,
+    color: Color::White,
+};
+
+fn game(fen: &str) -> BughouseGame {
+    let clocks = ByColor {
+        white: Duration::from_secs(60),
+        black: Duration::from_secs(60),
+    };
+    BughouseGame::from_boards(
+        parse_fen(fen).unwrap(),
+        parse_fen("4k3/8/8/8/8/8/8/4K3[] w - - 0 1").unwrap(),
+        clocks.clone(),
+        clocks,
+    )
+}
+
+#[test]
+fn ptells_parse_in_the_usual_phrasings() {
+    for (text, intent) in [
+        ("need n", PartnerIntent::Need(Role::Knight)),
+        ("Q", PartnerIntent::Need(Role::Queen)),
+        ("give me a knight", PartnerIntent::Need(Role::Knight)),
+        ("no r", PartnerIntent::Avoid(Role::Rook)),
+        ("don't trade queens", PartnerIntent::Avoid(Role::Queen)),
+        ("mate", PartnerIntent::MateThreat),
+        ("sit", PartnerIntent::Sit),
+        ("move", PartnerIntent::Go),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            text.parse::<PartnerIntent>().as_ref(),
+            Ok(intent),
+            "{}",
+            text
+        );
+        assert_eq!(
+            intent.to_string().parse::<PartnerIntent>().as_ref(),
+            Ok(intent)
+        );
+    }
+    assert!("nice move".parse::<PartnerIntent>().is_err());
+}
+
+#[test]
+fn asks_for_the_cheapest_mating_piece() {
+    // A back rank mate short of a rook or queen
+    let back_rank = game("6k1/5ppp/8/8/8/8/8/4K3[] w - - 0 1");
+    assert_eq!(
+        intents(&back_rank, WHITE_ON_A),
+        [PartnerIntent::Need(Role::Rook)]
+    );
+}
+
+#[test]
+fn warns_of_pieces_that_let_the_opponent_mate() {
+    // A knight on f2 would smother the white king
+    let quiet = game("k7/8/8/8/8/8/6PP/6RK[] w - - 0 1");
+    assert_eq!(
+        intents(&quiet, WHITE_ON_A),
+        [PartnerIntent::Avoid(Role::Knight), PartnerIntent::Sit]
+    );
+    // With the knight in hand the threat is immediate
+    let threatened = game("k7/8/8/8/8/8/6PP/6RK[n] w - - 0 1");
+    assert_eq!(
+        intents(&threatened, WHITE_ON_A),
+        [PartnerIntent::MateThreat]
+    );
+}
+
+#[test]
+fn requests_constrain_the_root_moves() {
+    // The queen on d1 is attacked by the rook, and the knight can take the queen on g5
+    let position = parse_fen("3rk3/8/8/6q1/8/5N2/8/3QK3[] w - - 0 1").unwrap();
+
+    let need = constraints(&position, &[PartnerIntent::Need(Role::Queen)]);
+    let only: Vec<String> = need
+        .filter
+        .only
+        .unwrap()
+        .iter()
+        .map(|m| Uci::from_standard(m).to_string())
+        .collect();
+    assert_eq!(only, ["f3g5"]);
+
+    let avoid = constraints(&position, &[PartnerIntent::Avoid(Role::Queen)]);
+    assert!(!avoid.filter.exclude.is_empty());
+    for m in position.legal_moves() {
+        let mut after = position.clone();
+        after.play_unchecked(&m);
+        let exposed = after
+            .legal_moves()
+            .iter()
+            .any(|reply| reply.capture() == Some(Role::Queen));
+        assert_eq!(
+            avoid.filter.allows(&m),
+            !exposed,
+            "{}",
+            Uci::from_standard(&m)
+        );
+    }
+
+    let held = constraints(
+        &position,
+        &[
+            PartnerIntent::Sit,
+            PartnerIntent::MateThreat,
+            PartnerIntent::Go,
+            PartnerIntent::Sit,
+        ],
+    );
+    assert!(held.sit);
+    assert_eq!(held.partner_danger, Some(1.0));
+    assert_eq!(held.filter, Default::default());
+}
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::eval::{king_danger, EvalParams};
+use ladybug::prior::{EvalPriors, PriorSource};
+use shakmaty::{Color, Move, Position, Role, Square};
+
+#[test]
+fn pieces_in_the_opponents_hand_endanger_an_open_king() {
+    let params = EvalParams::default();
+    let empty_hands = parse_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let knight = parse_fen("4k3/8/8/8/8/8/8/4K3[n] w - - 0 1").unwrap();
+    let bishop = parse_fen("4k3/8/8/8/8/8/8/4K3[b] w - - 0 1").unwrap();
+    assert_eq!(king_danger(&empty_hands, Color::White, &params), 0f32);
+    assert!(king_danger(&knight, Color::White, &params) > 0f32);
+    assert!(king_danger(&bishop, Color::White, &params) > 0f32);
+    // Black's king is not threatened by black's own pieces
+    assert_eq!(king_danger(&knight, Color::Black, &params), 0f32);
+}
+
+#[test]
+fn expansion_favours_winning_material() {
+    // The queen on d5 hangs to the pawn on e4
+    let position = parse_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    let moves = position.legal_moves();
+    let priors = EvalPriors {
+        params: Arc::new(EvalParams::default()),
+    }
+    .priors(&position, &moves);
+    let best = moves
+        .iter()
+        .zip(&priors)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(m, _)| m.clone())
+        .unwrap();
+    assert_eq!(
+        best,
+        Move::Normal {
+            role: Role::Pawn,
+            from: Square::E4,
+            capture: Some(Role::Queen),
+            to: Square::D5,
+            promotion: None,
+        }
+    );
+}
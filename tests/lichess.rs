@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Cursor};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ladybug::eval::EvalParams;
+use ladybug::json::JsonValue;
+use ladybug::lichess::{BotApi, Challenge, ChallengeFilter, LichessBot, LichessError};
+use ladybug::resign::ResignPolicy;
+use shakmaty::uci::Uci;
+use shakmaty::Position;
+
+// Serves canned game streams by game id and records what the bot sends
+#[derive(Clone, Default)]
+struct FakeApi {
+    games: HashMap<String, String>,
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeApi {
+    fn record(&self, request: String) -> Result<(), LichessError> {
+        self.sent.lock().unwrap().push(request);
+        Ok(())
+    }
+}
+
+impl BotApi for FakeApi {
+    fn account_id(&self) -> Result<String, LichessError> {
+        Ok("ladybug".to_string())
+    }
+
+    fn stream_events(&self) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        Ok(Box::new(Cursor::new(String::new())))
+    }
+
+    fn stream_game(&self, game: &str) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        Ok(Box::new(Cursor::new(
+            self.games.get(game).cloned().unwrap_or_default(),
+        )))
+    }
+
+    fn accept(&self, challenge: &str) -> Result<(), LichessError> {
+        self.record(format!("accept {}", challenge))
+    }
+
+    fn decline(&self, challenge: &str, reason: &str) -> Result<(), LichessError> {
+        self.record(format!("decline {} {}", challenge, reason))
+    }
+
+    fn play(&self, game: &str, uci: &str) -> Result<(), LichessError> {
+        self.record(format!("move {} {}", game, uci))
+    }
+
+    fn resign(&self, game: &str) -> Result<(), LichessError> {
+        self.record(format!("resign {}", game))
+    }
+
+    fn abort(&self, game: &str) -> Result<(), LichessError> {
+        self.record(format!("abort {}", game))
+    }
+}
+
+fn challenge(variant: &str, limit: u64, increment: u64) -> Challenge {
+    let json = format!(
+        r#"{{"id":"c1","challenger":{{"id":"someone"}},"variant":{{"key":"{}"}},"rated":true,"timeControl":{{"type":"clock","limit":{},"increment":{}}}}}"#,
+        variant, limit, increment
+    );
+    Challenge::from_json(&JsonValue::parse(&json).unwrap()).unwrap()
+}
+
+#[test]
+fn challenges_are_filtered_by_variant_and_time_control() {
+    let filter = ChallengeFilter {
+        max_initial: Duration::from_secs(600),
+        ..ChallengeFilter::default()
+    };
+    assert_eq!(
+        filter.decline_reason(&challenge("crazyhouse", 180, 2)),
+        None
+    );
+    assert_eq!(
+        filter.decline_reason(&challenge("standard", 180, 2)),
+        Some("variant")
+    );
+    assert_eq!(
+        filter.decline_reason(&challenge("crazyhouse", 15, 0)),
+        Some("tooFast")
+    );
+    assert_eq!(
+        filter.decline_reason(&challenge("crazyhouse", 1800, 0)),
+        Some("tooSlow")
+    );
+
+    let api = FakeApi::default();
+    let bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.answer(&challenge("crazyhouse", 180, 2)).unwrap();
+    bot.answer(&challenge("atomic", 180, 2)).unwrap();
+    assert_eq!(
+        *api.sent.lock().unwrap(),
+        ["accept c1", "decline c1 variant"]
+    );
+}
+
+#[test]
+fn the_bot_moves_when_it_is_its_turn() {
+    let full = r#"{"type":"gameFull","id":"g1","variant":{"key":"crazyhouse"},"initialFen":"startpos","white":{"id":"someone"},"black":{"id":"ladybug"},"state":{"type":"gameState","moves":"e2e4","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}}"#;
+    let finished = r#"{"type":"gameState","moves":"e2e4 e7e5","wtime":1,"btime":1,"winc":0,"binc":0,"status":"resign"}"#;
+    let api = FakeApi {
+        games: vec![("g1".to_string(), format!("{}\n\n{}\n", full, finished))]
+            .into_iter()
+            .collect(),
+        ..FakeApi::default()
+    };
+    let mut bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.time.moves_to_go = 1000;
+    bot.play_game("g1", "ladybug").unwrap();
+
+    let sent = api.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    let uci = sent[0].strip_prefix("move g1 ").expect("a move");
+    let mut position = ladybug::board::Bughouse::default();
+    let e4 = "e2e4".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    position.play_unchecked(&e4);
+    assert!(uci.parse::<Uci>().unwrap().to_move(&position).is_ok());
+}
+
+// A game in progress with `moves` played, where it is the bot's move as black
+fn started(moves: &str) -> String {
+    format!(
+        r#"{{"type":"gameFull","id":"g","variant":{{"key":"crazyhouse"}},"initialFen":"startpos","white":{{"id":"someone"}},"black":{{"id":"ladybug"}},"state":{{"type":"gameState","moves":"{}","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}}}}"#,
+        moves
+    ) + "\n"
+}
+
+#[test]
+fn shutdown_aborts_fresh_games_and_resigns_the_others() {
+    let api = FakeApi {
+        games: vec![
+            ("fresh".to_string(), started("e2e4")),
+            ("going".to_string(), started("e2e4 e7e5 g1f3")),
+        ]
+        .into_iter()
+        .collect(),
+        ..FakeApi::default()
+    };
+    let bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.shutdown.cancel();
+    // No move is played once shutdown is requested
+    bot.play_game("fresh", "ladybug").unwrap();
+    bot.play_game("going", "ladybug").unwrap();
+    bot.run().unwrap();
+
+    let mut sent = api.sent.lock().unwrap().clone();
+    sent.sort();
+    assert_eq!(sent, ["abort fresh", "resign going"]);
+}
+
+#[test]
+fn the_bot_resigns_below_the_threshold() {
+    let api = FakeApi {
+        games: vec![("g".to_string(), started("e2e4"))]
+            .into_iter()
+            .collect(),
+        ..FakeApi::default()
+    };
+    let mut bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.time.moves_to_go = 1000;
+    // Nothing short of a proven win clears a threshold of 1
+    bot.resign = Some(ResignPolicy::default().with_threshold(1f32));
+    bot.play_game("g", "ladybug").unwrap();
+    assert_eq!(*api.sent.lock().unwrap(), ["resign g"]);
+
+    api.sent.lock().unwrap().clear();
+    bot.resign = None;
+    bot.play_game("g", "ladybug").unwrap();
+    assert!(api.sent.lock().unwrap()[0].starts_with("move g "));
+}
+
+#[test]
+fn the_bot_keeps_up_with_a_fast_opponent() {
+    let full = r#"{"type":"gameFull","id":"g","variant":{"key":"crazyhouse"},"initialFen":"startpos","white":{"id":"someone"},"black":{"id":"ladybug"},"state":{"type":"gameState","moves":"","wtime":20000,"btime":20000,"winc":0,"binc":0,"status":"started"}}"#;
+    // White took a tenth of a second for the move
+    let moved = r#"{"type":"gameState","moves":"e2e4","wtime":19900,"btime":20000,"winc":0,"binc":0,"status":"started"}"#;
+    let finished = r#"{"type":"gameState","moves":"e2e4","wtime":19900,"btime":20000,"winc":0,"binc":0,"status":"resign"}"#;
+    let api = FakeApi {
+        games: vec![(
+            "g".to_string(),
+            format!("{}\n{}\n{}\n", full, moved, finished),
+        )]
+        .into_iter()
+        .collect(),
+        ..FakeApi::default()
+    };
+    let mut bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.resign = None;
+    // Two seconds for the move against an unknown opponent, half that at most here
+    bot.time.moves_to_go = 10;
+    bot.time.safety_margin = Duration::from_secs(0);
+    let started = std::time::Instant::now();
+    bot.play_game("g", "ladybug").unwrap();
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    assert!(api.sent.lock().unwrap()[0].starts_with("move g "));
+}
+
+#[test]
+fn the_bot_ponders_until_the_opponent_moves() {
+    let full = r#"{"type":"gameFull","id":"g","variant":{"key":"crazyhouse"},"initialFen":"startpos","white":{"id":"someone"},"black":{"id":"ladybug"},"state":{"type":"gameState","moves":"","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}}"#;
+    let moved = r#"{"type":"gameState","moves":"e2e4","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}"#;
+    let finished = r#"{"type":"gameState","moves":"e2e4","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"resign"}"#;
+    let api = FakeApi {
+        games: vec![(
+            "g".to_string(),
+            format!("{}\n{}\n{}\n", full, moved, finished),
+        )]
+        .into_iter()
+        .collect(),
+        ..FakeApi::default()
+    };
+    let mut bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.time.moves_to_go = 1000;
+    bot.ponder = true;
+    bot.play_game("g", "ladybug").unwrap();
+
+    let sent = api.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert!(sent[0].starts_with("move g "));
+}
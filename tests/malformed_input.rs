@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Cursor, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ladybug::cluster::{Coordinator, JobSpec};
+use ladybug::eval::{EvalHandle, EvalParams};
+use ladybug::json::JsonValue;
+use ladybug::lichess::{BotApi, LichessBot, LichessError};
+use ladybug::uci::UciEngine;
+use ladybug::xboard::XboardEngine;
+use shakmaty::uci::Uci;
+use shakmaty::Position;
+
+// Every front-end is fed broken input and must neither panic nor lose track of the
+// game: after the garbage, a well-formed exchange still gets the right answer.
+
+// Output the test can still read after handing it to the engine
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Shared {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.lock().unwrap())
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn uci_session(input: &[u8]) -> Vec<String> {
+    let output = Shared::default();
+    let mut engine = UciEngine::new(output.clone(), EvalHandle::default());
+    engine.run(input).unwrap();
+    output.lines()
+}
+
+fn xboard_session(input: &[u8]) -> Vec<String> {
+    let output = Shared::default();
+    let mut engine = XboardEngine::new(output.clone(), EvalHandle::default());
+    engine.run(input).unwrap();
+    output.lines()
+}
+
+// The move after `bestmove` in the last search
+fn last_bestmove(lines: &[String]) -> &str {
+    lines
+        .iter()
+        .rev()
+        .find_map(|line| line.strip_prefix("bestmove "))
+        .expect("no bestmove")
+        .split_whitespace()
+        .next()
+        .unwrap()
+}
+
+// Whether `uci` is legal after 1. e4 in crazyhouse
+fn legal_after_e4(uci: &str) -> bool {
+    let mut position = ladybug::board::Bughouse::default();
+    let e4 = "e2e4".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    position.play_unchecked(&e4);
+    uci.parse::<Uci>()
+        .ok()
+        .is_some_and(|uci| uci.to_move(&position).is_ok())
+}
+
+const UCI_GARBAGE: &[&str] = &[
+    "",
+    "   ",
+    "\t\t",
+    "position",
+    "position fen",
+    "position fen rnbqkbnr/pppp",
+    "position startpos moves",
+    "position startpos moves e2e4 e2e4",
+    "position startpos moves zz99 @@",
+    "position moves e2e4",
+    "position fen 8/8/8/8/8/8/8/8 w - - 0 1",
+    "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 99999999999999999999 1",
+    "go",
+    "go wtime",
+    "go wtime -5 btime abc winc",
+    "go depth 18446744073709551615 nodes 1",
+    "go movetime 0",
+    "go searchmoves",
+    "go searchmoves z9z9 excludemoves",
+    "stop",
+    "ponderhit",
+    "setoption",
+    "setoption name",
+    "setoption name Threads value 0",
+    "setoption name Threads value 99999999999999999999",
+    "setoption name Exploration value NaN",
+    "setoption name Exploration value -1",
+    "setoption name Seed value -7",
+    "setoption name UCI_Variant value",
+    "setoption name Rules value ???",
+    "setoption value 3",
+    "ucinewgame ucinewgame",
+    "isready extra words",
+    "ïsready",
+    "\u{0}\u{1}\u{7f}",
+    "go ponder",
+    "ponderhit",
+    "go infinite",
+    "stop",
+];
+
+#[test]
+fn uci_survives_garbage_and_stays_in_sync() {
+    let mut input = Vec::new();
+    for line in UCI_GARBAGE {
+        input.extend_from_slice(line.as_bytes());
+        input.push(b'\n');
+    }
+    // Bytes that are not UTF-8 and a line cut off without its newline
+    input.extend_from_slice(b"\xff\xfe position \xc3\n");
+    input.extend_from_slice(b"isready\n");
+    input.extend_from_slice(b"position startpos moves e2e4\ngo nodes 30\nisready\nposit");
+    let lines = uci_session(&input);
+
+    // `isready` is answered at once, even while searching, and extra words after it
+    // don't matter
+    let count = |prefix: &str| lines.iter().filter(|line| line.starts_with(prefix)).count();
+    assert_eq!(count("readyok"), 3, "{:#?}", lines);
+    let searches = UCI_GARBAGE
+        .iter()
+        .filter(|line| line.split_whitespace().next() == Some("go"))
+        .count();
+    assert_eq!(count("bestmove"), searches + 1, "{:#?}", lines);
+    let best = last_bestmove(&lines);
+    assert!(legal_after_e4(best), "{} after 1. e4", best);
+}
+
+#[test]
+fn uci_answers_every_go_with_one_bestmove() {
+    // Searches started out of order, each followed by another command
+    let lines = uci_session(
+        b"go nodes 10\nposition fen garbage\ngo nodes 10\nstop\nstop\nponderhit\n\
+          position startpos\ngo ponder nodes 10\nponderhit\ngo nodes 10\nisready\n",
+    );
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|line| line.starts_with("bestmove"))
+            .count(),
+        4,
+        "{:#?}",
+        lines
+    );
+    assert!(lines.contains(&"bestmove 0000".to_string()));
+    assert!(lines.contains(&"readyok".to_string()));
+}
+
+#[test]
+fn xboard_survives_garbage_and_stays_in_sync() {
+    let garbage: &[&str] = &[
+        "",
+        "protover",
+        "ping",
+        "variant",
+        "variant 960",
+        "setboard",
+        "setboard 8/8/8 w",
+        "holding",
+        "holding [",
+        "holding [PPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPP] [X",
+        "usermove",
+        "usermove e2e9",
+        "usermove P@",
+        "st",
+        "st -1",
+        "st 1e300",
+        "st NaN",
+        "sd",
+        "sd -3",
+        "sd 99999999999999999999999",
+        "level",
+        "level 40",
+        "level 40 5:",
+        "level 40 :30 x",
+        "level 99999999999999999999 99999999999999999999 99999999999999999999",
+        "time",
+        "time 18446744073709551615",
+        "time -1",
+        "otim zz",
+        "ptell",
+        "ptell &&&",
+        "partner",
+        "\u{0}\u{7f}",
+    ];
+    let mut input = b"xboard\nprotover 2\nnew\nforce\n".to_vec();
+    for line in garbage {
+        input.extend_from_slice(line.as_bytes());
+        input.push(b'\n');
+    }
+    input.extend_from_slice(b"\xff\xfe\n");
+    input.extend_from_slice(
+        b"new\nforce\nst 0.05\nsd 2\ntime 10000\nusermove e2e4\ngo\nping 9\nusermo",
+    );
+    let lines = xboard_session(&input);
+
+    // The cut-off last line is just another unknown command
+    let end = &lines[lines.len() - 3..];
+    assert_eq!(end[1..], ["pong 9", "Error (unknown command): usermo"]);
+    let best = end[0].strip_prefix("move ").expect("a move");
+    assert!(legal_after_e4(best), "{} after 1. e4", best);
+}
+
+#[test]
+fn xboard_rejects_moves_out_of_turn_without_losing_the_position() {
+    let lines = xboard_session(
+        b"new\nforce\nusermove e2e4\nusermove e2e4\nusermove e7e5\nusermove e7e5\nusermove g1f3\nping 1\n",
+    );
+    assert_eq!(
+        lines,
+        ["Illegal move: e2e4", "Illegal move: e7e5", "pong 1"]
+    );
+}
+
+// Serves canned event and game streams and records what the bot sends
+#[derive(Clone, Default)]
+struct FakeApi {
+    events: String,
+    games: HashMap<String, String>,
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeApi {
+    fn record(&self, request: String) -> Result<(), LichessError> {
+        self.sent.lock().unwrap().push(request);
+        Ok(())
+    }
+}
+
+impl BotApi for FakeApi {
+    fn account_id(&self) -> Result<String, LichessError> {
+        Ok("ladybug".to_string())
+    }
+
+    fn stream_events(&self) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        Ok(Box::new(Cursor::new(self.events.clone())))
+    }
+
+    fn stream_game(&self, game: &str) -> Result<Box<dyn BufRead + Send>, LichessError> {
+        Ok(Box::new(Cursor::new(
+            self.games.get(game).cloned().unwrap_or_default(),
+        )))
+    }
+
+    fn accept(&self, challenge: &str) -> Result<(), LichessError> {
+        self.record(format!("accept {}", challenge))
+    }
+
+    fn decline(&self, challenge: &str, reason: &str) -> Result<(), LichessError> {
+        self.record(format!("decline {} {}", challenge, reason))
+    }
+
+    fn play(&self, game: &str, uci: &str) -> Result<(), LichessError> {
+        self.record(format!("move {} {}", game, uci))
+    }
+
+    fn resign(&self, game: &str) -> Result<(), LichessError> {
+        self.record(format!("resign {}", game))
+    }
+
+    fn abort(&self, game: &str) -> Result<(), LichessError> {
+        self.record(format!("abort {}", game))
+    }
+}
+
+fn state(moves: &str) -> String {
+    format!(
+        r#"{{"type":"gameState","moves":"{}","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}}"#,
+        moves
+    )
+}
+
+#[test]
+fn lichess_skips_broken_events_and_plays_on_the_next_good_state() {
+    let full = r#"{"type":"gameFull","id":"g","variant":{"key":"crazyhouse"},"initialFen":"startpos","white":{"id":"someone"},"black":{"id":"ladybug"},"state":{"type":"gameState","moves":"","wtime":180000,"btime":180000,"winc":0,"binc":0,"status":"started"}}"#;
+    let good = state("e2e4");
+    let deep = "[".repeat(100_000);
+    let stream = [
+        full.to_string(),
+        // A state cut off mid-line, then noise of every kind
+        good[..good.len() / 2].to_string(),
+        "not json at all".to_string(),
+        "{}".to_string(),
+        "[1, 2, 3]".to_string(),
+        r#"{"type":"gameState"}"#.to_string(),
+        r#"{"type":"gameState","moves":42,"status":"started"}"#.to_string(),
+        r#"{"type":"gameState","moves":"e2e5 zz","status":"started"}"#.to_string(),
+        r#"{"type":"gameState","moves":"e2e4","wtime":-5,"btime":"x","status":"started"}"#
+            .to_string(),
+        r#"{"type":"chatLine","text":"\ud800"}"#.to_string(),
+        r#"{"type":"gameState","moves":"e2e4","wtime":1e999,"btime":1e999,"status":"started"}"#
+            .to_string(),
+        deep,
+        // Then the state the bot should answer
+        good,
+        r#"{"type":"gameState","moves":"e2e4 e7e5","status":"resign"}"#.to_string(),
+    ]
+    .join("\n");
+    let api = FakeApi {
+        games: vec![("g".to_string(), stream)].into_iter().collect(),
+        ..FakeApi::default()
+    };
+    let mut bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.time.moves_to_go = 1000;
+    bot.play_game("g", "ladybug").unwrap();
+
+    let sent = api.sent.lock().unwrap();
+    assert!(!sent.is_empty(), "the bot never moved");
+    for request in sent.iter() {
+        let uci = request.strip_prefix("move g ").expect("only moves");
+        assert!(legal_after_e4(uci), "{} after 1. e4", uci);
+    }
+}
+
+#[test]
+fn json_rejects_garbage_without_panicking() {
+    let deep_array = "[".repeat(100_000);
+    let deep_object = r#"{"a":"#.repeat(100_000);
+    let inputs = [
+        "",
+        "{",
+        "}",
+        "[1,",
+        "[1,]",
+        r#"{"a"}"#,
+        r#"{"a":}"#,
+        r#"{"a":1,}"#,
+        r#""\u12"#,
+        r#""\ud800A""#,
+        r#""\ud800""#,
+        r#""\x""#,
+        "\"unterminated",
+        "nul",
+        "tru",
+        "-",
+        "1e",
+        "01.2.3",
+        "\u{feff}{}",
+        &deep_array,
+        &deep_object,
+    ];
+    for input in inputs.iter() {
+        assert!(JsonValue::parse(input).is_err(), "{:?} parsed", input);
+    }
+}
+
+#[test]
+fn lichess_skips_broken_events_on_the_event_stream() {
+    let challenge = r#"{"type":"challenge","challenge":{"id":"c1","challenger":{"id":"someone"},"variant":{"key":"crazyhouse"},"rated":true,"timeControl":{"type":"clock","limit":180,"increment":2}}}"#;
+    let events = [
+        &challenge[..challenge.len() - 7],
+        r#"{"type":"challenge"}"#,
+        r#"{"type":"challenge","challenge":{"id":"c0"}}"#,
+        r#"{"type":"gameStart","game":{}}"#,
+        r#"{"type":"gameStart"#,
+        "\u{0}garbage",
+        challenge,
+    ]
+    .join("\n");
+    let api = FakeApi {
+        events,
+        ..FakeApi::default()
+    };
+    let bot = LichessBot::new(api.clone(), Arc::new(EvalParams::default()));
+    bot.run().unwrap();
+    assert_eq!(*api.sent.lock().unwrap(), ["accept c1"]);
+}
+
+// Sends `request` and closes our side, so a truncated request ends there
+fn exchange(addr: &str, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn coordinator_answers_garbage_with_errors() {
+    let dir =
+        std::env::temp_dir().join(format!("ladybug-malformed-cluster-{}", std::process::id()));
+    let job = JobSpec {
+        id: 0,
+        net_version: "weights-test".to_string(),
+        games: 1,
+        seed: 0,
+    };
+    let mut coordinator = Coordinator::new(vec![job], dir.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || coordinator.serve(&listener).unwrap());
+
+    let garbage: &[(&[u8], &str)] = &[
+        (b"", "error unknown command"),
+        (b"\n", "error unknown command"),
+        (b"hello world\n", "error unknown command"),
+        (b"requ", "error unknown command"),
+        (b"upload\n", "error unknown command"),
+        (b"upload x 3\nabc", "error malformed upload"),
+        (b"upload 0 -1\n", "error malformed upload"),
+        (b"upload 0 10\nabc", "error truncated upload"),
+        // Uploads before the job was handed out
+        (b"upload 0 3\nabc", "error unknown job"),
+        (b"upload 7 3\nabc", "error unknown job"),
+    ];
+    for (request, expected) in garbage {
+        let response = exchange(&addr, request);
+        assert_eq!(response.trim(), *expected, "{:?}", request);
+    }
+    // Bytes that are not UTF-8 get no answer, but don't take the coordinator down
+    exchange(&addr, b"request \xff\xfe\n");
+
+    let response = exchange(&addr, b"request worker\n");
+    assert!(response.starts_with("job 0 weights-test"), "{}", response);
+    assert_eq!(exchange(&addr, b"upload 0 3\nabc").trim(), "ok");
+    server.join().unwrap();
+    assert_eq!(std::fs::read(dir.join("job-0.dat")).unwrap(), b"abc");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,76 @@
+use ladybug::board::parse_fen;
+use ladybug::board::{Bughouse, MoveProbe};
+use shakmaty::{Bitboard, Move, Position, Role, Square};
+
+const FENS: &[&str] = &[
+    "r3k2r/pPp2ppp/8/3pP3/8/8/PPP2PPP/R3K2R[NBp] w KQkq d6 0 1",
+    "k7/8/8/8/8/8/r7/r3K3[NP] w - - 0 1",
+    "4k3/8/8/8/8/8/8/4K3[QRBNP] b - - 0 1",
+];
+
+// The probes agree with full move generation on every square
+fn check_against_legal_moves(position: &Bughouse) {
+    let legal = position.legal_moves();
+    for from in Bitboard::ALL {
+        for to in Bitboard::ALL {
+            let moves: Vec<&Move> = legal
+                .iter()
+                .filter(|m| m.from() == Some(from) && m.to() == to)
+                .collect();
+            let probe = position.can_move(from, to);
+            match moves.as_slice() {
+                [] if !matches!(probe, MoveProbe::Legal(Move::Castle { .. })) => {
+                    assert_eq!(probe, MoveProbe::Illegal, "{} {}", from, to)
+                }
+                [] => {}
+                [m] => assert_eq!(probe, MoveProbe::Legal((*m).clone()), "{} {}", from, to),
+                _ => assert_eq!(probe, MoveProbe::Promotion, "{} {}", from, to),
+            }
+        }
+    }
+    for role in [
+        Role::Pawn,
+        Role::Knight,
+        Role::Bishop,
+        Role::Rook,
+        Role::Queen,
+    ] {
+        for to in Bitboard::ALL {
+            let drop = Move::Put { role, to };
+            assert_eq!(
+                position.can_drop(role, to),
+                legal.contains(&drop),
+                "{}",
+                drop
+            );
+        }
+    }
+}
+
+#[test]
+fn probes_match_move_generation() {
+    for fen in FENS {
+        check_against_legal_moves(&parse_fen(fen).unwrap());
+    }
+}
+
+#[test]
+fn dragging_the_king_two_squares_castles() {
+    let position = parse_fen(FENS[0]).unwrap();
+    let castle = Move::Castle {
+        king: Square::E1,
+        rook: Square::H1,
+    };
+    assert_eq!(
+        position.can_move(Square::E1, Square::G1),
+        MoveProbe::Legal(castle.clone())
+    );
+    assert_eq!(
+        position.can_move(Square::E1, Square::H1),
+        MoveProbe::Legal(castle)
+    );
+    assert_eq!(
+        position.can_move(Square::B7, Square::A8),
+        MoveProbe::Promotion
+    );
+}
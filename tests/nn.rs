@@ -0,0 +1,67 @@
+#![cfg(feature = "nn")]
+
+use std::process;
+use std::sync::Arc;
+
+use ladybug::board::Bughouse;
+use ladybug::engine::{Engine, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::nn::{encode_position, move_index, Network, NnError, INPUT_SIZE, POLICY_SIZE};
+use shakmaty::{CastlingMode, Position};
+
+#[test]
+fn encoding_is_from_the_side_to_move() {
+    let white = Bughouse::from_fen(
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Pp] w KQkq - 2 3",
+        CastlingMode::Standard,
+    )
+    .unwrap();
+    let input = encode_position(&white);
+    assert_eq!(input.len(), INPUT_SIZE);
+    // The mirror image with black to move looks the same but for the turn flag
+    let black = encode_position(&white.mirrored());
+    assert_eq!(input[..INPUT_SIZE - 1], black[..INPUT_SIZE - 1]);
+    assert_eq!((input[INPUT_SIZE - 1], black[INPUT_SIZE - 1]), (1.0, 0.0));
+
+    for m in white.legal_moves() {
+        assert!(move_index(&white, &m) < POLICY_SIZE);
+    }
+}
+
+#[test]
+fn networks_survive_a_round_trip_and_bad_files_are_rejected() {
+    let network = Network::random(8, 1);
+    let path = std::env::temp_dir().join(format!("ladybug-network-{}.nn", process::id()));
+    network.save(&path).unwrap();
+    let loaded = Network::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, network);
+
+    let bytes = network.to_bytes();
+    assert!(matches!(
+        Network::from_bytes(&bytes[..bytes.len() - 4]),
+        Err(NnError::Size { .. })
+    ));
+    assert!(matches!(
+        Network::from_bytes(b"not a network"),
+        Err(NnError::NotANetwork)
+    ));
+
+    let position = Bughouse::default();
+    let output = network.evaluate(&position, &position.legal_moves());
+    assert!((0.0..=1.0).contains(&output.value));
+    assert!((output.policy.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn search_runs_on_the_network_instead_of_rollouts() {
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_options(SearchOptions {
+        network: Some(Arc::new(Network::random(8, 2))),
+        ..SearchOptions::default()
+    });
+    let position = Bughouse::default();
+    let analysis = engine.analyse(&position, SearchLimits::nodes(100));
+    assert!(position.is_legal(&analysis.best.unwrap()));
+}
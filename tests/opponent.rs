@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::eval::EvalParams;
+use ladybug::opponent::OpponentModel;
+use ladybug::time::TimeManager;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Position};
+
+// Plays the UCI `moves` from `position`, showing each to `model`
+fn observe(model: &mut OpponentModel, position: &mut Bughouse, moves: &[&str]) {
+    for uci in moves {
+        let m = uci.parse::<Uci>().unwrap().to_move(position).unwrap();
+        model.observe(position, &m, Some(Duration::from_millis(1000)));
+        position.play_unchecked(&m);
+    }
+}
+
+#[test]
+fn unknown_opponents_leave_everything_as_it_was() {
+    let model = OpponentModel::new(Color::Black);
+    assert_eq!(model.aggressiveness(), 0.5);
+    assert_eq!(model.drop_tendency(), 0.5);
+    assert_eq!(model.average_move_time(), None);
+    let params = EvalParams::default();
+    assert_eq!(model.bias(&params), params);
+    let time = TimeManager::default();
+    let budget = time.allot(Duration::from_secs(60), Duration::from_secs(0), None);
+    assert_eq!(
+        time.allot(
+            Duration::from_secs(60),
+            Duration::from_secs(0),
+            Some(&model)
+        ),
+        budget
+    );
+}
+
+#[test]
+fn forcing_moves_and_drops_are_counted_for_the_opponent_only() {
+    let mut model = OpponentModel::new(Color::Black);
+    let mut position = Bughouse::default();
+    // Black captures twice, white's capture does not count
+    observe(
+        &mut model,
+        &mut position,
+        &["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a2"],
+    );
+    assert_eq!(model.aggressiveness(), 3.0 / 5.0);
+    // With a pawn in hand black moved the queen
+    assert_eq!(model.drop_tendency(), 1.0 / 3.0);
+    assert_eq!(model.color(), Color::Black);
+
+    let biased = model.bias(&EvalParams::default());
+    let params = EvalParams::default();
+    assert!(biased.policy.check > params.policy.check);
+    assert!(biased.pocket.knight < params.pocket.knight);
+}
+
+#[test]
+fn drops_with_pieces_in_hand_raise_the_drop_tendency() {
+    let mut model = OpponentModel::new(Color::White);
+    let mut position = parse_fen("4k3/8/8/8/8/8/8/4K3[NNn] w - - 0 1").unwrap();
+    observe(&mut model, &mut position, &["N@c3", "N@c6", "N@f3"]);
+    assert_eq!(model.drop_tendency(), 3.0 / 4.0);
+    let biased = model.bias(&EvalParams::default());
+    assert!(biased.pocket.knight > EvalParams::default().pocket.knight);
+}
+
+#[test]
+fn fast_opponents_set_the_pace_down_to_half_our_budget() {
+    let mut model = OpponentModel::new(Color::White);
+    let mut position = Bughouse::default();
+    let e4 = "e2e4".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    model.observe(&position, &e4, Some(Duration::from_millis(1000)));
+    position.play_unchecked(&e4);
+    let nf3 = "g8f6".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    // Black's moves tell nothing about white
+    model.observe(&position, &nf3, Some(Duration::from_secs(50)));
+    position.play_unchecked(&nf3);
+    let d4 = "d2d4".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    model.observe(&position, &d4, Some(Duration::from_millis(0)));
+    // The newest move weighs 0.3
+    let close = |time: Duration, millis: f32| (time.as_secs_f32() * 1000f32 - millis).abs() < 0.1;
+    assert!(close(model.average_move_time().unwrap(), 700f32));
+
+    let time = TimeManager {
+        moves_to_go: 10,
+        safety_margin: Duration::from_secs(0),
+        ..TimeManager::default()
+    };
+    let zero = Duration::from_secs(0);
+    assert!(close(
+        time.allot(Duration::from_secs(10), zero, Some(&model)),
+        700f32
+    ));
+    // Never below half of our own budget
+    assert_eq!(
+        time.allot(Duration::from_secs(100), zero, Some(&model)),
+        Duration::from_secs(5)
+    );
+}
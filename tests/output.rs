@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use ladybug::json::JsonValue;
+use ladybug::output::{json_array, json_string, Format, JsonObject, SCHEMA_VERSION};
+use ladybug::validate::validate_fens;
+
+#[test]
+fn formats_round_trip_through_their_names() {
+    for format in [Format::Human, Format::Json] {
+        assert_eq!(format.to_string().parse::<Format>(), Ok(format));
+    }
+    assert_eq!(Format::default(), Format::Human);
+    assert!("yaml".parse::<Format>().is_err());
+}
+
+#[test]
+fn json_documents_are_valid_and_carry_their_schema() {
+    let document = JsonObject::document("example")
+        .string("name", "tab\there \"quoted\"\n")
+        .number("ratio", 0.25f32)
+        .number("undefined", f32::NAN)
+        .number("endless", f64::INFINITY)
+        .boolean("done", true)
+        .raw("list", json_array(["1".to_string(), json_string("two")]))
+        .to_string();
+    let parsed = JsonValue::parse(&document).unwrap();
+    assert_eq!(
+        parsed.get("schema_version").and_then(JsonValue::as_f64),
+        Some(SCHEMA_VERSION as f64)
+    );
+    assert_eq!(
+        parsed.get("type").and_then(JsonValue::as_str),
+        Some("example")
+    );
+    assert_eq!(
+        parsed.get("name").and_then(JsonValue::as_str),
+        Some("tab\there \"quoted\"\n")
+    );
+    assert_eq!(parsed.get("ratio").and_then(JsonValue::as_f64), Some(0.25));
+    // JSON has no NaN or infinity
+    assert_eq!(parsed.get("undefined"), Some(&JsonValue::Null));
+    assert_eq!(parsed.get("endless"), Some(&JsonValue::Null));
+    assert_eq!(parsed.get("done").and_then(JsonValue::as_bool), Some(true));
+    assert_eq!(
+        parsed.get("list"),
+        Some(&JsonValue::Array(vec![
+            JsonValue::Number(1.0),
+            JsonValue::String("two".to_string())
+        ]))
+    );
+    assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+}
+
+#[test]
+fn reports_print_for_people_and_for_scripts() {
+    let fens = "# two good, one bad\n\
+                rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1\n\
+                4k3/8/8/8/8/8/8/4K3[Qq] b - - 0 1\n\
+                not a fen\n";
+    let report = validate_fens(Cursor::new(fens)).unwrap();
+    assert!(report.to_string().starts_with("3 fens, 2 valid\n"));
+
+    let json = JsonValue::parse(&report.to_json().to_string()).unwrap();
+    assert_eq!(
+        json.get("type").and_then(JsonValue::as_str),
+        Some("validate")
+    );
+    assert_eq!(json.get("total").and_then(JsonValue::as_f64), Some(3.0));
+    assert_eq!(json.get("valid").and_then(JsonValue::as_f64), Some(2.0));
+    let invalid = match json.get("invalid") {
+        Some(JsonValue::Array(invalid)) => invalid,
+        other => panic!("expected an array, got {:?}", other),
+    };
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(
+        invalid[0].get("line").and_then(JsonValue::as_f64),
+        Some(4.0)
+    );
+    assert_eq!(
+        invalid[0].get("fen").and_then(JsonValue::as_str),
+        Some("not a fen")
+    );
+}
@@ -0,0 +1,103 @@
+use ladybug::bpgn::{parse_board_records, BoardRecord};
+use ladybug::partner::{Inconsistency, Transfer};
+use ladybug::validate::validate_records;
+use shakmaty::{Color, Role};
+
+// Black gets a knight from the partner board and drops it, white takes it and passes it
+// on
+const RECORD: &str = r#"[Event "crazyhouse-style record"]
+
+1A. e4{179.8} {[]} 1a. e5 {[n]} 2A. Nf3 2a. N@d4 3A. Nxd4 {[]} *
+"#;
+
+fn single(text: &str) -> BoardRecord {
+    let mut records = parse_board_records(text).unwrap();
+    assert_eq!(records.len(), 1);
+    records.remove(0)
+}
+
+#[test]
+fn the_flow_between_the_boards_is_reconstructed() {
+    let record = single(RECORD);
+    assert_eq!(record.tags[0].1, "crazyhouse-style record");
+    assert_eq!(record.moves.len(), 5);
+    assert_eq!(record.holdings[2], None);
+
+    let flow = record.infer_flow().unwrap();
+    assert_eq!(
+        flow.received,
+        [Transfer {
+            ply: 2,
+            color: Color::Black,
+            role: Role::Knight
+        }]
+    );
+    assert_eq!(
+        flow.sent,
+        [Transfer {
+            ply: 5,
+            color: Color::White,
+            role: Role::Knight
+        }]
+    );
+    assert_eq!(
+        flow.received_by(Color::Black)
+            .by_color(Color::Black)
+            .by_role(Role::Knight),
+        1
+    );
+}
+
+#[test]
+fn pieces_that_leave_the_pocket_without_a_drop_are_flagged() {
+    let record = single("1A. e4 {[n]} 1a. e5 {[]} *");
+    assert_eq!(
+        record.infer_flow(),
+        Err(Inconsistency::VanishedPiece {
+            ply: 2,
+            color: Color::Black,
+            role: Role::Knight
+        })
+    );
+}
+
+#[test]
+fn more_than_two_sets_of_material_is_flagged() {
+    let record = single("1A. e4 {[QQ]} *");
+    assert_eq!(
+        record.infer_flow(),
+        Err(Inconsistency::TooMuchMaterial {
+            ply: 1,
+            color: Color::White,
+            role: Role::Queen
+        })
+    );
+    assert!(single("1A. e4 {[K]} *").infer_flow().is_err());
+}
+
+#[test]
+fn records_of_both_boards_or_unseen_drops_are_not_read() {
+    assert!(parse_board_records("1A. e4 1B. d4 *").is_err());
+    // Nothing came from the partner, so there is no knight to drop
+    assert!(parse_board_records("1A. e4 1a. N@d4 *").is_err());
+    assert!(parse_board_records("1A. e4 {[n *").is_err());
+}
+
+#[test]
+fn archives_flag_the_corrupt_games() {
+    let archive = format!(
+        "{}\n[Event \"vanished\"]\n\n1A. e4 {{[n]}} 1a. e5 {{[]}} *\n\n\
+         [Event \"unreadable\"]\n\n1A. e4 1a. N@d4 *\n",
+        RECORD
+    );
+    let report = validate_records(&archive);
+    assert_eq!(report.total, 3);
+    assert_eq!(report.consistent, 1);
+    let games: Vec<usize> = report.corrupt.iter().map(|(game, _)| *game).collect();
+    assert_eq!(games, [1, 2]);
+    assert_eq!(
+        report.corrupt[0].1,
+        "black n left the pocket without a drop at ply 2"
+    );
+    assert!(report.to_json().to_string().contains("\"consistent\":1"));
+}
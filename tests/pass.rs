@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use ladybug::board::{Bughouse, Rules};
+use ladybug::engine::{parse_move, parse_move_list, to_san, Engine, RootFilter};
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::session::Session;
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Position, Setup};
+
+// White may pass, black may not
+fn handicap() -> Bughouse {
+    Bughouse::default().with_rules(Rules {
+        pass: ByColor {
+            white: true,
+            black: false,
+        },
+        ..Rules::default()
+    })
+}
+
+#[test]
+fn passes_parse_and_print_in_san_and_uci() {
+    let position = handicap();
+    assert_eq!(parse_move(&position, "--"), Ok(None));
+    assert_eq!(parse_move(&position, "0000"), Ok(None));
+    assert_eq!(to_san(&position, None).to_string(), "--");
+    // A pass is a move of its own, not one that can be listed
+    assert!(parse_move_list(&position, "e4,--").is_err());
+
+    let mut session = Session::new(position);
+    for token in ["--", "e5", "0000", "Nc6"] {
+        session.play_notation(token).unwrap();
+    }
+    let san: Vec<String> = session.san_moves().iter().map(|m| m.to_string()).collect();
+    assert_eq!(san, ["--", "e5", "--", "Nc6"]);
+    let uci: Vec<String> = session
+        .moves()
+        .iter()
+        .map(|m| m.as_ref().map_or(Uci::Null, Uci::from_standard).to_string())
+        .collect();
+    assert_eq!(uci, ["0000", "e7e5", "0000", "b8c6"]);
+    assert_eq!(session.position().turn(), Color::White);
+}
+
+#[test]
+fn passes_are_refused_without_the_rule() {
+    let mut position = Bughouse::default();
+    assert!(!position.can_pass());
+    assert!(parse_move(&position, "--").is_err());
+    assert!(parse_move(&position, "0000").is_err());
+    assert!(!position.pass());
+    assert_eq!(position.fen(), Bughouse::default().fen());
+
+    // Only the side given the rule may pass
+    let mut session = Session::new(handicap());
+    session.play_notation("e4").unwrap();
+    assert!(session.play_notation("--").is_err());
+    assert!(session.play_uci("0000").is_err());
+    assert_eq!(session.moves().len(), 1);
+}
+
+#[test]
+fn the_search_chooses_a_pass_when_nothing_else_is_allowed() {
+    let position = handicap();
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_root_filter(RootFilter {
+        only: None,
+        exclude: position.legal_moves().into_iter().collect(),
+    });
+    let analysis = engine.analyse(&position, SearchLimits::nodes(50));
+    assert_eq!(analysis.best, None);
+
+    // Listing the moves to search leaves the pass out
+    let e4 = parse_move_list(&position, "e4").unwrap();
+    engine.set_root_filter(RootFilter {
+        only: Some(e4.clone()),
+        exclude: Vec::new(),
+    });
+    let analysis = engine.analyse(&position, SearchLimits::nodes(50));
+    assert_eq!(analysis.best.as_ref(), e4.first());
+}
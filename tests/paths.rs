@@ -0,0 +1,58 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use ladybug::paths::{AppPaths, Platform};
+
+fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+    move |name| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| OsString::from(value))
+    }
+}
+
+#[test]
+fn platform_conventions() {
+    let unix = AppPaths::from_env(
+        Platform::Unix,
+        env(&[
+            ("HOME", "/home/a"),
+            ("XDG_CACHE_HOME", "/tmp/c"),
+            ("XDG_DATA_HOME", ""),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(unix.config, PathBuf::from("/home/a/.config/ladybug"));
+    assert_eq!(unix.cache, PathBuf::from("/tmp/c/ladybug"));
+    // Empty counts as unset
+    assert_eq!(unix.data, PathBuf::from("/home/a/.local/share/ladybug"));
+    assert_eq!(
+        unix.database(),
+        PathBuf::from("/home/a/.local/share/ladybug/positions.sqlite")
+    );
+
+    let mac = AppPaths::from_env(Platform::MacOs, env(&[("HOME", "/Users/a")])).unwrap();
+    assert_eq!(mac.cache, PathBuf::from("/Users/a/Library/Caches/ladybug"));
+
+    let windows = AppPaths::from_env(
+        Platform::Windows,
+        env(&[("APPDATA", "C:/Roaming"), ("LOCALAPPDATA", "C:/Local")]),
+    )
+    .unwrap();
+    assert_eq!(windows.data, PathBuf::from("C:/Roaming/ladybug"));
+    assert_eq!(windows.cache, PathBuf::from("C:/Local/ladybug/cache"));
+
+    assert_eq!(AppPaths::from_env(Platform::Unix, env(&[])), None);
+}
+
+#[test]
+fn ladybug_home_overrides_everything() {
+    let paths = AppPaths::from_env(
+        Platform::Unix,
+        env(&[("HOME", "/home/a"), ("LADYBUG_HOME", "/opt/lb")]),
+    )
+    .unwrap();
+    assert_eq!(paths.config, PathBuf::from("/opt/lb/config"));
+    assert_eq!(paths.artifacts(), PathBuf::from("/opt/lb/data/artifacts"));
+    assert_eq!(paths.entries().len(), 12);
+}
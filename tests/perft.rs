@@ -0,0 +1,100 @@
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use shakmaty::uci::Uci;
+use shakmaty::{Bitboard, Color, Move, Position, Role, Setup, Square};
+
+// Node counts from the crazyhouse perft suite that comes with shakmaty
+const SUITE: &[(&str, &[u64])] = &[
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        &[20, 400, 8902, 197281],
+    ),
+    ("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75353]),
+    (
+        "2k5/8/8/8/8/8/8/4K3[Qn] w - - 0 1",
+        &[67, 3083, 88634, 932554],
+    ),
+    (
+        "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+        &[42, 1347, 58057, 2083382],
+    ),
+];
+
+#[test]
+fn node_counts_match_the_suite() {
+    for (fen, counts) in SUITE {
+        let position = parse_fen(fen).unwrap();
+        for (depth, &count) in (1..).zip(counts.iter()) {
+            assert_eq!(position.perft(depth), count, "{} depth {}", fen, depth);
+        }
+    }
+}
+
+fn drops(position: &Bughouse) -> Vec<Move> {
+    position
+        .legal_moves()
+        .into_iter()
+        .filter(|m| matches!(m, Move::Put { .. }))
+        .collect()
+}
+
+#[test]
+fn drops_in_check_only_block() {
+    // The rook on a1 checks along the first rank, the one on a2 covers the escapes
+    let position = parse_fen("k7/8/8/8/8/8/r7/r3K3[N] w - - 0 1").unwrap();
+    let moves: Vec<String> = position
+        .legal_moves()
+        .iter()
+        .map(|m| Uci::from_standard(m).to_string())
+        .collect();
+    assert_eq!(moves, ["N@b1", "N@c1", "N@d1"]);
+
+    // A knight's check can't be blocked
+    let position = parse_fen("k7/8/8/8/8/3n4/8/4K3[QRBNP] w - - 0 1").unwrap();
+    assert!(drops(&position).is_empty());
+}
+
+#[test]
+fn double_check_allows_no_drops() {
+    // The bishop came from e7 and uncovered the rook
+    let position = parse_fen("4r2k/8/8/8/1b6/8/8/4K3[QRBNP] w - - 0 1").unwrap();
+    assert_eq!(position.checkers().count(), 2);
+    assert!(drops(&position).is_empty());
+    assert!(position
+        .legal_moves()
+        .iter()
+        .all(|m| m.role() == Role::King));
+}
+
+#[test]
+fn capturing_a_promoted_piece_pockets_a_pawn() {
+    let mut position = parse_fen("4k1rQ~/8/8/8/8/8/8/4K3[] b - - 0 1").unwrap();
+    let m = "g8h8".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    position.play_unchecked(&m);
+    let pocket = position.pockets().unwrap().by_color(Color::Black);
+    assert_eq!((pocket.pawns, pocket.queens), (1, 0));
+    let m = "e1d1".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    position.play_unchecked(&m);
+    assert!(drops(&position).iter().all(|m| m.role() == Role::Pawn));
+    assert!(!drops(&position).is_empty());
+}
+
+#[test]
+fn pawns_are_never_dropped_on_a_back_rank() {
+    for fen in [
+        "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+        "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] b - - 0 1",
+    ] {
+        let position = parse_fen(fen).unwrap();
+        let pawn_drops: Vec<Square> = drops(&position)
+            .iter()
+            .filter(|m| m.role() == Role::Pawn)
+            .map(|m| m.to())
+            .collect();
+        // Every empty square off the back ranks
+        assert_eq!(pawn_drops.len(), 48);
+        assert!(pawn_drops
+            .iter()
+            .all(|&to| !Bitboard::BACKRANKS.contains(to)));
+    }
+}
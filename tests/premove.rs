@@ -0,0 +1,77 @@
+use ladybug::board::parse_fen;
+use ladybug::eval::EvalParams;
+use ladybug::premove::{likely_premoves, premove_trap};
+use ladybug::rollout::{MoveHistory, RolloutPolicy};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position, Role};
+
+// White's queen on d4 can be taken by the pawn on e5
+const HANGING_QUEEN: &str = "4k3/8/8/4p3/3Q4/8/8/4K3[] w - - 0 1";
+
+fn parse(position: &str, uci: &str) -> Move {
+    let position = parse_fen(position).unwrap();
+    uci.parse::<Uci>().unwrap().to_move(&position).unwrap()
+}
+
+#[test]
+fn captures_of_valuable_pieces_are_likely_premoves() {
+    let params = EvalParams::default();
+    let position = parse_fen(HANGING_QUEEN).unwrap();
+    let premoves = likely_premoves(&position, None, &params);
+    assert_eq!(
+        premoves,
+        [parse("4k3/8/8/4p3/3Q4/8/8/4K3[] b - - 0 1", "e5d4")]
+    );
+}
+
+#[test]
+fn leaving_a_piece_to_a_premove_loses_it() {
+    let params = EvalParams::default();
+    let queen = params.board.by_role(Role::Queen);
+    let position = parse_fen(HANGING_QUEEN).unwrap();
+    let trap = premove_trap(&position, &parse(HANGING_QUEEN, "e1d1"), None, &params).unwrap();
+    assert_eq!(trap.premove.to_string(), "e5xd4");
+    assert_eq!(trap.loss, queen);
+
+    // Moving the queen away or taking the pawn makes the premove illegal
+    for uci in ["d4c3", "d4e5"] {
+        let m = parse(HANGING_QUEEN, uci);
+        assert_eq!(premove_trap(&position, &m, None, &params), None, "{}", uci);
+    }
+
+    // A defended queen is only lost for the pawn that takes it
+    let defended = "4k3/8/8/4p3/3Q4/2P5/8/4K3[] w - - 0 1";
+    let position = parse_fen(defended).unwrap();
+    let trap = premove_trap(&position, &parse(defended, "e1d1"), None, &params).unwrap();
+    assert_eq!(trap.loss, queen - params.board.by_role(Role::Pawn));
+}
+
+#[test]
+fn playouts_avoid_premove_traps_when_weighted() {
+    let position = parse_fen(HANGING_QUEEN).unwrap();
+    let moves = position.legal_moves();
+    let king_moves = |params: &EvalParams| {
+        let mut policy = RolloutPolicy::default();
+        let mut rng = StdRng::seed_from_u64(462);
+        (0..200)
+            .filter_map(|_| {
+                policy.choose(
+                    &position,
+                    &moves,
+                    0,
+                    &MoveHistory::default(),
+                    params,
+                    &mut rng,
+                )
+            })
+            .filter(|m| m.role() == Role::King)
+            .count()
+    };
+
+    let mut params = EvalParams::default();
+    assert!(king_moves(&params) > 0);
+    params.policy.premove_trap = 1000.0;
+    assert_eq!(king_moves(&params), 0);
+}
@@ -0,0 +1,35 @@
+use ladybug::board::parse_fen;
+use ladybug::eval::EvalParams;
+use ladybug::prior::PriorSource;
+use shakmaty::uci::Uci;
+use shakmaty::Position;
+
+// The prior of each of `moves` in `fen`, from the default weights
+fn priors(fen: &str, moves: &[&str]) -> Vec<f32> {
+    let position = parse_fen(fen).unwrap();
+    let legal = position.legal_moves();
+    let all = EvalParams::default().prior.priors(&position, &legal);
+    moves
+        .iter()
+        .map(|uci| {
+            let m = uci.parse::<Uci>().unwrap().to_move(&position).unwrap();
+            all[legal.iter().position(|legal| *legal == m).unwrap()]
+        })
+        .collect()
+}
+
+#[test]
+fn captures_are_ordered_by_mvv_lva() {
+    let fen = "4k3/8/8/1p1q4/4P3/8/8/3QK3 w - - 0 1";
+    let capture = priors(fen, &["e4d5", "d1d5", "d1b3"]);
+    // Pawn takes queen before queen takes queen, both before a quiet move
+    assert!(capture[0] > capture[1]);
+    assert!(capture[1] > capture[2]);
+}
+
+#[test]
+fn drops_against_the_king_come_first() {
+    let fen = "4k3/8/8/8/8/8/8/4K3[N] w - - 0 1";
+    let drops = priors(fen, &["N@c7", "N@b1"]);
+    assert!(drops[0] > drops[1]);
+}
@@ -0,0 +1,83 @@
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use ladybug::eval::EvalHandle;
+use ladybug::protocol::{command_lines, detect_protocol, Protocol};
+use ladybug::uci::UciEngine;
+use ladybug::xboard::XboardEngine;
+
+// Output the test can still read after handing it to the engine
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn the_first_command_names_the_protocol() {
+    assert_eq!(Protocol::detect("uci"), Some(Protocol::Uci));
+    assert_eq!(Protocol::detect("  uci\r\n"), Some(Protocol::Uci));
+    assert_eq!(Protocol::detect("xboard"), Some(Protocol::Xboard));
+    assert_eq!(Protocol::detect("protover 2"), Some(Protocol::Xboard));
+    assert_eq!(Protocol::detect("ucinewgame"), None);
+    assert_eq!(Protocol::detect(""), None);
+}
+
+#[test]
+fn detection_skips_noise_and_leaves_the_rest_of_the_input() {
+    let mut input = Cursor::new("\n  \nhello\nuci\nisready\n");
+    assert_eq!(
+        detect_protocol(&mut input).unwrap(),
+        Some((Protocol::Uci, "uci".to_string()))
+    );
+    let mut rest = String::new();
+    input.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "isready\n");
+
+    let mut silent = Cursor::new("hello\n\n");
+    assert_eq!(detect_protocol(&mut silent).unwrap(), None);
+}
+
+#[test]
+fn the_detected_front_end_answers_the_first_command() {
+    let mut input = Cursor::new("\nuci\nisready\nquit\n");
+    let (protocol, first) = detect_protocol(&mut input).unwrap().unwrap();
+    assert_eq!(protocol, Protocol::Uci);
+    let output = Shared::default();
+    UciEngine::new(output.clone(), EvalHandle::default())
+        .run(Cursor::new(format!("{}\n", first)).chain(input))
+        .unwrap();
+    let output = output.text();
+    assert!(output.contains("uciok"), "{}", output);
+    assert!(output.contains("readyok"), "{}", output);
+
+    let mut input = Cursor::new("xboard\nprotover 2\nquit\n");
+    let (protocol, first) = detect_protocol(&mut input).unwrap().unwrap();
+    assert_eq!(protocol, Protocol::Xboard);
+    let output = Shared::default();
+    XboardEngine::new(output.clone(), EvalHandle::default())
+        .run(Cursor::new(format!("{}\n", first)).chain(input))
+        .unwrap();
+    let output = output.text();
+    assert!(output.contains("feature"), "{}", output);
+}
+
+#[test]
+fn command_lines_survive_garbled_bytes() {
+    let input = Cursor::new(b"isready\r\nposition \xff\ngo\n".to_vec());
+    let lines: Vec<String> = command_lines(input).map(Result::unwrap).collect();
+    assert_eq!(lines, ["isready", "position \u{fffd}", "go"]);
+}
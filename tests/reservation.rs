@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::engine::{Engine, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::prior::PriorSource;
+use ladybug::reservation::{Reservation, ReservationMessage, Reservations};
+use shakmaty::{Color, Move, Position, Role, Setup};
+
+// The same prior for every move
+struct Uniform;
+
+impl PriorSource for Uniform {
+    fn priors(&self, _: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        vec![1f32 / moves.len() as f32; moves.len()]
+    }
+}
+
+#[test]
+fn messages_round_trip() {
+    let reserve = ReservationMessage::Reserve(Reservation {
+        id: 3,
+        color: Color::Black,
+        role: Role::Knight,
+        discount: 0.5,
+    });
+    assert_eq!(reserve.to_string(), "reserve 3 n 0.5");
+    assert_eq!("reserve 3 n 0.5".parse(), Ok(reserve));
+    assert_eq!("cancel 3".parse(), Ok(ReservationMessage::Cancel(3)));
+    for invalid in [
+        "reserve 3 k 0.5",
+        "reserve 3 n 2",
+        "reserve x n 0.5",
+        "cancel",
+        "go",
+    ] {
+        assert!(
+            invalid.parse::<ReservationMessage>().is_err(),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn reservations_last_until_the_partner_moves() {
+    let mut reservations = Reservations::new();
+    let knight = reservations.reserve(Color::White, Role::Knight, 0.8);
+    reservations.reserve(Color::White, Role::Bishop, 0.5);
+    reservations.reserve(Color::Black, Role::Queen, 2.0);
+    assert_eq!(reservations.active(Color::White).count(), 2);
+    // Discounts are clamped
+    assert_eq!(
+        reservations.active(Color::Black).next().unwrap().discount,
+        1.0
+    );
+
+    // The knight arrives, the bishop capture did not happen
+    let dropped = reservations.partner_moved(Color::White, Some(Role::Knight));
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].role, Role::Bishop);
+    assert_eq!(reservations.active(Color::White).count(), 0);
+    assert_eq!(reservations.active(Color::Black).count(), 1);
+
+    // Messages from the partner's side
+    let mut partner = Reservations::new();
+    partner.receive(knight);
+    assert_eq!(partner.active(Color::White).count(), 1);
+    partner.receive(ReservationMessage::Cancel(0));
+    assert_eq!(partner.active(Color::White).count(), 0);
+    assert!(!partner.cancel(0));
+}
+
+#[test]
+fn reserved_pieces_are_searched_as_if_in_hand_but_discounted() {
+    let position = Bughouse::default();
+    let mut reservations = Reservations::new();
+    reservations.reserve(Color::White, Role::Knight, 0.25);
+    let tentative = reservations.tentative(&position);
+    let pocket = tentative.pockets().unwrap().by_color(Color::White);
+    assert_eq!(pocket.by_role(Role::Knight), 1);
+
+    let moves = tentative.legal_moves();
+    let priors = reservations
+        .priors(Uniform, Color::White)
+        .priors(&tentative, &moves);
+    let prior = |m: &Move| priors[moves.iter().position(|other| other == m).unwrap()];
+    let drop = moves
+        .iter()
+        .find(|m| matches!(m, Move::Put { .. }))
+        .unwrap();
+    let push = moves.iter().find(|m| m.is_zeroing()).unwrap();
+    assert!((prior(drop) * 4f32 - prior(push)).abs() < 1e-6);
+    assert!((priors.iter().sum::<f32>() - 1f32).abs() < 1e-5);
+}
+
+#[test]
+fn searches_see_the_reserved_drops() {
+    // A knight on f2 would smother the king, but black has none yet
+    let position = parse_fen("6k1/8/8/8/8/8/6PP/6RK[] b - - 0 1").unwrap();
+    let mut reservations = Reservations::new();
+    reservations.reserve(Color::Black, Role::Knight, 1.0);
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_options(SearchOptions {
+        reservations: reservations.active(Color::Black).copied().collect(),
+        ..SearchOptions::default()
+    });
+    let analysis = engine.analyse(&reservations.tentative(&position), SearchLimits::nodes(300));
+    assert_eq!(analysis.mate, Some(1));
+    assert!(matches!(
+        analysis.best,
+        Some(Move::Put {
+            role: Role::Knight,
+            ..
+        })
+    ));
+}
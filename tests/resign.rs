@@ -0,0 +1,73 @@
+use ladybug::resign::ResignPolicy;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use shakmaty::{Color, Outcome};
+
+// Plays `games` audited games in which white would have resigned, ending in `outcome`
+fn audit(policy: &mut ResignPolicy, games: usize, outcome: Outcome) {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..games {
+        let mut game = policy.start_game(&mut rng);
+        assert!(game.is_audited());
+        assert!(!game.should_resign(Color::White, 0f32));
+        policy.finish_game(&game, outcome);
+    }
+}
+
+#[test]
+fn games_resign_below_the_threshold() {
+    let policy = ResignPolicy::default()
+        .with_audit_rate(0f32)
+        .with_threshold(0.1);
+    assert!(policy.should_resign(0.05));
+    assert!(!policy.should_resign(0.1));
+    assert!(!policy.should_resign(0.5));
+
+    let mut game = policy.start_game(&mut StdRng::seed_from_u64(1));
+    assert!(!game.is_audited());
+    assert!(!game.should_resign(Color::White, 0.2));
+    assert!(game.should_resign(Color::White, 0.05));
+}
+
+#[test]
+fn audits_that_lose_raise_the_threshold() {
+    let mut policy = ResignPolicy::default().with_audit_rate(1f32);
+    let lost = Outcome::Decisive {
+        winner: Color::Black,
+    };
+    // Nothing moves before enough audits are in
+    audit(&mut policy, 19, lost);
+    assert_eq!(policy.threshold, 0.05);
+    assert_eq!(policy.false_positive_rate(), Some(0f32));
+    audit(&mut policy, 1, lost);
+    assert!(policy.threshold > 0.05);
+    assert_eq!(policy.false_positive_rate(), None);
+}
+
+#[test]
+fn audits_that_survive_lower_the_threshold() {
+    let mut policy = ResignPolicy::default().with_audit_rate(1f32);
+    audit(&mut policy, 10, Outcome::Draw);
+    assert_eq!(policy.false_positive_rate(), Some(1f32));
+    audit(
+        &mut policy,
+        10,
+        Outcome::Decisive {
+            winner: Color::White,
+        },
+    );
+    assert!(policy.threshold < 0.05);
+}
+
+#[test]
+fn games_that_were_not_audited_leave_the_threshold_alone() {
+    let mut policy = ResignPolicy::default().with_audit_rate(0f32);
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..40 {
+        let mut game = policy.start_game(&mut rng);
+        assert!(game.should_resign(Color::White, 0f32));
+        policy.finish_game(&game, Outcome::Draw);
+    }
+    assert_eq!(policy.threshold, 0.05);
+    assert_eq!(policy.false_positive_rate(), None);
+}
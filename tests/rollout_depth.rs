@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::engine::{Analysis, Engine, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::rollout::RolloutStats;
+
+// Both sides can give plenty of checks with the pieces in hand
+const CHECKS: &str = "6k1/5ppp/8/8/8/8/5PPP/6K1[QRqr] w - - 0 1";
+
+fn analyse(fen: &str, rollout_depth: usize, check_extension: usize) -> (Analysis, RolloutStats) {
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_options(SearchOptions {
+        rollout_depth,
+        check_extension,
+        ..SearchOptions::default()
+    });
+    let analysis = engine.analyse(&parse_fen(fen).unwrap(), SearchLimits::nodes(200));
+    (analysis, *engine.rollout_stats().unwrap())
+}
+
+// Playout moves that consulted the move tables, as a measure of how long rollouts ran
+fn probes(stats: &RolloutStats) -> u64 {
+    stats.countermove_probes + stats.killer_probes
+}
+
+#[test]
+fn capped_rollouts_are_adjudicated_by_the_evaluation() {
+    // Without any rollout moves, every leaf is judged by the static evaluation
+    let (bare_kings, _) = analyse("k7/8/8/8/8/8/8/K7[] w - - 0 1", 0, 0);
+    assert_eq!(bare_kings.win_probability, 0.5);
+    let (queen_up, _) = analyse("4k3/8/8/8/8/8/8/3QK3[] w - - 0 1", 0, 0);
+    assert!(queen_up.win_probability > 0.9, "{:?}", queen_up);
+
+    let (_, short) = analyse(CHECKS, 4, 0);
+    let (_, long) = analyse(CHECKS, 200, 0);
+    assert!(probes(&short) > 0);
+    assert!(probes(&short) < probes(&long));
+}
+
+#[test]
+fn checking_sequences_run_past_the_cap() {
+    let (_, capped) = analyse(CHECKS, 0, 0);
+    let (_, extended) = analyse(CHECKS, 0, 20);
+    assert_eq!(probes(&capped), 0);
+    assert!(probes(&extended) > 0);
+}
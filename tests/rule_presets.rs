@@ -0,0 +1,64 @@
+use ladybug::board::{Bughouse, RulePreset, PRESETS};
+use shakmaty::san::San;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Move, Position, Role, Square};
+
+fn with_preset(fen: &str, preset: RulePreset) -> Bughouse {
+    Bughouse::from_fen(fen, CastlingMode::Standard)
+        .unwrap()
+        .with_preset(preset)
+}
+
+fn play(position: &mut Bughouse, uci: &str) {
+    let m = uci.parse::<Uci>().unwrap().to_move(position).unwrap();
+    position.play_unchecked(&m);
+}
+
+#[test]
+fn presets_round_trip_through_their_names() {
+    for preset in PRESETS {
+        assert_eq!(preset.to_string().parse::<RulePreset>(), Ok(preset));
+    }
+    assert!("icc-bughouse".parse::<RulePreset>().is_err());
+}
+
+#[test]
+fn seventh_rank_pawn_drops_follow_the_preset() {
+    let fen = "4k3/8/8/8/8/8/8/4K3[P] w - - 0 1";
+    let drop = Move::Put {
+        role: Role::Pawn,
+        to: Square::A7,
+    };
+    let lichess = with_preset(fen, RulePreset::LichessCrazyhouse);
+    assert!(lichess.is_legal(&drop));
+    assert_eq!(
+        San::from_move(&lichess, &drop).to_move(&lichess),
+        Ok(drop.clone())
+    );
+
+    let chess_com = with_preset(fen, RulePreset::ChessComBughouse);
+    assert!(!chess_com.is_legal(&drop));
+    assert!("P@a7".parse::<San>().unwrap().to_move(&chess_com).is_err());
+    assert!("P@a7".parse::<Uci>().unwrap().to_move(&chess_com).is_err());
+}
+
+#[test]
+fn dropped_pawns_step_once_under_chess_com_rules() {
+    let fen = "4k3/8/8/8/8/8/8/4K3[P] w - - 0 1";
+    let double_step = "d2d4".parse::<Uci>().unwrap();
+    for (preset, allowed) in [
+        (RulePreset::LichessCrazyhouse, true),
+        (RulePreset::ChessComBughouse, false),
+    ] {
+        let mut position = with_preset(fen, preset);
+        play(&mut position, "P@d2");
+        play(&mut position, "e8d8");
+        assert_eq!(
+            double_step.to_move(&position).is_ok(),
+            allowed,
+            "{}",
+            preset
+        );
+        assert!("d2d3".parse::<Uci>().unwrap().to_move(&position).is_ok());
+    }
+}
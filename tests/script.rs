@@ -0,0 +1,60 @@
+use std::io::Cursor;
+use std::process;
+
+use ladybug::script::{ScriptError, ScriptRunner};
+
+const SCRIPT: &str = "
+# a short game
+play e4 e5 Nf3
+assert turn black
+assert illegal Ke7e6
+nodes 60
+analyze
+echo done
+";
+
+fn run(script: &str) -> (Result<(), ScriptError>, String) {
+    let mut output = Vec::new();
+    let result = ScriptRunner::new(&mut output).run(Cursor::new(script));
+    (result, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn scripts_print_the_same_every_run() {
+    let (result, output) = run(SCRIPT);
+    result.unwrap();
+    assert!(output.starts_with("best "));
+    assert!(output.ends_with("done\n"));
+    assert_eq!(run(SCRIPT).1, output);
+}
+
+#[test]
+fn failed_assertions_stop_the_script_at_their_line() {
+    let (result, output) = run("echo one\nassert outcome 1-0\necho two\n");
+    let error = result.unwrap_err();
+    assert_eq!(error.line, 2);
+    assert_eq!(error.message, "expected outcome 1-0, got *");
+    assert_eq!(output, "one\n");
+
+    let (result, _) = run("play e4 e5 f4 Qh4 g4 Qh4#\n");
+    assert_eq!(result.unwrap_err().line, 1);
+    run("play f3 e5 g4 Qh4#\nassert outcome 0-1\n").0.unwrap();
+}
+
+#[test]
+fn exported_scripts_replay_the_session() {
+    let path = std::env::temp_dir().join(format!("ladybug-script-{}.txt", process::id()));
+    let mut output = Vec::new();
+    let mut runner = ScriptRunner::new(&mut output);
+    runner
+        .run(Cursor::new(format!(
+            "play d4 d5 Bf4\nexport {}\n",
+            path.display()
+        )))
+        .unwrap();
+    let fen = runner.session().position().fen();
+
+    let replay = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    run(&format!("{}assert fen {}\n", replay, fen)).0.unwrap();
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::cancel::CancelToken;
+use ladybug::engine::{Engine, ReplayError, RootNoise, RootStrategy, SearchLog, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::{SearchControl, SearchLimits};
+use ladybug::reservation::Reservation;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Position, Role};
+
+fn engine() -> Engine {
+    Engine::new(Arc::new(EvalParams::default()))
+}
+
+#[test]
+fn finds_mate_in_one() {
+    // 1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6, and the queen mates on f7
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+    let best = engine()
+        .search(&position, SearchLimits::nodes(300))
+        .unwrap();
+    assert_eq!(Uci::from_standard(&best).to_string(), "h5f7");
+}
+
+#[test]
+fn proven_mates_are_reported_by_distance() {
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+    let analysis = engine().analyse(&position, SearchLimits::nodes(300));
+    assert_eq!(analysis.mate, Some(1));
+
+    // A ladder mate: Rb7 leaves the king only g8, where Ra8 mates
+    let position = parse_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").unwrap();
+    let analysis = engine().analyse(
+        &position,
+        SearchLimits {
+            nodes: Some(20_000),
+            stop_on_mate: true,
+            ..SearchLimits::default()
+        },
+    );
+    assert_eq!(analysis.mate, Some(2));
+    assert_eq!(analysis.win_probability, 1f32);
+    assert!(analysis.nodes < 20_000);
+
+    // Black to move after Rb7: Kf8 or Kh8, and either way Ra8 mates
+    let position = parse_fen("6k1/1R6/8/8/8/8/R7/6K1 b - - 1 1").unwrap();
+    let analysis = engine().analyse(&position, SearchLimits::nodes(2_000));
+    assert_eq!(analysis.mate, Some(-1));
+    assert_eq!(analysis.win_probability, 0f32);
+}
+
+#[test]
+fn no_move_when_mated() {
+    let position =
+        parse_fen("r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4").unwrap();
+    assert_eq!(engine().search(&position, SearchLimits::nodes(10)), None);
+}
+
+#[test]
+fn threads_share_the_budget() {
+    let position =
+        parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4").unwrap();
+    let mut engine = engine();
+    engine.set_threads(4);
+    let analysis = engine.analyse(&position, SearchLimits::nodes(400));
+    assert_eq!(
+        Uci::from_standard(&analysis.best.unwrap()).to_string(),
+        "h5f7"
+    );
+    // Every thread checks the shared count before its next iteration
+    assert!(analysis.nodes < 400 + 4);
+    assert_eq!(analysis.win_probability, 1f32);
+}
+
+#[test]
+fn the_tree_is_kept_for_the_next_move() {
+    let mut kept = engine();
+    let mut position = Bughouse::default();
+    let best = kept.search(&position, SearchLimits::nodes(300)).unwrap();
+    let reply = kept.expected_reply(&best).expect("a reply to ponder on");
+    position.play_unchecked(&best);
+    position.play_unchecked(&reply);
+    let next = kept.search(&position, SearchLimits::nodes(50)).unwrap();
+    assert!(position.is_legal(&next));
+
+    let mut fresh = engine();
+    fresh.set_options(SearchOptions {
+        reuse_tree: false,
+        ..SearchOptions::default()
+    });
+    let best = fresh.search(&position, SearchLimits::nodes(50)).unwrap();
+    assert_eq!(fresh.expected_reply(&best), None);
+}
+
+#[test]
+fn sequential_halving_searches_below_the_root_moves() {
+    let mut engine = engine();
+    engine.set_options(SearchOptions {
+        root_strategy: RootStrategy::SequentialHalving,
+        ..SearchOptions::default()
+    });
+    let position = Bughouse::default();
+    let control = SearchControl::new(SearchLimits::nodes(400), CancelToken::new());
+    let best = engine.analyse_with(&position, &control).best.unwrap();
+    assert!(control.depth() > 1);
+    assert!(engine.expected_reply(&best).is_some());
+}
+
+#[test]
+fn new_weights_keep_the_tree() {
+    let mut engine = engine();
+    let position = Bughouse::default();
+    let best = engine.search(&position, SearchLimits::nodes(300)).unwrap();
+    let reply = engine.expected_reply(&best);
+    assert!(reply.is_some());
+
+    let mut params = EvalParams::default();
+    params.pocket.knight += 1.0;
+    engine.set_params(Arc::new(params.clone()));
+    assert_eq!(**engine.params(), params);
+    assert_eq!(engine.expected_reply(&best), reply);
+}
+
+#[test]
+fn rollouts_consult_the_move_tables() {
+    let mut engine = engine();
+    assert_eq!(engine.rollout_stats(), None);
+    engine.search(&Bughouse::default(), SearchLimits::nodes(300));
+    let stats = *engine.rollout_stats().unwrap();
+    assert!(stats.countermove_probes > 0 && stats.killer_probes > 0);
+    assert!(stats.countermove_hits <= stats.countermove_probes);
+    assert!(stats.killer_hit_rate() > 0f32 && stats.killer_hit_rate() <= 1f32);
+}
+
+#[test]
+fn search_logs_replay_reused_trees_with_their_options() {
+    let mut engine = engine();
+    engine.set_seed(3);
+    engine.set_options(SearchOptions {
+        exploration: 2.5,
+        rollout_depth: 40,
+        root_noise: Some(RootNoise::default()),
+        ..SearchOptions::default()
+    });
+    let mut position = Bughouse::default();
+    let best = engine.search(&position, SearchLimits::nodes(200)).unwrap();
+    let reply = engine.expected_reply(&best).unwrap();
+    position.play_unchecked(&best);
+    position.play_unchecked(&reply);
+    // The second search goes on from the kept tree with other options
+    let reserved = Reservation {
+        id: 4,
+        color: Color::White,
+        role: Role::Knight,
+        discount: 0.5,
+    };
+    engine.set_options(SearchOptions {
+        root_strategy: RootStrategy::SequentialHalving,
+        visit_decay: 0.5,
+        reservations: vec![reserved],
+        ..SearchOptions::default()
+    });
+    engine.search(&position, SearchLimits::nodes(100));
+
+    let log = engine.search_log().unwrap().clone();
+    assert_eq!(log.searches.len(), 2);
+    assert_eq!(log.searches[1].moves, [Some(best), Some(reply)]);
+    assert_eq!(log.searches[1].options.visit_decay, 0.5);
+    assert_eq!(log.searches[1].options.reservations, [reserved]);
+    assert_eq!(log.searches[0].expansions.len(), 200);
+    log.replay().unwrap();
+
+    let parsed = SearchLog::parse(&log.to_text()).unwrap();
+    assert_eq!(parsed, log);
+    parsed.replay().unwrap();
+
+    let mut tampered = log.clone();
+    tampered.searches[1].expansions[5] += 1;
+    assert!(matches!(
+        tampered.replay(),
+        Err(ReplayError::Diverged { iteration: 205, .. })
+    ));
+    let mut tampered = log;
+    tampered.searches[0].options.exploration = 1.0;
+    assert!(tampered.replay().is_err());
+}
+
+#[test]
+fn search_logs_replay_searches_after_new_weights() {
+    let mut engine = engine();
+    engine.set_seed(5);
+    let mut position = Bughouse::default();
+    let best = engine.search(&position, SearchLimits::nodes(200)).unwrap();
+    let reply = engine.expected_reply(&best).unwrap();
+    position.play_unchecked(&best);
+    position.play_unchecked(&reply);
+    // Reloaded weights carry on with the kept tree
+    let mut params = EvalParams::default();
+    params.pocket.knight += 2.0;
+    engine.set_params(Arc::new(params.clone()));
+    engine.search(&position, SearchLimits::nodes(100));
+    engine.search(&position, SearchLimits::nodes(50));
+
+    let log = engine.search_log().unwrap().clone();
+    assert_eq!(log.params, EvalParams::default());
+    assert_eq!(log.searches[0].params, None);
+    assert_eq!(log.searches[1].params, Some(params));
+    assert_eq!(log.searches[2].params, None);
+    log.replay().unwrap();
+    let parsed = SearchLog::parse(&log.to_text()).unwrap();
+    assert_eq!(parsed, log);
+    parsed.replay().unwrap();
+
+    // Replaying with the old weights throughout goes astray
+    let mut stale = log;
+    stale.searches[1].params = None;
+    assert!(stale.replay().is_err());
+}
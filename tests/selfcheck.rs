@@ -0,0 +1,10 @@
+use ladybug::selfcheck;
+
+#[test]
+fn every_self_check_passes() {
+    let results = selfcheck::run();
+    assert_eq!(results.len(), selfcheck::CHECKS.len());
+    for result in results {
+        assert!(result.passed(), "{}: {:?}", result.name, result.failure);
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::resign::ResignPolicy;
+use ladybug::selfplay::{self, play_game, SelfPlayConfig};
+use ladybug::training::{ExportOptions, TrainingExporter};
+use shakmaty::{Color, Outcome};
+
+fn config() -> SelfPlayConfig {
+    SelfPlayConfig {
+        games: 2,
+        nodes: 30,
+        max_plies: 12,
+        temperature_plies: 6,
+        seed: 7,
+        ..SelfPlayConfig::default()
+    }
+}
+
+#[test]
+fn games_replay_from_their_seed() {
+    let config = config();
+    let params = Arc::new(EvalParams::default());
+    let fens = |engine: &mut Engine| {
+        let game = play_game(engine, &config, 1);
+        assert!(game.records.len() <= config.max_plies);
+        if game.records.len() == config.max_plies {
+            assert_eq!(game.outcome, Outcome::Draw);
+        }
+        game.records
+            .iter()
+            .map(|record| record.position.fen())
+            .collect::<Vec<_>>()
+    };
+    let mut engine = Engine::new(params.clone());
+    engine.set_options(config.search_options());
+    let first = fens(&mut engine);
+    let mut other = Engine::new(params);
+    other.set_options(config.search_options());
+    assert_eq!(fens(&mut other), first);
+}
+
+#[test]
+fn every_searched_position_is_exported() {
+    let config = config();
+    let params = Arc::new(EvalParams::default());
+    let mut exporter =
+        TrainingExporter::new(ExportOptions::default(), &params, &config.search_options());
+    let mut positions = 0;
+    selfplay::run(&config, params, &mut exporter, |_, game| {
+        assert!(game.records.iter().all(|record| !record.visits.is_empty()));
+        positions += game.records.len();
+        true
+    });
+    let text = exporter.to_text();
+    let samples = text.lines().filter(|line| !line.starts_with('#')).count();
+    assert_eq!(samples, positions);
+}
+
+#[test]
+fn stopping_keeps_the_games_played_so_far() {
+    let config = SelfPlayConfig {
+        games: 3,
+        ..config()
+    };
+    let params = Arc::new(EvalParams::default());
+    let mut exporter =
+        TrainingExporter::new(ExportOptions::default(), &params, &config.search_options());
+    let mut played = 0;
+    selfplay::run(&config, params, &mut exporter, |_, _| {
+        played += 1;
+        false
+    });
+    assert_eq!(played, 1);
+    assert_eq!(exporter.dedup_stats().games, 1);
+}
+
+#[test]
+fn games_end_when_the_side_to_move_resigns() {
+    let always = ResignPolicy::default()
+        .with_audit_rate(0f32)
+        .with_threshold(1f32);
+    let resigning = SelfPlayConfig {
+        resign: Some(always.clone()),
+        ..config()
+    };
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_options(resigning.search_options());
+    let game = play_game(&mut engine, &resigning, 0);
+    assert_eq!(game.records.len(), 1);
+    assert_eq!(
+        game.outcome,
+        Outcome::Decisive {
+            winner: Color::Black
+        }
+    );
+
+    // Audited games play on
+    let audited = SelfPlayConfig {
+        resign: Some(always.with_audit_rate(1f32)),
+        ..config()
+    };
+    let game = play_game(&mut engine, &audited, 0);
+    assert!(game.resign.is_some_and(|resign| resign.is_audited()));
+    assert!(game.records.len() > 1);
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+use ladybug::board::Bughouse;
+use ladybug::cancel::CancelToken;
+use ladybug::eval::EvalParams;
+use ladybug::limits::{SearchControl, SearchLimits};
+use ladybug::session::{SessionError, SessionStore};
+use shakmaty::uci::Uci;
+
+fn dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ladybug-sessions-{}-{}", name, process::id()))
+}
+
+fn nodes(count: u64) -> SearchControl {
+    SearchControl::new(SearchLimits::nodes(count), CancelToken::new())
+}
+
+#[test]
+fn sessions_keep_their_trees_across_reopening() {
+    let dir = dir("reopen");
+    let params = Arc::new(EvalParams::default());
+    let (fen, iterations) = {
+        let mut store = SessionStore::open(&dir, Arc::clone(&params)).unwrap();
+        store.create("game-1", Bughouse::default()).unwrap();
+        let best = store.query("game-1", &nodes(300)).unwrap().best.unwrap();
+        assert!(store.analysis("game-1").unwrap().iterations() >= 300);
+
+        // Playing the searched move keeps what was searched below it
+        let uci = Uci::from_standard(&best).to_string();
+        let session = store.play("game-1", &[&uci]).unwrap();
+        assert_eq!(session.moves().len(), 1);
+        let fen = session.position().fen();
+        let analysis = store.analysis("game-1").unwrap();
+        assert_eq!(analysis.position().fen(), fen);
+        assert!(analysis.iterations() > 0);
+        (fen, analysis.iterations())
+    };
+
+    let mut store = SessionStore::open(&dir, params).unwrap();
+    assert_eq!(store.ids().collect::<Vec<_>>(), ["game-1"]);
+    assert_eq!(store.get("game-1").unwrap().position().fen(), fen);
+    assert_eq!(store.analysis("game-1").unwrap().iterations(), iterations);
+    assert!(store.query("game-1", &nodes(100)).unwrap().best.is_some());
+    assert!(store.analysis("game-1").unwrap().iterations() > iterations);
+
+    store.destroy("game-1").unwrap();
+    assert!(fs::read_dir(&dir).unwrap().next().is_none());
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn moves_are_played_all_or_none() {
+    let mut store = SessionStore::in_memory(Arc::new(EvalParams::default()));
+    store.create("game", Bughouse::default()).unwrap();
+    assert!(matches!(
+        store.play("game", &["e4", "e5", "Ke3"]),
+        Err(SessionError::IllegalMove(_))
+    ));
+    assert!(store.get("game").unwrap().moves().is_empty());
+    assert_eq!(
+        store.analysis("game").unwrap().position().fen(),
+        Bughouse::default().fen()
+    );
+
+    // SAN and UCI mix, and the analysis follows the game
+    let session = store.play("game", &["e2e4", "e5", "Nf3"]).unwrap();
+    assert_eq!(session.moves().len(), 3);
+    let fen = session.position().fen();
+    assert_eq!(store.analysis("game").unwrap().position().fen(), fen);
+}
+
+#[test]
+fn ids_are_checked() {
+    let mut store = SessionStore::in_memory(Arc::new(EvalParams::default()));
+    for id in ["", "../escape", "a b"] {
+        assert!(matches!(
+            store.create(id, Bughouse::default()),
+            Err(SessionError::InvalidId(_))
+        ));
+    }
+    store.create("game", Bughouse::default()).unwrap();
+    assert!(matches!(
+        store.create("game", Bughouse::default()),
+        Err(SessionError::AlreadyExists(_))
+    ));
+    for result in [
+        store.query("other", &nodes(10)).map(drop),
+        store.play("other", &["e4"]).map(drop),
+        store.destroy("other"),
+    ] {
+        assert!(matches!(result, Err(SessionError::UnknownSession(_))));
+    }
+}
+
+#[test]
+fn trees_of_another_position_are_replaced() {
+    let dir = dir("stale");
+    let params = Arc::new(EvalParams::default());
+    {
+        let mut store = SessionStore::open(&dir, Arc::clone(&params)).unwrap();
+        store.create("searched", Bughouse::default()).unwrap();
+        store.query("searched", &nodes(100)).unwrap();
+        store.create("played", Bughouse::default()).unwrap();
+        store.play("played", &["e4"]).unwrap();
+    }
+    // As if the session was saved but the tree of its last move wasn't
+    fs::copy(dir.join("searched.tree"), dir.join("played.tree")).unwrap();
+
+    let store = SessionStore::open(&dir, params).unwrap();
+    let analysis = store.analysis("played").unwrap();
+    assert_eq!(
+        analysis.position().fen(),
+        store.get("played").unwrap().position().fen()
+    );
+    assert_eq!(analysis.iterations(), 0);
+    assert!(store.analysis("searched").unwrap().iterations() >= 100);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,76 @@
+use ladybug::board::{parse_fen, Bughouse};
+use ladybug::engine::parse_move_list;
+use ladybug::prior::MoveClass;
+use ladybug::signature::{MaterialSignature, SignatureIndex};
+use shakmaty::{Color, Move, Outcome, Position};
+
+// The moves of `san`, played one after the other from the start
+fn game(san: &str) -> Vec<Move> {
+    let mut position = Bughouse::default();
+    san.split_whitespace()
+        .map(|token| {
+            let m = parse_move_list(&position, token).unwrap().remove(0);
+            position.play_unchecked(&m);
+            m
+        })
+        .collect()
+}
+
+#[test]
+fn signatures_ignore_where_the_pieces_stand() {
+    let start = MaterialSignature::of(&Bughouse::default());
+    let developed =
+        parse_fen("rnbqkb1r/pppppppp/5n2/8/8/5N2/PPPPPPPP/RNBQKB1R[] w KQkq - 2 2").unwrap();
+    assert_eq!(MaterialSignature::of(&developed), start);
+
+    let pocketed =
+        parse_fen("rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR[P] b KQkq - 0 2").unwrap();
+    let signature = MaterialSignature::of(&pocketed);
+    assert_ne!(signature, start);
+    assert!(signature.to_string().ends_with("[P]"), "{}", signature);
+}
+
+#[test]
+fn games_count_once_per_signature() {
+    let mut index = SignatureIndex::new();
+    let start = Bughouse::default();
+    // The fool's mate never leaves the starting material
+    index.add_game(
+        &start,
+        &game("f3 e5 g4 Qh4#"),
+        Outcome::Decisive {
+            winner: Color::Black,
+        },
+    );
+    assert_eq!(index.len(), 1);
+    index.add_game(
+        &start,
+        &game("e4 d5 exd5"),
+        Outcome::Decisive {
+            winner: Color::White,
+        },
+    );
+    assert_eq!(index.len(), 2);
+
+    let stats = index.lookup(&start).unwrap();
+    assert_eq!(stats.games, 2);
+    assert_eq!((stats.white_wins, stats.black_wins), (1, 1));
+    assert_eq!(stats.score(Color::Black), Some(0.5));
+
+    // Winners played e5, Qh4#, e4 and exd5 with the starting material
+    let plans = stats.typical_plans();
+    assert_eq!(plans.len(), 3);
+    assert_eq!(plans[0], (MoveClass::Quiet, 0.5));
+    assert!(plans.contains(&(MoveClass::Check, 0.25)));
+    assert!(plans.contains(&(MoveClass::Capture, 0.25)));
+
+    let mut after_capture = start.clone();
+    for m in game("e4 d5 exd5") {
+        after_capture.play_unchecked(&m);
+    }
+    let stats = index.lookup(&after_capture).unwrap();
+    assert_eq!(stats.games, 1);
+    assert_eq!(stats.score(Color::White), Some(1.0));
+    // Nobody moved from there
+    assert!(stats.typical_plans().is_empty());
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::board::{Action, BoardId, BughouseGame};
+use ladybug::danger::danger_score;
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::seats::{EngineAgent, Seat, TeamAgent};
+use ladybug::sitting::{mate_after_arrival, sit_for_mate, sit_for_partner, PARTNER_DANGER};
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, MaterialSide, Move, Role, Setup, Square};
+
+// White to move on a back rank mate, short of a rook or queen to drop
+const BACK_RANK: &str = "6k1/5ppp/8/8/8/8/8/4K3[] w - - 0 1";
+
+#[test]
+fn only_the_missing_piece_mates() {
+    let position = parse_fen(BACK_RANK).unwrap();
+    assert!(position.mating_moves().is_empty());
+    match mate_after_arrival(&position, Role::Rook) {
+        Some(Move::Put {
+            role: Role::Rook,
+            to,
+        }) => assert_eq!(to.rank(), Square::A8.rank()),
+        other => panic!("expected a rook drop, got {:?}", other),
+    }
+    assert_eq!(mate_after_arrival(&position, Role::Knight), None);
+}
+
+#[test]
+fn sit_while_the_partner_wins_the_piece() {
+    let clocks = ByColor {
+        white: Duration::from_secs(60),
+        black: Duration::from_secs(60),
+    };
+    // Black on B, partner of white on A, is about to win a rook
+    let mut game = BughouseGame::from_boards(
+        parse_fen(BACK_RANK).unwrap(),
+        parse_fen("4k3/8/8/8/R2q4/8/8/4K3[] b - - 0 1").unwrap(),
+        clocks.clone(),
+        clocks,
+    );
+    let seat = Seat {
+        board: BoardId::A,
+        color: Color::White,
+    };
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    let limits = SearchLimits::nodes(400);
+    assert!(sit_for_mate(&game, seat, &mut engine, limits.clone()).is_some());
+
+    game.act(BoardId::A, &Action::Wait, Duration::from_secs(2))
+        .unwrap();
+    assert_eq!(game.board(BoardId::A).turn(), Color::White);
+    assert_eq!(game.clocks(BoardId::A).white, Duration::from_secs(58));
+
+    let capture = "d4a4"
+        .parse::<Uci>()
+        .unwrap()
+        .to_move(game.board(BoardId::B))
+        .unwrap();
+    game.play(BoardId::B, &capture).unwrap();
+    assert!(!game.board(BoardId::A).mating_moves().is_empty());
+    // Nothing more to wait for
+    assert_eq!(sit_for_mate(&game, seat, &mut engine, limits), None);
+}
+
+// Black's king on board B with the g-file open, safe only while white has nothing to
+// drop
+const OPEN_KING: &str = "6k1/5p1p/8/8/8/8/8/K7[] w - - 0 1";
+// White to move on board A with the queen attacked by a pawn
+const HANGING_QUEEN: &str = "4k3/8/8/4p3/3Q4/8/8/4K3[] w - - 0 1";
+
+fn defending(board_a: &str, white: u64, black: u64) -> BughouseGame {
+    let clocks = ByColor {
+        white: Duration::from_secs(white),
+        black: Duration::from_secs(black),
+    };
+    BughouseGame::from_boards(
+        parse_fen(board_a).unwrap(),
+        parse_fen(OPEN_KING).unwrap(),
+        clocks,
+        ByColor {
+            white: Duration::from_secs(60),
+            black: Duration::from_secs(60),
+        },
+    )
+}
+
+#[test]
+fn danger_counts_what_the_attacker_may_receive() {
+    let position = parse_fen(OPEN_KING).unwrap();
+    let nothing = MaterialSide::new();
+    let mut queen = MaterialSide::new();
+    *queen.by_role_mut(Role::Queen) += 1;
+    assert!(danger_score(&position, Color::Black, &nothing) < PARTNER_DANGER);
+    assert!(danger_score(&position, Color::Black, &queen) >= PARTNER_DANGER);
+}
+
+#[test]
+fn sit_while_the_partner_king_is_in_danger() {
+    let seat = Seat {
+        board: BoardId::A,
+        color: Color::White,
+    };
+    let game = defending(HANGING_QUEEN, 60, 30);
+    assert!(sit_for_partner(&game, seat, PARTNER_DANGER));
+
+    // The opponent has the time to wait us out
+    assert!(!sit_for_partner(
+        &defending(HANGING_QUEEN, 30, 60),
+        seat,
+        PARTNER_DANGER
+    ));
+    // Nothing of ours is about to be passed on
+    assert!(!sit_for_partner(
+        &defending("4k3/8/8/4p3/8/3Q4/8/4K3[] w - - 0 1", 60, 30),
+        seat,
+        PARTNER_DANGER
+    ));
+
+    let engine = Engine::new(Arc::new(EvalParams::default()));
+    let mut agent = EngineAgent::new("ladybug", engine, SearchLimits::nodes(50));
+    assert_eq!(
+        agent.choose_action(&game, seat).unwrap(),
+        Some(Action::Wait)
+    );
+    agent.defensive_sits = false;
+    assert!(matches!(
+        agent.choose_action(&game, seat).unwrap(),
+        Some(Action::Move(_))
+    ));
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use ladybug::board::{parse_fen, Bughouse};
+use ladybug::engine::{parse_move_list, Engine, SearchLog, SearchOptions};
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::prior::PriorSource;
+use ladybug::sparring::{SparringPriors, Theme};
+use shakmaty::Move;
+
+// Every move equally likely, so only the theme tells them apart
+struct Uniform;
+
+impl PriorSource for Uniform {
+    fn priors(&self, _: &Bughouse, moves: &[Move]) -> Vec<f32> {
+        vec![1f32 / moves.len() as f32; moves.len()]
+    }
+}
+
+// Black's king on g8 with a knight in white's pocket
+const POSITION: &str = "6k1/5ppp/8/8/8/8/5PPP/R5K1[Nn] w - - 0 1";
+
+#[test]
+fn themes_round_trip_through_their_names() {
+    for theme in [Theme::KingAttack, Theme::PieceHoarding] {
+        assert_eq!(theme.to_string().parse::<Theme>(), Ok(theme));
+    }
+    assert!("endgames".parse::<Theme>().is_err());
+}
+
+#[test]
+fn themes_score_the_moves_that_lead_to_them() {
+    let position = parse_fen(POSITION).unwrap();
+    let moves = parse_move_list(&position, "N@e7,N@b1,Ra8,h3").unwrap();
+    let scores: Vec<f32> = moves
+        .iter()
+        .map(|m| Theme::KingAttack.score(&position, m))
+        .collect();
+    // The knight next to the king and the rook mate both beat a quiet pawn move
+    assert!(scores[0] > scores[1], "{:?}", scores);
+    assert!(scores[2] > scores[3], "{:?}", scores);
+    assert_eq!(scores[3], Theme::KingAttack.score(&position, &moves[1]));
+
+    // Dropping empties the pocket, anything else keeps both knights in hand
+    assert_eq!(Theme::PieceHoarding.score(&position, &moves[0]), 1f32);
+    assert_eq!(Theme::PieceHoarding.score(&position, &moves[3]), 2f32);
+}
+
+#[test]
+fn sparring_priors_favour_the_theme() {
+    let position = parse_fen(POSITION).unwrap();
+    let moves = parse_move_list(&position, "N@f6,h3").unwrap();
+    let priors = SparringPriors {
+        inner: Uniform,
+        theme: Theme::KingAttack,
+        strength: 1.0,
+    }
+    .priors(&position, &moves);
+    assert!((priors.iter().sum::<f32>() - 1f32).abs() < 1e-6);
+    assert!(priors[0] > priors[1], "{:?}", priors);
+
+    // Without any theme in sight the inner priors stay as they were
+    let quiet = parse_move_list(&position, "h3,g3").unwrap();
+    let priors = SparringPriors {
+        inner: Uniform,
+        theme: Theme::KingAttack,
+        strength: 1.0,
+    }
+    .priors(&position, &quiet);
+    assert_eq!(priors, [0.5, 0.5]);
+}
+
+#[test]
+fn sparring_searches_are_logged_with_their_theme() {
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    engine.set_options(SearchOptions {
+        sparring: Some(Theme::PieceHoarding),
+        ..SearchOptions::default()
+    });
+    let position = parse_fen(POSITION).unwrap();
+    assert!(engine.search(&position, SearchLimits::nodes(100)).is_some());
+
+    let log = engine.search_log().unwrap().clone();
+    assert_eq!(log.searches[0].options.sparring, Some(Theme::PieceHoarding));
+    assert!(log.to_text().contains("sparring piece-hoarding"));
+    let parsed = SearchLog::parse(&log.to_text()).unwrap();
+    assert_eq!(parsed, log);
+    parsed.replay().unwrap();
+}
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use ladybug::{parse_fen, parse_move_list, Bughouse, Engine, EvalParams, SearchLimits};
+use shakmaty::Position;
+
+// Only uses what the crate root re-exports as its stable core
+#[test]
+fn stable_core_analyses_a_position() {
+    let mut position = Bughouse::default();
+    for san in ["e4", "e5", "Nf3"].iter() {
+        let moves = parse_move_list(&position, san).unwrap();
+        position.play_unchecked(&moves[0]);
+    }
+    let reparsed = parse_fen(&position.fen()).unwrap();
+    assert_eq!(reparsed.fen(), position.fen());
+    assert!(!reparsed.legal_moves().is_empty());
+
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    let analysis = engine.analyse(&reparsed, SearchLimits::nodes(50));
+    assert!(reparsed.is_legal(&analysis.best.unwrap()));
+}
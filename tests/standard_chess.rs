@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::variant::{Variant, VariantPosition};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Move, Outcome, Position, Setup};
+
+fn chess(fen: &str) -> VariantPosition {
+    VariantPosition::from_fen(Variant::Chess, fen).unwrap()
+}
+
+#[test]
+fn chess_games_never_drop_or_fill_the_pockets() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..20 {
+        let mut position = VariantPosition::start(Variant::Chess).to_bughouse();
+        for _ in 0..200 {
+            let moves = position.legal_moves();
+            assert!(!moves.iter().any(|m| matches!(m, Move::Put { .. })));
+            let m = match moves.choose(&mut rng) {
+                Some(m) => m.clone(),
+                None => break,
+            };
+            position.play_unchecked(&m);
+            assert!(position.pockets().unwrap().is_empty());
+            if position.outcome().is_some() {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn the_search_returns_no_drop_for_a_chess_position() {
+    // 1. e4 d5 2. exd5 Qxd5 3. Nc3 Qxd2+, where crazyhouse rules would have put a pawn
+    // in each pocket
+    let mut position = VariantPosition::start(Variant::Chess);
+    for uci in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5d2"] {
+        position.play_uci(uci).unwrap();
+    }
+    let position = position.to_bughouse();
+    assert!(position.pockets().unwrap().is_empty());
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    for _ in 0..3 {
+        let best = engine.search(&position, SearchLimits::nodes(300)).unwrap();
+        assert!(!matches!(best, Move::Put { .. }), "{:?}", best);
+        assert!(position.is_legal(&best));
+    }
+}
+
+#[test]
+fn chess_draw_rules_apply() {
+    // A king and bishop can't mate a lone king, while in crazyhouse the pocket could
+    let bishop = "8/8/4k3/8/8/2B5/8/4K3 w - - 0 1";
+    assert_eq!(chess(bishop).outcome(), Some(Outcome::Draw));
+    assert_eq!(chess(bishop).to_bughouse().outcome(), Some(Outcome::Draw));
+    let zh = VariantPosition::from_fen(Variant::Crazyhouse, bishop).unwrap();
+    assert_eq!(zh.to_bughouse().outcome(), None);
+
+    // Fifty moves without a capture or pawn move
+    let quiet = chess("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").to_bughouse();
+    assert_eq!(quiet.outcome(), Some(Outcome::Draw));
+    assert!(quiet.legal_moves().is_empty());
+    let before = chess("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").to_bughouse();
+    assert_eq!(before.outcome(), None);
+
+    // Unless the last of them mates
+    let mut mate = chess("k7/8/1K6/8/8/8/8/7R w - - 99 80").to_bughouse();
+    let m = "h1h8".parse::<Uci>().unwrap().to_move(&mate).unwrap();
+    mate.play_unchecked(&m);
+    assert_eq!(
+        mate.outcome(),
+        Some(Outcome::Decisive {
+            winner: Color::White
+        })
+    );
+}
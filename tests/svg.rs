@@ -0,0 +1,176 @@
+use std::fs;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::{parse_fen, Bughouse};
+use ladybug::bpgn::parse;
+use ladybug::eval::EvalParams;
+use ladybug::prior::EvalPriors;
+use ladybug::svg::{drop_heat, frame_svg, match_frames, render_svg, write_frames, SvgOptions};
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Move, Role, Square};
+
+// Black on board A takes a pawn, which black's partner drops on board B
+const GAME: &str = r#"[WhiteA "alice"]
+[BlackA "bob"]
+[WhiteB "carol"]
+[BlackB "dave"]
+[TimeControl "180+0"]
+
+1A. e4{179.8} 1B. d4{179.5} 1a. d5{179.1} 1b. Nf6{178.0}
+2A. Nc3{178.2} 2a. dxe4{177.9} 2B. P@e5{176.4} 0-1
+"#;
+
+#[test]
+fn matches_render_a_frame_per_ply() {
+    let game = &parse(GAME).unwrap()[0];
+    assert_eq!(game.initial_time(), Some(Duration::from_secs(180)));
+    let frames = match_frames(game, game.initial_time());
+    assert_eq!(frames.len(), game.moves().len() + 1);
+    assert!(frames.iter().all(|boards| boards.len() == 2));
+    // Board B is seen from black's side, next to its partner on board A
+    assert!(!frames[0][0].flipped && frames[0][1].flipped);
+
+    let start = ByColor {
+        white: Duration::from_secs(180),
+        black: Duration::from_secs(180),
+    };
+    assert_eq!(frames[0][0].clocks, Some(start.clone()));
+    assert!(frame_svg(&frames[0]).contains(">3:00</text>"));
+    // Only the mover's clock changes
+    assert_eq!(
+        frames[1][0].clocks,
+        Some(ByColor {
+            white: Duration::from_millis(179_800),
+            black: Duration::from_secs(180),
+        })
+    );
+    assert_eq!(frames[1][1].clocks, Some(start));
+
+    // The pawn taken on board A is in white's pocket on board B until it is dropped
+    assert!(frames[5][1].pocket(Color::White).is_empty());
+    assert_eq!(frames[6][1].pocket(Color::White), [(Role::Pawn, 1)]);
+    let svg = frame_svg(&frames[6]);
+    assert!(svg.contains("\u{2659}</text>"), "{}", svg);
+    assert!(svg.contains(">1</text>"), "{}", svg);
+    assert!(svg.contains(">2:57</text>"), "{}", svg);
+    assert!(frames[7][1].pocket(Color::White).is_empty());
+}
+
+#[test]
+fn matches_without_a_start_time_have_no_clocks() {
+    let game = &parse(&GAME.replace("[TimeControl \"180+0\"]\n", "")).unwrap()[0];
+    assert_eq!(game.initial_time(), None);
+    let frames = match_frames(game, None);
+    assert!(frames.iter().flatten().all(|view| view.clocks.is_none()));
+    assert!(!frame_svg(&frames[3]).contains(":0"));
+}
+
+#[test]
+fn frames_are_written_in_order() {
+    let dir = std::env::temp_dir().join(format!("ladybug-frames-{}", process::id()));
+    let game = &parse(GAME).unwrap()[0];
+    let paths = write_frames(&match_frames(game, game.initial_time()), &dir).unwrap();
+    assert_eq!(paths.len(), 8);
+    assert_eq!(paths[7], dir.join("frame-0007.svg"));
+    let last = fs::read_to_string(&paths[7]).unwrap();
+    assert!(last.starts_with("<svg "));
+    assert!(last.contains(">2:56</text>"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// White has two knights and a queen in hand, black two pawns
+const POCKETS: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[QNNpp] w KQkq - 2 3";
+
+fn uci(position: &Bughouse, uci: &str) -> Move {
+    uci.parse::<Uci>().unwrap().to_move(position).unwrap()
+}
+
+#[test]
+fn positions_show_their_pockets() {
+    let svg = render_svg(&parse_fen(POCKETS).unwrap(), &SvgOptions::default());
+    assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+    // Queen and knights for white, pawns for black, with their counts
+    for glyph in ["\u{2655}", "\u{2658}", "\u{265f}"] {
+        assert!(svg.contains(&format!("{}</text>", glyph)), "{}", glyph);
+    }
+    assert_eq!(svg.matches(r#"font-size="12">2</text>"#).count(), 2);
+    assert_eq!(svg.matches(r#"font-size="12">1</text>"#).count(), 1);
+    // Nothing to overlay
+    assert!(!svg.contains("<line") && !svg.contains("#e03020"));
+}
+
+#[test]
+fn best_moves_are_arrows_and_drops_rings() {
+    let position = parse_fen(POCKETS).unwrap();
+    let svg = render_svg(
+        &position,
+        &SvgOptions {
+            best_move: Some(uci(&position, "f1c4")),
+            ..SvgOptions::default()
+        },
+    );
+    // From the center of f1 to the center of c4
+    assert!(
+        svg.contains(r#"<line x1="220" y1="332" x2="100" y2="212""#),
+        "{}",
+        svg
+    );
+    assert!(svg.contains(r#"marker-end="url(#arrowhead)""#));
+
+    let flipped = render_svg(
+        &position,
+        &SvgOptions {
+            flipped: true,
+            best_move: Some(uci(&position, "f1c4")),
+            ..SvgOptions::default()
+        },
+    );
+    assert!(flipped.contains(r#"<line x1="100" y1="52" x2="220" y2="172""#));
+
+    let drop = render_svg(
+        &position,
+        &SvgOptions {
+            best_move: Some(uci(&position, "N@g5")),
+            ..SvgOptions::default()
+        },
+    );
+    assert!(!drop.contains("<line"));
+    assert!(drop.contains(r##"<circle cx="260" cy="172" r="17" fill="none" stroke="#15781b""##));
+}
+
+#[test]
+fn drop_heat_shades_the_likely_squares() {
+    let position = parse_fen(POCKETS).unwrap();
+    let heat = drop_heat(
+        &position,
+        &EvalPriors {
+            params: Arc::new(EvalParams::default()),
+        },
+    );
+    // Knights and the queen go on any of the 32 empty squares
+    assert_eq!(heat.len(), 32);
+    assert!(heat.iter().all(|&(_, heat)| heat > 0f32 && heat <= 1f32));
+    assert!(heat.iter().any(|&(_, heat)| heat == 1f32));
+    assert!(drop_heat(
+        &Bughouse::default(),
+        &EvalPriors {
+            params: Arc::new(EvalParams::default()),
+        }
+    )
+    .is_empty());
+
+    let svg = render_svg(
+        &position,
+        &SvgOptions {
+            drop_heat: vec![(Square::E6, 1.0), (Square::D6, 0.5)],
+            ..SvgOptions::default()
+        },
+    );
+    assert_eq!(svg.matches("#e03020").count(), 2);
+    assert!(svg.contains(
+        r##"<rect x="160" y="112" width="40" height="40" fill="#e03020" fill-opacity="0.60"/>"##
+    ));
+    assert!(svg.contains(r#"fill-opacity="0.30""#));
+}
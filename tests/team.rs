@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ladybug::board::parse_fen;
+use ladybug::board::{BoardId, Bughouse, BughouseGame};
+use ladybug::engine::Engine;
+use ladybug::eval::EvalParams;
+use ladybug::limits::SearchLimits;
+use ladybug::seats::{EngineAgent, Seat, TeamAgent};
+use ladybug::team::{best_for_team, pawns, team_value, win_probability, Blend, TeamObjective};
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Move};
+
+// White on board A can queen the pawn or win a knight for the partner
+const CHOICE: &str = "4k3/P7/8/8/7n/8/8/4K2R[] w - - 0 1";
+// Black on board B, white's partner, is a queen down
+const LOSING: &str = "4k3/8/8/8/8/8/8/3QK3[] b - - 0 1";
+
+fn play(position: &Bughouse, uci: &str) -> Move {
+    uci.parse::<Uci>().unwrap().to_move(position).unwrap()
+}
+
+#[test]
+fn win_probabilities_and_pawns_are_inverses() {
+    assert_eq!(win_probability(0f32), 0.5);
+    assert!(win_probability(4f32) > 0.7);
+    assert!((pawns(win_probability(2.5)) - 2.5).abs() < 1e-3);
+    assert!(pawns(1f32).is_finite());
+}
+
+#[test]
+fn blends_weigh_the_boards() {
+    let average = Blend::default();
+    assert_eq!(average.blend(0.8, 0.2), 0.5);
+    assert_eq!(Blend::Weakest.blend(0.8, 0.2), 0.2);
+    let softmin = Blend::Softmin { sharpness: 10f32 };
+    let value = softmin.blend(0.8, 0.2);
+    assert!(value > 0.2 && value < 0.5);
+
+    for text in ["average:0.7", "weakest", "softmin:4"] {
+        assert_eq!(text.parse::<Blend>().unwrap().to_string(), text);
+    }
+    for invalid in ["average", "average:2", "weakest:1", "median", "softmin:x"] {
+        assert!(invalid.parse::<Blend>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn the_team_move_gives_up_on_our_board_to_save_the_partner() {
+    let params = EvalParams::default();
+    let own = parse_fen(CHOICE).unwrap();
+    let partner = parse_fen(LOSING).unwrap();
+    let queen = play(&own, "a7a8q");
+    let capture = play(&own, "h1h4");
+    let candidates = [queen.clone(), capture.clone()];
+
+    let selfish = Blend::Average { own_weight: 1f32 };
+    let best = best_for_team(&selfish, &own, &partner, &candidates, &params);
+    assert_eq!(best, Some(queen));
+    let best = best_for_team(&Blend::Weakest, &own, &partner, &candidates, &params);
+    assert_eq!(best, Some(capture.clone()));
+    // Any function of the two boards is an objective
+    let product = |own: f32, partner: f32| own * partner;
+    let value = team_value(&product, &own, Color::White, &partner, &params);
+    assert!(value > 0f32 && value < 1f32);
+    assert_eq!(best_for_team(&product, &own, &partner, &[], &params), None);
+}
+
+#[test]
+fn agents_choose_their_moves_by_the_objective() {
+    let clocks = ByColor {
+        white: Duration::from_secs(60),
+        black: Duration::from_secs(60),
+    };
+    let game = BughouseGame::from_boards(
+        parse_fen(CHOICE).unwrap(),
+        parse_fen(LOSING).unwrap(),
+        clocks.clone(),
+        clocks,
+    );
+    let seat = Seat {
+        board: BoardId::A,
+        color: Color::White,
+    };
+    let engine = Engine::new(Arc::new(EvalParams::default()));
+    let mut agent = EngineAgent::new("ladybug", engine, SearchLimits::nodes(400));
+    // On its own board the pawn is worth more than the knight
+    let selfish = agent.choose_move(&game, seat).unwrap();
+    assert_eq!(selfish, Some(play(game.board(BoardId::A), "a7a8q")));
+    agent.objective = Some(Box::new(Blend::Average { own_weight: 0f32 }));
+    let best = agent.choose_move(&game, seat).unwrap();
+    assert_eq!(best, Some(play(game.board(BoardId::A), "h1h4")));
+}
@@ -0,0 +1,77 @@
+use ladybug::board::{parse_fen, Bughouse, Rules};
+use shakmaty::uci::Uci;
+use shakmaty::{Color, Outcome, Position, RemainingChecks};
+
+fn play(position: &mut Bughouse, uci: &str) {
+    let m = uci.parse::<Uci>().unwrap().to_move(position).unwrap();
+    position.play_unchecked(&m);
+}
+
+#[test]
+fn the_third_check_wins() {
+    let mut position = parse_fen("4k3/8/8/8/8/8/8/4K3[Q] w - - 1+3 0 1").unwrap();
+    assert!(!position.is_game_over());
+    play(&mut position, "Q@a4");
+    assert_eq!(
+        position.checks_remaining(Color::White),
+        Some(RemainingChecks(0))
+    );
+    assert!(position.is_game_over());
+    assert!(position.legal_moves().is_empty());
+    assert_eq!(
+        position.outcome(),
+        Some(Outcome::Decisive {
+            winner: Color::White
+        })
+    );
+}
+
+#[test]
+fn check_counts_survive_a_fen_round_trip() {
+    let mut position = parse_fen("4k3/8/8/8/8/8/8/4K3[Qq] w - - 3+2 0 1").unwrap();
+    assert!(position.rules().three_check);
+    play(&mut position, "Q@a4");
+    let fen = position.fen();
+    assert!(fen.contains(" 2+2 "), "{}", fen);
+
+    let read_back = parse_fen(&fen).unwrap();
+    assert_eq!(read_back.fen(), fen);
+    assert_eq!(
+        read_back.checks_remaining(Color::White),
+        Some(RemainingChecks(2))
+    );
+    assert_eq!(
+        read_back.checks_remaining(Color::Black),
+        Some(RemainingChecks(2))
+    );
+}
+
+#[test]
+fn checks_are_not_counted_with_the_rule_off() {
+    let fen = "4k3/8/8/8/8/8/8/4K3[Q] w - - 0 1";
+    let mut position = parse_fen(fen).unwrap();
+    assert_eq!(position.checks_remaining(Color::White), None);
+
+    // Four checks in a row, each answered by a king move
+    for (check, reply) in [
+        ("Q@a4", "e8e7"),
+        ("a4b4", "e7e6"),
+        ("b4b3", "e6e5"),
+        ("b3b2", "e5e4"),
+    ] {
+        play(&mut position, check);
+        assert!(position.is_check());
+        play(&mut position, reply);
+    }
+    assert!(!position.is_game_over());
+    assert_eq!(position.outcome(), None);
+    assert_eq!(position.fen().split(' ').count(), fen.split(' ').count());
+
+    // Turning the rule off on a three-check position stops the count as well
+    let mut position = parse_fen("4k3/8/8/8/8/8/8/4K3[Q] w - - 1+3 0 1")
+        .unwrap()
+        .with_rules(Rules::default());
+    play(&mut position, "Q@a4");
+    assert!(!position.is_game_over());
+    assert!(!position.legal_moves().is_empty());
+}
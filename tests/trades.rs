@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::eval::{EvalParams, TradeWeights};
+use ladybug::prior::{EvalPriors, PriorSource};
+use ladybug::trades::{exposed_material, trade_penalty, TradePriors};
+use shakmaty::{Color, Move, Position, Role, Square};
+
+fn knight_takes_e5() -> Move {
+    Move::Normal {
+        role: Role::Knight,
+        from: Square::F3,
+        capture: Some(Role::Pawn),
+        to: Square::E5,
+        promotion: None,
+    }
+}
+
+#[test]
+fn only_captures_that_can_be_taken_back_expose_material() {
+    let params = EvalParams::default();
+    let defended = parse_fen("4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1").unwrap();
+    let hanging = parse_fen("4k3/8/8/4p3/8/5N2/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(
+        exposed_material(&defended, &knight_takes_e5(), &params),
+        params.pocket.knight
+    );
+    assert_eq!(
+        exposed_material(&hanging, &knight_takes_e5(), &params),
+        0f32
+    );
+
+    let weights = TradeWeights {
+        style: 0.1,
+        partner_danger: 0.5,
+    };
+    assert_eq!(trade_penalty(&weights, None), 0.1);
+    assert_eq!(trade_penalty(&weights, Some(1.0)), 0.6);
+}
+
+#[test]
+fn trades_lose_prior_for_the_side_searching() {
+    let params = Arc::new(EvalParams::default());
+    let position = parse_fen("4k3/8/3p4/4p3/8/5N2/8/4K3 w - - 0 1").unwrap();
+    let moves: Vec<Move> = position.legal_moves().into_iter().collect();
+    let trade = moves.iter().position(|m| *m == knight_takes_e5()).unwrap();
+    let inner = || EvalPriors {
+        params: params.clone(),
+    };
+    let prior = |color: Color| {
+        TradePriors {
+            inner: inner(),
+            params: params.clone(),
+            color,
+            penalty: 0.5,
+        }
+        .priors(&position, &moves)[trade]
+    };
+    let plain = inner().priors(&position, &moves)[trade];
+    assert!(prior(Color::White) < plain);
+    assert_eq!(prior(Color::Black), plain);
+}
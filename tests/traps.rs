@@ -0,0 +1,117 @@
+use std::io::Cursor;
+
+use ladybug::board::Bughouse;
+use ladybug::bpgn::parse;
+use ladybug::insights::Insights;
+use ladybug::script::ScriptRunner;
+use ladybug::traps::{Trap, TrapBook};
+use shakmaty::uci::Uci;
+use shakmaty::{Move, Position};
+
+// White on board A walks into the fool's mate
+const GAME: &str = r#"[WhiteA "alice"]
+[BlackA "bob"]
+[WhiteB "carol"]
+[BlackB "dave"]
+[Result "0-1"]
+
+1A. f3 1B. e4 1a. e5 2A. g4 2a. Qh4 0-1
+"#;
+
+fn play(position: &Bughouse, uci: &str) -> Move {
+    uci.parse::<Uci>().unwrap().to_move(position).unwrap()
+}
+
+fn fools_mate() -> Trap {
+    let position = Bughouse::default();
+    let bait = play(&position, "f2f3");
+    let mut line = position.clone();
+    line.play_unchecked(&bait);
+    let mut punishment = Vec::new();
+    for uci in ["e7e5", "g2g4", "d8h4"] {
+        let m = play(&line, uci);
+        line.play_unchecked(&m);
+        punishment.push(m);
+    }
+    let refutation = play(&position, "e2e4");
+    Trap::new("fool's mate", &position, bait, punishment, refutation).unwrap()
+}
+
+#[test]
+fn traps_are_kept_in_the_book_file() {
+    let dir = std::env::temp_dir().join(format!("ladybug-traps-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("traps.txt");
+    let mut book = TrapBook::open(&path).unwrap();
+    book.add(fools_mate()).unwrap();
+    // Adding it again replaces it
+    book.add(fools_mate()).unwrap();
+
+    let book = TrapBook::open(&path).unwrap();
+    assert_eq!(book.list(), [fools_mate()]);
+    let position = Bughouse::default();
+    assert_eq!(book.traps_in(&position).len(), 1);
+    let trap = book.fallen_for(&position, &play(&position, "f2f3"));
+    assert_eq!(trap.map(|trap| trap.name.as_str()), Some("fool's mate"));
+    assert!(book
+        .fallen_for(&position, &play(&position, "e2e4"))
+        .is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn traps_must_be_legal_lines() {
+    let position = Bughouse::default();
+    let bait = play(&position, "f2f3");
+    let refutation = play(&position, "e2e4");
+    // Black's reply played as if white were still to move
+    let wrong = play(&position, "g2g4");
+    assert!(Trap::new(
+        "bad",
+        &position,
+        bait.clone(),
+        vec![wrong],
+        refutation.clone()
+    )
+    .is_err());
+    assert!(Trap::new(" ", &position, bait, Vec::new(), refutation).is_err());
+}
+
+#[test]
+fn insights_count_the_traps_fallen_for() {
+    let mut book = TrapBook::in_memory();
+    book.add(fools_mate()).unwrap();
+    let mut insights = Insights::new();
+    for game in parse(GAME).unwrap() {
+        assert!(insights.add_bpgn_traps(&game, "alice", &book));
+        assert!(insights.add_bpgn_traps(&game, "carol", &book));
+        assert!(!insights.add_bpgn_traps(&game, "nobody", &book));
+    }
+    assert_eq!(insights.traps(), [("fool's mate", 1)]);
+    assert!(insights
+        .to_string()
+        .contains("fell for the fool's mate trap 1 times"));
+}
+
+#[test]
+fn scripts_add_traps_and_warn_of_them() {
+    let mut output = Vec::new();
+    let mut runner = ScriptRunner::new(&mut output);
+    let script = "trap fools f2f3 e2e4 e7e5 g2g4 d8h4\nexplain f2f3\nexplain e2e4\n";
+    runner.run(Cursor::new(script)).unwrap();
+    assert_eq!(runner.traps().list().len(), 1);
+    assert_eq!(runner.traps().list()[0].punishment.len(), 3);
+    drop(runner);
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1], "falls for the fools trap, play e2e4 instead");
+    assert!(lines[2].starts_with("e2e4: "));
+
+    // Without a punishment line the engine's reply is taken
+    let mut runner = ScriptRunner::new(Vec::new()).with_traps(TrapBook::in_memory());
+    runner.execute("nodes 50").unwrap();
+    runner.execute("trap fools f2f3 e2e4").unwrap();
+    assert_eq!(runner.traps().list()[0].punishment.len(), 1);
+    assert!(runner.execute("trap fools f2f3").is_err());
+}
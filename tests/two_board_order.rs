@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use ladybug::board::{Action, BoardId, Bughouse, BughouseGame, Played};
+use ladybug::match_log::MatchState;
+use ladybug::two_board::{TimedEvent, TwoBoardMatch};
+use shakmaty::fen::fen;
+use shakmaty::uci::Uci;
+use shakmaty::{ByColor, Color, Outcome, Position, Role, Setup};
+
+fn start() -> MatchState {
+    let minute = Duration::from_secs(60);
+    MatchState::new(
+        Bughouse::default(),
+        ByColor {
+            white: minute,
+            black: minute,
+        },
+    )
+}
+
+fn millis(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+// Plays `uci` on a scratch copy of `position` to build the event
+fn event(board: BoardId, at: u64, position: &mut Bughouse, uci: &str) -> TimedEvent {
+    let m = uci.parse::<Uci>().unwrap().to_move(position).unwrap();
+    position.play_unchecked(&m);
+    TimedEvent::play(board, millis(at), m, millis(100))
+}
+
+fn events() -> Vec<TimedEvent> {
+    let mut a = Bughouse::default();
+    let mut b = Bughouse::default();
+    vec![
+        event(BoardId::A, 100, &mut a, "e2e4"),
+        event(BoardId::B, 100, &mut b, "d2d4"),
+        event(BoardId::A, 200, &mut a, "d7d5"),
+        event(BoardId::B, 250, &mut b, "g8f6"),
+        // White on A captures a pawn, which goes to black on B
+        event(BoardId::A, 300, &mut a, "e4d5"),
+    ]
+}
+
+#[test]
+fn arrival_order_does_not_change_the_result() {
+    let in_order = TwoBoardMatch::replay(start(), start(), events()).unwrap();
+    let mut shuffled = events();
+    shuffled.reverse();
+    let reordered = TwoBoardMatch::replay(start(), start(), shuffled).unwrap();
+    for board in [BoardId::A, BoardId::B] {
+        assert_eq!(
+            fen(&in_order.board(board).position),
+            fen(&reordered.board(board).position)
+        );
+    }
+}
+
+#[test]
+fn captures_go_to_the_partner() {
+    let game = TwoBoardMatch::replay(start(), start(), events()).unwrap();
+    let a = &game.board(BoardId::A).position;
+    let b = &game.board(BoardId::B).position;
+    assert!(a.pockets().unwrap().by_color(Color::White).is_empty());
+    assert_eq!(
+        b.pockets()
+            .unwrap()
+            .by_color(Color::Black)
+            .by_role(Role::Pawn),
+        1
+    );
+}
+
+#[test]
+fn events_applied_out_of_order_are_refused() {
+    let mut game = TwoBoardMatch::new(start(), start());
+    let mut events = events();
+    let late = events.remove(1);
+    game.apply(&events[1]).unwrap_err();
+    game.apply(&events[0]).unwrap();
+    game.apply(&late).unwrap();
+    game.apply(&events[0]).unwrap_err();
+}
+
+#[test]
+fn bughouse_game_passes_captures_to_the_partner() {
+    let mut game = BughouseGame::new(Duration::from_secs(60));
+    for uci in ["e2e4", "d7d5", "e4d5"] {
+        let m = uci
+            .parse::<Uci>()
+            .unwrap()
+            .to_move(game.board(BoardId::A))
+            .unwrap();
+        let passed = game.play(BoardId::A, &m).unwrap();
+        assert_eq!(
+            passed,
+            if uci == "e4d5" {
+                Some(Role::Pawn)
+            } else {
+                None
+            }
+        );
+    }
+    let pockets = |board| game.board(board).pockets().unwrap().clone();
+    assert!(pockets(BoardId::A).is_empty());
+    assert_eq!(
+        pockets(BoardId::B)
+            .by_color(Color::Black)
+            .by_role(Role::Pawn),
+        1
+    );
+}
+
+#[test]
+fn mate_on_one_board_ends_both() {
+    let mut game = BughouseGame::new(Duration::from_secs(60));
+    let mut play = |board, uci: &str| {
+        let m = uci
+            .parse::<Uci>()
+            .unwrap()
+            .to_move(game.board(board))
+            .unwrap();
+        game.play(board, &m).unwrap();
+    };
+    play(BoardId::B, "e2e4");
+    for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+        play(BoardId::A, uci);
+    }
+    // Black mated on A, so their partner, White on B, wins as well
+    let black_wins = Outcome::Decisive {
+        winner: Color::Black,
+    };
+    assert_eq!(game.decided_on(), Some((BoardId::A, black_wins)));
+    assert_eq!(game.outcome(), Some(black_wins));
+    let b = game.board(BoardId::B);
+    assert!(b.is_game_over());
+    assert!(b.legal_moves().is_empty());
+    assert_eq!(
+        b.outcome(),
+        Some(Outcome::Decisive {
+            winner: Color::White
+        })
+    );
+}
+
+#[test]
+fn a_flag_on_board_b_decides_the_match() {
+    let mut game = BughouseGame::new(Duration::from_secs(10));
+    assert_eq!(game.tick(BoardId::B, Duration::from_secs(4)), None);
+    // White on B flags: the team of Black on A and White on B loses
+    let result = game.tick(BoardId::B, Duration::from_secs(6));
+    assert_eq!(
+        result,
+        Some(Outcome::Decisive {
+            winner: Color::White
+        })
+    );
+    assert!(game.board(BoardId::A).legal_moves().is_empty());
+}
+
+#[test]
+fn a_move_after_the_flag_fell_is_not_played() {
+    let mut game = BughouseGame::new(Duration::from_secs(10));
+    let m = "e2e4"
+        .parse::<Uci>()
+        .unwrap()
+        .to_move(game.board(BoardId::A))
+        .unwrap();
+    assert_eq!(
+        game.act(BoardId::A, &Action::Wait, Duration::from_secs(4))
+            .unwrap(),
+        Played::Waited
+    );
+    assert_eq!(
+        game.play_timed(BoardId::A, &m, Duration::from_secs(1))
+            .unwrap(),
+        Played::Moved(None)
+    );
+    // Black on A thinks too long
+    let reply = "e7e5"
+        .parse::<Uci>()
+        .unwrap()
+        .to_move(game.board(BoardId::A))
+        .unwrap();
+    assert_eq!(
+        game.play_timed(BoardId::A, &reply, Duration::from_secs(10))
+            .unwrap(),
+        Played::FlagFell
+    );
+    assert_eq!(game.board(BoardId::A).turn(), Color::Black);
+    assert_eq!(
+        game.decided_on(),
+        Some((
+            BoardId::A,
+            Outcome::Decisive {
+                winner: Color::White
+            }
+        ))
+    );
+    assert!(game.play_timed(BoardId::A, &reply, millis(0)).is_err());
+    // Waiting on either board afterwards reports the result, not another flag
+    for board in [BoardId::A, BoardId::B] {
+        assert_eq!(
+            game.act(board, &Action::Wait, millis(0)).unwrap(),
+            Played::GameOver(Outcome::Decisive {
+                winner: Color::White
+            })
+        );
+    }
+    assert_eq!(game.clocks(BoardId::B).white, Duration::from_secs(10));
+}
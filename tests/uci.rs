@@ -0,0 +1,343 @@
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ladybug::board::parse_fen;
+use ladybug::build_info::BuildInfo;
+use ladybug::eval::{EvalHandle, EvalParams};
+use ladybug::remote::serve_remote;
+use ladybug::uci::UciEngine;
+use ladybug::variant::{Variant, VariantPosition};
+use shakmaty::uci::Uci;
+use shakmaty::Position;
+
+// Output the test can still read after handing it to the engine
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs `commands` to the end and returns everything the engine sent
+fn session(commands: &str) -> Vec<String> {
+    let output = Shared::default();
+    let mut engine = UciEngine::new(output.clone(), EvalHandle::default());
+    engine.run(commands.as_bytes()).unwrap();
+    let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    text.lines().map(str::to_string).collect()
+}
+
+fn bestmove(lines: &[String]) -> &str {
+    lines
+        .iter()
+        .find_map(|line| line.strip_prefix("bestmove "))
+        .expect("no bestmove")
+}
+
+#[test]
+fn handshake() {
+    let lines = session("uci\nisready\nquit\n");
+    let name = BuildInfo::new(&EvalParams::default()).name();
+    assert_eq!(lines[0], format!("id name {}", name));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("option name UCI_Variant")));
+    assert_eq!(&lines[lines.len() - 2..], ["uciok", "readyok"]);
+}
+
+#[test]
+fn drops_are_written_with_an_at_sign() {
+    // The only way out of check is to drop the knight in between
+    let lines = session("position fen k7/8/8/8/8/8/r7/r3K3[N] w - - 0 1\ngo nodes 50\n");
+    let best = bestmove(&lines);
+    assert!(
+        ["N@b1", "N@c1", "N@d1"].contains(&best),
+        "unexpected move {}",
+        best
+    );
+}
+
+#[test]
+fn drop_statistics_follow_each_search() {
+    let lines = session("position fen k7/8/8/8/8/8/r7/r3K3[N] w - - 0 1\ngo nodes 50\n");
+    let drops = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("info string drops "))
+        .expect("no drop statistics");
+    // Every candidate is a knight drop, the only way out of check
+    assert!(drops.starts_with("pv "), "{}", drops);
+    assert!(drops.ends_with(", candidates 3/3 N@ 3"), "{}", drops);
+}
+
+#[test]
+fn rollout_hit_rates_follow_each_search() {
+    let lines = session("position startpos\ngo nodes 200\n");
+    let rollouts = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("info string rollouts "))
+        .expect("no rollout statistics");
+    assert!(rollouts.starts_with("countermoves "), "{}", rollouts);
+    assert!(rollouts.contains("%), killers "), "{}", rollouts);
+}
+
+#[test]
+fn searchmoves_restricts_the_root_moves() {
+    let lines = session("position startpos\ngo searchmoves a2a3 nodes 50\n");
+    assert_eq!(bestmove(&lines), "a2a3");
+
+    // The list runs up to the next keyword, and the mate is not among it
+    let lines = session(
+        "position fen r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4\ngo nodes 100 searchmoves h2h3 g2g3\n",
+    );
+    assert!(["h2h3", "g2g3"].contains(&bestmove(&lines)));
+}
+
+#[test]
+fn excludemoves_leaves_out_the_root_moves() {
+    // The three knight drops are the only way out of check
+    let lines = session(
+        "position fen k7/8/8/8/8/8/r7/r3K3[N] w - - 0 1\ngo excludemoves N@b1 N@d1 nodes 50\n",
+    );
+    assert_eq!(bestmove(&lines), "N@c1");
+
+    // Both lists at once
+    let lines = session("position startpos\ngo searchmoves a2a3 a2a4 excludemoves a2a3\n");
+    assert_eq!(bestmove(&lines), "a2a4");
+}
+
+#[test]
+fn chess_searches_never_drop() {
+    // Both sides captured a pawn, which crazyhouse would let them drop
+    let lines = session(
+        "setoption name UCI_Variant value chess\nposition startpos moves e2e4 d7d5 e4d5 d8d5 b1c3 d5d2\ngo nodes 100\n",
+    );
+    // Taking the queen back is all there is
+    let best = bestmove(&lines);
+    assert!(["c1d2", "d1d2", "e1d2"].contains(&best), "{}", best);
+}
+
+#[test]
+fn moves_after_the_position_are_played() {
+    let moves = "e2e4 e7e5 g1f3";
+    let lines = session(&format!(
+        "position startpos moves {}\ngo nodes 50\nstop\n",
+        moves
+    ));
+    let mut position = VariantPosition::start(Variant::Crazyhouse);
+    for uci in moves.split(' ') {
+        position.play_uci(uci).unwrap();
+    }
+    // Only legal for black
+    position.play_uci(bestmove(&lines)).unwrap();
+}
+
+#[test]
+fn invalid_positions_are_reported_and_not_searched() {
+    let lines = session("position fen not a fen\nposition startpos moves e2e5\ngo nodes 20\n");
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|line| line.starts_with("info string"))
+            .count(),
+        3
+    );
+    assert_eq!(bestmove(&lines), "0000");
+
+    // A valid position afterwards is searched as usual
+    let lines = session("position startpos moves e2e5\nposition startpos\ngo nodes 20\n");
+    assert_ne!(bestmove(&lines), "0000");
+}
+
+#[test]
+fn compatibility_mode_repairs_near_miss_fens() {
+    // Empty pockets as `[-]` and castling rights the board doesn't allow
+    let quirky = "position fen 4k3/8/8/8/8/8/8/4K2R[-] w KQkq - 0 1\ngo nodes 20\n";
+    let lines = session(quirky);
+    assert!(!lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("info string "))
+        .any(|info| !info.starts_with("drops ") && !info.starts_with("rollouts ")));
+    assert_ne!(bestmove(&lines), "0000");
+
+    let strict = session(&format!(
+        "setoption name Compatibility value false\n{}",
+        quirky
+    ));
+    assert_eq!(bestmove(&strict), "0000");
+
+    // A crazyhouse FEN sent to a GUI-side standard chess game, and a bughouse variant name
+    let lines = session(
+        "setoption name UCI_Variant value chess\nposition fen k7/8/8/8/8/8/r7/r3K3[N] w - - 0 1\ngo nodes 50\n",
+    );
+    assert!(bestmove(&lines).starts_with("N@"));
+    let lines = session("setoption name UCI_Variant value bughouse\nisready\n");
+    assert_eq!(lines, ["readyok"]);
+}
+
+#[test]
+fn pondering_answers_once() {
+    let bestmoves = |lines: &[String]| {
+        lines
+            .iter()
+            .filter(|line| line.starts_with("bestmove"))
+            .count()
+    };
+    // The ponder search only answers after `ponderhit` turned it into a real one
+    let lines = session("position startpos moves e2e4\ngo ponder nodes 50\nponderhit\n");
+    assert_eq!(bestmoves(&lines), 1);
+    let lines = session("position startpos moves e2e4\ngo ponder wtime 1000 btime 1000\nstop\n");
+    assert_eq!(bestmoves(&lines), 1);
+}
+
+#[test]
+fn book_moves_are_played_without_searching() {
+    let path = std::env::temp_dir().join(format!("ladybug-book-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"positions":[{"fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1","moves":[{"move":"b1c3"}]}]}"#,
+    )
+    .unwrap();
+    let lines = session(&format!(
+        "setoption name BookFile value {}\nposition startpos\ngo nodes 50\nquit\n",
+        path.display()
+    ));
+    std::fs::remove_file(&path).unwrap();
+    assert!(lines.iter().any(|line| line == "info string book move"));
+    assert_eq!(bestmove(&lines), "b1c3");
+}
+
+#[test]
+fn the_weights_file_is_reloaded_on_request() {
+    let path = std::env::temp_dir().join(format!("ladybug-weights-{}.txt", std::process::id()));
+    std::fs::write(&path, "pocket.knight = 4\n").unwrap();
+    let eval = EvalHandle::default();
+    let mut engine = UciEngine::new(io::sink(), eval.clone());
+    engine
+        .run(format!("setoption name EvalFile value {}\n", path.display()).as_bytes())
+        .unwrap();
+    assert_eq!(
+        *eval.params(),
+        EvalParams::parse("pocket.knight = 4").unwrap()
+    );
+    // Retuned weights only apply once the GUI asks for them
+    std::fs::write(&path, "pocket.knight = 5\n").unwrap();
+    assert_eq!(
+        *eval.params(),
+        EvalParams::parse("pocket.knight = 4").unwrap()
+    );
+    engine
+        .run("setoption name ReloadEvalFile\n".as_bytes())
+        .unwrap();
+    assert_eq!(
+        *eval.params(),
+        EvalParams::parse("pocket.knight = 5").unwrap()
+    );
+    // A file that no longer parses keeps the loaded weights
+    std::fs::write(&path, "pocket.knight = five\n").unwrap();
+    engine
+        .run("setoption name ReloadEvalFile\n".as_bytes())
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        *eval.params(),
+        EvalParams::parse("pocket.knight = 5").unwrap()
+    );
+}
+
+#[test]
+fn search_settings_are_options() {
+    let commands = "setoption name Seed value 3\n\
+                    setoption name PlayoutCap value 40\n\
+                    setoption name Exploration value 1.2\n\
+                    position startpos\n\
+                    go nodes 5000\n";
+    let lines = session(commands);
+    let nodes: u64 = lines
+        .iter()
+        .find_map(|line| line.split(" nodes ").nth(1))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|nodes| nodes.parse().ok())
+        .expect("no info line");
+    assert!(nodes <= 40, "{} nodes", nodes);
+    // The same seed plays the same move
+    assert_eq!(bestmove(&session(commands)), bestmove(&lines));
+    let lines = session("setoption name Exploration value -1\nisready\n");
+    assert!(lines[0].starts_with("info string"));
+}
+
+#[test]
+fn the_root_strategy_is_an_option() {
+    let lines = session("uci\nquit\n");
+    assert!(lines
+        .iter()
+        .any(|line| line == "option name RootStrategy type combo default uct var uct var halving"));
+    let lines = session(
+        "setoption name RootStrategy value halving\nposition startpos\ngo nodes 64\nquit\n",
+    );
+    assert!(!lines.iter().any(|line| line.contains("root strategy")));
+    assert_ne!(bestmove(&lines), "0000");
+    let lines = session("setoption name RootStrategy value best\nisready\n");
+    assert_eq!(lines[0], "info string unknown root strategy: best");
+}
+
+#[test]
+fn remote_engines_search_for_the_gui() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // A stand-in server that always plays its first legal move
+    thread::spawn(move || {
+        serve_remote(&listener, |position, _, _| {
+            position.legal_moves().first().cloned()
+        })
+    });
+    let lines = session(&format!(
+        "setoption name RemoteEngine value {}\nposition startpos\ngo movetime 50\nquit\n",
+        addr
+    ));
+    let first = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1")
+        .unwrap()
+        .legal_moves()[0]
+        .clone();
+    assert_eq!(bestmove(&lines), Uci::from_standard(&first).to_string());
+
+    // Without a server the search runs here
+    let lines = session(
+        "setoption name RemoteEngine value 127.0.0.1:1\nposition startpos\ngo movetime 50\nquit\n",
+    );
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("info string remote search failed")));
+    assert_ne!(bestmove(&lines), "0000");
+}
+
+#[test]
+fn searches_below_the_resign_threshold_say_so() {
+    let lines = session("uci\nposition startpos\ngo nodes 50\n");
+    assert!(lines
+        .iter()
+        .any(|line| line == "option name ResignThreshold type spin default 0 min 0 max 100"));
+    assert!(!lines.iter().any(|line| line == "info string resign"));
+
+    let lines =
+        session("setoption name ResignThreshold value 100\nposition startpos\ngo nodes 50\n");
+    let resign = lines
+        .iter()
+        .position(|line| line == "info string resign")
+        .expect("no resign");
+    let best = lines
+        .iter()
+        .position(|line| line.starts_with("bestmove"))
+        .unwrap();
+    assert!(resign < best);
+
+    let lines = session("setoption name ResignThreshold value 101\n");
+    assert_eq!(lines, ["info string invalid resign threshold: 101"]);
+}
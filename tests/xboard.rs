@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use ladybug::build_info::BuildInfo;
+use ladybug::eval::{EvalHandle, EvalParams};
+use ladybug::intent::PartnerIntent;
+use ladybug::xboard::XboardEngine;
+use shakmaty::{Color, Role, Setup};
+
+// Output the test can still read after handing it to the engine
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs `commands` and returns the engine along with everything it sent
+fn session(commands: &str) -> (XboardEngine<Shared>, Vec<String>) {
+    let output = Shared::default();
+    let mut engine = XboardEngine::new(output.clone(), EvalHandle::default());
+    engine.run(commands.as_bytes()).unwrap();
+    let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    (engine, text.lines().map(str::to_string).collect())
+}
+
+#[test]
+fn handshake_announces_bughouse() {
+    let (_, lines) = session("xboard\nprotover 2\nping 7\n");
+    assert_eq!(lines[0], "feature done=0");
+    let name = BuildInfo::new(&EvalParams::default()).name();
+    assert!(lines[1].starts_with(&format!("feature myname=\"{}\" ", name)));
+    assert!(lines[1].contains("variants=\"bughouse,crazyhouse\""));
+    assert_eq!(&lines[2..], ["feature done=1", "pong 7"]);
+}
+
+#[test]
+fn bughouse_pockets_come_from_holding() {
+    let (engine, lines) = session(
+        "new\nvariant bughouse\nforce\nusermove e2e4\nusermove d7d5\nusermove e4d5\n\
+         holding [N] [PQ] BQ\n",
+    );
+    assert!(lines.is_empty(), "{:?}", lines);
+    let pockets = engine.position().pockets().unwrap();
+    // The captured pawn went to the partner; the pockets are the holding's
+    assert_eq!(pockets.by_color(Color::White).by_role(Role::Knight), 1);
+    assert_eq!(pockets.by_color(Color::White).by_role(Role::Pawn), 0);
+    assert_eq!(pockets.by_color(Color::Black).by_role(Role::Queen), 1);
+
+    let (_, lines) = session("holding [X] []\nusermove e2e5\nfoo\n");
+    assert!(lines[0].starts_with("Error (bad piece)"));
+    assert_eq!(lines[1], "Illegal move: e2e5");
+    assert_eq!(lines[2], "Error (unknown command): foo");
+}
+
+#[test]
+fn sitting_holds_the_move_until_the_partner_says_go() {
+    let (engine, lines) = session(
+        "new\nvariant bughouse\npartner alice\nforce\n\
+         setboard 6k1/5ppp/8/8/8/8/8/4K3[] w - - 0 1\nptell sit\nholding [R] []\ngo\n",
+    );
+    assert_eq!(engine.partner(), Some("alice"));
+    assert!(engine.is_sitting());
+    assert_eq!(lines, ["tellics ptell sitting"]);
+
+    let (_, lines) = session(
+        "new\nvariant bughouse\nforce\nsetboard 6k1/5ppp/8/8/8/8/8/4K3[] w - - 0 1\n\
+         ptell sit\nholding [R] []\ngo\nptell go\n",
+    );
+    assert_eq!(lines[1], "tellics ptell going");
+    let reply = lines[2].strip_prefix("move R@").expect("a rook drop");
+    assert!(["a8", "b8", "c8", "d8", "e8"].contains(&reply), "{}", reply);
+}
+
+#[test]
+fn partner_requests_constrain_the_next_move() {
+    // The knight can take the queen on g5, which the partner asked for
+    let (engine, lines) = session(
+        "new\nvariant bughouse\nforce\nsetboard 3rk3/8/8/6q1/8/5N2/8/3QK3[] w - - 0 1\n\
+         ptell need q\nptell nice\n",
+    );
+    assert_eq!(engine.requests(), [PartnerIntent::Need(Role::Queen)]);
+    assert!(lines.is_empty());
+
+    let (engine, lines) = session(
+        "new\nvariant bughouse\nforce\nsetboard 3rk3/8/8/6q1/8/5N2/8/3QK3[] w - - 0 1\n\
+         ptell need q\ngo\n",
+    );
+    assert_eq!(lines, ["move f3g5"]);
+    assert!(engine.requests().is_empty());
+}
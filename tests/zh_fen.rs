@@ -0,0 +1,82 @@
+use ladybug::board::parse_fen;
+use ladybug::board::{Bughouse, FenError};
+use shakmaty::fen::ParseFenError;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Color, Piece, Position, PositionErrorKinds, Role, Setup, Square};
+
+// White has promoted on h8 and has a rook and a queen in hand, black a knight and a
+// bishop. Pockets are written in order of increasing value
+const PROMOTED: &str = "rnbqk1nQ~/ppppp3/8/5p2/8/5N2/PPPPPPP1/RNBQKB1R[RQnb] b KQq - 0 6";
+
+#[test]
+fn round_trip_keeps_pockets_and_promoted_pieces() {
+    let position = Bughouse::from_fen(PROMOTED, CastlingMode::Standard).unwrap();
+    assert_eq!(position.board().promoted().count(), 1);
+    assert!(position.board().promoted().contains(Square::H8));
+    let pockets = position.pockets().unwrap();
+    assert_eq!(pockets.by_color(Color::White).by_role(Role::Queen), 1);
+    assert_eq!(pockets.by_color(Color::Black).by_role(Role::Knight), 1);
+    assert_eq!(position.fen(), PROMOTED);
+}
+
+#[test]
+fn pieces_come_in_square_order_with_promoted_flags() {
+    let position = Bughouse::from_fen(
+        "4k1nQ~/8/8/8/8/8/1p6/R3K3[] w - - 0 1",
+        CastlingMode::Standard,
+    )
+    .unwrap();
+    let pieces: Vec<(Square, Piece, bool)> = position.pieces().collect();
+    assert_eq!(
+        pieces,
+        [
+            (Square::A1, Color::White.rook(), false),
+            (Square::E1, Color::White.king(), false),
+            (Square::B2, Color::Black.pawn(), false),
+            (Square::E8, Color::Black.king(), false),
+            (Square::G8, Color::Black.knight(), false),
+            (Square::H8, Color::White.queen(), true),
+        ]
+    );
+}
+
+#[test]
+fn pockets_may_follow_a_slash() {
+    let slash = PROMOTED.replace("[RQnb]", "/RQnb");
+    let position = Bughouse::from_fen(&slash, CastlingMode::Standard).unwrap();
+    assert_eq!(position.fen(), PROMOTED);
+}
+
+#[test]
+fn captured_promoted_piece_goes_to_the_pocket_as_a_pawn() {
+    let mut position =
+        Bughouse::from_fen("4k1rQ~/8/8/8/8/8/8/4K3[] b - - 0 1", CastlingMode::Standard).unwrap();
+    let m = "g8h8".parse::<Uci>().unwrap().to_move(&position).unwrap();
+    position.play_unchecked(&m);
+    assert_eq!(position.fen(), "4k2r/8/8/8/8/8/8/4K3[p] w - - 0 2");
+}
+
+#[test]
+fn rejected_fens_say_why() {
+    let err = Bughouse::from_fen("not a fen", CastlingMode::Standard).unwrap_err();
+    assert_eq!(err, FenError::Syntax(ParseFenError::InvalidBoard));
+    assert_eq!(err.to_string(), "syntax: board");
+
+    // No black king, and a white pawn on the back rank
+    let err =
+        Bughouse::from_fen("8/8/8/8/8/8/8/P3K3[] w - - 0 1", CastlingMode::Standard).unwrap_err();
+    match &err {
+        FenError::Position(kinds) => {
+            assert!(kinds.contains(PositionErrorKinds::MISSING_KING));
+            assert!(kinds.contains(PositionErrorKinds::PAWNS_ON_BACKRANK));
+        }
+        other => panic!("expected a position error, got {:?}", other),
+    }
+    assert_eq!(err.to_string(), "missing king, pawns on back rank");
+
+    // Sessions keep the reason
+    match parse_fen("8/8/8/8/8/8/8/P3K3[] w - - 0 1") {
+        Err(FenError::Position(_)) => {}
+        other => panic!("expected a position error, got {:?}", other),
+    }
+}
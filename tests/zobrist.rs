@@ -0,0 +1,106 @@
+use std::fs;
+use std::process;
+use std::sync::Arc;
+
+use ladybug::board::parse_fen;
+use ladybug::board::Bughouse;
+use ladybug::cancel::CancelToken;
+use ladybug::engine::{Engine, LongAnalysis};
+use ladybug::eval::EvalParams;
+use ladybug::explain::Reason;
+use ladybug::limits::{SearchControl, SearchLimits};
+use shakmaty::uci::Uci;
+use shakmaty::Position;
+
+fn play(moves: &[&str]) -> Bughouse {
+    play_from(&Bughouse::default(), moves)
+}
+
+fn play_from(position: &Bughouse, moves: &[&str]) -> Bughouse {
+    let mut position = position.clone();
+    for uci in moves {
+        let m = uci.parse::<Uci>().unwrap().to_move(&position).unwrap();
+        position.play_unchecked(&m);
+    }
+    position
+}
+
+#[test]
+fn transpositions_share_a_hash() {
+    let a = play(&["e2e4", "g8f6", "d2d4"]);
+    let b = play(&["d2d4", "g8f6", "e2e4"]);
+    assert_eq!(a.zobrist_hash(), b.zobrist_hash());
+    // The move counters are not part of the hash
+    let knights = play(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+    assert_eq!(knights.zobrist_hash(), Bughouse::default().zobrist_hash());
+}
+
+#[test]
+fn pockets_and_turn_change_the_hash() {
+    let hashes: Vec<u64> = [
+        "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3 b - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3[N] w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3[n] w - - 0 1",
+        "4k3/8/8/8/8/8/8/4K3[NN] w - - 0 1",
+    ]
+    .iter()
+    .map(|fen| parse_fen(fen).unwrap().zobrist_hash())
+    .collect();
+    for (i, a) in hashes.iter().enumerate() {
+        for b in &hashes[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
+
+#[test]
+fn transposed_positions_share_a_node() {
+    // Ka2, Kg8 and Kb2 is reached through Kb1 as well, both at ply 3
+    let position = parse_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+    let a = play_from(&position, &["a1a2", "h8g8", "a2b2"]);
+    let b = play_from(&position, &["a1b1", "h8g8", "b1b2"]);
+    assert!(a.transposes_to(&b));
+    assert!(!a.transposes_to(&play_from(&position, &["a1b1", "h8g8", "b1a2"])));
+
+    let mut analysis = LongAnalysis::new(&position, Arc::new(EvalParams::default()), 7);
+    let control = SearchControl::new(SearchLimits::nodes(300), CancelToken::new());
+    analysis.run(&control);
+    let path = std::env::temp_dir().join(format!("ladybug-transpositions-{}.tree", process::id()));
+    analysis.checkpoint(&path).unwrap();
+    let checkpoint = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    // Further parents of a shared node are written as edge lines
+    assert!(
+        checkpoint.lines().any(|line| line.starts_with("edge ")),
+        "{}",
+        checkpoint
+    );
+}
+
+#[test]
+fn colliding_hashes_are_not_taken_for_the_kept_tree() {
+    // Pocket counts past 15 hash alike, so these two positions collide
+    let fifteen = parse_fen("6rk/6pp/8/8/8/8/8/K7[NPPPPPPPPPPPPPPP] w - - 0 1").unwrap();
+    let sixteen = parse_fen("6rk/6pp/8/8/8/8/8/K7[NPPPPPPPPPPPPPPPP] w - - 0 1").unwrap();
+    assert_eq!(fifteen.zobrist_hash(), sixteen.zobrist_hash());
+    assert!(!fifteen.transposes_to(&sixteen));
+
+    let mut engine = Engine::new(Arc::new(EvalParams::default()));
+    let mate = engine.search(&fifteen, SearchLimits::nodes(300)).unwrap();
+    let explained = |engine: &Engine, position: &Bughouse| {
+        engine
+            .explain(position, &mate)
+            .reasons
+            .iter()
+            .any(|reason| matches!(reason, Reason::DominantContinuation(_)))
+    };
+    assert!(explained(&engine, &fifteen));
+    assert!(!explained(&engine, &sixteen));
+
+    // The next search starts a tree of its own
+    engine.search(&sixteen, SearchLimits::nodes(50));
+    let log = engine.search_log().unwrap();
+    assert_eq!(log.root_fen, sixteen.fen());
+    assert_eq!(log.searches.len(), 1);
+}